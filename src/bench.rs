@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use mini_spreadsheet::spreadsheet::synthetic::{chain, fan_out, row_sums};
+
+const DEFAULT_SIZE: usize = 1000;
+
+/// Measures the engine on generated sheets of about `N` cells each, given as the only
+/// argument, and prints how long each part took.
+pub fn run(args: &[String]) -> i32 {
+    let size = match args {
+        [] => DEFAULT_SIZE,
+        [size] => match size.parse::<usize>() {
+            Ok(size) if size > 0 => size,
+            _ => {
+                eprintln!("The size must be a positive number, not '{size}'");
+                return 2;
+            }
+        },
+        _ => {
+            eprintln!("Usage: mini_spreadsheet bench [N]");
+            return 2;
+        }
+    };
+
+    // The block is square, with about as many cells as the other sheets
+    let side = (size as f64).sqrt().ceil() as usize;
+    let sheets = [chain(size), fan_out(size), row_sums(side)];
+
+    println!(
+        "{:<10}{:>8}{:>14}{:>14}{:>14}",
+        "sheet", "cells", "parse", "compute all", "edit"
+    );
+    for sheet in sheets {
+        let timings = sheet.measure();
+        println!(
+            "{:<10}{:>8}{:>14}{:>14}{:>14}",
+            sheet.name,
+            sheet.cells.len(),
+            millis(timings.parse),
+            millis(timings.compute_all),
+            millis(timings.edit)
+        );
+    }
+    0
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.3} ms", duration.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,335 @@
+use std::{
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use mini_spreadsheet::common_types::error_text::{ErrorText, Language};
+use mini_spreadsheet::common_types::index_to_name;
+use mini_spreadsheet::spreadsheet::{
+    export::{render, OutputFormat},
+    stats::SheetStats,
+    watch::{apply_reload, FileWatcher},
+    SpreadSheet,
+};
+
+const USAGE: &str =
+    "Usage: mini_spreadsheet eval <file> [--format table|csv|tsv|json|markdown|html]
+       mini_spreadsheet watch <file> [--format table|csv|tsv|json|markdown|html] [--gui]
+       mini_spreadsheet diff <old file> <new file>
+       mini_spreadsheet graph <file>
+       mini_spreadsheet stats <file>
+       mini_spreadsheet [--config <file>] [--rows <n>|auto] [--columns <n>|auto]
+                        [--cell-width <px>] [--cell-height <px>] [--window-width <px>]
+                        [--window-height <px>] [--theme light|dark]";
+
+// How often a watched file is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What `mini_spreadsheet eval` was asked to do.
+#[derive(Debug, PartialEq)]
+pub struct EvalArgs {
+    pub path: PathBuf,
+    pub format: OutputFormat,
+}
+
+/// Reads the arguments following `eval`: the file to evaluate and an optional `--format`.
+pub fn parse_eval_args(args: &[String]) -> Result<EvalArgs, String> {
+    let mut path = None;
+    let mut format = OutputFormat::Table;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--format" {
+            format = match args.next().map(String::as_str) {
+                Some("table") => OutputFormat::Table,
+                Some("csv") => OutputFormat::Csv,
+                Some("tsv") => OutputFormat::Tsv,
+                Some("json") => OutputFormat::Json,
+                Some("markdown") => OutputFormat::Markdown,
+                Some("html") => OutputFormat::Html,
+                Some(other) => return Err(format!("Unknown format '{other}'")),
+                None => return Err("Missing format after --format".to_string()),
+            };
+        } else if arg.starts_with("--") {
+            return Err(format!("Unknown option '{arg}'"));
+        } else if path.replace(PathBuf::from(arg)).is_some() {
+            return Err("Only one file can be evaluated".to_string());
+        }
+    }
+
+    Ok(EvalArgs {
+        path: path.ok_or("Missing the file to evaluate")?,
+        format,
+    })
+}
+
+/// Reads the arguments following `watch`: those of `eval` and `--gui` to show the sheet in
+/// the window instead of printing it.
+pub fn parse_watch_args(args: &[String]) -> Result<(EvalArgs, bool), String> {
+    let gui = args.iter().any(|arg| arg == "--gui");
+    let args: Vec<String> = args.iter().filter(|arg| *arg != "--gui").cloned().collect();
+    Ok((parse_eval_args(&args)?, gui))
+}
+
+/// Prints the usage after the message of an argument error, returning the exit code for it.
+pub fn usage_error(message: &str) -> i32 {
+    eprintln!("{message}\n{USAGE}");
+    2
+}
+
+/// Evaluates a sheet file without opening a window, printing its values to stdout and its
+/// errors to stderr. Returns the exit code: 1 when a cell has an error, 2 when the sheet
+/// could not be evaluated at all.
+pub fn run_eval(args: &[String]) -> i32 {
+    let args = match parse_eval_args(args) {
+        Ok(args) => args,
+        Err(message) => return usage_error(&message),
+    };
+    match load(&args) {
+        Some(sheet) => i32::from(!print_sheet(&sheet, args.format)),
+        None => 2,
+    }
+}
+
+/// Prints the sheet like `eval` does, again whenever its file changes, until stopped. A
+/// file that cannot be read once it was loaded keeps the previous sheet.
+pub fn run_watch(args: EvalArgs) -> i32 {
+    let Some(mut sheet) = load(&args) else {
+        return 2;
+    };
+    let mut watcher = FileWatcher::new(args.path.clone());
+    print_sheet(&sheet, args.format);
+
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+        if !watcher.changed() {
+            continue;
+        }
+        match apply_reload(&mut sheet, SpreadSheet::load_from_file(&args.path)) {
+            Ok(()) => {
+                println!();
+                print_sheet(&sheet, args.format);
+            }
+            Err(warning) => eprintln!("{warning}"),
+        }
+    }
+}
+
+/// Prints the cells that differ between two sheet files, a line each. Returns the exit code
+/// like `diff` does: 0 for sheets with the same cells, 1 when they differ and 2 when they
+/// could not be compared.
+pub fn run_diff(args: &[String]) -> i32 {
+    let [old, new] = args else {
+        return usage_error("Expected the two files to compare");
+    };
+    let (Some(old), Some(new)) = (read(Path::new(old)), read(Path::new(new))) else {
+        return 2;
+    };
+
+    let diffs = old.diff(&new);
+    for diff in &diffs {
+        println!("{diff}");
+    }
+    i32::from(!diffs.is_empty())
+}
+
+/// Prints the dependencies between the cells of a sheet file as a Graphviz DOT graph.
+pub fn run_graph(args: &[String]) -> i32 {
+    let [path] = args else {
+        return usage_error("Expected the file to graph");
+    };
+    match read(Path::new(path)) {
+        Some(sheet) => {
+            print!("{}", sheet.dependencies_to_dot());
+            0
+        }
+        None => 2,
+    }
+}
+
+/// Prints the cell counts, dependencies and memory use of a sheet file as a table.
+pub fn run_stats(args: &[String]) -> i32 {
+    let [path] = args else {
+        return usage_error("Expected the file to measure");
+    };
+    match read(Path::new(path)) {
+        Some(sheet) => {
+            print!("{}", stats_table(&sheet.stats()));
+            0
+        }
+        None => 2,
+    }
+}
+
+/// Writes `stats` a line each, the numbers aligned on the right.
+fn stats_table(stats: &SheetStats) -> String {
+    let rows = [
+        ("Cells", stats.cells),
+        ("Formula cells", stats.formula_cells),
+        ("Value cells", stats.value_cells),
+        ("Error cells", stats.error_cells),
+        ("Dependency edges", stats.dependency_edges),
+        ("Longest dependency chain", stats.longest_chain),
+        ("Raw text bytes", stats.raw_bytes),
+        ("Formula tree bytes", stats.ast_bytes),
+    ];
+    let value_width = rows
+        .iter()
+        .map(|(_, value)| value.to_string().len())
+        .max()
+        .unwrap_or(0);
+    rows.iter()
+        .map(|(label, value)| format!("{label:<24}  {value:>value_width$}\n"))
+        .collect()
+}
+
+/// Loads the file of `args`, or says why it could not.
+pub fn load(args: &EvalArgs) -> Option<SpreadSheet> {
+    read(&args.path)
+}
+
+fn read(path: &Path) -> Option<SpreadSheet> {
+    // Files long enough to report their progress show it on a line of stderr
+    let mut reported = false;
+    let mut progress = |done: usize, total: usize| {
+        if done < total || reported {
+            reported = true;
+            eprint!("\rComputing {}%", done * 100 / total);
+            if done == total {
+                eprintln!();
+            }
+        }
+    };
+    SpreadSheet::load_from_file_with_progress(path, &mut progress)
+        .map_err(|err| eprintln!("Cannot read {}: {err}", path.display()))
+        .ok()
+}
+
+/// Prints the values of `sheet` to stdout and its errors to stderr, returning whether
+/// there were none.
+fn print_sheet(sheet: &SpreadSheet, format: OutputFormat) -> bool {
+    let (output, errors) = sheet_output(sheet, format);
+    print!("{output}");
+    for error in &errors {
+        eprintln!("{error}");
+    }
+    errors.is_empty()
+}
+
+/// Returns what is printed for `sheet`: its values in `format` and a line per error.
+fn sheet_output(sheet: &SpreadSheet, format: OutputFormat) -> (String, Vec<String>) {
+    let errors = sheet
+        .errors()
+        .into_iter()
+        .map(|(index, err)| {
+            let message = err.message(Language::English);
+            format!("{}: {err} {message}", index_to_name(index))
+        })
+        .collect();
+    (render(sheet, format), errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_eval_args() {
+        assert_eq!(
+            parse_eval_args(&args(&["input.sheet"])),
+            Ok(EvalArgs {
+                path: PathBuf::from("input.sheet"),
+                format: OutputFormat::Table,
+            })
+        );
+        assert_eq!(
+            parse_eval_args(&args(&["--format", "json", "input.sheet"])),
+            Ok(EvalArgs {
+                path: PathBuf::from("input.sheet"),
+                format: OutputFormat::Json,
+            })
+        );
+
+        assert!(parse_eval_args(&args(&[])).is_err());
+        assert!(parse_eval_args(&args(&["a.sheet", "b.sheet"])).is_err());
+        assert!(parse_eval_args(&args(&["a.sheet", "--format"])).is_err());
+        assert!(parse_eval_args(&args(&["a.sheet", "--format", "xml"])).is_err());
+        assert!(parse_eval_args(&args(&["a.sheet", "--verbose"])).is_err());
+    }
+
+    #[test]
+    fn test_eval_html() {
+        let path = std::env::temp_dir().join("mini_spreadsheet_eval.sheet");
+        std::fs::write(&path, "Price | 2\nDouble | =B1 * 2 | =B1 + TRUE\n").unwrap();
+        let args = args(&[path.to_str().unwrap(), "--format", "html"]);
+
+        let args = parse_eval_args(&args).unwrap();
+        let sheet = load(&args).unwrap();
+        let (output, errors) = sheet_output(&sheet, args.format);
+        assert!(output.starts_with("<table>\n  <tr>\n    <td class=\"text\">Price</td>"));
+        assert!(output.contains("<td class=\"num\">4</td>"));
+        assert!(output.contains("<td class=\"err\">#VALUE!</td>"));
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("C2: #VALUE!"));
+    }
+
+    #[test]
+    fn test_diff_exit_codes() {
+        let dir = std::env::temp_dir();
+        let (old, new) = (
+            dir.join("mini_spreadsheet_old.sheet"),
+            dir.join("mini_spreadsheet_new.sheet"),
+        );
+        std::fs::write(&old, "1 | =A1 * 2\n").unwrap();
+        std::fs::write(&new, "1 | =A1 * 3\n").unwrap();
+        let paths = |a: &Path, b: &Path| args(&[a.to_str().unwrap(), b.to_str().unwrap()]);
+
+        assert_eq!(run_diff(&paths(&old, &old)), 0);
+        assert_eq!(run_diff(&paths(&old, &new)), 1);
+        assert_eq!(
+            run_diff(&paths(&old, &dir.join("mini_spreadsheet_missing.sheet"))),
+            2
+        );
+        assert_eq!(run_diff(&args(&["only_one.sheet"])), 2);
+    }
+
+    #[test]
+    fn test_stats_table() {
+        let path = std::env::temp_dir().join("mini_spreadsheet_stats.sheet");
+        std::fs::write(&path, "1 | =A1 * 2\n=B1 + 1 | =A2 + TRUE\n").unwrap();
+        let sheet = read(&path).unwrap();
+
+        let table = stats_table(&sheet.stats());
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 8);
+        // The numbers end every line at the same column
+        assert!(lines.iter().all(|line| line.len() == lines[0].len()));
+        let value = |line: &str| line.rsplit(' ').next().unwrap().to_string();
+        assert!(lines[0].starts_with("Cells "));
+        assert_eq!(value(lines[0]), "4");
+        assert_eq!(value(lines[1]), "3");
+        assert_eq!(value(lines[3]), "1");
+        assert!(lines[5].starts_with("Longest dependency chain "));
+        // A1 -> B1 -> A2 -> B2
+        assert_eq!(value(lines[5]), "3");
+        assert_eq!(run_stats(&args(&[])), 2);
+    }
+
+    #[test]
+    fn test_parse_watch_args() {
+        let (eval, gui) = parse_watch_args(&args(&["--gui", "data.sheet"])).unwrap();
+        assert_eq!(eval.path, PathBuf::from("data.sheet"));
+        assert!(gui);
+
+        let (eval, gui) = parse_watch_args(&args(&["data.sheet", "--format", "csv"])).unwrap();
+        assert_eq!(eval.format, OutputFormat::Csv);
+        assert!(!gui);
+
+        assert!(parse_watch_args(&args(&["--gui"])).is_err());
+    }
+}
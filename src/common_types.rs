@@ -1,8 +1,18 @@
 use std::fmt::Display;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::spreadsheet::SpreadSheet;
+use error_text::ErrorText;
+
+pub use cell_addr::{col_to_letters, index_to_name, letters_to_col, name_to_index, AddrError};
+
+pub mod cell_addr;
+pub mod error_text;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
-    CellName(String),
+    CellName(Index),
     Number(f64),
     StringLiteral(String),
     Plus,
@@ -13,7 +23,7 @@ pub enum Token {
     RParen,
     Colon,
     Comma,
-    FunctionName(String),
+    FunctionName(FunctionId),
     Bool(bool),
 
     // logical operators
@@ -45,9 +55,84 @@ impl Token {
     }
 }
 
+impl Display for Token {
+    /// Writes the token as it is typed in a formula.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::CellName(index) => write!(f, "{}", index_to_name(*index)),
+            Token::FunctionName(name) => write!(f, "{name}"),
+            Token::Number(num) => write!(f, "{num}"),
+            Token::StringLiteral(s) => write!(f, "\"{s}\""),
+            Token::Bool(bool) => write!(f, "{}", bool.to_string().to_uppercase()),
+            Token::Plus => write!(f, "+"),
+            Token::Minus => write!(f, "-"),
+            Token::Division => write!(f, "/"),
+            Token::Multiply => write!(f, "*"),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::Colon => write!(f, ":"),
+            Token::Comma => write!(f, ","),
+            Token::Equals => write!(f, "=="),
+            Token::NotEquals => write!(f, "!="),
+            Token::GreaterThan => write!(f, ">"),
+            Token::LessThan => write!(f, "<"),
+            Token::GreaterEquals => write!(f, ">="),
+            Token::LessEquals => write!(f, "<="),
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+            Token::Not => write!(f, "!"),
+        }
+    }
+}
+
+/// A function a formula calls, interned against the builtin functions so that formulas do
+/// not each keep a copy of the names they call. Names no builtin has are kept as written,
+/// for the error computing the call gives.
+#[derive(Debug, PartialEq, Clone)]
+pub enum FunctionId {
+    /// The position of the function in `SpreadSheet::builtin_functions`.
+    Builtin(u16),
+    Unknown(Box<str>),
+}
+
+impl FunctionId {
+    pub fn new(name: &str) -> Self {
+        Self::find(name.chars()).unwrap_or_else(|| FunctionId::Unknown(name.into()))
+    }
+
+    /// Interns the name made of `chars`, as the tokenizer reads it.
+    pub fn from_chars(chars: &[char]) -> Self {
+        Self::find(chars.iter().copied())
+            .unwrap_or_else(|| FunctionId::Unknown(chars.iter().collect::<String>().into()))
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        match self {
+            FunctionId::Builtin(position) => {
+                SpreadSheet::builtin_functions()[usize::from(*position)].name
+            }
+            FunctionId::Unknown(name) => name,
+        }
+    }
+
+    fn find(name: impl Iterator<Item = char> + Clone) -> Option<Self> {
+        let position = SpreadSheet::builtin_functions()
+            .binary_search_by(|function| function.name.chars().cmp(name.clone()))
+            .ok()?;
+        Some(FunctionId::Builtin(position as u16))
+    }
+}
+
+impl Display for FunctionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum AST {
-    CellName(String),
+    CellName(Index),
     Value(Value),
     BinaryOp {
         op: Token,
@@ -59,19 +144,99 @@ pub enum AST {
         expr: Box<AST>,
     },
     Range {
-        from: String,
-        to: String,
+        from: Index,
+        to: Index,
     },
     FunctionCall {
-        name: String,
+        name: FunctionId,
         arguments: Vec<AST>,
     },
+    /// A name standing for a value, like a constant. See `SpreadSheet::define_constant`.
+    Name(Box<str>),
+}
+
+impl AST {
+    /// Writes the expression back as the text of a formula, without its leading `=`, in
+    /// canonical form: single spaces around binary operators, none inside parentheses, one
+    /// after each comma, and parentheses only where precedence needs them. Parsing the text
+    /// gives back the same AST.
+    #[must_use]
+    pub fn to_formula_string(&self) -> String {
+        match self {
+            AST::CellName(index) => index_to_name(*index),
+            AST::Name(name) => name.to_string(),
+            AST::Value(Value::Text(text)) => format!("\"{text}\""),
+            AST::Value(value) => value.to_string(),
+            AST::Range { from, to } => {
+                format!("{}:{}", index_to_name(*from), index_to_name(*to))
+            }
+            AST::FunctionCall { name, arguments } => {
+                let arguments: Vec<String> = arguments.iter().map(AST::to_formula_string).collect();
+                format!("{name}({})", arguments.join(", "))
+            }
+            AST::UnaryOp { op, expr } => match **expr {
+                AST::BinaryOp { .. } => format!("{op}({})", expr.to_formula_string()),
+                _ => format!("{op}{}", expr.to_formula_string()),
+            },
+            AST::BinaryOp { op, left, right } => {
+                // Operators of the same precedence group to the left
+                let precedence = op.get_precedence();
+                let operand = |ast: &AST, min_precedence: usize| match ast {
+                    AST::BinaryOp { op, .. } if op.get_precedence() < min_precedence => {
+                        format!("({})", ast.to_formula_string())
+                    }
+                    _ => ast.to_formula_string(),
+                };
+                format!(
+                    "{} {op} {}",
+                    operand(left, precedence),
+                    operand(right, precedence + 1)
+                )
+            }
+        }
+    }
+}
+
+impl Drop for AST {
+    /// Takes the tree apart one node at a time, as dropping the boxes of thousands of chained
+    /// operations one inside the other would overflow the stack.
+    fn drop(&mut self) {
+        let mut nodes = Vec::new();
+        self.take_children(&mut nodes);
+        while let Some(mut node) = nodes.pop() {
+            node.take_children(&mut nodes);
+        }
+    }
+}
+
+impl AST {
+    fn take_children(&mut self, nodes: &mut Vec<AST>) {
+        let mut take = |ast: &mut AST| {
+            nodes.push(std::mem::replace(ast, AST::Value(Value::Bool(false))));
+        };
+        match self {
+            AST::BinaryOp { left, right, .. } => {
+                take(left);
+                take(right);
+            }
+            AST::UnaryOp { expr, .. } => take(expr),
+            AST::FunctionCall { arguments, .. } => nodes.append(arguments),
+            AST::CellName(_) | AST::Value(_) | AST::Range { .. } | AST::Name(_) => {}
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Expression {
-    pub ast: AST,
+    /// Shared by the copies of the cell, like those computed in the background.
+    pub ast: Arc<AST>,
     pub dependencies: Vec<Index>,
+    /// The names the expression reads, computed again whenever one of them is defined.
+    pub names: Vec<Box<str>>,
+    /// Whether the expression reads cells only known once it is computed, through
+    /// `indirect` or `offset`. Those are not among its dependencies, so it is computed again
+    /// on every change to the sheet instead.
+    pub volatile: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -85,6 +250,18 @@ pub enum Value {
     Text(String),
     Number(f64),
     Bool(bool),
+    /// A matrix of values, a row at a time, like those `transpose` gives. A cell holding one
+    /// shows its top left value.
+    Array(Vec<Vec<Value>>),
+    /// Text showing `label` that leads to `url`, like those `hyperlink` gives. It reads as its
+    /// label wherever text is expected.
+    Link {
+        url: String,
+        label: String,
+    },
+    /// Numbers drawn as a tiny line chart in the cell, like those `sparkline` gives. Where
+    /// there is no room for a chart it reads as a bar per number, see `sparkline_text`.
+    Sparkline(Vec<f64>),
 }
 
 impl Display for Value {
@@ -93,11 +270,66 @@ impl Display for Value {
             Value::Text(s) => write!(f, "{s}"),
             Value::Number(num) => write!(f, "{num}"),
             Value::Bool(bool) => write!(f, "{}", bool.to_string().to_uppercase()),
+            Value::Array(_) => write!(f, "{}", self.clone().into_shown()),
+            Value::Link { label, .. } => write!(f, "{label}"),
+            Value::Sparkline(points) => write!(f, "{}", sparkline_text(points)),
         }
     }
 }
 
+/// Writes `points` as a bar for each, from the lowest `▁` for the smallest number to the
+/// highest `█` for the largest. Numbers all the same are written as the lowest bars.
+pub fn sparkline_text(points: &[f64]) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let min = points.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = points.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    points
+        .iter()
+        .map(|point| {
+            let level = match max > min {
+                true => ((point - min) / (max - min) * (BARS.len() - 1) as f64).round(),
+                false => 0.0,
+            };
+            BARS[level as usize]
+        })
+        .collect()
+}
+
 impl Value {
+    /// Returns the value a cell holding this one shows: the top left value of an array, or
+    /// empty text for an empty one.
+    #[must_use]
+    pub fn into_shown(self) -> Value {
+        match self {
+            Value::Array(rows) => rows
+                .into_iter()
+                .next()
+                .and_then(|row| row.into_iter().next())
+                .map_or(Value::Text(String::new()), Value::into_shown),
+            value => value,
+        }
+    }
+
+    /// Returns the text a link reads as, its label, leaving any other value as it is.
+    #[must_use]
+    pub fn into_label(self) -> Value {
+        match self {
+            Value::Link { label, .. } => Value::Text(label),
+            value => value,
+        }
+    }
+
+    /// Returns where the value leads when followed: the url of a link, or text that is a web
+    /// or mail address itself. An array leads where its top left value does.
+    pub fn link_url(&self) -> Option<&str> {
+        match self {
+            Value::Link { url, .. } => Some(url),
+            Value::Text(text) if looks_like_url(text) => Some(text),
+            Value::Array(rows) => rows.first()?.first()?.link_url(),
+            _ => None,
+        }
+    }
+
     #[must_use]
     pub fn add(&self, other: Value) -> Option<Value> {
         match (self, other) {
@@ -173,28 +405,398 @@ impl Value {
 
 }
 
-#[derive(Debug, Clone)]
-pub struct ParseError(pub String);
+/// Returns true for text starting with a scheme links are followed for, without any space.
+fn looks_like_url(text: &str) -> bool {
+    ["http://", "https://", "mailto:"].iter().any(|scheme| {
+        text.len() > scheme.len() && text.starts_with(scheme) && !text.contains(char::is_whitespace)
+    })
+}
 
-#[derive(Debug, Clone)]
+/// How computed values are written in cells. Numbers keep their full precision, only the
+/// text shown for them is rounded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplaySettings {
+    /// Most decimals shown, trailing zeros are dropped.
+    pub max_decimals: usize,
+    /// Numbers at least this large are shown in scientific notation, as are those too small
+    /// to show with `max_decimals`.
+    pub scientific_threshold: f64,
+    /// Put between each group of three digits of the integer part, if any.
+    pub thousands_separator: Option<char>,
+    pub decimal_separator: char,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        Self {
+            max_decimals: 6,
+            scientific_threshold: 1E15,
+            thousands_separator: Some(','),
+            decimal_separator: '.',
+        }
+    }
+}
+
+impl DisplaySettings {
+    pub fn format_number(&self, num: f64) -> String {
+        if !num.is_finite() {
+            return num.to_string();
+        }
+        if num == 0.0 {
+            return "0".to_string();
+        }
+        let smallest_shown = 0.5 * 10f64.powi(-(self.max_decimals as i32));
+        if num.abs() >= self.scientific_threshold || num.abs() < smallest_shown {
+            return fmt_f64(num, 10, 3, 2);
+        }
+
+        let fixed = format!("{:.*}", self.max_decimals, num.abs());
+        let (integer, decimals) = fixed.split_once('.').unwrap_or((&fixed, ""));
+        self.join_digits(num < 0.0, integer, decimals.trim_end_matches('0'))
+    }
+
+    /// Writes `num` with exactly `decimals` decimals, zeros included, grouping its integer
+    /// part like `format_number`. Numbers too large for it are written like `format_number`
+    /// writes them.
+    pub fn format_fixed(&self, num: f64, decimals: usize) -> String {
+        if !num.is_finite() || num.abs() >= self.scientific_threshold {
+            return self.format_number(num);
+        }
+        let fixed = format!("{:.*}", decimals, num.abs());
+        let (integer, decimals) = fixed.split_once('.').unwrap_or((&fixed, ""));
+        // Rounded to zero, a negative number loses its sign
+        let negative = num < 0.0 && fixed.bytes().any(|b| (b'1'..=b'9').contains(&b));
+        self.join_digits(negative, integer, decimals)
+    }
+
+    /// Returns how many decimals `format_number` writes `num` with.
+    pub fn shown_decimals(&self, num: f64) -> usize {
+        let text = self.format_number(num);
+        if text.contains('e') {
+            return 0;
+        }
+        text.split_once(self.decimal_separator)
+            .map_or(0, |(_, decimals)| decimals.len())
+    }
+
+    fn join_digits(&self, negative: bool, integer: &str, decimals: &str) -> String {
+        let mut text = String::new();
+        if negative {
+            text.push('-');
+        }
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i).is_multiple_of(3) {
+                text.extend(self.thousands_separator);
+            }
+            text.push(digit);
+        }
+        if !decimals.is_empty() {
+            text.push(self.decimal_separator);
+            text.push_str(decimals);
+        }
+        text
+    }
+}
+
+/// Returns the text shown in a cell for its computed value, empty for an empty cell.
+pub fn computed_to_text(
+    computed: Option<Result<Value, ComputeError>>,
+    display: &DisplaySettings,
+) -> String {
+    computed_to_text_as(computed, display, NumberFormat::General)
+}
+
+/// Like `computed_to_text`, writing a number in `format`.
+pub fn computed_to_text_as(
+    computed: Option<Result<Value, ComputeError>>,
+    display: &DisplaySettings,
+    format: NumberFormat,
+) -> String {
+    match computed {
+        Some(Ok(Value::Text(s))) => s,
+        Some(Ok(Value::Number(num))) => match format {
+            NumberFormat::General => display.format_number(num),
+            NumberFormat::Percent => format!("{}%", display.format_number(num * 100.0)),
+            NumberFormat::Fixed { decimals } => display.format_fixed(num, decimals.into()),
+            NumberFormat::FixedPercent { decimals } => {
+                format!("{}%", display.format_fixed(num * 100.0, decimals.into()))
+            }
+            NumberFormat::Currency { symbol, decimals } => {
+                let text = display.format_fixed(num.abs(), decimals.into());
+                // The sign goes before the symbol
+                if num < 0.0 && text != display.format_fixed(0.0, decimals.into()) {
+                    format!("-{symbol}{text}")
+                } else {
+                    format!("{symbol}{text}")
+                }
+            }
+        },
+        Some(Ok(Value::Bool(b))) => b.to_string(),
+        Some(Ok(array @ Value::Array(_))) => {
+            computed_to_text_as(Some(Ok(array.into_shown())), display, format)
+        }
+        Some(Ok(Value::Link { label, .. })) => label,
+        Some(Ok(Value::Sparkline(points))) => sparkline_text(&points),
+        Some(Err(err)) => err.to_string(),
+        None => String::new(),
+    }
+}
+
+/*
+   Format a float into scientific notation such as: 42.0 -> 4.200e+01
+   width controls the amount of left padded spaces
+   precision is the amount of decimals
+   exp_pad controls the amount of left padded 0s
+*/
+fn fmt_f64(num: f64, width: usize, precision: usize, exp_pad: usize) -> String {
+    let mut num = format!("{:.precision$e}", num, precision = precision);
+    // Safe to `unwrap` as `num` is guaranteed to contain `'e'`
+    let exp = num.split_off(num.find('e').expect("safe"));
+
+    let (sign, exp) = match exp.strip_prefix("e-") {
+        Some(exp) => ('-', exp),
+        None => ('+', &exp[1..]),
+    };
+    num.push_str(&format!("e{}{:0>pad$}", sign, exp, pad = exp_pad));
+
+    format!("{:>width$}", num, width = width)
+}
+
+/// Why content could not be parsed, and where in it when that is known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    /// The bytes of the content the error was found at, empty when it was found at its end.
+    pub span: Option<Range<usize>>,
+}
+
+impl ParseError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            span: None,
+        }
+    }
+
+    /// Returns the error found at the bytes `span` of `content`, telling in its message at
+    /// which character, counted from 1.
+    pub fn at(message: &str, content: &str, span: Range<usize>) -> Self {
+        let position = content[..span.start].chars().count() + 1;
+        Self {
+            message: format!("{message} at position {position}"),
+            span: Some(span),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum ComputeError {
     ParseError(String),
     TypeError(String),
     UnfindableReference(String),
     Cycle,
     UnknownFunction(String),
-    InvalidArgument(String)
+    InvalidArgument(String),
+    DivisionByZero,
+    /// An operation or function gave a number that is not finite, like infinity or NaN.
+    NotANumber,
+    /// The cells reached by `indirect` or `offset` nest deeper than the limit given.
+    DepthExceeded(usize),
 }
 
 impl Display for ComputeError {
+    /// Writes the short code of the error, see `ErrorText`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.short_code())
+    }
+}
+
+/// Why a change to the content of a sheet was refused.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditError {
+    /// The cell is locked and the sheet protected.
+    CellLocked(Index),
+    /// The cell was to be written into but holds content that would be lost.
+    CellOccupied(Index),
+}
+
+impl Display for EditError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ComputeError::ParseError(_) => write!(f, "!-PARSE ERROR-!"),
-            ComputeError::TypeError(_) => write!(f, "!-TYPE ERROR-!"),
-            ComputeError::UnfindableReference(_) => write!(f, "!-REFERENCE ERROR-!"),
-            ComputeError::Cycle => write!(f, "!-CYCLIC REFERENCE-!"),
-            ComputeError::UnknownFunction(_) => write!(f, "!-UNKNOWN FUNCTION-!"),
-            ComputeError::InvalidArgument(_) => write!(f, "!-INVALID FUNCTION ARGUMENT-!"),
+            EditError::CellLocked(index) => {
+                write!(f, "Cell {} is locked", index_to_name(*index))
+            }
+            EditError::CellOccupied(index) => {
+                write!(f, "Cell {} is not empty", index_to_name(*index))
+            }
+        }
+    }
+}
+
+/// How a cell is displayed, kept with the cell but apart from its content so that changing
+/// one leaves the other as it is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CellStyle {
+    /// The red, green, blue and alpha of the background.
+    pub fill: Option<[u8; 4]>,
+    pub bold: bool,
+    pub italic: bool,
+    pub number_format: NumberFormat,
+    /// Whether the content is kept from changing while the sheet is protected, see
+    /// `SpreadSheet::set_protection`.
+    pub locked: bool,
+    /// The lines drawn along the edges, over the grid lines.
+    pub borders: Borders,
+}
+
+/// A line drawn along an edge of a cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BorderStyle {
+    /// The thickness in pixels.
+    pub width: u8,
+    /// The red, green, blue and alpha of the line.
+    pub color: [u8; 4],
+}
+
+/// An edge of a cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl Edge {
+    pub const ALL: [Edge; 4] = [Edge::Top, Edge::Bottom, Edge::Left, Edge::Right];
+
+    /// Returns the edge of the neighbouring cell this one is shared with.
+    pub fn opposite(self) -> Edge {
+        match self {
+            Edge::Top => Edge::Bottom,
+            Edge::Bottom => Edge::Top,
+            Edge::Left => Edge::Right,
+            Edge::Right => Edge::Left,
+        }
+    }
+}
+
+/// The borders along the edges of a cell, `None` for an edge without one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Borders {
+    pub top: Option<BorderStyle>,
+    pub bottom: Option<BorderStyle>,
+    pub left: Option<BorderStyle>,
+    pub right: Option<BorderStyle>,
+}
+
+impl Borders {
+    pub fn get(&self, edge: Edge) -> Option<BorderStyle> {
+        match edge {
+            Edge::Top => self.top,
+            Edge::Bottom => self.bottom,
+            Edge::Left => self.left,
+            Edge::Right => self.right,
+        }
+    }
+
+    pub fn set(&mut self, edge: Edge, border: Option<BorderStyle>) {
+        match edge {
+            Edge::Top => self.top = border,
+            Edge::Bottom => self.bottom = border,
+            Edge::Left => self.left = border,
+            Edge::Right => self.right = border,
+        }
+    }
+}
+
+/// How the number in a cell is written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NumberFormat {
+    /// As `DisplaySettings` writes numbers.
+    #[default]
+    General,
+    /// A hundred times larger, followed by `%`. Set on cells a percentage is typed into.
+    Percent,
+    /// With exactly `decimals` decimals, its integer part grouped like `General` does.
+    Fixed { decimals: u8 },
+    /// Like `Percent`, with exactly `decimals` decimals.
+    FixedPercent { decimals: u8 },
+    /// Like `Fixed`, after `symbol`.
+    Currency { symbol: char, decimals: u8 },
+}
+
+impl NumberFormat {
+    /// The most decimals a format with a fixed number of them shows.
+    pub const MAX_DECIMALS: u8 = 15;
+
+    /// Returns true if numbers are written as percentages.
+    pub fn is_percent(self) -> bool {
+        matches!(self, Self::Percent | Self::FixedPercent { .. })
+    }
+
+    /// Returns true if both formats write numbers the same way but for their decimals and
+    /// currency symbol.
+    pub fn is_like(self, other: Self) -> bool {
+        let kind = |format: Self| match format {
+            Self::FixedPercent { .. } => std::mem::discriminant(&Self::Percent),
+            format => std::mem::discriminant(&format),
+        };
+        kind(self) == kind(other)
+    }
+
+    /// Returns the format showing `change` more decimals, or fewer when negative, than this
+    /// one. Formats without a fixed number of them start from the `shown` decimals.
+    pub fn change_decimals(self, change: i32, shown: usize) -> Self {
+        let decimals =
+            |decimals: usize| (decimals as i32 + change).clamp(0, Self::MAX_DECIMALS.into()) as u8;
+        match self {
+            Self::General => Self::Fixed {
+                decimals: decimals(shown),
+            },
+            Self::Percent => Self::FixedPercent {
+                decimals: decimals(shown),
+            },
+            Self::Fixed { decimals: current } => Self::Fixed {
+                decimals: decimals(current.into()),
+            },
+            Self::FixedPercent { decimals: current } => Self::FixedPercent {
+                decimals: decimals(current.into()),
+            },
+            Self::Currency {
+                symbol,
+                decimals: current,
+            } => Self::Currency {
+                symbol,
+                decimals: decimals(current.into()),
+            },
+        }
+    }
+}
+
+/// A flag of `CellStyle` changing how the text of a cell is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextStyle {
+    Bold,
+    Italic,
+}
+
+impl CellStyle {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn has(&self, text_style: TextStyle) -> bool {
+        match text_style {
+            TextStyle::Bold => self.bold,
+            TextStyle::Italic => self.italic,
+        }
+    }
+
+    pub fn set(&mut self, text_style: TextStyle, on: bool) {
+        match text_style {
+            TextStyle::Bold => self.bold = on,
+            TextStyle::Italic => self.italic = on,
         }
     }
 }
@@ -202,19 +804,27 @@ impl Display for ComputeError {
 #[derive(Debug, Clone)]
 pub struct Cell {
     pub needs_compute: bool,
+    /// Empty for a cell holding nothing but its style.
     pub raw_representation: String,
     pub parsed_representation: Option<Result<ParsedCell, ParseError>>,
     pub computed_value: Option<Result<Value, ComputeError>>,
+    pub style: CellStyle,
+    /// Whether the cell holds a formula that does not parse, left as written by a change that
+    /// rewrote the references of the others. Its own likely point at the wrong cells now.
+    pub needs_attention: bool,
 }
 
 impl Cell {
     #[must_use]
     pub fn from_raw(raw: String) -> Self {
         Self {
+            // A cell holding nothing but its style has nothing to compute
+            needs_compute: !raw.is_empty(),
             raw_representation: raw,
             parsed_representation: None,
             computed_value: None,
-            needs_compute: true,
+            style: CellStyle::default(),
+            needs_attention: false,
         }
     }
 }
@@ -224,3 +834,178 @@ pub struct Index {
     pub x: usize,
     pub y: usize,
 }
+
+/// A cell or a rectangle of cells referenced by a formula, the range given by two corners
+/// as written.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Reference {
+    Cell(Index),
+    Range(Index, Index),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_number_grouping() {
+        let display = DisplaySettings::default();
+        assert_eq!(display.format_number(1234567.891), "1,234,567.891");
+        assert_eq!(display.format_number(123.0), "123");
+        assert_eq!(display.format_number(1000.0), "1,000");
+        assert_eq!(display.format_number(-98765.4), "-98,765.4");
+        assert_eq!(display.format_number(-0.0), "0");
+
+        let display = DisplaySettings {
+            thousands_separator: Some('.'),
+            decimal_separator: ',',
+            ..DisplaySettings::default()
+        };
+        assert_eq!(display.format_number(1234567.891), "1.234.567,891");
+        let display = DisplaySettings {
+            thousands_separator: None,
+            ..DisplaySettings::default()
+        };
+        assert_eq!(display.format_number(1234567.0), "1234567");
+    }
+
+    #[test]
+    fn test_format_number_rounding() {
+        let display = DisplaySettings::default();
+        assert_eq!(display.format_number(0.1 + 0.2), "0.3");
+        assert_eq!(display.format_number(2.0 / 3.0), "0.666667");
+        assert_eq!(display.format_number(-1.0 / 3.0), "-0.333333");
+        assert_eq!(display.format_number(999.9999999), "1,000");
+
+        let display = DisplaySettings {
+            max_decimals: 2,
+            ..DisplaySettings::default()
+        };
+        assert_eq!(display.format_number(12.3456), "12.35");
+    }
+
+    #[test]
+    fn test_format_number_scientific() {
+        let display = DisplaySettings::default();
+        assert_eq!(display.format_number(1E15), " 1.000e+15");
+        assert_eq!(display.format_number(-2.5E20), "-2.500e+20");
+        assert_eq!(
+            display.format_number(999_999_999_999_999.0),
+            "999,999,999,999,999"
+        );
+        // Too small to show with six decimals
+        assert_eq!(display.format_number(1.5E-9), " 1.500e-09");
+        assert_eq!(display.format_number(f64::INFINITY), "inf");
+
+        let display = DisplaySettings {
+            scientific_threshold: 1E6,
+            ..DisplaySettings::default()
+        };
+        assert_eq!(display.format_number(1234567.0), " 1.235e+06");
+    }
+
+    #[test]
+    fn test_computed_to_text() {
+        let display = DisplaySettings::default();
+        let text = |computed| computed_to_text(computed, &display);
+        assert_eq!(text(Some(Ok(Value::Number(2500.5)))), "2,500.5");
+        assert_eq!(text(Some(Ok(Value::Text("a".to_string())))), "a");
+        assert_eq!(text(Some(Ok(Value::Bool(true)))), "true");
+        assert_eq!(text(Some(Err(ComputeError::Cycle))), "#CYCLE!");
+        assert_eq!(text(Some(Err(ComputeError::NotANumber))), "#NUM!");
+        assert_eq!(text(None), "");
+
+        let percent = |num| {
+            computed_to_text_as(
+                Some(Ok(Value::Number(num))),
+                &display,
+                NumberFormat::Percent,
+            )
+        };
+        assert_eq!(percent(0.75), "75%");
+        assert_eq!(percent(12.5), "1,250%");
+        assert_eq!(percent(-0.001), "-0.1%");
+
+        let text =
+            |num, format| computed_to_text_as(Some(Ok(Value::Number(num))), &display, format);
+        assert_eq!(
+            text(1234.5, NumberFormat::Fixed { decimals: 2 }),
+            "1,234.50"
+        );
+        assert_eq!(
+            text(0.125, NumberFormat::FixedPercent { decimals: 1 }),
+            "12.5%"
+        );
+        let euros = NumberFormat::Currency {
+            symbol: '€',
+            decimals: 2,
+        };
+        assert_eq!(text(-1234.567, euros), "-€1,234.57");
+        assert_eq!(text(-0.001, euros), "€0.00");
+        assert_eq!(text(2.0, NumberFormat::Fixed { decimals: 0 }), "2");
+    }
+
+    #[test]
+    fn test_change_decimals() {
+        let fixed = |decimals| NumberFormat::Fixed { decimals };
+        // Shown decimals are where the change starts from
+        assert_eq!(NumberFormat::General.change_decimals(1, 3), fixed(4));
+        assert_eq!(
+            NumberFormat::Percent.change_decimals(-1, 1),
+            NumberFormat::FixedPercent { decimals: 0 }
+        );
+        assert_eq!(fixed(2).change_decimals(1, 7), fixed(3));
+        assert_eq!(fixed(0).change_decimals(-1, 0), fixed(0));
+        assert_eq!(fixed(15).change_decimals(1, 0), fixed(15));
+
+        assert!(NumberFormat::Percent.is_like(NumberFormat::FixedPercent { decimals: 3 }));
+        assert!(!fixed(2).is_like(NumberFormat::General));
+
+        let display = DisplaySettings::default();
+        assert_eq!(display.shown_decimals(1.25), 2);
+        assert_eq!(display.shown_decimals(1000.0), 0);
+        assert_eq!(display.shown_decimals(1E20), 0);
+    }
+
+    #[test]
+    fn test_link_shows_label() {
+        let link = Value::Link {
+            url: "https://example.com".to_string(),
+            label: "Example".to_string(),
+        };
+        assert_eq!(link.to_string(), "Example");
+        assert_eq!(
+            computed_to_text(Some(Ok(link.clone())), &DisplaySettings::default()),
+            "Example"
+        );
+        assert_eq!(link.link_url(), Some("https://example.com"));
+        assert_eq!(link.into_label(), Value::Text("Example".to_string()));
+
+        let url = |text: &str| Value::Text(text.to_string()).link_url().is_some();
+        assert!(url("https://example.com/a?b=c"));
+        assert!(url("mailto:someone@example.com"));
+        for text in [
+            "https://",
+            "see https://example.com",
+            "http://a b",
+            "example.com",
+        ] {
+            assert!(!url(text), "{text}");
+        }
+    }
+
+    #[test]
+    fn test_sparkline_text() {
+        assert_eq!(sparkline_text(&[1.0, 3.0, 5.0, 7.0]), "▁▃▆█");
+        assert_eq!(sparkline_text(&[-2.0, 12.0, 5.0]), "▁█▅");
+        assert_eq!(sparkline_text(&[4.0, 4.0]), "▁▁");
+        assert_eq!(sparkline_text(&[]), "");
+
+        let sparkline = Value::Sparkline(vec![0.0, 10.0, 5.0]);
+        assert_eq!(sparkline.to_string(), "▁█▅");
+        assert_eq!(
+            computed_to_text(Some(Ok(sparkline)), &DisplaySettings::default()),
+            "▁█▅"
+        );
+    }
+}
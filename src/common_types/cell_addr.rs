@@ -0,0 +1,157 @@
+use std::fmt;
+
+use super::Index;
+
+/// Why a column or cell name could not be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddrError {
+    /// The column letters are missing or not all uppercase letters.
+    InvalidColumn,
+    /// The row number is missing, not a number or 0.
+    InvalidRow,
+    /// The column or row is too large to be counted.
+    OutOfRange,
+}
+
+impl fmt::Display for AddrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            AddrError::InvalidColumn => "the column should be uppercase letters",
+            AddrError::InvalidRow => "the row should be a number starting at 1",
+            AddrError::OutOfRange => "the cell is out of range",
+        };
+        f.write_str(reason)
+    }
+}
+
+/// Returns the letters of the column at `col`, counting from "A" over "Z" to "AA" and on.
+pub fn col_to_letters(col: usize) -> String {
+    let mut letters = Vec::new();
+    let mut rest = col;
+    loop {
+        letters.push(b'A' + (rest % 26) as u8);
+        if rest < 26 {
+            break;
+        }
+        rest = rest / 26 - 1;
+    }
+    letters.iter().rev().map(|&letter| letter as char).collect()
+}
+
+/// Reads uppercase column letters like "C" or "AB" into the index of their column.
+pub fn letters_to_col(letters: &str) -> Result<usize, AddrError> {
+    if letters.is_empty() {
+        return Err(AddrError::InvalidColumn);
+    }
+    let mut col: usize = 0;
+    for c in letters.chars() {
+        if !c.is_ascii_uppercase() {
+            return Err(AddrError::InvalidColumn);
+        }
+        col = col
+            .checked_mul(26)
+            .and_then(|col| col.checked_add(c as usize - 'A' as usize + 1))
+            .ok_or(AddrError::OutOfRange)?;
+    }
+    // Adjust for 0-based indexing
+    Ok(col - 1)
+}
+
+/// Returns the name of the cell at `index`, like "C7".
+pub fn index_to_name(index: Index) -> String {
+    format!("{}{}", col_to_letters(index.x), index.y + 1)
+}
+
+/// Reads a cell name like "C7" or "AB12" into its index, uppercase column letters followed by
+/// a row number starting at 1.
+pub fn name_to_index(name: &str) -> Result<Index, AddrError> {
+    let digits_start = name
+        .find(|c: char| !c.is_ascii_uppercase())
+        .ok_or(AddrError::InvalidRow)?;
+    let (letters, digits) = name.split_at(digits_start);
+    let x = letters_to_col(letters)?;
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(AddrError::InvalidRow);
+    }
+    let y = digits.parse::<usize>().map_err(|_| AddrError::OutOfRange)?;
+    match y.checked_sub(1) {
+        Some(y) => Ok(Index { x, y }),
+        None => Err(AddrError::InvalidRow),
+    }
+}
+
+/// Returns the index of the cell named `name`, for tests to write cells by name.
+#[cfg(test)]
+pub(crate) fn cell(name: &str) -> Index {
+    name_to_index(name).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_to_index() {
+        assert_eq!(name_to_index("A1"), Ok(Index { x: 0, y: 0 }));
+        assert_eq!(name_to_index("C7"), Ok(Index { x: 2, y: 6 }));
+        assert_eq!(name_to_index("AA10"), Ok(Index { x: 26, y: 9 }));
+        assert_eq!(name_to_index("ZZZ3"), Ok(Index { x: 18_277, y: 2 }));
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        for name in ["", "A", "7", "1A", "a1", "A-1", "A 1"] {
+            let error = name_to_index(name).unwrap_err();
+            assert!(
+                matches!(error, AddrError::InvalidColumn | AddrError::InvalidRow),
+                "{name}"
+            );
+        }
+        assert_eq!(name_to_index("A0"), Err(AddrError::InvalidRow));
+        assert_eq!(name_to_index("A1B"), Err(AddrError::InvalidRow));
+        assert_eq!(name_to_index("a1"), Err(AddrError::InvalidColumn));
+        assert_eq!(
+            name_to_index("A99999999999999999999999"),
+            Err(AddrError::OutOfRange)
+        );
+        assert_eq!(letters_to_col(&"Z".repeat(20)), Err(AddrError::OutOfRange));
+        assert_eq!(letters_to_col(""), Err(AddrError::InvalidColumn));
+    }
+
+    #[test]
+    fn test_col_letters() {
+        let cols = [(0, "A"), (25, "Z"), (26, "AA"), (51, "AZ"), (52, "BA")];
+        let cols = cols
+            .into_iter()
+            .chain([(701, "ZZ"), (702, "AAA"), (18_277, "ZZZ")]);
+        for (col, letters) in cols {
+            assert_eq!(col_to_letters(col), letters);
+            assert_eq!(letters_to_col(letters), Ok(col));
+        }
+    }
+
+    #[test]
+    fn test_col_round_trip() {
+        let mut previous = String::new();
+        for col in 0..100_000 {
+            let letters = col_to_letters(col);
+            assert_eq!(letters_to_col(&letters), Ok(col), "{letters}");
+            // Columns count up like the names sort, shorter names first
+            assert!(
+                (previous.len(), &previous) < (letters.len(), &letters),
+                "{previous} {letters}"
+            );
+            previous = letters;
+        }
+    }
+
+    #[test]
+    fn test_name_round_trip() {
+        for x in [0, 1, 25, 26, 27, 51, 52, 701, 702, 18_277, 99_999] {
+            for y in [0, 9, 999_999] {
+                let name = index_to_name(Index { x, y });
+                assert_eq!(name_to_index(&name), Ok(Index { x, y }), "{name}");
+            }
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use super::ComputeError;
+
+/// The languages the messages of errors are written in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Language {
+    #[default]
+    English,
+}
+
+/// How an error is shown: a short code that fits in a cell and stays the same whatever the
+/// language, and a message in full for where there is room, like a tooltip or the terminal.
+pub trait ErrorText {
+    fn short_code(&self) -> &'static str;
+
+    fn message(&self, language: Language) -> String;
+}
+
+impl ErrorText for ComputeError {
+    fn short_code(&self) -> &'static str {
+        match self {
+            ComputeError::ParseError(_) => "#ERROR!",
+            ComputeError::TypeError(_) => "#VALUE!",
+            ComputeError::UnfindableReference(_) => "#REF!",
+            ComputeError::Cycle => "#CYCLE!",
+            ComputeError::UnknownFunction(_) => "#NAME?",
+            ComputeError::InvalidArgument(_) => "#VALUE!",
+            ComputeError::DivisionByZero => "#DIV/0!",
+            ComputeError::NotANumber => "#NUM!",
+            ComputeError::DepthExceeded(_) => "#DEPTH!",
+        }
+    }
+
+    fn message(&self, language: Language) -> String {
+        match language {
+            Language::English => english(self),
+        }
+    }
+}
+
+/// Returns what went wrong in English. Errors made while computing carry their reasons,
+/// which are in English too.
+fn english(error: &ComputeError) -> String {
+    match error {
+        ComputeError::ParseError(reason) => reason.clone(),
+        ComputeError::TypeError(message) => message.clone(),
+        ComputeError::UnfindableReference(message) => message.clone(),
+        ComputeError::Cycle => "Detected cyclic computation".to_string(),
+        ComputeError::UnknownFunction(f) => format!("Unknown function '{f}'"),
+        ComputeError::InvalidArgument(message) => message.clone(),
+        ComputeError::DivisionByZero => "Division by zero".to_string(),
+        ComputeError::NotANumber => "The result is not a finite number".to_string(),
+        ComputeError::DepthExceeded(depth) => {
+            format!("References reach more than {depth} cells deep")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_text() {
+        let errors = [
+            (
+                ComputeError::ParseError("Unexpected ')' at position 4".to_string()),
+                "#ERROR!",
+                "Unexpected ')' at position 4",
+            ),
+            (
+                ComputeError::TypeError("Cannot add TRUE".to_string()),
+                "#VALUE!",
+                "Cannot add TRUE",
+            ),
+            (
+                ComputeError::UnfindableReference("B7 is empty".to_string()),
+                "#REF!",
+                "B7 is empty",
+            ),
+            (
+                ComputeError::Cycle,
+                "#CYCLE!",
+                "Detected cyclic computation",
+            ),
+            (
+                ComputeError::UnknownFunction("foo".to_string()),
+                "#NAME?",
+                "Unknown function 'foo'",
+            ),
+            (
+                ComputeError::InvalidArgument("round expects a number".to_string()),
+                "#VALUE!",
+                "round expects a number",
+            ),
+            (ComputeError::DivisionByZero, "#DIV/0!", "Division by zero"),
+            (
+                ComputeError::NotANumber,
+                "#NUM!",
+                "The result is not a finite number",
+            ),
+            (
+                ComputeError::DepthExceeded(100),
+                "#DEPTH!",
+                "References reach more than 100 cells deep",
+            ),
+        ];
+        for (error, code, message) in errors {
+            assert_eq!(error.short_code(), code);
+            assert_eq!(error.to_string(), code);
+            assert_eq!(error.message(Language::English), message);
+        }
+        assert_eq!(Language::default(), Language::English);
+    }
+}
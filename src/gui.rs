@@ -1,455 +1,3952 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
 use macroquad::prelude::*;
+use macroquad::miniquad::{self, CursorIcon};
 use macroquad::ui::widgets::InputText;
 use macroquad::ui::{hash, root_ui, Skin};
 
-use crate::common_types::{ComputeError, Value};
-use crate::{common_types::Index, spreadsheet::SpreadSheet};
+use mini_spreadsheet::common_types::error_text::{ErrorText, Language};
+use mini_spreadsheet::common_types::{
+    col_to_letters, computed_to_text, computed_to_text_as, index_to_name, name_to_index,
+    BorderStyle, Borders, CellStyle, ComputeError, DisplaySettings, Edge, Index, NumberFormat,
+    ParseError, Reference, TextStyle, Value,
+};
+use mini_spreadsheet::spreadsheet::{
+    background::BackgroundCompute,
+    clipboard::{self, CopyMode},
+    journal::sidecar_path,
+    stats::SheetStats,
+    summary::SummaryStat,
+    validation::{ValidationKind, ValidationState},
+    view_state::ViewState,
+    watch::{apply_reload, FileWatcher},
+    cycle_anchors, FillMode, FunctionInfo, RangeSummary, RecalcMode, Snapshot, SpreadSheet,
+};
+
+use autocomplete::{complete, completion_prefix, matching_functions, Autocomplete};
+use config::GuiConfig;
+use context_menu::{BorderPlacement, ContextMenu, MenuAction, MenuTarget, NumberStyle};
+use dependants::{count_outside, dependants_to_text, DependantDepth, DependantHighlight};
+use dropdown::{Dropdown, DropdownEvent, DropdownKey};
+use duplicates::DuplicateHighlight;
+use edit_state::{classify_click, Click, ClickKind, Direction, EditKey, EditMode, EditState};
+use filter::{distinct_values, ColumnFilter, FilterChoice, FilterPicker, RowFilter};
+use format::{aligned_text_x, auto_alignment, Alignment, CellFormat, CELL_TEXT_PADDING};
+use highlight::{distinct_references, highlight_spans, matching_paren, reference_at, Highlight};
+use history::history_lines;
+use layout::AxisLayout;
+use links::{cell_link, follow_link, Opener, SystemOpener};
+use overwrite::{BlockWrite, OverwritePrompt};
+use point_mode::PointMode;
+use selection::{fill_target, normalize_range, range_contains, range_to_string};
+use sparkline::{sparkline_positions, SPARKLINE_PADDING};
+use text_cache::{FontFace, TextCache};
+use text_editor::{caret_at, caret_offsets, underline, KeyRepeat, TextEditor};
+use theme::Theme;
+use viewport::{scroll_bounds, Label, Viewport};
+use wrap::wrap_text;
+mod autocomplete;
+pub mod config;
+mod context_menu;
+mod dependants;
+mod dropdown;
+mod duplicates;
+mod edit_state;
+mod filter;
+mod format;
+mod highlight;
+mod history;
+mod layout;
+mod links;
+mod overwrite;
+mod point_mode;
+mod selection;
+mod sparkline;
+mod text_cache;
+mod text_editor;
+mod theme;
+mod viewport;
+mod wrap;
 
-// Window configuration
+// Window configuration, by default
 const INITIAL_WINDOW_WIDTH: f32 = 1200.0;
 const INITIAL_WINDOW_HEIGHT: f32 = 900.0;
 
-// Grid configuration
-const GRID_ROWS: usize = 20;
-const GRID_COLS: usize = 6;
+// The fonts cells are drawn with unless the config names others
+const REGULAR_FONT: &[u8] = include_bytes!("../fonts/ttf/Hack-Regular.ttf");
+const BOLD_FONT: &[u8] = include_bytes!("../fonts/ttf/Hack-Bold.ttf");
+const ITALIC_FONT: &[u8] = include_bytes!("../fonts/ttf/Hack-Italic.ttf");
+const BOLD_ITALIC_FONT: &[u8] = include_bytes!("../fonts/ttf/Hack-BoldItalic.ttf");
+
+// Grid configuration, the size of the reachable sheet, by default
+const GRID_ROWS: usize = 1_000_000;
+const GRID_COLS: usize = 18_278; // Up to column ZZZ
+const CELL_WIDTH: f32 = 100.0;
+const CELL_HEIGHT: f32 = 30.0;
+const MIN_CELL_WIDTH: f32 = 20.0;
+const MIN_CELL_HEIGHT: f32 = 10.0;
+// How close to a column boundary the mouse has to be to start resizing
+const RESIZE_GRAB_TOLERANCE: f32 = 4.0;
+const WHEEL_ROWS_PER_NOTCH: isize = 3;
 
 // Editor configuration
 const EDITOR_HEIGHT: f32 = 24.0;
 const EDITOR_TOP_MARGIN: f32 = 0.0;
 const EDITOR_PADDING: f32 = 20.0;
 const EDITOR_WINDOW_HEIGHT: f32 = EDITOR_HEIGHT + EDITOR_PADDING * 2.0;
+const EDITOR_STATUS_WIDTH: f32 = 320.0;
+const EDITOR_STATUS_FONT_SIZE: u16 = 12;
+const EDITOR_FONT_SIZE: u16 = 16;
+// Room left around the text of the in-cell editor
+const CELL_EDITOR_PADDING: f32 = 16.0;
+const NAME_BOX_WIDTH: f32 = 80.0;
+const NAME_BOX_MARGIN: f32 = 8.0;
+// How long the name box stays red after an invalid cell name, in seconds
+const NAME_BOX_FLASH_TIME: f64 = 0.6;
+// Room between the border of an editor field and its text
+const TEXT_FIELD_PADDING: f32 = 4.0;
+// How long the caret stays shown and hidden while blinking, in seconds
+const CARET_BLINK_TIME: f64 = 0.5;
+
+// How strongly a range referenced by a formula is tinted with its color
+const REFERENCE_FILL_ALPHA: f32 = 0.1;
+// How strongly each group of duplicates is tinted, enough to tell groups apart
+const DUPLICATE_FILL_ALPHA: f32 = 0.3;
+
+// Function suggestions
+const SUGGESTION_ROW_HEIGHT: f32 = 20.0;
+const SUGGESTION_FONT_SIZE: u16 = 14;
+const SUGGESTION_MIN_WIDTH: f32 = 160.0;
+
+// Label context menus
+const MENU_WIDTH: f32 = 150.0;
+const MENU_ITEM_HEIGHT: f32 = 22.0;
+const MENU_FONT_SIZE: u16 = 14;
+const MENU_SWATCH_MARGIN: f32 = 5.0;
+// The borders the cell menu draws, visible over both themes
+const MENU_BORDER: BorderStyle = BorderStyle {
+    width: 1,
+    color: [90, 90, 90, 255],
+};
+const MENU_OUTLINE_BORDER: BorderStyle = BorderStyle {
+    width: 2,
+    color: [90, 90, 90, 255],
+};
+
+// The panel listing the changes of a cell, at the right of the grid
+const HISTORY_PANEL_WIDTH: f32 = 360.0;
+const HISTORY_PANEL_ROWS: usize = 16;
+
+// Column filters
+const FILTER_ICON_SIZE: f32 = 8.0;
+const FILTER_ICON_MARGIN: f32 = 4.0;
+const FILTER_PICKER_WIDTH: f32 = 200.0;
+// How many values the filter picker lists at once
+const FILTER_PICKER_ROWS: usize = 12;
 
 // Cell styling
 const CELL_FONT_SIZE: u16 = 12;
+const WRAPPED_LINE_HEIGHT: f32 = 16.0;
 const SELECTED_CELL_BORDER_WIDTH: f32 = 3.0;
 const NORMAL_CELL_BORDER_WIDTH: f32 = 1.0;
-
-// Colors
-const BACKGROUND_COLOR: Color = BLACK;
-const GRID_BACKGROUND_COLOR: Color = WHITE;
-const SELECTED_CELL_BORDER_COLOR: Color = ORANGE;
-const NORMAL_CELL_BORDER_COLOR: Color = BLACK;
-const CELL_TEXT_COLOR: Color = BLACK;
+// How long the mouse has to rest on an error cell before its details show, in seconds
+const ERROR_DETAIL_DELAY: f64 = 0.5;
 
 // Labels
 const ROW_LABEL_WIDTH: f32 = 40.0;
+// The space between the two lines marking where columns or rows are hidden
+const HIDDEN_MARKER_GAP: f32 = 3.0;
 const COL_LABEL_HEIGHT: f32 = 30.0;
 const LABEL_FONT_SIZE: u16 = 10;
-const LABEL_TEXT_COLOR: Color = DARKGRAY;
-const LABEL_BORDER_COLOR: Color = DARKGRAY;
-const SELECTED_LABEL_BACKGROUND: Color = SKYBLUE;
 
+// Fill handle
+const FILL_HANDLE_SIZE: f32 = 7.0;
+
+// Status bar
+const STATUS_BAR_HEIGHT: f32 = 22.0;
+const STATUS_BAR_FONT_SIZE: u16 = 12;
+// The bar showing how far a long compute is, left of the file name
+const PROGRESS_BAR_WIDTH: f32 = 80.0;
+const PROGRESS_BAR_HEIGHT: f32 = 6.0;
+
+// How often a watched file is checked for changes, in seconds
+const WATCH_INTERVAL: f64 = 1.0;
+
+/// A column boundary being dragged in the column label strip.
+struct ColumnResize {
+    col: usize,
+    grab_x: f32,
+    initial_width: f32,
+}
+
+/// The box left of the editor bar that shows the selected cell's name and jumps to the
+/// cell or range named in it. Ctrl+G focuses it.
+#[derive(Default)]
+struct NameBox {
+    content: String,
+    focused: bool,
+    // When the last invalid name was entered
+    error_time: Option<f64>,
+}
+
+/// What the path typed into the file prompt is for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FileAction {
+    Open,
+    Save,
+}
+
+/// A path being typed to open or save the sheet at.
+struct FilePrompt {
+    action: FileAction,
+    content: String,
+    // Why the last path entered could not be used
+    error: Option<String>,
+}
+
+/// Raw content put on the clipboard from a cell, remembered to adjust its references when it
+/// is pasted somewhere else.
+struct CopiedCell {
+    index: Index,
+    raw: String,
+}
+
+/// Result of checking the editor content while it is being typed.
+enum FormulaStatus {
+    Valid(Result<Value, ComputeError>),
+    Invalid(ParseError),
+}
+
+#[allow(clippy::upper_case_acronyms)]
 pub struct GUI {
-    selected_cell: Option<Index>,
-    editor_content: String,
+    edit: EditState,
+    viewport: Viewport,
+    columns: AxisLayout,
+    rows: AxisLayout,
+    // The (columns, rows) of the reachable grid
+    grid: (usize, usize),
+    window_size: (f32, f32),
+    column_resize: Option<ColumnResize>,
     regular_font: Font,
     bold_font: Font,
+    italic_font: Font,
+    bold_italic_font: Font,
+    // Cells and labels measure their text through it, drawing only borrowing the GUI
+    text_cache: RefCell<TextCache>,
     spread_sheet: SpreadSheet,
     editor_skin: Skin,
+    name_box_error_skin: Skin,
+    theme: Theme,
+    name_box: NameBox,
+    formula_status: Option<FormulaStatus>,
+    validated_content: String,
+    // Whether the editor bar was clicked, so edits stay there instead of moving into the cell
+    bar_focused: bool,
+    last_click: Option<Click>,
+    // Whether the mouse was pressed on a cell and is still held, extending the selection
+    dragging_selection: bool,
+    // The cell under the mouse and since when it is there
+    hover: Option<(Index, f64)>,
+    // The caret and text selection of the editor, shared by the bar and the in-cell editor
+    text_editor: TextEditor,
+    key_repeat: KeyRepeat<KeyCode>,
+    // Whether the mouse was pressed in the editor and is still held, selecting text
+    selecting_text: bool,
+    autocomplete: Autocomplete,
+    // The values a rule allows in the selected cell, listed under it while browsing. The list
+    // is `None` once closed, until another cell is selected.
+    value_list: Option<(Index, Option<Dropdown<String>>)>,
+    // Hides the rows of the data whose values the filters of their columns leave out
+    row_filter: RowFilter,
+    filter_picker: Option<FilterPicker>,
+    dependants: Option<DependantHighlight>,
+    duplicates: Option<DuplicateHighlight>,
+    // Whether a key of this frame was taken by the suggestions or the dependants highlight,
+    // so it does nothing else
+    key_consumed: bool,
+    point_mode: PointMode,
+    // The cell a reference is being dragged out from while editing a formula
+    reference_drag: Option<Index>,
+    // The cell the fill handle is being dragged from
+    fill_source: Option<Index>,
+    copied: Option<CopiedCell>,
+    // Cells without an entry use the default format
+    formats: HashMap<Index, CellFormat>,
+    context_menu: Option<ContextMenu>,
+    // Where the sheet was last opened from or saved to
+    file_path: Option<PathBuf>,
+    file_prompt: Option<FilePrompt>,
+    // A paste or fill over data waiting to be confirmed, and whether those are asked about
+    overwrite_prompt: Option<OverwritePrompt>,
+    confirm_overwrite: bool,
+    // How many changes the journal keeps, 0 when it is off, and the cell whose changes are
+    // listed in the history panel
+    journal_size: usize,
+    history: Option<Index>,
+    // The file reloaded whenever it changes on disk, and when it was last checked
+    watcher: Option<FileWatcher>,
+    last_watch_check: f64,
+    // The sheet as it was last opened or saved, to revert to
+    saved: Snapshot,
+    // Computes the cells depending on an edit without holding up drawing
+    background: BackgroundCompute,
+    // Follows the links of cells Ctrl+clicked
+    opener: Box<dyn Opener>,
+    // Written before numbers in the currency style
+    currency_symbol: char,
 }
 
 impl GUI {
-    pub async fn new(spread_sheet: SpreadSheet) -> Self {
-        let regular_font = load_ttf_font("fonts/ttf/Hack-Regular.ttf")
-            .await
-            .unwrap();
-
-        let bold_font = load_ttf_font("fonts/ttf/Hack-Bold.ttf")
-            .await
-            .unwrap();
-
-        // Create a minimal style for the editor
-        let editor_skin = {
-            let editbox_style = root_ui()
-                .style_builder()
-                .color_selected(Color::from_rgba(200, 200, 255, 255)) // Light blue selection
-                .with_font(&regular_font)
-                .unwrap()
-                .build();
-
-            let window_style = root_ui()
-                .style_builder()
-                .color(Color::from_rgba(240, 240, 240, 255)) // Light gray background
-                .with_font(&regular_font)
-                .unwrap()
-                .build();
-
-            Skin {
-                editbox_style,
-                window_style,
-                ..root_ui().default_skin()
-            }
-        };
+    pub async fn new(mut spread_sheet: SpreadSheet, config: GuiConfig) -> Self {
+        let fonts = &config.fonts;
+        let regular_font = load_font(fonts.regular.as_deref(), REGULAR_FONT).await;
+        let bold_font = load_font(fonts.bold.as_deref(), BOLD_FONT).await;
+        let italic_font = load_font(fonts.italic.as_deref(), ITALIC_FONT).await;
+        let bold_italic_font = load_font(fonts.bold_italic.as_deref(), BOLD_ITALIC_FONT).await;
+
+        let theme = config.theme;
+        let currency_symbol = config.currency_symbol;
+        let (editor_skin, name_box_error_skin) = name_box_skins(&theme, &regular_font);
+        let saved = spread_sheet.snapshot();
+        spread_sheet.defer_compute(true);
+        if config.journal > 0 {
+            spread_sheet.enable_journal(config.journal, None);
+        }
 
         Self {
-            selected_cell: None,
+            edit: EditState::default(),
+            viewport: Viewport::default(),
+            columns: AxisLayout::new(config.cell_width, MIN_CELL_WIDTH),
+            rows: AxisLayout::new(config.cell_height, MIN_CELL_HEIGHT),
+            grid: (config.columns, config.rows),
+            window_size: (config.window_width, config.window_height),
+            column_resize: None,
             regular_font,
-            editor_content: String::new(),
             spread_sheet,
             bold_font,
+            italic_font,
+            bold_italic_font,
+            text_cache: RefCell::default(),
             editor_skin,
+            name_box_error_skin,
+            theme,
+            name_box: NameBox::default(),
+            formula_status: None,
+            validated_content: String::new(),
+            bar_focused: false,
+            last_click: None,
+            dragging_selection: false,
+            hover: None,
+            text_editor: TextEditor::default(),
+            key_repeat: KeyRepeat::default(),
+            selecting_text: false,
+            autocomplete: Autocomplete::default(),
+            value_list: None,
+            row_filter: RowFilter::default(),
+            filter_picker: None,
+            dependants: None,
+            duplicates: None,
+            key_consumed: false,
+            point_mode: PointMode::default(),
+            reference_drag: None,
+            fill_source: None,
+            copied: None,
+            formats: HashMap::new(),
+            context_menu: None,
+            file_path: None,
+            file_prompt: None,
+            overwrite_prompt: None,
+            confirm_overwrite: true,
+            journal_size: config.journal,
+            history: None,
+            watcher: None,
+            last_watch_check: 0.0,
+            saved,
+            background: BackgroundCompute::start(),
+            opener: Box::new(SystemOpener),
+            currency_symbol,
+        }
+    }
+
+    /// Reloads the sheet from `path` whenever the file changes, as for a dashboard showing
+    /// data written by another program.
+    pub fn watch(&mut self, path: PathBuf) {
+        self.watcher = Some(FileWatcher::new(path.clone()));
+        self.file_path = Some(path);
+        self.start_journal();
+        self.restore_view_state();
+    }
+
+    /// Starts the journal of the sheet afresh when it is on, logging the changes next to the
+    /// sheet file.
+    fn start_journal(&mut self) {
+        if self.journal_size > 0 {
+            let log_path = self.file_path.as_deref().map(sidecar_path);
+            self.spread_sheet
+                .enable_journal(self.journal_size, log_path);
         }
     }
 
     pub async fn start(&mut self) {
-        request_new_screen_size(INITIAL_WINDOW_WIDTH, INITIAL_WINDOW_HEIGHT);
+        request_new_screen_size(self.window_size.0, self.window_size.1);
+        // Closing the window ends the loop, after the view state is kept
+        prevent_quit();
 
         loop {
-            clear_background(BACKGROUND_COLOR);
+            if is_quit_requested() {
+                self.save_view_state();
+                break;
+            }
+            clear_background(self.theme.background);
 
-            self.draw_editor();
-            self.draw_cells(
-                (0.0, EDITOR_WINDOW_HEIGHT),
-                (screen_width(), screen_height()),
-            );
+            let grid_start = (0.0, EDITOR_WINDOW_HEIGHT);
+            let grid_end = (screen_width(), screen_height() - STATUS_BAR_HEIGHT);
+
+            // Keyboard navigation brings the newly selected cell into view
+            let previous_selection = self.edit.selection;
+            let previously_selected = self.edit.selected_cell();
+            // While browsing the caret rests at the end, where editing starts
+            if self.edit.mode == EditMode::Browsing {
+                self.text_editor.move_end(&self.edit.editor_content, false);
+            }
+            self.key_consumed = false;
+            self.reload_watched_file();
+            self.sync_background_compute();
+            self.update_hidden_lines();
+            // Keys typed into a prompt or the filter picker are for them alone
+            if self.file_prompt.is_none()
+                && self.overwrite_prompt.is_none()
+                && self.filter_picker.is_none()
+            {
+                self.handle_go_to_key();
+                self.handle_file_keys();
+                self.handle_view_keys();
+                self.handle_recalc_keys();
+                self.handle_clipboard();
+                self.handle_format_keys();
+                self.handle_menu_keys();
+                self.handle_suggestion_keys();
+                self.handle_value_list_keys();
+                self.handle_dependant_keys();
+                self.handle_history_keys();
+                self.handle_duplicate_keys();
+                self.handle_anchor_key();
+                self.handle_edit_keys();
+                self.handle_navigation();
+                self.handle_paging(grid_start, grid_end);
+            } else {
+                self.key_consumed = true;
+            }
+            self.draw_editor(grid_start, grid_end);
+            if self.edit.selected_cell() != previously_selected {
+                self.bar_focused = false;
+                self.point_mode.reset();
+            }
+            if let Some(focus) = self.edit.selection_focus() {
+                if self.edit.selection != previous_selection {
+                    self.viewport.scroll_into_view(
+                        focus,
+                        (&self.columns, &self.rows),
+                        cells_area(grid_start, grid_end),
+                    );
+                }
+            }
 
+            self.handle_scroll();
+            self.update_row_heights();
+            self.update_formula_status();
+            self.draw_formula_status();
+            self.draw_cells(grid_start, grid_end);
+            self.draw_cell_editor(grid_start, grid_end);
+            self.draw_value_list(grid_start, grid_end);
+            self.draw_status_bar(grid_start, grid_end);
+            self.draw_history_panel(grid_start, grid_end);
+            self.draw_suggestions(grid_start, grid_end);
+            self.draw_filter_picker();
+            self.draw_context_menu();
+            self.draw_file_prompt();
+            self.draw_overwrite_prompt();
+
+            self.text_cache.get_mut().end_frame();
             next_frame().await
         }
     }
 
-    fn draw_editor(&mut self) {
-        // Push our custom skin before drawing the editor
-        root_ui().push_skin(&self.editor_skin);
+    fn draw_editor(&mut self, grid_start: (f32, f32), grid_end: (f32, f32)) {
+        // The right side of the editor bar is left free for the formula status
+        let window_width = screen_width() - EDITOR_STATUS_WIDTH;
+        let ((name_box_pos, name_box_size), (bar_pos, bar_size)) = editor_rects();
 
-        let window_id = hash!();
-        root_ui().window(
-            window_id,
-            vec2(0.0, EDITOR_TOP_MARGIN),
-            vec2(screen_width(), EDITOR_WINDOW_HEIGHT),
-            |ui| {
-                let input_text_id = hash!();
-                InputText::new(input_text_id)
-                    .label("")
-                    .position(vec2(ROW_LABEL_WIDTH, EDITOR_TOP_MARGIN + EDITOR_PADDING))
-                    .size(vec2(screen_width() - ROW_LABEL_WIDTH * 2.0, EDITOR_HEIGHT))
-                    .ui(ui, &mut self.editor_content);
-
-                // Focus the editor when a cell is selected
-                if self.selected_cell.is_some() {
-                    ui.set_input_focus(input_text_id);
-                } else {
-                    ui.set_input_focus(hash!());
-                }
+        if is_mouse_button_pressed(MouseButton::Left) {
+            let mouse = mouse_position();
+            let name_box_end = name_box_pos + name_box_size;
+            let bar_end = bar_pos + bar_size;
 
-                if is_key_pressed(KeyCode::Enter) {
-                    self.commit_editor();
-                    self.selected_cell = None;
-                    self.editor_content.clear();
+            if is_point_in_rect(mouse, name_box_pos.into(), name_box_end.into()) {
+                if !self.name_box.focused {
+                    self.name_box.focused = true;
+                    self.name_box.content.clear();
                 }
-            },
+            } else {
+                self.name_box.focused = false;
+                if is_point_in_rect(mouse, bar_pos.into(), bar_end.into()) {
+                    self.bar_focused = true;
+                    self.edit.begin_edit(&self.spread_sheet);
+                }
+            }
+        }
+
+        if !self.name_box.focused {
+            self.name_box.content = self
+                .edit
+                .selected_cell()
+                .map(index_to_name)
+                .unwrap_or_default();
+        }
+
+        draw_rectangle(
+            0.0,
+            EDITOR_TOP_MARGIN,
+            window_width,
+            EDITOR_WINDOW_HEIGHT,
+            self.theme.panel_background,
         );
 
-        // Pop the skin after we're done
+        // While browsing the bar only shows the content. Edits happen in the cell itself when
+        // it is shown, otherwise in the bar.
+        let bar_has_caret = self.edit.mode == EditMode::Editing
+            && !self.name_box.focused
+            && self.cell_editor_rect(grid_start, grid_end).is_none();
+        if bar_has_caret {
+            self.handle_text_field_mouse(bar_pos.into(), bar_size.into());
+        }
+        self.draw_text_field(bar_pos.into(), bar_size.into(), bar_has_caret);
+
+        root_ui().push_skin(&self.editor_skin);
+        root_ui().set_input_focus(if self.name_box.focused {
+            hash!("name_box")
+        } else {
+            hash!()
+        });
+        let flashing = self
+            .name_box
+            .error_time
+            .is_some_and(|time| get_time() - time < NAME_BOX_FLASH_TIME);
+        if flashing {
+            root_ui().push_skin(&self.name_box_error_skin);
+        }
+        InputText::new(hash!("name_box"))
+            .label("")
+            .position(name_box_pos)
+            .size(name_box_size)
+            .ui(&mut root_ui(), &mut self.name_box.content);
+        if flashing {
+            root_ui().pop_skin();
+        }
         root_ui().pop_skin();
-    }
 
-    fn draw_cells(&mut self, start: (f32, f32), end: (f32, f32)) {
-        let (start_x, start_y) = start;
-        let (end_x, end_y) = end;
+        if self.name_box.focused {
+            if is_key_pressed(KeyCode::Enter) {
+                self.jump_to_name_box_cell();
+            } else if is_key_pressed(KeyCode::Escape) {
+                self.name_box.focused = false;
+            }
+            return;
+        }
 
-        let grid_height = end_y - start_y - COL_LABEL_HEIGHT;
-        let grid_width = end_x - start_x - ROW_LABEL_WIDTH;
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        let commit_direction = if is_key_pressed(KeyCode::Enter) {
+            Some(if shift {
+                Direction::Up
+            } else {
+                Direction::Down
+            })
+        } else if is_key_pressed(KeyCode::Tab) {
+            Some(if shift {
+                Direction::Left
+            } else {
+                Direction::Right
+            })
+        } else {
+            None
+        };
 
-        let cell_height = grid_height / GRID_ROWS as f32;
-        let cell_width = grid_width / GRID_COLS as f32;
+        if let Some(direction) = commit_direction.filter(|_| !self.key_consumed) {
+            self.edit
+                .commit_and_move(direction, self.grid, &mut self.spread_sheet);
+            // Moving onto a hidden row or column goes on to the next shown one
+            if let Some(selected) = self
+                .edit
+                .selected_cell()
+                .filter(|idx| self.rows.is_hidden(idx.y) || self.columns.is_hidden(idx.x))
+            {
+                let next = self.step(selected, direction);
+                self.edit.select(next, &mut self.spread_sheet);
+            }
+        }
+    }
 
-        // Handle if mouse clicked
-        let mut hovered: Option<Index> = None;
-        let (x, y) = mouse_position();
-        if is_point_in_rect((x, y), start, end) {
-            let col = ((x - start_x - ROW_LABEL_WIDTH) / cell_width) as i32;
-            let row = ((y - start_y - COL_LABEL_HEIGHT) / cell_height) as i32;
-            let x_idx = col.try_into().expect("Got negative idx from click");
-            let y_idx = row.try_into().expect("Got negative idx from click");
+    /// Selects the cell or range named in the name box, or flashes the box when there is no
+    /// such cell.
+    fn jump_to_name_box_cell(&mut self) {
+        match parse_name_box_range(&self.name_box.content, self.grid) {
+            Some((top_left, bottom_right)) => {
+                self.edit.select(top_left, &mut self.spread_sheet);
+                self.edit
+                    .extend_selection(bottom_right, &mut self.spread_sheet);
+                self.name_box.focused = false;
+                self.name_box.error_time = None;
+            }
+            None => self.name_box.error_time = Some(get_time()),
+        }
+    }
 
-            hovered = Some(Index { x: x_idx, y: y_idx });
+    /// Focuses the name box with Ctrl+G, emptied to type the cell or range to go to.
+    fn handle_go_to_key(&mut self) {
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        if ctrl && is_key_pressed(KeyCode::G) && !self.name_box.focused {
+            self.name_box.focused = true;
+            self.name_box.content.clear();
+            self.name_box.error_time = None;
+            self.key_consumed = true;
+        }
+    }
 
-            if is_mouse_button_pressed(MouseButton::Left) {
-                if is_key_down(KeyCode::LeftControl) {
-                    if self.selected_cell.is_some() && &Some('=') == &self.editor_content.chars().nth(0) {
-                        self.editor_content.push_str(&format!(
-                            "{}{}",
-                            column_idx_to_string(x_idx),
-                            y_idx + 1
-                        ))
-                    }
-                } else {
-                    self.change_selected_cell(Index { x: x_idx, y: y_idx });
-                }
-            }
+    /// Returns the `(position, size)` of the cell at `idx`, if it is visible in the grid
+    /// between `start` and `end`.
+    fn cell_rect(
+        &self,
+        idx: Index,
+        start: (f32, f32),
+        end: (f32, f32),
+    ) -> Option<((f32, f32), (f32, f32))> {
+        let (visible_cols, visible_rows) =
+            visible_cells(start, end, &self.viewport, (&self.columns, &self.rows));
+        if !visible_cols.contains(&idx.x) || !visible_rows.contains(&idx.y) {
+            return None;
         }
 
-        // Draw background
-        draw_rectangle(
-            start_x,
-            start_y,
-            end_x - start_x,
-            end_y - start_y,
-            GRID_BACKGROUND_COLOR,
+        let cell_start = (
+            start.0 + ROW_LABEL_WIDTH + self.viewport.column_offset(&self.columns, idx.x)?,
+            start.1 + COL_LABEL_HEIGHT + self.viewport.row_offset(&self.rows, idx.y)?,
         );
+        Some((
+            cell_start,
+            (self.columns.size(idx.x), self.rows.size(idx.y)),
+        ))
+    }
 
-        // Draw the column labels
-        for col in 0..GRID_COLS {
-            let label_start_x = start_x + col as f32 * cell_width + ROW_LABEL_WIDTH;
-            let label_start_y = start_y;
-            self.draw_label(
-                col,
-                false, // Indicating column
-                (label_start_x, label_start_y),
-                (cell_width, COL_LABEL_HEIGHT),
-            );
+    /// Returns the `(position, size)` of the visible part of the rectangle of cells from
+    /// `top_left` to `bottom_right`, if any of it is visible in the grid between `start` and
+    /// `end`.
+    fn range_rect(
+        &self,
+        (top_left, bottom_right): (Index, Index),
+        start: (f32, f32),
+        end: (f32, f32),
+    ) -> Option<((f32, f32), (f32, f32))> {
+        let (visible_cols, visible_rows) =
+            visible_cells(start, end, &self.viewport, (&self.columns, &self.rows));
+
+        // The visible cells closest to the corners, with frozen lines the ones scrolled away
+        // between them are not drawn so the rectangle stays whole
+        let from = Index {
+            x: *visible_cols.iter().find(|&&col| col >= top_left.x)?,
+            y: *visible_rows.iter().find(|&&row| row >= top_left.y)?,
+        };
+        let to = Index {
+            x: *visible_cols
+                .iter()
+                .rev()
+                .find(|&&col| col <= bottom_right.x)?,
+            y: *visible_rows
+                .iter()
+                .rev()
+                .find(|&&row| row <= bottom_right.y)?,
+        };
+        if from.x > to.x || from.y > to.y {
+            return None;
         }
 
-        // Draw the row labels
-        for row in 0..GRID_ROWS {
-            let label_start_x = start_x;
-            let label_start_y = start_y + row as f32 * cell_height + COL_LABEL_HEIGHT;
-            self.draw_label(
-                row,
-                true, // Indicating row
-                (label_start_x, label_start_y),
-                (ROW_LABEL_WIDTH, cell_height),
-            );
+        let (from_pos, _) = self.cell_rect(from, start, end)?;
+        let (to_pos, to_size) = self.cell_rect(to, start, end)?;
+        Some((
+            from_pos,
+            (
+                to_pos.0 + to_size.0 - from_pos.0,
+                to_pos.1 + to_size.1 - from_pos.1,
+            ),
+        ))
+    }
+
+    /// Outlines the cells and ranges the formula of the selected cell references, each in the
+    /// color its reference has in the editor.
+    fn draw_precedents(&self, start: (f32, f32), end: (f32, f32)) {
+        let references = distinct_references(&self.edit.editor_content);
+        for (slot, reference) in references.into_iter().enumerate() {
+            let range = match reference {
+                Reference::Cell(idx) => (idx, idx),
+                Reference::Range(from, to) => normalize_range(from, to),
+            };
+            let Some(((x, y), (width, height))) = self.range_rect(range, start, end) else {
+                continue;
+            };
+
+            let color = self.theme.reference_color(slot);
+            let fill = Color {
+                a: REFERENCE_FILL_ALPHA,
+                ..color
+            };
+            draw_rectangle(x, y, width, height, fill);
+            draw_rectangle_lines(x, y, width, height, 2.0, color);
         }
+    }
 
-        // Draw all cells in the grid
-        for row in 0..GRID_ROWS {
-            for col in 0..GRID_COLS {
-                let cell_start_x = start_x + col as f32 * cell_width + ROW_LABEL_WIDTH;
-                let cell_start_y = start_y + row as f32 * cell_height + COL_LABEL_HEIGHT;
+    /// Returns the `(position, size)` of the fill handle, shown at the bottom right corner of
+    /// a single selected cell while browsing.
+    fn fill_handle_rect(
+        &self,
+        start: (f32, f32),
+        end: (f32, f32),
+    ) -> Option<((f32, f32), (f32, f32))> {
+        let (anchor, focus) = self.edit.selection?;
+        if anchor != focus || self.edit.mode != EditMode::Browsing {
+            return None;
+        }
 
-                // Adjust the height of the last row to account for any floating-point error
-                let adjusted_cell_height = if row == GRID_ROWS - 1 {
-                    grid_height - (row as f32 * cell_height)
-                } else {
-                    cell_height
-                };
+        let ((x, y), (width, height)) = self.cell_rect(anchor, start, end)?;
+        let half = FILL_HANDLE_SIZE / 2.0;
+        Some((
+            (x + width - half, y + height - half),
+            (FILL_HANDLE_SIZE, FILL_HANDLE_SIZE),
+        ))
+    }
 
-                self.draw_cell(
-                    Index { x: col, y: row },
-                    (cell_start_x, cell_start_y),
-                    (cell_width, adjusted_cell_height),
-                );
-            }
+    /// Returns the `(position, size)` of the in-cell editor, which is shown while the selected
+    /// cell is being edited and visible in the grid between `start` and `end`.
+    fn cell_editor_rect(
+        &self,
+        start: (f32, f32),
+        end: (f32, f32),
+    ) -> Option<((f32, f32), (f32, f32))> {
+        let idx = self.edit.selected_cell()?;
+        if self.edit.mode != EditMode::Editing || self.bar_focused {
+            return None;
         }
 
-        // Draw dialog box for hovered cell
-        if let Some(idx) = hovered {
-            let cell_end_x = start_x + idx.x as f32 * cell_width + ROW_LABEL_WIDTH + cell_width;
-            let cell_end_y = start_y + idx.y as f32 * cell_height + COL_LABEL_HEIGHT;
-            let dialog_pos = (cell_end_x, cell_end_y);
-            self.draw_dialog(idx, dialog_pos);
+        let (cell_start, cell_size) = self.cell_rect(idx, start, end)?;
+        let cells_start = (start.0 + ROW_LABEL_WIDTH, start.1 + COL_LABEL_HEIGHT);
+        let text_width = measure_text(
+            &self.edit.editor_content,
+            Some(&self.regular_font),
+            EDITOR_FONT_SIZE,
+            1.0,
+        )
+        .width;
+
+        Some(place_cell_editor(
+            cell_start,
+            cell_size,
+            text_width + CELL_EDITOR_PADDING,
+            (cells_start, end),
+        ))
+    }
+
+    /// Returns the `(position, size)` of the editor field that holds the caret while editing:
+    /// the in-cell editor when it is shown, otherwise the editor bar.
+    fn caret_field_rect(
+        &self,
+        start: (f32, f32),
+        end: (f32, f32),
+    ) -> Option<((f32, f32), (f32, f32))> {
+        if self.edit.mode != EditMode::Editing || self.name_box.focused {
+            return None;
         }
+        self.cell_editor_rect(start, end).or_else(|| {
+            let (_, (bar_pos, bar_size)) = editor_rects();
+            Some((bar_pos.into(), bar_size.into()))
+        })
     }
 
-    fn draw_cell(&self, index: Index, start: (f32, f32), dimensions: (f32, f32)) {
-        let (start_x, start_y) = start;
-        let (width, height) = dimensions;
+    /// Returns the partial function name at the caret and the functions it could be
+    /// completed to, while these are to be suggested.
+    fn suggestions(&self) -> Option<(std::ops::Range<usize>, Vec<FunctionInfo>)> {
+        let content = &self.edit.editor_content;
+        if self.edit.mode != EditMode::Editing
+            || self.name_box.focused
+            || self.autocomplete.is_dismissed(content)
+        {
+            return None;
+        }
 
-        let center_x = start_x + width / 2.0;
-        let center_y = start_y + height / 2.0;
+        let prefix = completion_prefix(content, self.text_editor.caret(content))?;
+        let matches =
+            matching_functions(&content[prefix.clone()], SpreadSheet::builtin_functions());
+        (!matches.is_empty()).then_some((prefix, matches))
+    }
 
-        let (border_width, border_color) = if Some(index) == self.selected_cell {
-            (SELECTED_CELL_BORDER_WIDTH, SELECTED_CELL_BORDER_COLOR)
-        } else {
-            (NORMAL_CELL_BORDER_WIDTH, NORMAL_CELL_BORDER_COLOR)
+    /// Lets the arrow keys pick a suggested function, Tab and Enter accept it and Escape close
+    /// the suggestions, instead of what these keys do otherwise.
+    fn handle_suggestion_keys(&mut self) {
+        let Some((prefix, matches)) = self.suggestions() else {
+            self.autocomplete.selected = 0;
+            return;
         };
 
-        draw_rectangle_lines(start_x, start_y, width, height, border_width, border_color);
-
-        let text = if Some(index) == self.selected_cell {
-            &self.editor_content
+        if is_key_pressed(KeyCode::Up) {
+            self.autocomplete.move_selection(-1, matches.len());
+        } else if is_key_pressed(KeyCode::Down) {
+            self.autocomplete.move_selection(1, matches.len());
+        } else if is_key_pressed(KeyCode::Tab) || is_key_pressed(KeyCode::Enter) {
+            let selected = matches[self.autocomplete.selected.min(matches.len() - 1)];
+            let content = &mut self.edit.editor_content;
+            let caret = complete(content, prefix, selected.name);
+            self.text_editor.set_caret(content, caret, false);
+            self.autocomplete.selected = 0;
+        } else if is_key_pressed(KeyCode::Escape) {
+            self.autocomplete.dismiss(&self.edit.editor_content);
         } else {
-            let computed = self.spread_sheet.get_computed(index);
-            if let Some(Err(_)) = computed {
-                let triangle_len = 10.;
-                draw_triangle(
-                    vec2(start_x + width, start_y),
-                    vec2(start_x + width - triangle_len, start_y),
-                    vec2(start_x + width, start_y + triangle_len),
-                    RED,
-                );
-            }
+            return;
+        }
+        self.key_consumed = true;
+    }
 
-            &computed_to_text(computed)
+    /// Lists the values allowed in the selected cell while browsing, when its validation rule
+    /// limits it to a list of them.
+    fn update_value_list(&mut self) {
+        let allowed = self
+            .edit
+            .selected_cell()
+            .filter(|_| self.edit.mode == EditMode::Browsing && !self.name_box.focused)
+            .and_then(|idx| match &self.spread_sheet.validation_rule(idx)?.kind {
+                ValidationKind::OneOf(options) => Some((idx, options.clone())),
+                _ => None,
+            });
+        let Some((idx, options)) = allowed else {
+            self.value_list = None;
+            return;
         };
+        if self
+            .value_list
+            .as_ref()
+            .is_some_and(|(listed, _)| *listed == idx)
+        {
+            return;
+        }
 
-        if !text.is_empty() {
-            let text_dimensions = measure_text(text, Some(&self.regular_font), CELL_FONT_SIZE, 1.0);
+        let mut dropdown = Dropdown::new(options);
+        if let Some(Ok(value)) = self.spread_sheet.get_computed(idx) {
+            dropdown.highlight_item(value.to_string().as_str());
+        }
+        self.value_list = Some((idx, Some(dropdown)));
+    }
 
-            let text_x = center_x - text_dimensions.width / 2.0;
-            let text_y = center_y + text_dimensions.height / 2.0; // Adjust y for baseline alignment
+    /// Lets the arrow keys move through the values listed for the selected cell, Enter write
+    /// the highlighted one into it and Escape close the list, instead of what these keys do
+    /// otherwise.
+    fn handle_value_list_keys(&mut self) {
+        self.update_value_list();
+        let Some((idx, Some(dropdown))) = &mut self.value_list else {
+            return;
+        };
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        if self.key_consumed || shift {
+            return;
+        }
 
-            draw_text_ex(
-                text,
-                text_x,
-                text_y,
-                TextParams {
-                    font: Some(&self.regular_font),
-                    font_size: CELL_FONT_SIZE,
-                    font_scale: 1.0,
-                    font_scale_aspect: 1.0,
-                    rotation: 0.0,
-                    color: CELL_TEXT_COLOR,
-                },
-            );
+        let key = if is_key_pressed(KeyCode::Up) {
+            DropdownKey::Up
+        } else if is_key_pressed(KeyCode::Down) {
+            DropdownKey::Down
+        } else if is_key_pressed(KeyCode::Enter) {
+            DropdownKey::Enter
+        } else if is_key_pressed(KeyCode::Escape) {
+            DropdownKey::Escape
+        } else {
+            return;
+        };
+        let idx = *idx;
+        match dropdown.handle_key(key) {
+            DropdownEvent::Moved => (),
+            DropdownEvent::Chosen(value) => {
+                self.value_list = Some((idx, None));
+                self.edit.write(value, &mut self.spread_sheet);
+            }
+            DropdownEvent::Closed => self.value_list = Some((idx, None)),
         }
+        self.key_consumed = true;
     }
 
-    fn draw_label(&self, idx: usize, is_row: bool, start: (f32, f32), dimensions: (f32, f32)) {
-        let (start_x, start_y) = start;
-        let (width, height) = dimensions;
-        let center_x = start_x + width / 2.0;
-        let center_y = start_y + height / 2.0;
+    /// Highlights the dependants of the selected cell with Ctrl+], reaching all of them when
+    /// pressed again. Escape or another selection ends the highlight.
+    fn handle_dependant_keys(&mut self) {
+        if self
+            .dependants
+            .is_some_and(|highlight| self.edit.selection != Some(highlight.selection))
+        {
+            self.dependants = None;
+        }
+        if self.name_box.focused || self.key_consumed {
+            return;
+        }
 
-        let is_selected_label = {
-            if let Some(selected) = self.selected_cell {
-                if is_row {
-                    selected.y == idx
-                } else {
-                    selected.x == idx
-                }
-            } else {
-                false
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        if ctrl && is_key_pressed(KeyCode::RightBracket) {
+            if let Some(selection) = self.edit.selection {
+                self.dependants = Some(DependantHighlight::extend(self.dependants, selection));
             }
-        };
-
-        if is_selected_label {
-            // Draw background
-            draw_rectangle(start_x, start_y, width, height, SELECTED_LABEL_BACKGROUND);
+        } else if self.dependants.is_some() && is_key_pressed(KeyCode::Escape) {
+            self.dependants = None;
+            self.key_consumed = true;
         }
+    }
 
-        draw_rectangle_lines(start_x, start_y, width, height, 1.0, LABEL_BORDER_COLOR);
-        let text = if is_row {
-            (idx + 1).to_string()
-        } else {
-            column_idx_to_string(idx)
+    /// Ends the highlight of duplicates with Escape, or once the content of a cell changed.
+    fn handle_duplicate_keys(&mut self) {
+        let Some(highlight) = &self.duplicates else {
+            return;
         };
-        let text_dimensions = measure_text(&text, Some(&self.regular_font), LABEL_FONT_SIZE, 1.0);
+        if highlight.revision != self.spread_sheet.revision() {
+            self.duplicates = None;
+        } else if !self.key_consumed && is_key_pressed(KeyCode::Escape) {
+            self.duplicates = None;
+            self.key_consumed = true;
+        }
+    }
 
-        let text_x = center_x - text_dimensions.width / 2.0;
-        let text_y = center_y + text_dimensions.height / 2.0; // Adjust y for baseline alignment
+    /// Closes the history panel with Escape.
+    fn handle_history_keys(&mut self) {
+        if self.history.is_some() && !self.key_consumed && is_key_pressed(KeyCode::Escape) {
+            self.history = None;
+            self.key_consumed = true;
+        }
+    }
 
-        draw_text_ex(
-            &text,
-            text_x,
-            text_y,
-            TextParams {
-                font: Some(if is_selected_label {
-                    &self.bold_font
-                } else {
-                    &self.regular_font
-                }),
-                font_size: LABEL_FONT_SIZE,
-                font_scale: 1.0,
-                font_scale_aspect: 1.0,
-                rotation: 0.0,
-                color: LABEL_TEXT_COLOR,
-            },
-        );
+    /// Closes the open context menu with Escape.
+    fn handle_menu_keys(&mut self) {
+        if self.context_menu.is_some() && is_key_pressed(KeyCode::Escape) {
+            self.context_menu = None;
+            self.key_consumed = true;
+        }
     }
 
-    fn commit_editor(&mut self) {
-        if let Some(idx) = self.selected_cell {
-            let previous_content = self.spread_sheet.get_raw(&idx).unwrap_or_default();
-            let new_content = self.editor_content.trim().to_string();
+    /// Applies the formatting shortcuts to the selected cells while browsing: Ctrl+Shift+W
+    /// toggles text wrapping, Ctrl+B bold and Ctrl+I italic. Ctrl+Shift+5 toggles the percent
+    /// style, Ctrl+Shift+4 the currency style and Ctrl+Shift+1 applies the comma style.
+    fn handle_format_keys(&mut self) {
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        if !ctrl || self.edit.mode != EditMode::Browsing || self.name_box.focused {
+            return;
+        }
+        let Some(anchor) = self.edit.selected_cell() else {
+            return;
+        };
+        // Every rectangle of a selection of several is formatted
+        let ranges = self.edit.selected_ranges();
 
-            match (previous_content, new_content.as_str()) {
-                (prev, new) if prev == new => (),
-                ("", "") => (),
-                ("", _added_content) => self.spread_sheet.add_cell_and_compute(idx, new_content),
-                (_deleted_content, "") => self.spread_sheet.remove_cell(idx),
-                (_mutated_from, _mutated_to) => self.spread_sheet.mutate_cell(idx, new_content),
+        if shift && is_key_pressed(KeyCode::W) {
+            // The anchor decides, so a mixed selection ends up all the same
+            let wrap = !self.format(anchor).wrap;
+            for &range in ranges.ranges() {
+                self.update_formats(range, |format| format.wrap = wrap);
             }
+        } else if !shift && is_key_pressed(KeyCode::B) {
+            self.spread_sheet
+                .toggle_text_style_of(ranges.cells(), TextStyle::Bold);
+        } else if !shift && is_key_pressed(KeyCode::I) {
+            self.spread_sheet
+                .toggle_text_style_of(ranges.cells(), TextStyle::Italic);
+        } else if shift && is_key_pressed(KeyCode::Key5) {
+            self.apply_number_style(NumberStyle::Percent);
+        } else if shift && is_key_pressed(KeyCode::Key4) {
+            self.apply_number_style(NumberStyle::Currency);
+        } else if shift && is_key_pressed(KeyCode::Key1) {
+            self.apply_number_style(NumberStyle::Comma);
         }
     }
 
-    fn change_selected_cell(&mut self, idx: Index) {
-        if self.selected_cell == Some(idx) {
-            return;
+    /// Applies `style` to the numbers of every selected cell, undone as a single step.
+    fn apply_number_style(&mut self, style: NumberStyle) {
+        let cells = self.edit.selected_ranges().cells();
+        let sheet = &mut self.spread_sheet;
+        match style {
+            NumberStyle::Percent => sheet.toggle_number_format(cells, NumberFormat::Percent),
+            NumberStyle::Currency => sheet.toggle_number_format(
+                cells,
+                NumberFormat::Currency {
+                    symbol: self.currency_symbol,
+                    decimals: 2,
+                },
+            ),
+            NumberStyle::Comma => {
+                sheet.set_number_format(cells, NumberFormat::Fixed { decimals: 2 })
+            }
+            NumberStyle::MoreDecimals => sheet.change_decimals(cells, 1),
+            NumberStyle::FewerDecimals => sheet.change_decimals(cells, -1),
         }
-
-        self.commit_editor();
-        self.editor_content = self
-            .spread_sheet
-            .get_raw(&idx)
-            .unwrap_or_default()
-            .to_owned();
-        self.selected_cell = Some(idx);
     }
 
-    fn draw_dialog(&self, idx: Index, pos: (f32, f32)) {
-        if let Some(err) = self.spread_sheet.get_error(idx) {
-            const DIALOG_WIDTH: f32 = 200.0;
-            const DIALOG_HEIGHT: f32 = 80.0;
-            const DIALOG_FONT_SIZE: u16 = 14;
+    /// Returns the font cell text with `style` is drawn in.
+    fn cell_font(&self, style: CellStyle) -> &Font {
+        self.font(FontFace::of(style))
+    }
 
-            let (dialog_x, dialog_y) = pos;
+    fn font(&self, face: FontFace) -> &Font {
+        match face {
+            FontFace::Regular => &self.regular_font,
+            FontFace::Bold => &self.bold_font,
+            FontFace::Italic => &self.italic_font,
+            FontFace::BoldItalic => &self.bold_italic_font,
+        }
+    }
 
-            // Draw dialog background
-            draw_rectangle(
-                dialog_x,
-                dialog_y,
-                DIALOG_WIDTH,
-                DIALOG_HEIGHT,
-                GRID_BACKGROUND_COLOR,
-            );
-            draw_rectangle_lines(dialog_x, dialog_y, DIALOG_WIDTH, DIALOG_HEIGHT, 4.0, RED);
+    /// Measures text of cells and labels, which is measured again only once it changed.
+    fn measure_cached(&self, text: &str, face: FontFace, font_size: u16) -> TextDimensions {
+        self.text_cache
+            .borrow_mut()
+            .measure(text, face, font_size, || {
+                measure_text(text, Some(self.font(face)), font_size, 1.0)
+            })
+    }
 
-            // Prepare dialog text
-            let dialog_text = format!("Error: {}", err_to_info(err));
+    /// Ctrl+O asks for a file to open in place of the sheet. Ctrl+S saves the sheet to the file
+    /// it came from, asking for one the first time. Ctrl+R reverts the sheet to how it was
+    /// last opened or saved, which can be undone.
+    fn handle_file_keys(&mut self) {
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        if !ctrl || shift || self.name_box.focused {
+            return;
+        }
 
-            let lines = split_into_lines(
-                &dialog_text,
-                &self.regular_font,
-                DIALOG_FONT_SIZE,
-                DIALOG_WIDTH - 10.0,
-            );
+        if is_key_pressed(KeyCode::O) {
+            self.prompt_for_file(FileAction::Open, None);
+        } else if is_key_pressed(KeyCode::S) {
+            match self.file_path.clone() {
+                Some(path) => self.save_file(path),
+                None => self.prompt_for_file(FileAction::Save, None),
+            }
+        } else if is_key_pressed(KeyCode::R) && self.spread_sheet.is_modified() {
+            self.spread_sheet.restore(self.saved.clone());
+            self.edit.reload(&self.spread_sheet);
+        }
+    }
 
-            // Calculate vertical starting position for centering the text block
-            let total_text_height = lines.len() as f32 * (DIALOG_FONT_SIZE as f32 + 4.0); // 4.0 for line spacing
-            let mut text_y = dialog_y + (DIALOG_HEIGHT - total_text_height) / 2.0;
+    fn prompt_for_file(&mut self, action: FileAction, error: Option<String>) {
+        let content = self
+            .file_path
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default();
+        self.file_prompt = Some(FilePrompt {
+            action,
+            content,
+            error,
+        });
+    }
 
-            // Draw each line of text
-            for line in lines {
-                let text_dimensions =
-                    measure_text(&line, Some(&self.bold_font), DIALOG_FONT_SIZE, 1.0);
-                let text_x = dialog_x + (DIALOG_WIDTH - text_dimensions.width) / 2.0;
+    /// Saves the sheet to `path`, asking for another path when that fails.
+    fn save_file(&mut self, path: PathBuf) {
+        // An edit in progress is saved too
+        self.edit.commit(&mut self.spread_sheet);
+        self.edit.reload(&self.spread_sheet);
 
-                draw_text_ex(
-                    &line,
-                    text_x,
-                    text_y,
-                    TextParams {
-                        font: Some(&self.bold_font),
-                        font_size: DIALOG_FONT_SIZE,
-                        font_scale: 1.0,
-                        font_scale_aspect: 1.0,
-                        rotation: 0.0,
-                        color: BLACK,
-                    },
-                );
+        match self.spread_sheet.save_to_file(&path) {
+            Ok(()) => {
+                self.saved = self.spread_sheet.snapshot();
+                self.spread_sheet.set_journal_log(Some(sidecar_path(&path)));
+                self.file_path = Some(path);
+                self.file_prompt = None;
+                self.save_view_state();
+            }
+            Err(err) => self.prompt_for_file(FileAction::Save, Some(err.to_string())),
+        }
+    }
 
-                text_y += DIALOG_FONT_SIZE as f32 + 4.0; // Move to next line
+    /// Replaces the sheet with the one read from `path`, keeping the current one when that
+    /// fails.
+    fn open_file(&mut self, path: PathBuf) {
+        match SpreadSheet::load_from_file(&path) {
+            Ok(spread_sheet) => {
+                if let Some(watcher) = &mut self.watcher {
+                    *watcher = FileWatcher::new(path.clone());
+                }
+                self.saved = spread_sheet.snapshot();
+                let recalc_mode = self.spread_sheet.recalc_mode();
+                self.spread_sheet = spread_sheet;
+                self.spread_sheet.defer_compute(true);
+                self.spread_sheet.set_recalc_mode(recalc_mode);
+                self.background.discard();
+                self.edit = EditState::default();
+                self.formats.clear();
+                self.dependants = None;
+                self.copied = None;
+                self.history = None;
+                self.file_path = Some(path);
+                self.file_prompt = None;
+                self.start_journal();
+                self.restore_view_state();
+            }
+            Err(err) => {
+                if let Some(prompt) = &mut self.file_prompt {
+                    prompt.error = Some(err.to_string());
+                }
             }
         }
     }
-}
 
-fn column_idx_to_string(mut idx: usize) -> String {
-    let mut s = String::new();
+    /// Returns how the sheet is shown, kept next to its file so that it is shown the same way
+    /// when opened again.
+    fn view_state(&self) -> ViewState {
+        let sizes = |layout: &AxisLayout| {
+            layout
+                .sizes()
+                .iter()
+                .map(|(&line, &size)| (line, size))
+                .collect()
+        };
+        ViewState {
+            selected: self.edit.selected_cell().unwrap_or(Index { x: 0, y: 0 }),
+            scroll: Index {
+                x: self.viewport.first_col,
+                y: self.viewport.first_row,
+            },
+            frozen: (self.viewport.frozen_cols, self.viewport.frozen_rows),
+            column_widths: sizes(&self.columns),
+            row_heights: sizes(&self.rows),
+            hidden_columns: self.spread_sheet.hidden_columns().clone(),
+            hidden_rows: self.spread_sheet.hidden_rows().clone(),
+            theme: Some(self.theme.name().to_string()),
+        }
+    }
 
-    loop {
-        let rem = (idx % 26) as u8;
-        s.insert(0, (b'A' + rem) as char); // Prepend the character
-        if idx < 26 {
-            break;
+    /// Shows the sheet the way `state` tells, fitted to the grid.
+    fn apply_view_state(&mut self, state: ViewState) {
+        let state = state.clamped(self.grid);
+        self.edit.select(state.selected, &mut self.spread_sheet);
+        self.viewport.freeze(state.frozen.0, state.frozen.1);
+        self.viewport.first_col = state.scroll.x;
+        self.viewport.first_row = state.scroll.y;
+        self.columns.set_sizes(state.column_widths);
+        self.rows.set_sizes(state.row_heights);
+        for col in state.hidden_columns {
+            self.spread_sheet.set_column_hidden(col, true);
+        }
+        for row in state.hidden_rows {
+            self.spread_sheet.set_row_hidden(row, true);
+        }
+        self.update_hidden_lines();
+        if let Some(theme) = state.theme.as_deref().and_then(Theme::named) {
+            self.theme = theme;
+            (self.editor_skin, self.name_box_error_skin) =
+                name_box_skins(&self.theme, &self.regular_font);
         }
-        idx = idx / 26 - 1;
     }
 
-    s
-}
+    /// Shows the sheet the way it was last shown, when a view state was kept for its file.
+    /// The sheet opens as usual without one, or with one that cannot be read.
+    fn restore_view_state(&mut self) {
+        let Some(path) = &self.file_path else {
+            return;
+        };
+        match ViewState::load(path) {
+            Ok(Some(state)) => self.apply_view_state(state),
+            Ok(None) => (),
+            Err(err) => eprintln!("Ignoring the view state: {err}"),
+        }
+    }
+
+    /// Keeps how the sheet is shown next to its file, see `restore_view_state`.
+    fn save_view_state(&self) {
+        let Some(path) = &self.file_path else {
+            return;
+        };
+        if let Err(err) = self.view_state().save(path) {
+            eprintln!("Cannot keep the view state of {}: {err}", path.display());
+        }
+    }
+
+    /// Reloads the watched file when it changed since the last check, keeping the selection
+    /// and the view. A file that cannot be read keeps the sheet as it is. Nothing is reloaded
+    /// during an edit, which would be lost, until it ends.
+    fn reload_watched_file(&mut self) {
+        if self.edit.mode == EditMode::Editing
+            || get_time() - self.last_watch_check < WATCH_INTERVAL
+        {
+            return;
+        }
+        self.last_watch_check = get_time();
+        let Some(watcher) = &mut self.watcher else {
+            return;
+        };
+        if !watcher.changed() {
+            return;
+        }
+
+        let loaded = SpreadSheet::load_from_file(watcher.path());
+        match apply_reload(&mut self.spread_sheet, loaded) {
+            Ok(()) => {
+                self.saved = self.spread_sheet.snapshot();
+                self.background.discard();
+                self.edit.reload(&self.spread_sheet);
+                self.start_journal();
+            }
+            Err(warning) => eprintln!("{warning}"),
+        }
+    }
+
+    /// Hands the cells left to compute by the last edits to the background compute, and takes
+    /// its values once they are ready. Edits made meanwhile supersede the compute in flight.
+    fn sync_background_compute(&mut self) {
+        if self.spread_sheet.take_compute_request() {
+            self.background.submit(self.spread_sheet.pending_compute());
+        }
+        if let Some(computed) = self.background.poll() {
+            self.spread_sheet.apply_computed(computed);
+        }
+    }
+
+    /// Draws the prompt for a file path over the editor bar. Enter opens or saves at the
+    /// path typed and Escape closes the prompt.
+    fn draw_file_prompt(&mut self) {
+        let Some(prompt) = &mut self.file_prompt else {
+            return;
+        };
+
+        draw_rectangle(
+            0.0,
+            EDITOR_TOP_MARGIN,
+            screen_width(),
+            EDITOR_WINDOW_HEIGHT,
+            self.theme.panel_background,
+        );
+        let label = match prompt.action {
+            FileAction::Open if self.spread_sheet.is_modified() => {
+                "Open (unsaved changes are lost):"
+            }
+            FileAction::Open => "Open:",
+            FileAction::Save => "Save as:",
+        };
+        let text_params = TextParams {
+            font: Some(&self.regular_font),
+            font_size: EDITOR_FONT_SIZE,
+            color: self.theme.panel_text,
+            ..Default::default()
+        };
+        let label_width =
+            measure_text(label, Some(&self.regular_font), EDITOR_FONT_SIZE, 1.0).width;
+        let field_y = EDITOR_TOP_MARGIN + EDITOR_PADDING;
+        let text_y = field_y + EDITOR_HEIGHT * 0.75;
+        draw_text_ex(label, ROW_LABEL_WIDTH, text_y, text_params.clone());
+
+        let field_x = ROW_LABEL_WIDTH + label_width + NAME_BOX_MARGIN;
+        let field_width = screen_width() - field_x - EDITOR_STATUS_WIDTH;
+        root_ui().push_skin(&self.editor_skin);
+        root_ui().set_input_focus(hash!("file_prompt"));
+        InputText::new(hash!("file_prompt"))
+            .label("")
+            .position(vec2(field_x, field_y))
+            .size(vec2(field_width, EDITOR_HEIGHT))
+            .ui(&mut root_ui(), &mut prompt.content);
+        root_ui().pop_skin();
+
+        if let Some(error) = &prompt.error {
+            draw_text_ex(
+                error,
+                field_x + field_width + NAME_BOX_MARGIN,
+                text_y,
+                TextParams {
+                    color: self.theme.error_text,
+                    ..text_params
+                },
+            );
+        }
+
+        if is_key_pressed(KeyCode::Escape) {
+            self.file_prompt = None;
+        } else if is_key_pressed(KeyCode::Enter) {
+            let path = PathBuf::from(prompt.content.trim());
+            match prompt.action {
+                FileAction::Open => self.open_file(path),
+                FileAction::Save => self.save_file(path),
+            }
+        }
+    }
+
+    /// Asks whether to go on with a paste or fill replacing cells that hold data: Enter goes on,
+    /// Escape drops it and Space toggles whether such writes are asked about again.
+    fn draw_overwrite_prompt(&mut self) {
+        let Some(prompt) = &mut self.overwrite_prompt else {
+            return;
+        };
+        // Nothing typed meanwhile reaches the cells
+        while let Some(c) = get_char_pressed() {
+            if c == ' ' {
+                prompt.dont_ask_again = !prompt.dont_ask_again;
+            }
+        }
+
+        draw_rectangle(
+            0.0,
+            EDITOR_TOP_MARGIN,
+            screen_width(),
+            EDITOR_WINDOW_HEIGHT,
+            self.theme.panel_background,
+        );
+        let check = if prompt.dont_ask_again { "x" } else { " " };
+        let text = format!(
+            "{} Enter replaces, Escape cancels.   [{check}] Don't ask again (Space)",
+            prompt.question()
+        );
+        draw_text_ex(
+            &text,
+            ROW_LABEL_WIDTH,
+            EDITOR_TOP_MARGIN + EDITOR_PADDING + EDITOR_HEIGHT * 0.75,
+            TextParams {
+                font: Some(&self.regular_font),
+                font_size: EDITOR_FONT_SIZE,
+                color: self.theme.panel_text,
+                ..Default::default()
+            },
+        );
+
+        if is_key_pressed(KeyCode::Escape) {
+            self.overwrite_prompt = None;
+        } else if is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::KpEnter) {
+            let prompt = self.overwrite_prompt.take().expect("Checked above");
+            // Asked about already, the write goes on whatever it replaces
+            self.confirm_overwrite = false;
+            self.write_block(prompt.write);
+            self.confirm_overwrite = !prompt.dont_ask_again;
+        }
+    }
+
+    /// Handles the shortcuts that change how the sheet is shown: Ctrl+Shift+T switches between
+    /// the light and the dark theme, Ctrl+Shift+F freezes the rows above and the columns left
+    /// of the selected cell, or unfreezes them.
+    fn handle_view_keys(&mut self) {
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        if !ctrl || !shift {
+            return;
+        }
+
+        if is_key_pressed(KeyCode::T) {
+            self.theme = self.theme.toggled();
+            (self.editor_skin, self.name_box_error_skin) =
+                name_box_skins(&self.theme, &self.regular_font);
+        } else if is_key_pressed(KeyCode::F) {
+            if self.viewport.is_frozen() {
+                self.viewport.freeze(0, 0);
+            } else if let Some(selected) = self.edit.selected_cell() {
+                self.viewport.freeze(selected.x, selected.y);
+            }
+        }
+    }
+
+    /// F9 computes the cells left to compute in manual recalculation mode, and Shift+F9
+    /// switches between automatic and manual recalculation.
+    fn handle_recalc_keys(&mut self) {
+        if !is_key_pressed(KeyCode::F9) {
+            return;
+        }
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        if shift {
+            let mode = match self.spread_sheet.recalc_mode() {
+                RecalcMode::Automatic => RecalcMode::Manual,
+                RecalcMode::Manual => RecalcMode::Automatic,
+            };
+            self.spread_sheet.set_recalc_mode(mode);
+        } else {
+            self.spread_sheet.recalculate();
+        }
+    }
+
+    fn format(&self, index: Index) -> CellFormat {
+        self.formats.get(&index).cloned().unwrap_or_default()
+    }
+
+    /// Changes the format of every cell in `range` with `update`.
+    fn update_formats(&mut self, range: (Index, Index), update: impl Fn(&mut CellFormat)) {
+        let (top_left, bottom_right) = normalize_range(range.0, range.1);
+        for y in top_left.y..=bottom_right.y {
+            for x in top_left.x..=bottom_right.x {
+                let index = Index { x, y };
+                let mut format = self.format(index);
+                update(&mut format);
+                if format == CellFormat::default() {
+                    self.formats.remove(&index);
+                } else {
+                    self.formats.insert(index, format);
+                }
+            }
+        }
+    }
+
+    /// Grows the rows holding wrapped text to fit all of its lines.
+    fn update_row_heights(&mut self) {
+        let mut heights: HashMap<usize, f32> = HashMap::new();
+        for (index, format) in &self.formats {
+            if !format.wrap {
+                continue;
+            }
+            let text = computed_to_text(
+                self.spread_sheet.get_computed(*index),
+                self.spread_sheet.display_settings(),
+            );
+            let lines = self.wrap_cell_text(&text, self.columns.size(index.x)).len();
+            let height = lines as f32 * WRAPPED_LINE_HEIGHT + 2.0 * CELL_TEXT_PADDING;
+            let row_height = heights.entry(index.y).or_default();
+            *row_height = row_height.max(height);
+        }
+        self.rows.set_content_sizes(heights);
+    }
+
+    /// Breaks the text of a cell `width` wide into the lines it is drawn on when wrapped.
+    fn wrap_cell_text(&self, text: &str, width: f32) -> Vec<String> {
+        wrap_text(text, width - 2.0 * CELL_TEXT_PADDING, |line| {
+            self.measure_cached(line, FontFace::Regular, CELL_FONT_SIZE)
+                .width
+        })
+    }
+
+    /// Returns the highlighted dependants of the selected cell.
+    fn highlighted_dependants(&self) -> Option<(DependantDepth, Vec<Index>)> {
+        let highlight = self.dependants?;
+        let cells = match highlight.depth {
+            DependantDepth::Direct => self.spread_sheet.direct_dependants(highlight.source()),
+            DependantDepth::Transitive => self.spread_sheet.all_dependants(highlight.source()),
+        };
+        Some((highlight.depth, cells))
+    }
+
+    /// Returns the rectangles of cells visible in the grid between `start` and `end`: one, or
+    /// up to four when rows or columns are frozen.
+    fn visible_ranges(&self, start: (f32, f32), end: (f32, f32)) -> Vec<(Index, Index)> {
+        let (visible_cols, visible_rows) =
+            visible_cells(start, end, &self.viewport, (&self.columns, &self.rows));
+        line_ranges(&visible_cols, &visible_rows)
+    }
+
+    /// Tints each group of the highlighted duplicates in a color of its own.
+    fn draw_duplicates(&self, start: (f32, f32), end: (f32, f32)) {
+        let Some(highlight) = &self.duplicates else {
+            return;
+        };
+        if highlight.revision != self.spread_sheet.revision() {
+            return;
+        }
+        for (slot, group) in highlight.groups.iter().enumerate() {
+            let color = self.theme.reference_color(slot);
+            let fill = Color {
+                a: DUPLICATE_FILL_ALPHA,
+                ..color
+            };
+            for &cell in group {
+                if let Some(((x, y), (width, height))) = self.cell_rect(cell, start, end) {
+                    draw_rectangle(x, y, width, height, fill);
+                }
+            }
+        }
+    }
+
+    fn draw_dependants(&self, start: (f32, f32), end: (f32, f32)) {
+        let Some((_, cells)) = self.highlighted_dependants() else {
+            return;
+        };
+
+        let fill = Color {
+            a: REFERENCE_FILL_ALPHA,
+            ..self.theme.dependant
+        };
+        for cell in cells {
+            if let Some(((x, y), (width, height))) = self.cell_rect(cell, start, end) {
+                draw_rectangle(x, y, width, height, fill);
+                draw_rectangle_lines(x, y, width, height, 2.0, self.theme.dependant);
+            }
+        }
+    }
+
+    /// Returns the position and size of the values listed under the selected cell, while it
+    /// is visible in the grid between `start` and `end`.
+    fn value_list_rect(
+        &self,
+        start: (f32, f32),
+        end: (f32, f32),
+    ) -> Option<((f32, f32), (f32, f32))> {
+        let (idx, Some(dropdown)) = self.value_list.as_ref()? else {
+            return None;
+        };
+        let ((x, y), (cell_width, cell_height)) = self.cell_rect(*idx, start, end)?;
+        let width = dropdown
+            .items()
+            .iter()
+            .map(|item| {
+                measure_text(item, Some(&self.regular_font), SUGGESTION_FONT_SIZE, 1.0).width
+                    + TEXT_FIELD_PADDING * 2.0
+            })
+            .fold(cell_width, f32::max);
+        let height = dropdown.items().len() as f32 * SUGGESTION_ROW_HEIGHT;
+        // Keep the whole list on screen
+        let x = x.min(screen_width() - width).max(0.0);
+        Some(((x, y + cell_height), (width, height)))
+    }
+
+    /// Draws the values listed under the selected cell, writing the one clicked into it.
+    fn draw_value_list(&mut self, start: (f32, f32), end: (f32, f32)) {
+        self.update_value_list();
+        let Some(((x, y), size)) = self.value_list_rect(start, end) else {
+            return;
+        };
+        let Some((idx, Some(dropdown))) = &self.value_list else {
+            return;
+        };
+
+        let (mouse_x, mouse_y) = mouse_position();
+        let clicked = (is_mouse_button_pressed(MouseButton::Left)
+            && self.context_menu.is_none()
+            && (x..x + size.0).contains(&mouse_x))
+        .then(|| dropdown.item_at(mouse_y - y, SUGGESTION_ROW_HEIGHT))
+        .flatten();
+        if let Some(item) = clicked {
+            let value = dropdown.items()[item].clone();
+            self.value_list = Some((*idx, None));
+            self.edit.write(value, &mut self.spread_sheet);
+            return;
+        }
+
+        draw_rectangle(x, y, size.0, size.1, self.theme.suggestion_background);
+        for (i, item) in dropdown.items().iter().enumerate() {
+            let row_y = y + i as f32 * SUGGESTION_ROW_HEIGHT;
+            if i == dropdown.highlighted() {
+                draw_rectangle(
+                    x,
+                    row_y,
+                    size.0,
+                    SUGGESTION_ROW_HEIGHT,
+                    self.theme.selected_suggestion_background,
+                );
+            }
+            draw_text_ex(
+                item,
+                x + TEXT_FIELD_PADDING,
+                row_y + SUGGESTION_ROW_HEIGHT * 0.7,
+                TextParams {
+                    font: Some(&self.regular_font),
+                    font_size: SUGGESTION_FONT_SIZE,
+                    color: self.theme.cell_text,
+                    ..Default::default()
+                },
+            );
+        }
+        draw_rectangle_lines(x, y, size.0, size.1, 1.0, self.theme.field_border);
+    }
+
+    /// Hides the columns and rows the sheet hides, and the rows of the data the filter leaves
+    /// out. The first row of the used range is taken as the header of the data and always
+    /// shows, unless hidden by hand.
+    fn update_hidden_lines(&mut self) {
+        let columns = self.spread_sheet.hidden_columns().iter().copied().collect();
+        self.columns.set_hidden(columns);
+        let mut hidden = match self.spread_sheet.used_range() {
+            Some((top_left, bottom_right)) if self.row_filter.is_active() => self
+                .row_filter
+                .hidden_rows(top_left.y + 1..=bottom_right.y, |idx| self.shown_text(idx)),
+            _ => HashSet::new(),
+        };
+        hidden.extend(self.spread_sheet.hidden_rows());
+        self.rows.set_hidden(hidden);
+    }
+
+    /// Returns the text the cell at `idx` shows.
+    fn shown_text(&self, idx: Index) -> String {
+        computed_to_text_with_detail(
+            self.spread_sheet.get_computed(idx),
+            self.spread_sheet.display_settings(),
+            self.spread_sheet.effective_format(idx).number_format,
+        )
+        .0
+    }
+
+    /// Returns the position and size of the filter icon at the right end of the label of
+    /// column `col`, shown for the columns of `data_columns` and those filtered.
+    fn filter_icon_rect(
+        &self,
+        col: usize,
+        start: (f32, f32),
+        data_columns: Option<&RangeInclusive<usize>>,
+    ) -> Option<((f32, f32), (f32, f32))> {
+        if !data_columns.is_some_and(|columns| columns.contains(&col))
+            && self.row_filter.get(col).is_none()
+        {
+            return None;
+        }
+        let offset = self.viewport.column_offset(&self.columns, col)?;
+        let x = start.0 + ROW_LABEL_WIDTH + offset + self.columns.size(col)
+            - FILTER_ICON_SIZE
+            - FILTER_ICON_MARGIN;
+        let y = start.1 + (COL_LABEL_HEIGHT - FILTER_ICON_SIZE) / 2.0;
+        Some(((x, y), (FILTER_ICON_SIZE, FILTER_ICON_SIZE)))
+    }
+
+    /// Opens the filter picker of column `col` at `pos`, listing the values of the column
+    /// below the header of the data. An edit in progress is committed first.
+    fn open_filter_picker(&mut self, col: usize, pos: (f32, f32)) {
+        self.edit.commit(&mut self.spread_sheet);
+        self.edit.reload(&self.spread_sheet);
+
+        let values = match self.spread_sheet.used_range() {
+            Some((top_left, bottom_right)) => distinct_values(
+                (top_left.y + 1..=bottom_right.y).map(|y| self.shown_text(Index { x: col, y })),
+            ),
+            None => Vec::new(),
+        };
+        let mut picker = FilterPicker::new(col, pos, values);
+        // The picker opens on the filter set before
+        match self.row_filter.get(col) {
+            Some(ColumnFilter::Contains(part)) => picker.type_text(part),
+            Some(ColumnFilter::Equals(value)) => picker
+                .list
+                .highlight_item(&FilterChoice::Value(value.clone())),
+            None => (),
+        }
+        self.filter_picker = Some(picker);
+    }
+
+    /// Returns the position and size of the open filter picker, kept on screen: a row for
+    /// what was typed above the values listed.
+    fn filter_picker_rect(&self) -> Option<((f32, f32), (f32, f32))> {
+        let picker = self.filter_picker.as_ref()?;
+        let rows = picker.list.items().len().min(FILTER_PICKER_ROWS) + 1;
+        let size = (FILTER_PICKER_WIDTH, rows as f32 * SUGGESTION_ROW_HEIGHT);
+        let x = picker.pos.0.min(screen_width() - size.0).max(0.0);
+        let y = picker.pos.1.min(screen_height() - size.1).max(0.0);
+        Some(((x, y), size))
+    }
+
+    /// Draws the open filter picker and filters its column with what is picked in it. Typing
+    /// narrows the values listed, the arrows and Enter pick one, and Escape closes the picker.
+    fn draw_filter_picker(&mut self) {
+        let Some(picker) = &mut self.filter_picker else {
+            return;
+        };
+
+        let mut typed = String::new();
+        while let Some(c) = get_char_pressed() {
+            if !c.is_control() {
+                typed.push(c);
+            }
+        }
+        if !typed.is_empty() {
+            picker.type_text(&typed);
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            picker.backspace();
+        }
+        let key = [
+            (KeyCode::Up, DropdownKey::Up),
+            (KeyCode::Down, DropdownKey::Down),
+            (KeyCode::Enter, DropdownKey::Enter),
+            (KeyCode::KpEnter, DropdownKey::Enter),
+            (KeyCode::Escape, DropdownKey::Escape),
+        ]
+        .into_iter()
+        .find(|(code, _)| is_key_pressed(*code))
+        .map(|(_, key)| key);
+        let event = key.map(|key| picker.list.handle_key(key));
+
+        let Some(((x, y), size)) = self.filter_picker_rect() else {
+            return;
+        };
+        let Some(picker) = &self.filter_picker else {
+            return;
+        };
+        // The rows listed scroll along with the highlight
+        let first = picker
+            .list
+            .highlighted()
+            .saturating_sub(FILTER_PICKER_ROWS - 1);
+        let list_y = y + SUGGESTION_ROW_HEIGHT;
+        let (mouse_x, mouse_y) = mouse_position();
+        let clicked = (is_mouse_button_pressed(MouseButton::Left)
+            && (x..x + size.0).contains(&mouse_x))
+        .then(|| picker.list.item_at(mouse_y - list_y, SUGGESTION_ROW_HEIGHT))
+        .flatten()
+        .filter(|&row| row < FILTER_PICKER_ROWS)
+        .map(|row| first + row)
+        .filter(|&item| item < picker.list.items().len());
+
+        let event = match clicked {
+            Some(item) => Some(DropdownEvent::Chosen(picker.list.items()[item].clone())),
+            None => event,
+        };
+        match event {
+            Some(DropdownEvent::Chosen(choice)) => {
+                let column = picker.column;
+                self.row_filter.set(column, choice.filter());
+                self.filter_picker = None;
+                return;
+            }
+            Some(DropdownEvent::Closed) => {
+                self.filter_picker = None;
+                return;
+            }
+            Some(DropdownEvent::Moved) | None => (),
+        }
+
+        draw_rectangle(x, y, size.0, size.1, self.theme.suggestion_background);
+        // What was typed, or a hint that typing searches
+        let (query, color) = match picker.query.as_str() {
+            "" => ("Type to search", self.theme.suggestion_signature),
+            query => (query, self.theme.cell_text),
+        };
+        draw_text_ex(
+            query,
+            x + TEXT_FIELD_PADDING,
+            y + SUGGESTION_ROW_HEIGHT * 0.7,
+            TextParams {
+                font: Some(&self.regular_font),
+                font_size: SUGGESTION_FONT_SIZE,
+                color,
+                ..Default::default()
+            },
+        );
+        draw_line(x, list_y, x + size.0, list_y, 1.0, self.theme.field_border);
+
+        let items = picker.list.items().iter().enumerate();
+        for (i, item) in items.skip(first).take(FILTER_PICKER_ROWS) {
+            let row_y = list_y + (i - first) as f32 * SUGGESTION_ROW_HEIGHT;
+            let label = item.to_string();
+            if i == picker.list.highlighted() {
+                draw_rectangle(
+                    x,
+                    row_y,
+                    size.0,
+                    SUGGESTION_ROW_HEIGHT,
+                    self.theme.selected_suggestion_background,
+                );
+            }
+            draw_text_ex(
+                label.as_str(),
+                x + TEXT_FIELD_PADDING,
+                row_y + SUGGESTION_ROW_HEIGHT * 0.7,
+                TextParams {
+                    font: Some(&self.regular_font),
+                    font_size: SUGGESTION_FONT_SIZE,
+                    color: self.theme.cell_text,
+                    ..Default::default()
+                },
+            );
+        }
+        draw_rectangle_lines(x, y, size.0, size.1, 1.0, self.theme.field_border);
+    }
+
+    /// Returns the position and size of the open context menu, kept on screen.
+    fn menu_rect(&self) -> Option<((f32, f32), (f32, f32))> {
+        let menu = self.context_menu?;
+        let size = (MENU_WIDTH, menu.items().len() as f32 * MENU_ITEM_HEIGHT);
+        let x = menu.pos.0.min(screen_width() - size.0).max(0.0);
+        let y = menu.pos.1.min(screen_height() - size.1).max(0.0);
+        Some(((x, y), size))
+    }
+
+    /// Draws the open context menu and runs the item clicked in it. Clicking anywhere closes
+    /// the menu.
+    fn draw_context_menu(&mut self) {
+        let (Some(menu), Some((pos, size))) = (self.context_menu, self.menu_rect()) else {
+            return;
+        };
+        let placed = ContextMenu { pos, ..menu };
+        let hovered = placed.item_at(mouse_position(), (MENU_WIDTH, MENU_ITEM_HEIGHT));
+
+        if is_mouse_button_pressed(MouseButton::Left) {
+            self.context_menu = None;
+            if let Some(item) = hovered {
+                self.run_menu_action(menu.target, menu.items()[item].0);
+            }
+            return;
+        }
+
+        let (x, y) = pos;
+        draw_rectangle(x, y, size.0, size.1, self.theme.suggestion_background);
+        for (i, (action, text)) in menu.items().iter().enumerate() {
+            let item_y = y + i as f32 * MENU_ITEM_HEIGHT;
+            if hovered == Some(i) {
+                draw_rectangle(
+                    x,
+                    item_y,
+                    size.0,
+                    MENU_ITEM_HEIGHT,
+                    self.theme.selected_suggestion_background,
+                );
+            }
+            draw_text_ex(
+                text,
+                x + TEXT_FIELD_PADDING * 2.0,
+                item_y + MENU_ITEM_HEIGHT * 0.7,
+                TextParams {
+                    font: Some(&self.regular_font),
+                    font_size: MENU_FONT_SIZE,
+                    color: self.theme.cell_text,
+                    ..Default::default()
+                },
+            );
+            // Fill colors show a swatch at the right
+            if let MenuAction::Fill(Some(fill)) = action {
+                let swatch = MENU_ITEM_HEIGHT - 2.0 * MENU_SWATCH_MARGIN;
+                let swatch_x = x + size.0 - MENU_SWATCH_MARGIN - swatch;
+                let swatch_y = item_y + MENU_SWATCH_MARGIN;
+                draw_rectangle(swatch_x, swatch_y, swatch, swatch, fill_color(*fill));
+                draw_rectangle_lines(
+                    swatch_x,
+                    swatch_y,
+                    swatch,
+                    swatch,
+                    1.0,
+                    self.theme.field_border,
+                );
+            }
+        }
+        draw_rectangle_lines(x, y, size.0, size.1, 1.0, self.theme.field_border);
+    }
+
+    fn run_menu_action(&mut self, target: MenuTarget, action: MenuAction) {
+        match (target, action) {
+            (
+                MenuTarget::Label(Label::Column(col)),
+                MenuAction::SortAscending | MenuAction::SortDescending,
+            ) => {
+                // An edit in progress is sorted along with the rest of its row
+                self.edit.commit(&mut self.spread_sheet);
+                let Some(range) = self.spread_sheet.used_range() else {
+                    return;
+                };
+                let result = self.spread_sheet.sort_rows_by_column(
+                    col,
+                    range,
+                    action == MenuAction::SortDescending,
+                );
+                self.edit.report(result);
+                self.edit.reload(&self.spread_sheet);
+            }
+            (MenuTarget::Label(Label::Column(col)), MenuAction::ColumnFormat(format)) => {
+                self.spread_sheet.set_column_format(col, format);
+            }
+            (MenuTarget::Label(label), MenuAction::Hide) => {
+                match label {
+                    Label::Column(col) => self.spread_sheet.set_column_hidden(col, true),
+                    Label::Row(row) => self.spread_sheet.set_row_hidden(row, true),
+                }
+                self.update_hidden_lines();
+                // The selection moves on to the next cell shown
+                if let Some(selected) = self.edit.selected_cell() {
+                    let next = match label {
+                        Label::Column(col) if col == selected.x => {
+                            Some(self.step(selected, Direction::Right))
+                        }
+                        Label::Row(row) if row == selected.y => {
+                            Some(self.step(selected, Direction::Down))
+                        }
+                        _ => None,
+                    };
+                    if let Some(next) = next {
+                        self.edit.select(next, &mut self.spread_sheet);
+                    }
+                }
+            }
+            (MenuTarget::Label(label), MenuAction::Unhide) => {
+                match label {
+                    Label::Column(col) => {
+                        let hidden = self
+                            .columns
+                            .hidden_next_to(col, |col| self.spread_sheet.is_column_hidden(col));
+                        for col in hidden {
+                            self.spread_sheet.set_column_hidden(col, false);
+                        }
+                    }
+                    Label::Row(row) => {
+                        let hidden = self
+                            .rows
+                            .hidden_next_to(row, |row| self.spread_sheet.is_row_hidden(row));
+                        for row in hidden {
+                            self.spread_sheet.set_row_hidden(row, false);
+                        }
+                    }
+                }
+                self.update_hidden_lines();
+            }
+            (MenuTarget::Cells, MenuAction::CopyAsCsv) => {
+                let Some(selection) = self.edit.selection else {
+                    return;
+                };
+                let text = clipboard::selection_to_csv(&self.spread_sheet, selection);
+                miniquad::window::clipboard_set(&text);
+                self.copied = None;
+            }
+            (MenuTarget::Cells, MenuAction::CopyShown) => {
+                let Some(selection) = self.edit.selection else {
+                    return;
+                };
+                let grid = clipboard::shown_cells(
+                    selection,
+                    |row| self.rows.is_hidden(row),
+                    |col| self.columns.is_hidden(col),
+                );
+                let text = clipboard::cells_to_tsv(&self.spread_sheet, &grid, CopyMode::Values);
+                miniquad::window::clipboard_set(&text);
+                self.copied = None;
+            }
+            (MenuTarget::Cells, MenuAction::PasteValues) => {
+                self.edit.commit(&mut self.spread_sheet);
+                self.paste(true);
+            }
+            (MenuTarget::Cells, MenuAction::ConvertToValues) => {
+                // An edit in progress is converted too
+                self.edit.commit(&mut self.spread_sheet);
+                if let Some(range) = self.edit.selection {
+                    let result = self.spread_sheet.convert_to_values(range);
+                    self.edit.report(result);
+                }
+                self.edit.reload(&self.spread_sheet);
+            }
+            (MenuTarget::Cells, MenuAction::Fill(fill)) => {
+                let Some(range) = self.edit.selection else {
+                    return;
+                };
+                let (top_left, bottom_right) = normalize_range(range.0, range.1);
+                self.spread_sheet.batch(|sheet| {
+                    for y in top_left.y..=bottom_right.y {
+                        for x in top_left.x..=bottom_right.x {
+                            sheet.set_fill(Index { x, y }, fill);
+                        }
+                    }
+                });
+            }
+            (MenuTarget::Cells, MenuAction::Borders(placement)) => {
+                let Some(range) = self.edit.selection else {
+                    return;
+                };
+                match placement {
+                    BorderPlacement::All => {
+                        self.spread_sheet
+                            .set_border(range, &Edge::ALL, Some(MENU_BORDER))
+                    }
+                    BorderPlacement::Outline => self
+                        .spread_sheet
+                        .set_outline_border(range, Some(MENU_OUTLINE_BORDER)),
+                    BorderPlacement::Clear => self.spread_sheet.set_border(range, &Edge::ALL, None),
+                }
+            }
+            (MenuTarget::Cells, MenuAction::ToggleLocked) => {
+                let Some(range) = self.edit.selection else {
+                    return;
+                };
+                // Locked unless all of them already are
+                let (top_left, bottom_right) = normalize_range(range.0, range.1);
+                let locked = (top_left.y..=bottom_right.y)
+                    .flat_map(|y| (top_left.x..=bottom_right.x).map(move |x| Index { x, y }))
+                    .any(|index| !self.spread_sheet.get_style(index).locked);
+                self.spread_sheet.set_locked(range, locked);
+            }
+            (MenuTarget::Cells, MenuAction::ToggleProtection) => {
+                // An edit in progress goes in before its cell may be protected
+                self.edit.commit(&mut self.spread_sheet);
+                let protected = self.spread_sheet.is_protected();
+                self.spread_sheet.set_protection(!protected);
+            }
+            (MenuTarget::Cells, MenuAction::NumberStyle(style)) => self.apply_number_style(style),
+            (MenuTarget::Cells, MenuAction::AddSummary) => {
+                // An edit in progress is summarized too
+                self.edit.commit(&mut self.spread_sheet);
+                if let Some(range) = self.edit.selection {
+                    let stats = [SummaryStat::Sum, SummaryStat::Average, SummaryStat::Count];
+                    let result = self.spread_sheet.add_summary_row(range, &stats);
+                    self.edit.report(result);
+                }
+                self.edit.reload(&self.spread_sheet);
+            }
+            (MenuTarget::Cells, MenuAction::HighlightDuplicates { ignore_case }) => {
+                // An edit in progress is compared too
+                self.edit.commit(&mut self.spread_sheet);
+                self.edit.reload(&self.spread_sheet);
+                self.duplicates = self.edit.selection.map(|range| DuplicateHighlight {
+                    revision: self.spread_sheet.revision(),
+                    groups: self.spread_sheet.find_duplicates(range, ignore_case),
+                });
+            }
+            (MenuTarget::Cells, MenuAction::ShowHistory) => {
+                // An edit in progress is part of the history
+                self.edit.commit(&mut self.spread_sheet);
+                self.edit.reload(&self.spread_sheet);
+                self.history = self.edit.selected_cell();
+            }
+            _ => (),
+        }
+    }
+
+    /// Lists the changes of the cell the history panel is open for at the right of the grid,
+    /// newest first, as far as they fit.
+    fn draw_history_panel(&self, start: (f32, f32), end: (f32, f32)) {
+        let Some(index) = self.history else {
+            return;
+        };
+        let mut lines = match self.spread_sheet.journal() {
+            Some(journal) => {
+                let mut lines = history_lines(journal.history(index), SystemTime::now());
+                if lines.is_empty() {
+                    lines.push("No changes kept".to_string());
+                }
+                lines.extend(journal.log_error().map(str::to_string));
+                lines
+            }
+            None => vec!["The journal is off, see journal in config.toml".to_string()],
+        };
+        lines.truncate(HISTORY_PANEL_ROWS);
+        lines.insert(
+            0,
+            format!("History of {} (Escape closes)", index_to_name(index)),
+        );
+
+        let x = (end.0 - HISTORY_PANEL_WIDTH).max(start.0);
+        let y = start.1 + COL_LABEL_HEIGHT;
+        let height = lines.len() as f32 * SUGGESTION_ROW_HEIGHT;
+        draw_rectangle(
+            x,
+            y,
+            HISTORY_PANEL_WIDTH,
+            height,
+            self.theme.panel_background,
+        );
+        for (i, line) in lines.iter().enumerate() {
+            draw_text_ex(
+                line,
+                x + TEXT_FIELD_PADDING,
+                y + (i as f32 + 0.7) * SUGGESTION_ROW_HEIGHT,
+                TextParams {
+                    font: Some(if i == 0 {
+                        &self.bold_font
+                    } else {
+                        &self.regular_font
+                    }),
+                    font_size: SUGGESTION_FONT_SIZE,
+                    color: self.theme.panel_text,
+                    ..Default::default()
+                },
+            );
+        }
+        draw_rectangle_lines(
+            x,
+            y,
+            HISTORY_PANEL_WIDTH,
+            height,
+            1.0,
+            self.theme.field_border,
+        );
+    }
+
+    /// Draws the suggested functions with their arguments under the partial name being typed.
+    fn draw_suggestions(&self, start: (f32, f32), end: (f32, f32)) {
+        let Some((prefix, matches)) = self.suggestions() else {
+            return;
+        };
+        let Some((field_pos, field_size)) = self.caret_field_rect(start, end) else {
+            return;
+        };
+
+        let (offsets, scroll) = self.text_field_offsets(field_size.0, true);
+        let prefix_x = offsets
+            .iter()
+            .find(|&&(i, _)| i == prefix.start)
+            .map_or(0.0, |&(_, x)| x);
+        let x = field_pos.0 + TEXT_FIELD_PADDING + prefix_x - scroll;
+        let y = field_pos.1 + field_size.1;
+
+        let width = matches
+            .iter()
+            .map(|function| {
+                measure_text(
+                    function.signature,
+                    Some(&self.regular_font),
+                    SUGGESTION_FONT_SIZE,
+                    1.0,
+                )
+                .width
+            })
+            .fold(SUGGESTION_MIN_WIDTH, f32::max)
+            + TEXT_FIELD_PADDING * 2.0;
+        let height = matches.len() as f32 * SUGGESTION_ROW_HEIGHT;
+        // Keep the whole list on screen
+        let x = x.min(screen_width() - width).max(0.0);
+
+        draw_rectangle(x, y, width, height, self.theme.suggestion_background);
+        let selected = self.autocomplete.selected.min(matches.len() - 1);
+        for (i, function) in matches.iter().enumerate() {
+            let row_y = y + i as f32 * SUGGESTION_ROW_HEIGHT;
+            if i == selected {
+                draw_rectangle(
+                    x,
+                    row_y,
+                    width,
+                    SUGGESTION_ROW_HEIGHT,
+                    self.theme.selected_suggestion_background,
+                );
+            }
+
+            // The name in bold, followed by its arguments in gray
+            let text_y = row_y + SUGGESTION_ROW_HEIGHT * 0.7;
+            let arguments = &function.signature[function.name.len()..];
+            let name_width = measure_text(
+                function.name,
+                Some(&self.bold_font),
+                SUGGESTION_FONT_SIZE,
+                1.0,
+            )
+            .width;
+            for (text, font, text_x, color) in [
+                (function.name, &self.bold_font, x, self.theme.cell_text),
+                (
+                    arguments,
+                    &self.regular_font,
+                    x + name_width,
+                    self.theme.suggestion_signature,
+                ),
+            ] {
+                draw_text_ex(
+                    text,
+                    text_x + TEXT_FIELD_PADDING,
+                    text_y,
+                    TextParams {
+                        font: Some(font),
+                        font_size: SUGGESTION_FONT_SIZE,
+                        font_scale: 1.0,
+                        font_scale_aspect: 1.0,
+                        rotation: 0.0,
+                        color,
+                    },
+                );
+            }
+        }
+        draw_rectangle_lines(x, y, width, height, 1.0, self.theme.field_border);
+    }
+
+    /// Draws an editor right over the cell being edited. It shares its content with the
+    /// editor bar, so typing in either shows up in both.
+    fn draw_cell_editor(&mut self, start: (f32, f32), end: (f32, f32)) {
+        let Some((pos, size)) = self.cell_editor_rect(start, end) else {
+            return;
+        };
+
+        self.handle_text_field_mouse(pos, size);
+        self.draw_text_field(pos, size, true);
+    }
+
+    /// Returns the caret positions of the editor content with their x coordinate relative to
+    /// the text start, and how far the text is scrolled so the caret stays inside `width`.
+    fn text_field_offsets(&self, width: f32, has_caret: bool) -> (Vec<(usize, f32)>, f32) {
+        let content = &self.edit.editor_content;
+        let offsets = caret_offsets(content, |c| {
+            measure_text(
+                c.encode_utf8(&mut [0; 4]),
+                Some(&self.regular_font),
+                EDITOR_FONT_SIZE,
+                1.0,
+            )
+            .width
+        });
+
+        let caret = self.text_editor.caret(content);
+        let scroll = match offsets.iter().find(|&&(i, _)| i == caret) {
+            Some(&(_, caret_x)) if has_caret => {
+                (caret_x - (width - TEXT_FIELD_PADDING * 2.0)).max(0.0)
+            }
+            _ => 0.0,
+        };
+        (offsets, scroll)
+    }
+
+    /// Places the caret where the editor field at `pos` is clicked, and selects text while
+    /// the mouse is dragged on.
+    fn handle_text_field_mouse(&mut self, pos: (f32, f32), size: (f32, f32)) {
+        let (x, y) = mouse_position();
+        let (offsets, scroll) = self.text_field_offsets(size.0, true);
+        let caret = caret_at(&offsets, x - pos.0 - TEXT_FIELD_PADDING + scroll);
+
+        let end = (pos.0 + size.0, pos.1 + size.1);
+        if is_mouse_button_pressed(MouseButton::Left) && is_point_in_rect((x, y), pos, end) {
+            let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+            self.text_editor
+                .set_caret(&self.edit.editor_content, caret, shift);
+            self.selecting_text = true;
+        } else if self.selecting_text && is_mouse_button_down(MouseButton::Left) {
+            self.text_editor
+                .set_caret(&self.edit.editor_content, caret, true);
+        }
+        if !is_mouse_button_down(MouseButton::Left) {
+            self.selecting_text = false;
+        }
+    }
+
+    /// Draws the editor content in a field at `pos`, colored as a formula. The field with the
+    /// caret also shows the text selection and the parenthesis matching the one at the caret.
+    fn draw_text_field(&self, pos: (f32, f32), size: (f32, f32), has_caret: bool) {
+        let content = &self.edit.editor_content;
+        draw_rectangle(pos.0, pos.1, size.0, size.1, self.theme.grid_background);
+        draw_rectangle_lines(pos.0, pos.1, size.0, size.1, 1.0, self.theme.field_border);
+
+        let (offsets, scroll) = self.text_field_offsets(size.0, has_caret);
+        let text_x = pos.0 + TEXT_FIELD_PADDING - scroll;
+        let x_of = |offset: usize| {
+            offsets
+                .iter()
+                .find(|&&(i, _)| i == offset)
+                .map_or(0.0, |&(_, x)| text_x + x)
+        };
+        let draw_background = |range: std::ops::Range<usize>, color: Color| {
+            let from = x_of(range.start).max(pos.0);
+            let to = x_of(range.end).min(pos.0 + size.0);
+            if to > from {
+                draw_rectangle(from, pos.1 + 2.0, to - from, size.1 - 4.0, color);
+            }
+        };
+
+        let caret = self.text_editor.caret(content);
+        if has_caret {
+            if let Some(selection) = self.text_editor.selection(content) {
+                draw_background(selection, self.theme.text_selection);
+            }
+            if let Some((paren, matching)) = matching_paren(content, caret) {
+                draw_background(paren..paren + 1, self.theme.matching_paren);
+                draw_background(matching..matching + 1, self.theme.matching_paren);
+            }
+        }
+
+        // Characters are drawn one by one, leaving out those that do not fit the field
+        let text_height = measure_text("0", Some(&self.regular_font), EDITOR_FONT_SIZE, 1.0).height;
+        let text_y = pos.1 + (size.1 + text_height) / 2.0;
+        let spans = highlight_spans(content);
+        for pair in offsets.windows(2) {
+            let ((start, x), (end, next_x)) = (pair[0], pair[1]);
+            let (x, next_x) = (text_x + x, text_x + next_x);
+            if x < pos.0 || next_x > pos.0 + size.0 {
+                continue;
+            }
+
+            let highlight = spans
+                .iter()
+                .find(|(range, _)| range.contains(&start))
+                .map_or(Highlight::Plain, |&(_, highlight)| highlight);
+            draw_text_ex(
+                &content[start..end],
+                x,
+                text_y,
+                TextParams {
+                    font: Some(&self.regular_font),
+                    font_size: EDITOR_FONT_SIZE,
+                    font_scale: 1.0,
+                    font_scale_aspect: 1.0,
+                    rotation: 0.0,
+                    color: highlight_color(&self.theme, highlight),
+                },
+            );
+        }
+
+        // Where the formula stopped parsing
+        let error_underline = self
+            .parse_error_span()
+            .and_then(|span| underline(&offsets, span, EDITOR_FONT_SIZE as f32 / 2.0));
+        if let Some((x, width)) = error_underline {
+            let from = (text_x + x).max(pos.0);
+            let to = (text_x + x + width).min(pos.0 + size.0);
+            if to > from {
+                let y = text_y + 3.0;
+                draw_line(from, y, to, y, 1.5, self.theme.invalid_formula);
+            }
+        }
+
+        if has_caret && get_time() % (CARET_BLINK_TIME * 2.0) < CARET_BLINK_TIME {
+            let x = x_of(caret);
+            draw_line(
+                x,
+                pos.1 + 4.0,
+                x,
+                pos.1 + size.1 - 4.0,
+                1.0,
+                self.theme.cell_text,
+            );
+        }
+    }
+
+    /// Returns the bytes of the editor content a parse error was found at: the error stored
+    /// with the selected cell while the editor holds its content, the one found checking the
+    /// content typed otherwise.
+    fn parse_error_span(&self) -> Option<std::ops::Range<usize>> {
+        let error = if self.edit.is_modified() {
+            match &self.formula_status {
+                Some(FormulaStatus::Invalid(error)) => error,
+                _ => return None,
+            }
+        } else {
+            self.spread_sheet.parse_error(self.edit.selected_cell()?)?
+        };
+        // The content is checked without the whitespace it starts with
+        let content = &self.edit.editor_content;
+        let skipped = content.len() - content.trim_start().len();
+        let span = error.span.clone()?;
+        Some(span.start + skipped..span.end + skipped)
+    }
+
+    /// Re-parses the editor content only when it changed since the last frame.
+    /// Never mutates the spreadsheet.
+    fn update_formula_status(&mut self) {
+        if self.edit.editor_content == self.validated_content {
+            return;
+        }
+        self.validated_content.clone_from(&self.edit.editor_content);
+
+        let content = self.edit.editor_content.trim();
+        self.formula_status = if content.starts_with('=') {
+            Some(match SpreadSheet::validate_formula(content) {
+                Ok(()) => FormulaStatus::Valid(match self.edit.selected_cell() {
+                    Some(idx) => self.spread_sheet.evaluate_in_cell(idx, content),
+                    None => self.spread_sheet.evaluate(content),
+                }),
+                Err(e) => FormulaStatus::Invalid(e),
+            })
+        } else {
+            None
+        };
+    }
+
+    fn draw_formula_status(&self) {
+        let start_x = screen_width() - EDITOR_STATUS_WIDTH;
+        let center_y = EDITOR_TOP_MARGIN + EDITOR_WINDOW_HEIGHT / 2.0;
+
+        draw_rectangle(
+            start_x,
+            EDITOR_TOP_MARGIN,
+            EDITOR_STATUS_WIDTH,
+            EDITOR_WINDOW_HEIGHT,
+            self.theme.panel_background,
+        );
+
+        let (text, color) = match &self.formula_status {
+            None => return,
+            Some(FormulaStatus::Valid(result)) => {
+                // Draw a check mark in front of the would-be value
+                let check_x = start_x + 8.0;
+                draw_line(
+                    check_x,
+                    center_y,
+                    check_x + 4.0,
+                    center_y + 5.0,
+                    2.0,
+                    self.theme.valid_formula,
+                );
+                draw_line(
+                    check_x + 4.0,
+                    center_y + 5.0,
+                    check_x + 12.0,
+                    center_y - 6.0,
+                    2.0,
+                    self.theme.valid_formula,
+                );
+
+                let color = if result.is_ok() {
+                    self.theme.valid_formula
+                } else {
+                    self.theme.invalid_formula
+                };
+                (
+                    format!(
+                        "= {}",
+                        computed_to_text(
+                            Some(result.clone()),
+                            self.spread_sheet.display_settings()
+                        )
+                    ),
+                    color,
+                )
+            }
+            Some(FormulaStatus::Invalid(e)) => (e.message.clone(), self.theme.invalid_formula),
+        };
+
+        let text_start_x = start_x + 28.0;
+        let lines = split_into_lines(
+            &text,
+            &self.regular_font,
+            EDITOR_STATUS_FONT_SIZE,
+            EDITOR_STATUS_WIDTH - 36.0,
+        );
+
+        // Only two lines fit into the editor bar
+        let line_height = EDITOR_STATUS_FONT_SIZE as f32 + 4.0;
+        let shown = lines.len().min(2);
+        let mut text_y = center_y - (shown as f32 * line_height) / 2.0 + line_height - 4.0;
+        for line in lines.iter().take(shown) {
+            draw_text_ex(
+                line,
+                text_start_x,
+                text_y,
+                TextParams {
+                    font: Some(&self.regular_font),
+                    font_size: EDITOR_STATUS_FONT_SIZE,
+                    color,
+                    ..Default::default()
+                },
+            );
+            text_y += line_height;
+        }
+    }
+
+    /// Draws the bar at the bottom of the window, summarizing a selection of several cells or
+    /// explaining the error of a single one.
+    fn draw_status_bar(&self, grid_start: (f32, f32), grid_end: (f32, f32)) {
+        let start_y = screen_height() - STATUS_BAR_HEIGHT;
+        draw_rectangle(
+            0.0,
+            start_y,
+            screen_width(),
+            STATUS_BAR_HEIGHT,
+            self.theme.panel_background,
+        );
+
+        // The file of the sheet on the right, marked while it has unsaved changes and preceded
+        // by a note while cells are being computed or wait to be
+        let file_name = self
+            .file_path
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .map_or_else(|| "Untitled".into(), |name| name.to_string_lossy());
+        let modified_mark = if self.spread_sheet.is_modified() {
+            "*"
+        } else {
+            ""
+        };
+        let computing = if self.background.is_busy() {
+            "Computing… "
+        } else if self.spread_sheet.needs_recalc() {
+            "Needs recalc (F9) "
+        } else {
+            ""
+        };
+        let file_text = format!("{computing}{file_name}{modified_mark}");
+        let file_text_width = measure_text(
+            &file_text,
+            Some(&self.regular_font),
+            STATUS_BAR_FONT_SIZE,
+            1.0,
+        )
+        .width;
+        draw_text_ex(
+            &file_text,
+            screen_width() - ROW_LABEL_WIDTH - file_text_width,
+            start_y + STATUS_BAR_HEIGHT / 2.0 + STATUS_BAR_FONT_SIZE as f32 / 2.0 - 2.0,
+            TextParams {
+                font: Some(&self.regular_font),
+                font_size: STATUS_BAR_FONT_SIZE,
+                color: self.theme.panel_text,
+                ..Default::default()
+            },
+        );
+        if let Some(progress) = self.background.progress() {
+            let x = screen_width() - ROW_LABEL_WIDTH - file_text_width - PROGRESS_BAR_WIDTH - 8.0;
+            let y = start_y + (STATUS_BAR_HEIGHT - PROGRESS_BAR_HEIGHT) / 2.0;
+            draw_rectangle_lines(
+                x,
+                y,
+                PROGRESS_BAR_WIDTH,
+                PROGRESS_BAR_HEIGHT,
+                1.0,
+                self.theme.field_border,
+            );
+            draw_rectangle(
+                x,
+                y,
+                PROGRESS_BAR_WIDTH * progress,
+                PROGRESS_BAR_HEIGHT,
+                self.theme.selected_cell_border,
+            );
+        }
+
+        // A link under the mouse tells where it leads, whatever is selected
+        let hovered_link = self
+            .hover
+            .and_then(|(idx, _)| cell_link(&self.spread_sheet, idx));
+        let text = if let Some(url) = hovered_link {
+            format!("Ctrl+click to open {url}")
+        } else if let Some(text) = self.selection_status(grid_start, grid_end) {
+            text
+        } else {
+            stats_to_text(
+                &self.spread_sheet.stats(),
+                self.spread_sheet.display_settings(),
+            )
+        };
+
+        draw_text_ex(
+            &text,
+            ROW_LABEL_WIDTH,
+            start_y + STATUS_BAR_HEIGHT / 2.0 + STATUS_BAR_FONT_SIZE as f32 / 2.0 - 2.0,
+            TextParams {
+                font: Some(&self.regular_font),
+                font_size: STATUS_BAR_FONT_SIZE,
+                color: self.theme.panel_text,
+                ..Default::default()
+            },
+        );
+    }
+
+    /// Returns what the status bar tells about the selection, if anything.
+    fn selection_status(&self, grid_start: (f32, f32), grid_end: (f32, f32)) -> Option<String> {
+        let (anchor, focus) = self.edit.selection?;
+        let text = if let Some(rejection) = &self.edit.rejection {
+            format!("Rejected: {rejection}")
+        } else if let Some((depth, cells)) = self.highlighted_dependants() {
+            let off_screen = count_outside(&cells, &self.visible_ranges(grid_start, grid_end));
+            dependants_to_text(depth, cells.len(), off_screen)
+        } else if let Some(highlight) = self
+            .duplicates
+            .as_ref()
+            .filter(|highlight| highlight.revision == self.spread_sheet.revision())
+        {
+            highlight.to_text()
+        } else if !self.edit.added.is_empty() {
+            summary_to_text(
+                &self
+                    .spread_sheet
+                    .summarize_cells(self.edit.selected_ranges().cells()),
+                self.spread_sheet.display_settings(),
+            )
+        } else if anchor != focus {
+            summary_to_text(
+                &self.spread_sheet.summarize_range(anchor, focus),
+                self.spread_sheet.display_settings(),
+            )
+        } else if let Some(err) = self.spread_sheet.get_error(anchor) {
+            err.message(Language::English)
+        } else if let Some(ValidationState::Invalid { message, .. }) =
+            self.spread_sheet.validation_state(anchor)
+        {
+            message
+        } else {
+            return None;
+        };
+        Some(text)
+    }
+
+    /// Scrolls vertically with the mouse wheel, or horizontally while Shift is held.
+    fn handle_scroll(&mut self) {
+        let (wheel_x, wheel_y) = mouse_wheel();
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+
+        let (rows, cols) = if shift {
+            (0, -wheel_steps(wheel_y))
+        } else {
+            (
+                -wheel_steps(wheel_y) * WHEEL_ROWS_PER_NOTCH,
+                wheel_steps(wheel_x),
+            )
+        };
+
+        if rows != 0 || cols != 0 {
+            // Hidden rows are scrolled past without counting
+            let first_row = self.viewport.first_row;
+            let rows = self.rows.advance(first_row, rows) as isize - first_row as isize;
+            self.viewport.scroll(rows, cols, self.scroll_bounds());
+        }
+    }
+
+    /// Returns the `(columns, rows)` the wheel and paging scroll within, reaching a margin
+    /// past the used cells and the selection.
+    fn scroll_bounds(&self) -> (usize, usize) {
+        let used_end = self.spread_sheet.used_range().map(|(_, end)| end);
+        let focus = self.edit.selection_focus();
+        let last_used = Index {
+            x: used_end.map_or(0, |idx| idx.x).max(focus.map_or(0, |idx| idx.x)),
+            y: used_end
+                .map_or(0, |idx| idx.y)
+                .max(focus.map_or(0, |idx| idx.y)),
+        };
+        scroll_bounds(last_used, self.grid)
+    }
+
+    /// PageUp and PageDown scroll a screen of rows, taking the selection along. Ctrl+Home
+    /// selects A1 and Ctrl+End the bottom right cell of the used range. Shift extends the
+    /// selection instead of moving it.
+    fn handle_paging(&mut self, grid_start: (f32, f32), grid_end: (f32, f32)) {
+        if self.name_box.focused || self.is_editing_formula() {
+            return;
+        }
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        let focus = self.edit.selection_focus().unwrap_or(Index { x: 0, y: 0 });
+
+        let target = if is_key_pressed(KeyCode::PageDown) || is_key_pressed(KeyCode::PageUp) {
+            let (_, height) = cells_area(grid_start, grid_end);
+            let page = self.viewport.page_rows(&self.rows, height).max(1) as isize;
+            let rows = if is_key_pressed(KeyCode::PageUp) {
+                -page
+            } else {
+                page
+            };
+            self.viewport.scroll(rows, 0, self.grid);
+            Index {
+                x: focus.x,
+                y: focus.y.saturating_add_signed(rows).min(self.grid.1 - 1),
+            }
+        } else if ctrl && is_key_pressed(KeyCode::Home) {
+            Index { x: 0, y: 0 }
+        } else if ctrl && is_key_pressed(KeyCode::End) {
+            self.spread_sheet
+                .used_range()
+                .map_or(Index { x: 0, y: 0 }, |(_, end)| end)
+        } else {
+            return;
+        };
+        // A hidden row is passed over towards the header of the data, a hidden column towards
+        // the first one
+        let target = match self.rows.is_hidden(target.y) {
+            true => self.step(target, Direction::Up),
+            false => target,
+        };
+        let target = match self.columns.is_hidden(target.x) {
+            true => self.step(target, Direction::Left),
+            false => target,
+        };
+
+        if shift {
+            self.edit.extend_selection(target, &mut self.spread_sheet);
+        } else {
+            self.edit.select(target, &mut self.spread_sheet);
+        }
+    }
+
+    /// Lets the user drag the boundary between two column labels to resize the left column.
+    /// Returns true while a resize is in progress, so the drag is not treated as a click.
+    fn handle_column_resize(&mut self, start: (f32, f32), end: (f32, f32)) -> bool {
+        let (x, y) = mouse_position();
+        let cells_start_x = start.0 + ROW_LABEL_WIDTH;
+
+        let in_label_strip = is_point_in_rect(
+            (x, y),
+            (cells_start_x, start.1),
+            (end.0, start.1 + COL_LABEL_HEIGHT),
+        );
+        let grabbed_col = in_label_strip
+            .then(|| {
+                self.viewport.column_boundary_at(
+                    &self.columns,
+                    x - cells_start_x,
+                    RESIZE_GRAB_TOLERANCE,
+                )
+            })
+            .flatten();
+
+        if let Some(col) = grabbed_col {
+            if is_mouse_button_pressed(MouseButton::Left) {
+                self.column_resize = Some(ColumnResize {
+                    col,
+                    grab_x: x,
+                    initial_width: self.columns.size(col),
+                });
+            }
+        }
+
+        if let Some(resize) = &self.column_resize {
+            if is_mouse_button_down(MouseButton::Left) {
+                self.columns
+                    .set_size(resize.col, resize.initial_width + x - resize.grab_x);
+            } else {
+                self.column_resize = None;
+            }
+        }
+
+        let cursor = if grabbed_col.is_some() || self.column_resize.is_some() {
+            CursorIcon::EWResize
+        } else {
+            CursorIcon::Default
+        };
+        miniquad::window::set_mouse_cursor(cursor);
+
+        self.column_resize.is_some()
+    }
+
+    /// Lets the user drag the fill handle down or right to fill the dragged cells from the
+    /// selected one, as a series while Control is held. Returns true while the drag is going
+    /// on, so it is not treated as a click.
+    fn handle_fill_drag(
+        &mut self,
+        start: (f32, f32),
+        end: (f32, f32),
+        hovered: Option<Index>,
+    ) -> bool {
+        if is_mouse_button_pressed(MouseButton::Left) {
+            if let Some((pos, size)) = self.fill_handle_rect(start, end) {
+                if is_point_in_rect(mouse_position(), pos, (pos.0 + size.0, pos.1 + size.1)) {
+                    self.fill_source = self.edit.selected_cell();
+                }
+            }
+        }
+
+        let Some(source) = self.fill_source else {
+            return false;
+        };
+        if is_mouse_button_down(MouseButton::Left) {
+            return true;
+        }
+
+        self.fill_source = None;
+        if let Some(target_end) = hovered.and_then(|idx| fill_target(source, idx)) {
+            let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+            let mode = if ctrl {
+                FillMode::Series
+            } else {
+                FillMode::Copy
+            };
+            self.write_block(BlockWrite::Fill {
+                source,
+                target: (source, target_end),
+                mode,
+            });
+        }
+        true
+    }
+
+    fn draw_cells(&mut self, start: (f32, f32), end: (f32, f32)) {
+        let (start_x, start_y) = start;
+        let (end_x, end_y) = end;
+
+        let cells_start_x = start_x + ROW_LABEL_WIDTH;
+        let cells_start_y = start_y + COL_LABEL_HEIGHT;
+        let (visible_cols, visible_rows) =
+            visible_cells(start, end, &self.viewport, (&self.columns, &self.rows));
+
+        // Clicks on an open menu, value list or filter picker belong to it, not to the cells
+        // below
+        let over_menu = [
+            self.menu_rect(),
+            self.value_list_rect(start, end),
+            self.filter_picker_rect(),
+        ]
+            .into_iter()
+            .flatten()
+            .any(|(pos, size)| {
+                is_point_in_rect(mouse_position(), pos, (pos.0 + size.0, pos.1 + size.1))
+            });
+        let resizing = !over_menu && self.handle_column_resize(start, end);
+        // Clicks inside the in-cell editor belong to it, not to the cells below
+        let over_cell_editor = self
+            .cell_editor_rect(start, end)
+            .is_some_and(|(pos, size)| {
+                is_point_in_rect(mouse_position(), pos, (pos.0 + size.0, pos.1 + size.1))
+            });
+
+        // Handle if mouse clicked
+        let mut hovered: Option<Index> = None;
+        let (x, y) = mouse_position();
+        if !resizing && !over_menu && !over_cell_editor && is_point_in_rect((x, y), start, end) {
+            let relative = (x - cells_start_x, y - cells_start_y);
+            hovered = self
+                .viewport
+                .cell_at(relative, (&self.columns, &self.rows))
+                .filter(|idx| visible_cols.contains(&idx.x) && visible_rows.contains(&idx.y));
+        }
+
+        let filling = self.handle_fill_drag(start, end, hovered);
+
+        // Right clicking a label or a cell opens its menu, when it has one
+        if !resizing
+            && is_mouse_button_pressed(MouseButton::Right)
+            && is_point_in_rect((x, y), start, end)
+        {
+            let relative = (x - cells_start_x, y - cells_start_y);
+            let target = match self
+                .viewport
+                .label_at(relative, (&self.columns, &self.rows))
+            {
+                Some(label @ Label::Column(col)) if visible_cols.contains(&col) => {
+                    Some(MenuTarget::Label(label))
+                }
+                Some(label @ Label::Row(row)) if visible_rows.contains(&row) => {
+                    Some(MenuTarget::Label(label))
+                }
+                _ => hovered.map(|idx| {
+                    // A cell outside of the selection is selected first
+                    if !self
+                        .edit
+                        .selection
+                        .is_some_and(|range| range_contains(range, idx))
+                    {
+                        self.edit.select(idx, &mut self.spread_sheet);
+                    }
+                    MenuTarget::Cells
+                }),
+            };
+            self.context_menu = target
+                .map(|target| ContextMenu {
+                    target,
+                    pos: (x, y),
+                })
+                .filter(|menu| !menu.items().is_empty());
+        }
+
+        // Clicking the filter icon of a column opens its picker, or closes it when open.
+        // Clicking anywhere else closes the picker.
+        let data_columns = self
+            .spread_sheet
+            .used_range()
+            .map(|(top_left, bottom_right)| top_left.x..=bottom_right.x);
+        let clicked_icon = visible_cols.iter().copied().find(|&col| {
+            self.filter_icon_rect(col, start, data_columns.as_ref())
+                .is_some_and(|(pos, size)| {
+                    is_point_in_rect((x, y), pos, (pos.0 + size.0, pos.1 + size.1))
+                })
+        });
+        if !over_menu && is_mouse_button_pressed(MouseButton::Left) {
+            let closed = self.filter_picker.take().map(|picker| picker.column);
+            if let Some(col) = clicked_icon.filter(|&col| closed != Some(col)) {
+                self.open_filter_picker(col, (x, start_y + COL_LABEL_HEIGHT));
+            }
+        }
+
+        // Clicking a label selects its whole row or column
+        if !resizing
+            && !over_menu
+            && clicked_icon.is_none()
+            && is_mouse_button_pressed(MouseButton::Left)
+            && is_point_in_rect((x, y), start, end)
+        {
+            let relative = (x - cells_start_x, y - cells_start_y);
+            let selected = match self
+                .viewport
+                .label_at(relative, (&self.columns, &self.rows))
+            {
+                Some(Label::Column(col)) if visible_cols.contains(&col) => Some((
+                    Index { x: col, y: 0 },
+                    Index {
+                        x: col,
+                        y: self.grid.1 - 1,
+                    },
+                )),
+                Some(Label::Row(row)) if visible_rows.contains(&row) => Some((
+                    Index { x: 0, y: row },
+                    Index {
+                        x: self.grid.0 - 1,
+                        y: row,
+                    },
+                )),
+                _ => None,
+            };
+            if let Some((anchor, focus)) = selected {
+                self.edit.select(anchor, &mut self.spread_sheet);
+                self.edit.extend_selection(focus, &mut self.spread_sheet);
+            }
+        }
+
+        if let Some(idx) = hovered.filter(|_| !filling) {
+            if is_mouse_button_pressed(MouseButton::Left) {
+                if is_key_down(KeyCode::LeftControl) {
+                    // Links are followed unless a formula is being written
+                    let opened = match self.edit.mode {
+                        EditMode::Browsing => {
+                            follow_link(&self.spread_sheet, idx, self.opener.as_mut())
+                        }
+                        EditMode::Editing => None,
+                    };
+                    if let Some(Err(err)) = &opened {
+                        eprintln!("Cannot open the link: {err}");
+                    }
+                    if self.edit.mode == EditMode::Browsing {
+                        if opened.is_none() {
+                            // Another rectangle is added to the selection, dragging extends it
+                            self.edit.add_to_selection(idx, &mut self.spread_sheet);
+                            self.dragging_selection = true;
+                        }
+                    } else if self.edit.selected_cell().is_some()
+                        && self.edit.editor_content.starts_with('=')
+                    {
+                        let caret = self.text_editor.caret(&self.edit.editor_content);
+                        self.point_mode.insert(
+                            &mut self.edit.editor_content,
+                            caret,
+                            &index_to_name(idx),
+                        );
+                        self.move_caret_after_reference();
+                        self.reference_drag = Some(idx);
+                    }
+                } else if is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift) {
+                    self.edit.extend_selection(idx, &mut self.spread_sheet);
+                } else {
+                    let click = Click {
+                        index: idx,
+                        time: get_time(),
+                    };
+                    let (kind, last_click) = classify_click(self.last_click, click);
+                    self.last_click = last_click;
+
+                    self.edit.select(idx, &mut self.spread_sheet);
+                    if kind == ClickKind::Double {
+                        self.edit.begin_edit(&self.spread_sheet);
+                    } else {
+                        self.dragging_selection = true;
+                    }
+                }
+            } else if let Some(anchor) = self.reference_drag {
+                // Dragging on while editing a formula turns the reference into a range
+                self.point_mode
+                    .replace_last(&mut self.edit.editor_content, &range_to_string(anchor, idx));
+                self.move_caret_after_reference();
+            } else if self.dragging_selection
+                && is_mouse_button_down(MouseButton::Left)
+                && self.edit.selection_focus() != Some(idx)
+            {
+                self.edit.extend_selection(idx, &mut self.spread_sheet);
+            }
+        }
+        if !is_mouse_button_down(MouseButton::Left) {
+            self.dragging_selection = false;
+            self.reference_drag = None;
+        }
+
+        // Draw background
+        draw_rectangle(
+            start_x,
+            start_y,
+            end_x - start_x,
+            end_y - start_y,
+            self.theme.grid_background,
+        );
+
+        // Draw the column labels
+        let mut label_start_x = cells_start_x;
+        for &col in &visible_cols {
+            let width = self.columns.size(col);
+            self.draw_label(
+                col,
+                false, // Indicating column
+                (label_start_x, start_y),
+                (width, COL_LABEL_HEIGHT),
+            );
+            if let Some(((x, y), (icon_width, icon_height))) =
+                self.filter_icon_rect(col, start, data_columns.as_ref())
+            {
+                let color = match self.row_filter.get(col) {
+                    Some(_) => self.theme.filter,
+                    None => self.theme.label_text,
+                };
+                draw_triangle(
+                    vec2(x, y),
+                    vec2(x + icon_width, y),
+                    vec2(x + icon_width / 2.0, y + icon_height),
+                    color,
+                );
+            }
+            if col > 0 && self.columns.is_hidden(col - 1) {
+                self.draw_hidden_marker(
+                    (label_start_x, start_y),
+                    (label_start_x, start_y + COL_LABEL_HEIGHT),
+                    self.theme.label_text,
+                );
+            }
+            label_start_x += width;
+        }
+
+        // Draw the row labels
+        let mut label_start_y = cells_start_y;
+        for &row in &visible_rows {
+            let height = self.rows.size(row);
+            self.draw_label(
+                row,
+                true, // Indicating row
+                (start_x, label_start_y),
+                (ROW_LABEL_WIDTH, height),
+            );
+            // Rows hidden by hand are marked like columns, those the filter hides in its color
+            if row > 0 && self.rows.is_hidden(row - 1) {
+                let color = match self.spread_sheet.is_row_hidden(row - 1) {
+                    true => self.theme.label_text,
+                    false => self.theme.filter,
+                };
+                self.draw_hidden_marker(
+                    (start_x, label_start_y),
+                    (start_x + ROW_LABEL_WIDTH, label_start_y),
+                    color,
+                );
+            }
+            label_start_y += height;
+        }
+
+        // Draw the visible cells of the grid, reading the content of those having any
+        let occupied = occupied_cells(
+            &self.spread_sheet,
+            &line_ranges(&visible_cols, &visible_rows),
+        );
+        let mut cell_start_y = cells_start_y;
+        for &row in &visible_rows {
+            let height = self.rows.size(row);
+            let mut cell_start_x = cells_start_x;
+            for &col in &visible_cols {
+                let width = self.columns.size(col);
+                let index = Index { x: col, y: row };
+                self.draw_cell(
+                    index,
+                    (cell_start_x, cell_start_y),
+                    (width, height),
+                    occupied.contains(&index),
+                );
+                cell_start_x += width;
+            }
+            cell_start_y += height;
+        }
+
+        // The borders of the cells over the grid lines, each edge drawn once
+        let mut cell_start_y = cells_start_y;
+        for (i, &row) in visible_rows.iter().enumerate() {
+            let height = self.rows.size(row);
+            let mut cell_start_x = cells_start_x;
+            for (j, &col) in visible_cols.iter().enumerate() {
+                let width = self.columns.size(col);
+                let last = (j + 1 == visible_cols.len(), i + 1 == visible_rows.len());
+                let borders = self
+                    .spread_sheet
+                    .borders_to_draw(Index { x: col, y: row }, last);
+                draw_borders(borders, (cell_start_x, cell_start_y), (width, height));
+                cell_start_x += width;
+            }
+            cell_start_y += height;
+        }
+        // The selected cell stays outlined over the borders around it
+        if let Some(((x, y), (width, height))) = self
+            .edit
+            .selected_cell()
+            .and_then(|idx| self.cell_rect(idx, start, end))
+        {
+            draw_rectangle_lines(
+                x,
+                y,
+                width,
+                height,
+                SELECTED_CELL_BORDER_WIDTH,
+                self.theme.selected_cell_border,
+            );
+        }
+
+        // Mark where the frozen rows and columns end
+        if self.viewport.frozen_cols > 0 {
+            let x = cells_start_x + self.columns.offset_of(0, self.viewport.frozen_cols);
+            draw_line(x, start_y, x, end_y, 2.0, self.theme.frozen_divider);
+        }
+        if self.viewport.frozen_rows > 0 {
+            let y = cells_start_y + self.rows.offset_of(0, self.viewport.frozen_rows);
+            draw_line(start_x, y, end_x, y, 2.0, self.theme.frozen_divider);
+        }
+
+        self.draw_duplicates(start, end);
+        self.draw_precedents(start, end);
+        self.draw_dependants(start, end);
+
+        // Draw the fill handle and where a drag of it fills
+        if let Some(((x, y), (width, height))) = self.fill_handle_rect(start, end) {
+            draw_rectangle(x, y, width, height, self.theme.fill_handle);
+        }
+        if let Some(source) = self.fill_source {
+            let target_end = hovered.and_then(|idx| fill_target(source, idx));
+            let source_rect = self.cell_rect(source, start, end);
+            let end_rect = target_end.and_then(|idx| self.cell_rect(idx, start, end));
+            if let (Some((from, _)), Some((to, size))) = (source_rect, end_rect) {
+                draw_dashed_rectangle_lines(
+                    from,
+                    (to.0 + size.0 - from.0, to.1 + size.1 - from.1),
+                    2.0,
+                    self.theme.fill_preview,
+                );
+            }
+        }
+
+        // Draw dialog box for a cell hovered long enough
+        self.hover = match (hovered, self.hover) {
+            (Some(idx), Some((previous, since))) if previous == idx => Some((idx, since)),
+            (Some(idx), _) => Some((idx, get_time())),
+            (None, _) => None,
+        };
+        if let Some((idx, since)) = self.hover {
+            let (_, detail) = computed_to_text_with_detail(
+                self.spread_sheet.get_computed(idx),
+                self.spread_sheet.display_settings(),
+                NumberFormat::General,
+            );
+            let rect = self.cell_rect(idx, start, end);
+            if let (Some(detail), Some(((x, y), (width, _)))) = (
+                detail.filter(|_| get_time() - since >= ERROR_DETAIL_DELAY),
+                rect,
+            ) {
+                self.draw_dialog(&detail, (x + width, y));
+            }
+        }
+    }
+
+    /// Draws the cell at `index`, whose content is only looked up when it is `occupied`.
+    fn draw_cell(&self, index: Index, start: (f32, f32), dimensions: (f32, f32), occupied: bool) {
+        let (start_x, start_y) = start;
+        let (width, height) = dimensions;
+
+        let center_y = start_y + height / 2.0;
+
+        let (border_width, border_color) = if Some(index) == self.edit.selected_cell() {
+            (SELECTED_CELL_BORDER_WIDTH, self.theme.selected_cell_border)
+        } else {
+            (NORMAL_CELL_BORDER_WIDTH, self.theme.cell_border)
+        };
+
+        let style = self.spread_sheet.effective_format(index);
+        if let Some(fill) = style.fill {
+            draw_rectangle(start_x, start_y, width, height, fill_color(fill));
+        }
+        if self
+            .edit
+            .selection
+            .is_some_and(|range| range.0 != range.1 && range_contains(range, index))
+            || self.edit.added.contains(index)
+        {
+            draw_rectangle(start_x, start_y, width, height, self.theme.selection_fill);
+        }
+        draw_rectangle_lines(start_x, start_y, width, height, border_width, border_color);
+        if occupied && self.spread_sheet.is_locked(index) {
+            // A padlock in the top left corner, its shackle over its body
+            let color = self.theme.locked_cell;
+            draw_rectangle_lines(start_x + 4.0, start_y + 2.0, 5.0, 6.0, 1.5, color);
+            draw_rectangle(start_x + 3.0, start_y + 5.0, 7.0, 5.0, color);
+        }
+
+        let format = self.format(index);
+        let mut wrap = format.wrap;
+        let computed = occupied
+            .then(|| self.spread_sheet.get_computed(index))
+            .flatten();
+        let mut alignment = format
+            .alignment
+            .unwrap_or_else(|| auto_alignment(computed.as_ref()));
+        let mut text_color = self.theme.cell_text;
+        let text = if Some(index) == self.edit.selected_cell() {
+            // Preview the result of a parsable formula without committing it
+            if let Some(FormulaStatus::Valid(result)) = &self.formula_status {
+                text_color = self.theme.preview_text;
+                alignment = format
+                    .alignment
+                    .unwrap_or_else(|| auto_alignment(Some(result)));
+                computed_to_text(Some(result.clone()), self.spread_sheet.display_settings())
+            } else {
+                if self.edit.mode == EditMode::Editing {
+                    // Raw content being typed reads from the left on one line like the editor
+                    alignment = Alignment::Left;
+                    wrap = false;
+                }
+                self.edit.editor_content.clone()
+            }
+        } else {
+            if let Some(Ok(value)) = &computed {
+                if value.link_url().is_some() {
+                    text_color = self.theme.link;
+                }
+            }
+            if let Some(Ok(Value::Sparkline(points))) = &computed {
+                // Drawn as a chart instead of its text
+                let rect = Rect::new(
+                    start_x + SPARKLINE_PADDING,
+                    start_y + SPARKLINE_PADDING,
+                    width - 2.0 * SPARKLINE_PADDING,
+                    height - 2.0 * SPARKLINE_PADDING,
+                );
+                let positions = sparkline_positions(points, rect);
+                for pair in positions.windows(2) {
+                    let (from, to) = (pair[0], pair[1]);
+                    draw_line(from.x, from.y, to.x, to.y, 1.5, self.theme.sparkline);
+                }
+                if let [only] = positions.as_slice() {
+                    draw_circle(only.x, only.y, 1.5, self.theme.sparkline);
+                }
+                return;
+            }
+            let (text, detail) = computed_to_text_with_detail(
+                computed,
+                self.spread_sheet.display_settings(),
+                style.number_format,
+            );
+            let validation = occupied
+                .then(|| self.spread_sheet.validation_state(index))
+                .flatten();
+            if let Some(ValidationState::Invalid { .. }) = validation {
+                // A warning in the bottom left corner, clear of the error marker
+                let triangle_len = 10.;
+                draw_triangle(
+                    vec2(start_x, start_y + height),
+                    vec2(start_x + triangle_len, start_y + height),
+                    vec2(start_x, start_y + height - triangle_len),
+                    self.theme.invalid_value,
+                );
+            }
+            if occupied && self.spread_sheet.needs_attention(index) {
+                // An amber corner at the bottom right, the others being taken
+                let triangle_len = 10.;
+                draw_triangle(
+                    vec2(start_x + width, start_y + height),
+                    vec2(start_x + width - triangle_len, start_y + height),
+                    vec2(start_x + width, start_y + height - triangle_len),
+                    self.theme.needs_attention,
+                );
+            }
+            if detail.is_some() {
+                text_color = self.theme.error_text;
+                let triangle_len = 10.;
+                draw_triangle(
+                    vec2(start_x + width, start_y),
+                    vec2(start_x + width - triangle_len, start_y),
+                    vec2(start_x + width, start_y + triangle_len),
+                    self.theme.error_text,
+                );
+            }
+
+            text
+        };
+
+        let lines = if wrap {
+            self.wrap_cell_text(&text, width)
+        } else if text.is_empty() {
+            Vec::new()
+        } else {
+            vec![text]
+        };
+        // The lines are centered as a block, a single one on the middle of the cell
+        let lines_top = center_y - lines.len() as f32 * WRAPPED_LINE_HEIGHT / 2.0;
+        let font = self.cell_font(style);
+        for (i, line) in lines.iter().enumerate() {
+            let text_dimensions = self.measure_cached(line, FontFace::of(style), CELL_FONT_SIZE);
+
+            let line_center_y = lines_top + (i as f32 + 0.5) * WRAPPED_LINE_HEIGHT;
+            let text_x = aligned_text_x(alignment, start_x, width, text_dimensions.width);
+            let text_y = line_center_y + text_dimensions.height / 2.0; // Adjust y for baseline alignment
+
+            draw_text_ex(
+                line,
+                text_x,
+                text_y,
+                TextParams {
+                    font: Some(font),
+                    font_size: CELL_FONT_SIZE,
+                    font_scale: 1.0,
+                    font_scale_aspect: 1.0,
+                    rotation: 0.0,
+                    color: text_color,
+                },
+            );
+        }
+    }
+
+    /// Draws the thin double line marking where columns or rows are hidden, along the edge of
+    /// a label from `from` to `to`.
+    fn draw_hidden_marker(&self, from: (f32, f32), to: (f32, f32), color: Color) {
+        let half_gap = HIDDEN_MARKER_GAP / 2.0;
+        let (dx, dy) = match from.1 == to.1 {
+            true => (0.0, half_gap),
+            false => (half_gap, 0.0),
+        };
+        for side in [-1.0, 1.0] {
+            let (dx, dy) = (dx * side, dy * side);
+            draw_line(from.0 + dx, from.1 + dy, to.0 + dx, to.1 + dy, 1.0, color);
+        }
+    }
+
+    fn draw_label(&self, idx: usize, is_row: bool, start: (f32, f32), dimensions: (f32, f32)) {
+        let (start_x, start_y) = start;
+        let (width, height) = dimensions;
+        let center_x = start_x + width / 2.0;
+        let center_y = start_y + height / 2.0;
+
+        let is_selected_label =
+            self.edit
+                .selected_ranges()
+                .ranges()
+                .iter()
+                .any(|&(top_left, bottom_right)| {
+                    if is_row {
+                        (top_left.y..=bottom_right.y).contains(&idx)
+                    } else {
+                        (top_left.x..=bottom_right.x).contains(&idx)
+                    }
+                });
+
+        if is_selected_label {
+            // Draw background
+            draw_rectangle(
+                start_x,
+                start_y,
+                width,
+                height,
+                self.theme.selected_label_background,
+            );
+        }
+
+        draw_rectangle_lines(
+            start_x,
+            start_y,
+            width,
+            height,
+            1.0,
+            self.theme.label_border,
+        );
+        let text = if is_row {
+            (idx + 1).to_string()
+        } else {
+            col_to_letters(idx)
+        };
+        let text_dimensions = self.measure_cached(&text, FontFace::Regular, LABEL_FONT_SIZE);
+
+        let text_x = center_x - text_dimensions.width / 2.0;
+        let text_y = center_y + text_dimensions.height / 2.0; // Adjust y for baseline alignment
+
+        draw_text_ex(
+            &text,
+            text_x,
+            text_y,
+            TextParams {
+                font: Some(if is_selected_label {
+                    &self.bold_font
+                } else {
+                    &self.regular_font
+                }),
+                font_size: LABEL_FONT_SIZE,
+                font_scale: 1.0,
+                font_scale_aspect: 1.0,
+                rotation: 0.0,
+                color: self.theme.label_text,
+            },
+        );
+    }
+
+    /// Copies, cuts and pastes the raw content of the selected cell while browsing. Copying a
+    /// selection of several cells puts their values on the clipboard as tab separated text,
+    /// or their raw content with Shift held. Text with tabs or several lines is pasted as a
+    /// block of cells starting at the selected one, see `paste`. Within an edit
+    /// these work on the text selected in the editor instead.
+    fn handle_clipboard(&mut self) {
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        if !ctrl || self.name_box.focused {
+            return;
+        }
+        if self.edit.mode == EditMode::Editing {
+            self.handle_text_clipboard();
+            return;
+        }
+        let Some(selected) = self.edit.selected_cell() else {
+            return;
+        };
+        let selection = self
+            .edit
+            .selection
+            .filter(|(anchor, focus)| anchor != focus);
+
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        let mode = if shift {
+            CopyMode::Formulas
+        } else {
+            CopyMode::Values
+        };
+
+        if is_key_pressed(KeyCode::C) && !self.edit.added.is_empty() {
+            // Like other spreadsheets, only rectangles lining up are copied together
+            match self.edit.selected_ranges().copy_grid() {
+                Ok(grid) => {
+                    let text = clipboard::cells_to_tsv(&self.spread_sheet, &grid, mode);
+                    miniquad::window::clipboard_set(&text);
+                    self.copied = None;
+                }
+                Err(err) => self.edit.rejection = Some(err),
+            }
+        } else if let (true, Some(selection)) = (is_key_pressed(KeyCode::C), selection) {
+            let text = clipboard::selection_to_tsv(&self.spread_sheet, selection, mode);
+            miniquad::window::clipboard_set(&text);
+            self.copied = None;
+        } else if is_key_pressed(KeyCode::C) || is_key_pressed(KeyCode::X) {
+            let raw = self.edit.editor_content.clone();
+            miniquad::window::clipboard_set(&raw);
+            self.copied = Some(CopiedCell {
+                index: selected,
+                raw,
+            });
+
+            if is_key_pressed(KeyCode::X) {
+                self.edit.write(String::new(), &mut self.spread_sheet);
+            }
+        } else if is_key_pressed(KeyCode::V) {
+            self.paste(false);
+        }
+    }
+
+    /// Pastes the clipboard at the selected cell. Text with tabs or several lines is pasted as
+    /// a block of cells, which is then selected, its fields separated by tabs, commas or
+    /// semicolons, whichever `clipboard::sniff_delimiter` finds. With `values_only`, a cell copied from the
+    /// sheet gives its value and pasted formulas are replaced by theirs. A block over cells
+    /// holding data waits for that to be confirmed, see `write_block`.
+    fn paste(&mut self, values_only: bool) {
+        let Some(selected) = self.edit.selected_cell() else {
+            return;
+        };
+        let Some(text) = miniquad::window::clipboard_get() else {
+            return;
+        };
+        if !clipboard::is_block(&text) {
+            let content = match &self.copied {
+                Some(copied) if values_only && copied.raw == text => self
+                    .spread_sheet
+                    .value_as_raw(copied.index)
+                    .unwrap_or_default(),
+                _ => pasted_content(&text, self.copied.as_ref(), selected),
+            };
+            // A formula that does not parse is pasted as written, see `relocate_raw_tracked`
+            let kept_broken = self.copied.as_ref().is_some_and(|copied| {
+                !values_only
+                    && copied.raw == text
+                    && SpreadSheet::relocate_raw_tracked(&text, copied.index, selected).1
+            });
+            let edit = &mut self.edit;
+            self.spread_sheet.batch(|sheet| {
+                edit.write(content, sheet);
+                if kept_broken && edit.rejection.is_none() {
+                    sheet.mark_needs_attention(selected);
+                }
+                if values_only && edit.rejection.is_none() {
+                    let result = sheet.convert_to_values((selected, selected));
+                    edit.report(result);
+                }
+            });
+            self.edit.reload(&self.spread_sheet);
+            return;
+        }
+
+        self.write_block(BlockWrite::Paste {
+            block: clipboard::parse_pasted(&text),
+            target: selected,
+            values_only,
+        });
+    }
+
+    /// Writes `write` right away, unless it replaces cells holding data and those writes are
+    /// asked about, see `draw_overwrite_prompt`.
+    fn write_block(&mut self, write: BlockWrite) {
+        if self.confirm_overwrite {
+            if let Some(prompt) = OverwritePrompt::for_write(write.clone(), &self.spread_sheet) {
+                self.overwrite_prompt = Some(prompt);
+                return;
+            }
+        }
+
+        let (top_left, end) = write.rect();
+        match write {
+            BlockWrite::Paste {
+                block,
+                target,
+                values_only,
+            } => {
+                let result = self.spread_sheet.batch(|sheet| {
+                    sheet.set_range(target, &block)?;
+                    if values_only {
+                        sheet.convert_to_values((target, end))?;
+                    }
+                    Ok(())
+                });
+                self.edit.report(result);
+                self.edit.reload(&self.spread_sheet);
+                self.edit.select(top_left, &mut self.spread_sheet);
+            }
+            BlockWrite::Fill {
+                source,
+                target,
+                mode,
+            } => {
+                let result = self.spread_sheet.fill(source, target, mode);
+                self.edit.report(result);
+            }
+        }
+        self.edit.extend_selection(end, &mut self.spread_sheet);
+    }
+
+    fn handle_text_clipboard(&mut self) {
+        let content = &mut self.edit.editor_content;
+        if is_key_pressed(KeyCode::C) || is_key_pressed(KeyCode::X) {
+            let selected = self.text_editor.selected_text(content);
+            if !selected.is_empty() {
+                miniquad::window::clipboard_set(selected);
+                if is_key_pressed(KeyCode::X) {
+                    self.text_editor.delete_selection(content);
+                }
+            }
+        } else if is_key_pressed(KeyCode::V) {
+            if let Some(text) = miniquad::window::clipboard_get() {
+                // The editor holds a single line
+                let line = text.lines().next().unwrap_or_default();
+                self.text_editor.insert(content, line);
+            }
+        } else if is_key_pressed(KeyCode::A) {
+            self.text_editor.select_all(content);
+        }
+    }
+
+    /// Puts the caret right after the reference point mode inserted last.
+    fn move_caret_after_reference(&mut self) {
+        if let Some(end) = self.point_mode.last_end() {
+            self.text_editor
+                .set_caret(&self.edit.editor_content, end, false);
+        }
+    }
+
+    /// Routes the keys whose meaning depends on the edit mode. While browsing, Delete and
+    /// Backspace clear the selected cell instead of reaching the editor text.
+    fn handle_edit_keys(&mut self) {
+        // Shortcuts held with Control are not typing
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        let mut typed = String::new();
+        while let Some(c) = get_char_pressed() {
+            if !c.is_control() && !ctrl {
+                typed.push(c);
+            }
+        }
+
+        // The name box takes the keyboard until it is left
+        if self.name_box.focused {
+            return;
+        }
+        if self.key_consumed {
+            self.edit_text(&typed);
+            return;
+        }
+
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        let key = if is_key_pressed(KeyCode::Escape) {
+            EditKey::Escape
+        } else if ctrl && (is_key_pressed(KeyCode::Y) || shift && is_key_pressed(KeyCode::Z)) {
+            EditKey::Redo
+        } else if ctrl && is_key_pressed(KeyCode::Z) {
+            EditKey::Undo
+        } else if is_key_pressed(KeyCode::Delete) {
+            EditKey::Delete
+        } else if is_key_pressed(KeyCode::Backspace) {
+            EditKey::Backspace
+        } else if !typed.is_empty() {
+            EditKey::Printable
+        } else {
+            self.edit_text("");
+            return;
+        };
+
+        self.edit.handle_key(key, &mut self.spread_sheet);
+        self.edit_text(&typed);
+    }
+
+    /// Applies typing and the caret keys to the editor text while editing. Held keys repeat.
+    fn edit_text(&mut self, typed: &str) {
+        const EDITOR_KEYS: [KeyCode; 6] = [
+            KeyCode::Backspace,
+            KeyCode::Delete,
+            KeyCode::Left,
+            KeyCode::Right,
+            KeyCode::Home,
+            KeyCode::End,
+        ];
+        let pressed = EDITOR_KEYS.into_iter().find(|&key| is_key_pressed(key));
+        let key = self.key_repeat.update(pressed, is_key_down, get_time());
+        if self.edit.mode != EditMode::Editing {
+            return;
+        }
+
+        // Arrows only move the caret within formulas, see `handle_navigation`
+        let editing_formula = self.is_editing_formula();
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        let content = &mut self.edit.editor_content;
+        match key {
+            Some(KeyCode::Backspace) => self.text_editor.backspace(content),
+            Some(KeyCode::Delete) => self.text_editor.delete(content),
+            // Control jumps over whole words
+            Some(KeyCode::Left) if editing_formula && ctrl => {
+                self.text_editor.move_word_left(content, shift)
+            }
+            Some(KeyCode::Right) if editing_formula && ctrl => {
+                self.text_editor.move_word_right(content, shift)
+            }
+            Some(KeyCode::Left) if editing_formula => self.text_editor.move_left(content, shift),
+            Some(KeyCode::Right) if editing_formula => self.text_editor.move_right(content, shift),
+            Some(KeyCode::Home) => self.text_editor.move_home(content, shift),
+            Some(KeyCode::End) => self.text_editor.move_end(content, shift),
+            _ => (),
+        }
+
+        if !typed.is_empty() {
+            self.text_editor.insert(content, typed);
+        }
+    }
+
+    /// Moves the selection with the arrow keys, committing the current edit like a click would.
+    ///
+    /// While editing, the editor has the focus, so arrows would also move its caret. To keep both
+    /// usable, arrows only navigate the grid unless a formula is being edited: plain values are
+    /// committed and left behind, formulas keep the arrows for caret movement.
+    fn handle_navigation(&mut self) {
+        let Some(direction) = pressed_direction() else {
+            return;
+        };
+
+        if self.name_box.focused || self.key_consumed {
+            return;
+        }
+
+        let Some(selected) = self.edit.selected_cell() else {
+            self.edit
+                .select(Index { x: 0, y: 0 }, &mut self.spread_sheet);
+            return;
+        };
+
+        if self.is_editing_formula() {
+            return;
+        }
+
+        // Shift moves the focus of the selection instead of the selected cell
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        if shift {
+            let focus = self.edit.selection_focus().unwrap_or(selected);
+            let focus = self.step(focus, direction);
+            self.edit.extend_selection(focus, &mut self.spread_sheet);
+        } else {
+            let selected = self.step(selected, direction);
+            self.edit.select(selected, &mut self.spread_sheet);
+        }
+    }
+
+    /// Returns the cell a step in `direction` from `index`, passing over hidden rows and
+    /// columns.
+    fn step(&self, index: Index, direction: Direction) -> Index {
+        let (columns, rows) = match direction {
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+        };
+        Index {
+            x: self.columns.advance(index.x, columns).min(self.grid.0 - 1),
+            y: self.rows.advance(index.y, rows).min(self.grid.1 - 1),
+        }
+    }
+
+    /// F4 turns the reference the caret is in or right after into the next of its absolute
+    /// and relative forms, `A1`, `$A$1`, `A$1`, `$A1`, leaving the caret after it.
+    fn handle_anchor_key(&mut self) {
+        if !is_key_pressed(KeyCode::F4) || !self.is_editing_formula() {
+            return;
+        }
+        let content = &mut self.edit.editor_content;
+        let Some(range) = reference_at(content, self.text_editor.caret(content)) else {
+            return;
+        };
+        let cycled = cycle_anchors(&content[range.clone()]);
+        let end = range.start + cycled.len();
+        content.replace_range(range, &cycled);
+        self.text_editor.set_caret(content, end, false);
+        self.key_consumed = true;
+    }
+
+    fn is_editing_formula(&self) -> bool {
+        self.edit.mode == EditMode::Editing
+            && self.edit.editor_content.trim_start().starts_with('=')
+    }
+
+    fn draw_dialog(&self, detail: &str, pos: (f32, f32)) {
+        const DIALOG_WIDTH: f32 = 200.0;
+        const DIALOG_HEIGHT: f32 = 80.0;
+        const DIALOG_FONT_SIZE: u16 = 14;
+
+        let (dialog_x, dialog_y) = pos;
+
+        // Draw dialog background
+        draw_rectangle(
+            dialog_x,
+            dialog_y,
+            DIALOG_WIDTH,
+            DIALOG_HEIGHT,
+            self.theme.grid_background,
+        );
+        draw_rectangle_lines(
+            dialog_x,
+            dialog_y,
+            DIALOG_WIDTH,
+            DIALOG_HEIGHT,
+            4.0,
+            self.theme.error_text,
+        );
+
+        // Prepare dialog text
+        let dialog_text = format!("Error: {detail}");
+
+        let lines = split_into_lines(
+            &dialog_text,
+            &self.regular_font,
+            DIALOG_FONT_SIZE,
+            DIALOG_WIDTH - 10.0,
+        );
+
+        // Calculate vertical starting position for centering the text block
+        let total_text_height = lines.len() as f32 * (DIALOG_FONT_SIZE as f32 + 4.0); // 4.0 for line spacing
+        let mut text_y = dialog_y + (DIALOG_HEIGHT - total_text_height) / 2.0;
+
+        // Draw each line of text
+        for line in lines {
+            let text_dimensions = measure_text(&line, Some(&self.bold_font), DIALOG_FONT_SIZE, 1.0);
+            let text_x = dialog_x + (DIALOG_WIDTH - text_dimensions.width) / 2.0;
+
+            draw_text_ex(
+                &line,
+                text_x,
+                text_y,
+                TextParams {
+                    font: Some(&self.bold_font),
+                    font_size: DIALOG_FONT_SIZE,
+                    font_scale: 1.0,
+                    font_scale_aspect: 1.0,
+                    rotation: 0.0,
+                    color: self.theme.cell_text,
+                },
+            );
+
+            text_y += DIALOG_FONT_SIZE as f32 + 4.0; // Move to next line
+        }
+    }
+}
+
+/// Builds the skins of the name box in `theme`, as it normally is and while it flashes an
+/// invalid name.
+fn name_box_skins(theme: &Theme, font: &Font) -> (Skin, Skin) {
+    let editbox_style = |background: Color| {
+        root_ui()
+            .style_builder()
+            .color(background)
+            .color_hovered(background)
+            .color_clicked(background)
+            .color_selected(theme.text_selection)
+            .text_color(theme.cell_text)
+            .with_font(font)
+            .unwrap()
+            .font_size(EDITOR_FONT_SIZE)
+            .build()
+    };
+
+    let editor_skin = Skin {
+        editbox_style: editbox_style(theme.grid_background),
+        ..root_ui().default_skin()
+    };
+    let error_skin = Skin {
+        editbox_style: editbox_style(theme.name_box_error),
+        ..editor_skin.clone()
+    };
+    (editor_skin, error_skin)
+}
+
+/// Returns the `(position, size)` of the name box and of the editor bar right of it.
+fn editor_rects() -> ((Vec2, Vec2), (Vec2, Vec2)) {
+    let window_width = screen_width() - EDITOR_STATUS_WIDTH;
+
+    let name_box_pos = vec2(ROW_LABEL_WIDTH, EDITOR_TOP_MARGIN + EDITOR_PADDING);
+    let name_box_size = vec2(NAME_BOX_WIDTH, EDITOR_HEIGHT);
+    let bar_pos = vec2(
+        name_box_pos.x + NAME_BOX_WIDTH + NAME_BOX_MARGIN,
+        name_box_pos.y,
+    );
+    let bar_size = vec2(window_width - bar_pos.x - ROW_LABEL_WIDTH, EDITOR_HEIGHT);
+    ((name_box_pos, name_box_size), (bar_pos, bar_size))
+}
+
+/// Returns what pasting `text` into `target` writes. Text copied from a cell of this sheet
+/// has its references moved along, unless it is a formula that does not parse. Anything else
+/// is pasted as is.
+fn pasted_content(text: &str, copied: Option<&CopiedCell>, target: Index) -> String {
+    match copied {
+        Some(copied) if copied.raw == text => SpreadSheet::relocate_raw(text, copied.index, target),
+        _ => text.to_string(),
+    }
+}
+
+/// Returns the cell named by what was typed into the name box, if it is inside the grid of
+/// `(columns, rows)`. Lowercase names and surrounding spaces are accepted.
+fn parse_name_box(content: &str, grid: (usize, usize)) -> Option<Index> {
+    name_to_index(&content.trim().to_ascii_uppercase())
+        .ok()
+        .filter(|idx| idx.x < grid.0 && idx.y < grid.1)
+}
+
+/// Returns the top left and bottom right corners of the range typed into the name box, like
+/// "A1:D20", or the same cell twice for a single cell name.
+fn parse_name_box_range(content: &str, grid: (usize, usize)) -> Option<(Index, Index)> {
+    match content.split_once(':') {
+        Some((from, to)) => Some(normalize_range(
+            parse_name_box(from, grid)?,
+            parse_name_box(to, grid)?,
+        )),
+        None => parse_name_box(content, grid).map(|idx| (idx, idx)),
+    }
+}
+
+/// Loads the font at `path`, or the embedded one when there is none or it cannot be loaded.
+async fn load_font(path: Option<&Path>, embedded: &[u8]) -> Font {
+    if let Some(path) = path {
+        match load_ttf_font(&path.to_string_lossy()).await {
+            Ok(font) => return font,
+            Err(err) => eprintln!("Cannot load the font {}: {err}", path.display()),
+        }
+    }
+    load_ttf_font_from_bytes(embedded).expect("The embedded fonts are valid")
+}
+
+/// Returns the `(width, height)` available to cells in the grid between `start` and `end`.
+fn cells_area(start: (f32, f32), end: (f32, f32)) -> (f32, f32) {
+    (
+        end.0 - start.0 - ROW_LABEL_WIDTH,
+        end.1 - start.1 - COL_LABEL_HEIGHT,
+    )
+}
+
+/// Draws the outline of a rectangle with dashed lines.
+fn draw_dashed_rectangle_lines(pos: (f32, f32), size: (f32, f32), thickness: f32, color: Color) {
+    const DASH_LENGTH: f32 = 6.0;
+
+    let (x, y) = pos;
+    let (width, height) = size;
+    let corners = [
+        (x, y),
+        (x + width, y),
+        (x + width, y + height),
+        (x, y + height),
+        (x, y),
+    ];
+
+    for side in corners.windows(2) {
+        let (from, to) = (vec2(side[0].0, side[0].1), vec2(side[1].0, side[1].1));
+        let length = from.distance(to);
+        let direction = (to - from).normalize_or_zero();
+
+        let mut dash_start = 0.0;
+        while dash_start < length {
+            let dash_end = (dash_start + DASH_LENGTH).min(length);
+            let a = from + direction * dash_start;
+            let b = from + direction * dash_end;
+            draw_line(a.x, a.y, b.x, b.y, thickness, color);
+            dash_start += DASH_LENGTH * 2.0;
+        }
+    }
+}
+
+/// Places an editor of at least `min_width` over the cell at `cell_start`. It grows to the
+/// right, and moves left or up when it would leave the `(start, end)` area.
+fn place_cell_editor(
+    cell_start: (f32, f32),
+    cell_size: (f32, f32),
+    min_width: f32,
+    area: ((f32, f32), (f32, f32)),
+) -> ((f32, f32), (f32, f32)) {
+    let (start, end) = area;
+    let width = cell_size.0.max(min_width).min(end.0 - start.0);
+    let height = cell_size.1.min(end.1 - start.1);
+
+    let x = cell_start.0.min(end.0 - width).max(start.0);
+    let y = cell_start.1.min(end.1 - height).max(start.1);
+
+    ((x, y), (width, height))
+}
+
+/// Returns the `(columns, rows)` shown in the grid between `start` and `end`, in the order
+/// they are shown. The last column and row may be cut off at the edge of the grid.
+fn visible_cells(
+    start: (f32, f32),
+    end: (f32, f32),
+    viewport: &Viewport,
+    layout: (&AxisLayout, &AxisLayout),
+) -> (Vec<usize>, Vec<usize>) {
+    let (width, height) = cells_area(start, end);
+    let (columns, rows) = layout;
+
+    (
+        viewport.visible_columns(columns, width),
+        viewport.visible_rows(rows, height),
+    )
+}
+
+/// Returns the rectangles of cells made by the runs of `columns` and `rows` without gaps.
+fn line_ranges(columns: &[usize], rows: &[usize]) -> Vec<(Index, Index)> {
+    let col_runs = consecutive_runs(columns);
+
+    consecutive_runs(rows)
+        .into_iter()
+        .flat_map(|(top, bottom)| {
+            col_runs.iter().map(move |&(left, right)| {
+                (
+                    Index { x: left, y: top },
+                    Index {
+                        x: right,
+                        y: bottom,
+                    },
+                )
+            })
+        })
+        .collect()
+}
+
+/// Returns the cells of `sheet` with content or a style in the rectangles of `ranges`, the
+/// only ones the grid reads anything of beyond their formats.
+fn occupied_cells(sheet: &SpreadSheet, ranges: &[(Index, Index)]) -> HashSet<Index> {
+    ranges
+        .iter()
+        .flat_map(|&(from, to)| sheet.cells_in_rect(from, to))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Splits ascending `lines` into the first and last line of each run without gaps.
+fn consecutive_runs(lines: &[usize]) -> Vec<(usize, usize)> {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for &line in lines {
+        match runs.last_mut() {
+            Some((_, last)) if *last + 1 == line => *last = line,
+            _ => runs.push((line, line)),
+        }
+    }
+    runs
+}
+
+/// Turns a mouse wheel delta into a single step, whatever the platform's notch size is.
+fn wheel_steps(delta: f32) -> isize {
+    if delta > 0.0 {
+        1
+    } else if delta < 0.0 {
+        -1
+    } else {
+        0
+    }
+}
+
+fn pressed_direction() -> Option<Direction> {
+    if is_key_pressed(KeyCode::Up) {
+        Some(Direction::Up)
+    } else if is_key_pressed(KeyCode::Down) {
+        Some(Direction::Down)
+    } else if is_key_pressed(KeyCode::Left) {
+        Some(Direction::Left)
+    } else if is_key_pressed(KeyCode::Right) {
+        Some(Direction::Right)
+    } else {
+        None
+    }
+}
 
 fn is_point_in_rect<T: std::cmp::PartialOrd>(
     point: (T, T),
@@ -462,59 +3959,81 @@ fn is_point_in_rect<T: std::cmp::PartialOrd>(
         && point.1 <= rect_end.1
 }
 
-/*
-   Format a float into scientific notation such as: 42.0 -> 4.200e+01
-   width controls the amount of left padded spaces
-   precision is the amount of decimals
-   exp_pad controls the amount of left padded 0s
-*/
-fn fmt_f64(num: f64, width: usize, precision: usize, exp_pad: usize) -> String {
-    if !num.is_finite(){
-        return num.to_string();
-    }
-    let mut num = format!("{:.precision$e}", num, precision = precision);
-    // Safe to `unwrap` as `num` is guaranteed to contain `'e'`
-    let exp = num.split_off(num.find('e').expect("safe"));
+/// Returns the color of a cell fill stored by the spreadsheet.
+fn fill_color([r, g, b, a]: [u8; 4]) -> Color {
+    Color::from_rgba(r, g, b, a)
+}
 
-    let (sign, exp) = if exp.starts_with("e-") {
-        ('-', &exp[2..])
-    } else {
-        ('+', &exp[1..])
-    };
-    num.push_str(&format!("e{}{:0>pad$}", sign, exp, pad = exp_pad));
+/// Draws `borders` along the edges of the cell at `start` of size `dimensions`.
+fn draw_borders(borders: Borders, start: (f32, f32), dimensions: (f32, f32)) {
+    let (x, y) = start;
+    let (width, height) = dimensions;
+    for edge in Edge::ALL {
+        let Some(border) = borders.get(edge) else {
+            continue;
+        };
+        let (from, to) = match edge {
+            Edge::Top => ((x, y), (x + width, y)),
+            Edge::Bottom => ((x, y + height), (x + width, y + height)),
+            Edge::Left => ((x, y), (x, y + height)),
+            Edge::Right => ((x + width, y), (x + width, y + height)),
+        };
+        draw_line(
+            from.0,
+            from.1,
+            to.0,
+            to.1,
+            f32::from(border.width),
+            fill_color(border.color),
+        );
+    }
+}
 
-    format!("{:>width$}", num, width = width)
+fn highlight_color(theme: &Theme, highlight: Highlight) -> Color {
+    match highlight {
+        Highlight::Plain => theme.cell_text,
+        Highlight::Reference(slot) => theme.reference_color(slot),
+        Highlight::Number => theme.number,
+        Highlight::Text => theme.string,
+        Highlight::Function => theme.function,
+    }
 }
 
-fn computed_to_text(computed: Option<Result<Value, ComputeError>>) -> String {
+/// Returns the text shown in a cell for `computed` and, for an error, the full detail of it
+/// that does not fit the cell.
+fn computed_to_text_with_detail(
+    computed: Option<Result<Value, ComputeError>>,
+    display: &DisplaySettings,
+    format: NumberFormat,
+) -> (String, Option<String>) {
     match computed {
-        Some(value) => match value {
-            Ok(inner) => match inner {
-                Value::Text(s) => s,
-                Value::Number(num) => {
-                    if num >= 1E15 {
-                        fmt_f64(num, 10, 3, 2)
-                    } else {
-                        num.to_string()
-                    }
-                }
-                Value::Bool(b) => b.to_string(),
-            },
-            Err(err) => err.to_string(),
-        },
-        None => String::new(),
+        Some(Err(err)) => (err.to_string(), Some(err.message(Language::English))),
+        computed => (computed_to_text_as(computed, display, format), None),
     }
 }
 
-fn err_to_info(err: ComputeError) -> String {
-    match err {
-        ComputeError::ParseError(reason) => reason,
-        ComputeError::TypeError(message) => message,
-        ComputeError::UnfindableReference(message) => message,
-        ComputeError::Cycle => "Detected cyclic computation".to_string(),
-        ComputeError::UnknownFunction(f) => format!("Unknown function '{f}'"),
-        ComputeError::InvalidArgument(message) => message,
-    }
+fn summary_to_text(summary: &RangeSummary, display: &DisplaySettings) -> String {
+    let number = |num: f64| display.format_number(num);
+    let average = summary.average.map_or("-".to_string(), number);
+
+    format!(
+        "Sum: {}  Avg: {}  Count: {}",
+        number(summary.sum),
+        average,
+        summary.count
+    )
+}
+
+/// Writes the counts of `stats` the status bar shows when there is nothing to tell about the
+/// selection.
+fn stats_to_text(stats: &SheetStats, display: &DisplaySettings) -> String {
+    let count = |count: usize| display.format_number(count as f64);
+    format!(
+        "{} cells / {} formulas / {} errors",
+        count(stats.cells),
+        count(stats.formula_cells),
+        count(stats.error_cells)
+    )
 }
 
 fn split_into_lines(text: &str, font: &Font, font_size: u16, max_width: f32) -> Vec<String> {
@@ -546,3 +4065,326 @@ fn split_into_lines(text: &str, font: &Font, font_size: u16, max_width: f32) ->
 
     lines
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRID: (usize, usize) = (GRID_COLS, GRID_ROWS);
+
+    /// Returns the index of the cell named `name`, for the tests of the GUI to write cells by
+    /// name.
+    pub(super) fn cell(name: &str) -> Index {
+        name_to_index(name).unwrap()
+    }
+
+    #[test]
+    fn test_column_labels_beyond_z() {
+        assert_eq!(col_to_letters(0), "A");
+        assert_eq!(col_to_letters(25), "Z");
+        assert_eq!(col_to_letters(26), "AA");
+        assert_eq!(col_to_letters(27), "AB");
+        assert_eq!(col_to_letters(701), "ZZ");
+        assert_eq!(col_to_letters(GRID_COLS - 1), "ZZZ");
+    }
+
+    #[test]
+    fn test_parse_name_box() {
+        assert_eq!(parse_name_box("C7", GRID), Some(Index { x: 2, y: 6 }));
+        assert_eq!(parse_name_box(" ab12 ", GRID), Some(Index { x: 27, y: 11 }));
+        assert_eq!(
+            parse_name_box("ZZZ1000000", GRID),
+            Some(Index {
+                x: 18_277,
+                y: 999_999
+            })
+        );
+
+        assert_eq!(parse_name_box("", GRID), None);
+        assert_eq!(parse_name_box("C0", GRID), None);
+        assert_eq!(parse_name_box("=C7", GRID), None);
+        // Outside of the grid
+        assert_eq!(parse_name_box("AAAA1", GRID), None);
+        assert_eq!(parse_name_box("A1000001", GRID), None);
+        assert_eq!(parse_name_box("C7", (3, 7)), Some(Index { x: 2, y: 6 }));
+        assert_eq!(parse_name_box("D7", (3, 7)), None);
+    }
+
+    #[test]
+    fn test_parse_name_box_range() {
+        let (a1, d20) = (Index { x: 0, y: 0 }, Index { x: 3, y: 19 });
+        assert_eq!(parse_name_box_range("A1:D20", GRID), Some((a1, d20)));
+        assert_eq!(parse_name_box_range(" d20 : a1 ", GRID), Some((a1, d20)));
+        assert_eq!(
+            parse_name_box_range("D1:A20", GRID).map(|(from, _)| from),
+            Some(a1)
+        );
+        assert_eq!(
+            parse_name_box_range("bc12", GRID),
+            Some((Index { x: 54, y: 11 }, Index { x: 54, y: 11 }))
+        );
+
+        assert_eq!(parse_name_box_range("A1:", GRID), None);
+        assert_eq!(parse_name_box_range("A1:B2:C3", GRID), None);
+        assert_eq!(parse_name_box_range("A1:AAAA1", GRID), None);
+    }
+
+    #[test]
+    fn test_pasted_content() {
+        let copied = CopiedCell {
+            index: Index { x: 0, y: 0 },
+            raw: "=A2+1".to_string(),
+        };
+        let c3 = Index { x: 2, y: 2 };
+
+        assert_eq!(pasted_content("=A2+1", Some(&copied), c3), "=C4+1");
+        // The clipboard was changed by another application since
+        assert_eq!(pasted_content("=A5", Some(&copied), c3), "=A5");
+        assert_eq!(pasted_content("=A2+1", None, c3), "=A2+1");
+
+        let broken = CopiedCell {
+            raw: "=A2 +* 1".to_string(),
+            ..copied
+        };
+        assert_eq!(pasted_content("=A2 +* 1", Some(&broken), c3), "=A2 +* 1");
+    }
+
+    #[test]
+    fn test_computed_to_text_with_detail() {
+        let display = DisplaySettings::default();
+        let detail = |err: ComputeError| {
+            computed_to_text_with_detail(Some(Err(err)), &display, NumberFormat::General)
+        };
+
+        assert_eq!(
+            detail(ComputeError::ParseError("Unexpected token )".to_string())),
+            (
+                "#ERROR!".to_string(),
+                Some("Unexpected token )".to_string())
+            )
+        );
+        assert_eq!(
+            detail(ComputeError::TypeError("Cannot add TRUE".to_string())),
+            ("#VALUE!".to_string(), Some("Cannot add TRUE".to_string()))
+        );
+        assert_eq!(
+            detail(ComputeError::UnfindableReference("B7 is empty".to_string())),
+            ("#REF!".to_string(), Some("B7 is empty".to_string()))
+        );
+        assert_eq!(
+            detail(ComputeError::Cycle),
+            (
+                "#CYCLE!".to_string(),
+                Some("Detected cyclic computation".to_string())
+            )
+        );
+        assert_eq!(
+            detail(ComputeError::UnknownFunction("foo".to_string())),
+            (
+                "#NAME?".to_string(),
+                Some("Unknown function 'foo'".to_string())
+            )
+        );
+        assert_eq!(
+            detail(ComputeError::InvalidArgument(
+                "round expects a number".to_string()
+            )),
+            (
+                "#VALUE!".to_string(),
+                Some("round expects a number".to_string())
+            )
+        );
+
+        assert_eq!(
+            computed_to_text_with_detail(
+                Some(Ok(Value::Number(2.5))),
+                &display,
+                NumberFormat::General
+            ),
+            ("2.5".to_string(), None)
+        );
+        assert_eq!(
+            computed_to_text_with_detail(
+                Some(Ok(Value::Number(2.5))),
+                &display,
+                NumberFormat::Percent
+            ),
+            ("250%".to_string(), None)
+        );
+        assert_eq!(
+            computed_to_text_with_detail(None, &display, NumberFormat::General),
+            (String::new(), None)
+        );
+    }
+
+    #[test]
+    fn test_summary_to_text() {
+        let summary = RangeSummary {
+            sum: 123.0,
+            average: Some(24.6),
+            count: 5,
+        };
+        let display = DisplaySettings::default();
+        assert_eq!(
+            summary_to_text(&summary, &display),
+            "Sum: 123  Avg: 24.6  Count: 5"
+        );
+
+        let empty = RangeSummary {
+            sum: 0.0,
+            average: None,
+            count: 0,
+        };
+        assert_eq!(
+            summary_to_text(&empty, &display),
+            "Sum: 0  Avg: -  Count: 0"
+        );
+
+        let large = RangeSummary {
+            sum: 1234567.0,
+            average: Some(0.1 + 0.2),
+            count: 2,
+        };
+        assert_eq!(
+            summary_to_text(&large, &display),
+            "Sum: 1,234,567  Avg: 0.3  Count: 2"
+        );
+    }
+
+    #[test]
+    fn test_stats_to_text() {
+        let stats = SheetStats {
+            cells: 1240,
+            formula_cells: 312,
+            error_cells: 2,
+            ..SheetStats::default()
+        };
+        assert_eq!(
+            stats_to_text(&stats, &DisplaySettings::default()),
+            "1,240 cells / 312 formulas / 2 errors"
+        );
+    }
+
+    #[test]
+    fn test_visible_cells() {
+        let mut columns = AxisLayout::new(CELL_WIDTH, MIN_CELL_WIDTH);
+        let rows = AxisLayout::new(CELL_HEIGHT, MIN_CELL_HEIGHT);
+        let viewport = Viewport::default();
+        let start = (0.0, 0.0);
+        let end = (
+            ROW_LABEL_WIDTH + CELL_WIDTH * 4.5,
+            COL_LABEL_HEIGHT + CELL_HEIGHT * 10.0,
+        );
+
+        let layout = (&columns, &rows);
+        // The fifth column shows cut off
+        assert_eq!(
+            visible_cells(start, end, &viewport, layout),
+            ((0..5).collect(), (0..10).collect())
+        );
+        assert_eq!(
+            visible_cells(start, (10.0, 10.0), &viewport, layout),
+            (vec![], vec![])
+        );
+
+        // A larger window shows more cells rather than larger ones
+        let large = (
+            ROW_LABEL_WIDTH + CELL_WIDTH * 12.0,
+            COL_LABEL_HEIGHT + CELL_HEIGHT * 30.25,
+        );
+        assert_eq!(
+            visible_cells(start, large, &viewport, layout),
+            ((0..12).collect(), (0..31).collect())
+        );
+        let small = (
+            ROW_LABEL_WIDTH + CELL_WIDTH * 0.5,
+            COL_LABEL_HEIGHT + CELL_HEIGHT * 2.0,
+        );
+        assert_eq!(
+            visible_cells(start, small, &viewport, layout),
+            (vec![0], vec![0, 1])
+        );
+
+        // A narrower column makes room for one more
+        columns.set_size(1, CELL_WIDTH / 4.0);
+        let layout = (&columns, &rows);
+        assert_eq!(
+            visible_cells(start, end, &viewport, layout),
+            ((0..6).collect(), (0..10).collect())
+        );
+    }
+
+    #[test]
+    fn test_occupied_cells_far_down() {
+        // A cell every 100 rows down to row 1000000, in one of 26 columns. Checking the compute
+        // of the sheet after each of them would take minutes
+        let mut sheet = SpreadSheet::default();
+        sheet.set_verify_compute(false);
+        for i in 0..10_000 {
+            let index = Index {
+                x: i % 26,
+                y: i * 100,
+            };
+            sheet.add_cell_and_compute(index, i.to_string()).unwrap();
+        }
+        let columns = AxisLayout::new(CELL_WIDTH, MIN_CELL_WIDTH);
+        let rows = AxisLayout::new(CELL_HEIGHT, MIN_CELL_HEIGHT);
+        let end = (1920.0, 1080.0);
+        let mut viewport = Viewport::default();
+        viewport.freeze(0, 1);
+
+        // A frame drawn at every step of scrolling down from row 100000
+        for first_row in (100_000..110_000).step_by(7) {
+            viewport.first_row = first_row;
+            let (cols, shown_rows) = visible_cells((0.0, 0.0), end, &viewport, (&columns, &rows));
+            let occupied = occupied_cells(&sheet, &line_ranges(&cols, &shown_rows));
+
+            let expected: HashSet<Index> = shown_rows
+                .iter()
+                .filter(|&&y| y % 100 == 0)
+                .map(|&y| Index { x: y / 100 % 26, y })
+                .filter(|index| cols.contains(&index.x))
+                .collect();
+            // The frozen first row shows A1 above them all
+            assert!(occupied.contains(&Index { x: 0, y: 0 }));
+            assert_eq!(occupied, expected);
+        }
+    }
+
+    #[test]
+    fn test_consecutive_runs() {
+        assert_eq!(consecutive_runs(&[0, 1, 7, 8, 9]), vec![(0, 1), (7, 9)]);
+        assert_eq!(consecutive_runs(&[3]), vec![(3, 3)]);
+        assert!(consecutive_runs(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_place_cell_editor() {
+        let area = ((0.0, 0.0), (500.0, 300.0));
+
+        // Short content keeps the cell rectangle
+        assert_eq!(
+            place_cell_editor((100.0, 30.0), (100.0, 30.0), 40.0, area),
+            ((100.0, 30.0), (100.0, 30.0))
+        );
+
+        // Long content grows to the right while there is room
+        assert_eq!(
+            place_cell_editor((100.0, 30.0), (100.0, 30.0), 250.0, area),
+            ((100.0, 30.0), (250.0, 30.0))
+        );
+
+        // Near the right edge it grows to the left instead
+        assert_eq!(
+            place_cell_editor((400.0, 30.0), (100.0, 30.0), 250.0, area),
+            ((250.0, 30.0), (250.0, 30.0))
+        );
+
+        // It never gets wider than the area, nor leaves it at the bottom
+        assert_eq!(
+            place_cell_editor((400.0, 290.0), (100.0, 30.0), 900.0, area),
+            ((0.0, 270.0), (500.0, 30.0))
+        );
+    }
+}
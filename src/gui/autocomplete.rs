@@ -0,0 +1,159 @@
+use std::ops::Range;
+
+use mini_spreadsheet::spreadsheet::FunctionInfo;
+
+/// The state of the function name suggestions shown while a formula is typed.
+#[derive(Debug, Default)]
+pub struct Autocomplete {
+    /// The highlighted suggestion, accepted with Tab or Enter.
+    pub selected: usize,
+    // The content the suggestions were closed for, they stay closed until it changes
+    dismissed: Option<String>,
+}
+
+impl Autocomplete {
+    /// Moves the highlight by `step` through `count` suggestions, wrapping around the ends.
+    pub fn move_selection(&mut self, step: isize, count: usize) {
+        if count > 0 {
+            self.selected = (self.selected as isize + step).rem_euclid(count as isize) as usize;
+        }
+    }
+
+    /// Hides the suggestions until `content` is changed.
+    pub fn dismiss(&mut self, content: &str) {
+        self.dismissed = Some(content.to_string());
+        self.selected = 0;
+    }
+
+    pub fn is_dismissed(&self, content: &str) -> bool {
+        self.dismissed.as_deref() == Some(content)
+    }
+}
+
+/// Returns the byte range of the partial function name that ends at `caret`, if `content`
+/// is a formula and the caret is right after a lowercase name outside of a string.
+pub fn completion_prefix(content: &str, caret: usize) -> Option<Range<usize>> {
+    let formula_start = content.find('=')?;
+    if !content[..formula_start].trim().is_empty() || caret > content.len() {
+        return None;
+    }
+
+    let before = content.get(formula_start + 1..caret)?;
+    if before.matches('"').count() % 2 == 1 {
+        return None;
+    }
+
+    let name_len = before.len()
+        - before
+            .trim_end_matches(|c: char| c.is_ascii_lowercase() || c == '_')
+            .len();
+    let start = caret - name_len;
+    // Part of something longer, like a cell name or a number
+    let follows_identifier =
+        before[..before.len() - name_len].ends_with(|c: char| c.is_ascii_alphanumeric());
+    if name_len == 0 || follows_identifier {
+        return None;
+    }
+    Some(start..caret)
+}
+
+/// Returns the functions whose name starts with `prefix`, closest match first: shorter names
+/// before longer ones, then alphabetically.
+pub fn matching_functions(prefix: &str, functions: &[FunctionInfo]) -> Vec<FunctionInfo> {
+    let mut matches: Vec<FunctionInfo> = functions
+        .iter()
+        .filter(|function| function.name.starts_with(prefix))
+        .copied()
+        .collect();
+    matches.sort_by_key(|function| (function.name.len(), function.name));
+    matches
+}
+
+/// Replaces the partial name at `prefix` with `name` and an opening parenthesis, returning
+/// the byte offset after it for the caret.
+pub fn complete(content: &mut String, prefix: Range<usize>, name: &str) -> usize {
+    let has_paren = content[prefix.end..].starts_with('(');
+    let start = prefix.start;
+    content.replace_range(prefix, name);
+    if !has_paren {
+        content.insert(start + name.len(), '(');
+    }
+    start + name.len() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FUNCTIONS: &[FunctionInfo] = &[
+        FunctionInfo {
+            name: "max",
+            signature: "max(number, ...)",
+        },
+        FunctionInfo {
+            name: "min",
+            signature: "min(number, ...)",
+        },
+        FunctionInfo {
+            name: "sum",
+            signature: "sum(number, ...)",
+        },
+        FunctionInfo {
+            name: "sumsq",
+            signature: "sumsq(number, ...)",
+        },
+    ];
+
+    fn names(prefix: &str) -> Vec<&'static str> {
+        matching_functions(prefix, FUNCTIONS)
+            .iter()
+            .map(|function| function.name)
+            .collect()
+    }
+
+    #[test]
+    fn test_matching_functions() {
+        assert_eq!(names("m"), vec!["max", "min"]);
+        assert_eq!(names("s"), vec!["sum", "sumsq"]);
+        assert_eq!(names("sums"), vec!["sumsq"]);
+        assert_eq!(names("sum"), vec!["sum", "sumsq"]);
+        assert!(names("x").is_empty());
+    }
+
+    #[test]
+    fn test_completion_prefix() {
+        assert_eq!(completion_prefix("=su", 3), Some(1..3));
+        assert_eq!(completion_prefix("=A1 + ma", 8), Some(6..8));
+        assert_eq!(completion_prefix(" =max(A1, mi", 12), Some(10..12));
+        // The caret in the middle of the name only completes what is before it
+        assert_eq!(completion_prefix("=sum", 2), Some(1..2));
+
+        assert_eq!(completion_prefix("su", 2), None);
+        assert_eq!(completion_prefix("=", 1), None);
+        assert_eq!(completion_prefix("=sum(", 5), None);
+        assert_eq!(completion_prefix("=\"su", 4), None);
+        assert_eq!(completion_prefix("=1e", 3), None);
+        assert_eq!(completion_prefix("=\"a\" & su", 9), Some(7..9));
+    }
+
+    #[test]
+    fn test_complete() {
+        let mut content = "=A1 + su".to_string();
+        assert_eq!(complete(&mut content, 6..8, "sum"), 10);
+        assert_eq!(content, "=A1 + sum(");
+
+        // An existing parenthesis is not doubled
+        let mut content = "=ma(A1)".to_string();
+        assert_eq!(complete(&mut content, 1..3, "max"), 5);
+        assert_eq!(content, "=max(A1)");
+    }
+
+    #[test]
+    fn test_move_selection_wraps() {
+        let mut autocomplete = Autocomplete::default();
+        autocomplete.move_selection(-1, 3);
+        assert_eq!(autocomplete.selected, 2);
+        autocomplete.move_selection(1, 3);
+        assert_eq!(autocomplete.selected, 0);
+    }
+}
@@ -0,0 +1,492 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::theme::Theme;
+use super::{
+    CELL_HEIGHT, CELL_WIDTH, GRID_COLS, GRID_ROWS, INITIAL_WINDOW_HEIGHT, INITIAL_WINDOW_WIDTH,
+    MIN_CELL_HEIGHT, MIN_CELL_WIDTH,
+};
+
+/// The file read for the configuration when no `--config` is given, next to the executable.
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+// The command line options overriding the config file, and the keys they set
+const FLAGS: [(&str, &str); 8] = [
+    ("--rows", "grid.rows"),
+    ("--columns", "grid.columns"),
+    ("--cell-width", "grid.cell_width"),
+    ("--cell-height", "grid.cell_height"),
+    ("--window-width", "window.width"),
+    ("--window-height", "window.height"),
+    ("--theme", "theme"),
+    ("--journal", "journal"),
+];
+
+/// How the window opens: the size of the grid and of its cells, the fonts, the theme and the
+/// symbol of the currency format. Read from a `config.toml` like
+///
+/// ```toml
+/// theme = "dark"
+/// currency_symbol = "€"
+/// journal = 1000     # Changes to cells kept, and logged next to the sheet file
+///
+/// [grid]
+/// rows = 5000
+/// columns = "auto"   # As many as there are, up to column ZZZ
+/// cell_width = 120
+///
+/// [window]
+/// width = 1600
+///
+/// [fonts]
+/// regular = "fonts/FiraCode-Regular.ttf"
+/// ```
+///
+/// where every key is optional, then from the command line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GuiConfig {
+    pub rows: usize,
+    pub columns: usize,
+    pub cell_width: f32,
+    pub cell_height: f32,
+    pub window_width: f32,
+    pub window_height: f32,
+    pub theme: Theme,
+    pub fonts: FontPaths,
+    /// Written before numbers the currency style is applied to.
+    pub currency_symbol: char,
+    /// How many changes of the content of the cells the journal keeps, 0 turning it off.
+    pub journal: usize,
+}
+
+/// Font files to draw cells with in place of the embedded ones, relative to the config file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FontPaths {
+    pub regular: Option<PathBuf>,
+    pub bold: Option<PathBuf>,
+    pub italic: Option<PathBuf>,
+    pub bold_italic: Option<PathBuf>,
+}
+
+/// Why a config file could not be read, on which of its lines.
+#[derive(Debug, PartialEq)]
+pub struct ConfigError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ConfigValue {
+    Text(String),
+    Number(f64),
+}
+
+impl Default for GuiConfig {
+    fn default() -> Self {
+        Self {
+            rows: GRID_ROWS,
+            columns: GRID_COLS,
+            cell_width: CELL_WIDTH,
+            cell_height: CELL_HEIGHT,
+            window_width: INITIAL_WINDOW_WIDTH,
+            window_height: INITIAL_WINDOW_HEIGHT,
+            theme: Theme::default(),
+            fonts: FontPaths::default(),
+            currency_symbol: '$',
+            journal: 0,
+        }
+    }
+}
+
+impl GuiConfig {
+    /// Reads the options given to open the window: the config file `--config` names, or the
+    /// one next to the executable when there is one, then the other options over it.
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut config = Self::default();
+        let mut config_path = None;
+        let mut overrides = Vec::new();
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let key = match FLAGS.iter().find(|(flag, _)| flag == arg) {
+                Some((_, key)) => *key,
+                None if arg == "--config" => "",
+                None => return Err(format!("Unknown option '{arg}'")),
+            };
+            let Some(value) = args.next() else {
+                return Err(format!("Missing value after {arg}"));
+            };
+            match key {
+                "" => config_path = Some(PathBuf::from(value)),
+                key => overrides.push((arg, key, value)),
+            }
+        }
+
+        match config_path {
+            Some(path) => config.load(&path)?,
+            None => {
+                let path = std::env::current_exe()
+                    .ok()
+                    .and_then(|exe| Some(exe.parent()?.join(CONFIG_FILE_NAME)));
+                if let Some(path) = path.filter(|path| path.is_file()) {
+                    config.load(&path)?;
+                }
+            }
+        }
+        for (flag, key, value) in overrides {
+            let value = match value.parse() {
+                Ok(number) => ConfigValue::Number(number),
+                Err(_) => ConfigValue::Text(value.clone()),
+            };
+            config
+                .set(key, value)
+                .map_err(|message| format!("{flag}: {message}"))?;
+        }
+        Ok(config)
+    }
+
+    /// Applies the config file at `path`, its font paths taken relative to its directory.
+    pub fn load(&mut self, path: &Path) -> Result<(), String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("Cannot read {}: {err}", path.display()))?;
+        self.apply(&text)
+            .map_err(|err| format!("{}: {err}", path.display()))?;
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let fonts = &mut self.fonts;
+        for font in [
+            &mut fonts.regular,
+            &mut fonts.bold,
+            &mut fonts.italic,
+            &mut fonts.bold_italic,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *font = dir.join(&*font);
+        }
+        Ok(())
+    }
+
+    /// Applies the keys set in the text of a config file, leaving the others as they are.
+    pub fn apply(&mut self, text: &str) -> Result<(), ConfigError> {
+        let mut section = String::new();
+        for (line_idx, line) in text.lines().enumerate() {
+            let error = |message: String| ConfigError {
+                line: line_idx + 1,
+                message,
+            };
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[') {
+                let Some(name) = name.strip_suffix(']') else {
+                    return Err(error(format!("Missing ']' after section '{name}'")));
+                };
+                section = name.trim().to_string();
+                if !["grid", "window", "fonts"].contains(&section.as_str()) {
+                    return Err(error(format!(
+                        "Unknown section [{section}], expected [grid], [window] or [fonts]"
+                    )));
+                }
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error(format!("Expected 'key = value', found '{line}'")));
+            };
+            let key = match section.as_str() {
+                "" => key.trim().to_string(),
+                section => format!("{section}.{}", key.trim()),
+            };
+            let value = parse_value(value.trim()).map_err(error)?;
+            self.set(&key, value).map_err(error)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the option `key`, the name of its section and its own joined by a dot, checking
+    /// `value` suits it.
+    fn set(&mut self, key: &str, value: ConfigValue) -> Result<(), String> {
+        match key {
+            "theme" => {
+                let name = text(key, value)?;
+                self.theme = Theme::named(&name).ok_or_else(|| {
+                    format!("Unknown theme '{name}', expected \"light\" or \"dark\"")
+                })?;
+            }
+            "currency_symbol" => {
+                let symbol = text(key, value)?;
+                let mut chars = symbol.chars();
+                let (Some(first), None) = (chars.next(), chars.next()) else {
+                    return Err(format!(
+                        "{key} should be a single character, not \"{symbol}\""
+                    ));
+                };
+                self.currency_symbol = first;
+            }
+            "journal" => self.journal = journal_size(key, value)?,
+            "grid.rows" => self.rows = count(key, value, GRID_ROWS)?,
+            "grid.columns" => self.columns = count(key, value, GRID_COLS)?,
+            "grid.cell_width" => self.cell_width = size(key, value, MIN_CELL_WIDTH)?,
+            "grid.cell_height" => self.cell_height = size(key, value, MIN_CELL_HEIGHT)?,
+            "window.width" => self.window_width = size(key, value, 1.0)?,
+            "window.height" => self.window_height = size(key, value, 1.0)?,
+            "fonts.regular" => self.fonts.regular = Some(text(key, value)?.into()),
+            "fonts.bold" => self.fonts.bold = Some(text(key, value)?.into()),
+            "fonts.italic" => self.fonts.italic = Some(text(key, value)?.into()),
+            "fonts.bold_italic" => self.fonts.bold_italic = Some(text(key, value)?.into()),
+            _ => return Err(format!("Unknown option '{key}'")),
+        }
+        Ok(())
+    }
+}
+
+/// Cuts a `#` comment off the end of a line, unless it is in a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => (),
+        }
+    }
+    line
+}
+
+fn parse_value(value: &str) -> Result<ConfigValue, String> {
+    if let Some(quoted) = value.strip_prefix('"') {
+        return match quoted.strip_suffix('"') {
+            Some(text) if !text.contains('"') => Ok(ConfigValue::Text(text.to_string())),
+            _ => Err(format!("Unterminated string {value}")),
+        };
+    }
+    match value.replace('_', "").parse() {
+        Ok(number) => Ok(ConfigValue::Number(number)),
+        Err(_) if value.is_empty() => Err("Missing value after '='".to_string()),
+        Err(_) => Err(format!(
+            "Expected a number or a string, found {value} (strings are written in quotes, \
+             like \"{value}\")"
+        )),
+    }
+}
+
+fn text(key: &str, value: ConfigValue) -> Result<String, String> {
+    match value {
+        ConfigValue::Text(text) => Ok(text),
+        ConfigValue::Number(number) => Err(format!("{key} should be a string, not {number}")),
+    }
+}
+
+/// Reads a number of rows or columns, `"auto"` for as many as the grid reaches.
+fn count(key: &str, value: ConfigValue, max: usize) -> Result<usize, String> {
+    match value {
+        ConfigValue::Text(text) if text == "auto" => Ok(max),
+        ConfigValue::Number(number) if number.fract() == 0.0 && number >= 1.0 => {
+            match number <= max as f64 {
+                true => Ok(number as usize),
+                false => Err(format!("{key} can be at most {max}, not {number}")),
+            }
+        }
+        ConfigValue::Number(number) => Err(format!(
+            "{key} should be a whole number from 1 to {max}, not {number}"
+        )),
+        ConfigValue::Text(text) => Err(format!(
+            "{key} should be a number or \"auto\", not \"{text}\""
+        )),
+    }
+}
+
+/// Reads how many changes the journal keeps, where 0 keeps none.
+fn journal_size(key: &str, value: ConfigValue) -> Result<usize, String> {
+    match value {
+        ConfigValue::Number(number) if number.fract() == 0.0 && number >= 0.0 => {
+            Ok(number as usize)
+        }
+        ConfigValue::Number(number) => Err(format!(
+            "{key} should be a whole number of changes, not {number}"
+        )),
+        ConfigValue::Text(text) => Err(format!("{key} should be a number, not \"{text}\"")),
+    }
+}
+
+fn size(key: &str, value: ConfigValue, min: f32) -> Result<f32, String> {
+    match value {
+        ConfigValue::Number(number) if number.is_finite() && number as f32 >= min => {
+            Ok(number as f32)
+        }
+        ConfigValue::Number(number) => Err(format!("{key} should be at least {min}, not {number}")),
+        ConfigValue::Text(text) => Err(format!("{key} should be a number, not \"{text}\"")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<GuiConfig, ConfigError> {
+        let mut config = GuiConfig::default();
+        config.apply(text).map(|()| config)
+    }
+
+    fn error(line: usize, message: &str) -> Result<GuiConfig, ConfigError> {
+        Err(ConfigError {
+            line,
+            message: message.to_string(),
+        })
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let config = parse(
+            "# Wide cells for long labels\n\
+             theme = \"dark\"\n\
+             currency_symbol = \"€\"\n\
+             journal = 500\n\
+             \n\
+             [grid]\n\
+             rows = 5_000  # Enough for a year of data\n\
+             columns = \"auto\"\n\
+             cell_width = 120.5\n\
+             [fonts]\n\
+             bold = \"fonts/#bold.ttf\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            GuiConfig {
+                rows: 5000,
+                columns: GRID_COLS,
+                cell_width: 120.5,
+                theme: Theme::DARK,
+                currency_symbol: '€',
+                journal: 500,
+                fonts: FontPaths {
+                    bold: Some("fonts/#bold.ttf".into()),
+                    ..FontPaths::default()
+                },
+                ..GuiConfig::default()
+            }
+        );
+        assert_eq!(parse(""), Ok(GuiConfig::default()));
+    }
+
+    #[test]
+    fn test_config_errors() {
+        assert_eq!(
+            parse("\n[grid]\nrow = 10"),
+            error(3, "Unknown option 'grid.row'")
+        );
+        assert_eq!(
+            parse("[grid]\nrows = 0"),
+            error(
+                2,
+                "grid.rows should be a whole number from 1 to 1000000, not 0"
+            )
+        );
+        assert_eq!(
+            parse("[grid]\ncolumns = 20000"),
+            error(2, "grid.columns can be at most 18278, not 20000")
+        );
+        assert_eq!(
+            parse("[grid]\ncell_height = 5"),
+            error(2, "grid.cell_height should be at least 10, not 5")
+        );
+        assert_eq!(
+            parse("theme = dark"),
+            error(
+                1,
+                "Expected a number or a string, found dark (strings are written in quotes, \
+                 like \"dark\")"
+            )
+        );
+        assert_eq!(
+            parse("theme = \"blue\""),
+            error(1, "Unknown theme 'blue', expected \"light\" or \"dark\"")
+        );
+        assert_eq!(
+            parse("currency_symbol = \"US$\""),
+            error(
+                1,
+                "currency_symbol should be a single character, not \"US$\""
+            )
+        );
+        assert_eq!(
+            parse("journal = 2.5"),
+            error(1, "journal should be a whole number of changes, not 2.5")
+        );
+        assert_eq!(
+            parse("[colors]"),
+            error(
+                1,
+                "Unknown section [colors], expected [grid], [window] or [fonts]"
+            )
+        );
+        assert_eq!(
+            parse("[window\nwidth = 10"),
+            error(1, "Missing ']' after section 'window'")
+        );
+        assert_eq!(
+            parse("[window]\nwidth"),
+            error(2, "Expected 'key = value', found 'width'")
+        );
+        assert_eq!(
+            parse("[fonts]\nregular = \"a.ttf"),
+            error(2, "Unterminated string \"a.ttf")
+        );
+    }
+
+    #[test]
+    fn test_config_from_args() {
+        let args = |args: &[&str]| -> Vec<String> { args.iter().map(|a| a.to_string()).collect() };
+        let path = std::env::temp_dir().join(format!("gui_config_{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[grid]\nrows = 100\ncolumns = 10\n[fonts]\nitalic = \"it.ttf\"",
+        )
+        .unwrap();
+
+        // Options given on the command line win over the file
+        let config = GuiConfig::from_args(&args(&[
+            "--columns",
+            "auto",
+            "--config",
+            path.to_str().unwrap(),
+            "--theme",
+            "dark",
+        ]))
+        .unwrap();
+        assert_eq!((config.rows, config.columns), (100, GRID_COLS));
+        assert_eq!(config.theme, Theme::DARK);
+        assert_eq!(
+            config.fonts.italic,
+            Some(std::env::temp_dir().join("it.ttf"))
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            GuiConfig::from_args(&args(&["--rows", "-3"])),
+            Err("--rows: grid.rows should be a whole number from 1 to 1000000, not -3".to_string())
+        );
+        assert_eq!(
+            GuiConfig::from_args(&args(&["--theme"])),
+            Err("Missing value after --theme".to_string())
+        );
+        assert_eq!(
+            GuiConfig::from_args(&args(&["--zoom", "2"])),
+            Err("Unknown option '--zoom'".to_string())
+        );
+        assert!(GuiConfig::from_args(&args(&["--config", "missing.toml"]))
+            .unwrap_err()
+            .starts_with("Cannot read missing.toml"));
+    }
+}
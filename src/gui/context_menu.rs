@@ -0,0 +1,217 @@
+use mini_spreadsheet::common_types::NumberFormat;
+
+use super::viewport::Label;
+
+/// What an item of a context menu does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MenuAction {
+    SortAscending,
+    SortDescending,
+    /// Copies the values of the selected cells as comma separated values.
+    CopyAsCsv,
+    /// Pastes the values of what was copied rather than its formulas.
+    PasteValues,
+    /// Replaces the formulas of the selected cells by their values.
+    ConvertToValues,
+    /// Sets the background of the selected cells, `None` clearing it.
+    Fill(Option<[u8; 4]>),
+    Borders(BorderPlacement),
+    /// Locks the selected cells, or unlocks them when all of them are locked.
+    ToggleLocked,
+    /// Turns the protection of the locked cells of the sheet on or off.
+    ToggleProtection,
+    /// Sets the number format of the cells of the column without one of their own.
+    ColumnFormat(NumberFormat),
+    /// Writes the sum, average and count of each selected column beneath the selection.
+    AddSummary,
+    /// Changes how the numbers of the selected cells are written.
+    NumberStyle(NumberStyle),
+    /// Tints the selected cells holding the same value, a tint for each value, comparing text
+    /// in any case with `ignore_case`.
+    HighlightDuplicates {
+        ignore_case: bool,
+    },
+    /// Lists the changes the journal kept of the selected cell.
+    ShowHistory,
+    /// Copies the values of the selected cells left shown, passing over hidden rows and
+    /// columns.
+    CopyShown,
+    /// Hides the column or row of the label.
+    Hide,
+    /// Shows again the columns or rows hidden next to the label.
+    Unhide,
+}
+
+/// The number formats the `MenuAction::NumberStyle` items apply in one click.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NumberStyle {
+    /// Percentages, or the general format again when all the cells already are.
+    Percent,
+    /// Two decimals after the currency symbol of the config, or the general format again
+    /// like `Percent`.
+    Currency,
+    /// Two decimals, the integer part in groups of three.
+    Comma,
+    MoreDecimals,
+    FewerDecimals,
+}
+
+/// Where the `MenuAction::Borders` item draws lines around the selected cells.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BorderPlacement {
+    /// Along every edge of every cell.
+    All,
+    /// Around the outside of the selection.
+    Outline,
+    /// Nowhere, clearing the borders of the cells.
+    Clear,
+}
+
+/// What a context menu was opened on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MenuTarget {
+    Label(Label),
+    /// The selected cells.
+    Cells,
+}
+
+/// A menu opened by right clicking a label or a cell, offering actions for it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContextMenu {
+    pub target: MenuTarget,
+    /// The top left corner of the menu on screen.
+    pub pos: (f32, f32),
+}
+
+impl ContextMenu {
+    /// Returns the items of the menu with their text, from the top down.
+    pub fn items(&self) -> &'static [(MenuAction, &'static str)] {
+        match self.target {
+            MenuTarget::Label(Label::Column(_)) => &[
+                (MenuAction::SortAscending, "Sort ascending"),
+                (MenuAction::SortDescending, "Sort descending"),
+                (
+                    MenuAction::ColumnFormat(NumberFormat::Percent),
+                    "Percent column",
+                ),
+                (
+                    MenuAction::ColumnFormat(NumberFormat::General),
+                    "General column",
+                ),
+                (MenuAction::Hide, "Hide column"),
+                (MenuAction::Unhide, "Unhide"),
+            ],
+            MenuTarget::Label(Label::Row(_)) => &[
+                (MenuAction::Hide, "Hide row"),
+                (MenuAction::Unhide, "Unhide"),
+            ],
+            MenuTarget::Cells => &[
+                (MenuAction::Fill(None), "No fill"),
+                (MenuAction::Fill(Some([255, 241, 118, 255])), "Yellow"),
+                (MenuAction::Fill(Some([165, 214, 167, 255])), "Green"),
+                (MenuAction::Fill(Some([144, 202, 249, 255])), "Blue"),
+                (MenuAction::Fill(Some([239, 154, 154, 255])), "Red"),
+                (MenuAction::Fill(Some([206, 147, 216, 255])), "Purple"),
+                (MenuAction::Borders(BorderPlacement::All), "All borders"),
+                (
+                    MenuAction::Borders(BorderPlacement::Outline),
+                    "Outline border",
+                ),
+                (MenuAction::Borders(BorderPlacement::Clear), "No borders"),
+                (
+                    MenuAction::NumberStyle(NumberStyle::Percent),
+                    "Percent style",
+                ),
+                (
+                    MenuAction::NumberStyle(NumberStyle::Currency),
+                    "Currency style",
+                ),
+                (MenuAction::NumberStyle(NumberStyle::Comma), "Comma style"),
+                (
+                    MenuAction::NumberStyle(NumberStyle::MoreDecimals),
+                    "More decimals",
+                ),
+                (
+                    MenuAction::NumberStyle(NumberStyle::FewerDecimals),
+                    "Fewer decimals",
+                ),
+                (MenuAction::CopyAsCsv, "Copy as CSV"),
+                (MenuAction::CopyShown, "Copy shown cells"),
+                (MenuAction::PasteValues, "Paste values"),
+                (MenuAction::ConvertToValues, "Convert to values"),
+                (MenuAction::ToggleLocked, "Toggle lock"),
+                (MenuAction::ToggleProtection, "Toggle protection"),
+                (MenuAction::AddSummary, "Add summary rows"),
+                (
+                    MenuAction::HighlightDuplicates { ignore_case: false },
+                    "Find duplicates",
+                ),
+                (
+                    MenuAction::HighlightDuplicates { ignore_case: true },
+                    "Find, any case",
+                ),
+                (MenuAction::ShowHistory, "Cell history"),
+            ],
+        }
+    }
+
+    /// Returns the position of the item under `pos`, for items of `item_size` stacked down
+    /// from the top left corner of the menu.
+    pub fn item_at(&self, pos: (f32, f32), item_size: (f32, f32)) -> Option<usize> {
+        let (x, y) = (pos.0 - self.pos.0, pos.1 - self.pos.1);
+        let (width, height) = item_size;
+        if x < 0.0 || x >= width || y < 0.0 {
+            return None;
+        }
+
+        let item = (y / height) as usize;
+        (item < self.items().len()).then_some(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_item_at() {
+        let menu = ContextMenu {
+            target: MenuTarget::Label(Label::Column(2)),
+            pos: (100.0, 50.0),
+        };
+        let item_size = (120.0, 20.0);
+
+        assert_eq!(menu.item_at((110.0, 55.0), item_size), Some(0));
+        assert_eq!(menu.item_at((219.0, 70.0), item_size), Some(1));
+        // Below the last item, and left or right of the menu
+        let below = 50.0 + 20.0 * menu.items().len() as f32;
+        assert_eq!(menu.item_at((110.0, below), item_size), None);
+        assert_eq!(menu.item_at((99.0, 55.0), item_size), None);
+        assert_eq!(menu.item_at((220.0, 55.0), item_size), None);
+        assert_eq!(menu.item_at((110.0, 49.0), item_size), None);
+    }
+
+    #[test]
+    fn test_rows_only_hide() {
+        let menu = ContextMenu {
+            target: MenuTarget::Label(Label::Row(0)),
+            pos: (0.0, 0.0),
+        };
+        let actions: Vec<MenuAction> = menu.items().iter().map(|(action, _)| *action).collect();
+        assert_eq!(actions, [MenuAction::Hide, MenuAction::Unhide]);
+        assert_eq!(menu.item_at((5.0, 45.0), (120.0, 20.0)), None);
+    }
+
+    #[test]
+    fn test_cell_menu_starts_with_clearing_the_fill() {
+        let menu = ContextMenu {
+            target: MenuTarget::Cells,
+            pos: (0.0, 0.0),
+        };
+        assert_eq!(menu.items()[0].0, MenuAction::Fill(None));
+        assert!(menu.items()[1..6]
+            .iter()
+            .all(|(action, _)| matches!(action, MenuAction::Fill(Some(_)))));
+        assert_eq!(menu.items()[6..].len(), 18);
+    }
+}
@@ -0,0 +1,125 @@
+use mini_spreadsheet::common_types::Index;
+
+use super::selection::range_contains;
+
+/// How far the dependants of a cell reach.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DependantDepth {
+    /// Cells whose formula references the cell itself.
+    Direct,
+    /// Cells that depend on it over any number of formulas.
+    Transitive,
+}
+
+/// The dependants highlighted for the selected cell. The highlight belongs to the selection
+/// it was asked for and is gone once that changes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DependantHighlight {
+    pub selection: (Index, Index),
+    pub depth: DependantDepth,
+}
+
+impl DependantHighlight {
+    /// Returns the highlight after it was asked for with `selection` selected: the direct
+    /// dependants at first, then all of them when asked again for the same selection.
+    pub fn extend(current: Option<Self>, selection: (Index, Index)) -> Self {
+        match current {
+            Some(highlight) if highlight.selection == selection => Self {
+                depth: DependantDepth::Transitive,
+                ..highlight
+            },
+            _ => Self {
+                selection,
+                depth: DependantDepth::Direct,
+            },
+        }
+    }
+
+    /// Returns the cell whose dependants are highlighted.
+    pub fn source(&self) -> Index {
+        self.selection.0
+    }
+}
+
+/// Returns how many of `cells` lie outside of all the `visible` rectangles.
+pub fn count_outside(cells: &[Index], visible: &[(Index, Index)]) -> usize {
+    cells
+        .iter()
+        .filter(|&&cell| !visible.iter().any(|&range| range_contains(range, cell)))
+        .count()
+}
+
+/// Describes the highlighted dependants for the status bar.
+pub fn dependants_to_text(depth: DependantDepth, count: usize, off_screen: usize) -> String {
+    let kind = match depth {
+        DependantDepth::Direct => "Direct dependants",
+        DependantDepth::Transitive => "All dependants",
+    };
+    if off_screen > 0 {
+        format!("{kind}: {count} ({off_screen} off screen)")
+    } else {
+        format!("{kind}: {count}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extend_highlight() {
+        let a1 = Index { x: 0, y: 0 };
+        let b2 = Index { x: 1, y: 1 };
+
+        let direct = DependantHighlight::extend(None, (a1, a1));
+        assert_eq!(direct.depth, DependantDepth::Direct);
+        assert_eq!(direct.source(), a1);
+
+        let transitive = DependantHighlight::extend(Some(direct), (a1, a1));
+        assert_eq!(transitive.depth, DependantDepth::Transitive);
+        assert_eq!(
+            DependantHighlight::extend(Some(transitive), (a1, a1)),
+            transitive
+        );
+
+        // Another selection starts over from the direct dependants
+        assert_eq!(
+            DependantHighlight::extend(Some(transitive), (b2, b2)),
+            DependantHighlight {
+                selection: (b2, b2),
+                depth: DependantDepth::Direct
+            }
+        );
+    }
+
+    #[test]
+    fn test_count_outside() {
+        let cells = [
+            Index { x: 0, y: 0 },
+            Index { x: 2, y: 3 },
+            Index { x: 9, y: 1 },
+            Index { x: 1, y: 40 },
+        ];
+        let visible = (Index { x: 0, y: 0 }, Index { x: 5, y: 20 });
+
+        assert_eq!(count_outside(&cells, &[visible]), 2);
+        assert_eq!(count_outside(&cells, &[]), 4);
+
+        // A frozen first row above scrolled rows
+        let frozen = (Index { x: 0, y: 0 }, Index { x: 5, y: 0 });
+        let scrolled = (Index { x: 0, y: 30 }, Index { x: 5, y: 50 });
+        assert_eq!(count_outside(&cells, &[frozen, scrolled]), 2);
+    }
+
+    #[test]
+    fn test_dependants_to_text() {
+        assert_eq!(
+            dependants_to_text(DependantDepth::Direct, 3, 0),
+            "Direct dependants: 3"
+        );
+        assert_eq!(
+            dependants_to_text(DependantDepth::Transitive, 5, 2),
+            "All dependants: 5 (2 off screen)"
+        );
+    }
+}
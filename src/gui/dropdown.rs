@@ -0,0 +1,151 @@
+/// Keys a dropdown answers to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DropdownKey {
+    Up,
+    Down,
+    Enter,
+    Escape,
+}
+
+/// What a key pressed in a dropdown did.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DropdownEvent<T> {
+    /// Another item is highlighted.
+    Moved,
+    /// The highlighted item was picked.
+    Chosen(T),
+    /// The dropdown was closed without picking anything.
+    Closed,
+}
+
+/// A list of items popping up under a cell or a text field, one of them highlighted. The
+/// arrow keys move the highlight, wrapping around the ends, Enter picks the highlighted item
+/// and Escape closes the list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dropdown<T> {
+    items: Vec<T>,
+    highlighted: usize,
+}
+
+impl<T: Clone> Dropdown<T> {
+    /// Opens the list with its first item highlighted.
+    pub fn new(items: Vec<T>) -> Self {
+        Self {
+            items,
+            highlighted: 0,
+        }
+    }
+
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    pub fn highlighted(&self) -> usize {
+        self.highlighted
+    }
+
+    /// Highlights the first item equal to `item`, leaving the highlight where it is when
+    /// there is none.
+    pub fn highlight_item<Q>(&mut self, item: &Q)
+    where
+        T: PartialEq<Q>,
+        Q: ?Sized,
+    {
+        if let Some(position) = self.items.iter().position(|listed| listed == item) {
+            self.highlighted = position;
+        }
+    }
+
+    /// Moves the highlight by `step` items, wrapping around the ends.
+    pub fn move_highlight(&mut self, step: isize) {
+        let count = self.items.len() as isize;
+        if count > 0 {
+            self.highlighted = (self.highlighted as isize + step).rem_euclid(count) as usize;
+        }
+    }
+
+    pub fn handle_key(&mut self, key: DropdownKey) -> DropdownEvent<T> {
+        match key {
+            DropdownKey::Up => {
+                self.move_highlight(-1);
+                DropdownEvent::Moved
+            }
+            DropdownKey::Down => {
+                self.move_highlight(1);
+                DropdownEvent::Moved
+            }
+            DropdownKey::Enter => match self.items.get(self.highlighted) {
+                Some(item) => DropdownEvent::Chosen(item.clone()),
+                None => DropdownEvent::Closed,
+            },
+            DropdownKey::Escape => DropdownEvent::Closed,
+        }
+    }
+
+    /// Returns the position of the item `y` below the top of the list, for rows of
+    /// `row_height` stacked down from it.
+    pub fn item_at(&self, y: f32, row_height: f32) -> Option<usize> {
+        if y < 0.0 {
+            return None;
+        }
+        let item = (y / row_height) as usize;
+        (item < self.items.len()).then_some(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dropdown() -> Dropdown<String> {
+        Dropdown::new(vec![
+            "yes".to_string(),
+            "no".to_string(),
+            "maybe".to_string(),
+        ])
+    }
+
+    #[test]
+    fn test_keys_move_and_pick() {
+        let mut dropdown = dropdown();
+        assert_eq!(dropdown.highlighted(), 0);
+        assert_eq!(dropdown.handle_key(DropdownKey::Down), DropdownEvent::Moved);
+        assert_eq!(
+            dropdown.handle_key(DropdownKey::Enter),
+            DropdownEvent::Chosen("no".to_string())
+        );
+
+        // Wrapping around both ends
+        dropdown.handle_key(DropdownKey::Down);
+        dropdown.handle_key(DropdownKey::Down);
+        assert_eq!(dropdown.highlighted(), 0);
+        dropdown.handle_key(DropdownKey::Up);
+        assert_eq!(dropdown.highlighted(), 2);
+
+        assert_eq!(
+            dropdown.handle_key(DropdownKey::Escape),
+            DropdownEvent::Closed
+        );
+        let mut empty = Dropdown::<String>::new(Vec::new());
+        empty.move_highlight(1);
+        assert_eq!(empty.handle_key(DropdownKey::Enter), DropdownEvent::Closed);
+    }
+
+    #[test]
+    fn test_highlight_item() {
+        let mut dropdown = dropdown();
+        dropdown.highlight_item("maybe");
+        assert_eq!(dropdown.highlighted(), 2);
+        dropdown.highlight_item("never");
+        assert_eq!(dropdown.highlighted(), 2);
+    }
+
+    #[test]
+    fn test_item_at() {
+        let dropdown = dropdown();
+        assert_eq!(dropdown.item_at(5.0, 20.0), Some(0));
+        assert_eq!(dropdown.item_at(45.0, 20.0), Some(2));
+        assert_eq!(dropdown.item_at(60.0, 20.0), None);
+        assert_eq!(dropdown.item_at(-1.0, 20.0), None);
+    }
+}
@@ -0,0 +1,47 @@
+use mini_spreadsheet::common_types::Index;
+
+/// The groups of cells holding the same value highlighted among the selected ones, each in a
+/// tint of its own. The highlight is gone once the content of any cell changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateHighlight {
+    /// The revision of the sheet the groups were found in, see `SpreadSheet::revision`.
+    pub revision: u64,
+    pub groups: Vec<Vec<Index>>,
+}
+
+impl DuplicateHighlight {
+    /// Describes the groups for the status bar.
+    pub fn to_text(&self) -> String {
+        let cells: usize = self.groups.iter().map(Vec::len).sum();
+        match self.groups.len() {
+            0 => "No duplicates".to_string(),
+            1 => format!("Duplicates: {cells} cells alike"),
+            groups => format!("Duplicates: {cells} cells in {groups} groups"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicates_to_text() {
+        let a1 = Index { x: 0, y: 0 };
+        let b1 = Index { x: 1, y: 0 };
+        let c1 = Index { x: 2, y: 0 };
+        let highlight = |groups| DuplicateHighlight {
+            revision: 0,
+            groups,
+        };
+        assert_eq!(highlight(vec![]).to_text(), "No duplicates");
+        assert_eq!(
+            highlight(vec![vec![a1, b1, c1]]).to_text(),
+            "Duplicates: 3 cells alike"
+        );
+        assert_eq!(
+            highlight(vec![vec![a1, b1], vec![c1, Index { x: 3, y: 0 }]]).to_text(),
+            "Duplicates: 4 cells in 2 groups"
+        );
+    }
+}
@@ -0,0 +1,941 @@
+use mini_spreadsheet::common_types::{EditError, Index};
+use mini_spreadsheet::spreadsheet::{validation::ValidationState, SpreadSheet};
+
+use super::selection::SelectionSet;
+
+/// Direction of a single step of keyboard navigation on the grid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Whether keystrokes go to the grid or into the editor.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum EditMode {
+    /// A cell is selected but its content has not been touched yet.
+    #[default]
+    Browsing,
+    /// The editor content is being changed and awaits a commit or cancel.
+    Editing,
+}
+
+/// Keys that behave differently depending on the edit mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EditKey {
+    Delete,
+    Backspace,
+    Escape,
+    /// Any printable character typed on the keyboard.
+    Printable,
+    Undo,
+    Redo,
+}
+
+/// What has to happen to the selected cell as the result of an `EditKey`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EditAction {
+    /// The key is left to the editor widget.
+    None,
+    ClearCell,
+    /// Empties the editor so typing starts over, leaving the cell until the edit is committed.
+    ReplaceContent,
+    Cancel,
+    /// Drops the edit in progress without leaving the cell, as it never reached the sheet.
+    DiscardEdit,
+    /// Steps back or forth through the history of the sheet, selecting the cells it changes.
+    Undo,
+    Redo,
+}
+
+/// Returns the mode after `key` was pressed in `mode`, and the action it causes.
+pub fn transition(mode: EditMode, key: EditKey) -> (EditMode, EditAction) {
+    match (mode, key) {
+        (_, EditKey::Escape) => (EditMode::Browsing, EditAction::Cancel),
+        (EditMode::Browsing, EditKey::Delete) => (EditMode::Browsing, EditAction::ClearCell),
+        (EditMode::Browsing, EditKey::Backspace) => (EditMode::Editing, EditAction::ClearCell),
+        (EditMode::Browsing, EditKey::Printable) => (EditMode::Editing, EditAction::ReplaceContent),
+        (EditMode::Browsing, EditKey::Undo) => (EditMode::Browsing, EditAction::Undo),
+        (EditMode::Browsing, EditKey::Redo) => (EditMode::Browsing, EditAction::Redo),
+        // The history only holds committed changes, so an edit in progress goes first
+        (EditMode::Editing, EditKey::Undo) => (EditMode::Browsing, EditAction::DiscardEdit),
+        (EditMode::Editing, _) => (EditMode::Editing, EditAction::None),
+    }
+}
+
+/// The selection and the content of the editor, kept apart from any rendering so the
+/// editing flow can be driven without a window.
+#[derive(Debug, Default)]
+pub struct EditState {
+    /// The selected rectangle as `(anchor, focus)`. Edits go to the anchor, while the focus is
+    /// the corner moved when extending the selection.
+    pub selection: Option<(Index, Index)>,
+    /// The rectangles selected before `selection` with Ctrl+click, making a single selection
+    /// with it. Selecting another cell any other way drops them.
+    pub added: SelectionSet,
+    pub mode: EditMode,
+    /// The editing buffer, which only reaches the spreadsheet through `commit`.
+    pub editor_content: String,
+    /// Raw content of the selected cell as it was loaded, restored when an edit is cancelled.
+    committed_content: String,
+    /// Why the last change was refused, when it broke a strict validation rule or touched a
+    /// locked cell.
+    pub rejection: Option<String>,
+}
+
+impl EditState {
+    /// Returns the cell edits go to, the anchor of the selection.
+    pub fn selected_cell(&self) -> Option<Index> {
+        self.selection.map(|(anchor, _)| anchor)
+    }
+
+    /// Writes the editor content into the selected cell, adding, mutating or removing it.
+    /// Content breaking a strict validation rule of the cell, or for a locked cell, is dropped
+    /// instead, see `rejection`.
+    pub fn commit(&mut self, spread_sheet: &mut SpreadSheet) {
+        self.rejection = None;
+        if let Some(idx) = self.selected_cell() {
+            let previous_content = spread_sheet.get_raw(&idx).unwrap_or_default();
+            let new_content = self.editor_content.trim().to_string();
+            if previous_content != new_content {
+                if let Some(ValidationState::Invalid {
+                    message,
+                    strict: true,
+                }) = spread_sheet.check_validation(idx, &new_content)
+                {
+                    self.rejection = Some(message);
+                    return;
+                }
+            }
+
+            let result = match (previous_content, new_content.as_str()) {
+                (prev, new) if prev == new => Ok(()),
+                ("", "") => Ok(()),
+                ("", _added_content) => spread_sheet.add_cell_and_compute(idx, new_content),
+                (_deleted_content, "") => spread_sheet.remove_cell(idx),
+                (_mutated_from, _mutated_to) => spread_sheet.mutate_cell(idx, new_content),
+            };
+            self.report(result);
+        }
+    }
+
+    /// Keeps why a change made to the sheet was refused as the `rejection`, clearing it once
+    /// a change goes through.
+    pub fn report(&mut self, result: Result<(), EditError>) {
+        self.rejection = result.err().map(|err| err.to_string());
+    }
+
+    /// Commits the current edit and loads the raw content of `idx` into the editor.
+    pub fn select(&mut self, idx: Index, spread_sheet: &mut SpreadSheet) {
+        if self.selected_cell() == Some(idx) {
+            self.selection = Some((idx, idx));
+            self.added.clear();
+            return;
+        }
+
+        self.commit(spread_sheet);
+        self.load(idx, spread_sheet);
+    }
+
+    /// Selects `idx` like `select`, keeping the current selection as part of a selection of
+    /// several rectangles.
+    pub fn add_to_selection(&mut self, idx: Index, spread_sheet: &mut SpreadSheet) {
+        let mut added = std::mem::take(&mut self.added);
+        if let Some(selection) = self.selection {
+            added.add(selection);
+        }
+        self.select(idx, spread_sheet);
+        self.added = added;
+    }
+
+    /// Returns all the selected rectangles, those added with Ctrl+click and the current one.
+    pub fn selected_ranges(&self) -> SelectionSet {
+        match self.selection {
+            Some(selection) => self.added.with(selection),
+            None => SelectionSet::default(),
+        }
+    }
+
+    /// Replaces the content of the selected cell, as if it was typed and committed.
+    pub fn write(&mut self, content: String, spread_sheet: &mut SpreadSheet) {
+        let Some(idx) = self.selected_cell() else {
+            return;
+        };
+
+        self.editor_content = content;
+        self.commit(spread_sheet);
+        self.load(idx, spread_sheet);
+    }
+
+    /// Commits the current edit and moves the selection one step, clamped to `bounds`.
+    /// The editor is reloaded even when the selection is stuck at the edge of the grid.
+    pub fn commit_and_move(
+        &mut self,
+        direction: Direction,
+        bounds: (usize, usize),
+        spread_sheet: &mut SpreadSheet,
+    ) {
+        let Some(selected) = self.selected_cell() else {
+            return;
+        };
+
+        self.commit(spread_sheet);
+        self.load(move_index(selected, direction, bounds), spread_sheet);
+    }
+
+    /// Applies a mode dependent key to the selected cell. Only undo and redo work without
+    /// a selected cell. Keys that would change locked cells are refused, see `rejection`.
+    pub fn handle_key(&mut self, key: EditKey, spread_sheet: &mut SpreadSheet) {
+        let Some(range) = self.selection else {
+            if matches!(key, EditKey::Undo | EditKey::Redo) {
+                self.step_history(key, spread_sheet);
+            }
+            return;
+        };
+
+        let (mode, action) = transition(self.mode, key);
+        // Clearing empties the whole selection, typing only the anchor
+        let ranges = self.selected_ranges();
+        let refused = match action {
+            EditAction::ClearCell => ranges
+                .ranges()
+                .iter()
+                .try_for_each(|&range| spread_sheet.check_unlocked(range)),
+            EditAction::ReplaceContent => spread_sheet.check_unlocked((range.0, range.0)),
+            _ => Ok(()),
+        };
+        if let Err(err) = refused {
+            self.rejection = Some(err.to_string());
+            return;
+        }
+
+        match action {
+            EditAction::None => (),
+            EditAction::ClearCell => {
+                let cleared: Vec<Index> = spread_sheet
+                    .cells
+                    .keys()
+                    .copied()
+                    .filter(|&cell| ranges.contains(cell))
+                    .collect();
+                let result = spread_sheet.batch(|sheet| {
+                    cleared
+                        .into_iter()
+                        .try_for_each(|cell| sheet.remove_cell(cell))
+                });
+                self.report(result);
+                self.editor_content.clear();
+                self.committed_content.clear();
+            }
+            EditAction::ReplaceContent => self.editor_content.clear(),
+            EditAction::Cancel => self.cancel(),
+            EditAction::DiscardEdit => self.editor_content.clone_from(&self.committed_content),
+            EditAction::Undo | EditAction::Redo => self.step_history(key, spread_sheet),
+        }
+        self.mode = mode;
+    }
+
+    /// Undoes or redoes a step of the sheet history and selects the rectangle of the cells it
+    /// changed, with the anchor at its top left.
+    fn step_history(&mut self, key: EditKey, spread_sheet: &mut SpreadSheet) {
+        let changed = match key {
+            EditKey::Redo => spread_sheet.redo(),
+            _ => spread_sheet.undo(),
+        };
+        let Some(changed) = changed.filter(|changed| !changed.is_empty()) else {
+            return;
+        };
+
+        let top_left = Index {
+            x: changed.iter().map(|idx| idx.x).min().unwrap_or_default(),
+            y: changed.iter().map(|idx| idx.y).min().unwrap_or_default(),
+        };
+        let bottom_right = Index {
+            x: changed.iter().map(|idx| idx.x).max().unwrap_or_default(),
+            y: changed.iter().map(|idx| idx.y).max().unwrap_or_default(),
+        };
+        self.load(top_left, spread_sheet);
+        self.selection = Some((top_left, bottom_right));
+    }
+
+    /// Moves the focus of the selection to `idx`, keeping its anchor. Any edit of the anchor
+    /// is committed first.
+    pub fn extend_selection(&mut self, idx: Index, spread_sheet: &mut SpreadSheet) {
+        let Some(anchor) = self.selected_cell() else {
+            self.select(idx, spread_sheet);
+            return;
+        };
+
+        if self.mode == EditMode::Editing {
+            self.commit(spread_sheet);
+            self.load(anchor, spread_sheet);
+        }
+        self.selection = Some((anchor, idx));
+    }
+
+    /// Loads the selected cell again after the sheet changed under it, keeping the selection.
+    pub fn reload(&mut self, spread_sheet: &SpreadSheet) {
+        let Some(selection) = self.selection else {
+            return;
+        };
+        let added = std::mem::take(&mut self.added);
+        self.load(selection.0, spread_sheet);
+        self.selection = Some(selection);
+        self.added = added;
+    }
+
+    /// Returns the corner of the selection that moves when it is extended.
+    pub fn selection_focus(&self) -> Option<Index> {
+        self.selection.map(|(_, focus)| focus)
+    }
+
+    /// Starts editing the selected cell, keeping its content. A locked cell is left as it is,
+    /// see `rejection`.
+    pub fn begin_edit(&mut self, spread_sheet: &SpreadSheet) {
+        let Some(idx) = self.selected_cell() else {
+            return;
+        };
+        match spread_sheet.check_unlocked((idx, idx)) {
+            Ok(()) => self.mode = EditMode::Editing,
+            Err(err) => self.rejection = Some(err.to_string()),
+        }
+    }
+
+    /// Returns true if the editing buffer differs from the selected cell's committed content.
+    pub fn is_modified(&self) -> bool {
+        self.editor_content != self.committed_content
+    }
+
+    /// Discards the current edit without committing it. When there is nothing to discard
+    /// the selection is cleared instead, so a second cancel deselects the cell.
+    pub fn cancel(&mut self) {
+        self.mode = EditMode::Browsing;
+        if self.is_modified() {
+            self.editor_content.clone_from(&self.committed_content);
+        } else {
+            self.selection = None;
+            self.added.clear();
+            self.editor_content.clear();
+            self.committed_content.clear();
+        }
+    }
+
+    fn load(&mut self, idx: Index, spread_sheet: &SpreadSheet) {
+        self.committed_content = spread_sheet.get_raw(&idx).unwrap_or_default().to_owned();
+        self.editor_content.clone_from(&self.committed_content);
+        self.selection = Some((idx, idx));
+        self.added.clear();
+        self.mode = EditMode::Browsing;
+    }
+}
+
+/// Longest time in seconds between two clicks on a cell for them to make a double click.
+pub const DOUBLE_CLICK_TIME: f64 = 0.4;
+
+/// A click on a cell of the grid at a point in time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Click {
+    pub index: Index,
+    pub time: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClickKind {
+    Single,
+    Double,
+}
+
+/// Tells whether `click` completes a double click started by `previous`. Returns the kind of
+/// the click and the click to compare the next one against. A double click is not
+/// remembered, so a third click in a row starts over.
+pub fn classify_click(previous: Option<Click>, click: Click) -> (ClickKind, Option<Click>) {
+    match previous {
+        Some(previous)
+            if previous.index == click.index && click.time - previous.time <= DOUBLE_CLICK_TIME =>
+        {
+            (ClickKind::Double, None)
+        }
+        _ => (ClickKind::Single, Some(click)),
+    }
+}
+
+/// Moves `index` one step in `direction`, clamped to a grid of `(columns, rows)`.
+pub fn move_index(index: Index, direction: Direction, bounds: (usize, usize)) -> Index {
+    let (columns, rows) = bounds;
+    match direction {
+        Direction::Up => Index {
+            y: index.y.saturating_sub(1),
+            ..index
+        },
+        Direction::Down => Index {
+            y: (index.y + 1).min(rows.saturating_sub(1)),
+            ..index
+        },
+        Direction::Left => Index {
+            x: index.x.saturating_sub(1),
+            ..index
+        },
+        Direction::Right => Index {
+            x: (index.x + 1).min(columns.saturating_sub(1)),
+            ..index
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mini_spreadsheet::common_types::{ComputeError, Value};
+    use mini_spreadsheet::spreadsheet::validation::ValidationKind;
+
+    const BOUNDS: (usize, usize) = (6, 20);
+
+    #[test]
+    fn test_move_index() {
+        let index = Index { x: 2, y: 3 };
+
+        assert_eq!(
+            move_index(index, Direction::Up, BOUNDS),
+            Index { x: 2, y: 2 }
+        );
+        assert_eq!(
+            move_index(index, Direction::Down, BOUNDS),
+            Index { x: 2, y: 4 }
+        );
+        assert_eq!(
+            move_index(index, Direction::Left, BOUNDS),
+            Index { x: 1, y: 3 }
+        );
+        assert_eq!(
+            move_index(index, Direction::Right, BOUNDS),
+            Index { x: 3, y: 3 }
+        );
+    }
+
+    #[test]
+    fn test_move_index_clamps_to_grid() {
+        let top_left = Index { x: 0, y: 0 };
+        let bottom_right = Index { x: 5, y: 19 };
+
+        assert_eq!(move_index(top_left, Direction::Up, BOUNDS), top_left);
+        assert_eq!(move_index(top_left, Direction::Left, BOUNDS), top_left);
+        assert_eq!(
+            move_index(bottom_right, Direction::Down, BOUNDS),
+            bottom_right
+        );
+        assert_eq!(
+            move_index(bottom_right, Direction::Right, BOUNDS),
+            bottom_right
+        );
+    }
+
+    #[test]
+    fn test_enter_fills_a_column() {
+        let mut spread_sheet = SpreadSheet::default();
+        let mut state = EditState::default();
+        state.select(Index { x: 0, y: 0 }, &mut spread_sheet);
+
+        // Type a value and press Enter, three times
+        for value in ["1", "2", "=A1+A2"] {
+            state.editor_content = value.to_string();
+            state.commit_and_move(Direction::Down, BOUNDS, &mut spread_sheet);
+        }
+
+        assert_eq!(state.selected_cell(), Some(Index { x: 0, y: 3 }));
+        assert!(matches!(
+            spread_sheet.get_computed(Index { x: 0, y: 2 }),
+            Some(Ok(Value::Number(3.0)))
+        ));
+    }
+
+    #[test]
+    fn test_tab_and_shift_variants_reload_editor() {
+        let mut spread_sheet = SpreadSheet::default();
+        spread_sheet
+            .add_cell_and_compute(Index { x: 0, y: 0 }, "first".to_string())
+            .unwrap();
+        let mut state = EditState::default();
+        state.select(Index { x: 0, y: 0 }, &mut spread_sheet);
+
+        // Tab writes B1 and moves right, Shift+Tab comes back to B1
+        state.commit_and_move(Direction::Right, BOUNDS, &mut spread_sheet);
+        state.editor_content = "second".to_string();
+        state.commit_and_move(Direction::Right, BOUNDS, &mut spread_sheet);
+        state.commit_and_move(Direction::Left, BOUNDS, &mut spread_sheet);
+        assert_eq!(state.selected_cell(), Some(Index { x: 1, y: 0 }));
+        assert_eq!(state.editor_content, "second");
+
+        // Shift+Enter on the first row stays in place
+        state.commit_and_move(Direction::Up, BOUNDS, &mut spread_sheet);
+        assert_eq!(state.selected_cell(), Some(Index { x: 1, y: 0 }));
+        assert_eq!(state.editor_content, "second");
+    }
+
+    #[test]
+    fn test_commit_and_move_clamps_at_edge() {
+        let mut spread_sheet = SpreadSheet::default();
+        let mut state = EditState::default();
+        let last = Index { x: 5, y: 19 };
+        state.select(last, &mut spread_sheet);
+
+        state.editor_content = " 42 ".to_string();
+        state.commit_and_move(Direction::Down, BOUNDS, &mut spread_sheet);
+
+        assert_eq!(state.selected_cell(), Some(last));
+        assert_eq!(state.editor_content, "42");
+    }
+
+    #[test]
+    fn test_cancel_restores_committed_content() {
+        let mut spread_sheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        spread_sheet
+            .add_cell_and_compute(a1, "=1+1".to_string())
+            .unwrap();
+        let mut state = EditState::default();
+        state.select(a1, &mut spread_sheet);
+
+        state.editor_content = "=1+".to_string();
+        assert!(state.is_modified());
+        state.cancel();
+
+        assert_eq!(state.selected_cell(), Some(a1));
+        assert_eq!(state.editor_content, "=1+1");
+        assert!(!state.is_modified());
+        assert_eq!(spread_sheet.get_raw(&a1), Some("=1+1"));
+    }
+
+    #[test]
+    fn test_second_cancel_deselects() {
+        let mut spread_sheet = SpreadSheet::default();
+        let mut state = EditState::default();
+        state.select(Index { x: 0, y: 0 }, &mut spread_sheet);
+
+        state.editor_content = "typo".to_string();
+        state.cancel();
+        state.cancel();
+
+        assert_eq!(state.selected_cell(), None);
+        assert!(state.editor_content.is_empty());
+        assert!(spread_sheet.cells.is_empty());
+    }
+
+    #[test]
+    fn test_select_after_cancel_commits_nothing() {
+        let mut spread_sheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let b1 = Index { x: 1, y: 0 };
+        spread_sheet
+            .add_cell_and_compute(a1, "keep".to_string())
+            .unwrap();
+        let mut state = EditState::default();
+        state.select(a1, &mut spread_sheet);
+
+        state.editor_content = "discard".to_string();
+        state.cancel();
+        state.select(b1, &mut spread_sheet);
+
+        assert_eq!(state.selected_cell(), Some(b1));
+        assert_eq!(spread_sheet.get_raw(&a1), Some("keep"));
+    }
+
+    #[test]
+    fn test_transitions() {
+        use EditAction as A;
+        use EditKey as K;
+        use EditMode as M;
+
+        assert_eq!(
+            transition(M::Browsing, K::Delete),
+            (M::Browsing, A::ClearCell)
+        );
+        assert_eq!(
+            transition(M::Browsing, K::Backspace),
+            (M::Editing, A::ClearCell)
+        );
+        assert_eq!(
+            transition(M::Browsing, K::Printable),
+            (M::Editing, A::ReplaceContent)
+        );
+        assert_eq!(transition(M::Browsing, K::Escape), (M::Browsing, A::Cancel));
+
+        assert_eq!(transition(M::Editing, K::Delete), (M::Editing, A::None));
+        assert_eq!(transition(M::Editing, K::Backspace), (M::Editing, A::None));
+        assert_eq!(transition(M::Editing, K::Printable), (M::Editing, A::None));
+        assert_eq!(transition(M::Editing, K::Escape), (M::Browsing, A::Cancel));
+
+        assert_eq!(transition(M::Browsing, K::Undo), (M::Browsing, A::Undo));
+        assert_eq!(transition(M::Browsing, K::Redo), (M::Browsing, A::Redo));
+        assert_eq!(
+            transition(M::Editing, K::Undo),
+            (M::Browsing, A::DiscardEdit)
+        );
+        assert_eq!(transition(M::Editing, K::Redo), (M::Editing, A::None));
+    }
+
+    #[test]
+    fn test_undo_discards_edit_before_history() {
+        let mut spread_sheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        spread_sheet
+            .add_cell_and_compute(a1, "1".to_string())
+            .unwrap();
+        let mut state = EditState::default();
+        state.select(a1, &mut spread_sheet);
+
+        state.handle_key(EditKey::Printable, &mut spread_sheet);
+        state.editor_content = "2".to_string();
+        state.handle_key(EditKey::Undo, &mut spread_sheet);
+        assert_eq!(state.mode, EditMode::Browsing);
+        assert_eq!(state.editor_content, "1");
+        assert_eq!(spread_sheet.get_raw(&a1), Some("1"));
+
+        // Now the history is undone, adding the cell
+        state.handle_key(EditKey::Undo, &mut spread_sheet);
+        assert!(spread_sheet.get_raw(&a1).is_none());
+        assert_eq!(state.editor_content, "");
+        state.handle_key(EditKey::Redo, &mut spread_sheet);
+        assert_eq!(state.editor_content, "1");
+    }
+
+    #[test]
+    fn test_undo_selects_changed_cells() {
+        let mut spread_sheet = SpreadSheet::default();
+        let b2 = Index { x: 1, y: 1 };
+        let c4 = Index { x: 2, y: 3 };
+        spread_sheet
+            .add_cell_and_compute(b2, "x".to_string())
+            .unwrap();
+        let mut state = EditState::default();
+        state.select(b2, &mut spread_sheet);
+        state.extend_selection(c4, &mut spread_sheet);
+        state.handle_key(EditKey::Delete, &mut spread_sheet);
+        spread_sheet
+            .add_cell_and_compute(c4, "y".to_string())
+            .unwrap();
+        spread_sheet.undo();
+
+        // Without anything selected the history still works
+        state.selection = None;
+        state.handle_key(EditKey::Redo, &mut spread_sheet);
+        assert_eq!(state.selection, Some((c4, c4)));
+        assert_eq!(state.editor_content, "y");
+
+        state.handle_key(EditKey::Undo, &mut spread_sheet);
+        state.handle_key(EditKey::Undo, &mut spread_sheet);
+        assert_eq!(state.selection, Some((b2, b2)));
+        assert_eq!(state.editor_content, "x");
+    }
+
+    #[test]
+    fn test_delete_clears_cell_and_recomputes_dependants() {
+        let mut spread_sheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let a2 = Index { x: 0, y: 1 };
+        spread_sheet
+            .add_cell_and_compute(a1, "5".to_string())
+            .unwrap();
+        spread_sheet
+            .add_cell_and_compute(a2, "=A1*2".to_string())
+            .unwrap();
+        let mut state = EditState::default();
+        state.select(a1, &mut spread_sheet);
+
+        state.handle_key(EditKey::Delete, &mut spread_sheet);
+
+        assert_eq!(state.mode, EditMode::Browsing);
+        assert!(state.editor_content.is_empty());
+        assert!(spread_sheet.get_raw(&a1).is_none());
+        assert!(matches!(
+            spread_sheet.get_computed(a2),
+            Some(Err(ComputeError::UnfindableReference(_)))
+        ));
+    }
+
+    #[test]
+    fn test_backspace_clears_and_starts_editing() {
+        let mut spread_sheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        spread_sheet
+            .add_cell_and_compute(a1, "=1+2".to_string())
+            .unwrap();
+        let mut state = EditState::default();
+        state.select(a1, &mut spread_sheet);
+
+        state.handle_key(EditKey::Backspace, &mut spread_sheet);
+        assert_eq!(state.mode, EditMode::Editing);
+        assert!(spread_sheet.get_raw(&a1).is_none());
+
+        // Further keys while editing belong to the editor widget
+        state.editor_content = "7".to_string();
+        state.handle_key(EditKey::Backspace, &mut spread_sheet);
+        assert_eq!(state.editor_content, "7");
+
+        state.commit_and_move(Direction::Down, BOUNDS, &mut spread_sheet);
+        assert_eq!(state.mode, EditMode::Browsing);
+        assert_eq!(spread_sheet.get_raw(&a1), Some("7"));
+    }
+
+    #[test]
+    fn test_keys_without_selection_are_ignored() {
+        let mut spread_sheet = SpreadSheet::default();
+        let mut state = EditState::default();
+
+        state.handle_key(EditKey::Backspace, &mut spread_sheet);
+
+        assert_eq!(state.mode, EditMode::Browsing);
+        assert_eq!(state.selected_cell(), None);
+    }
+
+    #[test]
+    fn test_typing_replaces_content_until_committed() {
+        let mut spread_sheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        spread_sheet
+            .add_cell_and_compute(a1, "=1+2".to_string())
+            .unwrap();
+        let mut state = EditState::default();
+        state.select(a1, &mut spread_sheet);
+
+        state.handle_key(EditKey::Printable, &mut spread_sheet);
+        assert_eq!(state.mode, EditMode::Editing);
+        assert!(state.editor_content.is_empty());
+        assert_eq!(spread_sheet.get_raw(&a1), Some("=1+2"));
+
+        // Cancelling brings the formula back
+        state.handle_key(EditKey::Escape, &mut spread_sheet);
+        assert_eq!(state.editor_content, "=1+2");
+    }
+
+    #[test]
+    fn test_begin_edit_keeps_content() {
+        let mut spread_sheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        spread_sheet
+            .add_cell_and_compute(a1, "=1+2".to_string())
+            .unwrap();
+        let mut state = EditState::default();
+
+        state.begin_edit(&spread_sheet);
+        assert_eq!(state.mode, EditMode::Browsing);
+
+        state.select(a1, &mut spread_sheet);
+        state.begin_edit(&spread_sheet);
+        assert_eq!(state.mode, EditMode::Editing);
+        assert_eq!(state.editor_content, "=1+2");
+    }
+
+    #[test]
+    fn test_locked_cell_refuses_edits() {
+        let mut spread_sheet = SpreadSheet::default();
+        let (a1, b1) = (Index { x: 0, y: 0 }, Index { x: 1, y: 0 });
+        spread_sheet.set_cell(a1, "1".to_string()).unwrap();
+        spread_sheet.set_cell(b1, "2".to_string()).unwrap();
+        spread_sheet.set_locked((a1, a1), true);
+        spread_sheet.set_protection(true);
+        let mut state = EditState::default();
+        state.select(a1, &mut spread_sheet);
+
+        state.begin_edit(&spread_sheet);
+        assert_eq!(state.mode, EditMode::Browsing);
+        assert_eq!(state.rejection.as_deref(), Some("Cell A1 is locked"));
+        for key in [EditKey::Printable, EditKey::Backspace] {
+            state.handle_key(key, &mut spread_sheet);
+            assert_eq!(state.mode, EditMode::Browsing);
+        }
+        // Clearing a selection reaching a locked cell clears none of it
+        state.extend_selection(b1, &mut spread_sheet);
+        state.handle_key(EditKey::Delete, &mut spread_sheet);
+        assert_eq!(spread_sheet.get_raw(&b1), Some("2"));
+
+        // Written content is refused like content breaking a strict rule
+        state.write("5".to_string(), &mut spread_sheet);
+        assert_eq!(spread_sheet.get_raw(&a1), Some("1"));
+        assert_eq!(state.rejection.as_deref(), Some("Cell A1 is locked"));
+        state.select(b1, &mut spread_sheet);
+        state.write("3".to_string(), &mut spread_sheet);
+        assert_eq!(spread_sheet.get_raw(&b1), Some("3"));
+        assert_eq!(state.rejection, None);
+    }
+
+    #[test]
+    fn test_classify_click() {
+        let a1 = Index { x: 0, y: 0 };
+        let b1 = Index { x: 1, y: 0 };
+        let click = |index, time| Click { index, time };
+
+        let (kind, last) = classify_click(None, click(a1, 1.0));
+        assert_eq!(kind, ClickKind::Single);
+        assert_eq!(last, Some(click(a1, 1.0)));
+
+        // Quick second click on the same cell
+        let (kind, last) = classify_click(last, click(a1, 1.3));
+        assert_eq!(kind, ClickKind::Double);
+        assert_eq!(last, None);
+
+        // A third click starts over
+        let (kind, _) = classify_click(last, click(a1, 1.5));
+        assert_eq!(kind, ClickKind::Single);
+
+        // Too slow, or on another cell
+        let (kind, _) = classify_click(Some(click(a1, 1.0)), click(a1, 1.5));
+        assert_eq!(kind, ClickKind::Single);
+        let (kind, last) = classify_click(Some(click(a1, 1.0)), click(b1, 1.1));
+        assert_eq!(kind, ClickKind::Single);
+        assert_eq!(last, Some(click(b1, 1.1)));
+    }
+
+    #[test]
+    fn test_strict_validation_rejects_commit() {
+        let mut spread_sheet = SpreadSheet::default();
+        let (a1, a2) = (Index { x: 0, y: 0 }, Index { x: 0, y: 1 });
+        spread_sheet
+            .set_validation((a1, a2), ValidationKind::NumberBetween(0.0, 1.0), true)
+            .unwrap();
+        let mut state = EditState::default();
+        state.select(a1, &mut spread_sheet);
+
+        state.editor_content = "5".to_string();
+        state.commit_and_move(Direction::Down, BOUNDS, &mut spread_sheet);
+        assert!(spread_sheet.get_raw(&a1).is_none());
+        assert_eq!(
+            state.rejection.as_deref(),
+            Some("Expected a number from 0 to 1")
+        );
+
+        state.write("0.5".to_string(), &mut spread_sheet);
+        assert_eq!(spread_sheet.get_raw(&a2), Some("0.5"));
+        assert_eq!(state.rejection, None);
+
+        // A rule that is not strict only flags the value
+        spread_sheet
+            .set_validation((a1, a2), ValidationKind::NumberBetween(0.0, 1.0), false)
+            .unwrap();
+        state.write("5".to_string(), &mut spread_sheet);
+        assert_eq!(spread_sheet.get_raw(&a2), Some("5"));
+        assert_eq!(state.rejection, None);
+    }
+
+    #[test]
+    fn test_write_commits_and_reloads() {
+        let mut spread_sheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        spread_sheet
+            .add_cell_and_compute(a1, "old".to_string())
+            .unwrap();
+        let mut state = EditState::default();
+        state.select(a1, &mut spread_sheet);
+
+        state.write(" =1+1 ".to_string(), &mut spread_sheet);
+        assert_eq!(spread_sheet.get_raw(&a1), Some("=1+1"));
+        assert_eq!(state.editor_content, "=1+1");
+        assert!(!state.is_modified());
+
+        state.write(String::new(), &mut spread_sheet);
+        assert!(spread_sheet.get_raw(&a1).is_none());
+    }
+
+    #[test]
+    fn test_extend_selection_keeps_anchor() {
+        let mut spread_sheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let c3 = Index { x: 2, y: 2 };
+        let mut state = EditState::default();
+
+        state.select(a1, &mut spread_sheet);
+        state.editor_content = "5".to_string();
+        state.mode = EditMode::Editing;
+        state.extend_selection(c3, &mut spread_sheet);
+
+        assert_eq!(state.selection, Some((a1, c3)));
+        assert_eq!(state.selected_cell(), Some(a1));
+        assert_eq!(state.selection_focus(), Some(c3));
+        assert_eq!(state.mode, EditMode::Browsing);
+        assert_eq!(spread_sheet.get_raw(&a1), Some("5"));
+
+        // Clicking the anchor again collapses the selection
+        state.select(a1, &mut spread_sheet);
+        assert_eq!(state.selection, Some((a1, a1)));
+    }
+
+    #[test]
+    fn test_reload_keeps_selection() {
+        let mut spread_sheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let b2 = Index { x: 1, y: 1 };
+        let mut state = EditState::default();
+
+        state.select(a1, &mut spread_sheet);
+        state.extend_selection(b2, &mut spread_sheet);
+        spread_sheet
+            .add_cell_and_compute(a1, "7".to_string())
+            .unwrap();
+        state.reload(&spread_sheet);
+
+        assert_eq!(state.selection, Some((a1, b2)));
+        assert_eq!(state.editor_content, "7");
+        assert!(!state.is_modified());
+    }
+
+    #[test]
+    fn test_delete_clears_whole_selection() {
+        let mut spread_sheet = SpreadSheet::default();
+        let inside = [Index { x: 1, y: 1 }, Index { x: 2, y: 3 }];
+        let outside = Index { x: 3, y: 1 };
+        for idx in inside.iter().chain([&outside]) {
+            spread_sheet
+                .add_cell_and_compute(*idx, "1".to_string())
+                .unwrap();
+        }
+        let mut state = EditState::default();
+
+        state.select(Index { x: 2, y: 3 }, &mut spread_sheet);
+        state.extend_selection(Index { x: 1, y: 0 }, &mut spread_sheet);
+        state.handle_key(EditKey::Delete, &mut spread_sheet);
+
+        assert!(inside.iter().all(|idx| spread_sheet.get_raw(idx).is_none()));
+        assert_eq!(spread_sheet.get_raw(&outside), Some("1"));
+    }
+
+    #[test]
+    fn test_added_rectangles() {
+        let mut spread_sheet = SpreadSheet::default();
+        let cells = [
+            Index { x: 0, y: 0 },
+            Index { x: 2, y: 2 },
+            Index { x: 3, y: 2 },
+            Index { x: 1, y: 1 },
+        ];
+        for idx in cells {
+            spread_sheet
+                .add_cell_and_compute(idx, "1".to_string())
+                .unwrap();
+        }
+        let mut state = EditState::default();
+
+        state.select(cells[0], &mut spread_sheet);
+        state.add_to_selection(cells[1], &mut spread_sheet);
+        state.extend_selection(cells[2], &mut spread_sheet);
+        assert_eq!(
+            state.selected_ranges().ranges(),
+            [(cells[0], cells[0]), (cells[1], cells[2])]
+        );
+        // Reloading keeps them
+        state.reload(&spread_sheet);
+        assert_eq!(state.selected_ranges().ranges().len(), 2);
+
+        // Deleting empties all of them, in one step
+        state.handle_key(EditKey::Delete, &mut spread_sheet);
+        assert_eq!(spread_sheet.cells.len(), 1);
+        state.handle_key(EditKey::Undo, &mut spread_sheet);
+        assert_eq!(spread_sheet.cells.len(), 4);
+
+        // Selecting another cell drops them
+        state.add_to_selection(cells[3], &mut spread_sheet);
+        state.select(cells[0], &mut spread_sheet);
+        assert!(state.added.is_empty());
+        assert_eq!(state.selected_ranges().ranges(), [(cells[0], cells[0])]);
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use mini_spreadsheet::common_types::Index;
+
+use super::dropdown::Dropdown;
+
+/// Which values of a column the rows kept by a `RowFilter` have.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnFilter {
+    /// The text shown in the cell is exactly this one.
+    Equals(String),
+    /// The text shown in the cell contains this one, whatever the case.
+    Contains(String),
+}
+
+impl ColumnFilter {
+    pub fn matches(&self, text: &str) -> bool {
+        match self {
+            ColumnFilter::Equals(value) => text == value,
+            ColumnFilter::Contains(part) => text.to_lowercase().contains(&part.to_lowercase()),
+        }
+    }
+}
+
+/// Filters on the columns of the sheet, hiding the rows of its data whose values do not
+/// match them. Only what is shown changes, the rows keep their content and their numbers.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RowFilter {
+    columns: BTreeMap<usize, ColumnFilter>,
+}
+
+impl RowFilter {
+    /// Filters `column` with `filter`, `None` showing all of its values again.
+    pub fn set(&mut self, column: usize, filter: Option<ColumnFilter>) {
+        match filter {
+            Some(filter) => self.columns.insert(column, filter),
+            None => self.columns.remove(&column),
+        };
+    }
+
+    pub fn get(&self, column: usize) -> Option<&ColumnFilter> {
+        self.columns.get(&column)
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.columns.is_empty()
+    }
+
+    /// Returns the rows of `rows` hidden because the text `text_of` gives for one of their
+    /// cells does not match the filter of its column.
+    pub fn hidden_rows(
+        &self,
+        rows: RangeInclusive<usize>,
+        text_of: impl Fn(Index) -> String,
+    ) -> HashSet<usize> {
+        rows.filter(|&y| {
+            self.columns
+                .iter()
+                .any(|(&x, filter)| !filter.matches(&text_of(Index { x, y })))
+        })
+        .collect()
+    }
+}
+
+/// Returns every distinct text of `texts` once, sorted whatever the case.
+pub fn distinct_values(texts: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut values: Vec<String> = texts.into_iter().collect();
+    values.sort_by(|a, b| a.to_lowercase().cmp(&b.to_lowercase()).then(a.cmp(b)));
+    values.dedup();
+    values
+}
+
+/// An item of a `FilterPicker`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterChoice {
+    /// Shows every row again.
+    All,
+    Contains(String),
+    Value(String),
+}
+
+impl FilterChoice {
+    /// Returns the filter the choice sets on its column.
+    pub fn filter(self) -> Option<ColumnFilter> {
+        match self {
+            FilterChoice::All => None,
+            FilterChoice::Contains(part) => Some(ColumnFilter::Contains(part)),
+            FilterChoice::Value(value) => Some(ColumnFilter::Equals(value)),
+        }
+    }
+}
+
+impl fmt::Display for FilterChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterChoice::All => write!(f, "(All)"),
+            FilterChoice::Contains(part) => write!(f, "Contains \"{part}\""),
+            FilterChoice::Value(value) if value.is_empty() => write!(f, "(Empty)"),
+            FilterChoice::Value(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+/// The popup picking the filter of a column, listing its distinct values. Typing narrows them
+/// to those containing what was typed, which can also be kept as the filter itself.
+#[derive(Debug, Clone)]
+pub struct FilterPicker {
+    pub column: usize,
+    /// The top left corner of the popup on screen.
+    pub pos: (f32, f32),
+    pub query: String,
+    pub list: Dropdown<FilterChoice>,
+    values: Vec<String>,
+}
+
+impl FilterPicker {
+    pub fn new(column: usize, pos: (f32, f32), values: Vec<String>) -> Self {
+        let mut picker = Self {
+            column,
+            pos,
+            query: String::new(),
+            list: Dropdown::new(Vec::new()),
+            values,
+        };
+        picker.refresh();
+        picker
+    }
+
+    /// Adds `text` to the query.
+    pub fn type_text(&mut self, text: &str) {
+        self.query.push_str(text);
+        self.refresh();
+    }
+
+    /// Removes the last character of the query.
+    pub fn backspace(&mut self) {
+        self.query.pop();
+        self.refresh();
+    }
+
+    fn refresh(&mut self) {
+        let query = self.query.to_lowercase();
+        let mut items = vec![FilterChoice::All];
+        if !query.is_empty() {
+            items.push(FilterChoice::Contains(self.query.clone()));
+        }
+        items.extend(
+            self.values
+                .iter()
+                .filter(|value| value.to_lowercase().contains(&query))
+                .map(|value| FilterChoice::Value(value.clone())),
+        );
+        self.list = Dropdown::new(items);
+        // What was typed is what is most likely wanted
+        if !query.is_empty() {
+            self.list.move_highlight(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hidden_rows() {
+        // Rows 1 to 4 of columns A and B, under a header in row 0
+        let data = [
+            ["fruit", "red"],
+            ["Fig", "green"],
+            ["figs", "red"],
+            ["", "red"],
+        ];
+        let text_of = |index: Index| data[index.y - 1][index.x].to_string();
+        let mut filter = RowFilter::default();
+        assert!(filter.hidden_rows(1..=4, text_of).is_empty());
+
+        filter.set(1, Some(ColumnFilter::Equals("red".to_string())));
+        assert_eq!(filter.hidden_rows(1..=4, text_of), HashSet::from([2]));
+        // Rows are kept only when every filter matches
+        filter.set(0, Some(ColumnFilter::Contains("FIG".to_string())));
+        assert_eq!(filter.hidden_rows(1..=4, text_of), HashSet::from([1, 2, 4]));
+
+        filter.set(1, None);
+        assert_eq!(filter.hidden_rows(1..=4, text_of), HashSet::from([1, 4]));
+        filter.set(0, None);
+        assert!(!filter.is_active());
+    }
+
+    #[test]
+    fn test_distinct_values() {
+        let texts = ["b", "A", "b", "", "a", "10", "A"].map(String::from);
+        assert_eq!(distinct_values(texts), vec!["", "10", "A", "a", "b"]);
+    }
+
+    #[test]
+    fn test_picker_narrows_to_query() {
+        let values = distinct_values(["Fig", "Apple", "fig tree", "Pear"].map(String::from));
+        let mut picker = FilterPicker::new(2, (0.0, 0.0), values);
+        assert_eq!(picker.list.items().len(), 5);
+        assert_eq!(picker.list.items()[0], FilterChoice::All);
+
+        picker.type_text("fI");
+        assert_eq!(
+            picker.list.items(),
+            [
+                FilterChoice::All,
+                FilterChoice::Contains("fI".to_string()),
+                FilterChoice::Value("Fig".to_string()),
+                FilterChoice::Value("fig tree".to_string()),
+            ]
+        );
+        assert_eq!(picker.list.highlighted(), 1);
+        assert_eq!(
+            FilterChoice::Contains("fI".to_string()).filter(),
+            Some(ColumnFilter::Contains("fI".to_string()))
+        );
+
+        picker.backspace();
+        picker.backspace();
+        assert_eq!(picker.list.items().len(), 5);
+        assert_eq!(FilterChoice::Value(String::new()).to_string(), "(Empty)");
+    }
+}
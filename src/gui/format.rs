@@ -0,0 +1,75 @@
+use mini_spreadsheet::common_types::{ComputeError, Value};
+
+/// Space kept between aligned text and the side of its cell.
+pub const CELL_TEXT_PADDING: f32 = 5.;
+
+/// Where the text of a cell is placed horizontally.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Alignment {
+    Left,
+    Center,
+    Right,
+}
+
+/// How a cell is displayed. Anything left unset is decided from the value of the cell.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CellFormat {
+    pub alignment: Option<Alignment>,
+    /// Whether text wider than the column continues on more lines, growing the row.
+    pub wrap: bool,
+}
+
+/// Returns the alignment of a cell without one set: numbers to the right, text to the left,
+/// booleans and errors centered.
+pub fn auto_alignment(computed: Option<&Result<Value, ComputeError>>) -> Alignment {
+    match computed {
+        Some(Ok(Value::Number(_))) => Alignment::Right,
+        Some(Ok(Value::Bool(_)) | Err(_)) => Alignment::Center,
+        Some(Ok(Value::Text(_) | Value::Link { .. } | Value::Sparkline(_))) | None => {
+            Alignment::Left
+        }
+        Some(Ok(array @ Value::Array(_))) => auto_alignment(Some(&Ok(array.clone().into_shown()))),
+    }
+}
+
+/// Returns the x position to draw text of `text_width` at in a cell starting at `cell_x`.
+pub fn aligned_text_x(alignment: Alignment, cell_x: f32, cell_width: f32, text_width: f32) -> f32 {
+    match alignment {
+        Alignment::Left => cell_x + CELL_TEXT_PADDING,
+        Alignment::Center => cell_x + (cell_width - text_width) / 2.0,
+        Alignment::Right => cell_x + cell_width - CELL_TEXT_PADDING - text_width,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_alignment() {
+        assert_eq!(
+            auto_alignment(Some(&Ok(Value::Number(1.5)))),
+            Alignment::Right
+        );
+        assert_eq!(
+            auto_alignment(Some(&Ok(Value::Text("a".to_string())))),
+            Alignment::Left
+        );
+        assert_eq!(
+            auto_alignment(Some(&Ok(Value::Bool(true)))),
+            Alignment::Center
+        );
+        assert_eq!(
+            auto_alignment(Some(&Err(ComputeError::Cycle))),
+            Alignment::Center
+        );
+        assert_eq!(auto_alignment(None), Alignment::Left);
+    }
+
+    #[test]
+    fn test_aligned_text_x() {
+        assert_eq!(aligned_text_x(Alignment::Left, 100., 80., 30.), 105.);
+        assert_eq!(aligned_text_x(Alignment::Center, 100., 80., 30.), 125.);
+        assert_eq!(aligned_text_x(Alignment::Right, 100., 80., 30.), 145.);
+    }
+}
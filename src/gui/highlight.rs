@@ -0,0 +1,231 @@
+use std::ops::Range;
+
+use mini_spreadsheet::common_types::{Reference, Token};
+use mini_spreadsheet::spreadsheet::SpreadSheet;
+
+/// The kinds of text a formula is colored by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Highlight {
+    /// Operators, parentheses and anything that does not tokenize.
+    Plain,
+    /// A cell or range, with the position of the reference in `distinct_references`.
+    Reference(usize),
+    Number,
+    Text,
+    Function,
+}
+
+/// Returns the highlight of a token. Cell names are left plain, as references are colored
+/// together with the range they may be part of.
+pub fn token_highlight(token: &Token) -> Highlight {
+    match token {
+        Token::Number(_) => Highlight::Number,
+        Token::StringLiteral(_) => Highlight::Text,
+        Token::FunctionName(_) => Highlight::Function,
+        _ => Highlight::Plain,
+    }
+}
+
+/// Splits `content` into byte ranges to color. The ranges cover the whole content in order;
+/// anything that is not a formula, and the part of a formula after its first invalid token,
+/// is plain.
+pub fn highlight_spans(content: &str) -> Vec<(Range<usize>, Highlight)> {
+    let distinct = distinct_references(content);
+    let mut highlighted: Vec<(Range<usize>, Highlight)> = formula_tokens(content)
+        .into_iter()
+        .map(|(token, range)| (range, token_highlight(&token)))
+        .filter(|(_, highlight)| *highlight != Highlight::Plain)
+        .chain(
+            formula_references(content)
+                .into_iter()
+                .map(|(reference, range)| {
+                    let slot = distinct
+                        .iter()
+                        .position(|&r| r == reference)
+                        .unwrap_or_default();
+                    (range, Highlight::Reference(slot))
+                }),
+        )
+        .collect();
+    highlighted.sort_by_key(|(range, _)| range.start);
+
+    let mut spans = Vec::new();
+    let mut end = 0;
+    for (range, highlight) in highlighted {
+        if end < range.start {
+            spans.push((end..range.start, Highlight::Plain));
+        }
+        end = range.end;
+        spans.push((range, highlight));
+    }
+    if end < content.len() {
+        spans.push((end..content.len(), Highlight::Plain));
+    }
+    spans
+}
+
+/// Returns the references of `content` in the order they are first written, each once. The
+/// position of a reference in this list picks its color, in the text and on the grid alike.
+pub fn distinct_references(content: &str) -> Vec<Reference> {
+    let mut distinct = Vec::new();
+    for (reference, _) in formula_references(content) {
+        if !distinct.contains(&reference) {
+            distinct.push(reference);
+        }
+    }
+    distinct
+}
+
+/// Returns the byte range of the reference `caret` is in or right after, a range like
+/// `A1:B2` as a whole.
+pub fn reference_at(content: &str, caret: usize) -> Option<Range<usize>> {
+    formula_references(content)
+        .into_iter()
+        .map(|(_, range)| range)
+        .find(|range| range.start <= caret && caret <= range.end)
+}
+
+/// Returns the byte offsets of the parenthesis right before or after `caret` and the one
+/// matching it, the one before the caret taking precedence. Parentheses inside strings do
+/// not count.
+pub fn matching_paren(content: &str, caret: usize) -> Option<(usize, usize)> {
+    let parens: Vec<(bool, usize)> = formula_tokens(content)
+        .into_iter()
+        .filter_map(|(token, range)| match token {
+            Token::LParen => Some((true, range.start)),
+            Token::RParen => Some((false, range.start)),
+            _ => None,
+        })
+        .collect();
+
+    let adjacent = parens
+        .iter()
+        .position(|&(_, offset)| offset + 1 == caret)
+        .or_else(|| parens.iter().position(|&(_, offset)| offset == caret))?;
+
+    let (opening, offset) = parens[adjacent];
+    let mut depth = 0;
+    let mut closes = |&&(is_opening, _): &&(bool, usize)| {
+        depth += if is_opening == opening { 1 } else { -1 };
+        depth == 0
+    };
+    let matching = if opening {
+        parens[adjacent..].iter().find(&mut closes)
+    } else {
+        parens[..=adjacent].iter().rev().find(&mut closes)
+    };
+    matching.map(|&(_, other)| (offset, other))
+}
+
+// The tokens of `content` with ranges into it, allowing whitespace before the `=`
+fn formula_tokens(content: &str) -> Vec<(Token, Range<usize>)> {
+    let formula = content.trim_start();
+    let start = content.len() - formula.len();
+    SpreadSheet::formula_tokens(formula)
+        .into_iter()
+        .map(|(token, range)| (token, range.start + start..range.end + start))
+        .collect()
+}
+
+fn formula_references(content: &str) -> Vec<(Reference, Range<usize>)> {
+    let formula = content.trim_start();
+    let start = content.len() - formula.len();
+    SpreadSheet::formula_references(formula)
+        .into_iter()
+        .map(|(reference, range)| (reference, range.start + start..range.end + start))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mini_spreadsheet::common_types::Index;
+
+    fn colored(content: &str) -> Vec<(&str, Highlight)> {
+        highlight_spans(content)
+            .into_iter()
+            .map(|(range, highlight)| (&content[range], highlight))
+            .collect()
+    }
+
+    #[test]
+    fn test_highlight_spans() {
+        assert_eq!(
+            colored("=sum(A1:B2, 3) & \"x\""),
+            vec![
+                ("=", Highlight::Plain),
+                ("sum", Highlight::Function),
+                ("(", Highlight::Plain),
+                ("A1:B2", Highlight::Reference(0)),
+                (", ", Highlight::Plain),
+                ("3", Highlight::Number),
+                (") & \"x\"", Highlight::Plain),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_references_share_colors() {
+        assert_eq!(
+            colored("=A1+B7*A1"),
+            vec![
+                ("=", Highlight::Plain),
+                ("A1", Highlight::Reference(0)),
+                ("+", Highlight::Plain),
+                ("B7", Highlight::Reference(1)),
+                ("*", Highlight::Plain),
+                ("A1", Highlight::Reference(0)),
+            ]
+        );
+
+        let a1 = Index { x: 0, y: 0 };
+        let b7 = Index { x: 1, y: 6 };
+        assert_eq!(
+            distinct_references("=A1+B7*A1 + sum(A1:B7)"),
+            vec![
+                Reference::Cell(a1),
+                Reference::Cell(b7),
+                Reference::Range(a1, b7)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_highlight_half_typed_formula() {
+        assert_eq!(
+            colored(" =A1 * \"unterminated"),
+            vec![
+                (" =", Highlight::Plain),
+                ("A1", Highlight::Reference(0)),
+                (" * \"unterminated", Highlight::Plain),
+            ]
+        );
+        assert_eq!(colored("A1 + 2"), vec![("A1 + 2", Highlight::Plain)]);
+        assert!(colored("").is_empty());
+    }
+
+    #[test]
+    fn test_matching_paren() {
+        let formula = "=max(1, (A1), \")\")";
+
+        // Right after the opening paren of max and right before its closing paren
+        assert_eq!(matching_paren(formula, 5), Some((4, 17)));
+        assert_eq!(matching_paren(formula, 17), Some((17, 4)));
+        // Between two parens the one before the caret wins
+        assert_eq!(matching_paren(formula, 12), Some((11, 8)));
+
+        assert_eq!(matching_paren(formula, 2), None);
+        assert_eq!(matching_paren("=max(1", 5), None);
+        assert_eq!(matching_paren("(1)", 1), None);
+    }
+
+    #[test]
+    fn test_reference_at() {
+        let formula = " =A1+sum($B$2:C3)";
+        assert_eq!(reference_at(formula, 2), Some(2..4));
+        assert_eq!(reference_at(formula, 4), Some(2..4));
+        assert_eq!(reference_at(formula, 12), Some(9..16));
+        assert_eq!(reference_at(formula, 6), None);
+        assert_eq!(reference_at("A1", 1), None);
+    }
+}
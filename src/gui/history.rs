@@ -0,0 +1,72 @@
+use std::time::SystemTime;
+
+use mini_spreadsheet::spreadsheet::journal::ChangeRecord;
+
+/// Describes how long before `now` a change made at `time` was, like "5 min ago".
+pub fn time_ago(time: SystemTime, now: SystemTime) -> String {
+    let seconds = now.duration_since(time).unwrap_or_default().as_secs();
+    match seconds {
+        0..=9 => "just now".to_string(),
+        10..=59 => format!("{seconds} s ago"),
+        60..=3599 => format!("{} min ago", seconds / 60),
+        3600..=86_399 => format!("{} h ago", seconds / 3600),
+        _ => format!("{} d ago", seconds / 86_400),
+    }
+}
+
+/// Returns a line for each of the changes of a cell, newest first, each saying when the
+/// change was made and what the content was before and after it.
+pub fn history_lines(records: &[ChangeRecord], now: SystemTime) -> Vec<String> {
+    let shown = |raw: &str| match raw {
+        "" => "(empty)".to_string(),
+        raw => raw.to_string(),
+    };
+    records
+        .iter()
+        .rev()
+        .map(|record| {
+            format!(
+                "{:<11} {} -> {}",
+                time_ago(record.time, now),
+                shown(&record.old_raw),
+                shown(&record.new_raw)
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use mini_spreadsheet::common_types::Index;
+
+    #[test]
+    fn test_history_lines() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let record = |seconds_ago, old_raw: &str, new_raw: &str| ChangeRecord {
+            time: now - Duration::from_secs(seconds_ago),
+            index: Index { x: 0, y: 0 },
+            old_raw: old_raw.to_string(),
+            new_raw: new_raw.to_string(),
+        };
+        let records = [
+            record(200_000, "", "1"),
+            record(7200, "1", "=B1 * 2"),
+            record(125, "=B1 * 2", ""),
+            record(30, "", "x"),
+            record(2, "x", "y"),
+        ];
+        assert_eq!(
+            history_lines(&records, now),
+            [
+                "just now    x -> y",
+                "30 s ago    (empty) -> x",
+                "2 min ago   =B1 * 2 -> (empty)",
+                "2 h ago     1 -> =B1 * 2",
+                "2 d ago     (empty) -> 1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::{HashMap, HashSet};
+
+/// Sizes of the columns (or rows) along one axis of the grid, where every line has the
+/// default size unless it was resized. A line also grows to the size its content needs, and
+/// hidden lines take no room at all.
+#[derive(Debug, Clone)]
+pub struct AxisLayout {
+    default_size: f32,
+    min_size: f32,
+    sizes: HashMap<usize, f32>,
+    content_sizes: HashMap<usize, f32>,
+    hidden: HashSet<usize>,
+}
+
+impl AxisLayout {
+    pub fn new(default_size: f32, min_size: f32) -> Self {
+        Self {
+            default_size,
+            min_size,
+            sizes: HashMap::new(),
+            content_sizes: HashMap::new(),
+            hidden: HashSet::new(),
+        }
+    }
+
+    pub fn size(&self, idx: usize) -> f32 {
+        if self.hidden.contains(&idx) {
+            return 0.0;
+        }
+        let size = self.sizes.get(&idx).copied().unwrap_or(self.default_size);
+        self.content_sizes
+            .get(&idx)
+            .map_or(size, |&content| size.max(content))
+    }
+
+    /// Sets the sizes the contents of lines need, replacing the previous ones. Smaller lines
+    /// grow to these, larger ones keep their size.
+    pub fn set_content_sizes(&mut self, content_sizes: HashMap<usize, f32>) {
+        self.content_sizes = content_sizes;
+    }
+
+    /// Hides the `hidden` lines, replacing those hidden before.
+    pub fn set_hidden(&mut self, hidden: HashSet<usize>) {
+        self.hidden = hidden;
+    }
+
+    pub fn is_hidden(&self, idx: usize) -> bool {
+        self.hidden.contains(&idx)
+    }
+
+    /// Returns the lines hidden right before and right after `idx`, those its marker stands
+    /// for, of those `hidden_by_hand` tells were hidden rather than filtered.
+    pub fn hidden_next_to(&self, idx: usize, hidden_by_hand: impl Fn(usize) -> bool) -> Vec<usize> {
+        let hidden = |line: &usize| self.is_hidden(*line) && hidden_by_hand(*line);
+        let before = (0..idx).rev().take_while(hidden);
+        let after = (idx + 1..).take_while(hidden);
+        let mut lines: Vec<usize> = before.chain(after).collect();
+        lines.sort_unstable();
+        lines
+    }
+
+    /// Returns the line `steps` shown lines away from `from`, passing over hidden ones. Going
+    /// back stops at the first shown line.
+    pub fn advance(&self, from: usize, steps: isize) -> usize {
+        let mut idx = from;
+        for _ in 0..steps.unsigned_abs() {
+            let mut next = idx;
+            loop {
+                next = match steps < 0 {
+                    true => match next.checked_sub(1) {
+                        Some(previous) => previous,
+                        None => return idx,
+                    },
+                    false => next + 1,
+                };
+                if !self.is_hidden(next) {
+                    break;
+                }
+            }
+            idx = next;
+        }
+        idx
+    }
+
+    /// Returns the sizes of the lines resized, see `set_size`.
+    pub fn sizes(&self) -> &HashMap<usize, f32> {
+        &self.sizes
+    }
+
+    /// Resizes the `sizes` lines like `set_size`, the others going back to the default size.
+    pub fn set_sizes(&mut self, sizes: impl IntoIterator<Item = (usize, f32)>) {
+        self.sizes.clear();
+        for (idx, size) in sizes {
+            self.set_size(idx, size);
+        }
+    }
+
+    /// Resizes a line, never making it smaller than the minimum size.
+    pub fn set_size(&mut self, idx: usize, size: f32) {
+        self.sizes.insert(idx, size.max(self.min_size));
+    }
+
+    /// Returns the distance from the start of `first` to the start of `idx`.
+    pub fn offset_of(&self, first: usize, idx: usize) -> f32 {
+        (first..idx).map(|i| self.size(i)).sum()
+    }
+
+    /// Returns the line containing `offset`, measured from the start of `first`.
+    pub fn index_at(&self, first: usize, offset: f32) -> Option<usize> {
+        if offset < 0.0 {
+            return None;
+        }
+
+        let mut idx = first;
+        let mut end = self.size(idx);
+        while end <= offset {
+            idx += 1;
+            end += self.size(idx);
+        }
+        Some(idx)
+    }
+
+    /// Returns how many whole lines starting at `first` fit into `available`.
+    pub fn fitting(&self, first: usize, available: f32) -> usize {
+        let mut count = 0;
+        let mut used = self.size(first);
+        while used <= available {
+            count += 1;
+            used += self.size(first + count);
+        }
+        count
+    }
+
+    /// Returns how many lines starting at `first` begin within `available`, the last of them
+    /// possibly cut off at its end.
+    pub fn starting_within(&self, first: usize, available: f32) -> usize {
+        let mut count = 0;
+        let mut start = 0.0;
+        while start < available {
+            start += self.size(first + count);
+            count += 1;
+        }
+        count
+    }
+
+    /// Returns the line whose end lies within `tolerance` of `offset`, measured from the
+    /// start of `first`. This is the line resized when dragging that boundary.
+    pub fn boundary_at(&self, first: usize, offset: f32, tolerance: f32) -> Option<usize> {
+        let mut idx = first;
+        let mut end = self.size(idx);
+        while end <= offset + tolerance {
+            if (offset - end).abs() <= tolerance {
+                return Some(idx);
+            }
+            idx += 1;
+            end += self.size(idx);
+        }
+        None
+    }
+
+    /// Returns the first line to show so that `target` is the last line fully inside `available`.
+    pub fn first_showing_last(&self, target: usize, available: f32) -> usize {
+        let mut first = target;
+        let mut used = self.size(target);
+        while first > 0 && used + self.size(first - 1) <= available {
+            first -= 1;
+            used += self.size(first);
+        }
+        first
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout() -> AxisLayout {
+        // Columns of 100 except for B which is 50 and D which is 200
+        let mut layout = AxisLayout::new(100.0, 20.0);
+        layout.set_size(1, 50.0);
+        layout.set_size(3, 200.0);
+        layout
+    }
+
+    #[test]
+    fn test_set_size_respects_minimum() {
+        let mut layout = layout();
+        layout.set_size(0, 5.0);
+        assert_eq!(layout.size(0), 20.0);
+        assert_eq!(layout.size(7), 100.0);
+    }
+
+    #[test]
+    fn test_index_at_is_cumulative() {
+        let layout = layout();
+
+        assert_eq!(layout.index_at(0, -1.0), None);
+        assert_eq!(layout.index_at(0, 0.0), Some(0));
+        assert_eq!(layout.index_at(0, 99.9), Some(0));
+        assert_eq!(layout.index_at(0, 100.0), Some(1));
+        assert_eq!(layout.index_at(0, 149.0), Some(1));
+        assert_eq!(layout.index_at(0, 150.0), Some(2));
+        assert_eq!(layout.index_at(0, 449.0), Some(3));
+        assert_eq!(layout.index_at(0, 450.0), Some(4));
+
+        // Starting from a scrolled position
+        assert_eq!(layout.index_at(1, 49.0), Some(1));
+        assert_eq!(layout.index_at(1, 160.0), Some(3));
+    }
+
+    #[test]
+    fn test_content_sizes_grow_lines() {
+        let mut layout = layout();
+        layout.set_content_sizes(HashMap::from([(0, 160.0), (1, 40.0), (2, 70.0)]));
+        assert_eq!(layout.size(0), 160.0);
+        assert_eq!(layout.size(1), 50.0);
+        assert_eq!(layout.size(2), 100.0);
+        assert_eq!(layout.offset_of(0, 4), 510.0);
+
+        // Content sizes are replaced as a whole
+        layout.set_content_sizes(HashMap::new());
+        assert_eq!(layout.size(0), 100.0);
+    }
+
+    #[test]
+    fn test_offset_of() {
+        let layout = layout();
+        assert_eq!(layout.offset_of(0, 0), 0.0);
+        assert_eq!(layout.offset_of(0, 3), 250.0);
+        assert_eq!(layout.offset_of(2, 4), 300.0);
+    }
+
+    #[test]
+    fn test_fitting() {
+        let layout = layout();
+        assert_eq!(layout.fitting(0, 449.0), 3);
+        assert_eq!(layout.fitting(0, 450.0), 4);
+        assert_eq!(layout.fitting(3, 150.0), 0);
+    }
+
+    #[test]
+    fn test_starting_within() {
+        let layout = layout();
+        assert_eq!(layout.starting_within(0, 0.0), 0);
+        assert_eq!(layout.starting_within(0, 100.0), 1);
+        assert_eq!(layout.starting_within(0, 101.0), 2);
+        assert_eq!(layout.starting_within(0, 450.0), 4);
+        assert_eq!(layout.starting_within(3, 150.0), 1);
+    }
+
+    #[test]
+    fn test_boundary_at() {
+        let layout = layout();
+        assert_eq!(layout.boundary_at(0, 102.0, 4.0), Some(0));
+        assert_eq!(layout.boundary_at(0, 148.0, 4.0), Some(1));
+        assert_eq!(layout.boundary_at(0, 120.0, 4.0), None);
+        assert_eq!(layout.boundary_at(2, 98.0, 4.0), Some(2));
+    }
+
+    #[test]
+    fn test_hidden_lines_take_no_room() {
+        let mut layout = layout();
+        layout.set_hidden(HashSet::from([1, 2, 5]));
+        assert_eq!(layout.size(1), 0.0);
+        assert_eq!(layout.offset_of(0, 4), 300.0);
+        assert_eq!(layout.index_at(0, 99.0), Some(0));
+        assert_eq!(layout.index_at(0, 100.0), Some(3));
+        assert_eq!(layout.index_at(0, 300.0), Some(4));
+        assert_eq!(layout.index_at(0, 400.0), Some(6));
+
+        assert_eq!(layout.advance(0, 1), 3);
+        assert_eq!(layout.advance(0, 3), 6);
+        assert_eq!(layout.advance(6, -2), 3);
+        assert_eq!(layout.advance(3, -5), 0);
+        // Nothing shows before a hidden first line
+        layout.set_hidden(HashSet::from([0]));
+        assert_eq!(layout.advance(1, -1), 1);
+    }
+
+    #[test]
+    fn test_lines_hidden_by_hand() {
+        // Lines hidden by hand are passed over like those the filter hides
+        let mut layout = layout();
+        layout.set_hidden(HashSet::from([1, 2, 4, 6]));
+        assert_eq!(layout.advance(0, 1), 3);
+        assert_eq!(layout.advance(3, 1), 5);
+        assert_eq!(layout.advance(5, -2), 0);
+
+        // Only those hidden by hand show again, and only those next to the line
+        let by_hand = |idx| [1, 2, 6].contains(&idx);
+        assert_eq!(layout.hidden_next_to(3, by_hand), [1, 2]);
+        assert_eq!(layout.hidden_next_to(0, by_hand), [1, 2]);
+        assert_eq!(layout.hidden_next_to(5, by_hand), [6]);
+        assert_eq!(layout.hidden_next_to(7, by_hand), [6]);
+        assert!(layout.hidden_next_to(8, by_hand).is_empty());
+    }
+
+    #[test]
+    fn test_first_showing_last() {
+        let layout = layout();
+        assert_eq!(layout.first_showing_last(4, 300.0), 3);
+        assert_eq!(layout.first_showing_last(4, 450.0), 1);
+        assert_eq!(layout.first_showing_last(2, 10_000.0), 0);
+    }
+}
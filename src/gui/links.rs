@@ -0,0 +1,94 @@
+use std::io;
+
+use mini_spreadsheet::common_types::Index;
+use mini_spreadsheet::spreadsheet::SpreadSheet;
+
+/// Follows links outside of the program, behind a trait so that clicking a link can be driven
+/// without opening anything.
+pub trait Opener {
+    fn open(&mut self, url: &str) -> io::Result<()>;
+}
+
+/// Opens links with the program the system uses for them. Without the `links` feature it
+/// fails to open any.
+#[derive(Debug, Default)]
+pub struct SystemOpener;
+
+impl Opener for SystemOpener {
+    #[cfg(feature = "links")]
+    fn open(&mut self, url: &str) -> io::Result<()> {
+        open::that_detached(url)
+    }
+
+    #[cfg(not(feature = "links"))]
+    fn open(&mut self, _url: &str) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "built without the links feature",
+        ))
+    }
+}
+
+/// Returns where the value of the cell at `index` leads, see `Value::link_url`.
+pub fn cell_link(spread_sheet: &SpreadSheet, index: Index) -> Option<String> {
+    let value = spread_sheet.get_computed(index)?.ok()?;
+    value.link_url().map(str::to_string)
+}
+
+/// Opens where the value of the cell at `index` leads with `opener`, or returns `None` when
+/// it leads nowhere.
+pub fn follow_link(
+    spread_sheet: &SpreadSheet,
+    index: Index,
+    opener: &mut dyn Opener,
+) -> Option<io::Result<()>> {
+    let url = cell_link(spread_sheet, index)?;
+    Some(opener.open(&url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Remembers what it was asked to open instead of opening it.
+    #[derive(Default)]
+    struct Recorder {
+        opened: Vec<String>,
+    }
+
+    impl Opener for Recorder {
+        fn open(&mut self, url: &str) -> io::Result<()> {
+            self.opened.push(url.to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_follow_link() {
+        let mut spread_sheet = SpreadSheet::default();
+        let cells = [
+            "=hyperlink(\"https://example.com\", \"Example\")",
+            "mailto:someone@example.com",
+            "https://example.com is down",
+            "=hyperlink(1)",
+        ];
+        for (y, raw) in cells.iter().enumerate() {
+            spread_sheet
+                .set_cell(Index { x: 0, y }, raw.to_string())
+                .unwrap();
+        }
+        let mut recorder = Recorder::default();
+        let mut follow = |y| follow_link(&spread_sheet, Index { x: 0, y }, &mut recorder);
+
+        assert!(follow(0).unwrap().is_ok());
+        assert!(follow(1).unwrap().is_ok());
+        // Text with more than an address, errors and empty cells lead nowhere
+        assert!(follow(2).is_none());
+        assert!(follow(3).is_none());
+        assert!(follow(4).is_none());
+        assert_eq!(
+            recorder.opened,
+            ["https://example.com", "mailto:someone@example.com"]
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use mini_spreadsheet::common_types::Index;
+use mini_spreadsheet::spreadsheet::{FillMode, SpreadSheet};
+
+/// A write of a block of cells, held back while it waits for replacing the data under it to
+/// be confirmed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockWrite {
+    /// The fields of pasted text, written from `target` on.
+    Paste {
+        block: Vec<Vec<String>>,
+        target: Index,
+        values_only: bool,
+    },
+    /// The rectangle spanned by `target` filled from `source`, see `SpreadSheet::fill`.
+    Fill {
+        source: Index,
+        target: (Index, Index),
+        mode: FillMode,
+    },
+}
+
+impl BlockWrite {
+    /// Returns the top left and bottom right cells the write covers.
+    pub fn rect(&self) -> (Index, Index) {
+        match self {
+            BlockWrite::Paste { block, target, .. } => {
+                let width = block.iter().map(Vec::len).max().unwrap_or(1).max(1);
+                let end = Index {
+                    x: target.x + width - 1,
+                    y: target.y + block.len().max(1) - 1,
+                };
+                (*target, end)
+            }
+            BlockWrite::Fill { target, .. } => *target,
+        }
+    }
+
+    /// Returns the cells with content the write replaces, which a fill leaves out its source
+    /// of.
+    pub fn overwritten(&self, sheet: &SpreadSheet) -> Vec<Index> {
+        let mut occupied = sheet.check_overwrite(self.rect());
+        if let BlockWrite::Fill { source, .. } = self {
+            occupied.retain(|index| index != source);
+        }
+        occupied
+    }
+}
+
+/// Asks before a block write replaces cells holding data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverwritePrompt {
+    pub write: BlockWrite,
+    /// How many cells with content the write replaces.
+    pub occupied: usize,
+    /// Whether writes are no longer asked about once this one is confirmed.
+    pub dont_ask_again: bool,
+}
+
+impl OverwritePrompt {
+    /// Returns the prompt for `write`, unless it replaces no data.
+    pub fn for_write(write: BlockWrite, sheet: &SpreadSheet) -> Option<Self> {
+        let occupied = write.overwritten(sheet).len();
+        (occupied > 0).then_some(Self {
+            write,
+            occupied,
+            dont_ask_again: false,
+        })
+    }
+
+    pub fn question(&self) -> String {
+        match self.occupied {
+            1 => "Replace existing data in 1 cell?".to_string(),
+            n => format!("Replace existing data in {n} cells?"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gui::tests::cell;
+
+    #[test]
+    fn test_overwrite_prompt() {
+        let mut sheet = SpreadSheet::default();
+        for name in ["A1", "B2", "C2"] {
+            sheet.set_cell(cell(name), "1".to_string()).unwrap();
+        }
+
+        // A ragged block covers its widest row
+        let paste = BlockWrite::Paste {
+            block: vec![
+                vec!["x".to_string()],
+                vec!["y".to_string(), "z".to_string()],
+            ],
+            target: cell("B1"),
+            values_only: false,
+        };
+        assert_eq!(paste.rect(), (cell("B1"), cell("C2")));
+        let prompt = OverwritePrompt::for_write(paste, &sheet).unwrap();
+        assert_eq!(prompt.question(), "Replace existing data in 2 cells?");
+
+        // The source of a fill is not replaced
+        let fill = |target| BlockWrite::Fill {
+            source: cell("A1"),
+            target: (cell("A1"), target),
+            mode: FillMode::Copy,
+        };
+        assert_eq!(OverwritePrompt::for_write(fill(cell("A5")), &sheet), None);
+        let prompt = OverwritePrompt::for_write(fill(cell("B2")), &sheet).unwrap();
+        assert_eq!(prompt.question(), "Replace existing data in 1 cell?");
+    }
+}
@@ -0,0 +1,146 @@
+use std::ops::Range;
+
+/// Inserts the references of cells clicked while a formula is being edited. A reference
+/// clicked right after another one replaces it, as long as nothing was typed in between,
+/// so pointing at the wrong cell is fixed by pointing at the right one.
+#[derive(Debug, Default)]
+pub struct PointMode {
+    last: Option<Inserted>,
+}
+
+#[derive(Debug)]
+struct Inserted {
+    // Byte range of the reference in the content
+    range: Range<usize>,
+    // The whole content right after the reference was inserted
+    content: String,
+}
+
+impl PointMode {
+    /// Inserts `reference` into `content` at the byte offset `caret`, or replaces the
+    /// reference inserted last if the content was not touched since. A `+` is put in front of
+    /// the reference when it would otherwise follow a cell name, number or `)` directly.
+    pub fn insert(&mut self, content: &mut String, caret: usize, reference: &str) {
+        if self.replace_last(content, reference) {
+            return;
+        }
+
+        let mut caret = caret.min(content.len());
+        while !content.is_char_boundary(caret) {
+            caret -= 1;
+        }
+
+        let needs_separator = content[..caret]
+            .trim_end()
+            .ends_with(|c: char| c.is_ascii_digit() || c == ')');
+        if needs_separator {
+            content.insert(caret, '+');
+            caret += 1;
+        }
+
+        content.insert_str(caret, reference);
+        self.remember(content, caret..caret + reference.len());
+    }
+
+    /// Replaces the reference inserted last with `reference`, for as long as the content was
+    /// not touched since. Returns false if there is nothing to replace.
+    pub fn replace_last(&mut self, content: &mut String, reference: &str) -> bool {
+        let Some(last) = self.last.take() else {
+            return false;
+        };
+        if last.content != *content {
+            return false;
+        }
+
+        content.replace_range(last.range.clone(), reference);
+        let start = last.range.start;
+        self.remember(content, start..start + reference.len());
+        true
+    }
+
+    /// Returns the byte offset right after the reference inserted last, where the caret goes.
+    pub fn last_end(&self) -> Option<usize> {
+        self.last.as_ref().map(|last| last.range.end)
+    }
+
+    /// Forgets the reference inserted last, so the next one is inserted next to it.
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+
+    fn remember(&mut self, content: &str, range: Range<usize>) {
+        self.last = Some(Inserted {
+            range,
+            content: content.to_string(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_at_caret() {
+        let mut point_mode = PointMode::default();
+        let mut content = "=sum()".to_string();
+
+        point_mode.insert(&mut content, 5, "A1");
+        assert_eq!(content, "=sum(A1)");
+        assert_eq!(point_mode.last_end(), Some(7));
+    }
+
+    #[test]
+    fn test_click_replaces_until_typing() {
+        let mut point_mode = PointMode::default();
+        let mut content = "=".to_string();
+
+        let end = content.len();
+        point_mode.insert(&mut content, end, "A1");
+        assert_eq!(content, "=A1");
+
+        // Clicking another cell right away replaces the reference
+        let end = content.len();
+        point_mode.insert(&mut content, end, "B2");
+        assert_eq!(content, "=B2");
+
+        // After typing, a click adds a new reference
+        content.push('*');
+        let end = content.len();
+        point_mode.insert(&mut content, end, "C3");
+        assert_eq!(content, "=B2*C3");
+    }
+
+    #[test]
+    fn test_separator_after_operand() {
+        let mut point_mode = PointMode::default();
+
+        for (before, after) in [
+            ("=A1 ", "=A1 +B2"),
+            ("=2", "=2+B2"),
+            ("=sum(A1:A3)", "=sum(A1:A3)+B2"),
+            ("=A1-", "=A1-B2"),
+            ("=if(", "=if(B2"),
+        ] {
+            let mut content = before.to_string();
+            point_mode.reset();
+            let end = content.len();
+            point_mode.insert(&mut content, end, "B2");
+            assert_eq!(content, after);
+        }
+    }
+
+    #[test]
+    fn test_replace_last_while_dragging() {
+        let mut point_mode = PointMode::default();
+        let mut content = "=5*".to_string();
+
+        assert!(!point_mode.replace_last(&mut content, "A1:A2"));
+
+        let end = content.len();
+        point_mode.insert(&mut content, end, "A1");
+        assert!(point_mode.replace_last(&mut content, "A1:A2"));
+        assert!(point_mode.replace_last(&mut content, "A1:B3"));
+        assert_eq!(content, "=5*A1:B3");
+    }
+}
@@ -0,0 +1,302 @@
+use mini_spreadsheet::common_types::{index_to_name, Index};
+
+/// Returns the top left and bottom right corners of the rectangle spanned by `a` and `b`.
+pub fn normalize_range(a: Index, b: Index) -> (Index, Index) {
+    (
+        Index {
+            x: a.x.min(b.x),
+            y: a.y.min(b.y),
+        },
+        Index {
+            x: a.x.max(b.x),
+            y: a.y.max(b.y),
+        },
+    )
+}
+
+/// Returns true if `index` lies inside the rectangle spanned by the corners of `range`,
+/// which can be given in any order.
+pub fn range_contains(range: (Index, Index), index: Index) -> bool {
+    let (top_left, bottom_right) = normalize_range(range.0, range.1);
+    (top_left.x..=bottom_right.x).contains(&index.x)
+        && (top_left.y..=bottom_right.y).contains(&index.y)
+}
+
+/// Returns the reference to the rectangle spanned by `a` and `b` as written in a formula,
+/// like "B2:B20", or just the cell name when both are the same cell.
+pub fn range_to_string(a: Index, b: Index) -> String {
+    let (top_left, bottom_right) = normalize_range(a, b);
+    if top_left == bottom_right {
+        index_to_name(top_left)
+    } else {
+        format!(
+            "{}:{}",
+            index_to_name(top_left),
+            index_to_name(bottom_right)
+        )
+    }
+}
+
+/// Returns the last cell filled when the fill handle of `source` is dragged to `hovered`.
+/// The fill goes either down or right, along the axis the mouse moved further on, and there
+/// is nothing to fill while the mouse has not left the source that way.
+pub fn fill_target(source: Index, hovered: Index) -> Option<Index> {
+    let down = hovered.y.saturating_sub(source.y);
+    let right = hovered.x.saturating_sub(source.x);
+
+    if down == 0 && right == 0 {
+        None
+    } else if down >= right {
+        Some(Index {
+            y: hovered.y,
+            ..source
+        })
+    } else {
+        Some(Index {
+            x: hovered.x,
+            ..source
+        })
+    }
+}
+
+/// The rectangles of a selection made of several, added with Ctrl+click or Ctrl+drag. Each is
+/// kept by its top left and bottom right corners, in the order they were added.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SelectionSet {
+    ranges: Vec<(Index, Index)>,
+}
+
+impl SelectionSet {
+    /// Adds the rectangle spanned by the corners of `range`, dropping the rectangles it covers.
+    /// A rectangle already covered by one of the set is left out.
+    pub fn add(&mut self, range: (Index, Index)) {
+        let range = normalize_range(range.0, range.1);
+        if self.ranges.iter().any(|&other| covers(other, range)) {
+            return;
+        }
+        self.ranges.retain(|&other| !covers(range, other));
+        self.ranges.push(range);
+    }
+
+    /// Returns the set with the rectangle spanned by the corners of `range` added.
+    pub fn with(&self, range: (Index, Index)) -> Self {
+        let mut set = self.clone();
+        set.add(range);
+        set
+    }
+
+    pub fn clear(&mut self) {
+        self.ranges.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn ranges(&self) -> &[(Index, Index)] {
+        &self.ranges
+    }
+
+    /// Returns true if `index` lies inside any of the rectangles.
+    pub fn contains(&self, index: Index) -> bool {
+        self.ranges
+            .iter()
+            .any(|&range| range_contains(range, index))
+    }
+
+    /// Returns the cells of the rectangles a row at a time, those in several of them once.
+    pub fn cells(&self) -> Vec<Index> {
+        let mut cells: Vec<Index> = self
+            .ranges
+            .iter()
+            .flat_map(|&(top_left, bottom_right)| {
+                (top_left.y..=bottom_right.y)
+                    .flat_map(move |y| (top_left.x..=bottom_right.x).map(move |x| Index { x, y }))
+            })
+            .collect();
+        cells.sort_by_key(|index| (index.y, index.x));
+        cells.dedup();
+        cells
+    }
+
+    /// Returns the cells copied from the selection a row at a time. Rectangles spanning the
+    /// same rows are put side by side and those spanning the same columns one under the
+    /// other, in the order they are on the sheet. Any other selection cannot be copied.
+    pub fn copy_grid(&self) -> Result<Vec<Vec<Index>>, String> {
+        let mut ranges = self.ranges.clone();
+        let Some(&(first_top_left, first_bottom_right)) = ranges.first() else {
+            return Ok(Vec::new());
+        };
+        let rows = |(top_left, bottom_right): (Index, Index)| (top_left.y, bottom_right.y);
+        let columns = |(top_left, bottom_right): (Index, Index)| (top_left.x, bottom_right.x);
+
+        if ranges
+            .iter()
+            .all(|&range| rows(range) == (first_top_left.y, first_bottom_right.y))
+        {
+            ranges.sort_by_key(|&range| columns(range));
+            Ok((first_top_left.y..=first_bottom_right.y)
+                .map(|y| {
+                    ranges
+                        .iter()
+                        .flat_map(|&range| {
+                            let (left, right) = columns(range);
+                            (left..=right).map(move |x| Index { x, y })
+                        })
+                        .collect()
+                })
+                .collect())
+        } else if ranges
+            .iter()
+            .all(|&range| columns(range) == (first_top_left.x, first_bottom_right.x))
+        {
+            ranges.sort_by_key(|&range| rows(range));
+            Ok(ranges
+                .iter()
+                .flat_map(|&range| {
+                    let (top, bottom) = rows(range);
+                    (top..=bottom).map(|y| {
+                        (first_top_left.x..=first_bottom_right.x)
+                            .map(|x| Index { x, y })
+                            .collect()
+                    })
+                })
+                .collect())
+        } else {
+            Err("Only ranges spanning the same rows or the same columns can be copied".to_string())
+        }
+    }
+}
+
+/// Returns true if the rectangle `outer` holds all of `inner`, both given by their top left
+/// and bottom right corners.
+fn covers(outer: (Index, Index), inner: (Index, Index)) -> bool {
+    range_contains(outer, inner.0) && range_contains(outer, inner.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gui::tests::cell;
+
+    #[test]
+    fn test_normalize_range() {
+        let b2 = Index { x: 1, y: 1 };
+        let d5 = Index { x: 3, y: 4 };
+        let b5 = Index { x: 1, y: 4 };
+        let d2 = Index { x: 3, y: 1 };
+
+        assert_eq!(normalize_range(b2, d5), (b2, d5));
+        assert_eq!(normalize_range(d5, b2), (b2, d5));
+        assert_eq!(normalize_range(b5, d2), (b2, d5));
+        assert_eq!(normalize_range(d2, d2), (d2, d2));
+    }
+
+    #[test]
+    fn test_range_contains() {
+        // Dragged from D5 up to B2
+        let range = (Index { x: 3, y: 4 }, Index { x: 1, y: 1 });
+
+        assert!(range_contains(range, Index { x: 1, y: 1 }));
+        assert!(range_contains(range, Index { x: 2, y: 3 }));
+        assert!(range_contains(range, Index { x: 3, y: 4 }));
+
+        assert!(!range_contains(range, Index { x: 0, y: 2 }));
+        assert!(!range_contains(range, Index { x: 4, y: 2 }));
+        assert!(!range_contains(range, Index { x: 2, y: 5 }));
+    }
+
+    #[test]
+    fn test_range_to_string() {
+        let b2 = Index { x: 1, y: 1 };
+        let b20 = Index { x: 1, y: 19 };
+        let aa3 = Index { x: 26, y: 2 };
+
+        assert_eq!(range_to_string(b2, b20), "B2:B20");
+        assert_eq!(range_to_string(b20, b2), "B2:B20");
+        assert_eq!(range_to_string(aa3, b20), "B3:AA20");
+        assert_eq!(range_to_string(b2, b2), "B2");
+    }
+
+    #[test]
+    fn test_fill_target() {
+        let b2 = Index { x: 1, y: 1 };
+
+        assert_eq!(fill_target(b2, b2), None);
+        // Up and left are not filled
+        assert_eq!(fill_target(b2, Index { x: 0, y: 0 }), None);
+
+        assert_eq!(
+            fill_target(b2, Index { x: 2, y: 6 }),
+            Some(Index { x: 1, y: 6 })
+        );
+        assert_eq!(
+            fill_target(b2, Index { x: 5, y: 3 }),
+            Some(Index { x: 5, y: 1 })
+        );
+        assert_eq!(
+            fill_target(b2, Index { x: 0, y: 4 }),
+            Some(Index { x: 1, y: 4 })
+        );
+    }
+
+    #[test]
+    fn test_selection_set() {
+        let mut set = SelectionSet::default();
+        set.add((cell("B3"), cell("A1")));
+        set.add((cell("D2"), cell("D2")));
+        // Covered by a rectangle of the set
+        set.add((cell("B2"), cell("A2")));
+        assert_eq!(
+            set.ranges(),
+            [(cell("A1"), cell("B3")), (cell("D2"), cell("D2"))]
+        );
+
+        assert!(set.contains(cell("B2")));
+        assert!(set.contains(cell("D2")));
+        assert!(!set.contains(cell("C2")));
+        assert!(!set.contains(cell("D3")));
+
+        // Overlapping cells are listed once, in reading order
+        let set = set.with((cell("B3"), cell("B4")));
+        assert_eq!(
+            set.cells(),
+            ["A1", "B1", "A2", "B2", "D2", "A3", "B3", "B4"].map(cell)
+        );
+
+        // Covering rectangles replace those they cover
+        let set = set.with((cell("A1"), cell("D4")));
+        assert_eq!(set.ranges(), [(cell("A1"), cell("D4"))]);
+    }
+
+    #[test]
+    fn test_copy_grid() {
+        let mut set = SelectionSet::default();
+        set.add((cell("D1"), cell("D2")));
+        set.add((cell("A1"), cell("B2")));
+        // Side by side, as they are on the sheet
+        assert_eq!(
+            set.copy_grid(),
+            Ok(vec![
+                ["A1", "B1", "D1"].map(cell).to_vec(),
+                ["A2", "B2", "D2"].map(cell).to_vec(),
+            ])
+        );
+
+        let mut set = SelectionSet::default();
+        set.add((cell("A5"), cell("B5")));
+        set.add((cell("A1"), cell("B1")));
+        assert_eq!(
+            set.copy_grid(),
+            Ok(vec![
+                ["A1", "B1"].map(cell).to_vec(),
+                ["A5", "B5"].map(cell).to_vec(),
+            ])
+        );
+
+        // Neither the same rows nor the same columns
+        set.add((cell("C3"), cell("C3")));
+        assert!(set.copy_grid().is_err());
+        assert_eq!(SelectionSet::default().copy_grid(), Ok(Vec::new()));
+    }
+}
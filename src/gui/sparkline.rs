@@ -0,0 +1,56 @@
+use macroquad::math::{vec2, Rect, Vec2};
+
+/// The room left between a sparkline and the borders of its cell.
+pub const SPARKLINE_PADDING: f32 = 3.0;
+
+/// Returns where to draw each of `points` in `rect`, spread evenly from its left to its right
+/// edge with the smallest number at the bottom and the largest at the top. Numbers all the
+/// same are drawn across the middle, and a single one in the middle.
+pub fn sparkline_positions(points: &[f64], rect: Rect) -> Vec<Vec2> {
+    let min = points.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = points.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let step = match points.len() {
+        0 | 1 => 0.0,
+        len => rect.w / (len - 1) as f32,
+    };
+    points
+        .iter()
+        .enumerate()
+        .map(|(i, point)| {
+            let height = match max > min {
+                true => ((point - min) / (max - min)) as f32,
+                false => 0.5,
+            };
+            let x = match points.len() {
+                1 => rect.center().x,
+                _ => rect.x + i as f32 * step,
+            };
+            vec2(x, rect.bottom() - height * rect.h)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sparkline_positions() {
+        let rect = Rect::new(10.0, 20.0, 90.0, 40.0);
+        assert_eq!(
+            sparkline_positions(&[2.0, 6.0, 4.0, -2.0], rect),
+            [
+                vec2(10.0, 40.0),
+                vec2(40.0, 20.0),
+                vec2(70.0, 30.0),
+                vec2(100.0, 60.0)
+            ]
+        );
+        assert_eq!(
+            sparkline_positions(&[5.0, 5.0], rect),
+            [vec2(10.0, 40.0), vec2(100.0, 40.0)]
+        );
+        assert_eq!(sparkline_positions(&[1.0], rect), [vec2(55.0, 40.0)]);
+        assert!(sparkline_positions(&[], rect).is_empty());
+    }
+}
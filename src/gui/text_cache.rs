@@ -0,0 +1,186 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use macroquad::text::TextDimensions;
+
+use mini_spreadsheet::common_types::CellStyle;
+
+/// How many measurements are kept, enough for every cell of a full window several times over.
+const CAPACITY: usize = 8192;
+
+/// Which of the loaded fonts text is drawn with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FontFace {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+}
+
+impl FontFace {
+    /// Returns the face the text of a cell with `style` is drawn with.
+    pub fn of(style: CellStyle) -> Self {
+        match (style.bold, style.italic) {
+            (false, false) => FontFace::Regular,
+            (true, false) => FontFace::Bold,
+            (false, true) => FontFace::Italic,
+            (true, true) => FontFace::BoldItalic,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    text: String,
+    dimensions: TextDimensions,
+    // The frame the entry was last used in
+    used: u64,
+}
+
+/// The dimensions of the text drawn in cells and labels, measured once for as long as it
+/// keeps being drawn. Entries are found by the text itself, so text that changed is simply
+/// measured again and what is no longer drawn is dropped once the cache is full, least
+/// recently used first.
+#[derive(Debug, Default)]
+pub struct TextCache {
+    entries: HashMap<(u64, FontFace, u16), Entry>,
+    frame: u64,
+    // Measurements taken during the current frame
+    measured: usize,
+}
+
+impl TextCache {
+    /// Returns the dimensions of `text` in `face` at `size`, calling `measure` for them when
+    /// they are not known yet.
+    pub fn measure(
+        &mut self,
+        text: &str,
+        face: FontFace,
+        size: u16,
+        measure: impl FnOnce() -> TextDimensions,
+    ) -> TextDimensions {
+        let mut hasher = DefaultHasher::new();
+        text.hash(&mut hasher);
+        let key = (hasher.finish(), face, size);
+
+        if let Some(entry) = self
+            .entries
+            .get_mut(&key)
+            .filter(|entry| entry.text == text)
+        {
+            entry.used = self.frame;
+            return entry.dimensions;
+        }
+
+        self.measured += 1;
+        let dimensions = measure();
+        if self.entries.len() >= CAPACITY {
+            self.evict();
+        }
+        self.entries.insert(
+            key,
+            Entry {
+                text: text.to_string(),
+                dimensions,
+                used: self.frame,
+            },
+        );
+        dimensions
+    }
+
+    /// Starts the next frame, returning how many measurements the one ending took.
+    pub fn end_frame(&mut self) -> usize {
+        self.frame += 1;
+        std::mem::take(&mut self.measured)
+    }
+
+    /// Drops the least recently used quarter of the entries, making room for a while.
+    fn evict(&mut self) {
+        let mut used: Vec<u64> = self.entries.values().map(|entry| entry.used).collect();
+        let (_, &mut oldest_kept, _) = used.select_nth_unstable(CAPACITY / 4);
+        self.entries.retain(|_, entry| entry.used >= oldest_kept);
+        // Entries all used in the same frame are dropped at random
+        if self.entries.len() >= CAPACITY {
+            let excess = self.entries.len() - CAPACITY * 3 / 4;
+            let dropped: Vec<_> = self.entries.keys().take(excess).copied().collect();
+            for key in dropped {
+                self.entries.remove(&key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dimensions(width: f32) -> TextDimensions {
+        TextDimensions {
+            width,
+            height: 10.0,
+            offset_y: 8.0,
+        }
+    }
+
+    #[test]
+    fn test_idle_frame_measures_nothing() {
+        let mut cache = TextCache::default();
+        let texts: Vec<String> = (0..100).map(|i| format!("Cell {i}")).collect();
+        let draw = |cache: &mut TextCache| {
+            for text in &texts {
+                cache.measure(
+                    text,
+                    FontFace::Regular,
+                    12,
+                    || dimensions(text.len() as f32),
+                );
+            }
+            cache.measure("A", FontFace::Regular, 10, || dimensions(1.0));
+            cache.measure("A", FontFace::Bold, 10, || dimensions(2.0));
+            cache.end_frame()
+        };
+
+        assert_eq!(draw(&mut cache), 102);
+        assert_eq!(draw(&mut cache), 0);
+        assert_eq!(draw(&mut cache), 0);
+
+        // Only the text that changed is measured again
+        assert_eq!(
+            cache
+                .measure("Cell 1", FontFace::Regular, 12, || unreachable!())
+                .width,
+            6.0
+        );
+        cache.measure("Edited", FontFace::Regular, 12, || dimensions(6.0));
+        assert_eq!(cache.end_frame(), 1);
+        assert_eq!(
+            cache
+                .measure("A", FontFace::Bold, 10, || unreachable!())
+                .width,
+            2.0
+        );
+    }
+
+    #[test]
+    fn test_least_recently_used_evicted() {
+        let mut cache = TextCache::default();
+        cache.measure("kept", FontFace::Regular, 12, || dimensions(4.0));
+        cache.end_frame();
+        for i in 0..CAPACITY * 2 {
+            cache.measure("kept", FontFace::Regular, 12, || unreachable!());
+            cache.measure(&i.to_string(), FontFace::Regular, 12, || dimensions(1.0));
+            cache.end_frame();
+        }
+
+        assert!(cache.entries.len() <= CAPACITY);
+        assert_eq!(
+            cache
+                .measure("kept", FontFace::Regular, 12, || unreachable!())
+                .width,
+            4.0
+        );
+        cache.measure("0", FontFace::Regular, 12, || dimensions(1.0));
+        assert_eq!(cache.end_frame(), 1);
+    }
+}
@@ -0,0 +1,378 @@
+use std::ops::Range;
+
+/// Seconds a key has to be held before it starts repeating.
+pub const KEY_REPEAT_DELAY: f64 = 0.5;
+/// Seconds between two repeats of a held key.
+pub const KEY_REPEAT_INTERVAL: f64 = 0.04;
+
+/// The caret and selection of a single line text field. The text itself is owned elsewhere
+/// and passed to every call, so anything can change it in between; positions are byte
+/// offsets that are clamped to the text before they are used.
+#[derive(Debug, Default)]
+pub struct TextEditor {
+    caret: usize,
+    // Where the selection started, the caret being its other end
+    anchor: Option<usize>,
+}
+
+impl TextEditor {
+    /// Returns the byte offset of the caret in `text`.
+    pub fn caret(&self, text: &str) -> usize {
+        floor_boundary(text, self.caret)
+    }
+
+    /// Returns the selected byte range of `text`, if anything is selected.
+    pub fn selection(&self, text: &str) -> Option<Range<usize>> {
+        let anchor = floor_boundary(text, self.anchor?);
+        let caret = self.caret(text);
+        match anchor.cmp(&caret) {
+            std::cmp::Ordering::Less => Some(anchor..caret),
+            std::cmp::Ordering::Greater => Some(caret..anchor),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+
+    pub fn selected_text<'a>(&self, text: &'a str) -> &'a str {
+        self.selection(text).map_or("", |range| &text[range])
+    }
+
+    /// Moves the caret to `position`, selecting the text passed over when `extend` is set.
+    pub fn set_caret(&mut self, text: &str, position: usize, extend: bool) {
+        if extend {
+            self.anchor.get_or_insert(self.caret(text));
+        } else {
+            self.anchor = None;
+        }
+        self.caret = floor_boundary(text, position);
+    }
+
+    pub fn move_left(&mut self, text: &str, extend: bool) {
+        let caret = self.caret(text);
+        let position = match self.selection(text) {
+            Some(range) if !extend => range.start,
+            _ => text[..caret].char_indices().last().map_or(0, |(i, _)| i),
+        };
+        self.set_caret(text, position, extend);
+    }
+
+    pub fn move_right(&mut self, text: &str, extend: bool) {
+        let caret = self.caret(text);
+        let position = match self.selection(text) {
+            Some(range) if !extend => range.end,
+            _ => next_boundary(text, caret),
+        };
+        self.set_caret(text, position, extend);
+    }
+
+    /// Moves the caret to the start of the word before it, over anything between the two.
+    pub fn move_word_left(&mut self, text: &str, extend: bool) {
+        let caret = self.caret(text);
+        let mut chars = text[..caret].char_indices().rev().peekable();
+        while chars.next_if(|&(_, c)| !is_word_char(c)).is_some() {}
+        while chars.next_if(|&(_, c)| is_word_char(c)).is_some() {}
+        let position = chars.peek().map_or(0, |&(i, c)| i + c.len_utf8());
+        self.set_caret(text, position, extend);
+    }
+
+    /// Moves the caret to the end of the word after it, over anything between the two.
+    pub fn move_word_right(&mut self, text: &str, extend: bool) {
+        let caret = self.caret(text);
+        let mut chars = text[caret..].char_indices().peekable();
+        while chars.next_if(|&(_, c)| !is_word_char(c)).is_some() {}
+        while chars.next_if(|&(_, c)| is_word_char(c)).is_some() {}
+        let position = chars.peek().map_or(text.len(), |&(i, _)| caret + i);
+        self.set_caret(text, position, extend);
+    }
+
+    pub fn move_home(&mut self, text: &str, extend: bool) {
+        self.set_caret(text, 0, extend);
+    }
+
+    pub fn move_end(&mut self, text: &str, extend: bool) {
+        self.set_caret(text, text.len(), extend);
+    }
+
+    pub fn select_all(&mut self, text: &str) {
+        self.anchor = Some(0);
+        self.caret = text.len();
+    }
+
+    /// Types `input` at the caret, replacing the selection.
+    pub fn insert(&mut self, text: &mut String, input: &str) {
+        self.delete_selection(text);
+        let caret = self.caret(text);
+        text.insert_str(caret, input);
+        self.caret = caret + input.len();
+    }
+
+    /// Removes the selection, or the character before the caret.
+    pub fn backspace(&mut self, text: &mut String) {
+        if self.delete_selection(text) {
+            return;
+        }
+        let caret = self.caret(text);
+        if let Some((start, _)) = text[..caret].char_indices().last() {
+            text.replace_range(start..caret, "");
+            self.caret = start;
+        }
+    }
+
+    /// Removes the selection, or the character after the caret.
+    pub fn delete(&mut self, text: &mut String) {
+        if self.delete_selection(text) {
+            return;
+        }
+        let caret = self.caret(text);
+        text.replace_range(caret..next_boundary(text, caret), "");
+        self.caret = caret;
+    }
+
+    /// Removes the selected text. Returns false if nothing was selected.
+    pub fn delete_selection(&mut self, text: &mut String) -> bool {
+        let selection = self.selection(text);
+        self.anchor = None;
+        let Some(range) = selection else {
+            return false;
+        };
+
+        self.caret = range.start;
+        text.replace_range(range, "");
+        true
+    }
+}
+
+/// Whether `c` belongs to a word the caret jumps over at once, like a function name or a
+/// reference.
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn floor_boundary(text: &str, mut position: usize) -> usize {
+    position = position.min(text.len());
+    while !text.is_char_boundary(position) {
+        position -= 1;
+    }
+    position
+}
+
+fn next_boundary(text: &str, position: usize) -> usize {
+    text[position..]
+        .chars()
+        .next()
+        .map_or(position, |c| position + c.len_utf8())
+}
+
+/// Returns the byte offset of every caret position in `text` with its distance from the
+/// start of the text, given the advance of each character.
+pub fn caret_offsets(text: &str, advance: impl Fn(char) -> f32) -> Vec<(usize, f32)> {
+    let mut x = 0.0;
+    let mut offsets = Vec::with_capacity(text.len() + 1);
+    for (i, c) in text.char_indices() {
+        offsets.push((i, x));
+        x += advance(c);
+    }
+    offsets.push((text.len(), x));
+    offsets
+}
+
+/// Returns the caret position of `offsets` closest to the distance `x`.
+pub fn caret_at(offsets: &[(usize, f32)], x: f32) -> usize {
+    offsets
+        .iter()
+        .min_by(|a, b| (a.1 - x).abs().total_cmp(&(b.1 - x).abs()))
+        .map_or(0, |&(i, _)| i)
+}
+
+/// Returns where the underline of the bytes `span` of a text starts and how long it is, from
+/// the caret `offsets` of the text. An empty span, like an error found at the end of the text,
+/// is underlined for `min_width`. Nothing is underlined for a span outside of the text.
+pub fn underline(
+    offsets: &[(usize, f32)],
+    span: Range<usize>,
+    min_width: f32,
+) -> Option<(f32, f32)> {
+    let x_of = |i: usize| {
+        offsets
+            .iter()
+            .find(|&&(offset, _)| offset == i)
+            .map(|&(_, x)| x)
+    };
+    let (start, end) = (x_of(span.start)?, x_of(span.end)?);
+    Some((start, (end - start).max(min_width)))
+}
+
+/// Turns key presses into the repeated presses of a key held down, like typing does.
+#[derive(Debug)]
+pub struct KeyRepeat<K> {
+    // The held key, when it was pressed and when it last fired
+    held: Option<(K, f64, f64)>,
+}
+
+impl<K> Default for KeyRepeat<K> {
+    fn default() -> Self {
+        Self { held: None }
+    }
+}
+
+impl<K: Copy + PartialEq> KeyRepeat<K> {
+    /// Returns the key that fires at `now`: one of `pressed` in this frame, or the key held
+    /// since an earlier frame once it repeats. `is_down` tells if a key is still held.
+    pub fn update(
+        &mut self,
+        pressed: Option<K>,
+        is_down: impl Fn(K) -> bool,
+        now: f64,
+    ) -> Option<K> {
+        if let Some(key) = pressed {
+            self.held = Some((key, now, now));
+            return Some(key);
+        }
+
+        let (key, pressed_at, fired_at) = self.held?;
+        if !is_down(key) {
+            self.held = None;
+            return None;
+        }
+        if now - pressed_at >= KEY_REPEAT_DELAY && now - fired_at >= KEY_REPEAT_INTERVAL {
+            self.held = Some((key, pressed_at, now));
+            return Some(key);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typing_and_erasing() {
+        let mut text = String::new();
+        let mut editor = TextEditor::default();
+
+        editor.insert(&mut text, "=sum");
+        editor.insert(&mut text, "()");
+        editor.move_left(&text, false);
+        editor.insert(&mut text, "A1");
+        assert_eq!(text, "=sum(A1)");
+        assert_eq!(editor.caret(&text), 7);
+
+        editor.backspace(&mut text);
+        editor.delete(&mut text);
+        assert_eq!(text, "=sum(A");
+        editor.delete(&mut text);
+        assert_eq!(text, "=sum(A");
+
+        editor.move_home(&text, false);
+        editor.backspace(&mut text);
+        editor.delete(&mut text);
+        assert_eq!(text, "sum(A");
+    }
+
+    #[test]
+    fn test_selection() {
+        let mut text = "=A1+B2".to_string();
+        let mut editor = TextEditor::default();
+
+        editor.move_end(&text, false);
+        editor.move_left(&text, true);
+        editor.move_left(&text, true);
+        assert_eq!(editor.selected_text(&text), "B2");
+
+        // Moving without extending collapses the selection to its side
+        editor.move_left(&text, false);
+        assert_eq!(editor.selection(&text), None);
+        assert_eq!(editor.caret(&text), 4);
+
+        editor.select_all(&text);
+        editor.insert(&mut text, "=C3");
+        assert_eq!(text, "=C3");
+
+        editor.set_caret(&text, 1, false);
+        editor.set_caret(&text, 3, true);
+        editor.backspace(&mut text);
+        assert_eq!(text, "=");
+    }
+
+    #[test]
+    fn test_word_jumps() {
+        let text = "=sum(A1, héllo)";
+        let mut editor = TextEditor::default();
+
+        editor.move_word_right(text, false);
+        assert_eq!(editor.caret(text), 4);
+        editor.move_word_right(text, false);
+        assert_eq!(editor.caret(text), 7);
+        editor.move_word_right(text, true);
+        assert_eq!(editor.selected_text(text), ", héllo");
+        editor.move_word_right(text, false);
+        assert_eq!(editor.caret(text), text.len());
+
+        editor.move_word_left(text, false);
+        assert_eq!(&text[editor.caret(text)..], "héllo)");
+        editor.move_word_left(text, true);
+        assert_eq!(editor.selected_text(text), "A1, ");
+        editor.move_word_left(text, false);
+        editor.move_word_left(text, false);
+        assert_eq!(editor.caret(text), 0);
+    }
+
+    #[test]
+    fn test_positions_follow_the_text() {
+        let mut text = "=\"héé\"".to_string();
+        let mut editor = TextEditor::default();
+
+        editor.move_end(&text, false);
+        editor.move_left(&text, false);
+        editor.backspace(&mut text);
+        assert_eq!(text, "=\"hé\"");
+
+        // The text changed elsewhere, the caret is clamped to it
+        editor.move_end(&text, false);
+        text.truncate(1);
+        assert_eq!(editor.caret(&text), 1);
+        editor.insert(&mut text, "1");
+        assert_eq!(text, "=1");
+    }
+
+    #[test]
+    fn test_caret_at() {
+        let offsets = caret_offsets("ab€", |_| 10.0);
+        assert_eq!(offsets, vec![(0, 0.0), (1, 10.0), (2, 20.0), (5, 30.0)]);
+
+        assert_eq!(caret_at(&offsets, -5.0), 0);
+        assert_eq!(caret_at(&offsets, 4.0), 0);
+        assert_eq!(caret_at(&offsets, 6.0), 1);
+        assert_eq!(caret_at(&offsets, 27.0), 5);
+        assert_eq!(caret_at(&offsets, 100.0), 5);
+    }
+
+    #[test]
+    fn test_underline() {
+        // Narrow parentheses, like most fonts have
+        let text = "=sum(A1))";
+        let offsets = caret_offsets(text, |c| if "()".contains(c) { 4.0 } else { 10.0 });
+
+        assert_eq!(underline(&offsets, 8..9, 6.0), Some((68.0, 6.0)));
+        assert_eq!(underline(&offsets, 1..4, 6.0), Some((10.0, 30.0)));
+        // At the end of the text
+        assert_eq!(underline(&offsets, 9..9, 6.0), Some((72.0, 6.0)));
+        assert_eq!(underline(&offsets, 9..12, 6.0), None);
+    }
+
+    #[test]
+    fn test_key_repeat() {
+        let mut repeat = KeyRepeat::default();
+        let held = |_| true;
+
+        assert_eq!(repeat.update(Some('a'), held, 0.0), Some('a'));
+        assert_eq!(repeat.update(None, held, 0.3), None);
+        assert_eq!(repeat.update(None, held, 0.5), Some('a'));
+        assert_eq!(repeat.update(None, held, 0.52), None);
+        assert_eq!(repeat.update(None, held, 0.55), Some('a'));
+
+        // Released keys stop repeating
+        assert_eq!(repeat.update(None, |_| false, 2.0), None);
+        assert_eq!(repeat.update(None, held, 3.0), None);
+    }
+}
@@ -0,0 +1,206 @@
+use macroquad::prelude::*;
+
+/// Every color the interface is drawn with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    /// Behind everything, showing around the grid.
+    pub background: Color,
+    pub grid_background: Color,
+    pub cell_border: Color,
+    pub selected_cell_border: Color,
+    pub cell_text: Color,
+    /// The result of a formula previewed while it is typed.
+    pub preview_text: Color,
+    pub error_text: Color,
+    pub selection_fill: Color,
+    pub label_text: Color,
+    pub label_border: Color,
+    pub selected_label_background: Color,
+    pub fill_handle: Color,
+    pub fill_preview: Color,
+    /// The line between frozen rows or columns and the ones scrolling past them.
+    pub frozen_divider: Color,
+    /// The editor bar, the formula status and the status bar.
+    pub panel_background: Color,
+    pub panel_text: Color,
+    pub field_border: Color,
+    pub text_selection: Color,
+    pub name_box_error: Color,
+    /// References cycle through these in the order they are written.
+    pub references: [Color; 6],
+    pub number: Color,
+    pub string: Color,
+    pub function: Color,
+    pub matching_paren: Color,
+    pub dependant: Color,
+    pub suggestion_background: Color,
+    pub selected_suggestion_background: Color,
+    pub suggestion_signature: Color,
+    pub valid_formula: Color,
+    pub invalid_formula: Color,
+    /// The marker of cells whose value breaks their validation rule.
+    pub invalid_value: Color,
+    /// The marker of broken formulas left as written when cells moved, see
+    /// `SpreadSheet::needs_attention`.
+    pub needs_attention: Color,
+    /// The padlock drawn on locked cells while the sheet is protected.
+    pub locked_cell: Color,
+    /// The text of cells leading somewhere, see `Value::link_url`.
+    pub link: Color,
+    /// The icons of filtered columns and the marks where filtered rows are hidden.
+    pub filter: Color,
+    /// The line of cells showing a `Value::Sparkline`.
+    pub sparkline: Color,
+}
+
+impl Theme {
+    pub const LIGHT: Theme = Theme {
+        background: BLACK,
+        grid_background: WHITE,
+        cell_border: BLACK,
+        selected_cell_border: ORANGE,
+        cell_text: BLACK,
+        preview_text: GRAY,
+        error_text: RED,
+        selection_fill: Color::new(0.4, 0.6, 1.0, 0.2),
+        label_text: DARKGRAY,
+        label_border: DARKGRAY,
+        selected_label_background: SKYBLUE,
+        fill_handle: ORANGE,
+        fill_preview: DARKGRAY,
+        frozen_divider: DARKGRAY,
+        panel_background: Color::new(0.94, 0.94, 0.94, 1.0),
+        panel_text: DARKGRAY,
+        field_border: GRAY,
+        text_selection: Color::new(0.78, 0.78, 1.0, 1.0),
+        name_box_error: Color::new(1.0, 0.59, 0.59, 1.0),
+        references: [
+            Color::new(0.1, 0.3, 0.9, 1.0),
+            Color::new(0.85, 0.15, 0.15, 1.0),
+            Color::new(0.1, 0.6, 0.2, 1.0),
+            Color::new(0.75, 0.4, 0.0, 1.0),
+            Color::new(0.0, 0.55, 0.6, 1.0),
+            Color::new(0.8, 0.1, 0.55, 1.0),
+        ],
+        number: DARKGREEN,
+        string: Color::new(0.6, 0.35, 0.1, 1.0),
+        function: Color::new(0.5, 0.1, 0.6, 1.0),
+        matching_paren: Color::new(1.0, 0.85, 0.3, 0.8),
+        dependant: Color::new(0.55, 0.2, 0.75, 1.0),
+        suggestion_background: WHITE,
+        selected_suggestion_background: Color::new(0.78, 0.78, 1.0, 1.0),
+        suggestion_signature: GRAY,
+        valid_formula: DARKGREEN,
+        invalid_formula: RED,
+        invalid_value: Color::new(0.9, 0.55, 0.0, 1.0),
+        needs_attention: Color::new(1.0, 0.75, 0.0, 1.0),
+        locked_cell: GRAY,
+        link: Color::new(0.1, 0.3, 0.85, 1.0),
+        filter: Color::new(0.1, 0.55, 0.25, 1.0),
+        sparkline: Color::new(0.2, 0.4, 0.75, 1.0),
+    };
+
+    pub const DARK: Theme = Theme {
+        background: Color::new(0.05, 0.05, 0.06, 1.0),
+        grid_background: Color::new(0.12, 0.12, 0.13, 1.0),
+        cell_border: Color::new(0.32, 0.32, 0.35, 1.0),
+        selected_cell_border: ORANGE,
+        cell_text: Color::new(0.9, 0.9, 0.9, 1.0),
+        preview_text: Color::new(0.55, 0.55, 0.58, 1.0),
+        error_text: Color::new(1.0, 0.42, 0.42, 1.0),
+        selection_fill: Color::new(0.4, 0.6, 1.0, 0.25),
+        label_text: Color::new(0.7, 0.7, 0.72, 1.0),
+        label_border: Color::new(0.38, 0.38, 0.4, 1.0),
+        selected_label_background: Color::new(0.2, 0.35, 0.55, 1.0),
+        fill_handle: ORANGE,
+        fill_preview: LIGHTGRAY,
+        frozen_divider: Color::new(0.6, 0.6, 0.63, 1.0),
+        panel_background: Color::new(0.18, 0.18, 0.2, 1.0),
+        panel_text: Color::new(0.7, 0.7, 0.72, 1.0),
+        field_border: Color::new(0.4, 0.4, 0.43, 1.0),
+        text_selection: Color::new(0.25, 0.3, 0.55, 1.0),
+        name_box_error: Color::new(0.5, 0.18, 0.18, 1.0),
+        references: [
+            Color::new(0.4, 0.6, 1.0, 1.0),
+            Color::new(1.0, 0.45, 0.45, 1.0),
+            Color::new(0.4, 0.85, 0.45, 1.0),
+            Color::new(1.0, 0.65, 0.25, 1.0),
+            Color::new(0.3, 0.85, 0.9, 1.0),
+            Color::new(1.0, 0.45, 0.8, 1.0),
+        ],
+        number: Color::new(0.45, 0.8, 0.45, 1.0),
+        string: Color::new(0.9, 0.65, 0.4, 1.0),
+        function: Color::new(0.8, 0.55, 0.95, 1.0),
+        matching_paren: Color::new(0.6, 0.5, 0.1, 0.8),
+        dependant: Color::new(0.75, 0.5, 0.95, 1.0),
+        suggestion_background: Color::new(0.16, 0.16, 0.18, 1.0),
+        selected_suggestion_background: Color::new(0.25, 0.3, 0.55, 1.0),
+        suggestion_signature: Color::new(0.6, 0.6, 0.62, 1.0),
+        valid_formula: Color::new(0.45, 0.8, 0.45, 1.0),
+        invalid_formula: Color::new(1.0, 0.42, 0.42, 1.0),
+        invalid_value: Color::new(1.0, 0.7, 0.2, 1.0),
+        needs_attention: Color::new(1.0, 0.8, 0.3, 1.0),
+        locked_cell: Color::new(0.55, 0.55, 0.58, 1.0),
+        link: Color::new(0.45, 0.65, 1.0, 1.0),
+        filter: Color::new(0.4, 0.8, 0.5, 1.0),
+        sparkline: Color::new(0.55, 0.75, 1.0, 1.0),
+    };
+
+    /// Returns the preset called `name`, "light" or "dark".
+    pub fn named(name: &str) -> Option<Theme> {
+        match name {
+            "light" => Some(Theme::LIGHT),
+            "dark" => Some(Theme::DARK),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the preset, see `named`: "dark" for the dark one and "light" for
+    /// anything else.
+    pub fn name(&self) -> &'static str {
+        if *self == Theme::DARK {
+            "dark"
+        } else {
+            "light"
+        }
+    }
+
+    /// Returns the other preset: dark for light and light for anything else.
+    pub fn toggled(&self) -> Theme {
+        if *self == Theme::LIGHT {
+            Theme::DARK
+        } else {
+            Theme::LIGHT
+        }
+    }
+
+    /// Returns the color of the reference at `slot` among the distinct references of a formula.
+    pub fn reference_color(&self, slot: usize) -> Color {
+        self.references[slot % self.references.len()]
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::LIGHT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toggled() {
+        assert_eq!(Theme::LIGHT.toggled(), Theme::DARK);
+        assert_eq!(Theme::DARK.toggled(), Theme::LIGHT);
+        assert_eq!(Theme::default(), Theme::LIGHT);
+    }
+
+    #[test]
+    fn test_reference_colors_cycle() {
+        let theme = Theme::DARK;
+        assert_eq!(theme.reference_color(1), theme.references[1]);
+        assert_eq!(theme.reference_color(7), theme.references[1]);
+    }
+}
@@ -0,0 +1,469 @@
+use mini_spreadsheet::common_types::Index;
+
+use super::layout::AxisLayout;
+
+/// A row or column label of the grid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Label {
+    Column(usize),
+    Row(usize),
+}
+
+/// How many columns and rows the wheel and paging scroll past the last used ones, leaving
+/// room to enter new data.
+pub const SCROLL_MARGIN: (usize, usize) = (20, 100);
+
+/// The part of the sheet shown on screen, identified by its first visible column and row.
+/// The first `frozen_cols` columns and `frozen_rows` rows stay in place in front of them
+/// while the rest scrolls, so the first scrolling column and row come after those.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub first_col: usize,
+    pub first_row: usize,
+    pub frozen_cols: usize,
+    pub frozen_rows: usize,
+}
+
+impl Viewport {
+    /// Scrolls by a signed amount of rows and columns, clamped to a sheet of `(columns, rows)`.
+    /// A viewport already past the bounds may scroll back but not further.
+    pub fn scroll(&mut self, rows: isize, cols: isize, bounds: (usize, usize)) {
+        let (max_cols, max_rows) = bounds;
+        self.first_row = self
+            .first_row
+            .saturating_add_signed(rows)
+            .min(max_rows.saturating_sub(1).max(self.first_row))
+            .max(self.frozen_rows);
+        self.first_col = self
+            .first_col
+            .saturating_add_signed(cols)
+            .min(max_cols.saturating_sub(1).max(self.first_col))
+            .max(self.frozen_cols);
+    }
+
+    /// Returns how many scrolling rows fit whole into `height`, what paging moves by.
+    pub fn page_rows(&self, rows: &AxisLayout, height: f32) -> usize {
+        rows.fitting(self.first_row, height - rows.offset_of(0, self.frozen_rows))
+    }
+
+    /// Keeps the first `cols` columns and `rows` rows in place while scrolling.
+    pub fn freeze(&mut self, cols: usize, rows: usize) {
+        self.frozen_cols = cols;
+        self.frozen_rows = rows;
+        self.first_col = self.first_col.max(cols);
+        self.first_row = self.first_row.max(rows);
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen_cols > 0 || self.frozen_rows > 0
+    }
+
+    /// Returns the columns shown in `width` in the order they are shown: the frozen ones, then
+    /// the scrolling ones, leaving out the hidden ones. The last of them may not fit whole and
+    /// is drawn cut off.
+    pub fn visible_columns(&self, columns: &AxisLayout, width: f32) -> Vec<usize> {
+        visible_lines(self.frozen_cols, self.first_col, columns, width)
+    }
+
+    pub fn visible_rows(&self, rows: &AxisLayout, height: f32) -> Vec<usize> {
+        visible_lines(self.frozen_rows, self.first_row, rows, height)
+    }
+
+    /// Returns the distance from the left of the first shown column to `col`, unless it is
+    /// scrolled out of view behind the frozen columns or to the left.
+    pub fn column_offset(&self, columns: &AxisLayout, col: usize) -> Option<f32> {
+        line_offset(self.frozen_cols, self.first_col, columns, col)
+    }
+
+    pub fn row_offset(&self, rows: &AxisLayout, row: usize) -> Option<f32> {
+        line_offset(self.frozen_rows, self.first_row, rows, row)
+    }
+
+    /// Returns the column whose right boundary lies within `tolerance` of `x`, measured like
+    /// in `column_offset`.
+    pub fn column_boundary_at(
+        &self,
+        columns: &AxisLayout,
+        x: f32,
+        tolerance: f32,
+    ) -> Option<usize> {
+        let frozen_width = columns.offset_of(0, self.frozen_cols);
+        columns
+            .boundary_at(0, x, tolerance)
+            .filter(|&col| col < self.frozen_cols)
+            .or_else(|| columns.boundary_at(self.first_col, x - frozen_width, tolerance))
+    }
+
+    /// Scrolls the least amount needed for `index` to be fully inside an area of
+    /// `(width, height)` pixels.
+    pub fn scroll_into_view(
+        &mut self,
+        index: Index,
+        layout: (&AxisLayout, &AxisLayout),
+        available: (f32, f32),
+    ) {
+        let (columns, rows) = layout;
+        let (width, height) = available;
+        self.first_col = scroll_axis(self.frozen_cols, self.first_col, index.x, columns, width);
+        self.first_row = scroll_axis(self.frozen_rows, self.first_row, index.y, rows, height);
+    }
+
+    /// Returns the cell under `pos`, given relative to the top left corner of the first
+    /// visible cell. Positions above or left of it have no cell.
+    pub fn cell_at(&self, pos: (f32, f32), layout: (&AxisLayout, &AxisLayout)) -> Option<Index> {
+        let (x, y) = pos;
+        let (columns, rows) = layout;
+
+        Some(Index {
+            x: line_at(self.frozen_cols, self.first_col, columns, x)?,
+            y: line_at(self.frozen_rows, self.first_row, rows, y)?,
+        })
+    }
+
+    /// Returns the label under `pos`, given relative to the top left corner of the first
+    /// visible cell like in `cell_at`. Row labels are left of it and column labels above it.
+    pub fn label_at(&self, pos: (f32, f32), layout: (&AxisLayout, &AxisLayout)) -> Option<Label> {
+        let (x, y) = pos;
+        let (columns, rows) = layout;
+
+        match (x < 0.0, y < 0.0) {
+            (true, false) => Some(Label::Row(line_at(
+                self.frozen_rows,
+                self.first_row,
+                rows,
+                y,
+            )?)),
+            (false, true) => Some(Label::Column(line_at(
+                self.frozen_cols,
+                self.first_col,
+                columns,
+                x,
+            )?)),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the `(columns, rows)` the wheel and paging scroll within: up to `SCROLL_MARGIN`
+/// past `last_used`, inside a sheet of `bounds`.
+pub fn scroll_bounds(last_used: Index, bounds: (usize, usize)) -> (usize, usize) {
+    (
+        (last_used.x + 1 + SCROLL_MARGIN.0).min(bounds.0),
+        (last_used.y + 1 + SCROLL_MARGIN.1).min(bounds.1),
+    )
+}
+
+// Along one axis, the frozen lines are followed by the lines from `first` on
+fn scroll_axis(
+    frozen: usize,
+    first: usize,
+    target: usize,
+    layout: &AxisLayout,
+    available: f32,
+) -> usize {
+    // Frozen lines are always in view, the others share what is left after them
+    let available = available - layout.offset_of(0, frozen);
+    if target < frozen {
+        first
+    } else if target < first {
+        target
+    } else if target >= first + layout.fitting(first, available) {
+        layout.first_showing_last(target, available).max(frozen)
+    } else {
+        first
+    }
+}
+
+fn visible_lines(frozen: usize, first: usize, layout: &AxisLayout, available: f32) -> Vec<usize> {
+    let shown_frozen = layout.starting_within(0, available).min(frozen);
+    // Scrolling lines only show once all frozen ones fit
+    let frozen_size = layout.offset_of(0, frozen);
+    let scrolling = if frozen_size >= available {
+        0
+    } else {
+        layout.starting_within(first, available - frozen_size)
+    };
+    (0..shown_frozen)
+        .chain(first..first + scrolling)
+        .filter(|&idx| !layout.is_hidden(idx))
+        .collect()
+}
+
+fn line_offset(frozen: usize, first: usize, layout: &AxisLayout, idx: usize) -> Option<f32> {
+    if idx < frozen {
+        Some(layout.offset_of(0, idx))
+    } else if idx >= first {
+        Some(layout.offset_of(0, frozen) + layout.offset_of(first, idx))
+    } else {
+        None
+    }
+}
+
+fn line_at(frozen: usize, first: usize, layout: &AxisLayout, offset: f32) -> Option<usize> {
+    let frozen_size = layout.offset_of(0, frozen);
+    if offset < frozen_size {
+        layout.index_at(0, offset)
+    } else {
+        layout.index_at(first, offset - frozen_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOUNDS: (usize, usize) = (100, 1000);
+
+    #[test]
+    fn test_scroll_clamps() {
+        let mut viewport = Viewport::default();
+
+        viewport.scroll(-3, -1, BOUNDS);
+        assert_eq!(viewport, Viewport::default());
+
+        viewport.scroll(5, 2, BOUNDS);
+        assert_eq!(
+            viewport,
+            Viewport {
+                first_col: 2,
+                first_row: 5,
+                ..Viewport::default()
+            }
+        );
+
+        viewport.scroll(5000, 5000, BOUNDS);
+        assert_eq!(
+            viewport,
+            Viewport {
+                first_col: 99,
+                first_row: 999,
+                ..Viewport::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_scroll_past_bounds() {
+        let mut viewport = Viewport {
+            first_row: 500,
+            ..Viewport::default()
+        };
+
+        // Further out than the bounds, the viewport stays until scrolled back
+        viewport.scroll(3, 0, (100, 100));
+        assert_eq!(viewport.first_row, 500);
+        viewport.scroll(-3, 0, (100, 100));
+        assert_eq!(viewport.first_row, 497);
+    }
+
+    #[test]
+    fn test_scroll_bounds() {
+        assert_eq!(scroll_bounds(Index { x: 0, y: 0 }, BOUNDS), (21, 101));
+        assert_eq!(scroll_bounds(Index { x: 3, y: 40 }, BOUNDS), (24, 141));
+        // Never past the sheet
+        assert_eq!(scroll_bounds(Index { x: 95, y: 990 }, BOUNDS), (100, 1000));
+    }
+
+    #[test]
+    fn test_page_rows() {
+        let (_, rows) = layout();
+        let mut viewport = Viewport::default();
+        assert_eq!(viewport.page_rows(&rows, 300.0), 10);
+
+        // Frozen rows take part of the page
+        viewport.freeze(0, 2);
+        assert_eq!(viewport.page_rows(&rows, 300.0), 8);
+    }
+
+    fn layout() -> (AxisLayout, AxisLayout) {
+        (AxisLayout::new(100.0, 20.0), AxisLayout::new(30.0, 10.0))
+    }
+
+    #[test]
+    fn test_scroll_into_view() {
+        let mut viewport = Viewport::default();
+        let (columns, rows) = layout();
+        let layout = (&columns, &rows);
+        let available = (1000.0, 600.0);
+
+        // Already visible, nothing moves
+        viewport.scroll_into_view(Index { x: 9, y: 19 }, layout, available);
+        assert_eq!(viewport, Viewport::default());
+
+        // Pushing past the bottom right edge scrolls by one
+        viewport.scroll_into_view(Index { x: 10, y: 20 }, layout, available);
+        assert_eq!(
+            viewport,
+            Viewport {
+                first_col: 1,
+                first_row: 1,
+                ..Viewport::default()
+            }
+        );
+
+        // Jumping back above the viewport puts the cell first
+        viewport.scroll_into_view(Index { x: 0, y: 0 }, layout, available);
+        assert_eq!(viewport, Viewport::default());
+    }
+
+    #[test]
+    fn test_scroll_into_view_with_wide_column() {
+        let mut viewport = Viewport::default();
+        let (mut columns, rows) = layout();
+        columns.set_size(12, 500.0);
+
+        // Column M is five columns wide, so more columns have to scroll away for it to fit
+        viewport.scroll_into_view(Index { x: 12, y: 0 }, (&columns, &rows), (1000.0, 600.0));
+        assert_eq!(viewport.first_col, 7);
+    }
+
+    #[test]
+    fn test_cell_at_respects_offset() {
+        let viewport = Viewport {
+            first_col: 27,
+            first_row: 4,
+            ..Viewport::default()
+        };
+
+        let (columns, rows) = layout();
+
+        assert_eq!(
+            viewport.cell_at((250.0, 15.0), (&columns, &rows)),
+            Some(Index { x: 29, y: 4 })
+        );
+        assert_eq!(viewport.cell_at((-1.0, 15.0), (&columns, &rows)), None);
+    }
+
+    #[test]
+    fn test_label_at() {
+        let viewport = Viewport {
+            first_col: 2,
+            first_row: 10,
+            ..Viewport::default()
+        };
+        let (columns, rows) = layout();
+        let layout = (&columns, &rows);
+
+        assert_eq!(
+            viewport.label_at((150.0, -5.0), layout),
+            Some(Label::Column(3))
+        );
+        assert_eq!(
+            viewport.label_at((-5.0, 65.0), layout),
+            Some(Label::Row(12))
+        );
+        // The corner between both strips, and the cells themselves
+        assert_eq!(viewport.label_at((-5.0, -5.0), layout), None);
+        assert_eq!(viewport.label_at((150.0, 65.0), layout), None);
+    }
+
+    fn frozen() -> Viewport {
+        // A and B and the first row stay while the view is scrolled to column K and row 21
+        Viewport {
+            first_col: 10,
+            first_row: 20,
+            frozen_cols: 2,
+            frozen_rows: 1,
+        }
+    }
+
+    #[test]
+    fn test_cell_at_with_frozen_lines() {
+        let viewport = frozen();
+        let (columns, rows) = layout();
+        let layout = (&columns, &rows);
+
+        // Within the frozen corner, row and column
+        assert_eq!(
+            viewport.cell_at((150.0, 15.0), layout),
+            Some(Index { x: 1, y: 0 })
+        );
+        assert_eq!(
+            viewport.cell_at((50.0, 45.0), layout),
+            Some(Index { x: 0, y: 20 })
+        );
+        assert_eq!(
+            viewport.cell_at((250.0, 15.0), layout),
+            Some(Index { x: 10, y: 0 })
+        );
+        // Past the frozen lines the scrolled ones continue
+        assert_eq!(
+            viewport.cell_at((399.0, 89.0), layout),
+            Some(Index { x: 11, y: 21 })
+        );
+        assert_eq!(viewport.cell_at((-1.0, 15.0), layout), None);
+
+        assert_eq!(
+            viewport.label_at((250.0, -5.0), layout),
+            Some(Label::Column(10))
+        );
+        assert_eq!(viewport.label_at((-5.0, 15.0), layout), Some(Label::Row(0)));
+    }
+
+    #[test]
+    fn test_offsets_with_frozen_lines() {
+        let viewport = frozen();
+        let (columns, rows) = layout();
+
+        assert_eq!(viewport.column_offset(&columns, 1), Some(100.0));
+        assert_eq!(viewport.column_offset(&columns, 12), Some(400.0));
+        // Scrolled away behind the frozen columns
+        assert_eq!(viewport.column_offset(&columns, 5), None);
+        assert_eq!(viewport.row_offset(&rows, 20), Some(30.0));
+
+        assert_eq!(
+            viewport.visible_columns(&columns, 450.0),
+            vec![0, 1, 10, 11, 12]
+        );
+        // Not even the frozen columns fit, the second is cut off
+        assert_eq!(viewport.visible_columns(&columns, 150.0), vec![0, 1]);
+
+        assert_eq!(viewport.column_boundary_at(&columns, 98.0, 4.0), Some(0));
+        assert_eq!(viewport.column_boundary_at(&columns, 302.0, 4.0), Some(10));
+    }
+
+    #[test]
+    fn test_scrolling_with_frozen_lines() {
+        let mut viewport = frozen();
+        let (columns, rows) = layout();
+
+        // Scrolling stops at the frozen lines
+        viewport.scroll(-100, -100, BOUNDS);
+        assert_eq!((viewport.first_col, viewport.first_row), (2, 1));
+
+        // Frozen cells are always in view and a cell past the edge is shown after them
+        viewport.scroll_into_view(Index { x: 0, y: 0 }, (&columns, &rows), (500.0, 300.0));
+        assert_eq!((viewport.first_col, viewport.first_row), (2, 1));
+        viewport.scroll_into_view(Index { x: 6, y: 1 }, (&columns, &rows), (500.0, 300.0));
+        assert_eq!(viewport.first_col, 4);
+
+        viewport.freeze(0, 0);
+        assert!(!viewport.is_frozen());
+        viewport.freeze(5, 3);
+        assert_eq!((viewport.first_col, viewport.first_row), (5, 3));
+    }
+
+    #[test]
+    fn test_hidden_rows_are_skipped() {
+        let viewport = Viewport {
+            first_row: 1,
+            frozen_rows: 1,
+            ..Viewport::default()
+        };
+        let (columns, mut rows) = layout();
+        rows.set_hidden(std::collections::HashSet::from([2, 3, 5]));
+
+        // Rows of 30, the header frozen over rows 1, 4, 6 and 7
+        assert_eq!(viewport.visible_rows(&rows, 130.0), vec![0, 1, 4, 6, 7]);
+        let row_at = |y| {
+            viewport
+                .cell_at((5.0, y), (&columns, &rows))
+                .map(|idx| idx.y)
+        };
+        assert_eq!(row_at(15.0), Some(0));
+        assert_eq!(row_at(45.0), Some(1));
+        assert_eq!(row_at(75.0), Some(4));
+        assert_eq!(row_at(105.0), Some(6));
+        assert_eq!(viewport.row_offset(&rows, 6), Some(90.0));
+    }
+}
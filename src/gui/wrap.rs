@@ -0,0 +1,73 @@
+/// Breaks `text` into lines no wider than `width`, given the width `measure` returns for a
+/// piece of text. Lines break greedily at spaces; a word too wide for a line of its own is
+/// broken between characters.
+pub fn wrap_text(text: &str, width: f32, measure: impl Fn(&str) -> f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        if !line.is_empty() {
+            let joined = format!("{line} {word}");
+            if measure(&joined) <= width {
+                line = joined;
+                continue;
+            }
+            lines.push(std::mem::take(&mut line));
+        }
+
+        for c in word.chars() {
+            line.push(c);
+            // A line keeps at least one character, however narrow the width
+            if line.chars().count() > 1 && measure(&line) > width {
+                line.pop();
+                lines.push(std::mem::take(&mut line));
+                line.push(c);
+            }
+        }
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every character is 10 wide
+    fn wrap(text: &str, width: f32) -> Vec<String> {
+        wrap_text(text, width, |s| s.chars().count() as f32 * 10.0)
+    }
+
+    #[test]
+    fn test_wrap_at_spaces() {
+        assert_eq!(
+            wrap("the quick brown fox", 100.0),
+            vec!["the quick", "brown fox"]
+        );
+        assert_eq!(
+            wrap("the quick brown fox", 190.0),
+            vec!["the quick brown fox"]
+        );
+        assert_eq!(wrap("a  b   c", 30.0), vec!["a b", "c"]);
+    }
+
+    #[test]
+    fn test_wrap_long_words() {
+        assert_eq!(wrap("abcdefgh", 30.0), vec!["abc", "def", "gh"]);
+        assert_eq!(
+            wrap("hi abcdefgh ok", 50.0),
+            vec!["hi", "abcde", "fgh", "ok"]
+        );
+        // Narrower than a single character
+        assert_eq!(wrap("ab", 5.0), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_wrap_empty() {
+        assert!(wrap("", 100.0).is_empty());
+        assert!(wrap("   ", 100.0).is_empty());
+    }
+}
@@ -0,0 +1,2 @@
+pub mod common_types;
+pub mod spreadsheet;
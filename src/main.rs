@@ -1,15 +1,55 @@
-pub mod common_types;
+use std::{env, path::PathBuf, process};
 
-use gui::GUI;
-use spreadsheet::SpreadSheet;
+use gui::{config::GuiConfig, GUI};
+use mini_spreadsheet::spreadsheet::SpreadSheet;
 
+#[cfg(feature = "bench")]
+mod bench;
+mod cli;
 mod gui;
-mod renderer;
-mod spreadsheet;
+mod repl;
 
-#[macroquad::main("Spredsheet")]
-async fn main() {
-    let spread_sheet = SpreadSheet::default();
-    let mut gui = GUI::new(spread_sheet).await;
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    // Nothing but the GUI opens a window
+    match args.first().map(String::as_str) {
+        Some("eval") => process::exit(cli::run_eval(&args[1..])),
+        Some("diff") => process::exit(cli::run_diff(&args[1..])),
+        Some("graph") => process::exit(cli::run_graph(&args[1..])),
+        Some("stats") => process::exit(cli::run_stats(&args[1..])),
+        Some("repl") => return repl::run(),
+        #[cfg(feature = "bench")]
+        Some("bench") => process::exit(bench::run(&args[1..])),
+        Some("watch") => match cli::parse_watch_args(&args[1..]) {
+            Ok((args, true)) => {
+                let Some(spread_sheet) = cli::load(&args) else {
+                    process::exit(2);
+                };
+                let config = gui_config(&[]);
+                return macroquad::Window::new(
+                    "Spredsheet",
+                    run_gui(spread_sheet, config, Some(args.path)),
+                );
+            }
+            Ok((args, false)) => process::exit(cli::run_watch(args)),
+            Err(message) => process::exit(cli::usage_error(&message)),
+        },
+        _ => (),
+    }
+
+    let config = gui_config(&args);
+    macroquad::Window::new("Spredsheet", run_gui(SpreadSheet::default(), config, None));
+}
+
+/// Reads the config the window opens with, exiting when it is invalid.
+fn gui_config(args: &[String]) -> GuiConfig {
+    GuiConfig::from_args(args).unwrap_or_else(|message| process::exit(cli::usage_error(&message)))
+}
+
+async fn run_gui(spread_sheet: SpreadSheet, config: GuiConfig, watched: Option<PathBuf>) {
+    let mut gui = GUI::new(spread_sheet, config).await;
+    if let Some(path) = watched {
+        gui.watch(path);
+    }
     gui.start().await;
 }
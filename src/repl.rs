@@ -0,0 +1,172 @@
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use mini_spreadsheet::common_types::error_text::{ErrorText, Language};
+use mini_spreadsheet::common_types::{
+    computed_to_text, index_to_name, name_to_index, ComputeError, Index, Value,
+};
+use mini_spreadsheet::spreadsheet::{export::render_table, SpreadSheet};
+
+const HELP: &str = "\
+A1 = <content>   set a cell, an empty content clears it
+=<formula>       evaluate a formula against the sheet without storing it
+show             print the used range of the sheet
+load <path>      replace the sheet with a file
+save <path>      write the sheet to a file
+help             print this help
+quit             leave";
+
+/// A line typed into the REPL.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Set(Index, String),
+    Evaluate(String),
+    Show,
+    Load(PathBuf),
+    Save(PathBuf),
+    Help,
+    Quit,
+}
+
+/// Reads a line typed into the REPL, `None` for a blank one.
+pub fn parse_command(line: &str) -> Option<Result<Command, String>> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+    if line.starts_with('=') {
+        return Some(Ok(Command::Evaluate(line.to_string())));
+    }
+
+    let (word, rest) = line
+        .split_once(char::is_whitespace)
+        .map_or((line, ""), |(word, rest)| (word, rest.trim()));
+    let path = |rest: &str| match rest {
+        "" => Err(format!("{word} needs a path")),
+        rest => Ok(PathBuf::from(rest)),
+    };
+    let command = match word {
+        "show" if rest.is_empty() => Ok(Command::Show),
+        "help" if rest.is_empty() => Ok(Command::Help),
+        "quit" | "exit" if rest.is_empty() => Ok(Command::Quit),
+        "load" => path(rest).map(Command::Load),
+        "save" => path(rest).map(Command::Save),
+        _ => match line.split_once('=') {
+            Some((name, content)) => name_to_index(&name.trim().to_ascii_uppercase())
+                .map(|index| Command::Set(index, content.trim().to_string()))
+                .map_err(|_| format!("'{}' is not a cell name", name.trim())),
+            None => Err(format!("Unknown command '{word}', try help")),
+        },
+    };
+    Some(command)
+}
+
+/// Reads commands from stdin until it ends or `quit`, printing what each of them gives.
+pub fn run() {
+    let mut sheet = SpreadSheet::default();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("> ");
+        // A prompt that fails to show is no reason to stop
+        let _ = io::stdout().flush();
+        let Some(Ok(line)) = lines.next() else {
+            return;
+        };
+
+        match parse_command(&line) {
+            None => (),
+            Some(Err(message)) => println!("error: {message}"),
+            Some(Ok(Command::Quit)) => return,
+            Some(Ok(command)) => execute(&mut sheet, command),
+        }
+    }
+}
+
+fn execute(sheet: &mut SpreadSheet, command: Command) {
+    match command {
+        Command::Set(index, content) => {
+            if let Err(err) = sheet.set_cell(index, content) {
+                println!("error: {err}");
+                return;
+            }
+            let computed = sheet.get_computed(index);
+            print_result(index_to_name(index), computed, sheet);
+        }
+        Command::Evaluate(formula) => {
+            let result = sheet.evaluate(&formula);
+            print_result(formula, Some(result), sheet);
+        }
+        Command::Show => print!("{}", render_table(sheet)),
+        Command::Load(path) => match SpreadSheet::load_from_file(&path) {
+            Ok(loaded) => *sheet = loaded,
+            Err(err) => println!("error: cannot read {}: {err}", path.display()),
+        },
+        Command::Save(path) => {
+            if let Err(err) = sheet.save_to_file(&path) {
+                println!("error: cannot write {}: {err}", path.display());
+            }
+        }
+        Command::Help => println!("{HELP}"),
+        Command::Quit => (),
+    }
+}
+
+fn print_result(label: String, computed: Option<Result<Value, ComputeError>>, sheet: &SpreadSheet) {
+    match computed {
+        Some(Err(err)) => println!("{label}: {err} {}", err.message(Language::English)),
+        computed => println!(
+            "{label}: {}",
+            computed_to_text(computed, sheet.display_settings())
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_set() {
+        let a1 = Index { x: 0, y: 0 };
+        assert_eq!(
+            parse_command("A1 = 5"),
+            Some(Ok(Command::Set(a1, "5".to_string())))
+        );
+        assert_eq!(
+            parse_command("b12= =sum(A1:A3) "),
+            Some(Ok(Command::Set(
+                Index { x: 1, y: 11 },
+                "=sum(A1:A3)".to_string()
+            )))
+        );
+        assert_eq!(
+            parse_command("A1 ="),
+            Some(Ok(Command::Set(a1, String::new())))
+        );
+        assert!(matches!(parse_command("A0 = 5"), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(parse_command("   "), None);
+        assert_eq!(
+            parse_command(" =sum(A1:A3)"),
+            Some(Ok(Command::Evaluate("=sum(A1:A3)".to_string())))
+        );
+        assert_eq!(parse_command("show"), Some(Ok(Command::Show)));
+        assert_eq!(parse_command("exit"), Some(Ok(Command::Quit)));
+        assert_eq!(
+            parse_command("load  my sheet.txt"),
+            Some(Ok(Command::Load(PathBuf::from("my sheet.txt"))))
+        );
+        assert_eq!(
+            parse_command("save out.sheet"),
+            Some(Ok(Command::Save(PathBuf::from("out.sheet"))))
+        );
+
+        assert!(matches!(parse_command("save"), Some(Err(_))));
+        assert!(matches!(parse_command("show me"), Some(Err(_))));
+        assert!(matches!(parse_command("frobnicate"), Some(Err(_))));
+    }
+}
@@ -1,23 +1,188 @@
 use parser::{
-    ast_resolver::{ASTResolver, VarContext},
+    ast_resolver::{builtin_functions, ASTResolver, InCell, VarContext},
     dependancy_graph::{DependancyGraph, TopologicalSort},
+    references::{find_references, shift_references, split_anchors, with_anchors},
     CellParser,
 };
-use std::{collections::HashMap, fs::File, io::Read, path::PathBuf};
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap, HashSet},
+    fs::{self, File},
+    io::{self, Read},
+    ops::{ControlFlow, Range},
+    path::{Path, PathBuf},
+};
+
+use background::{ComputedValues, PendingCompute};
+use diff::CellDiff;
+use evaluation::Evaluation;
+use journal::Journal;
+use trace::{Trace, TraceValue};
+use validation::ValidationRule;
 
-use crate::common_types::{Cell, ComputeError, Expression, Index, ParsedCell, Value};
+use crate::common_types::{
+    index_to_name, Cell, CellStyle, ComputeError, DisplaySettings, EditError, Expression, Index,
+    NumberFormat, ParseError, ParsedCell, Reference, TextStyle, Token, Value, AST,
+};
+pub mod background;
+pub mod borders;
+pub mod cell_styles;
+pub mod clipboard;
+pub mod consistency;
+pub mod diff;
+pub mod duplicates;
+pub mod evaluation;
+pub mod export;
+pub mod import;
+pub mod journal;
+pub mod number_formats;
+pub mod stats;
+pub mod summary;
+pub mod trace;
+pub mod validation;
+pub mod view_state;
+pub mod watch;
+mod constants;
 mod parser;
+#[cfg(any(test, feature = "bench"))]
+pub mod synthetic;
+
+pub use parser::ast_resolver::builtin_functions::FunctionInfo;
+pub use parser::references::cycle_anchors;
+
+/// How `SpreadSheet::fill` continues the content of its source cell.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FillMode {
+    /// Copies the source, moving the references of a formula along.
+    Copy,
+    /// Continues a number as a series counting up by one per cell, copies anything else.
+    Series,
+}
+
+/// When the cells depending on an edited one are computed again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecalcMode {
+    /// Right after each edit.
+    #[default]
+    Automatic,
+    /// Only once `SpreadSheet::recalculate` is called. Edited cells are still computed
+    /// themselves, those depending on them keep their last values until then.
+    Manual,
+}
+
+/// Aggregates over the numbers in a range of cells.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeSummary {
+    pub sum: f64,
+    /// Missing when there are no numbers to average.
+    pub average: Option<f64>,
+    pub count: usize,
+}
+
+/// A rule styling the cells of a range while a condition holds for them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConditionalFormat {
+    /// The corners of the rectangle the rule covers.
+    pub range: (Index, Index),
+    /// A formula written for the top left cell of the range. Its references move along for
+    /// the other cells, like when the formula is filled over the range.
+    pub condition: String,
+    /// Applied over the style of a cell while its condition evaluates to true.
+    pub style: CellStyle,
+}
+
+/// The raw content and style of the cells of a sheet, its conditional formats, its
+/// validation rules and the formats of its columns and rows at some point, for
+/// `SpreadSheet::restore` to go back to.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    cells: HashMap<Index, (String, CellStyle)>,
+    conditional_formats: Vec<ConditionalFormat>,
+    validation_rules: Vec<ValidationRule>,
+    column_formats: HashMap<usize, NumberFormat>,
+    row_formats: HashMap<usize, NumberFormat>,
+    modified: bool,
+}
+
+/// The cells a change touched as they were before it, `None` for cells that did not exist.
+type HistoryStep = HashMap<Index, Option<(String, CellStyle)>>;
+
+/// How many changes can be undone, older ones being forgotten.
+const MAX_UNDO_STEPS: usize = 100;
+
+/// How many cells `compute_all_with_progress` computes between reports of its progress.
+pub const PROGRESS_INTERVAL: usize = 1000;
 
 #[derive(Debug, Default)]
 pub struct SpreadSheet {
     pub cells: HashMap<Index, Cell>,
     dependencies: DependancyGraph,
+    conditional_formats: Vec<ConditionalFormat>,
+    // Whether the condition of the rule at a position holds for a cell, evaluated when first
+    // asked for and forgotten whenever a cell changes
+    condition_results: RefCell<HashMap<(usize, Index), bool>>,
+    validation_rules: Vec<ValidationRule>,
+    // Whether the value of a cell follows the rule covering it, like `condition_results`
+    validation_results: RefCell<HashMap<Index, bool>>,
+    // The corners of the used range, worked out when first asked for and forgotten whenever
+    // a cell comes or goes
+    used_range: RefCell<Option<(Index, Index)>>,
+    // The number formats of the cells of a column or a row without one of their own, see
+    // `set_column_format`
+    column_formats: HashMap<usize, NumberFormat>,
+    row_formats: HashMap<usize, NumberFormat>,
+    // The columns and rows left out of what is shown, see `set_column_hidden`
+    hidden_columns: BTreeSet<usize>,
+    hidden_rows: BTreeSet<usize>,
+    // Whether any cell changed since the sheet was loaded or saved
+    modified: bool,
+    // Counts the changes of the content of the cells, see `revision`
+    revision: u64,
+    undo_steps: Vec<HistoryStep>,
+    redo_steps: Vec<HistoryStep>,
+    // The step changes are gathered into while a batch runs, and how deep batches are nested
+    open_step: HistoryStep,
+    batch_depth: usize,
+    display: DisplaySettings,
+    // Whether `compute_all` leaves computing to the caller, and whether it was called since
+    // the caller last asked
+    deferred_compute: bool,
+    compute_requested: bool,
+    // When the cells depending on edited ones are computed, see `set_recalc_mode`
+    recalc_mode: RecalcMode,
+    // Whether formulas typed into cells are stored in canonical form
+    canonical_formulas: bool,
+    // The cells computed again on every change, see `Expression::volatile`
+    volatile: HashSet<Index>,
+    // The values formulas read by name, see `define_constant`
+    constants: HashMap<String, Value>,
+    // The cells whose values changed since those depending on them were marked to compute,
+    // which the cells marked are computed again for
+    changed: HashSet<Index>,
+    // Whether locked cells keep their content, see `set_protection`
+    protected: bool,
+    // Whether computes are left unchecked against the sheet computed from scratch, see
+    // `set_verify_compute`
+    unverified: bool,
+    // The changes of the content of the cells kept, see `enable_journal`
+    journal: Option<Journal>,
+    // How deep the cells reached by `indirect` or `offset` may nest, when not
+    // `MAX_REFERENCE_DEPTH`, see `set_max_reference_depth`
+    max_reference_depth: Option<usize>,
+    // How many times a cell was computed, for tests to tell which were
+    #[cfg(test)]
+    computes: std::cell::Cell<usize>,
 }
 
 impl VarContext for SpreadSheet {
     fn get_variable(&self, index: Index) -> Option<Result<Value, ComputeError>> {
         self.get_computed(index)
     }
+
+    fn get_constant(&self, name: &str) -> Option<Value> {
+        self.constants.get(name).cloned()
+    }
 }
 
 impl SpreadSheet {
@@ -31,6 +196,7 @@ impl SpreadSheet {
         } else {
             self.dependencies.add_node(index, &vec![]);
         }
+        self.update_volatile(index, cell);
     }
 
     /// Updates the dependency graph for a cell based on its parsed representation.
@@ -43,52 +209,359 @@ impl SpreadSheet {
         } else {
             self.dependencies.change_node(index, &vec![]);
         }
+        self.update_volatile(index, cell);
+    }
+
+    fn update_volatile(&mut self, index: Index, cell: &Cell) {
+        if is_volatile(cell) {
+            self.volatile.insert(index);
+        } else {
+            self.volatile.remove(&index);
+        }
+    }
+
+    /// Marks the cells depending on those at `changed` to compute, along with the volatile
+    /// cells and those depending on them. Returns true when any cell was marked.
+    fn mark_dependants(&mut self, changed: &[Index]) -> bool {
+        let volatile: Vec<Index> = self.volatile.iter().copied().collect();
+        let mut dirty = volatile.clone();
+        for index in changed.iter().chain(&volatile) {
+            dirty.extend(self.dependencies.get_all_dependants(*index));
+        }
+
+        let mut marked = false;
+        for dep in dirty {
+            if let Some(cell) = self.cells.get_mut(&dep) {
+                cell.needs_compute = true;
+                marked = true;
+            }
+        }
+        if marked {
+            self.changed.extend(changed);
+        }
+        marked
     }
 
-    /// Computes the value of a cell based on its parsed representation.
-    fn compute_cell(&self, cell: &Cell) -> Option<Result<Value, ComputeError>> {
+    /// Computes the value of the cell at `index` based on its parsed representation.
+    fn compute_cell(&self, index: Index, cell: &Cell) -> Option<Result<Value, ComputeError>> {
+        #[cfg(test)]
+        self.computes.set(self.computes.get() + 1);
+        let known = |index: Index| match self.cells.get(&index) {
+            Some(cell) => (cell.computed_value.clone(), cell.needs_compute),
+            None => (None, false),
+        };
+        Evaluation::evaluate(self, index, cell, &known)
+    }
+
+    fn compute_parsed(
+        cell: &Cell,
+        variables: &dyn VarContext,
+    ) -> Option<Result<Value, ComputeError>> {
         match cell.parsed_representation {
-            Some(Ok(ParsedCell::Expr(ref expr))) => Some(ASTResolver::resolve(&expr.ast, self)),
+            Some(Ok(ParsedCell::Expr(ref expr))) => {
+                Some(ASTResolver::resolve(&expr.ast, variables))
+            }
             Some(Ok(ParsedCell::Value(ref value))) => Some(Ok(value.clone())),
-            Some(Err(ref e)) => Some(Err(ComputeError::ParseError(e.0.clone()))),
+            Some(Err(ref e)) => Some(Err(ComputeError::ParseError(e.message.clone()))),
             None => None,
         }
     }
 
     pub fn from_file_path(input_path: PathBuf) -> Self {
+        Self::load_from_file(&input_path).expect("Cannot read file")
+    }
+
+    /// Reads a sheet from a file with a line per row and the cells of a row separated by `|`,
+    /// with the styles kept next to it, see `cell_styles::cell_styles_path`. Each cell is read
+    /// back as `escape_field` writes it.
+    pub fn load_from_file(path: &Path) -> io::Result<Self> {
+        Self::load_from_file_with_progress(path, &mut |_, _| ())
+    }
+
+    /// Reads a sheet like `load_from_file`, telling `progress` how many cells are loaded out
+    /// of how many there are every `PROGRESS_INTERVAL` cells and once all are.
+    pub fn load_from_file_with_progress(
+        path: &Path,
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> io::Result<Self> {
         let mut buffer = String::new();
-        let mut f = File::open(input_path).expect("Cannot open file");
-        f.read_to_string(&mut buffer)
-            .expect("Cannot read file to string");
+        File::open(path)?.read_to_string(&mut buffer)?;
+
+        let cells: Vec<(Index, String)> = buffer
+            .lines()
+            .enumerate()
+            .flat_map(|(y, line)| {
+                split_fields(line)
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(_, raw)| !raw.is_empty())
+                    .map(move |(x, raw)| (Index { x, y }, raw))
+            })
+            .collect();
 
         let mut spreadsheet = Self::default();
+        for (done, (index, raw)) in cells.iter().enumerate() {
+            if done > 0 && done % PROGRESS_INTERVAL == 0 {
+                progress(done, cells.len());
+            }
+            spreadsheet
+                .add_cell_and_compute(*index, raw.clone())
+                .expect("Expected a new sheet to be unprotected");
+        }
+        progress(cells.len(), cells.len());
+        spreadsheet.load_cell_styles(path)?;
+
+        spreadsheet.modified = false;
+        spreadsheet.undo_steps.clear();
+        Ok(spreadsheet)
+    }
 
-        for (y, line) in buffer.lines().enumerate() {
-            for (x, cell) in line.split('|').enumerate() {
-                let cell = cell.trim().to_string();
-                if cell.is_empty() {
+    /// Loads the first pipe table in `text` from A1 on, the header as the first row. Fields
+    /// reading as numbers become numbers and the rest text, formulas included. See
+    /// `import::parse_markdown`.
+    pub fn from_markdown(text: &str) -> Self {
+        let mut spreadsheet = Self::default();
+        for (y, row) in import::parse_markdown(text).into_iter().enumerate() {
+            for (x, field) in row.into_iter().enumerate() {
+                if field.is_empty() {
                     continue;
                 }
-                spreadsheet.add_cell_and_compute(Index { x, y }, cell);
+                let mut cell = import::cell_from_field(&field);
+                if cell.parsed_representation.is_none() {
+                    CellParser::parse_cell(&mut cell);
+                    cell.computed_value = spreadsheet.compute_cell(Index { x, y }, &cell);
+                    cell.needs_compute = false;
+                }
+                spreadsheet.cells.insert(Index { x, y }, cell);
             }
         }
-
         spreadsheet
     }
 
+    /// Writes the raw content of the cells to a file in the format `load_from_file` reads, and
+    /// what is kept of the styles of the sheet to a file next to it, see
+    /// `cell_styles::SheetStyles`. Conditional formats are not kept.
+    pub fn save_to_file(&mut self, path: &Path) -> io::Result<()> {
+        // The last column with content in each row
+        let mut row_ends: HashMap<usize, usize> = HashMap::new();
+        for (index, cell) in &self.cells {
+            if !cell.raw_representation.is_empty() {
+                let end = row_ends.entry(index.y).or_default();
+                *end = (*end).max(index.x);
+            }
+        }
+
+        let rows = row_ends.keys().max().map_or(0, |&last| last + 1);
+        let mut text = String::new();
+        for y in 0..rows {
+            if let Some(&end) = row_ends.get(&y) {
+                let row: Vec<String> = (0..=end)
+                    .map(|x| escape_field(self.get_raw(&Index { x, y }).unwrap_or_default()))
+                    .collect();
+                text.push_str(&row.join(" | "));
+            }
+            text.push('\n');
+        }
+
+        fs::write(path, text)?;
+        self.save_cell_styles(path)?;
+        self.modified = false;
+        Ok(())
+    }
+
+    /// Copies what `restore` needs to put the sheet back the way it is now. Computed values
+    /// are left out, to be computed again.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            cells: self
+                .cells
+                .iter()
+                .map(|(index, cell)| (*index, (cell.raw_representation.clone(), cell.style)))
+                .collect(),
+            conditional_formats: self.conditional_formats.clone(),
+            validation_rules: self.validation_rules.clone(),
+            column_formats: self.column_formats.clone(),
+            row_formats: self.row_formats.clone(),
+            modified: self.modified,
+        }
+    }
+
+    /// Puts the cells, conditional formats, validation rules and the formats of the columns
+    /// and rows back the way they were when `snapshot` was taken, computing every cell again.
+    /// Undoing it brings the cells back as they were before, in a single step.
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        let mut indices: Vec<Index> = self
+            .cells
+            .keys()
+            .chain(snapshot.cells.keys())
+            .copied()
+            .collect();
+        indices.sort_by_key(|index| (index.y, index.x));
+        indices.dedup();
+        let before = self.journal_before(indices.iter().copied());
+        self.batch(|sheet| {
+            for index in indices {
+                if sheet.cell_state(index).as_ref() != snapshot.cells.get(&index) {
+                    sheet.remember(index);
+                }
+            }
+        });
+
+        self.content_changed();
+        self.dependencies = DependancyGraph::default();
+        self.volatile.clear();
+        self.cells.clear();
+        self.insert_uncomputed(snapshot.cells.into_iter().map(|(index, (raw, style))| {
+            let cell = Cell {
+                style,
+                ..Cell::from_raw(raw)
+            };
+            (index, cell)
+        }));
+        self.recalculate();
+
+        self.conditional_formats = snapshot.conditional_formats;
+        self.validation_rules = snapshot.validation_rules;
+        self.validation_results.get_mut().clear();
+        self.column_formats = snapshot.column_formats;
+        self.row_formats = snapshot.row_formats;
+        self.modified = snapshot.modified;
+        self.journal_changes(before);
+    }
+
+    /// Parses `cells` into empty places of the sheet, computing those without references
+    /// right away as they are not part of the dependency graph. The others are left for
+    /// `compute_all`.
+    fn insert_uncomputed(&mut self, cells: impl IntoIterator<Item = (Index, Cell)>) {
+        for (index, mut cell) in cells {
+            if !cell.raw_representation.is_empty() {
+                CellParser::parse_cell(&mut cell);
+                self.add_dependencies(index, &cell);
+            }
+            self.cells.insert(index, cell);
+        }
+
+        let independent: Vec<Index> = self
+            .cells
+            .iter()
+            .filter(|(_, cell)| {
+                cell.needs_compute
+                    && !is_volatile(cell)
+                    && !matches!(
+                        &cell.parsed_representation,
+                        Some(Ok(ParsedCell::Expr(Expression { dependencies, .. }))) if !dependencies.is_empty()
+                    )
+            })
+            .map(|(index, _)| *index)
+            .collect();
+        for index in independent {
+            let computed = self.compute_cell(index, &self.cells[&index]);
+            let cell = self.cells.get_mut(&index).expect("should not fail");
+            cell.computed_value = computed;
+            cell.needs_compute = false;
+        }
+    }
+
+    /// Returns a number that grows whenever the content of any cell changes, for what is shown
+    /// of the cells to tell when it no longer holds.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Returns true when cells changed since the sheet was loaded or last saved.
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    /// Returns how the computed values of the sheet are written in its cells.
+    pub fn display_settings(&self) -> &DisplaySettings {
+        &self.display
+    }
+
+    pub fn set_display_settings(&mut self, display: DisplaySettings) {
+        self.display = display;
+    }
+
+    /// Sets whether formulas are stored in canonical form once they are typed, like
+    /// `=A1 + sum(B2:B4, 5)` for `=A1+   sum( B2:B4 ,5 )`. Formulas that do not parse are
+    /// kept as typed, as are those already in cells.
+    pub fn set_canonical_formulas(&mut self, canonical: bool) {
+        self.canonical_formulas = canonical;
+    }
+
     pub fn compute_all(&mut self) {
+        let _ = self.compute_all_with_progress(&mut |_, _| ControlFlow::Continue(()));
+    }
+
+    /// Computes the cells that need it like `compute_all`. Every `PROGRESS_INTERVAL` cells
+    /// and once all are done, `progress` is told how many are done out of how many there
+    /// are. When it breaks, the pass stops there, leaving the rest of the cells to compute
+    /// for the next one. In manual mode nothing is computed, the cells are left for
+    /// `recalculate`.
+    pub fn compute_all_with_progress(
+        &mut self,
+        progress: &mut dyn FnMut(usize, usize) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        if self.recalc_mode == RecalcMode::Manual {
+            return ControlFlow::Continue(());
+        }
+        self.recalculate_with_progress(progress)
+    }
+
+    /// Computes every cell left to compute, whatever the recalculation mode. With deferred
+    /// compute they are left to the caller, like for `compute_all`.
+    pub fn recalculate(&mut self) {
+        let _ = self.recalculate_with_progress(&mut |_, _| ControlFlow::Continue(()));
+    }
+
+    fn recalculate_with_progress(
+        &mut self,
+        progress: &mut dyn FnMut(usize, usize) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        if self.deferred_compute {
+            self.compute_requested = true;
+            return ControlFlow::Continue(());
+        }
         let TopologicalSort { sorted, cycles } = self.dependencies.topological_sort();
-        for idx in sorted {
-            let Some(cell) = self.cells.get(&idx) else {
-                continue;
-            };
-            if !cell.needs_compute {
-                continue;
+        let mut dirty: Vec<Index> = sorted
+            .into_iter()
+            .filter(|idx| self.cells.get(idx).is_some_and(|cell| cell.needs_compute))
+            .collect();
+        if !self.volatile.is_empty() {
+            // A volatile cell without references or dependants is not part of the graph
+            let listed: HashSet<Index> = dirty.iter().copied().collect();
+            dirty.extend(self.volatile.iter().filter(|idx| {
+                !listed.contains(idx) && self.cells.get(idx).is_some_and(|cell| cell.needs_compute)
+            }));
+            // The cells volatile ones read are unknown to the order, so those come last along
+            // with what depends on them, which keeps the order among them
+            let mut last: HashSet<Index> = self.volatile.clone();
+            for &index in &self.volatile {
+                last.extend(self.dependencies.get_all_dependants(index));
             }
-            let computed = self.compute_cell(cell);
+            dirty.sort_by_key(|idx| last.contains(idx));
+        }
+        let total = dirty.len();
+        // Cells only change along with what they read, so those reading nothing that changed
+        // keep their values
+        let mut changed = std::mem::take(&mut self.changed);
+        for (done, idx) in dirty.into_iter().enumerate() {
+            if done > 0 && done % PROGRESS_INTERVAL == 0 && progress(done, total).is_break() {
+                self.changed = changed;
+                return ControlFlow::Break(());
+            }
+            let computed = match may_change(&self.cells[&idx], &changed) {
+                true => Some(self.compute_cell(idx, &self.cells[&idx])),
+                false => None,
+            };
 
             let cell = self.cells.get_mut(&idx).expect("should not fail");
-            cell.computed_value = computed;
+            if let Some(computed) = computed {
+                if cell.computed_value != computed {
+                    changed.insert(idx);
+                }
+                cell.computed_value = computed;
+            }
             cell.needs_compute = false
         }
 
@@ -99,6 +572,68 @@ impl SpreadSheet {
             }
             cell.computed_value = Some(Err(ComputeError::Cycle));
         }
+        self.verify_compute();
+        progress(total, total)
+    }
+
+    /// Sets when the cells depending on edited ones are computed. Switching back to automatic
+    /// computes those left to compute meanwhile.
+    pub fn set_recalc_mode(&mut self, mode: RecalcMode) {
+        let resumed = self.recalc_mode == RecalcMode::Manual && mode == RecalcMode::Automatic;
+        self.recalc_mode = mode;
+        if resumed {
+            self.compute_all();
+        }
+    }
+
+    pub fn recalc_mode(&self) -> RecalcMode {
+        self.recalc_mode
+    }
+
+    /// Returns true while cells wait for `recalculate` in manual mode.
+    pub fn needs_recalc(&self) -> bool {
+        self.recalc_mode == RecalcMode::Manual && self.cells.values().any(|cell| cell.needs_compute)
+    }
+
+    /// Makes `compute_all` only note that cells need computing, for the caller to compute
+    /// `pending_compute` elsewhere and bring the values back with `apply_computed`. Changed cells are
+    /// still computed right away, those depending on them keep their last values until then.
+    pub fn defer_compute(&mut self, deferred: bool) {
+        self.deferred_compute = deferred;
+    }
+
+    /// Returns true when cells were left to compute since the last call. See `defer_compute`.
+    pub fn take_compute_request(&mut self) -> bool {
+        std::mem::take(&mut self.compute_requested)
+    }
+
+    /// Copies the cells as parsed and computed so far, for `background::compute_pending` to
+    /// compute those left to compute elsewhere.
+    pub fn pending_compute(&self) -> PendingCompute {
+        PendingCompute {
+            cells: self.cells.clone(),
+            changed: self.changed.clone(),
+        }
+    }
+
+    /// Takes the values computed for the cells of `pending_compute`. Cells changed since it
+    /// was taken keep theirs.
+    pub fn apply_computed(&mut self, computed: ComputedValues) {
+        // Every change was computed for, unless cells were left to compute since
+        if computed.finished && !self.compute_requested {
+            self.changed.clear();
+        }
+        for (index, computed) in computed.cells {
+            let Some(cell) = self.cells.get_mut(&index) else {
+                continue;
+            };
+            if cell.raw_representation == computed.raw_representation {
+                cell.computed_value = computed.computed_value;
+                cell.needs_compute = computed.needs_compute;
+            }
+        }
+        self.condition_results.get_mut().clear();
+        self.verify_compute();
     }
 
     pub fn get_computed(&self, index: Index) -> Option<Result<Value, ComputeError>> {
@@ -116,49 +651,119 @@ impl SpreadSheet {
         
     }
 
-    pub fn add_cell_and_compute(&mut self, index: Index, raw: String) {
-        let mut cell = Cell::from_raw(raw);
-        CellParser::parse_cell(&mut cell);
+    /// Returns why the content of the cell at `index` did not parse, and where in it.
+    pub fn parse_error(&self, index: Index) -> Option<&ParseError> {
+        self.cells
+            .get(&index)?
+            .parsed_representation
+            .as_ref()?
+            .as_ref()
+            .err()
+    }
+
+    /// Returns how the formula of the cell at `index` computes from the current values of the
+    /// cells it references, or None when the cell holds no formula. Only the cell asked for is
+    /// traced, computing it once more.
+    pub fn explain(&self, index: Index) -> Option<Trace> {
+        let Some(Ok(ParsedCell::Expr(expr))) = &self.cells.get(&index)?.parsed_representation
+        else {
+            return None;
+        };
+
+        let mut steps = Vec::new();
+        let variables = InCell {
+            variables: self,
+            index,
+        };
+        let value = ASTResolver::resolve_traced(&expr.ast, &variables, Some(&mut steps));
+        let reference = match &*expr.ast {
+            AST::CellName(index) => Some(index_to_name(*index)),
+            AST::Name(name) => Some(name.to_string()),
+            _ => None,
+        };
+        Some(Trace {
+            steps,
+            value: TraceValue { reference, value },
+        })
+    }
+
+    /// Returns every cell whose computation failed with its error, in reading order.
+    pub fn errors(&self) -> Vec<(Index, ComputeError)> {
+        let mut errors: Vec<(Index, ComputeError)> = self
+            .cells
+            .keys()
+            .filter_map(|&index| Some((index, self.get_error(index)?)))
+            .collect();
+        errors.sort_by_key(|(index, _)| (index.y, index.x));
+        errors
+    }
+
+    pub fn add_cell_and_compute(&mut self, index: Index, raw: String) -> Result<(), EditError> {
+        self.check_all_unlocked([index])?;
+        let before = self.journal_before([index]);
+        self.remember(index);
+        self.content_changed();
+        let mut cell = self.parse_typed(index, raw);
 
         self.add_dependencies(index, &cell);
 
-        cell.computed_value = self.compute_cell(&cell);
+        cell.computed_value = self.compute_cell(index, &cell);
         cell.needs_compute = false;
+        let unchanged = self
+            .cells
+            .get(&index)
+            .is_some_and(|old| old.computed_value == cell.computed_value);
+        let rewired = !dependencies_of(&cell).is_empty();
         self.cells.insert(index, cell);
 
-        let mut need_compute = false;
-        for dep in self.dependencies.get_all_dependants(index) {
-            if let Some(cell) = self.cells.get_mut(&dep) {
-                cell.needs_compute = true;
-                need_compute = true;
-            }
-        }
-        if need_compute {
+        // The cells depending on one keeping its value keep theirs, unless it may now be part
+        // of a cycle through them
+        let changed: &[Index] = if unchanged && !rewired { &[] } else { &[index] };
+        if self.mark_dependants(changed) {
             self.compute_all();
         }
+        self.verify_compute();
+        self.journal_changes(before);
+        Ok(())
     }
 
-    pub fn remove_cell(&mut self, index: Index) {
-        let mut need_compute = false;
-        for dep in self.dependencies.get_all_dependants(index) {
-            if let Some(cell) = self.cells.get_mut(&dep) {
-                cell.needs_compute = true;
-                need_compute = true;
-            }
-        }
+    pub fn remove_cell(&mut self, index: Index) -> Result<(), EditError> {
+        self.check_all_unlocked([index])?;
+        let before = self.journal_before([index]);
+        self.remember(index);
+        self.content_changed();
+        let need_compute = self.mark_dependants(&[index]);
 
         self.dependencies.remove_node(index);
-        self.cells.remove(&index);
+        self.volatile.remove(&index);
+        // The style stays behind when the content goes
+        if let Some(cell) = self.cells.remove(&index) {
+            if !cell.style.is_default() {
+                self.cells.insert(
+                    index,
+                    Cell {
+                        style: cell.style,
+                        ..Cell::from_raw(String::new())
+                    },
+                );
+            }
+        }
 
         if need_compute {
             self.compute_all();
         }
+        self.verify_compute();
+        self.journal_changes(before);
+        Ok(())
     }
 
-    pub fn mutate_cell(&mut self, index: Index, new_raw: String) {
-        let mut new_cell = Cell::from_raw(new_raw);
-        CellParser::parse_cell(&mut new_cell);
-        new_cell.computed_value = self.compute_cell(&new_cell);
+    pub fn mutate_cell(&mut self, index: Index, new_raw: String) -> Result<(), EditError> {
+        self.check_all_unlocked([index])?;
+        let before = self.journal_before([index]);
+        self.remember(index);
+        self.content_changed();
+        let mut new_cell = self.parse_typed(index, new_raw);
+        new_cell.computed_value = self.compute_cell(index, &new_cell);
         new_cell.needs_compute = false;
 
         self.update_dependencies(index, &new_cell);
@@ -167,131 +772,3087 @@ impl SpreadSheet {
             .cells
             .get_mut(&index)
             .expect("Expected valid index for mutate cell");
+        let unchanged = cell.computed_value == new_cell.computed_value;
+        let rewired = dependencies_of(cell) != dependencies_of(&new_cell)
+            || matches!(new_cell.computed_value, Some(Err(ComputeError::Cycle)));
         *cell = new_cell;
 
-        let mut need_compute = false;
-        for dep in self.dependencies.get_all_dependants(index) {
-            if let Some(cell) = self.cells.get_mut(&dep) {
-                cell.needs_compute = true;
-                need_compute = true;
-            }
-        }
-        if need_compute {
+        // See `add_cell_and_compute`
+        let changed: &[Index] = if unchanged && !rewired { &[] } else { &[index] };
+        if self.mark_dependants(changed) {
             self.compute_all();
         }
+        self.verify_compute();
+        self.journal_changes(before);
+        Ok(())
+    }
+
+    /// Parses content typed into the cell at `index`, rewriting a formula that parses in
+    /// canonical form when the sheet canonicalizes formulas. The cell keeps its style, only
+    /// taking the format of a percentage typed into it.
+    fn parse_typed(&self, index: Index, raw: String) -> Cell {
+        let mut cell = Cell::from_raw(raw);
+        CellParser::parse_cell(&mut cell);
+        let typed_format = cell.style.number_format;
+        cell.style = self.get_style(index);
+        // A percentage keeps the decimals of a percent format the cell already has
+        if typed_format != NumberFormat::General && !cell.style.number_format.is_like(typed_format)
+        {
+            cell.style.number_format = typed_format;
+        }
+        if let (true, Some(Ok(ParsedCell::Expr(expr)))) =
+            (self.canonical_formulas, &cell.parsed_representation)
+        {
+            let canonical = format!("={}", expr.ast.to_formula_string());
+            cell.raw_representation = keep_anchors(&cell.raw_representation, canonical);
+        }
+        cell
     }
 
     pub fn get_raw(&self, index: &Index) -> Option<&str> {
-        Some(&self.cells.get(index)?.raw_representation)
+        Some(self.cells.get(index)?.raw_representation.as_str()).filter(|raw| !raw.is_empty())
     }
-}
-#[cfg(test)]
-mod tests {
-    use super::*;
 
-    #[test]
-    fn test_empty_ref() {
-        let mut spreadsheet = SpreadSheet::default();
-        let a1 = Index { x: 0, y: 0 };
+    /// Returns the style of the cell at `index`, the default one for a cell never styled.
+    pub fn get_style(&self, index: Index) -> CellStyle {
+        self.cells
+            .get(&index)
+            .map_or_else(CellStyle::default, |cell| cell.style)
+    }
 
-        spreadsheet.add_cell_and_compute(a1, "=A5".to_string());
+    /// Sets the background of the cell at `index`, `None` clearing it. An empty cell keeps
+    /// its fill without getting any content.
+    pub fn set_fill(&mut self, index: Index, fill: Option<[u8; 4]>) {
+        self.update_style(index, |style| style.fill = fill);
+    }
 
-        assert!(matches!(
-            spreadsheet.get_computed(a1),
-            Some(Err(ComputeError::UnfindableReference(_)))
-        ));
+    /// Turns `text_style` on for every cell of the rectangle spanned by the corners of `range`
+    /// when any of them is without it, and off for all of them otherwise.
+    pub fn toggle_text_style(&mut self, range: (Index, Index), text_style: TextStyle) {
+        let (from, to) = range;
+        let indices = (from.y.min(to.y)..=from.y.max(to.y))
+            .flat_map(|y| (from.x.min(to.x)..=from.x.max(to.x)).map(move |x| Index { x, y }));
+        self.toggle_text_style_of(indices, text_style);
     }
 
-    #[test]
-    fn test_circular() {
-        let mut spreadsheet = SpreadSheet::default();
-        let a1 = Index { x: 0, y: 0 };
-        let a2 = Index { x: 0, y: 1 };
-        spreadsheet.add_cell_and_compute(a1, "=A2".to_string());
-        spreadsheet.add_cell_and_compute(a2, "=A1".to_string());
+    /// Like `toggle_text_style`, for the cells at `indices`.
+    pub fn toggle_text_style_of(
+        &mut self,
+        indices: impl IntoIterator<Item = Index>,
+        text_style: TextStyle,
+    ) {
+        let indices: Vec<Index> = indices.into_iter().collect();
+        let on = indices
+            .iter()
+            .any(|&index| !self.get_style(index).has(text_style));
+        self.batch(|sheet| {
+            for index in indices {
+                sheet.update_style(index, |style| style.set(text_style, on));
+            }
+        });
+    }
 
-        assert!(matches!(
-            spreadsheet.get_computed(a1),
-            Some(Err(ComputeError::Cycle))
-        ));
+    /// Locks or unlocks every cell of the rectangle spanned by the corners of `range`. Locked
+    /// cells only keep their content while the sheet is protected, see `set_protection`.
+    pub fn set_locked(&mut self, range: (Index, Index), locked: bool) {
+        let (from, to) = range;
+        self.batch(|sheet| {
+            for y in from.y.min(to.y)..=from.y.max(to.y) {
+                for x in from.x.min(to.x)..=from.x.max(to.x) {
+                    sheet.update_style(Index { x, y }, |style| style.locked = locked);
+                }
+            }
+        });
+    }
 
-        assert!(matches!(
-            spreadsheet.get_computed(a2),
-            Some(Err(ComputeError::Cycle))
-        ));
+    /// Turns the protection of the sheet on or off. While it is on, changing the content of a
+    /// locked cell fails with `EditError::CellLocked`, through any method. Undo and redo still
+    /// step through the history, which only holds changes that were allowed.
+    pub fn set_protection(&mut self, protected: bool) {
+        self.protected = protected;
     }
 
-    #[test]
-    fn test_mutate() {
-        let mut spreadsheet = SpreadSheet::default();
-        let a1 = Index { x: 0, y: 0 };
-        let a2 = Index { x: 0, y: 1 };
-        let a3 = Index { x: 0, y: 2 };
+    pub fn is_protected(&self) -> bool {
+        self.protected
+    }
 
-        spreadsheet.add_cell_and_compute(a3, "=A2 * 3".to_string());
-        spreadsheet.add_cell_and_compute(a2, "=A1 * 2".to_string());
-        spreadsheet.add_cell_and_compute(a1, "1".to_string());
+    /// Returns true when the content of the cell at `index` cannot change: it is locked and
+    /// the sheet protected.
+    pub fn is_locked(&self, index: Index) -> bool {
+        self.protected && self.get_style(index).locked
+    }
 
-        assert!(matches!(
-            spreadsheet.get_computed(a2),
-            Some(Ok(Value::Number(2.0)))
-        ));
+    /// Returns whether the cell at `index` holds a formula that does not parse, left as
+    /// written by sorting or copying that would have rewritten its references. Editing the
+    /// cell clears it, and so does undoing or redoing, which only keep content.
+    pub fn needs_attention(&self, index: Index) -> bool {
+        self.cells
+            .get(&index)
+            .is_some_and(|cell| cell.needs_attention)
+    }
 
-        assert!(matches!(
-            spreadsheet.get_computed(a3),
-            Some(Ok(Value::Number(6.0)))
-        ));
+    /// Marks the cell at `index` as needing attention, see `needs_attention`. Does nothing
+    /// for an empty cell.
+    pub fn mark_needs_attention(&mut self, index: Index) {
+        if let Some(cell) = self.cells.get_mut(&index) {
+            cell.needs_attention = true;
+        }
+    }
 
-        spreadsheet.mutate_cell(a1, "7".to_string());
-        assert!(matches!(
-            spreadsheet.get_computed(a2),
-            Some(Ok(Value::Number(14.0)))
-        ));
+    /// Fails with the first cell of the rectangle spanned by the corners of `range`, row by
+    /// row, whose content cannot change. See `is_locked`.
+    pub fn check_unlocked(&self, range: (Index, Index)) -> Result<(), EditError> {
+        if !self.protected {
+            return Ok(());
+        }
+        let (from, to) = range;
+        let columns = from.x.min(to.x)..=from.x.max(to.x);
+        let rows = from.y.min(to.y)..=from.y.max(to.y);
+        self.check_all_unlocked(
+            self.cells
+                .keys()
+                .copied()
+                .filter(|index| columns.contains(&index.x) && rows.contains(&index.y)),
+        )
+    }
 
-        assert!(matches!(
-            spreadsheet.get_computed(a3),
-            Some(Ok(Value::Number(42.0)))
-        ));
+    /// Like `check_unlocked`, for the cells at `indices`.
+    fn check_all_unlocked(
+        &self,
+        indices: impl IntoIterator<Item = Index>,
+    ) -> Result<(), EditError> {
+        match indices
+            .into_iter()
+            .filter(|&index| self.is_locked(index))
+            .min_by_key(|index| (index.y, index.x))
+        {
+            Some(index) => Err(EditError::CellLocked(index)),
+            None => Ok(()),
+        }
     }
 
-    #[test]
-    fn test_remove_cell() {
-        let mut spreadsheet = SpreadSheet::default();
-        let a1 = Index { x: 0, y: 0 };
-        let a2 = Index { x: 0, y: 1 };
+    /// Adds a rule applying `style` to the cells of the rectangle spanned by the corners of
+    /// `range` whose `condition` holds. See `ConditionalFormat` for how the condition is
+    /// written.
+    pub fn add_conditional_format(
+        &mut self,
+        range: (Index, Index),
+        condition: &str,
+        style: CellStyle,
+    ) -> Result<(), ParseError> {
+        let condition = condition.trim();
+        Self::validate_formula(condition)?;
 
-        spreadsheet.add_cell_and_compute(a1, "10".to_string());
-        spreadsheet.add_cell_and_compute(a2, "=A1 * 2".to_string());
+        let (from, to) = range;
+        let top_left = Index {
+            x: from.x.min(to.x),
+            y: from.y.min(to.y),
+        };
+        let bottom_right = Index {
+            x: from.x.max(to.x),
+            y: from.y.max(to.y),
+        };
+        self.conditional_formats.push(ConditionalFormat {
+            range: (top_left, bottom_right),
+            condition: condition.to_string(),
+            style,
+        });
+        Ok(())
+    }
 
-        spreadsheet.remove_cell(a1);
+    pub fn conditional_formats(&self) -> &[ConditionalFormat] {
+        &self.conditional_formats
+    }
 
-        assert!(matches!(
-            spreadsheet.get_computed(a2),
-            Some(Err(ComputeError::UnfindableReference(_)))
-        ));
+    /// Returns the style the cell at `index` is shown with: its own, with the style of every
+    /// rule holding for it applied over it in the order the rules were added.
+    pub fn effective_format(&self, index: Index) -> CellStyle {
+        let mut style = self.get_style(index);
+        // A cell without a number format of its own takes the one of its row or column
+        if style.number_format == NumberFormat::General {
+            style.number_format = match self.row_format(index.y) {
+                NumberFormat::General => self.column_format(index.x),
+                format => format,
+            };
+        }
+        for (i, rule) in self.conditional_formats.iter().enumerate() {
+            let (top_left, bottom_right) = rule.range;
+            let inside = (top_left.x..=bottom_right.x).contains(&index.x)
+                && (top_left.y..=bottom_right.y).contains(&index.y);
+            if !inside || !self.condition_holds(i, index) {
+                continue;
+            }
+
+            style.fill = rule.style.fill.or(style.fill);
+            style.bold |= rule.style.bold;
+            style.italic |= rule.style.italic;
+        }
+        style
     }
 
-    #[test]
-    fn test_invalid_expression() {
-        let mut spreadsheet = SpreadSheet::default();
-        let a1 = Index { x: 0, y: 0 };
+    /// Sets the number format of the cells of column `col` without one of their own, those
+    /// written into it later included. `NumberFormat::General` clears it. Where a row has a
+    /// format too, the format of the row wins. See `effective_format`.
+    pub fn set_column_format(&mut self, col: usize, format: NumberFormat) {
+        match format {
+            NumberFormat::General => self.column_formats.remove(&col),
+            format => self.column_formats.insert(col, format),
+        };
+    }
 
-        spreadsheet.add_cell_and_compute(a1, "=A1 +".to_string());
+    /// Returns the number format set for column `col`, `NumberFormat::General` when none is.
+    pub fn column_format(&self, col: usize) -> NumberFormat {
+        self.column_formats.get(&col).copied().unwrap_or_default()
+    }
 
-        assert!(matches!(
-            spreadsheet.get_computed(a1),
-            Some(Err(ComputeError::ParseError(_)))
-        ));
+    /// Sets the number format of the cells of row `row` without one of their own, like
+    /// `set_column_format`.
+    pub fn set_row_format(&mut self, row: usize, format: NumberFormat) {
+        match format {
+            NumberFormat::General => self.row_formats.remove(&row),
+            format => self.row_formats.insert(row, format),
+        };
     }
 
-    #[test]
-    fn test_self_reference() {
-        let mut spreadsheet = SpreadSheet::default();
-        let a1 = Index { x: 0, y: 0 };
+    /// Returns the number format set for row `row`, `NumberFormat::General` when none is.
+    pub fn row_format(&self, row: usize) -> NumberFormat {
+        self.row_formats.get(&row).copied().unwrap_or_default()
+    }
 
-        spreadsheet.add_cell_and_compute(a1, "=A1".to_string());
+    /// Hides column `col` or shows it again. Hiding only changes what is shown: the cells of a
+    /// hidden column are computed, read and counted in ranges like any others.
+    pub fn set_column_hidden(&mut self, col: usize, hidden: bool) {
+        match hidden {
+            true => self.hidden_columns.insert(col),
+            false => self.hidden_columns.remove(&col),
+        };
+    }
 
-        assert!(matches!(
+    /// Hides row `row` or shows it again, like `set_column_hidden`.
+    pub fn set_row_hidden(&mut self, row: usize, hidden: bool) {
+        match hidden {
+            true => self.hidden_rows.insert(row),
+            false => self.hidden_rows.remove(&row),
+        };
+    }
+
+    pub fn is_column_hidden(&self, col: usize) -> bool {
+        self.hidden_columns.contains(&col)
+    }
+
+    pub fn is_row_hidden(&self, row: usize) -> bool {
+        self.hidden_rows.contains(&row)
+    }
+
+    pub fn hidden_columns(&self) -> &BTreeSet<usize> {
+        &self.hidden_columns
+    }
+
+    pub fn hidden_rows(&self) -> &BTreeSet<usize> {
+        &self.hidden_rows
+    }
+
+    fn condition_holds(&self, rule: usize, index: Index) -> bool {
+        if let Some(&holds) = self.condition_results.borrow().get(&(rule, index)) {
+            return holds;
+        }
+
+        let ConditionalFormat {
+            range, condition, ..
+        } = &self.conditional_formats[rule];
+        let condition = Self::relocate_raw(condition, range.0, index);
+        // A condition is no cell, so reading the cell it formats is no cycle
+        let holds = matches!(self.evaluate(&condition), Ok(Value::Bool(true)));
+        self.condition_results
+            .borrow_mut()
+            .insert((rule, index), holds);
+        holds
+    }
+
+    /// Marks the sheet as modified and drops the evaluated conditions and validations, as any
+    /// change of a cell may change their outcome.
+    fn content_changed(&mut self) {
+        self.modified = true;
+        self.revision += 1;
+        *self.used_range.get_mut() = None;
+        self.condition_results.get_mut().clear();
+        self.validation_results.get_mut().clear();
+    }
+
+    fn update_style(&mut self, index: Index, update: impl FnOnce(&mut CellStyle)) {
+        self.remember(index);
+        let cell = self
+            .cells
+            .entry(index)
+            .or_insert_with(|| Cell::from_raw(String::new()));
+        update(&mut cell.style);
+        // Without content or style there is no cell left
+        if cell.raw_representation.is_empty() && cell.style.is_default() {
+            self.cells.remove(&index);
+        }
+        *self.used_range.get_mut() = None;
+    }
+
+    /// Sums, averages and counts the computed numbers in the rectangle spanned by `from` and
+    /// `to`. Text, booleans, errors and empty cells are skipped.
+    pub fn summarize_range(&self, from: Index, to: Index) -> RangeSummary {
+        let indices = (from.y.min(to.y)..=from.y.max(to.y))
+            .flat_map(|y| (from.x.min(to.x)..=from.x.max(to.x)).map(move |x| Index { x, y }));
+        self.summarize_cells(indices)
+    }
+
+    /// Like `summarize_range`, for the cells at `indices`, each counted as often as it is
+    /// given.
+    pub fn summarize_cells(&self, indices: impl IntoIterator<Item = Index>) -> RangeSummary {
+        let numbers: Vec<Value> = indices
+            .into_iter()
+            .filter_map(|index| self.get_computed(index).and_then(Result::ok))
+            .filter(|value| matches!(value, Value::Number(_)))
+            .collect();
+
+        let as_number = |result: Result<Value, ComputeError>| match result {
+            Ok(Value::Number(num)) => Some(num),
+            _ => None,
+        };
+        RangeSummary {
+            sum: as_number(builtin_functions::sum(numbers.clone())).unwrap_or_default(),
+            average: as_number(builtin_functions::average(numbers.clone())),
+            count: as_number(builtin_functions::count(numbers)).unwrap_or_default() as usize,
+        }
+    }
+
+    /// Returns the computed values of the rectangle from `from` to `to`, a row at a time.
+    /// Empty cells and errors are `None`.
+    ///
+    /// Panics when `from` is right of or below `to`.
+    pub fn get_range(&self, from: Index, to: Index) -> Vec<Vec<Option<Value>>> {
+        assert!(
+            from.x <= to.x && from.y <= to.y,
+            "The range from {from:?} to {to:?} is not spanned from its top left corner"
+        );
+        (from.y..=to.y)
+            .map(|y| {
+                (from.x..=to.x)
+                    .map(|x| self.get_computed(Index { x, y }).and_then(Result::ok))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Sets the raw content of a cell, adding, mutating or removing it as needed.
+    /// Empty content removes the cell.
+    pub fn set_cell(&mut self, index: Index, raw: String) -> Result<(), EditError> {
+        match (self.get_raw(&index).is_some(), raw.is_empty()) {
+            (false, true) => Ok(()),
+            (false, false) => self.add_cell_and_compute(index, raw),
+            (true, true) => self.remove_cell(index),
+            (true, false) => self.mutate_cell(index, raw),
+        }
+    }
+
+    /// Returns the cells with content in the rectangle spanned by the corners of `rect` from
+    /// the top left, row by row: those writing a block over it replaces. Cells only holding a
+    /// style are left out, as writing keeps styles.
+    pub fn check_overwrite(&self, rect: (Index, Index)) -> Vec<Index> {
+        let mut occupied: Vec<Index> = self
+            .cells_in_rect(rect.0, rect.1)
+            .filter(|(_, cell)| !cell.raw_representation.is_empty())
+            .map(|(index, _)| index)
+            .collect();
+        occupied.sort_by_key(|index| (index.y, index.x));
+        occupied
+    }
+
+    /// Fills every cell of the rectangle spanned by the corners of `target` from the cell at
+    /// `source`, which is left as it is when inside the rectangle. An empty source empties
+    /// the target. Nothing is filled when any cell of the target is locked.
+    pub fn fill(
+        &mut self,
+        source: Index,
+        target: (Index, Index),
+        mode: FillMode,
+    ) -> Result<(), EditError> {
+        let raw = self.get_raw(&source).unwrap_or_default().to_string();
+        let series_start = match mode {
+            FillMode::Series => raw.parse::<f64>().ok(),
+            FillMode::Copy => None,
+        };
+
+        let (from, to) = target;
+        // With whether each copy is a formula left as written, see `relocate_raw_tracked`
+        let mut filled = Vec::new();
+        for x in from.x.min(to.x)..=from.x.max(to.x) {
+            for y in from.y.min(to.y)..=from.y.max(to.y) {
+                let index = Index { x, y };
+                if index == source {
+                    continue;
+                }
+
+                let (raw, skipped) = match series_start {
+                    Some(start) => {
+                        let steps = (x as f64 - source.x as f64) + (y as f64 - source.y as f64);
+                        ((start + steps).to_string(), false)
+                    }
+                    None => Self::relocate_raw_tracked(&raw, source, index),
+                };
+                filled.push((index, raw, skipped));
+            }
+        }
+        self.check_all_unlocked(filled.iter().map(|(index, ..)| *index))?;
+
+        self.batch(|sheet| {
+            filled.into_iter().try_for_each(|(index, raw, skipped)| {
+                sheet.set_cell(index, raw)?;
+                if skipped {
+                    sheet.mark_needs_attention(index);
+                }
+                Ok(())
+            })
+        })
+    }
+
+    /// Runs `changes` as a single step of the history, undone and redone all at once,
+    /// returning what they return.
+    pub fn batch<R>(&mut self, changes: impl FnOnce(&mut Self) -> R) -> R {
+        self.batch_depth += 1;
+        let result = changes(self);
+        self.batch_depth -= 1;
+
+        if self.batch_depth == 0 && !self.open_step.is_empty() {
+            let step = std::mem::take(&mut self.open_step);
+            self.push_undo_step(step);
+        }
+        result
+    }
+
+    /// Reverts the last step of the history, returning the cells it changed from the top
+    /// left, row by row.
+    pub fn undo(&mut self) -> Option<Vec<Index>> {
+        let step = self.undo_steps.pop()?;
+        let (redo_step, changed) = self.apply_step(step);
+        self.redo_steps.push(redo_step);
+        Some(changed)
+    }
+
+    /// Applies the last undone step again, returning the cells it changed like `undo`.
+    /// Any new change forgets the undone steps.
+    pub fn redo(&mut self) -> Option<Vec<Index>> {
+        let step = self.redo_steps.pop()?;
+        let (undo_step, changed) = self.apply_step(step);
+        self.undo_steps.push(undo_step);
+        Some(changed)
+    }
+
+    /// Puts the cells of `step` back the way it holds them, returning the step that reverts
+    /// this and the cells changed.
+    fn apply_step(&mut self, step: HistoryStep) -> (HistoryStep, Vec<Index>) {
+        let mut changed: Vec<Index> = step.keys().copied().collect();
+        changed.sort_by_key(|index| (index.y, index.x));
+        let reverse = changed
+            .iter()
+            .map(|&index| (index, self.cell_state(index)))
+            .collect();
+
+        let changes = step
+            .into_iter()
+            .map(|(index, cell)| {
+                let cell = cell.map(|(raw, style)| Cell {
+                    style,
+                    ..Cell::from_raw(raw)
+                });
+                (index, cell)
+            })
+            .collect();
+        self.replace_cells(changes);
+        (reverse, changed)
+    }
+
+    fn cell_state(&self, index: Index) -> Option<(String, CellStyle)> {
+        let cell = self.cells.get(&index)?;
+        Some((cell.raw_representation.clone(), cell.style))
+    }
+
+    /// Keeps the cell at `index` as it is before a change, in the open batch or as a step of
+    /// its own outside of one.
+    fn remember(&mut self, index: Index) {
+        if self.batch_depth > 0 {
+            if !self.open_step.contains_key(&index) {
+                let state = self.cell_state(index);
+                self.open_step.insert(index, state);
+            }
+        } else {
+            let step = HashMap::from([(index, self.cell_state(index))]);
+            self.push_undo_step(step);
+        }
+    }
+
+    fn push_undo_step(&mut self, step: HistoryStep) {
+        if self.undo_steps.len() == MAX_UNDO_STEPS {
+            self.undo_steps.remove(0);
+        }
+        self.undo_steps.push(step);
+        self.redo_steps.clear();
+    }
+
+    /// Replaces many cells at once with unparsed ones, `None` removing a cell. Everything
+    /// affected is computed once all of them are in place, so cells that only reference each
+    /// other correctly in the end can be changed in any order.
+    fn replace_cells(&mut self, changes: HashMap<Index, Option<Cell>>) {
+        self.content_changed();
+        let indices: Vec<Index> = changes.keys().copied().collect();
+        let before = self.journal_before(indices.iter().copied());
+        for index in &indices {
+            self.dependencies.remove_node(*index);
+            self.volatile.remove(index);
+            self.cells.remove(index);
+        }
+
+        for (index, cell) in changes {
+            let Some(mut cell) = cell else {
+                continue;
+            };
+            if cell.raw_representation.is_empty() {
+                self.cells.insert(index, cell);
+                continue;
+            }
+            CellParser::parse_cell(&mut cell);
+            self.add_dependencies(index, &cell);
+            // Cells without references do not wait for any other
+            let has_dependencies = matches!(
+                &cell.parsed_representation,
+                Some(Ok(ParsedCell::Expr(Expression { dependencies, .. }))) if !dependencies.is_empty()
+            );
+            if !has_dependencies && !is_volatile(&cell) {
+                cell.computed_value = self.compute_cell(index, &cell);
+                cell.needs_compute = false;
+            }
+            self.cells.insert(index, cell);
+        }
+
+        self.mark_dependants(&indices);
+        self.compute_all();
+        self.journal_changes(before);
+    }
+
+    /// Writes which cells are computed from which as a Graphviz DOT graph. See
+    /// `DependancyGraph::to_dot`.
+    pub fn dependencies_to_dot(&self) -> String {
+        self.dependencies.to_dot(index_to_name)
+    }
+
+    /// Returns the cells that were added, removed or changed going from `self` to `other`, in
+    /// reading order. See `diff::diff`.
+    pub fn diff(&self, other: &SpreadSheet) -> Vec<CellDiff> {
+        diff::diff(self, other)
+    }
+
+    /// Writes the rectangle spanned by the corners of `range`, or else the used range, as a
+    /// Markdown table. See `export::render_markdown`.
+    pub fn to_markdown(&self, range: Option<(Index, Index)>, first_row_header: bool) -> String {
+        export::render_markdown(self, range, first_row_header)
+    }
+
+    /// Writes the rectangle spanned by the corners of `range`, or else the used range, as an
+    /// HTML table. See `export::render_html`.
+    pub fn to_html(&self, range: Option<(Index, Index)>) -> String {
+        export::render_html(self, range)
+    }
+
+    /// Returns the top left and bottom right corners of the smallest rectangle holding every
+    /// cell with content, if there is any. It is worked out again only once cells came or
+    /// went, as the grid asks for it every frame.
+    pub fn used_range(&self) -> Option<(Index, Index)> {
+        if let Some(range) = *self.used_range.borrow() {
+            return Some(range);
+        }
+        let top_left = Index {
+            x: self.cells.keys().map(|idx| idx.x).min()?,
+            y: self.cells.keys().map(|idx| idx.y).min()?,
+        };
+        let bottom_right = Index {
+            x: self.cells.keys().map(|idx| idx.x).max()?,
+            y: self.cells.keys().map(|idx| idx.y).max()?,
+        };
+        *self.used_range.borrow_mut() = Some((top_left, bottom_right));
+        Some((top_left, bottom_right))
+    }
+
+    /// Returns the cells in the rectangle spanned by `from` and `to`, in no particular order.
+    /// A rectangle with fewer places than the sheet has cells is looked up place by place,
+    /// so a view over a huge sheet costs as much as its size and not that of the sheet.
+    pub fn cells_in_rect(&self, from: Index, to: Index) -> impl Iterator<Item = (Index, &Cell)> {
+        let columns = from.x.min(to.x)..=from.x.max(to.x);
+        let rows = from.y.min(to.y)..=from.y.max(to.y);
+        let area =
+            (columns.end() - columns.start() + 1).saturating_mul(rows.end() - rows.start() + 1);
+        let by_place = area <= self.cells.len();
+
+        let looked_up = by_place.then(|| {
+            let columns = columns.clone();
+            rows.clone()
+                .flat_map(move |y| columns.clone().map(move |x| Index { x, y }))
+                .filter_map(|index| Some((index, self.cells.get(&index)?)))
+        });
+        let filtered = (!by_place).then(|| {
+            self.cells
+                .iter()
+                .filter(move |(index, _)| columns.contains(&index.x) && rows.contains(&index.y))
+                .map(|(index, cell)| (*index, cell))
+        });
+        looked_up
+            .into_iter()
+            .flatten()
+            .chain(filtered.into_iter().flatten())
+    }
+
+    /// Reorders the rows of the rectangle spanned by `range` by the computed values in column
+    /// `col`: numbers, then text, then booleans, all reversed when `descending`. Errors and
+    /// empty cells come last either way, and rows that tie keep their order.
+    ///
+    /// Each row moves as a unit with its styles, like moving rows by hand: references to its
+    /// cells follow it, from formulas inside the range and outside of it alike. Ranges in
+    /// formulas keep pointing at the same cells, which are only reordered among themselves.
+    /// Formulas that do not parse are left as written, needing attention when their references
+    /// would have followed, see `needs_attention`. Nothing moves when any cell of the range is
+    /// locked, or any formula outside of it that would follow.
+    pub fn sort_rows_by_column(
+        &mut self,
+        col: usize,
+        range: (Index, Index),
+        descending: bool,
+    ) -> Result<(), EditError> {
+        self.check_unlocked(range)?;
+        let (from, to) = range;
+        let columns = from.x.min(to.x)..=from.x.max(to.x);
+        let first_row = from.y.min(to.y);
+
+        let mut keyed_rows: Vec<(usize, Option<Result<Value, ComputeError>>)> = (first_row
+            ..=from.y.max(to.y))
+            .map(|y| {
+                let computed = self.get_computed(Index { x: col, y });
+                let shown = |value: Value| value.into_shown().into_label();
+                (y, computed.map(|value| value.map(shown)))
+            })
+            .collect();
+        keyed_rows.sort_by(|(_, a), (_, b)| compare_for_sort(a, b, descending));
+
+        let moved_to: HashMap<usize, usize> = keyed_rows
+            .iter()
+            .enumerate()
+            .map(|(i, &(y, _))| (y, first_row + i))
+            .filter(|(from, to)| from != to)
+            .collect();
+        if moved_to.is_empty() {
+            return Ok(());
+        }
+        let follow = |index: Index| match moved_to.get(&index.y) {
+            Some(&y) if columns.contains(&index.x) => Index { x: index.x, y },
+            _ => index,
+        };
+
+        let mut changes: HashMap<Index, Option<Cell>> = HashMap::new();
+        for (&index, cell) in &self.cells {
+            let raw = &cell.raw_representation;
+            let mapped = map_cell_references(raw, follow);
+            let (raw, needs_attention) = match cell.parsed_representation {
+                // What reads as a reference in a formula that does not parse may well not be one
+                Some(Err(_)) => (raw.clone(), cell.needs_attention || mapped != *raw),
+                _ => (mapped, false),
+            };
+            let target = follow(index);
+            let moved = Cell {
+                style: cell.style,
+                needs_attention,
+                ..Cell::from_raw(raw)
+            };
+            if target != index {
+                // Emptied unless another row moves into it
+                changes.entry(index).or_insert(None);
+                changes.insert(target, Some(moved));
+            } else if moved.raw_representation != cell.raw_representation
+                || moved.needs_attention != cell.needs_attention
+            {
+                changes.insert(index, Some(moved));
+            }
+        }
+        self.check_all_unlocked(changes.keys().copied())?;
+        self.batch(|sheet| {
+            for &index in changes.keys() {
+                sheet.remember(index);
+            }
+            sheet.replace_cells(changes);
+        });
+        Ok(())
+    }
+
+    /// Writes a block of raw contents, such as rows read by `clipboard::parse_tsv`, with its
+    /// first field at `top_left`. Rows may differ in length. Empty fields empty their cell and
+    /// styles stay where they are. The whole block is computed once and undone as a single
+    /// step, and nothing is written when any cell it would change is locked.
+    pub fn set_range(&mut self, top_left: Index, values: &[Vec<String>]) -> Result<(), EditError> {
+        let mut changes: HashMap<Index, Option<Cell>> = HashMap::new();
+        for (dy, row) in values.iter().enumerate() {
+            for (dx, raw) in row.iter().enumerate() {
+                let index = Index {
+                    x: top_left.x + dx,
+                    y: top_left.y + dy,
+                };
+                let style = self.get_style(index);
+                let cell = if !raw.is_empty() || !style.is_default() {
+                    Some(Cell {
+                        style,
+                        ..Cell::from_raw(raw.clone())
+                    })
+                } else if self.cells.contains_key(&index) {
+                    None
+                } else {
+                    continue;
+                };
+                changes.insert(index, cell);
+            }
+        }
+        self.check_all_unlocked(changes.keys().copied())?;
+
+        self.batch(|sheet| {
+            for &index in changes.keys() {
+                sheet.remember(index);
+            }
+            sheet.replace_cells(changes);
+        });
+        Ok(())
+    }
+
+    /// Returns raw content giving the current value of the cell at `index` when typed, errors
+    /// as they are displayed. Text reading as anything else gets a leading `'`.
+    pub fn value_as_raw(&self, index: Index) -> Option<String> {
+        let text = match self.get_computed(index)? {
+            Ok(Value::Number(num)) => return Some(num.to_string()),
+            Ok(Value::Bool(b)) => return Some(Value::Bool(b).to_string()),
+            Ok(value) => value.into_shown().to_string(),
+            Err(err) => err.to_string(),
+        };
+
+        let mut cell = Cell::from_raw(text.clone());
+        if !text.is_empty() {
+            CellParser::parse_cell(&mut cell);
+        }
+        match cell.parsed_representation {
+            Some(Ok(ParsedCell::Value(Value::Text(parsed)))) if parsed == text => Some(text),
+            _ => Some(format!("'{text}")),
+        }
+    }
+
+    /// Replaces every formula of the rectangle spanned by the corners of `range` by its
+    /// current value, see `value_as_raw`. Cells depending on them keep their values until the
+    /// cells the formulas read change. Undone as a single step, and nothing is converted when
+    /// any of the formulas is locked.
+    pub fn convert_to_values(&mut self, range: (Index, Index)) -> Result<(), EditError> {
+        let (from, to) = range;
+        let columns = from.x.min(to.x)..=from.x.max(to.x);
+        let rows = from.y.min(to.y)..=from.y.max(to.y);
+        let values: Vec<(Index, String)> = self
+            .cells
+            .iter()
+            .filter(|(index, cell)| {
+                columns.contains(&index.x)
+                    && rows.contains(&index.y)
+                    && matches!(cell.parsed_representation, Some(Ok(ParsedCell::Expr(_))))
+            })
+            .filter_map(|(index, _)| Some((*index, self.value_as_raw(*index)?)))
+            .collect();
+        self.check_all_unlocked(values.iter().map(|(index, _)| *index))?;
+
+        self.batch(|sheet| {
+            values
+                .into_iter()
+                .try_for_each(|(index, raw)| sheet.mutate_cell(index, raw))
+        })
+    }
+
+    /// Returns `raw` as it reads after copying it from the cell at `from` to the cell at `to`.
+    /// The references of a formula move along with it, other content is copied as is, and so
+    /// is a formula that does not parse.
+    pub fn relocate_raw(raw: &str, from: Index, to: Index) -> String {
+        Self::relocate_raw_tracked(raw, from, to).0
+    }
+
+    /// Like `relocate_raw`, also returning whether `raw` is a formula that does not parse whose
+    /// references would have moved. It is left as written rather than rewritten from what
+    /// merely looks like references, and the copy needs attention, see `needs_attention`.
+    pub fn relocate_raw_tracked(raw: &str, from: Index, to: Index) -> (String, bool) {
+        if !raw.starts_with('=') {
+            return (raw.to_string(), false);
+        }
+
+        let offset = (
+            to.x as isize - from.x as isize,
+            to.y as isize - from.y as isize,
+        );
+        let shifted = shift_references(raw, offset);
+        match CellParser::parse_expression(raw) {
+            Ok(_) => (shifted, false),
+            Err(_) => (raw.to_string(), shifted != raw),
+        }
+    }
+
+    /// Checks that a formula (including its leading `=`) tokenizes and parses, without resolving it.
+    pub fn validate_formula(formula: &str) -> Result<(), ParseError> {
+        CellParser::parse_expression(formula).map(|_| ())
+    }
+
+    /// Splits a formula (including its leading `=`) into tokens with the byte range each was
+    /// read from. Tokenizing stops at the first error, so a half typed formula still gives
+    /// the tokens of its valid start.
+    pub fn formula_tokens(formula: &str) -> Vec<(Token, Range<usize>)> {
+        CellParser::tokenize_lossy(formula)
+    }
+
+    /// Returns the references of a formula (including its leading `=`) in the order they are
+    /// written, each with the byte range of `formula` it spans. Like `formula_tokens` this
+    /// stops at the first invalid token.
+    pub fn formula_references(formula: &str) -> Vec<(Reference, Range<usize>)> {
+        find_references(&CellParser::tokenize_lossy(formula))
+    }
+
+    /// Returns the cells and ranges the formula at `index` directly references, each once and
+    /// in the order they are first written. Ranges are kept as they are, not split into cells.
+    pub fn precedents(&self, index: Index) -> Vec<Reference> {
+        let mut precedents = Vec::new();
+        for (reference, _) in Self::formula_references(self.get_raw(&index).unwrap_or_default()) {
+            if !precedents.contains(&reference) {
+                precedents.push(reference);
+            }
+        }
+        precedents
+    }
+
+    /// Returns the cells whose formula references the cell at `index`, directly or through a
+    /// range.
+    pub fn direct_dependants(&self, index: Index) -> Vec<Index> {
+        self.dependencies.get_dependants(index)
+    }
+
+    /// Returns every cell whose value depends on the cell at `index`, over any number of
+    /// formulas.
+    pub fn all_dependants(&self, index: Index) -> Vec<Index> {
+        let mut dependants = self.dependencies.get_all_dependants(index);
+        // A cell in a cycle depends on itself
+        dependants.retain(|&dependant| dependant != index);
+        dependants
+    }
+
+    /// Returns the functions formulas can call, sorted by name.
+    pub fn builtin_functions() -> &'static [FunctionInfo] {
+        builtin_functions::CATALOG
+    }
+
+    /// Evaluates raw cell content against the current sheet without storing anything.
+    pub fn evaluate(&self, raw: &str) -> Result<Value, ComputeError> {
+        Self::evaluate_with(raw, self)
+    }
+
+    fn evaluate_with(raw: &str, variables: &dyn VarContext) -> Result<Value, ComputeError> {
+        let raw = raw.trim();
+        if raw.is_empty() {
+            return Ok(Value::Text(String::new()));
+        }
+
+        let mut cell = Cell::from_raw(raw.to_string());
+        CellParser::parse_cell(&mut cell);
+        Self::compute_parsed(&cell, variables)
+            .expect("Parsed cell should always compute to a value")
+    }
+
+    /// Evaluates raw content as if it were written into `index`, reporting a cycle if it would
+    /// reference the cell itself or any of its dependants.
+    pub fn evaluate_in_cell(&self, index: Index, raw: &str) -> Result<Value, ComputeError> {
+        let raw = raw.trim();
+        if raw.starts_with('=') {
+            if let Ok(ParsedCell::Expr(Expression { dependencies, .. })) =
+                CellParser::parse_expression(raw)
+            {
+                let dependants = self.dependencies.get_all_dependants(index);
+                if dependencies
+                    .iter()
+                    .any(|dep| *dep == index || dependants.contains(dep))
+                {
+                    return Err(ComputeError::Cycle);
+                }
+            }
+        }
+
+        Self::evaluate_with(
+            raw,
+            &InCell {
+                variables: self,
+                index,
+            },
+        )
+    }
+}
+
+/// Writes the raw content of a cell as a field of a sheet file: backslashes, `|` and line
+/// breaks are escaped with a backslash, and so are spaces and tabs at either end, which
+/// would otherwise be taken for the padding around the field.
+fn escape_field(raw: &str) -> String {
+    let inner = raw.trim_matches([' ', '\t']);
+    let start = raw.len() - raw.trim_start_matches([' ', '\t']).len();
+    let end = start + inner.len();
+    let mut escaped = String::with_capacity(raw.len());
+    for (i, c) in raw.char_indices() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '|' => escaped.push_str("\\|"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            ' ' if i < start || i >= end => escaped.push_str("\\s"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Reads the fields of a line of a sheet file, written by `escape_field` and separated by
+/// `|` with spaces around.
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                field.push(c);
+                field.extend(chars.next());
+            }
+            '|' => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+        .iter()
+        .map(|field| unescape_field(field.trim_matches([' ', '\t'])))
+        .collect()
+}
+
+/// Undoes `escape_field`. A backslash before any other character is kept as it is.
+fn unescape_field(field: &str) -> String {
+    let mut raw = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            raw.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => raw.push('\\'),
+            Some('|') => raw.push('|'),
+            Some('n') => raw.push('\n'),
+            Some('r') => raw.push('\r'),
+            Some('s') => raw.push(' '),
+            Some('t') => raw.push('\t'),
+            Some(other) => raw.extend(['\\', other]),
+            None => raw.push('\\'),
+        }
+    }
+    raw
+}
+
+fn is_volatile(cell: &Cell) -> bool {
+    matches!(
+        &cell.parsed_representation,
+        Some(Ok(ParsedCell::Expr(Expression { volatile: true, .. })))
+    )
+}
+
+/// Returns true when computing `cell` again may give another value than it has: it was
+/// never computed, is volatile or references one of the cells in `changed`.
+fn may_change(cell: &Cell, changed: &HashSet<Index>) -> bool {
+    match &cell.parsed_representation {
+        _ if cell.computed_value.is_none() => true,
+        Some(Ok(ParsedCell::Expr(Expression {
+            dependencies,
+            volatile: false,
+            ..
+        }))) => dependencies.iter().any(|index| changed.contains(index)),
+        _ => true,
+    }
+}
+
+/// Returns the cells the formula of `cell` references, none for any other content.
+fn dependencies_of(cell: &Cell) -> &[Index] {
+    match &cell.parsed_representation {
+        Some(Ok(ParsedCell::Expr(Expression { dependencies, .. }))) => dependencies,
+        _ => &[],
+    }
+}
+
+/// Returns `raw` with every cell reference of its formula replaced by where `map` moves it.
+/// Ranges and anything that is not a formula are left as they are.
+fn map_cell_references(raw: &str, map: impl Fn(Index) -> Index) -> String {
+    if !raw.starts_with('=') {
+        return raw.to_string();
+    }
+
+    let mut mapped = raw.to_string();
+    // From the end, so the spans of the earlier references stay valid
+    for (reference, span) in SpreadSheet::formula_references(raw).into_iter().rev() {
+        if let Reference::Cell(index) = reference {
+            let target = map(index);
+            if target != index {
+                let (anchors, _) = split_anchors(&raw[span.clone()]);
+                mapped.replace_range(span, &with_anchors(&index_to_name(target), anchors));
+            }
+        }
+    }
+    mapped
+}
+
+/// Writes the anchors of the cell names of `raw` onto the same names of `canonical`, the
+/// formula printed back from its syntax tree, which has none.
+fn keep_anchors(raw: &str, canonical: String) -> String {
+    if !raw.contains('$') {
+        return canonical;
+    }
+
+    let cell_names = |formula: &str| -> Vec<Range<usize>> {
+        CellParser::tokenize_lossy(formula)
+            .into_iter()
+            .filter(|(token, _)| matches!(token, Token::CellName(_)))
+            .map(|(_, span)| span)
+            .collect()
+    };
+    let mut anchored = canonical.clone();
+    // From the end, so the spans of the earlier names stay valid
+    for (from, to) in cell_names(raw)
+        .into_iter()
+        .zip(cell_names(&canonical))
+        .rev()
+    {
+        let (anchors, _) = split_anchors(&raw[from]);
+        let anchored_name = with_anchors(&canonical[to.clone()], anchors);
+        anchored.replace_range(to, &anchored_name);
+    }
+    anchored
+}
+
+/// Orders two computed values for `SpreadSheet::sort_rows_by_column`.
+fn compare_for_sort(
+    a: &Option<Result<Value, ComputeError>>,
+    b: &Option<Result<Value, ComputeError>>,
+    descending: bool,
+) -> Ordering {
+    let rank = |value: &Option<Result<Value, ComputeError>>| match value {
+        Some(Ok(Value::Number(_))) => 0,
+        // Arrays and links were replaced by the value they show
+        Some(Ok(Value::Text(_) | Value::Array(_) | Value::Link { .. } | Value::Sparkline(_))) => 1,
+        Some(Ok(Value::Bool(_))) => 2,
+        Some(Err(_)) => 3,
+        None => 4,
+    };
+    let (rank_a, rank_b) = (rank(a), rank(b));
+    // Errors and empty cells stay last whatever the direction
+    if rank_a >= 3 || rank_b >= 3 {
+        return rank_a.cmp(&rank_b);
+    }
+
+    let ordering = match (a, b) {
+        (Some(Ok(Value::Number(x))), Some(Ok(Value::Number(y)))) => x.total_cmp(y),
+        (Some(Ok(Value::Text(x))), Some(Ok(Value::Text(y)))) => {
+            x.to_lowercase().cmp(&y.to_lowercase())
+        }
+        (Some(Ok(Value::Bool(x))), Some(Ok(Value::Bool(y)))) => x.cmp(y),
+        _ => rank_a.cmp(&rank_b),
+    };
+    if descending {
+        ordering.reverse()
+    } else {
+        ordering
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_types::cell_addr::cell;
+    use crate::common_types::error_text::{ErrorText, Language};
+    use crate::common_types::{computed_to_text, computed_to_text_as, BorderStyle, Edge};
+
+    #[test]
+    fn test_empty_ref() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+
+        spreadsheet
+            .add_cell_and_compute(a1, "=A5".to_string())
+            .unwrap();
+
+        assert!(matches!(
+            spreadsheet.get_computed(a1),
+            Some(Err(ComputeError::UnfindableReference(_)))
+        ));
+    }
+
+    #[test]
+    fn test_circular() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let a2 = Index { x: 0, y: 1 };
+        spreadsheet
+            .add_cell_and_compute(a1, "=A2".to_string())
+            .unwrap();
+        spreadsheet
+            .add_cell_and_compute(a2, "=A1".to_string())
+            .unwrap();
+
+        assert!(matches!(
+            spreadsheet.get_computed(a1),
+            Some(Err(ComputeError::Cycle))
+        ));
+
+        assert!(matches!(
+            spreadsheet.get_computed(a2),
+            Some(Err(ComputeError::Cycle))
+        ));
+    }
+
+    #[test]
+    fn test_mutate() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let a2 = Index { x: 0, y: 1 };
+        let a3 = Index { x: 0, y: 2 };
+
+        spreadsheet
+            .add_cell_and_compute(a3, "=A2 * 3".to_string())
+            .unwrap();
+        spreadsheet
+            .add_cell_and_compute(a2, "=A1 * 2".to_string())
+            .unwrap();
+        spreadsheet
+            .add_cell_and_compute(a1, "1".to_string())
+            .unwrap();
+
+        assert!(matches!(
+            spreadsheet.get_computed(a2),
+            Some(Ok(Value::Number(2.0)))
+        ));
+
+        assert!(matches!(
+            spreadsheet.get_computed(a3),
+            Some(Ok(Value::Number(6.0)))
+        ));
+
+        spreadsheet.mutate_cell(a1, "7".to_string()).unwrap();
+        assert!(matches!(
+            spreadsheet.get_computed(a2),
+            Some(Ok(Value::Number(14.0)))
+        ));
+
+        assert!(matches!(
+            spreadsheet.get_computed(a3),
+            Some(Ok(Value::Number(42.0)))
+        ));
+    }
+
+    #[test]
+    fn test_remove_cell() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let a2 = Index { x: 0, y: 1 };
+
+        spreadsheet
+            .add_cell_and_compute(a1, "10".to_string())
+            .unwrap();
+        spreadsheet
+            .add_cell_and_compute(a2, "=A1 * 2".to_string())
+            .unwrap();
+
+        spreadsheet.remove_cell(a1).unwrap();
+
+        assert!(matches!(
+            spreadsheet.get_computed(a2),
+            Some(Err(ComputeError::UnfindableReference(_)))
+        ));
+    }
+
+    #[test]
+    fn test_fill_survives_edits() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let b1 = Index { x: 1, y: 0 };
+        let yellow = Some([255, 240, 120, 255]);
+
+        spreadsheet
+            .add_cell_and_compute(a1, "1".to_string())
+            .unwrap();
+        spreadsheet
+            .add_cell_and_compute(b1, "=A1 + 1".to_string())
+            .unwrap();
+        spreadsheet.set_fill(a1, yellow);
+        spreadsheet.mutate_cell(a1, "5".to_string()).unwrap();
+        assert_eq!(spreadsheet.get_style(a1).fill, yellow);
+        assert!(matches!(
+            spreadsheet.get_computed(b1),
+            Some(Ok(Value::Number(6.0)))
+        ));
+
+        // Clearing the content keeps the fill, and the cell reads as empty
+        spreadsheet.remove_cell(a1).unwrap();
+        assert_eq!(spreadsheet.get_style(a1).fill, yellow);
+        assert!(spreadsheet.get_raw(&a1).is_none());
+        assert!(spreadsheet.get_computed(a1).is_none());
+
+        spreadsheet.set_cell(a1, "7".to_string()).unwrap();
+        assert_eq!(spreadsheet.get_style(a1).fill, yellow);
+        assert!(matches!(
+            spreadsheet.get_computed(b1),
+            Some(Ok(Value::Number(8.0)))
+        ));
+    }
+
+    #[test]
+    fn test_fill_empty_cell() {
+        let mut spreadsheet = SpreadSheet::default();
+        let c3 = Index { x: 2, y: 2 };
+
+        spreadsheet.set_fill(c3, Some([0, 0, 255, 255]));
+        assert!(spreadsheet.get_raw(&c3).is_none());
+        assert_eq!(spreadsheet.get_style(c3).fill, Some([0, 0, 255, 255]));
+
+        // Clearing the fill of an empty cell leaves nothing behind
+        spreadsheet.set_fill(c3, None);
+        assert!(spreadsheet.cells.is_empty());
+    }
+
+    #[test]
+    fn test_toggle_text_style() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let b2 = Index { x: 1, y: 1 };
+        spreadsheet
+            .add_cell_and_compute(a1, "bold already".to_string())
+            .unwrap();
+        spreadsheet.toggle_text_style((a1, a1), TextStyle::Bold);
+        assert!(spreadsheet.get_style(a1).bold);
+
+        // Part of the selection lacks bold, so all of it becomes bold
+        spreadsheet.toggle_text_style((b2, a1), TextStyle::Bold);
+        assert!(spreadsheet.get_style(a1).bold);
+        assert!(spreadsheet.get_style(b2).bold);
+        assert!(!spreadsheet.get_style(b2).italic);
+
+        // All of it is bold, so none of it stays bold
+        spreadsheet.toggle_text_style((a1, b2), TextStyle::Bold);
+        assert!(!spreadsheet.get_style(a1).bold);
+        assert_eq!(spreadsheet.get_raw(&a1), Some("bold already"));
+        assert!(!spreadsheet.cells.contains_key(&b2));
+    }
+
+    #[test]
+    fn test_text_style_survives_edits() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        spreadsheet
+            .add_cell_and_compute(a1, "1".to_string())
+            .unwrap();
+        spreadsheet.toggle_text_style((a1, a1), TextStyle::Italic);
+
+        spreadsheet.mutate_cell(a1, "2".to_string()).unwrap();
+        assert!(spreadsheet.get_style(a1).italic);
+        spreadsheet.set_cell(a1, String::new()).unwrap();
+        spreadsheet.set_cell(a1, "3".to_string()).unwrap();
+        assert!(spreadsheet.get_style(a1).italic);
+    }
+
+    #[test]
+    fn test_links_read_as_their_label() {
+        let mut spreadsheet = SpreadSheet::default();
+        let cells = [
+            ("A1", "=hyperlink(\"https://b.example\", \"Beta\")"),
+            ("A2", "alpha"),
+            ("A3", "=hyperlink(\"https://c.example\", \"Gamma\")"),
+            ("B1", "=A1 == \"Beta\""),
+            ("B2", "=A1 + \"!\""),
+            ("B3", "=length(A3)"),
+        ];
+        for (name, raw) in cells {
+            spreadsheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        let value = |sheet: &SpreadSheet, name| sheet.get_computed(cell(name)).map(Result::unwrap);
+
+        assert_eq!(value(&spreadsheet, "B1"), Some(Value::Bool(true)));
+        assert_eq!(
+            value(&spreadsheet, "B2"),
+            Some(Value::Text("Beta!".to_string()))
+        );
+        assert_eq!(value(&spreadsheet, "B3"), Some(Value::Number(5.0)));
+
+        // Sorted among text by the label, keeping where they lead
+        spreadsheet
+            .sort_rows_by_column(0, (cell("A1"), cell("A3")), false)
+            .unwrap();
+        let shown: Vec<_> = ["A1", "A2", "A3"]
+            .map(|name| value(&spreadsheet, name).unwrap().to_string())
+            .to_vec();
+        assert_eq!(shown, ["alpha", "Beta", "Gamma"]);
+        assert_eq!(
+            value(&spreadsheet, "A2").unwrap().link_url(),
+            Some("https://b.example")
+        );
+    }
+
+    #[test]
+    fn test_sparkline_follows_its_range() {
+        let mut spreadsheet = SpreadSheet::default();
+        for (name, raw) in [
+            ("A1", "1"),
+            ("A2", "4"),
+            ("A4", "2"),
+            ("B1", "=sparkline(A1:A4)"),
+        ] {
+            spreadsheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        // Empty cells are left out
+        assert_eq!(
+            spreadsheet.get_computed(cell("B1")),
+            Some(Ok(Value::Sparkline(vec![1.0, 4.0, 2.0])))
+        );
+
+        spreadsheet
+            .set_cell(cell("A3"), "=A2 * 2".to_string())
+            .unwrap();
+        spreadsheet.set_cell(cell("A2"), "10".to_string()).unwrap();
+        assert_eq!(
+            spreadsheet.get_computed(cell("B1")),
+            Some(Ok(Value::Sparkline(vec![1.0, 10.0, 20.0, 2.0])))
+        );
+        assert_eq!(
+            export::render(&spreadsheet, export::OutputFormat::Csv),
+            "1,▁▄█▁\n10,\n20,\n2,\n"
+        );
+
+        spreadsheet.set_cell(cell("A4"), "two".to_string()).unwrap();
+        assert!(matches!(
+            spreadsheet.get_error(cell("B1")),
+            Some(ComputeError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_locked_cells_refuse_edits() {
+        let mut spreadsheet = SpreadSheet::default();
+        for (name, raw) in [("A1", "1"), ("A2", "=A1 * 2"), ("B1", "x")] {
+            spreadsheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        for name in ["A2", "C1"] {
+            spreadsheet.set_locked((cell(name), cell(name)), true);
+        }
+
+        // Locks only hold while the sheet is protected
+        assert!(!spreadsheet.is_locked(cell("A2")));
+        spreadsheet
+            .set_cell(cell("A2"), "=A1 * 3".to_string())
+            .unwrap();
+        spreadsheet.set_protection(true);
+        assert!(spreadsheet.is_locked(cell("A2")));
+        assert!(!spreadsheet.is_locked(cell("A1")));
+
+        let locked = Err(EditError::CellLocked(cell("A2")));
+        assert_eq!(spreadsheet.set_cell(cell("A2"), "5".to_string()), locked);
+        assert_eq!(spreadsheet.mutate_cell(cell("A2"), "5".to_string()), locked);
+        assert_eq!(spreadsheet.remove_cell(cell("A2")), locked);
+        assert_eq!(
+            spreadsheet.add_cell_and_compute(cell("C1"), "5".to_string()),
+            Err(EditError::CellLocked(cell("C1")))
+        );
+        assert_eq!(
+            EditError::CellLocked(cell("A2")).to_string(),
+            "Cell A2 is locked"
+        );
+        assert_eq!(spreadsheet.get_raw(&cell("A2")), Some("=A1 * 3"));
+        assert_eq!(spreadsheet.get_raw(&cell("C1")), None);
+
+        // Locked formulas still follow the cells they read
+        spreadsheet.set_cell(cell("A1"), "2".to_string()).unwrap();
+        assert_eq!(
+            spreadsheet.get_computed(cell("A2")).map(Result::unwrap),
+            Some(Value::Number(6.0))
+        );
+        spreadsheet.set_cell(cell("B1"), "y".to_string()).unwrap();
+
+        spreadsheet.set_protection(false);
+        spreadsheet.set_cell(cell("A2"), "5".to_string()).unwrap();
+        assert!(spreadsheet.get_style(cell("A2")).locked);
+    }
+
+    #[test]
+    fn test_locked_cells_refuse_bulk_edits() {
+        let mut spreadsheet = SpreadSheet::default();
+        for (name, raw) in [("A1", "3"), ("A2", "=A1 + 1"), ("A3", "1"), ("B1", "x")] {
+            spreadsheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        spreadsheet.set_locked((cell("A2"), cell("A2")), true);
+        spreadsheet.set_protection(true);
+        let before = spreadsheet.snapshot().cells;
+        let locked = Err(EditError::CellLocked(cell("A2")));
+
+        // Nothing changes, not even the cells that are not locked
+        assert_eq!(
+            spreadsheet.fill(cell("A1"), (cell("A1"), cell("A3")), FillMode::Copy),
+            locked
+        );
+        let block = vec![vec!["1".to_string(), "2".to_string()]; 2];
+        assert_eq!(spreadsheet.set_range(cell("A1"), &block), locked);
+        assert_eq!(
+            spreadsheet.sort_rows_by_column(0, (cell("A1"), cell("B3")), false),
+            locked
+        );
+        assert_eq!(
+            spreadsheet.convert_to_values((cell("A1"), cell("A3"))),
+            locked
+        );
+        assert_eq!(spreadsheet.check_unlocked((cell("B3"), cell("A1"))), locked);
+        assert_eq!(spreadsheet.snapshot().cells, before);
+
+        // A locked source fills the cells that are not locked
+        spreadsheet
+            .fill(cell("A2"), (cell("A2"), cell("A4")), FillMode::Copy)
+            .unwrap();
+        assert_eq!(spreadsheet.get_raw(&cell("A4")), Some("=A3 + 1"));
+        spreadsheet.set_range(cell("B1"), &block).unwrap();
+        assert_eq!(spreadsheet.get_raw(&cell("C2")), Some("2"));
+    }
+
+    #[test]
+    fn test_sort_refuses_to_rewrite_locked_formulas() {
+        let mut spreadsheet = SpreadSheet::default();
+        for (name, raw) in [("A1", "3"), ("A2", "2"), ("A3", "1"), ("C1", "=A1 * 10")] {
+            spreadsheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        spreadsheet.set_locked((cell("C1"), cell("C1")), true);
+        spreadsheet.set_protection(true);
+        let before = spreadsheet.snapshot().cells;
+
+        // C1 is outside of the range, yet its reference would follow A1 to A3
+        assert_eq!(
+            spreadsheet.sort_rows_by_column(0, (cell("A1"), cell("A3")), false),
+            Err(EditError::CellLocked(cell("C1")))
+        );
+        assert_eq!(spreadsheet.snapshot().cells, before);
+
+        spreadsheet.set_protection(false);
+        spreadsheet
+            .sort_rows_by_column(0, (cell("A1"), cell("A3")), false)
+            .unwrap();
+        assert_eq!(spreadsheet.get_raw(&cell("C1")), Some("=A3 * 10"));
+    }
+
+    #[test]
+    fn test_locks_are_kept() {
+        let mut spreadsheet = SpreadSheet::default();
+        spreadsheet.set_cell(cell("A1"), "1".to_string()).unwrap();
+        spreadsheet.set_locked((cell("A1"), cell("A2")), true);
+        let snapshot = spreadsheet.snapshot();
+
+        spreadsheet.set_locked((cell("A1"), cell("A2")), false);
+        assert!(!spreadsheet.get_style(cell("A1")).locked);
+        spreadsheet.restore(snapshot);
+        assert!(spreadsheet.get_style(cell("A1")).locked);
+        assert!(spreadsheet.get_style(cell("A2")).locked);
+
+        // Undone like any other style, and carried along by sorted rows
+        spreadsheet.undo();
+        assert!(!spreadsheet.get_style(cell("A1")).locked);
+        spreadsheet.redo();
+        spreadsheet.set_cell(cell("A2"), "0".to_string()).unwrap();
+        spreadsheet
+            .sort_rows_by_column(0, (cell("A1"), cell("A3")), false)
+            .unwrap();
+        assert_eq!(spreadsheet.get_raw(&cell("A1")), Some("0"));
+        assert!(spreadsheet.get_style(cell("A1")).locked);
+        assert!(!spreadsheet.get_style(cell("A3")).locked);
+    }
+
+    #[test]
+    fn test_hidden_lines_still_compute() {
+        let mut spreadsheet = SpreadSheet::default();
+        for (name, raw) in [("A1", "1"), ("A2", "2"), ("A3", "4"), ("B1", "=sum(A1:A3)")] {
+            spreadsheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        spreadsheet.set_row_hidden(1, true);
+        spreadsheet.set_column_hidden(0, true);
+        assert!(spreadsheet.is_row_hidden(1));
+        assert!(spreadsheet.is_column_hidden(0));
+        assert_eq!(
+            spreadsheet.get_computed(cell("B1")),
+            Some(Ok(Value::Number(7.0)))
+        );
+
+        // Cells in hidden lines are read and changed like any others
+        spreadsheet.set_cell(cell("A2"), "10".to_string()).unwrap();
+        assert_eq!(
+            spreadsheet.get_computed(cell("B1")),
+            Some(Ok(Value::Number(15.0)))
+        );
+
+        spreadsheet.set_row_hidden(1, false);
+        assert!(spreadsheet.hidden_rows().is_empty());
+        assert_eq!(spreadsheet.hidden_columns(), &BTreeSet::from([0]));
+    }
+
+    #[test]
+    fn test_column_and_row_formats() {
+        let mut spreadsheet = SpreadSheet::default();
+        let format = |sheet: &SpreadSheet, name| sheet.effective_format(cell(name)).number_format;
+        spreadsheet
+            .set_cell(cell("B1"), "0.25".to_string())
+            .unwrap();
+        spreadsheet.set_fill(cell("B2"), Some([255, 0, 0, 255]));
+        spreadsheet.set_column_format(1, NumberFormat::Percent);
+        assert_eq!(spreadsheet.column_format(1), NumberFormat::Percent);
+        assert_eq!(format(&spreadsheet, "B1"), NumberFormat::Percent);
+        assert_eq!(format(&spreadsheet, "B2"), NumberFormat::Percent);
+        assert_eq!(format(&spreadsheet, "A1"), NumberFormat::General);
+        // The style of the cell is left as it is
+        assert_eq!(
+            spreadsheet.get_style(cell("B1")).number_format,
+            NumberFormat::General
+        );
+
+        // Cells written later take the format of their column
+        spreadsheet
+            .set_cell(cell("B40"), "=B1 * 2".to_string())
+            .unwrap();
+        assert_eq!(format(&spreadsheet, "B40"), NumberFormat::Percent);
+        assert_eq!(
+            computed_to_text_as(
+                spreadsheet.get_computed(cell("B40")),
+                spreadsheet.display_settings(),
+                format(&spreadsheet, "B40"),
+            ),
+            "50%"
+        );
+
+        // The format of a cell wins over those of its row and column, that of the row over
+        // that of the column
+        spreadsheet.set_cell(cell("C2"), "10%".to_string()).unwrap();
+        spreadsheet.set_row_format(1, NumberFormat::Percent);
+        spreadsheet.set_column_format(1, NumberFormat::General);
+        assert_eq!(format(&spreadsheet, "B1"), NumberFormat::General);
+        assert_eq!(format(&spreadsheet, "A2"), NumberFormat::Percent);
+        assert_eq!(format(&spreadsheet, "C2"), NumberFormat::Percent);
+
+        // Brought back with the rest of the sheet
+        let snapshot = spreadsheet.snapshot();
+        spreadsheet.set_row_format(1, NumberFormat::General);
+        assert_eq!(format(&spreadsheet, "A2"), NumberFormat::General);
+        spreadsheet.restore(snapshot);
+        assert_eq!(spreadsheet.row_format(1), NumberFormat::Percent);
+    }
+
+    #[test]
+    fn test_conditional_format_follows_inputs() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a = |y| Index { x: 0, y };
+        let b1 = Index { x: 1, y: 0 };
+        let red = CellStyle {
+            fill: Some([255, 0, 0, 255]),
+            ..CellStyle::default()
+        };
+        for y in 0..5 {
+            spreadsheet
+                .add_cell_and_compute(a(y), y.to_string())
+                .unwrap();
+        }
+        spreadsheet
+            .add_cell_and_compute(b1, "50".to_string())
+            .unwrap();
+        spreadsheet
+            .add_conditional_format((a(0), a(4)), "=B1 > 100", red)
+            .unwrap();
+        assert!(spreadsheet.effective_format(a(0)).is_default());
+
+        spreadsheet.mutate_cell(b1, "150".to_string()).unwrap();
+        assert_eq!(spreadsheet.effective_format(a(0)), red);
+        // Each cell of the range reads its own row, and B2 is empty
+        assert!(spreadsheet.effective_format(a(1)).is_default());
+        // The stored style is left alone
+        assert!(spreadsheet.get_style(a(0)).is_default());
+
+        spreadsheet.remove_cell(b1).unwrap();
+        assert!(spreadsheet.effective_format(a(0)).is_default());
+    }
+
+    #[test]
+    fn test_conditional_format_over_cell_style() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a = |y| Index { x: 0, y };
+        spreadsheet
+            .add_cell_and_compute(a(0), "5".to_string())
+            .unwrap();
+        spreadsheet
+            .add_cell_and_compute(a(1), "500".to_string())
+            .unwrap();
+        spreadsheet.set_fill(a(1), Some([0, 0, 255, 255]));
+        spreadsheet.toggle_text_style((a(0), a(1)), TextStyle::Italic);
+        let bold = CellStyle {
+            bold: true,
+            ..CellStyle::default()
+        };
+        spreadsheet
+            .add_conditional_format((a(0), a(1)), "=A1 > 100", bold)
+            .unwrap();
+
+        let style = spreadsheet.effective_format(a(1));
+        assert!(style.bold && style.italic);
+        assert_eq!(style.fill, Some([0, 0, 255, 255]));
+        assert!(!spreadsheet.effective_format(a(0)).bold);
+
+        assert!(spreadsheet
+            .add_conditional_format((a(0), a(1)), "=A1 >", bold)
+            .is_err());
+        assert_eq!(spreadsheet.conditional_formats().len(), 1);
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let path = std::env::temp_dir().join("mini_spreadsheet_round_trip.csv");
+        let mut spreadsheet = SpreadSheet::default();
+        assert!(!spreadsheet.is_modified());
+
+        spreadsheet
+            .add_cell_and_compute(Index { x: 0, y: 0 }, "5".to_string())
+            .unwrap();
+        spreadsheet
+            .add_cell_and_compute(Index { x: 2, y: 0 }, "=A1 * 2".to_string())
+            .unwrap();
+        spreadsheet
+            .add_cell_and_compute(Index { x: 1, y: 2 }, "hello".to_string())
+            .unwrap();
+        assert!(spreadsheet.is_modified());
+
+        spreadsheet.save_to_file(&path).unwrap();
+        assert!(!spreadsheet.is_modified());
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "5 |  | =A1 * 2\n\n | hello\n"
+        );
+
+        let mut loaded = SpreadSheet::load_from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(!loaded.is_modified());
+        assert_eq!(loaded.get_raw(&Index { x: 1, y: 2 }), Some("hello"));
+        assert!(matches!(
+            loaded.get_computed(Index { x: 2, y: 0 }),
+            Some(Ok(Value::Number(10.0)))
+        ));
+
+        loaded
+            .mutate_cell(Index { x: 0, y: 0 }, "6".to_string())
+            .unwrap();
+        assert!(loaded.is_modified());
+    }
+
+    #[test]
+    fn test_styles_survive_save_and_load() {
+        let path = std::env::temp_dir().join(format!("styles_{}.txt", std::process::id()));
+        let styles_path = cell_styles::cell_styles_path(&path);
+        let (a1, b2, c3) = (
+            Index { x: 0, y: 0 },
+            Index { x: 1, y: 1 },
+            Index { x: 2, y: 2 },
+        );
+        let mut spreadsheet = SpreadSheet::default();
+        spreadsheet.set_cell(a1, "5".to_string()).unwrap();
+        spreadsheet.set_fill(a1, Some([255, 200, 0, 255]));
+        spreadsheet.set_fill(b2, Some([0, 0, 255, 255]));
+        // A cell holding nothing but its style keeps it too
+        spreadsheet.set_fill(c3, Some([0, 128, 0, 255]));
+        spreadsheet.toggle_text_style((a1, b2), TextStyle::Bold);
+        spreadsheet.toggle_text_style((b2, c3), TextStyle::Italic);
+        let border = BorderStyle {
+            width: 2,
+            color: [0, 0, 0, 255],
+        };
+        spreadsheet.set_outline_border((a1, b2), Some(border));
+        spreadsheet.save_to_file(&path).unwrap();
+
+        let loaded = SpreadSheet::load_from_file(&path).unwrap();
+        assert!(!loaded.is_modified());
+        assert_eq!(loaded.get_raw(&a1), Some("5"));
+        for index in [a1, b2, c3, Index { x: 1, y: 0 }] {
+            let (saved, loaded) = (spreadsheet.get_style(index), loaded.get_style(index));
+            assert_eq!(
+                (saved.fill, saved.bold, saved.italic, saved.borders),
+                (loaded.fill, loaded.bold, loaded.italic, loaded.borders)
+            );
+        }
+        assert_eq!(
+            loaded.border(Index { x: 1, y: 0 }, Edge::Right),
+            Some(border)
+        );
+        assert_eq!(loaded.get_style(a1).fill, Some([255, 200, 0, 255]));
+        assert!(loaded.get_style(a1).bold && !loaded.get_style(a1).italic);
+        assert!(loaded.get_style(c3).italic && !loaded.get_style(c3).bold);
+
+        // Without styles left the file of styles goes
+        for index in [a1, b2, c3] {
+            spreadsheet.set_fill(index, None);
+        }
+        spreadsheet.set_outline_border((a1, b2), None);
+        spreadsheet.toggle_text_style((a1, b2), TextStyle::Bold);
+        // Still italic, the file of styles is kept
+        spreadsheet.save_to_file(&path).unwrap();
+        assert!(styles_path.exists());
+        spreadsheet.toggle_text_style((b2, c3), TextStyle::Italic);
+        spreadsheet.save_to_file(&path).unwrap();
+        assert!(!styles_path.exists());
+        assert!(SpreadSheet::load_from_file(&path)
+            .unwrap()
+            .get_style(a1)
+            .is_default());
+
+        fs::write(&styles_path, "{\"cells\": {}}").unwrap();
+        let error = SpreadSheet::load_from_file(&path).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&styles_path).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_locks_survive_save_and_load() {
+        let path = std::env::temp_dir().join(format!("locks_{}.txt", std::process::id()));
+        let mut spreadsheet = SpreadSheet::default();
+        spreadsheet.set_cell(cell("A1"), "1".to_string()).unwrap();
+        spreadsheet.set_locked((cell("A1"), cell("B2")), true);
+        spreadsheet.set_protection(true);
+        spreadsheet.save_to_file(&path).unwrap();
+
+        let mut loaded = SpreadSheet::load_from_file(&path).unwrap();
+        assert!(loaded.is_protected());
+        assert!(loaded.is_locked(cell("A1")) && loaded.is_locked(cell("B2")));
+        assert!(!loaded.is_locked(cell("C1")));
+        assert_eq!(
+            loaded.set_cell(cell("B2"), "2".to_string()),
+            Err(EditError::CellLocked(cell("B2")))
+        );
+
+        // Protection alone is kept as well
+        spreadsheet.set_locked((cell("A1"), cell("B2")), false);
+        spreadsheet.save_to_file(&path).unwrap();
+        let loaded = SpreadSheet::load_from_file(&path).unwrap();
+        assert!(loaded.is_protected() && !loaded.is_locked(cell("A1")));
+
+        spreadsheet.set_protection(false);
+        spreadsheet.save_to_file(&path).unwrap();
+        assert!(!cell_styles::cell_styles_path(&path).exists());
+        assert!(!SpreadSheet::load_from_file(&path).unwrap().is_protected());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_number_formats_survive_save_and_load() {
+        let path = std::env::temp_dir().join(format!("formats_{}.txt", std::process::id()));
+        let euros = NumberFormat::Currency {
+            symbol: '€',
+            decimals: 2,
+        };
+        let mut spreadsheet = SpreadSheet::default();
+        spreadsheet
+            .set_cell(Index { x: 1, y: 4 }, "0.5".to_string())
+            .unwrap();
+        spreadsheet.set_column_format(1, NumberFormat::Percent);
+        spreadsheet.set_row_format(4, euros);
+        // Typing a percentage sets the format of the cell
+        spreadsheet
+            .set_cell(Index { x: 0, y: 0 }, "12%".to_string())
+            .unwrap();
+        spreadsheet.set_number_format([Index { x: 2, y: 2 }], NumberFormat::Fixed { decimals: 3 });
+        spreadsheet.save_to_file(&path).unwrap();
+
+        let loaded = SpreadSheet::load_from_file(&path).unwrap();
+        assert!(!loaded.is_modified());
+        assert_eq!(loaded.column_format(1), NumberFormat::Percent);
+        assert_eq!(loaded.row_format(4), euros);
+        assert_eq!(loaded.column_format(0), NumberFormat::General);
+        assert_eq!(
+            loaded.effective_format(Index { x: 1, y: 4 }).number_format,
+            euros
+        );
+        assert_eq!(
+            loaded.effective_format(Index { x: 1, y: 3 }).number_format,
+            NumberFormat::Percent
+        );
+        assert_eq!(
+            loaded.get_style(Index { x: 0, y: 0 }).number_format,
+            NumberFormat::Percent
+        );
+        assert_eq!(
+            loaded.get_style(Index { x: 2, y: 2 }).number_format,
+            NumberFormat::Fixed { decimals: 3 }
+        );
+
+        spreadsheet.set_column_format(1, NumberFormat::General);
+        spreadsheet.set_row_format(4, NumberFormat::General);
+        spreadsheet.set_number_format(
+            [Index { x: 0, y: 0 }, Index { x: 2, y: 2 }],
+            NumberFormat::General,
+        );
+        spreadsheet.save_to_file(&path).unwrap();
+        assert!(!cell_styles::cell_styles_path(&path).exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_validation_rules_survive_save_and_load() {
+        use validation::{ValidationKind, ValidationState};
+
+        let path = std::env::temp_dir().join(format!("rules_{}.txt", std::process::id()));
+        let mut spreadsheet = SpreadSheet::default();
+        for (name, raw) in [("A1", "12"), ("B1", "maybe"), ("C1", "3")] {
+            spreadsheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        let rules = [
+            (("A1", "A5"), ValidationKind::NumberBetween(1.0, 10.0), true),
+            (
+                ("B1", "B5"),
+                ValidationKind::OneOf(vec!["yes".to_string(), "no".to_string()]),
+                false,
+            ),
+            (
+                ("C1", "C5"),
+                ValidationKind::Formula("=C1 > 5".to_string()),
+                false,
+            ),
+        ];
+        for ((from, to), kind, strict) in rules {
+            spreadsheet
+                .set_validation((cell(from), cell(to)), kind, strict)
+                .unwrap();
+        }
+        spreadsheet.save_to_file(&path).unwrap();
+
+        let loaded = SpreadSheet::load_from_file(&path).unwrap();
+        assert!(!loaded.is_modified());
+        assert_eq!(loaded.validation_rules(), spreadsheet.validation_rules());
+        assert_eq!(
+            loaded.validation_state(cell("A1")),
+            Some(ValidationState::Invalid {
+                message: "Expected a number from 1 to 10".to_string(),
+                strict: true,
+            })
+        );
+        assert!(loaded.validation_state(cell("B1")) != Some(ValidationState::Valid));
+        assert!(loaded.validation_state(cell("C1")) != Some(ValidationState::Valid));
+        assert_eq!(
+            loaded.check_validation(cell("C2"), "6"),
+            Some(ValidationState::Valid)
+        );
+        fs::remove_file(cell_styles::cell_styles_path(&path)).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_save_and_load_escaped_fields() {
+        let path = std::env::temp_dir().join(format!("escaped_{}.txt", std::process::id()));
+        let raws = [
+            "a|b",
+            "=\"x|y\"",
+            "  padded\t",
+            "back\\slash | \\|",
+            "two\nlines",
+            "=concat(\" \", \"\\n\")",
+            " ",
+        ];
+        let mut spreadsheet = SpreadSheet::default();
+        for (x, raw) in raws.iter().enumerate() {
+            spreadsheet
+                .set_cell(Index { x, y: 0 }, raw.to_string())
+                .unwrap();
+        }
+        spreadsheet
+            .set_cell(Index { x: 1, y: 1 }, "last".to_string())
+            .unwrap();
+        spreadsheet.save_to_file(&path).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert_eq!(text.lines().count(), 2);
+        assert!(text.starts_with("a\\|b | =\"x\\|y\" | \\s\\spadded\\t | "));
+
+        let loaded = SpreadSheet::load_from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        for (x, raw) in raws.iter().enumerate() {
+            assert_eq!(loaded.get_raw(&Index { x, y: 0 }), Some(*raw), "{raw:?}");
+        }
+        assert_eq!(
+            loaded.get_raw(&Index {
+                x: raws.len(),
+                y: 0
+            }),
+            None
+        );
+        assert_eq!(
+            loaded.get_computed(Index { x: 1, y: 0 }),
+            Some(Ok(Value::Text("x|y".to_string())))
+        );
+        assert_eq!(loaded.get_raw(&Index { x: 1, y: 1 }), Some("last"));
+
+        // A backslash before anything else reads as it is
+        assert_eq!(split_fields(r" a\b | \| "), [r"a\b", "|"]);
+    }
+
+    #[test]
+    fn test_load_missing_file_fails() {
+        let path = std::env::temp_dir().join("mini_spreadsheet_missing.csv");
+        assert!(SpreadSheet::load_from_file(&path).is_err());
+    }
+
+    #[test]
+    fn test_undo_and_redo() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let b1 = Index { x: 1, y: 0 };
+        assert!(spreadsheet.undo().is_none());
+
+        spreadsheet
+            .add_cell_and_compute(a1, "1".to_string())
+            .unwrap();
+        spreadsheet
+            .add_cell_and_compute(b1, "=A1 * 2".to_string())
+            .unwrap();
+        spreadsheet.mutate_cell(a1, "5".to_string()).unwrap();
+        spreadsheet.set_fill(a1, Some([1, 2, 3, 255]));
+
+        assert_eq!(spreadsheet.undo(), Some(vec![a1]));
+        assert!(spreadsheet.get_style(a1).is_default());
+        assert_eq!(spreadsheet.undo(), Some(vec![a1]));
+        assert_eq!(spreadsheet.get_raw(&a1), Some("1"));
+        assert!(matches!(
+            spreadsheet.get_computed(b1),
+            Some(Ok(Value::Number(2.0)))
+        ));
+
+        assert_eq!(spreadsheet.redo(), Some(vec![a1]));
+        assert!(matches!(
+            spreadsheet.get_computed(b1),
+            Some(Ok(Value::Number(10.0)))
+        ));
+
+        // A new change forgets what was undone
+        spreadsheet.remove_cell(b1).unwrap();
+        assert!(spreadsheet.redo().is_none());
+        spreadsheet.undo();
+        assert_eq!(spreadsheet.get_raw(&b1), Some("=A1 * 2"));
+    }
+
+    #[test]
+    fn test_undo_fill_as_one_step() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a = |y| Index { x: 0, y };
+        spreadsheet
+            .add_cell_and_compute(a(0), "1".to_string())
+            .unwrap();
+        spreadsheet
+            .add_cell_and_compute(a(2), "old".to_string())
+            .unwrap();
+        spreadsheet
+            .fill(a(0), (a(0), a(3)), FillMode::Series)
+            .unwrap();
+        assert_eq!(column_raws(&spreadsheet, 0, 4), vec!["1", "2", "3", "4"]);
+
+        assert_eq!(spreadsheet.undo(), Some(vec![a(1), a(2), a(3)]));
+        assert_eq!(column_raws(&spreadsheet, 0, 4), vec!["1", "", "old", ""]);
+        spreadsheet.redo();
+        assert_eq!(column_raws(&spreadsheet, 0, 4), vec!["1", "2", "3", "4"]);
+
+        spreadsheet.batch(|sheet| {
+            sheet.remove_cell(a(0)).unwrap();
+            sheet.remove_cell(a(1)).unwrap();
+        });
+        spreadsheet.undo();
+        assert_eq!(column_raws(&spreadsheet, 0, 4), vec!["1", "2", "3", "4"]);
+    }
+
+    #[test]
+    fn test_paste_block() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a = |y| Index { x: 0, y };
+        let b = |y| Index { x: 1, y };
+        spreadsheet
+            .add_cell_and_compute(a(1), "old".to_string())
+            .unwrap();
+        spreadsheet.set_fill(b(1), Some([1, 2, 3, 255]));
+
+        let block = clipboard::parse_tsv("1\t=A1 * 2\n\t3\n");
+        spreadsheet.set_range(a(0), &block).unwrap();
+        assert_eq!(column_raws(&spreadsheet, 0, 2), vec!["1", ""]);
+        assert_eq!(column_raws(&spreadsheet, 1, 2), vec!["=A1 * 2", "3"]);
+        assert!(matches!(
+            spreadsheet.get_computed(b(0)),
+            Some(Ok(Value::Number(2.0)))
+        ));
+        assert_eq!(spreadsheet.get_style(b(1)).fill, Some([1, 2, 3, 255]));
+
+        assert_eq!(spreadsheet.undo(), Some(vec![a(0), b(0), a(1), b(1)]));
+        assert_eq!(column_raws(&spreadsheet, 0, 2), vec!["", "old"]);
+        assert_eq!(column_raws(&spreadsheet, 1, 2), vec!["", ""]);
+        assert_eq!(spreadsheet.get_style(b(1)).fill, Some([1, 2, 3, 255]));
+    }
+
+    #[test]
+    fn test_set_range_and_get_range() {
+        let mut spreadsheet = SpreadSheet::default();
+        let index = |x, y| Index { x, y };
+        spreadsheet
+            .add_cell_and_compute(index(0, 0), "10".to_string())
+            .unwrap();
+
+        let block: Vec<Vec<String>> = [
+            vec!["=A1 + 1", "=B2 * 2", "text"],
+            vec!["5", "=B3 + A1", "=NOPE()"],
+            vec!["", "=B2 + 2"],
+        ]
+        .iter()
+        .map(|row| row.iter().map(|raw| raw.to_string()).collect())
+        .collect();
+        spreadsheet.set_range(index(1, 1), &block).unwrap();
+
+        let number = |num| Some(Value::Number(num));
+        assert_eq!(
+            spreadsheet.get_range(index(0, 0), index(3, 3)),
+            vec![
+                vec![number(10.0), None, None, None],
+                vec![
+                    None,
+                    number(11.0),
+                    number(22.0),
+                    Some(Value::Text("text".to_string()))
+                ],
+                vec![None, number(5.0), number(15.0), None],
+                vec![None, None, number(13.0), None],
+            ]
+        );
+
+        // One undo step for the block
+        spreadsheet.undo();
+        assert_eq!(
+            spreadsheet.get_range(index(0, 0), index(1, 1)),
+            vec![vec![number(10.0), None], vec![None, None]]
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_get_range_reversed() {
+        SpreadSheet::default().get_range(Index { x: 1, y: 0 }, Index { x: 0, y: 1 });
+    }
+
+    #[test]
+    fn test_dependencies_to_dot() {
+        let mut spreadsheet = SpreadSheet::default();
+        for (x, raw) in ["2", "=A1 * 2", "=A1 + B1", "=D1"].iter().enumerate() {
+            spreadsheet
+                .add_cell_and_compute(Index { x, y: 0 }, raw.to_string())
+                .unwrap();
+        }
+
+        let dot = spreadsheet.dependencies_to_dot();
+        assert!(dot.starts_with("digraph dependencies {\n"));
+        assert!(dot.contains("    \"A1\" -> \"B1\";\n"));
+        assert!(dot.contains("    \"A1\" -> \"C1\";\n"));
+        assert!(dot.contains("    \"B1\" -> \"C1\";\n"));
+        assert!(dot.contains("    \"D1\" [color=red, fontcolor=red];\n"));
+        assert!(dot.contains("    \"D1\" -> \"D1\";\n"));
+        assert_eq!(spreadsheet.dependencies_to_dot(), dot);
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a = |y| Index { x: 0, y };
+        let b = |y| Index { x: 1, y };
+        for (y, raw) in ["1", "=A1 + 1", "=A2 * 10", "=B4", "text"]
+            .iter()
+            .enumerate()
+        {
+            spreadsheet
+                .add_cell_and_compute(a(y), raw.to_string())
+                .unwrap();
+        }
+        spreadsheet
+            .add_cell_and_compute(b(3), "=A4".to_string())
+            .unwrap();
+        spreadsheet.set_fill(b(0), Some([9, 9, 9, 255]));
+        let values = |sheet: &SpreadSheet| -> Vec<String> {
+            (0..6)
+                .flat_map(|y| [a(y), b(y)])
+                .map(|index| computed_to_text(sheet.get_computed(index), sheet.display_settings()))
+                .collect()
+        };
+        let original = values(&spreadsheet);
+
+        let snapshot = spreadsheet.snapshot();
+        // Snapshots can be handed to other threads
+        std::thread::spawn({
+            let snapshot = snapshot.clone();
+            move || drop(snapshot)
+        });
+
+        spreadsheet
+            .add_cell_and_compute(a(0), "5".to_string())
+            .unwrap();
+        spreadsheet.remove_cell(a(4)).unwrap();
+        spreadsheet
+            .add_cell_and_compute(b(3), "7".to_string())
+            .unwrap();
+        spreadsheet
+            .add_cell_and_compute(b(5), "=A3".to_string())
+            .unwrap();
+        spreadsheet.set_fill(b(0), None);
+        assert_ne!(values(&spreadsheet), original);
+
+        spreadsheet.restore(snapshot);
+        assert_eq!(values(&spreadsheet), original);
+        assert_eq!(
+            column_raws(&spreadsheet, 1, 6),
+            vec!["", "", "", "=A4", "", ""]
+        );
+        assert_eq!(spreadsheet.get_style(b(0)).fill, Some([9, 9, 9, 255]));
+        // The dependencies are back too
+        spreadsheet
+            .add_cell_and_compute(a(0), "2".to_string())
+            .unwrap();
+        assert_eq!(
+            spreadsheet.get_computed(a(2)).map(Result::unwrap),
+            Some(Value::Number(30.0))
+        );
+
+        // Undoing the restore brings back the cells as they were before it
+        spreadsheet.undo();
+        spreadsheet.undo();
+        assert_eq!(
+            column_raws(&spreadsheet, 1, 6),
+            vec!["", "", "", "7", "", "=A3"]
+        );
+    }
+
+    #[test]
+    fn test_compute_progress() {
+        let chain = synthetic::chain(2500);
+        let mut reports = Vec::new();
+        let mut spreadsheet = chain.uncomputed();
+        let flow = spreadsheet.compute_all_with_progress(&mut |done, total| {
+            reports.push((done, total));
+            ControlFlow::Continue(())
+        });
+        assert_eq!(flow, ControlFlow::Continue(()));
+        // Every formula below A1 is counted once
+        assert_eq!(reports, vec![(1000, 2499), (2000, 2499), (2499, 2499)]);
+        assert_eq!(
+            spreadsheet
+                .get_computed(Index { x: 0, y: 2499 })
+                .map(Result::unwrap),
+            Some(Value::Number(2500.0))
+        );
+    }
+
+    #[test]
+    fn test_cancel_compute() {
+        let chain = synthetic::chain(2500);
+        let mut spreadsheet = chain.uncomputed();
+        let flow = spreadsheet.compute_all_with_progress(&mut |_, _| ControlFlow::Break(()));
+        assert_eq!(flow, ControlFlow::Break(()));
+
+        // The cells computed are done, the rest still need computing
+        let a = |y| Index { x: 0, y };
+        assert_eq!(
+            spreadsheet.get_computed(a(1000)).map(Result::unwrap),
+            Some(Value::Number(1001.0))
+        );
+        assert!(!spreadsheet.cells[&a(1000)].needs_compute);
+        assert!(spreadsheet.get_computed(a(1001)).is_none());
+        let dirty = spreadsheet
+            .cells
+            .values()
+            .filter(|cell| cell.needs_compute)
+            .count();
+        assert_eq!(dirty, 1499);
+
+        // A later pass finishes the job
+        let mut reports = Vec::new();
+        let _ = spreadsheet.compute_all_with_progress(&mut |done, total| {
+            reports.push((done, total));
+            ControlFlow::Continue(())
+        });
+        assert_eq!(reports, vec![(1000, 1499), (1499, 1499)]);
+        let finished = spreadsheet.get_range(a(0), a(2499));
+        assert_eq!(finished, chain.build().get_range(a(0), a(2499)));
+    }
+
+    #[test]
+    fn test_manual_recalc_keeps_stale_values() {
+        let mut spreadsheet = SpreadSheet::default();
+        let number = |spreadsheet: &SpreadSheet, name| match spreadsheet.get_computed(cell(name)) {
+            Some(Ok(Value::Number(num))) => num,
+            computed => panic!("Expected a number in {name}, got {computed:?}"),
+        };
+        for (name, raw) in [("A1", "1"), ("A2", "=A1 * 10"), ("A3", "=A2 + 1")] {
+            spreadsheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        spreadsheet.set_recalc_mode(RecalcMode::Manual);
+        assert!(!spreadsheet.needs_recalc());
+
+        spreadsheet.set_cell(cell("A1"), "2".to_string()).unwrap();
+        spreadsheet
+            .set_cell(cell("B1"), "=A3 * 2".to_string())
+            .unwrap();
+        // Edited cells are computed, the cells depending on them keep their values
+        assert_eq!(number(&spreadsheet, "A1"), 2.0);
+        assert_eq!(number(&spreadsheet, "A2"), 10.0);
+        assert_eq!(number(&spreadsheet, "A3"), 11.0);
+        assert_eq!(number(&spreadsheet, "B1"), 22.0);
+        assert!(spreadsheet.needs_recalc());
+        spreadsheet.undo();
+        assert_eq!(spreadsheet.get_raw(&cell("B1")), None);
+        assert_eq!(number(&spreadsheet, "A3"), 11.0);
+
+        spreadsheet.recalculate();
+        assert!(!spreadsheet.needs_recalc());
+        assert_eq!(number(&spreadsheet, "A2"), 20.0);
+        assert_eq!(number(&spreadsheet, "A3"), 21.0);
+        assert_eq!(spreadsheet.recalc_mode(), RecalcMode::Manual);
+    }
+
+    #[test]
+    fn test_styles_need_no_recalc() {
+        let mut spreadsheet = SpreadSheet::default();
+        let (a1, b1) = (Index { x: 0, y: 0 }, Index { x: 1, y: 0 });
+        spreadsheet.set_recalc_mode(RecalcMode::Manual);
+        spreadsheet.set_fill(a1, Some([255, 0, 0, 255]));
+        assert!(!spreadsheet.needs_recalc());
+
+        // Nor does a style left behind by its content
+        spreadsheet.set_cell(b1, "=1 + 1".to_string()).unwrap();
+        spreadsheet.set_fill(b1, Some([0, 255, 0, 255]));
+        spreadsheet.set_cell(b1, String::new()).unwrap();
+        assert!(!spreadsheet.needs_recalc());
+        spreadsheet.undo();
+        spreadsheet.recalculate();
+        assert!(!spreadsheet.needs_recalc());
+        assert_eq!(spreadsheet.get_computed(b1), Some(Ok(Value::Number(2.0))));
+    }
+
+    #[test]
+    fn test_automatic_recalc_resumes() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a = |y| Index { x: 0, y };
+        spreadsheet.set_cell(a(0), "1".to_string()).unwrap();
+        spreadsheet
+            .set_cell(a(1), "=sum(A1:A1) + 1".to_string())
+            .unwrap();
+        spreadsheet.set_recalc_mode(RecalcMode::Manual);
+        spreadsheet.set_cell(a(0), "5".to_string()).unwrap();
+        spreadsheet.remove_cell(a(0)).unwrap();
+        assert!(matches!(
+            spreadsheet.get_computed(a(1)),
+            Some(Ok(Value::Number(2.0)))
+        ));
+
+        // Switching back computes what was left
+        spreadsheet.set_recalc_mode(RecalcMode::Automatic);
+        assert!(matches!(
+            spreadsheet.get_computed(a(1)),
+            Some(Ok(Value::Number(1.0)))
+        ));
+        spreadsheet.set_cell(a(0), "3".to_string()).unwrap();
+        assert!(matches!(
+            spreadsheet.get_computed(a(1)),
+            Some(Ok(Value::Number(4.0)))
+        ));
+    }
+
+    #[test]
+    fn test_unchanged_values_cut_off() {
+        // Both branches from A1 meet in D1, the second only keeping its sign
+        let mut spreadsheet = SpreadSheet::default();
+        for (name, raw) in [
+            ("A1", "1"),
+            ("B1", "=if(A1 > 0, 1, 0)"),
+            ("C1", "=A1 * 0"),
+            ("D1", "=B1 + C1 + 10"),
+            ("E1", "=D1 * 2"),
+        ] {
+            spreadsheet
+                .add_cell_and_compute(cell(name), raw.to_string())
+                .unwrap();
+        }
+        let computes_of = |spreadsheet: &mut SpreadSheet, name, raw: &str| {
+            spreadsheet.computes.set(0);
+            spreadsheet
+                .mutate_cell(cell(name), raw.to_string())
+                .unwrap();
+            spreadsheet.computes.get()
+        };
+
+        // A1 and both branches, which keep their values
+        assert_eq!(computes_of(&mut spreadsheet, "A1", "2"), 3);
+        // The sign changes, computing the rest through E1
+        assert_eq!(computes_of(&mut spreadsheet, "A1", "=0 - 1"), 5);
+        // Rewritten to the same value, nothing depending on it is computed
+        assert_eq!(computes_of(&mut spreadsheet, "B1", "=if(0 < A1, 1, 0)"), 1);
+        assert_eq!(computes_of(&mut spreadsheet, "C1", "=A1 * 0 + 0"), 1);
+        assert_eq!(
+            spreadsheet.get_computed(cell("E1")).map(Result::unwrap),
+            Some(Value::Number(20.0))
+        );
+
+        assert_eq!(computes_of(&mut spreadsheet, "A1", "5"), 5);
+        assert_eq!(
+            spreadsheet.get_computed(cell("E1")).map(Result::unwrap),
+            Some(Value::Number(22.0))
+        );
+    }
+
+    #[test]
+    fn test_closing_a_cycle_keeping_the_value() {
+        let mut spreadsheet = SpreadSheet::default();
+        spreadsheet.set_cell(cell("A1"), "5".to_string()).unwrap();
+        spreadsheet.set_cell(cell("B1"), "=A1".to_string()).unwrap();
+        // Read before the cycle is found, B1 still gives A1 the value it had
+        spreadsheet.set_cell(cell("A1"), "=B1".to_string()).unwrap();
+        for name in ["A1", "B1"] {
+            assert_eq!(
+                spreadsheet.get_error(cell(name)),
+                Some(ComputeError::Cycle),
+                "{name}"
+            );
+        }
+
+        spreadsheet.set_cell(cell("C2"), "x".to_string()).unwrap();
+        spreadsheet.set_cell(cell("C2"), "=C2".to_string()).unwrap();
+        assert_eq!(spreadsheet.get_error(cell("C2")), Some(ComputeError::Cycle));
+
+        // A new cell can close a cycle too
+        spreadsheet.set_cell(cell("D1"), "=E1".to_string()).unwrap();
+        spreadsheet.set_cell(cell("E1"), "=D1".to_string()).unwrap();
+        assert_eq!(spreadsheet.get_error(cell("D1")), Some(ComputeError::Cycle));
+
+        spreadsheet.set_cell(cell("A1"), "7".to_string()).unwrap();
+        assert_eq!(
+            spreadsheet.get_computed(cell("B1")),
+            Some(Ok(Value::Number(7.0)))
+        );
+    }
+
+    #[test]
+    fn test_indirect() {
+        let mut spreadsheet = SpreadSheet::default();
+        for (name, raw) in [
+            ("A1", "10"),
+            ("A2", "20"),
+            ("A3", "30"),
+            ("B1", "2"),
+            ("C1", "=indirect(concat(\"A\", B1))"),
+            ("D1", "=C1 * 2"),
+        ] {
+            spreadsheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        let value = |sheet: &SpreadSheet, name| sheet.get_computed(cell(name)).map(Result::unwrap);
+        assert_eq!(value(&spreadsheet, "C1"), Some(Value::Number(20.0)));
+        assert_eq!(value(&spreadsheet, "D1"), Some(Value::Number(40.0)));
+
+        spreadsheet.set_cell(cell("B1"), "3".to_string()).unwrap();
+        assert_eq!(value(&spreadsheet, "D1"), Some(Value::Number(60.0)));
+        // A3 is no dependency of C1, which is volatile instead and follows it all the same
+        let is_volatile = |sheet: &SpreadSheet, name| {
+            matches!(
+                &sheet.cells[&cell(name)].parsed_representation,
+                Some(Ok(ParsedCell::Expr(Expression { volatile: true, .. })))
+            )
+        };
+        assert!(is_volatile(&spreadsheet, "C1"));
+        assert!(!is_volatile(&spreadsheet, "D1"));
+        spreadsheet.set_cell(cell("A3"), "35".to_string()).unwrap();
+        assert_eq!(value(&spreadsheet, "C1"), Some(Value::Number(35.0)));
+        assert_eq!(value(&spreadsheet, "D1"), Some(Value::Number(70.0)));
+
+        // Computed after the cells it reads, which are unknown to the order of computing
+        spreadsheet
+            .set_cell(cell("E1"), "=indirect(\"F1\")".to_string())
+            .unwrap();
+        spreadsheet
+            .set_cell(cell("F1"), "=B1 * 100".to_string())
+            .unwrap();
+        spreadsheet.set_cell(cell("B1"), "1".to_string()).unwrap();
+        assert_eq!(value(&spreadsheet, "E1"), Some(Value::Number(100.0)));
+        spreadsheet.remove_cell(cell("F1")).unwrap();
+        assert!(matches!(
+            spreadsheet.get_error(cell("E1")),
+            Some(ComputeError::UnfindableReference(_))
+        ));
+
+        for raw in [
+            "=indirect(\"A0\")",
+            "=indirect(\"a1\")",
+            "=indirect(5)",
+            "=indirect(\"A1\", 2)",
+        ] {
+            spreadsheet.set_cell(cell("G1"), raw.to_string()).unwrap();
+            assert!(
+                matches!(
+                    spreadsheet.get_error(cell("G1")),
+                    Some(ComputeError::InvalidArgument(_))
+                ),
+                "{raw}"
+            );
+        }
+        // A volatile cell put back by undoing stays volatile
+        spreadsheet
+            .set_cell(cell("G1"), "=indirect(\"A1\")".to_string())
+            .unwrap();
+        spreadsheet.set_cell(cell("G1"), String::new()).unwrap();
+        spreadsheet.undo();
+        spreadsheet.set_cell(cell("A1"), "6".to_string()).unwrap();
+        assert_eq!(value(&spreadsheet, "G1"), Some(Value::Number(6.0)));
+    }
+
+    #[test]
+    fn test_offset() {
+        let mut spreadsheet = SpreadSheet::default();
+        for (name, raw) in [
+            ("A1", "10"),
+            ("A2", "20"),
+            ("B3", "30"),
+            ("C1", "1"),
+            ("D1", "=offset(A1, C1, 0)"),
+            ("D2", "=offset(A1, 2, C1) * 2"),
+            ("D3", "=offset(B3, 0 - 1, 0 - 1)"),
+        ] {
+            spreadsheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        let value = |sheet: &SpreadSheet, name| sheet.get_computed(cell(name)).map(Result::unwrap);
+        assert_eq!(value(&spreadsheet, "D1"), Some(Value::Number(20.0)));
+        assert_eq!(value(&spreadsheet, "D2"), Some(Value::Number(60.0)));
+        assert_eq!(value(&spreadsheet, "D3"), Some(Value::Number(20.0)));
+
+        // The target follows both the distances and its own content
+        spreadsheet.set_cell(cell("C1"), "0".to_string()).unwrap();
+        assert_eq!(value(&spreadsheet, "D1"), Some(Value::Number(10.0)));
+        spreadsheet.set_cell(cell("A1"), "15".to_string()).unwrap();
+        assert_eq!(value(&spreadsheet, "D1"), Some(Value::Number(15.0)));
+
+        for raw in ["=offset(A1, 0 - 1, 0)", "=offset(B3, 0, C1 - 2)"] {
+            spreadsheet.set_cell(cell("E1"), raw.to_string()).unwrap();
+            assert!(
+                matches!(
+                    spreadsheet.get_error(cell("E1")),
+                    Some(ComputeError::UnfindableReference(_))
+                ),
+                "{raw}"
+            );
+        }
+        for raw in [
+            "=offset(A1, 0.5, 0)",
+            "=offset(A1, \"1\", 0)",
+            "=offset(5, 1, 0)",
+            "=offset(A1, 1)",
+        ] {
+            spreadsheet.set_cell(cell("E1"), raw.to_string()).unwrap();
+            assert!(
+                matches!(
+                    spreadsheet.get_error(cell("E1")),
+                    Some(ComputeError::InvalidArgument(_))
+                ),
+                "{raw}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_transpose() {
+        let mut spreadsheet = SpreadSheet::default();
+        for (name, raw) in [
+            ("A1", "1"),
+            ("B1", "2"),
+            ("C1", "3"),
+            ("A2", "4"),
+            ("B2", "5"),
+            ("C2", "6"),
+            ("E1", "=transpose(A1:C2)"),
+            ("F1", "=sumproduct(transpose(A1:C2), G1:H3)"),
+            ("G1", "1"),
+            ("G2", "1"),
+            ("G3", "1"),
+            ("H3", "10"),
+            ("F2", "=sum(transpose(A1:C2)) + sumproduct(A1:C1, A2:C2)"),
+            ("F3", "=sumproduct(A1:C2, G1:H3)"),
+        ] {
+            spreadsheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        let number = |num| Value::Number(num);
+        assert_eq!(
+            spreadsheet.get_computed(cell("E1")).map(Result::unwrap),
+            Some(Value::Array(vec![
+                vec![number(1.0), number(4.0)],
+                vec![number(2.0), number(5.0)],
+                vec![number(3.0), number(6.0)],
+            ]))
+        );
+        // The cell shows the top left value, its range gives the whole array
+        let display = DisplaySettings::default();
+        assert_eq!(computed_to_text(spreadsheet.get_computed(cell("E1")), &display), "1");
+        let range = spreadsheet.get_range(cell("E1"), cell("E1"));
+        assert!(matches!(&range[0][0], Some(Value::Array(rows)) if rows.len() == 3));
+
+        // 1 + 2 + 3 from the first column, the empty H1 and H2 count as 0, and 6 * 10
+        assert_eq!(
+            spreadsheet.get_computed(cell("F1")).map(Result::unwrap),
+            Some(number(66.0))
+        );
+        // Other functions are given each value of the array
+        assert_eq!(
+            spreadsheet.get_computed(cell("F2")).map(Result::unwrap),
+            Some(number(21.0 + 32.0))
+        );
+        spreadsheet.set_cell(cell("C2"), "7".to_string()).unwrap();
+        assert_eq!(
+            spreadsheet.get_computed(cell("F1")).map(Result::unwrap),
+            Some(number(76.0))
+        );
+        assert!(matches!(
+            spreadsheet.get_error(cell("F3")),
+            Some(ComputeError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_row_and_column() {
+        let mut spreadsheet = SpreadSheet::default();
+        for (name, raw) in [
+            ("C1", "=if(row() > 1, column(), 0)"),
+            ("C4", "=if(row() > 1, column(), 0)"),
+            ("B2", "=row() * 10 + column()"),
+        ] {
+            spreadsheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        let value = |name| spreadsheet.get_computed(cell(name)).map(Result::unwrap);
+        assert_eq!(value("C1"), Some(Value::Number(0.0)));
+        assert_eq!(value("C4"), Some(Value::Number(3.0)));
+        assert_eq!(value("B2"), Some(Value::Number(22.0)));
+
+        assert_eq!(
+            spreadsheet.evaluate_in_cell(cell("D5"), "=row()").unwrap(),
+            Value::Number(5.0)
+        );
+        // Outside of any cell there is no row to give
+        assert!(matches!(
+            spreadsheet.evaluate("=row()"),
+            Err(ComputeError::InvalidArgument(_))
+        ));
+        spreadsheet
+            .set_cell(cell("A1"), "=column(B9)".to_string())
+            .unwrap();
+        assert!(matches!(
+            spreadsheet.get_error(cell("A1")),
+            Some(ComputeError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_convert_to_values() {
+        let mut spreadsheet = SpreadSheet::default();
+        let raw = |sheet: &SpreadSheet, name| sheet.get_raw(&cell(name)).map(str::to_string);
+        for (name, raw) in [
+            ("A1", "2"),
+            ("A2", "=A1 * 10"),
+            ("A3", "=A2 + 1"),
+            ("B1", "=\"=\" + \"x\""),
+            ("B2", "=concat(1, 2)"),
+            ("B3", "=A1 > 1"),
+            ("B4", "=A1 + TRUE"),
+            ("B5", "=\"\""),
+        ] {
+            spreadsheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        // The middle link of the chain is frozen
+        spreadsheet
+            .convert_to_values((cell("A2"), cell("A2")))
+            .unwrap();
+        assert_eq!(spreadsheet.get_raw(&cell("A2")), Some("20"));
+        assert_eq!(
+            spreadsheet.get_computed(cell("A3")).map(Result::unwrap),
+            Some(Value::Number(21.0))
+        );
+        spreadsheet.set_cell(cell("A1"), "5".to_string()).unwrap();
+        assert_eq!(
+            spreadsheet.get_computed(cell("A2")).map(Result::unwrap),
+            Some(Value::Number(20.0))
+        );
+        assert_eq!(
+            spreadsheet.get_computed(cell("A3")).map(Result::unwrap),
+            Some(Value::Number(21.0))
+        );
+
+        let before: Vec<_> = (1..=5)
+            .map(|y| spreadsheet.get_computed(cell(&format!("B{y}"))))
+            .collect();
+        spreadsheet
+            .convert_to_values((cell("B5"), cell("A1")))
+            .unwrap();
+        assert_eq!(raw(&spreadsheet, "B1").as_deref(), Some("'=x"));
+        assert_eq!(raw(&spreadsheet, "B2").as_deref(), Some("'12"));
+        assert_eq!(raw(&spreadsheet, "B3").as_deref(), Some("TRUE"));
+        assert_eq!(raw(&spreadsheet, "B4").as_deref(), Some("#VALUE!"));
+        assert_eq!(raw(&spreadsheet, "B5").as_deref(), Some("'"));
+        for (y, before) in (1..=5).zip(before) {
+            let after = spreadsheet.get_computed(cell(&format!("B{y}")));
+            match (before, after) {
+                (Some(Ok(before)), Some(Ok(after))) => assert_eq!(before, after),
+                (Some(Err(_)), Some(Ok(Value::Text(text)))) => assert_eq!(text, "#VALUE!"),
+                other => panic!("B{y}: {other:?}"),
+            }
+        }
+
+        // Converting is a single step of the history
+        spreadsheet.undo();
+        assert_eq!(raw(&spreadsheet, "B1").as_deref(), Some("=\"=\" + \"x\""));
+        assert_eq!(raw(&spreadsheet, "B5").as_deref(), Some("=\"\""));
+        assert_eq!(raw(&spreadsheet, "A2").as_deref(), Some("20"));
+    }
+
+    #[test]
+    fn test_coercion_functions() {
+        let mut spreadsheet = SpreadSheet::default();
+        for (name, raw) in [
+            ("A1", "12"),
+            ("A2", "text"),
+            ("A3", "TRUE"),
+            ("A4", "=1 + \"x\""),
+            ("B1", "=n(A1) + n(A2) + n(A3) + n(\"2\")"),
+            ("B2", "=concat(t(A1), t(A2), t(A3))"),
+            ("B3", "=type(A1) * 100 + type(A2) * 10 + type(A3)"),
+            ("B4", "=type(A4)"),
+            ("B5", "=n(A9)"),
+        ] {
+            spreadsheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        let value = |name| spreadsheet.get_computed(cell(name)).map(Result::unwrap);
+        assert_eq!(value("B1"), Some(Value::Number(15.0)));
+        assert_eq!(value("B2"), Some(Value::Text("text".to_string())));
+        assert_eq!(value("B3"), Some(Value::Number(124.0)));
+        // Errors and empty cells are not values to tell the type of, they are passed on
+        assert!(matches!(
+            spreadsheet.get_error(cell("B4")),
+            Some(ComputeError::TypeError(_))
+        ));
+        assert!(matches!(
+            spreadsheet.get_error(cell("B5")),
+            Some(ComputeError::UnfindableReference(_))
+        ));
+    }
+
+    #[test]
+    fn test_means_over_ranges() {
+        let mut spreadsheet = SpreadSheet::default();
+        for (name, raw) in [
+            ("A1", "1"),
+            ("A2", "4"),
+            ("A4", "2"),
+            ("A5", "x"),
+            ("B1", "=geomean(A1:A2)"),
+            ("B2", "=harmean(A1:A4, 2)"),
+            ("B3", "=sumsq(A4:A4, A1:A3)"),
+            ("B4", "=geomean(A4:A4)"),
+            ("B5", "=sumsq(A1:A5)"),
+        ] {
+            spreadsheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        let value = |name| spreadsheet.get_computed(cell(name)).map(Result::unwrap);
+        assert_eq!(value("B1"), Some(Value::Number(2.0)));
+        // The empty A3 is skipped, leaving 4 / (1 + 1 / 4 + 1 / 2 + 1 / 2)
+        assert_eq!(value("B2"), Some(Value::Number(4.0 / 2.25)));
+        assert_eq!(value("B3"), Some(Value::Number(21.0)));
+        assert_eq!(value("B4"), Some(Value::Number(2.0)));
+        // Text is not skipped, as for sum
+        assert!(matches!(
+            spreadsheet.get_error(cell("B5")),
+            Some(ComputeError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_regression_over_ranges() {
+        let mut spreadsheet = SpreadSheet::default();
+        // y = 2x + 1, with a label and a missing point in the ranges
+        for (name, raw) in [
+            ("A1", "x"),
+            ("A2", "1"),
+            ("A3", "2"),
+            ("A4", "3"),
+            ("A5", "4"),
+            ("B1", "y"),
+            ("B2", "3"),
+            ("B3", "5"),
+            ("B5", "9"),
+            ("C1", "=slope(B1:B5, A1:A5)"),
+            ("C2", "=intercept(B1:B5, A1:A5)"),
+            ("C3", "=correl(B1:B5, A1:A5)"),
+            ("C4", "=slope(B1:B5, A1:A4)"),
+        ] {
+            spreadsheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        let value = |name| spreadsheet.get_computed(cell(name)).map(Result::unwrap);
+        assert_eq!(value("C1"), Some(Value::Number(2.0)));
+        assert_eq!(value("C2"), Some(Value::Number(1.0)));
+        assert_eq!(value("C3"), Some(Value::Number(1.0)));
+        assert!(matches!(
+            spreadsheet.get_error(cell("C4")),
+            Some(ComputeError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_elementwise_operators() {
+        let mut spreadsheet = SpreadSheet::default();
+        for (name, raw) in [
+            ("A1", "1"),
+            ("A2", "2"),
+            ("A3", "3"),
+            ("B1", "4"),
+            ("B2", "5"),
+            ("B3", "6"),
+            ("C1", "=A1:A3 * 2"),
+            ("C2", "=10 - A1:B1"),
+            ("C3", "=A1:A3 + B1:B3"),
+            ("C4", "=sum(A1:A3 * B1:B3)"),
+            ("C5", "=sumproduct(A1:A3, B1:B3)"),
+            ("C6", "=A1:A3 > 1 && A1:A3 < 3"),
+            ("D1", "=A1:A3 * B1:B2"),
+            ("D2", "=A1:A3 + B1:D1"),
+            ("D3", "=A1:A3 * \"x\""),
+        ] {
+            spreadsheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        let value = |name| spreadsheet.get_computed(cell(name)).map(Result::unwrap);
+        let column = |values: [f64; 3]| {
+            Some(Value::Array(
+                values.map(|value| vec![Value::Number(value)]).to_vec(),
+            ))
+        };
+
+        // A single value goes with every value of the range, on either side
+        assert_eq!(value("C1"), column([2.0, 4.0, 6.0]));
+        assert_eq!(
+            value("C2"),
+            Some(Value::Array(vec![vec![
+                Value::Number(9.0),
+                Value::Number(6.0)
+            ]]))
+        );
+        // Ranges of the same shape go value by value, showing their first value in a cell
+        assert_eq!(value("C3"), column([5.0, 7.0, 9.0]));
+        assert_eq!(value("C3").unwrap().to_string(), "5");
+        assert_eq!(value("C4"), Some(Value::Number(32.0)));
+        assert_eq!(value("C4"), value("C5"));
+        assert_eq!(
+            value("C6"),
+            Some(Value::Array(vec![
+                vec![Value::Bool(false)],
+                vec![Value::Bool(true)],
+                vec![Value::Bool(false)],
+            ]))
+        );
+
+        for name in ["D1", "D2", "D3"] {
+            assert!(
+                matches!(
+                    spreadsheet.get_error(cell(name)),
+                    Some(ComputeError::TypeError(_))
+                ),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_long_formula_on_a_small_stack() {
+        // Each term nests the tree of the formula one level deeper
+        let formula = format!("={}", vec!["A1"; 100_000].join(" + "));
+        let computed = std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(move || {
+                let mut spreadsheet = SpreadSheet::default();
+                spreadsheet
+                    .set_cell(Index { x: 0, y: 0 }, "2".to_string())
+                    .unwrap();
+                spreadsheet.set_cell(Index { x: 1, y: 0 }, formula).unwrap();
+                spreadsheet
+                    .get_computed(Index { x: 1, y: 0 })
+                    .map(Result::unwrap)
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(computed, Some(Value::Number(200_000.0)));
+    }
+
+    #[test]
+    fn test_negative_literal() {
+        let mut spreadsheet = SpreadSheet::default();
+        let (a1, a2, b1) = (
+            Index { x: 0, y: 0 },
+            Index { x: 0, y: 1 },
+            Index { x: 1, y: 0 },
+        );
+        spreadsheet.set_cell(a1, "-5".to_string()).unwrap();
+        spreadsheet.set_cell(a2, " +2.5 ".to_string()).unwrap();
+        spreadsheet
+            .set_cell(b1, "=A1 * 2 + A2".to_string())
+            .unwrap();
+        assert_eq!(
+            spreadsheet.get_computed(b1).map(Result::unwrap),
+            Some(Value::Number(-7.5))
+        );
+
+        spreadsheet.set_cell(a1, "-".to_string()).unwrap();
+        assert!(matches!(
+            spreadsheet.get_error(b1),
+            Some(ComputeError::TypeError(_))
+        ));
+    }
+
+    #[test]
+    fn test_typed_numbers() {
+        let mut spreadsheet = SpreadSheet::default();
+        let typed = [
+            ("A1", "1,000", Value::Number(1000.0)),
+            ("A2", "-1,234,567.25", Value::Number(-1234567.25)),
+            ("A3", "75%", Value::Number(0.75)),
+            ("A4", "1,250.5%", Value::Number(12.505)),
+            ("A5", "2024-01-15", Value::Text("2024-01-15".to_string())),
+            ("A6", "1,00", Value::Text("1,00".to_string())),
+            ("A7", "12%x", Value::Text("12%x".to_string())),
+            ("A8", "1,000,00", Value::Text("1,000,00".to_string())),
+            ("A9", "1000,000", Value::Text("1000,000".to_string())),
+            ("A10", "--5", Value::Text("--5".to_string())),
+        ];
+        for (name, raw, _) in &typed {
+            spreadsheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        for (name, raw, value) in typed {
+            assert_eq!(
+                spreadsheet.get_computed(cell(name)).map(Result::unwrap),
+                Some(value),
+                "{raw}"
+            );
+            // The raw text is kept as typed
+            assert_eq!(spreadsheet.get_raw(&cell(name)), Some(raw));
+        }
+
+        // Percentages set the format of their cell, which stays for later numbers
+        let format = |sheet: &SpreadSheet, name| sheet.get_style(cell(name)).number_format;
+        assert_eq!(format(&spreadsheet, "A1"), NumberFormat::General);
+        assert_eq!(format(&spreadsheet, "A3"), NumberFormat::Percent);
+        spreadsheet.set_cell(cell("A3"), "0.5".to_string()).unwrap();
+        assert_eq!(format(&spreadsheet, "A3"), NumberFormat::Percent);
+        spreadsheet
+            .set_cell(cell("B1"), "=A1 * A3 + A4".to_string())
+            .unwrap();
+        assert_eq!(
+            spreadsheet.get_computed(cell("B1")).map(Result::unwrap),
+            Some(Value::Number(512.505))
+        );
+    }
+
+    #[test]
+    fn test_canonical_formulas() {
+        let mut spreadsheet = SpreadSheet::default();
+        let (a1, a2, a3) = (
+            Index { x: 0, y: 0 },
+            Index { x: 0, y: 1 },
+            Index { x: 0, y: 2 },
+        );
+        spreadsheet.set_cell(a1, "=1+(2*3)".to_string()).unwrap();
+        assert_eq!(spreadsheet.get_raw(&a1), Some("=1+(2*3)"));
+
+        spreadsheet.set_canonical_formulas(true);
+        spreadsheet
+            .set_cell(a2, "=  A1+   sum( B2:B4 ,5 )".to_string())
+            .unwrap();
+        assert_eq!(spreadsheet.get_raw(&a2), Some("=A1 + sum(B2:B4, 5)"));
+        assert_eq!(
+            spreadsheet.get_computed(a2).map(Result::unwrap),
+            Some(Value::Number(12.0))
+        );
+        spreadsheet.set_cell(a1, "=(1+2)*3".to_string()).unwrap();
+        assert_eq!(spreadsheet.get_raw(&a1), Some("=(1 + 2) * 3"));
+
+        // Only formulas are rewritten, and only when they parse
+        spreadsheet.set_cell(a3, "=1 +* 2".to_string()).unwrap();
+        assert_eq!(spreadsheet.get_raw(&a3), Some("=1 +* 2"));
+        spreadsheet.set_cell(a3, " some  text".to_string()).unwrap();
+        assert_eq!(spreadsheet.get_raw(&a3), Some(" some  text"));
+    }
+
+    #[test]
+    fn test_from_markdown() {
+        let sheet = SpreadSheet::from_markdown(
+            "| Date       | Amount   | Note          |\n\
+             |------------|---------:|---------------|\n\
+             | 2024-01-31 | 1,250.50 | =A1 is text   |\n\
+             | 2024-02-29 | -30      |\n\
+             | TRUE       |          | `a \\| b`     |\n",
+        );
+
+        let value = |x, y| sheet.get_computed(Index { x, y }).map(Result::unwrap);
+        let text = |s: &str| Some(Value::Text(s.to_string()));
+        assert_eq!(value(0, 0), text("Date"));
+        assert_eq!(value(0, 1), text("2024-01-31"));
+        assert_eq!(value(1, 1), Some(Value::Number(1250.5)));
+        assert_eq!(value(2, 1), text("=A1 is text"));
+        assert_eq!(value(1, 2), Some(Value::Number(-30.0)));
+        // Missing trailing cells and empty fields stay empty
+        assert_eq!(value(2, 2), None);
+        assert_eq!(value(1, 3), None);
+        assert_eq!(value(0, 3), text("TRUE"));
+        assert_eq!(value(2, 3), text("`a | b`"));
+        assert!(!sheet.is_modified());
+
+        // Imported numbers can be computed over
+        assert_eq!(sheet.evaluate("=B2 + B3").unwrap(), Value::Number(1220.5));
+    }
+
+    #[test]
+    fn test_errors_in_reading_order() {
+        let mut spreadsheet = SpreadSheet::default();
+        let (a2, b1, c1) = (
+            Index { x: 0, y: 1 },
+            Index { x: 1, y: 0 },
+            Index { x: 2, y: 0 },
+        );
+        spreadsheet
+            .add_cell_and_compute(a2, "=NOPE()".to_string())
+            .unwrap();
+        spreadsheet
+            .add_cell_and_compute(c1, "=1 + TRUE".to_string())
+            .unwrap();
+        spreadsheet
+            .add_cell_and_compute(b1, "2".to_string())
+            .unwrap();
+
+        let errors: Vec<Index> = spreadsheet
+            .errors()
+            .into_iter()
+            .map(|(idx, _)| idx)
+            .collect();
+        assert_eq!(errors, vec![c1, a2]);
+    }
+
+    #[test]
+    fn test_non_finite_results_are_errors() {
+        let mut sheet = SpreadSheet::default();
+        for (name, raw) in [
+            ("A1", "0"),
+            ("A2", "1e200"),
+            ("B1", "=A1 / A1"),
+            ("B2", "=(A2 * A2) - (A2 * A2)"),
+            ("B3", "=pow(A1 - 1, 0.5)"),
+            ("C1", "=sum(B3:B3) + 1"),
+            ("C2", "=average(A1:A2) * 2"),
+        ] {
+            sheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+
+        assert_eq!(
+            sheet.get_error(cell("B1")),
+            Some(ComputeError::DivisionByZero)
+        );
+        // The first operation out of range stops the expression
+        assert_eq!(sheet.get_error(cell("B2")), Some(ComputeError::NotANumber));
+        assert_eq!(sheet.get_error(cell("B3")), Some(ComputeError::NotANumber));
+        // An aggregate over it does not hide the error
+        assert_eq!(sheet.get_error(cell("C1")), Some(ComputeError::NotANumber));
+        assert_eq!(
+            sheet.get_computed(cell("C2")),
+            Some(Ok(Value::Number(1e200)))
+        );
+        assert_eq!(
+            computed_to_text(sheet.get_computed(cell("B3")), sheet.display_settings()),
+            "#NUM!"
+        );
+
+        sheet.set_cell(cell("A1"), "2".to_string()).unwrap();
+        assert_eq!(sheet.get_computed(cell("B1")), Some(Ok(Value::Number(1.0))));
+        // Numbers too large to be one are text
+        sheet.set_cell(cell("A3"), "1e400".to_string()).unwrap();
+        assert_eq!(
+            sheet.get_computed(cell("A3")),
+            Some(Ok(Value::Text("1e400".to_string())))
+        );
+    }
+
+    #[test]
+    fn test_invalid_expression() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+
+        spreadsheet
+            .add_cell_and_compute(a1, "=A1 +".to_string())
+            .unwrap();
+
+        assert!(matches!(
+            spreadsheet.get_computed(a1),
+            Some(Err(ComputeError::ParseError(_)))
+        ));
+    }
+
+    #[test]
+    fn test_parse_error_position() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let formula = "=sum(A1, B2))";
+
+        spreadsheet.set_cell(a1, formula.to_string()).unwrap();
+        let error = spreadsheet.parse_error(a1).unwrap();
+        assert_eq!(error.message, "Unexpected ')' at position 13");
+        assert_eq!(error.span, Some(12..13));
+        assert_eq!(
+            spreadsheet
+                .get_error(a1)
+                .unwrap()
+                .message(Language::English),
+            "Unexpected ')' at position 13"
+        );
+
+        // Past the last token, and within a token
+        spreadsheet.set_cell(a1, "=1 +".to_string()).unwrap();
+        let error = spreadsheet.parse_error(a1).unwrap();
+        assert_eq!(error.message, "Unexpected end of formula at position 5");
+        assert_eq!(error.span, Some(4..4));
+        spreadsheet.set_cell(a1, "=(1 2) + 3".to_string()).unwrap();
+        let error = spreadsheet.parse_error(a1).unwrap();
+        assert_eq!(error.message, "Mismatched parentheses at position 5");
+        // Positions count characters, spans bytes
+        spreadsheet.set_cell(a1, "=\"é\" + #".to_string()).unwrap();
+        let error = spreadsheet.parse_error(a1).unwrap();
+        assert_eq!(error.message, "Unexpected character '#' at position 8");
+        assert_eq!(error.span, Some(8..9));
+
+        spreadsheet.set_cell(a1, "=1 + 2".to_string()).unwrap();
+        assert_eq!(spreadsheet.parse_error(a1), None);
+    }
+
+    #[test]
+    fn test_self_reference() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+
+        spreadsheet
+            .add_cell_and_compute(a1, "=A1".to_string())
+            .unwrap();
+
+        assert!(matches!(
             spreadsheet.get_computed(a1),
             Some(Err(ComputeError::Cycle))
         ));
@@ -304,9 +3865,15 @@ mod tests {
         let b1 = Index { x: 1, y: 0 };
         let c1 = Index { x: 2, y: 0 };
 
-        spreadsheet.add_cell_and_compute(a1, "=C1".to_string());
-        spreadsheet.add_cell_and_compute(b1, "=A1 * 2".to_string());
-        spreadsheet.add_cell_and_compute(c1, "=B1".to_string());
+        spreadsheet
+            .add_cell_and_compute(a1, "=C1".to_string())
+            .unwrap();
+        spreadsheet
+            .add_cell_and_compute(b1, "=A1 * 2".to_string())
+            .unwrap();
+        spreadsheet
+            .add_cell_and_compute(c1, "=B1".to_string())
+            .unwrap();
 
         assert!(matches!(
             spreadsheet.get_computed(a1),
@@ -329,9 +3896,15 @@ mod tests {
         let b1 = Index { x: 1, y: 0 };
         let c1 = Index { x: 2, y: 0 };
 
-        spreadsheet.add_cell_and_compute(a1, "15".to_string());
-        spreadsheet.add_cell_and_compute(b1, "23".to_string());
-        spreadsheet.add_cell_and_compute(c1, "=sum(A1:B1)".to_string());
+        spreadsheet
+            .add_cell_and_compute(a1, "15".to_string())
+            .unwrap();
+        spreadsheet
+            .add_cell_and_compute(b1, "23".to_string())
+            .unwrap();
+        spreadsheet
+            .add_cell_and_compute(c1, "=sum(A1:B1)".to_string())
+            .unwrap();
         let computed = spreadsheet.get_computed(c1);
         assert!(matches!(computed, Some(Ok(Value::Number(38.0)))));
     }
@@ -341,7 +3914,9 @@ mod tests {
         let mut spreadsheet = SpreadSheet::default();
         let a1 = Index { x: 0, y: 0 };
 
-        spreadsheet.add_cell_and_compute(a1, "=\"hello\"".to_string());
+        spreadsheet
+            .add_cell_and_compute(a1, "=\"hello\"".to_string())
+            .unwrap();
         let computed = spreadsheet.get_computed(a1);
         let expected = String::from("hello");
         let result = computed.unwrap().unwrap();
@@ -350,4 +3925,576 @@ mod tests {
             _ => panic!("Expected text"),
         }
     }
+
+    #[test]
+    fn test_relocate_raw() {
+        let a1 = Index { x: 0, y: 0 };
+        let b3 = Index { x: 1, y: 2 };
+
+        assert_eq!(SpreadSheet::relocate_raw("=A2*2", a1, b3), "=B4*2");
+        assert_eq!(SpreadSheet::relocate_raw("=B4*2", b3, a1), "=A2*2");
+        // Only formulas hold references
+        assert_eq!(SpreadSheet::relocate_raw("A2", a1, b3), "A2");
+        // Formulas that do not parse are kept as written
+        assert_eq!(
+            SpreadSheet::relocate_raw_tracked("=A2 +* 2", a1, b3),
+            ("=A2 +* 2".to_string(), true)
+        );
+        assert_eq!(
+            SpreadSheet::relocate_raw_tracked("=A2 +* 2", a1, a1),
+            ("=A2 +* 2".to_string(), false)
+        );
+        assert_eq!(
+            SpreadSheet::relocate_raw_tracked("=(1 + 2", a1, b3),
+            ("=(1 + 2".to_string(), false)
+        );
+
+        let mut spread_sheet = SpreadSheet::default();
+        spread_sheet
+            .add_cell_and_compute(a1, "=B1".to_string())
+            .unwrap();
+        let relocated = SpreadSheet::relocate_raw("=B1", a1, Index { x: 0, y: 1 });
+        spread_sheet
+            .add_cell_and_compute(Index { x: 1, y: 1 }, "7".to_string())
+            .unwrap();
+        spread_sheet
+            .add_cell_and_compute(Index { x: 0, y: 1 }, relocated)
+            .unwrap();
+        assert!(matches!(
+            spread_sheet.get_computed(Index { x: 0, y: 1 }),
+            Some(Ok(Value::Number(7.0)))
+        ));
+    }
+
+    #[test]
+    fn test_fill_copies_relative_formulas() {
+        let mut spread_sheet = SpreadSheet::default();
+        for y in 0..4 {
+            spread_sheet
+                .add_cell_and_compute(Index { x: 0, y }, (y + 1).to_string())
+                .unwrap();
+        }
+        let b1 = Index { x: 1, y: 0 };
+        spread_sheet
+            .add_cell_and_compute(b1, "=A1*10".to_string())
+            .unwrap();
+
+        spread_sheet
+            .fill(b1, (b1, Index { x: 1, y: 3 }), FillMode::Copy)
+            .unwrap();
+
+        assert_eq!(spread_sheet.get_raw(&Index { x: 1, y: 3 }), Some("=A4*10"));
+        assert!(matches!(
+            spread_sheet.get_computed(Index { x: 1, y: 2 }),
+            Some(Ok(Value::Number(30.0)))
+        ));
+    }
+
+    #[test]
+    fn test_fill_keeps_anchored_references() {
+        let mut spread_sheet = SpreadSheet::default();
+        spread_sheet.set_canonical_formulas(true);
+        spread_sheet
+            .set_cell(Index { x: 0, y: 0 }, "10".to_string())
+            .unwrap();
+        spread_sheet
+            .set_cell(Index { x: 0, y: 1 }, "2".to_string())
+            .unwrap();
+        let b2 = Index { x: 1, y: 1 };
+        spread_sheet
+            .set_cell(b2, "=A2*$A$1+  sum(A$1:$A2)".to_string())
+            .unwrap();
+        // The anchors survive the canonical form
+        assert_eq!(spread_sheet.get_raw(&b2), Some("=A2 * $A$1 + sum(A$1:$A2)"));
+
+        spread_sheet
+            .fill(b2, (b2, Index { x: 2, y: 2 }), FillMode::Copy)
+            .unwrap();
+        assert_eq!(
+            spread_sheet.get_raw(&Index { x: 2, y: 2 }),
+            Some("=B3 * $A$1 + sum(B$1:$A3)")
+        );
+        spread_sheet
+            .set_cell(Index { x: 0, y: 2 }, "3".to_string())
+            .unwrap();
+        assert_eq!(
+            spread_sheet.get_computed(Index { x: 1, y: 2 }),
+            Some(Ok(Value::Number(45.0)))
+        );
+    }
+
+    #[test]
+    fn test_fill_series() {
+        let mut spread_sheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        spread_sheet
+            .add_cell_and_compute(a1, "5".to_string())
+            .unwrap();
+        spread_sheet
+            .add_cell_and_compute(Index { x: 3, y: 0 }, "old".to_string())
+            .unwrap();
+
+        spread_sheet
+            .fill(a1, (a1, Index { x: 3, y: 0 }), FillMode::Series)
+            .unwrap();
+        assert_eq!(spread_sheet.get_raw(&Index { x: 1, y: 0 }), Some("6"));
+        assert_eq!(spread_sheet.get_raw(&Index { x: 3, y: 0 }), Some("8"));
+
+        // Text can only be copied
+        spread_sheet.mutate_cell(a1, "label".to_string()).unwrap();
+        spread_sheet
+            .fill(a1, (a1, Index { x: 0, y: 2 }), FillMode::Series)
+            .unwrap();
+        assert_eq!(spread_sheet.get_raw(&Index { x: 0, y: 2 }), Some("label"));
+    }
+
+    #[test]
+    fn test_set_cell() {
+        let mut spread_sheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+
+        spread_sheet.set_cell(a1, "1".to_string()).unwrap();
+        spread_sheet.set_cell(a1, "2".to_string()).unwrap();
+        assert_eq!(spread_sheet.get_raw(&a1), Some("2"));
+
+        spread_sheet.set_cell(a1, String::new()).unwrap();
+        spread_sheet.set_cell(a1, String::new()).unwrap();
+        assert!(spread_sheet.cells.is_empty());
+    }
+
+    #[test]
+    fn test_summarize_range() {
+        let mut spread_sheet = SpreadSheet::default();
+        for (y, raw) in ["10", "=A1*2", "text", "=1/", "TRUE", "5"]
+            .iter()
+            .enumerate()
+        {
+            spread_sheet
+                .add_cell_and_compute(Index { x: 0, y }, raw.to_string())
+                .unwrap();
+        }
+        // Outside of the range
+        spread_sheet
+            .add_cell_and_compute(Index { x: 1, y: 0 }, "100".to_string())
+            .unwrap();
+
+        let summary = spread_sheet.summarize_range(Index { x: 0, y: 9 }, Index { x: 0, y: 0 });
+        assert_eq!(
+            summary,
+            RangeSummary {
+                sum: 35.0,
+                average: Some(35.0 / 3.0),
+                count: 3,
+            }
+        );
+
+        let empty = spread_sheet.summarize_range(Index { x: 5, y: 5 }, Index { x: 6, y: 6 });
+        assert_eq!(
+            empty,
+            RangeSummary {
+                sum: 0.0,
+                average: None,
+                count: 0,
+            }
+        );
+
+        // Cells from apart, like a selection of several rectangles
+        let cells = [
+            Index { x: 0, y: 0 },
+            Index { x: 1, y: 0 },
+            Index { x: 0, y: 2 },
+        ];
+        let summary = spread_sheet.summarize_cells(cells);
+        assert_eq!(summary.sum, 110.0);
+        assert_eq!(summary.count, 2);
+    }
+
+    #[test]
+    fn test_formula_tokens() {
+        let formula = "=max(B2, \"é\") + 1.5 &";
+        let tokens = SpreadSheet::formula_tokens(formula);
+
+        let spans: Vec<&str> = tokens
+            .iter()
+            .map(|(_, span)| &formula[span.clone()])
+            .collect();
+        assert_eq!(spans, vec!["max", "(", "B2", ",", "\"é\"", ")", "+", "1.5"]);
+        assert_eq!(tokens[2].0, Token::CellName(Index { x: 1, y: 1 }));
+
+        assert!(SpreadSheet::formula_tokens("12").is_empty());
+    }
+
+    #[test]
+    fn test_precedents() {
+        let mut spread_sheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let b2 = Index { x: 1, y: 1 };
+        let c1 = Index { x: 2, y: 0 };
+        spread_sheet
+            .set_cell(c1, "=B2 * sum(A1:B2) + B2".to_string())
+            .unwrap();
+        spread_sheet.set_cell(a1, "7".to_string()).unwrap();
+
+        assert_eq!(
+            spread_sheet.precedents(c1),
+            vec![Reference::Cell(b2), Reference::Range(a1, b2)]
+        );
+        assert!(spread_sheet.precedents(a1).is_empty());
+        assert!(spread_sheet.precedents(b2).is_empty());
+    }
+
+    #[test]
+    fn test_dependants() {
+        let mut spread_sheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let b1 = Index { x: 1, y: 0 };
+        let c1 = Index { x: 2, y: 0 };
+        let d1 = Index { x: 3, y: 0 };
+        spread_sheet.set_cell(a1, "1".to_string()).unwrap();
+        spread_sheet.set_cell(b1, "=A1 + A1".to_string()).unwrap();
+        spread_sheet
+            .set_cell(c1, "=sum(A1:B1)".to_string())
+            .unwrap();
+        spread_sheet.set_cell(d1, "=C1".to_string()).unwrap();
+
+        let mut direct = spread_sheet.direct_dependants(a1);
+        direct.sort();
+        assert_eq!(direct, vec![b1, c1]);
+
+        let mut all = spread_sheet.all_dependants(a1);
+        all.sort();
+        assert_eq!(all, vec![b1, c1, d1]);
+        assert!(spread_sheet.all_dependants(d1).is_empty());
+    }
+
+    #[test]
+    fn test_validate_formula() {
+        assert!(SpreadSheet::validate_formula("=sum(A1:A3) * 2").is_ok());
+        assert!(SpreadSheet::validate_formula("=A1 +").is_err());
+        assert!(SpreadSheet::validate_formula("=(A1").is_err());
+    }
+
+    #[test]
+    fn test_evaluate_does_not_mutate() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let b1 = Index { x: 1, y: 0 };
+
+        spreadsheet
+            .add_cell_and_compute(a1, "21".to_string())
+            .unwrap();
+
+        assert!(matches!(
+            spreadsheet.evaluate("=A1 * 2"),
+            Ok(Value::Number(42.0))
+        ));
+        assert!(spreadsheet.get_raw(&b1).is_none());
+        assert_eq!(spreadsheet.cells.len(), 1);
+    }
+
+    #[test]
+    fn test_evaluate_in_cell_previews_errors() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let a2 = Index { x: 0, y: 1 };
+        let a3 = Index { x: 0, y: 2 };
+
+        spreadsheet
+            .add_cell_and_compute(a1, "1".to_string())
+            .unwrap();
+        spreadsheet
+            .add_cell_and_compute(a2, "=A1 + 1".to_string())
+            .unwrap();
+
+        assert!(matches!(
+            spreadsheet.evaluate_in_cell(a3, "=A2 * 10"),
+            Ok(Value::Number(20.0))
+        ));
+        assert!(matches!(
+            spreadsheet.evaluate_in_cell(a3, "=B7"),
+            Err(ComputeError::UnfindableReference(_))
+        ));
+        assert!(matches!(
+            spreadsheet.evaluate_in_cell(a1, "=A2"),
+            Err(ComputeError::Cycle)
+        ));
+        assert!(matches!(
+            spreadsheet.evaluate_in_cell(a3, "=A3"),
+            Err(ComputeError::Cycle)
+        ));
+
+        // Previewing never touches the stored cells
+        assert!(matches!(
+            spreadsheet.get_computed(a1),
+            Some(Ok(Value::Number(1.0)))
+        ));
+        assert!(spreadsheet.get_computed(a3).is_none());
+    }
+
+    fn column_raws(spreadsheet: &SpreadSheet, x: usize, rows: usize) -> Vec<&str> {
+        (0..rows)
+            .map(|y| spreadsheet.get_raw(&Index { x, y }).unwrap_or_default())
+            .collect()
+    }
+
+    #[test]
+    fn test_sort_rows_numeric() {
+        let mut spreadsheet = SpreadSheet::default();
+        for (y, (number, name)) in [("3", "c"), ("10", "d"), ("1", "a"), ("2", "b")]
+            .into_iter()
+            .enumerate()
+        {
+            spreadsheet
+                .add_cell_and_compute(Index { x: 0, y }, number.to_string())
+                .unwrap();
+            spreadsheet
+                .add_cell_and_compute(Index { x: 1, y }, name.to_string())
+                .unwrap();
+        }
+        let range = (Index { x: 0, y: 0 }, Index { x: 1, y: 3 });
+
+        spreadsheet.set_fill(Index { x: 1, y: 2 }, Some([255, 0, 0, 255]));
+
+        spreadsheet.sort_rows_by_column(0, range, false).unwrap();
+        assert_eq!(column_raws(&spreadsheet, 0, 4), vec!["1", "2", "3", "10"]);
+        assert_eq!(column_raws(&spreadsheet, 1, 4), vec!["a", "b", "c", "d"]);
+        // Styles move with their rows
+        assert_eq!(
+            spreadsheet.get_style(Index { x: 1, y: 0 }).fill,
+            Some([255, 0, 0, 255])
+        );
+        assert!(spreadsheet.get_style(Index { x: 1, y: 2 }).is_default());
+
+        spreadsheet.sort_rows_by_column(1, range, true).unwrap();
+        assert_eq!(column_raws(&spreadsheet, 0, 4), vec!["10", "3", "2", "1"]);
+    }
+
+    #[test]
+    fn test_sort_rows_mixed_types() {
+        let mut spreadsheet = SpreadSheet::default();
+        // Row 4 is left empty
+        for (y, raw) in [
+            (0, "=Z100"),
+            (1, "banana"),
+            (2, "TRUE"),
+            (3, "10"),
+            (5, "Apple"),
+            (6, "2"),
+        ] {
+            spreadsheet
+                .add_cell_and_compute(Index { x: 0, y }, raw.to_string())
+                .unwrap();
+        }
+        let range = (Index { x: 0, y: 0 }, Index { x: 0, y: 6 });
+
+        spreadsheet.sort_rows_by_column(0, range, false).unwrap();
+        assert_eq!(
+            column_raws(&spreadsheet, 0, 7),
+            vec!["2", "10", "Apple", "banana", "TRUE", "=Z100", ""]
+        );
+
+        spreadsheet.sort_rows_by_column(0, range, true).unwrap();
+        assert_eq!(
+            column_raws(&spreadsheet, 0, 7),
+            vec!["TRUE", "banana", "Apple", "10", "2", "=Z100", ""]
+        );
+    }
+
+    #[test]
+    fn test_sort_keeps_broken_formulas() {
+        let mut spreadsheet = SpreadSheet::default();
+        let cells = [
+            ("A1", "3"),
+            ("A2", "1"),
+            ("A3", "2"),
+            ("B1", "=A1 * 2"),
+            ("B2", "=A3 +* 2"),
+            ("C1", "=A1 +* 2"),
+            ("C2", "=D1 +* 2"),
+        ];
+        for (name, raw) in cells {
+            spreadsheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        spreadsheet
+            .sort_rows_by_column(0, (cell("A1"), cell("B3")), false)
+            .unwrap();
+
+        // Moved along with their row or not, they stay as written
+        let raw = |sheet: &SpreadSheet, name| sheet.get_raw(&cell(name)).map(str::to_string);
+        assert_eq!(raw(&spreadsheet, "B3").as_deref(), Some("=A3 * 2"));
+        assert_eq!(raw(&spreadsheet, "B1").as_deref(), Some("=A3 +* 2"));
+        assert_eq!(raw(&spreadsheet, "C1").as_deref(), Some("=A1 +* 2"));
+        assert!(spreadsheet.needs_attention(cell("B1")));
+        assert!(spreadsheet.needs_attention(cell("C1")));
+        // Nothing it reads moved
+        assert!(!spreadsheet.needs_attention(cell("C2")));
+        assert!(!spreadsheet.needs_attention(cell("B3")));
+        assert!(matches!(
+            spreadsheet.get_error(cell("C1")),
+            Some(ComputeError::ParseError(_))
+        ));
+
+        // Still needing attention once moved again, until edited
+        spreadsheet
+            .sort_rows_by_column(1, (cell("A1"), cell("B3")), true)
+            .unwrap();
+        let moved = (0..3)
+            .map(|y| Index { x: 1, y })
+            .find(|&index| spreadsheet.get_raw(&index) == Some("=A3 +* 2"))
+            .unwrap();
+        assert!(spreadsheet.needs_attention(moved));
+        spreadsheet
+            .set_cell(cell("C1"), "=A2 + 2".to_string())
+            .unwrap();
+        assert!(!spreadsheet.needs_attention(cell("C1")));
+
+        for _ in 0..3 {
+            spreadsheet.undo();
+        }
+        assert_eq!(raw(&spreadsheet, "B2").as_deref(), Some("=A3 +* 2"));
+        assert!(!spreadsheet.needs_attention(cell("B2")));
+    }
+
+    #[test]
+    fn test_fill_keeps_broken_formulas() {
+        let mut spreadsheet = SpreadSheet::default();
+        spreadsheet
+            .set_cell(cell("A1"), "=B1 +* 2".to_string())
+            .unwrap();
+        spreadsheet
+            .set_cell(cell("C1"), "=(1 + 2".to_string())
+            .unwrap();
+        spreadsheet
+            .fill(cell("A1"), (cell("A2"), cell("A3")), FillMode::Copy)
+            .unwrap();
+        spreadsheet
+            .fill(cell("C1"), (cell("C2"), cell("C2")), FillMode::Copy)
+            .unwrap();
+
+        for name in ["A2", "A3"] {
+            assert_eq!(spreadsheet.get_raw(&cell(name)), Some("=B1 +* 2"));
+            assert!(spreadsheet.needs_attention(cell(name)), "{name}");
+        }
+        assert!(!spreadsheet.needs_attention(cell("A1")));
+        // Without references there is nothing to get wrong
+        assert_eq!(spreadsheet.get_raw(&cell("C2")), Some("=(1 + 2"));
+        assert!(!spreadsheet.needs_attention(cell("C2")));
+    }
+
+    #[test]
+    fn test_sort_rows_formulas_follow() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a = |y| Index { x: 0, y };
+        let b = |y| Index { x: 1, y };
+        let d = |y| Index { x: 3, y };
+        for (y, number) in ["3", "1", "2"].into_iter().enumerate() {
+            spreadsheet
+                .add_cell_and_compute(a(y), number.to_string())
+                .unwrap();
+        }
+        spreadsheet
+            .add_cell_and_compute(b(0), "=A1 * 10".to_string())
+            .unwrap();
+        spreadsheet
+            .add_cell_and_compute(d(0), "=A1".to_string())
+            .unwrap();
+        spreadsheet
+            .add_cell_and_compute(d(1), "=sum(A1:A3)".to_string())
+            .unwrap();
+
+        spreadsheet
+            .sort_rows_by_column(0, (a(0), b(2)), false)
+            .unwrap();
+
+        // The formula moved with its row and still reads from it
+        assert_eq!(spreadsheet.get_raw(&b(2)), Some("=A3 * 10"));
+        assert!(spreadsheet.get_raw(&b(0)).is_none());
+        assert!(matches!(
+            spreadsheet.get_computed(b(2)),
+            Some(Ok(Value::Number(30.0)))
+        ));
+
+        // Outside of the range, references follow the rows and ranges stay
+        assert_eq!(spreadsheet.get_raw(&d(0)), Some("=A3"));
+        assert!(matches!(
+            spreadsheet.get_computed(d(0)),
+            Some(Ok(Value::Number(3.0)))
+        ));
+        assert_eq!(spreadsheet.get_raw(&d(1)), Some("=sum(A1:A3)"));
+        assert!(matches!(
+            spreadsheet.get_computed(d(1)),
+            Some(Ok(Value::Number(6.0)))
+        ));
+    }
+
+    #[test]
+    fn test_used_range_follows_cells() {
+        let mut spreadsheet = SpreadSheet::default();
+        let b2 = Index { x: 1, y: 1 };
+        let d5 = Index { x: 3, y: 4 };
+        assert_eq!(spreadsheet.used_range(), None);
+
+        spreadsheet
+            .add_cell_and_compute(b2, "1".to_string())
+            .unwrap();
+        assert_eq!(spreadsheet.used_range(), Some((b2, b2)));
+        spreadsheet.set_fill(d5, Some([255, 0, 0, 255]));
+        assert_eq!(spreadsheet.used_range(), Some((b2, d5)));
+        spreadsheet.set_fill(d5, None);
+        assert_eq!(spreadsheet.used_range(), Some((b2, b2)));
+
+        spreadsheet.undo();
+        assert_eq!(spreadsheet.used_range(), Some((b2, d5)));
+        spreadsheet.remove_cell(b2).unwrap();
+        assert_eq!(spreadsheet.used_range(), Some((d5, d5)));
+    }
+
+    #[test]
+    fn test_revision_follows_content() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let start = spreadsheet.revision();
+        spreadsheet.set_cell(a1, "1".to_string()).unwrap();
+        let edited = spreadsheet.revision();
+        assert!(edited > start);
+
+        // Styles are not content
+        spreadsheet.set_fill(a1, Some([255, 0, 0, 255]));
+        assert_eq!(spreadsheet.revision(), edited);
+        spreadsheet.undo();
+        spreadsheet.undo();
+        assert!(spreadsheet.revision() > edited);
+    }
+
+    #[test]
+    fn test_check_overwrite() {
+        let mut spreadsheet = SpreadSheet::default();
+        for (name, raw) in [("A1", "1"), ("B2", "=A1"), ("C2", "'"), ("D4", "text")] {
+            spreadsheet
+                .add_cell_and_compute(cell(name), raw.to_string())
+                .unwrap();
+        }
+        // A style alone is no data to lose, nor is a cell emptied
+        spreadsheet.set_fill(cell("B1"), Some([0, 0, 255, 255]));
+        spreadsheet
+            .add_cell_and_compute(cell("C1"), "2".to_string())
+            .unwrap();
+        spreadsheet.remove_cell(cell("C1")).unwrap();
+
+        assert_eq!(
+            spreadsheet.check_overwrite((cell("A1"), cell("C3"))),
+            vec![cell("A1"), cell("B2"), cell("C2")]
+        );
+        // Corners in any order
+        assert_eq!(
+            spreadsheet.check_overwrite((cell("C2"), cell("B1"))),
+            vec![cell("B2"), cell("C2")]
+        );
+        assert!(spreadsheet
+            .check_overwrite((cell("B1"), cell("C1")))
+            .is_empty());
+        assert!(spreadsheet
+            .check_overwrite((cell("E1"), cell("Z100")))
+            .is_empty());
+    }
 }
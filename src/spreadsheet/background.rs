@@ -0,0 +1,391 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread;
+
+use super::SpreadSheet;
+use crate::common_types::{Cell, Index};
+
+/// Runs jobs one at a time on a worker thread, only ever the latest one submitted. A job
+/// submitted while another is running supersedes it: jobs still waiting are skipped and
+/// the result of the running one is dropped when it arrives.
+///
+/// On the web, where there are no threads, the latest job waits for the next `poll` instead
+/// and runs within it.
+pub struct ComputeQueue<J, R> {
+    #[cfg(not(target_arch = "wasm32"))]
+    jobs: Sender<(u64, J)>,
+    #[cfg(not(target_arch = "wasm32"))]
+    results: Receiver<(u64, R)>,
+    #[cfg(target_arch = "wasm32")]
+    compute: Box<dyn Fn(J) -> R>,
+    #[cfg(target_arch = "wasm32")]
+    waiting: Option<J>,
+    // The generations of the last job submitted and of the last result taken
+    submitted: u64,
+    received: u64,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl<J: Send + 'static, R: Send + 'static> ComputeQueue<J, R> {
+    pub fn new(compute: impl Fn(J) -> R + Send + 'static) -> Self {
+        Self {
+            compute: Box::new(compute),
+            waiting: None,
+            submitted: 0,
+            received: 0,
+        }
+    }
+
+    /// Keeps `job` for the next `poll`, superseding those submitted before it.
+    pub fn submit(&mut self, job: J) {
+        self.submitted += 1;
+        self.waiting = Some(job);
+    }
+
+    /// Runs the latest job and returns its result, or `None` when there is none.
+    pub fn poll(&mut self) -> Option<R> {
+        let job = self.waiting.take()?;
+        self.received = self.submitted;
+        Some((self.compute)(job))
+    }
+
+    /// Drops the job waiting, as when what it was submitted for is gone.
+    pub fn discard(&mut self) {
+        self.waiting = None;
+        self.submitted += 1;
+        self.received = self.submitted;
+    }
+
+    /// Returns true while a job waits for `poll`.
+    pub fn is_busy(&self) -> bool {
+        self.received < self.submitted
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<J: Send + 'static, R: Send + 'static> ComputeQueue<J, R> {
+    /// Starts the worker running `compute` for each job. It stops once the queue is dropped.
+    pub fn new(compute: impl Fn(J) -> R + Send + 'static) -> Self {
+        let (jobs, waiting) = mpsc::channel::<(u64, J)>();
+        let (finished, results) = mpsc::channel();
+        thread::spawn(move || {
+            while let Ok(mut job) = waiting.recv() {
+                // Only the latest of the jobs that piled up meanwhile is worth running
+                while let Ok(newer) = waiting.try_recv() {
+                    job = newer;
+                }
+                let (generation, job) = job;
+                if finished.send((generation, compute(job))).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Self {
+            jobs,
+            results,
+            submitted: 0,
+            received: 0,
+        }
+    }
+
+    /// Queues `job`, superseding those submitted before it.
+    pub fn submit(&mut self, job: J) {
+        self.submitted += 1;
+        // The worker only stops when its compute panicked, leaving the queue busy for good
+        let _ = self.jobs.send((self.submitted, job));
+    }
+
+    /// Returns the result of the latest job once it is done, without waiting for it. Results
+    /// of superseded jobs are dropped.
+    pub fn poll(&mut self) -> Option<R> {
+        let mut latest = None;
+        while let Ok((generation, result)) = self.results.try_recv() {
+            if generation == self.submitted {
+                self.received = generation;
+                latest = Some(result);
+            }
+        }
+        latest
+    }
+
+    /// Drops the results of every job submitted so far, as when what they were computed
+    /// for is gone.
+    pub fn discard(&mut self) {
+        self.submitted += 1;
+        self.received = self.submitted;
+    }
+
+    /// Returns true while the result of the latest job is yet to be taken.
+    pub fn is_busy(&self) -> bool {
+        self.received < self.submitted
+    }
+}
+
+/// The cells of a sheet as parsed and computed so far, some of them left to compute. See
+/// `SpreadSheet::pending_compute`.
+#[derive(Debug, Clone)]
+pub struct PendingCompute {
+    pub(super) cells: HashMap<Index, Cell>,
+    // The cells whose changed values the others were left to compute for
+    pub(super) changed: HashSet<Index>,
+}
+
+/// The cells of a `PendingCompute` that were left to compute, as computed.
+#[derive(Debug, Default)]
+pub struct ComputedValues {
+    pub(super) cells: HashMap<Index, Cell>,
+    // Whether the compute ran to the end instead of being cancelled
+    pub(super) finished: bool,
+}
+
+/// Computes the cells `pending` leaves to compute in a sheet of its own, for a
+/// `ComputeQueue` to run off the thread of the sheet it was taken from. The other cells keep
+/// their values, exactly as when the sheet computes them itself. `progress` follows the
+/// compute like for `SpreadSheet::compute_all_with_progress`, and the values of cells left
+/// by cancelling it are missing.
+pub fn compute_pending(
+    pending: PendingCompute,
+    progress: &mut dyn FnMut(usize, usize) -> ControlFlow<()>,
+) -> ComputedValues {
+    let mut sheet = SpreadSheet::default();
+    for (index, cell) in &pending.cells {
+        sheet.add_dependencies(*index, cell);
+    }
+    let dirty: Vec<Index> = pending
+        .cells
+        .iter()
+        .filter(|(_, cell)| cell.needs_compute)
+        .map(|(index, _)| *index)
+        .collect();
+    sheet.cells = pending.cells;
+    sheet.changed = pending.changed;
+    let flow = sheet.compute_all_with_progress(progress);
+
+    ComputedValues {
+        cells: dirty
+            .into_iter()
+            .filter_map(|index| Some((index, sheet.cells.remove(&index)?)))
+            .collect(),
+        finished: flow.is_continue(),
+    }
+}
+
+/// How far the compute running in the background got, shared with its worker.
+#[derive(Debug, Default)]
+struct Progress {
+    done: AtomicUsize,
+    total: AtomicUsize,
+    // Set when newer cells are submitted, cancelling the compute running
+    superseded: AtomicBool,
+}
+
+/// Computes what edits to a sheet leave to compute on a worker thread, each submission
+/// cancelling the compute of the one before it. On the web it is computed when polled, see
+/// `ComputeQueue`.
+pub struct BackgroundCompute {
+    queue: ComputeQueue<PendingCompute, ComputedValues>,
+    progress: Arc<Progress>,
+}
+
+impl BackgroundCompute {
+    pub fn start() -> Self {
+        let progress = Arc::new(Progress::default());
+        let queue = ComputeQueue::new({
+            let progress = progress.clone();
+            move |pending| {
+                progress.superseded.store(false, Ordering::Relaxed);
+                progress.done.store(0, Ordering::Relaxed);
+                progress.total.store(0, Ordering::Relaxed);
+                compute_pending(pending, &mut |done, total| {
+                    progress.done.store(done, Ordering::Relaxed);
+                    progress.total.store(total, Ordering::Relaxed);
+                    match progress.superseded.load(Ordering::Relaxed) {
+                        true => ControlFlow::Break(()),
+                        false => ControlFlow::Continue(()),
+                    }
+                })
+            }
+        });
+        Self { queue, progress }
+    }
+
+    /// Queues `pending` to compute. See `ComputeQueue::submit`.
+    pub fn submit(&mut self, pending: PendingCompute) {
+        self.progress.superseded.store(true, Ordering::Relaxed);
+        self.queue.submit(pending);
+    }
+
+    /// Returns the values of the cells submitted last once they are computed.
+    pub fn poll(&mut self) -> Option<ComputedValues> {
+        self.queue.poll()
+    }
+
+    /// Drops the values of every submission so far.
+    pub fn discard(&mut self) {
+        self.progress.superseded.store(true, Ordering::Relaxed);
+        self.queue.discard();
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.queue.is_busy()
+    }
+
+    /// Returns the part of the cells computed so far, from 0 to 1, while a compute runs long
+    /// enough to report it.
+    pub fn progress(&self) -> Option<f32> {
+        let total = self.progress.total.load(Ordering::Relaxed);
+        let done = self.progress.done.load(Ordering::Relaxed);
+        (self.is_busy() && total > 0).then(|| done as f32 / total as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::common_types::{ComputeError, Value};
+
+    /// Polls until a result comes, for a second at most.
+    fn wait_for<R>(mut poll: impl FnMut() -> Option<R>) -> Option<R> {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(1) {
+            if let Some(result) = poll() {
+                return Some(result);
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        None
+    }
+
+    #[test]
+    fn test_latest_job_supersedes() {
+        // Each job takes until the test lets it finish, and says when it started
+        let (started, job_started) = mpsc::channel();
+        let (let_finish, finish) = mpsc::channel::<()>();
+        let computed = Arc::new(Mutex::new(Vec::new()));
+        let mut queue = ComputeQueue::new({
+            let computed = computed.clone();
+            move |job: u32| {
+                started.send(job).unwrap();
+                finish.recv().unwrap();
+                computed.lock().unwrap().push(job);
+                job * 10
+            }
+        });
+        assert!(!queue.is_busy());
+
+        queue.submit(1);
+        assert_eq!(job_started.recv().unwrap(), 1);
+        // Edits made while the first job runs
+        queue.submit(2);
+        queue.submit(3);
+        assert!(queue.is_busy());
+
+        let_finish.send(()).unwrap();
+        // The second job is skipped for the third
+        assert_eq!(job_started.recv().unwrap(), 3);
+        assert_eq!(queue.poll(), None);
+        assert!(queue.is_busy());
+
+        let_finish.send(()).unwrap();
+        assert_eq!(wait_for(|| queue.poll()), Some(30));
+        assert!(!queue.is_busy());
+        assert_eq!(*computed.lock().unwrap(), vec![1, 3]);
+    }
+
+    #[test]
+    fn test_discard() {
+        let mut queue = ComputeQueue::new(|job: u32| {
+            thread::sleep(Duration::from_millis(20));
+            job
+        });
+        queue.submit(1);
+        queue.discard();
+        assert!(!queue.is_busy());
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(queue.poll(), None);
+
+        queue.submit(2);
+        assert_eq!(wait_for(|| queue.poll()), Some(2));
+    }
+
+    #[test]
+    fn test_compute_in_background() {
+        let mut sheet = SpreadSheet::default();
+        let a = |y| Index { x: 0, y };
+        sheet.add_cell_and_compute(a(0), "1".to_string()).unwrap();
+        sheet
+            .add_cell_and_compute(a(1), "=A1 + 1".to_string())
+            .unwrap();
+        sheet
+            .add_cell_and_compute(a(2), "=A2 * 10".to_string())
+            .unwrap();
+        sheet.defer_compute(true);
+        let mut background = BackgroundCompute::start();
+
+        sheet.add_cell_and_compute(a(0), "2".to_string()).unwrap();
+        // The dependants keep their last values until the background compute is merged
+        assert_eq!(
+            sheet.get_computed(a(2)).map(Result::unwrap),
+            Some(Value::Number(20.0))
+        );
+        assert!(sheet.take_compute_request());
+        assert!(!sheet.take_compute_request());
+        background.submit(sheet.pending_compute());
+
+        let computed = wait_for(|| background.poll()).unwrap();
+        // An edit made meanwhile is kept, it was not part of the compute
+        sheet.add_cell_and_compute(a(3), "x".to_string()).unwrap();
+        sheet.apply_computed(computed);
+        assert_eq!(
+            sheet.get_computed(a(1)).map(Result::unwrap),
+            Some(Value::Number(3.0))
+        );
+        assert_eq!(
+            sheet.get_computed(a(2)).map(Result::unwrap),
+            Some(Value::Number(30.0))
+        );
+        assert_eq!(
+            sheet.get_computed(a(3)).map(Result::unwrap),
+            Some(Value::Text("x".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_compute_pending_keeps_values() {
+        let mut sheet = SpreadSheet::default();
+        let (a, b) = (|y| Index { x: 0, y }, |y| Index { x: 1, y });
+        for (y, raw) in ["1", "=A1 + 1", "3"].iter().enumerate() {
+            sheet.add_cell_and_compute(a(y), raw.to_string()).unwrap();
+        }
+        sheet
+            .add_cell_and_compute(b(0), "=sum(A1:A3)".to_string())
+            .unwrap();
+        sheet.add_cell_and_compute(b(1), "=B2".to_string()).unwrap();
+        sheet.defer_compute(true);
+
+        sheet.add_cell_and_compute(a(2), "4".to_string()).unwrap();
+        let computed = compute_pending(sheet.pending_compute(), &mut |_, _| {
+            ControlFlow::Continue(())
+        });
+        // The sum was left to compute, over the value A2 already had, and the cycle is
+        // always left to compute
+        let mut indices: Vec<Index> = computed.cells.keys().copied().collect();
+        indices.sort_by_key(|index| (index.y, index.x));
+        assert_eq!(indices, vec![b(0), b(1)]);
+        sheet.apply_computed(computed);
+        assert_eq!(
+            sheet.get_computed(b(0)).map(Result::unwrap),
+            Some(Value::Number(7.0))
+        );
+        assert!(matches!(sheet.get_error(b(1)), Some(ComputeError::Cycle)));
+    }
+}
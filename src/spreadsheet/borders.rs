@@ -0,0 +1,188 @@
+use super::SpreadSheet;
+use crate::common_types::{BorderStyle, Borders, Edge, Index};
+
+/// Returns the border drawn along an edge two cells share, from the border the cell above or
+/// left of it set on its side and the one the other cell set on its side. The cell that set
+/// a border owns the edge, the first of the two when both did, and the second gives way.
+pub fn edge_owner(first: Option<BorderStyle>, second: Option<BorderStyle>) -> Option<BorderStyle> {
+    first.or(second)
+}
+
+/// Returns the cell sharing `edge` with the cell at `index`, if there is one.
+fn neighbour(index: Index, edge: Edge) -> Option<Index> {
+    let Index { x, y } = index;
+    match edge {
+        Edge::Top => Some(Index {
+            x,
+            y: y.checked_sub(1)?,
+        }),
+        Edge::Bottom => Some(Index { x, y: y + 1 }),
+        Edge::Left => Some(Index {
+            x: x.checked_sub(1)?,
+            y,
+        }),
+        Edge::Right => Some(Index { x: x + 1, y }),
+    }
+}
+
+impl SpreadSheet {
+    /// Sets the border along `edges` of every cell of the rectangle spanned by the corners of
+    /// `range`, `None` clearing it. Undone as a single step.
+    pub fn set_border(
+        &mut self,
+        range: (Index, Index),
+        edges: &[Edge],
+        border: Option<BorderStyle>,
+    ) {
+        let (from, to) = range;
+        self.batch(|sheet| {
+            for y in from.y.min(to.y)..=from.y.max(to.y) {
+                for x in from.x.min(to.x)..=from.x.max(to.x) {
+                    sheet.update_style(Index { x, y }, |style| {
+                        for &edge in edges {
+                            style.borders.set(edge, border);
+                        }
+                    });
+                }
+            }
+        });
+    }
+
+    /// Sets the border along the outside of the rectangle spanned by the corners of `range`,
+    /// `None` clearing it. The edges between its cells keep theirs. Undone as a single step.
+    pub fn set_outline_border(&mut self, range: (Index, Index), border: Option<BorderStyle>) {
+        let (from, to) = range;
+        let (left, right) = (from.x.min(to.x), from.x.max(to.x));
+        let (top, bottom) = (from.y.min(to.y), from.y.max(to.y));
+        let corner = |x, y| Index { x, y };
+        self.batch(|sheet| {
+            sheet.set_border(
+                (corner(left, top), corner(right, top)),
+                &[Edge::Top],
+                border,
+            );
+            sheet.set_border(
+                (corner(left, bottom), corner(right, bottom)),
+                &[Edge::Bottom],
+                border,
+            );
+            sheet.set_border(
+                (corner(left, top), corner(left, bottom)),
+                &[Edge::Left],
+                border,
+            );
+            sheet.set_border(
+                (corner(right, top), corner(right, bottom)),
+                &[Edge::Right],
+                border,
+            );
+        });
+    }
+
+    /// Returns the border drawn along `edge` of the cell at `index`, whichever of it and the
+    /// cell sharing the edge set it. See `edge_owner`.
+    pub fn border(&self, index: Index, edge: Edge) -> Option<BorderStyle> {
+        let own = self.get_style(index).borders.get(edge);
+        let Some(neighbour) = neighbour(index, edge) else {
+            return own;
+        };
+        let theirs = self.get_style(neighbour).borders.get(edge.opposite());
+        match edge {
+            Edge::Top | Edge::Left => edge_owner(theirs, own),
+            Edge::Bottom | Edge::Right => edge_owner(own, theirs),
+        }
+    }
+
+    /// Returns the borders to draw for the cell at `index` in a grid of cells drawn each with
+    /// its own, so that every edge is drawn once: its top and left edges, and its right and
+    /// bottom ones when it is `last` in its row and in its column.
+    pub fn borders_to_draw(&self, index: Index, last: (bool, bool)) -> Borders {
+        let (last_in_row, last_in_column) = last;
+        let mut borders = Borders::default();
+        for edge in Edge::ALL {
+            let drawn = match edge {
+                Edge::Top | Edge::Left => true,
+                Edge::Right => last_in_row,
+                Edge::Bottom => last_in_column,
+            };
+            if drawn {
+                borders.set(edge, self.border(index, edge));
+            }
+        }
+        borders
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_types::cell_addr::cell;
+
+    const THIN: BorderStyle = BorderStyle {
+        width: 1,
+        color: [0, 0, 0, 255],
+    };
+    const THICK: BorderStyle = BorderStyle {
+        width: 3,
+        color: [200, 0, 0, 255],
+    };
+
+    #[test]
+    fn test_edge_owner() {
+        assert_eq!(edge_owner(Some(THIN), None), Some(THIN));
+        assert_eq!(edge_owner(None, Some(THICK)), Some(THICK));
+        // The first cell wins when both set a border
+        assert_eq!(edge_owner(Some(THIN), Some(THICK)), Some(THIN));
+        assert_eq!(edge_owner(None, None), None);
+    }
+
+    #[test]
+    fn test_shared_edges() {
+        let mut sheet = SpreadSheet::default();
+        sheet.set_border((cell("B2"), cell("B2")), &[Edge::Bottom], Some(THICK));
+        // The same edge, seen from the cell below
+        assert_eq!(sheet.border(cell("B3"), Edge::Top), Some(THICK));
+        assert_eq!(sheet.border(cell("B2"), Edge::Bottom), Some(THICK));
+
+        // The explicitly styled cell wins over the default of its neighbour
+        sheet.set_border((cell("C2"), cell("C2")), &[Edge::Left], Some(THIN));
+        assert_eq!(sheet.border(cell("B2"), Edge::Right), Some(THIN));
+        sheet.set_border((cell("B2"), cell("B2")), &[Edge::Right], Some(THICK));
+        assert_eq!(sheet.border(cell("C2"), Edge::Left), Some(THICK));
+        assert_eq!(sheet.border(cell("A1"), Edge::Top), None);
+
+        // Each edge of a row of cells is drawn once
+        let drawn: Vec<Borders> = ["B2", "C2"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| sheet.borders_to_draw(cell(name), (i == 1, true)))
+            .collect();
+        assert_eq!(drawn[0].right, None);
+        assert_eq!(drawn[1].left, Some(THICK));
+        assert_eq!(drawn[0].bottom, Some(THICK));
+        assert_eq!(drawn[1].bottom, None);
+    }
+
+    #[test]
+    fn test_borders_are_kept() {
+        let mut sheet = SpreadSheet::default();
+        sheet.set_cell(cell("A1"), "total".to_string()).unwrap();
+        sheet.set_outline_border((cell("C3"), cell("A1")), Some(THIN));
+        let snapshot = sheet.snapshot();
+        assert_eq!(sheet.get_style(cell("A1")).borders.top, Some(THIN));
+        assert_eq!(sheet.get_style(cell("C3")).borders.right, Some(THIN));
+        assert_eq!(sheet.get_style(cell("B2")).borders, Borders::default());
+
+        // Undone as one step, and brought back with the rest of the sheet
+        sheet.undo();
+        assert_eq!(sheet.get_style(cell("C3")).borders, Borders::default());
+        assert_eq!(sheet.get_raw(&cell("A1")), Some("total"));
+        sheet.restore(snapshot);
+        assert_eq!(sheet.get_style(cell("A1")).borders.left, Some(THIN));
+        assert_eq!(sheet.get_style(cell("B3")).borders.bottom, Some(THIN));
+
+        // Content written into a cell leaves its borders
+        sheet.set_cell(cell("B1"), "=A1".to_string()).unwrap();
+        assert_eq!(sheet.get_style(cell("B1")).borders.top, Some(THIN));
+    }
+}
@@ -0,0 +1,797 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::export::json_string;
+use super::validation::{ValidationKind, ValidationRule};
+use super::view_state::{column_of, row_of, Json, JsonReader};
+use super::SpreadSheet;
+use crate::common_types::{
+    col_to_letters, index_to_name, name_to_index, BorderStyle, CellStyle, Edge, Index, NumberFormat,
+};
+
+/// The version of the cell style files written. Files of later versions are read all the
+/// same, keys unknown to this one left out.
+pub const CELL_STYLES_VERSION: u32 = 1;
+
+/// Returns the file the styles of the cells of the sheet at `sheet_path` are kept in, like
+/// `budget.sheet.style.json` for `budget.sheet`, the sheet file only holding content.
+pub fn cell_styles_path(sheet_path: &Path) -> PathBuf {
+    let mut name = sheet_path.as_os_str().to_owned();
+    name.push(".style.json");
+    PathBuf::from(name)
+}
+
+fn edge_name(edge: Edge) -> &'static str {
+    match edge {
+        Edge::Top => "top",
+        Edge::Bottom => "bottom",
+        Edge::Left => "left",
+        Edge::Right => "right",
+    }
+}
+
+/// What is kept of a sheet in the file next to it, apart from its content.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SheetStyles {
+    /// Whether the sheet is protected, see `SpreadSheet::set_protection`.
+    pub protected: bool,
+    /// The number formats of columns and rows, see `SpreadSheet::set_column_format`.
+    pub column_formats: BTreeMap<usize, NumberFormat>,
+    pub row_formats: BTreeMap<usize, NumberFormat>,
+    /// The cells with a style other than the default one.
+    pub cells: Vec<(Index, CellStyle)>,
+    /// The rules of the sheet in the order they were set, see `SpreadSheet::set_validation`.
+    pub validation_rules: Vec<ValidationRule>,
+}
+
+impl SheetStyles {
+    /// Returns whether there is nothing to keep.
+    pub fn is_empty(&self) -> bool {
+        !self.protected
+            && self.column_formats.is_empty()
+            && self.row_formats.is_empty()
+            && self.validation_rules.is_empty()
+            && self.cells.iter().all(|(_, style)| style.is_default())
+    }
+
+    /// Writes what is kept as a JSON object, cells by name from the top left, row by row,
+    /// columns by letter and rows by number like the labels of the grid, and colors as red,
+    /// green, blue and alpha. A bound of a number rule that is not finite is left out as
+    /// `null`, reading as no bound.
+    pub fn to_json(&self) -> String {
+        let mut styles: Vec<&(Index, CellStyle)> = self
+            .cells
+            .iter()
+            .filter(|(_, style)| !style.is_default())
+            .collect();
+        styles.sort_by_key(|(index, _)| (index.y, index.x));
+        let cells: Vec<String> = styles
+            .into_iter()
+            .map(|(index, style)| {
+                let mut fields = Vec::new();
+                if let Some(fill) = style.fill {
+                    fields.push(format!("\"fill\": {}", color_json(fill)));
+                }
+                if style.bold {
+                    fields.push("\"bold\": true".to_string());
+                }
+                if style.italic {
+                    fields.push("\"italic\": true".to_string());
+                }
+                if style.number_format != NumberFormat::General {
+                    fields.push(format!(
+                        "\"number_format\": {}",
+                        format_json(style.number_format)
+                    ));
+                }
+                if style.locked {
+                    fields.push("\"locked\": true".to_string());
+                }
+                let borders: Vec<String> = Edge::ALL
+                    .into_iter()
+                    .filter_map(|edge| {
+                        let BorderStyle { width, color } = style.borders.get(edge)?;
+                        Some(format!(
+                            "\"{}\": {{\"width\": {width}, \"color\": {}}}",
+                            edge_name(edge),
+                            color_json(color)
+                        ))
+                    })
+                    .collect();
+                if !borders.is_empty() {
+                    fields.push(format!("\"borders\": {{{}}}", borders.join(", ")));
+                }
+                format!("\"{}\": {{{}}}", index_to_name(*index), fields.join(", "))
+            })
+            .collect();
+        let mut fields = vec![format!("\"version\": {CELL_STYLES_VERSION}")];
+        if self.protected {
+            fields.push("\"protected\": true".to_string());
+        }
+        let formats = |formats: &BTreeMap<usize, NumberFormat>, name: fn(usize) -> String| {
+            let entries: Vec<String> = formats
+                .iter()
+                .map(|(&line, &format)| format!("\"{}\": {}", name(line), format_json(format)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        };
+        if !self.column_formats.is_empty() {
+            fields.push(format!(
+                "\"column_formats\": {}",
+                formats(&self.column_formats, col_to_letters)
+            ));
+        }
+        if !self.row_formats.is_empty() {
+            fields.push(format!(
+                "\"row_formats\": {}",
+                formats(&self.row_formats, |row| (row + 1).to_string())
+            ));
+        }
+        if !self.validation_rules.is_empty() {
+            let rules: Vec<String> = self.validation_rules.iter().map(rule_json).collect();
+            fields.push(format!(
+                "\"validation_rules\": [\n    {}\n  ]",
+                rules.join(",\n    ")
+            ));
+        }
+        fields.push(format!(
+            "\"cells\": {{{}}}",
+            match cells.is_empty() {
+                true => String::new(),
+                false => format!("\n    {}\n  ", cells.join(",\n    ")),
+            }
+        ));
+        format!("{{\n  {}\n}}\n", fields.join(",\n  "))
+    }
+
+    /// Reads what `to_json` writes, each style the default one with what it keeps. Unknown
+    /// keys are skipped, but a known key holding a value it cannot is an error.
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let Json::Object(entries) = JsonReader::read(text)? else {
+            return Err("Expected an object".to_string());
+        };
+
+        let mut styles = Self::default();
+        let mut version = false;
+        for (key, value) in entries {
+            match (key.as_str(), value) {
+                ("version", Json::Number(_)) => version = true,
+                ("version", _) => return Err("version should be a number".to_string()),
+                ("protected", Json::Bool(protected)) => styles.protected = protected,
+                ("protected", _) => return Err("protected should be a boolean".to_string()),
+                ("column_formats", value) => {
+                    styles.column_formats = formats(&key, value, column_of)?;
+                }
+                ("row_formats", value) => styles.row_formats = formats(&key, value, row_of)?,
+                ("cells", Json::Object(cells)) => {
+                    for (name, fields) in cells {
+                        let index = name_to_index(&name)
+                            .map_err(|_| format!("cells: invalid cell name {name}"))?;
+                        styles.cells.push((index, style(&name, fields)?));
+                    }
+                }
+                ("cells", _) => return Err("cells should be an object".to_string()),
+                ("validation_rules", Json::Array(rules)) => {
+                    for (i, rule) in rules.into_iter().enumerate() {
+                        let key = format!("{key}.{i}");
+                        styles.validation_rules.extend(validation_rule(&key, rule)?);
+                    }
+                }
+                ("validation_rules", _) => {
+                    return Err("validation_rules should be an array".to_string())
+                }
+                // Written by a later version
+                _ => (),
+            }
+        }
+        match version {
+            true => Ok(styles),
+            false => Err("Missing version".to_string()),
+        }
+    }
+}
+
+fn style(name: &str, fields: Json) -> Result<CellStyle, String> {
+    let Json::Object(fields) = fields else {
+        return Err(format!("{name} should be an object"));
+    };
+    let mut style = CellStyle::default();
+    for (field, value) in fields {
+        match (field.as_str(), value) {
+            ("fill", value) => style.fill = Some(color(&format!("{name}.fill"), value)?),
+            ("bold", Json::Bool(bold)) => style.bold = bold,
+            ("italic", Json::Bool(italic)) => style.italic = italic,
+            ("locked", Json::Bool(locked)) => style.locked = locked,
+            ("number_format", value) => {
+                style.number_format = number_format(&format!("{name}.number_format"), value)?
+            }
+            ("borders", Json::Object(borders)) => {
+                for (key, border) in borders {
+                    let Some(edge) = Edge::ALL.into_iter().find(|&edge| edge_name(edge) == key)
+                    else {
+                        continue;
+                    };
+                    let key = format!("{name}.borders.{key}");
+                    style.borders.set(edge, Some(border_style(&key, border)?));
+                }
+            }
+            ("borders", _) => return Err(format!("{name}.borders should be an object")),
+            ("bold" | "italic" | "locked", _) => {
+                return Err(format!("{name}.{field} should be a boolean"))
+            }
+            _ => (),
+        }
+    }
+    Ok(style)
+}
+
+/// Reads an object of number formats by line, its keys read into lines by `line`.
+fn formats(
+    key: &str,
+    value: Json,
+    line: fn(&str) -> Option<usize>,
+) -> Result<BTreeMap<usize, NumberFormat>, String> {
+    let Json::Object(entries) = value else {
+        return Err(format!("{key} should be an object"));
+    };
+    entries
+        .into_iter()
+        .map(|(name, format)| match line(&name) {
+            Some(line) => Ok((line, number_format(&format!("{key}.{name}"), format)?)),
+            None => Err(format!("{key}: invalid line {name}")),
+        })
+        .collect()
+}
+
+/// Writes a number format as an object of its kind and what else it has.
+fn format_json(format: NumberFormat) -> String {
+    match format {
+        NumberFormat::General => "{\"kind\": \"general\"}".to_string(),
+        NumberFormat::Percent => "{\"kind\": \"percent\"}".to_string(),
+        NumberFormat::Fixed { decimals } => {
+            format!("{{\"kind\": \"fixed\", \"decimals\": {decimals}}}")
+        }
+        NumberFormat::FixedPercent { decimals } => {
+            format!("{{\"kind\": \"fixed_percent\", \"decimals\": {decimals}}}")
+        }
+        NumberFormat::Currency { symbol, decimals } => format!(
+            "{{\"kind\": \"currency\", \"symbol\": {}, \"decimals\": {decimals}}}",
+            json_string(&symbol.to_string())
+        ),
+    }
+}
+
+/// Reads a number format written by `format_json`. A kind unknown to this version reads as
+/// `NumberFormat::General`.
+fn number_format(key: &str, value: Json) -> Result<NumberFormat, String> {
+    let Json::Object(fields) = value else {
+        return Err(format!("{key} should be an object"));
+    };
+    let (mut kind, mut decimals, mut symbol) = (None, None, None);
+    for (field, value) in fields {
+        match (field.as_str(), value) {
+            ("kind", Json::Text(text)) => kind = Some(text),
+            ("decimals", value) => match byte(&value) {
+                Some(count) if count <= NumberFormat::MAX_DECIMALS => decimals = Some(count),
+                _ => {
+                    return Err(format!(
+                        "{key}.decimals should be a whole number from 0 to {}",
+                        NumberFormat::MAX_DECIMALS
+                    ))
+                }
+            },
+            ("symbol", Json::Text(text)) if text.chars().count() == 1 => {
+                symbol = text.chars().next()
+            }
+            ("kind", _) => return Err(format!("{key}.kind should be a string")),
+            ("symbol", _) => return Err(format!("{key}.symbol should be a single character")),
+            _ => (),
+        }
+    }
+    let Some(kind) = kind else {
+        return Err(format!("{key} should have a kind"));
+    };
+    let decimals = || decimals.ok_or_else(|| format!("{key} should have decimals"));
+    Ok(match kind.as_str() {
+        "percent" => NumberFormat::Percent,
+        "fixed" => NumberFormat::Fixed {
+            decimals: decimals()?,
+        },
+        "fixed_percent" => NumberFormat::FixedPercent {
+            decimals: decimals()?,
+        },
+        "currency" => NumberFormat::Currency {
+            symbol: symbol.ok_or_else(|| format!("{key} should have a symbol"))?,
+            decimals: decimals()?,
+        },
+        // General, or written by a later version
+        _ => NumberFormat::General,
+    })
+}
+
+fn rule_json(rule: &ValidationRule) -> String {
+    let (top_left, bottom_right) = rule.range;
+    let bound = |bound: f64| match bound.is_finite() {
+        true => bound.to_string(),
+        false => "null".to_string(),
+    };
+    let kind = match &rule.kind {
+        ValidationKind::NumberBetween(min, max) => format!(
+            "\"kind\": \"number_between\", \"min\": {}, \"max\": {}",
+            bound(*min),
+            bound(*max)
+        ),
+        ValidationKind::OneOf(options) => {
+            let options: Vec<String> = options.iter().map(|option| json_string(option)).collect();
+            format!(
+                "\"kind\": \"one_of\", \"options\": [{}]",
+                options.join(", ")
+            )
+        }
+        ValidationKind::Formula(formula) => {
+            format!(
+                "\"kind\": \"formula\", \"formula\": {}",
+                json_string(formula)
+            )
+        }
+    };
+    format!(
+        "{{\"range\": \"{}:{}\", {kind}, \"strict\": {}}}",
+        index_to_name(top_left),
+        index_to_name(bottom_right),
+        rule.strict
+    )
+}
+
+/// Reads a rule written by `rule_json`, `None` for a kind unknown to this version.
+fn validation_rule(key: &str, value: Json) -> Result<Option<ValidationRule>, String> {
+    let Json::Object(fields) = value else {
+        return Err(format!("{key} should be an object"));
+    };
+    let (mut range, mut kind, mut strict) = (None, None, false);
+    let (mut min, mut max, mut options, mut formula) =
+        (f64::NEG_INFINITY, f64::INFINITY, None, None);
+    for (field, value) in fields {
+        match (field.as_str(), value) {
+            ("range", Json::Text(text)) => {
+                let corners = text.split_once(':').and_then(|(from, to)| {
+                    Some((name_to_index(from).ok()?, name_to_index(to).ok()?))
+                });
+                match corners {
+                    Some(corners) => range = Some(corners),
+                    None => return Err(format!("{key}: invalid range {text}")),
+                }
+            }
+            ("kind", Json::Text(text)) => kind = Some(text),
+            ("strict", Json::Bool(value)) => strict = value,
+            ("min", Json::Number(value)) => min = value,
+            ("max", Json::Number(value)) => max = value,
+            ("min" | "max", Json::Null) => (),
+            ("options", Json::Array(items)) => {
+                let items: Option<Vec<String>> = items
+                    .into_iter()
+                    .map(|item| match item {
+                        Json::Text(text) => Some(text),
+                        _ => None,
+                    })
+                    .collect();
+                match items {
+                    Some(items) => options = Some(items),
+                    None => return Err(format!("{key}.options should be an array of strings")),
+                }
+            }
+            ("formula", Json::Text(text)) => formula = Some(text),
+            ("range" | "kind" | "formula", _) => {
+                return Err(format!("{key}.{field} should be a string"))
+            }
+            ("strict", _) => return Err(format!("{key}.strict should be a boolean")),
+            ("min" | "max", _) => return Err(format!("{key}.{field} should be a number or null")),
+            ("options", _) => return Err(format!("{key}.options should be an array")),
+            _ => (),
+        }
+    }
+    let Some(range) = range else {
+        return Err(format!("{key} should have a range"));
+    };
+    let kind = match kind.as_deref() {
+        Some("number_between") => ValidationKind::NumberBetween(min, max),
+        Some("one_of") => {
+            ValidationKind::OneOf(options.ok_or_else(|| format!("{key} should have options"))?)
+        }
+        Some("formula") => {
+            ValidationKind::Formula(formula.ok_or_else(|| format!("{key} should have a formula"))?)
+        }
+        Some(_) => return Ok(None),
+        None => return Err(format!("{key} should have a kind")),
+    };
+    Ok(Some(ValidationRule {
+        range,
+        kind,
+        strict,
+    }))
+}
+
+fn border_style(key: &str, value: Json) -> Result<BorderStyle, String> {
+    let Json::Object(fields) = value else {
+        return Err(format!("{key} should be an object"));
+    };
+    let (mut width, mut color_of) = (None, None);
+    for (field, value) in fields {
+        match field.as_str() {
+            "width" => {
+                width =
+                    Some(byte(&value).ok_or_else(|| {
+                        format!("{key}.width should be a whole number from 0 to 255")
+                    })?)
+            }
+            "color" => color_of = Some(color(&format!("{key}.color"), value)?),
+            _ => (),
+        }
+    }
+    match (width, color_of) {
+        (Some(width), Some(color)) => Ok(BorderStyle { width, color }),
+        _ => Err(format!("{key} should have a width and a color")),
+    }
+}
+
+fn color_json([red, green, blue, alpha]: [u8; 4]) -> String {
+    format!("[{red}, {green}, {blue}, {alpha}]")
+}
+
+/// Reads red, green, blue and alpha.
+fn color(key: &str, value: Json) -> Result<[u8; 4], String> {
+    let Json::Array(channels) = value else {
+        return Err(format!("{key} should be an array"));
+    };
+    let channels: Option<Vec<u8>> = channels.iter().map(byte).collect();
+    match channels.as_deref() {
+        Some(&[red, green, blue, alpha]) => Ok([red, green, blue, alpha]),
+        _ => Err(format!("{key} should be 4 numbers from 0 to 255")),
+    }
+}
+
+fn byte(value: &Json) -> Option<u8> {
+    match *value {
+        Json::Number(value) if value.fract() == 0.0 && (0.0..=255.0).contains(&value) => {
+            Some(value as u8)
+        }
+        _ => None,
+    }
+}
+
+impl SpreadSheet {
+    /// Writes what is kept of the sheet at `sheet_path` next to it, see `SheetStyles`. With
+    /// nothing to keep there is no file, one left from before being removed.
+    pub(super) fn save_cell_styles(&self, sheet_path: &Path) -> io::Result<()> {
+        let styles = SheetStyles {
+            protected: self.protected,
+            column_formats: self.column_formats.clone().into_iter().collect(),
+            row_formats: self.row_formats.clone().into_iter().collect(),
+            cells: self
+                .cells
+                .iter()
+                .map(|(index, cell)| (*index, cell.style))
+                .filter(|(_, style)| !style.is_default())
+                .collect(),
+            validation_rules: self.validation_rules.clone(),
+        };
+        let path = cell_styles_path(sheet_path);
+        if styles.is_empty() {
+            return match fs::remove_file(path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            };
+        }
+        fs::write(path, styles.to_json())
+    }
+
+    /// Applies what is kept of the sheet at `sheet_path` next to it, if anything.
+    pub(super) fn load_cell_styles(&mut self, sheet_path: &Path) -> io::Result<()> {
+        let path = cell_styles_path(sheet_path);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        let invalid = |err: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {err}", path.display()),
+            )
+        };
+        let styles = SheetStyles::from_json(&text).map_err(invalid)?;
+        for (index, kept) in styles.cells {
+            self.update_style(index, |style| *style = kept);
+        }
+        for (col, format) in styles.column_formats {
+            self.set_column_format(col, format);
+        }
+        for (row, format) in styles.row_formats {
+            self.set_row_format(row, format);
+        }
+        for rule in styles.validation_rules {
+            self.set_validation(rule.range, rule.kind, rule.strict)
+                .map_err(|err| invalid(err.message))?;
+        }
+        self.set_protection(styles.protected);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_types::{cell_addr::cell, Borders};
+
+    #[test]
+    fn test_styles_json() {
+        let styles = [
+            (
+                cell("C1"),
+                CellStyle {
+                    fill: Some([255, 200, 0, 255]),
+                    bold: true,
+                    ..CellStyle::default()
+                },
+            ),
+            (
+                cell("A2"),
+                CellStyle {
+                    fill: Some([0, 0, 0, 128]),
+                    italic: true,
+                    ..CellStyle::default()
+                },
+            ),
+            (
+                cell("D2"),
+                CellStyle {
+                    bold: true,
+                    italic: true,
+                    ..CellStyle::default()
+                },
+            ),
+            (
+                cell("B1"),
+                CellStyle {
+                    locked: true,
+                    ..CellStyle::default()
+                },
+            ),
+            (
+                cell("B2"),
+                CellStyle {
+                    borders: Borders {
+                        top: Some(BorderStyle {
+                            width: 2,
+                            color: [0, 0, 0, 255],
+                        }),
+                        right: Some(BorderStyle {
+                            width: 1,
+                            color: [255, 0, 0, 255],
+                        }),
+                        ..Borders::default()
+                    },
+                    ..CellStyle::default()
+                },
+            ),
+            (
+                cell("E1"),
+                CellStyle {
+                    number_format: NumberFormat::Percent,
+                    ..CellStyle::default()
+                },
+            ),
+            (cell("F1"), CellStyle::default()),
+        ];
+        let styles = SheetStyles {
+            protected: true,
+            cells: styles.to_vec(),
+            ..SheetStyles::default()
+        };
+        let json = styles.to_json();
+        assert_eq!(
+            json,
+            "{\n  \"version\": 1,\n  \"protected\": true,\n  \"cells\": {\n    \
+             \"B1\": {\"locked\": true},\n    \
+             \"C1\": {\"fill\": [255, 200, 0, 255], \"bold\": true},\n    \
+             \"E1\": {\"number_format\": {\"kind\": \"percent\"}},\n    \
+             \"A2\": {\"fill\": [0, 0, 0, 128], \"italic\": true},\n    \
+             \"B2\": {\"borders\": {\"top\": {\"width\": 2, \"color\": [0, 0, 0, 255]}, \
+             \"right\": {\"width\": 1, \"color\": [255, 0, 0, 255]}}},\n    \
+             \"D2\": {\"bold\": true, \"italic\": true}\n  }\n}\n"
+        );
+        // Default styles are left out
+        let read = SheetStyles::from_json(&json).unwrap();
+        assert!(read.protected);
+        let mut kept = styles.cells[..6].to_vec();
+        kept.sort_by_key(|(index, _)| (index.y, index.x));
+        assert_eq!(read.cells, kept);
+
+        assert!(SheetStyles::default().is_empty());
+        assert_eq!(
+            SheetStyles::default().to_json(),
+            "{\n  \"version\": 1,\n  \"cells\": {}\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_formats_json() {
+        let formats = [
+            NumberFormat::Percent,
+            NumberFormat::Fixed { decimals: 2 },
+            NumberFormat::FixedPercent { decimals: 0 },
+            NumberFormat::Currency {
+                symbol: '"',
+                decimals: 15,
+            },
+        ];
+        let styles = SheetStyles {
+            column_formats: formats.into_iter().enumerate().collect(),
+            row_formats: BTreeMap::from([(9, NumberFormat::Fixed { decimals: 1 })]),
+            ..SheetStyles::default()
+        };
+        assert!(!styles.is_empty());
+        let json = styles.to_json();
+        assert_eq!(
+            json,
+            "{\n  \"version\": 1,\n  \"column_formats\": {\
+             \"A\": {\"kind\": \"percent\"}, \
+             \"B\": {\"kind\": \"fixed\", \"decimals\": 2}, \
+             \"C\": {\"kind\": \"fixed_percent\", \"decimals\": 0}, \
+             \"D\": {\"kind\": \"currency\", \"symbol\": \"\\\"\", \"decimals\": 15}},\n  \
+             \"row_formats\": {\"10\": {\"kind\": \"fixed\", \"decimals\": 1}},\n  \
+             \"cells\": {}\n}\n"
+        );
+        assert_eq!(SheetStyles::from_json(&json), Ok(styles));
+
+        let error = |format| {
+            let json = format!("{{\"version\": 1, \"row_formats\": {{\"1\": {format}}}}}");
+            SheetStyles::from_json(&json).unwrap_err()
+        };
+        assert_eq!(
+            error("{\"kind\": \"fixed\"}"),
+            "row_formats.1 should have decimals"
+        );
+        assert_eq!(
+            error("{\"kind\": \"fixed\", \"decimals\": 16}"),
+            "row_formats.1.decimals should be a whole number from 0 to 15"
+        );
+        assert_eq!(
+            error("{\"kind\": \"currency\", \"symbol\": \"EUR\", \"decimals\": 2}"),
+            "row_formats.1.symbol should be a single character"
+        );
+        assert_eq!(
+            error("{\"decimals\": 2}"),
+            "row_formats.1 should have a kind"
+        );
+        assert_eq!(
+            SheetStyles::from_json("{\"version\": 1, \"row_formats\": {\"0\": {}}}").unwrap_err(),
+            "row_formats: invalid line 0"
+        );
+    }
+
+    #[test]
+    fn test_validation_rules_json() {
+        let rule = |from, to, kind, strict| ValidationRule {
+            range: (cell(from), cell(to)),
+            kind,
+            strict,
+        };
+        let styles = SheetStyles {
+            validation_rules: vec![
+                rule("A1", "A9", ValidationKind::NumberBetween(0.5, 10.0), true),
+                rule(
+                    "B2",
+                    "C3",
+                    ValidationKind::OneOf(vec!["yes".to_string(), "say \"no\"".to_string()]),
+                    false,
+                ),
+                rule(
+                    "D1",
+                    "D1",
+                    ValidationKind::NumberBetween(0.0, f64::INFINITY),
+                    false,
+                ),
+                rule(
+                    "E1",
+                    "E5",
+                    ValidationKind::Formula("=E1 > D1".to_string()),
+                    false,
+                ),
+            ],
+            ..SheetStyles::default()
+        };
+        assert!(!styles.is_empty());
+        let json = styles.to_json();
+        assert_eq!(
+            json,
+            "{\n  \"version\": 1,\n  \"validation_rules\": [\n    \
+             {\"range\": \"A1:A9\", \"kind\": \"number_between\", \"min\": 0.5, \"max\": 10, \
+             \"strict\": true},\n    \
+             {\"range\": \"B2:C3\", \"kind\": \"one_of\", \
+             \"options\": [\"yes\", \"say \\\"no\\\"\"], \"strict\": false},\n    \
+             {\"range\": \"D1:D1\", \"kind\": \"number_between\", \"min\": 0, \"max\": null, \
+             \"strict\": false},\n    \
+             {\"range\": \"E1:E5\", \"kind\": \"formula\", \"formula\": \"=E1 > D1\", \
+             \"strict\": false}\n  ],\n  \"cells\": {}\n}\n"
+        );
+        assert_eq!(SheetStyles::from_json(&json), Ok(styles));
+
+        let error = |rule| {
+            let json = format!("{{\"version\": 1, \"validation_rules\": [{rule}]}}");
+            SheetStyles::from_json(&json).unwrap_err()
+        };
+        assert_eq!(
+            error("{\"range\": \"A1\", \"kind\": \"formula\", \"formula\": \"=1\"}"),
+            "validation_rules.0: invalid range A1"
+        );
+        assert_eq!(
+            error("{\"range\": \"A1:A2\", \"kind\": \"one_of\"}"),
+            "validation_rules.0 should have options"
+        );
+        assert_eq!(
+            error("{\"range\": \"A1:A2\", \"kind\": \"number_between\", \"min\": \"1\"}"),
+            "validation_rules.0.min should be a number or null"
+        );
+        // Kinds of a later version are skipped
+        assert_eq!(
+            SheetStyles::from_json(
+                "{\"version\": 2, \"validation_rules\": \
+                 [{\"range\": \"A1:A2\", \"kind\": \"date\"}]}"
+            ),
+            Ok(SheetStyles::default())
+        );
+    }
+
+    #[test]
+    fn test_invalid_styles() {
+        let error = |text| SheetStyles::from_json(text).unwrap_err();
+        assert_eq!(error("{\"cells\": {}}"), "Missing version");
+        assert_eq!(
+            error("{\"version\": 1, \"cells\": {\"a1\": {}}}"),
+            "cells: invalid cell name a1"
+        );
+        assert_eq!(
+            error("{\"version\": 1, \"cells\": {\"A1\": {\"fill\": [1, 2, 3]}}}"),
+            "A1.fill should be 4 numbers from 0 to 255"
+        );
+        assert_eq!(
+            error("{\"version\": 1, \"cells\": {\"A1\": {\"fill\": \"red\"}}}"),
+            "A1.fill should be an array"
+        );
+        assert_eq!(
+            error("{\"version\": 1, \"cells\": {\"A1\": {\"bold\": 1}}}"),
+            "A1.bold should be a boolean"
+        );
+        assert_eq!(
+            error(
+                "{\"version\": 1, \"cells\": {\"A1\": {\"borders\": {\"top\": {\"width\": 1}}}}}"
+            ),
+            "A1.borders.top should have a width and a color"
+        );
+        assert_eq!(
+            error(
+                "{\"version\": 1, \"cells\": {\"A1\": {\"borders\": {\"left\": \
+                 {\"width\": -1, \"color\": [0, 0, 0, 255]}}}}}"
+            ),
+            "A1.borders.left.width should be a whole number from 0 to 255"
+        );
+        assert_eq!(
+            error("{\"version\": 1, \"protected\": \"yes\", \"cells\": {}}"),
+            "protected should be a boolean"
+        );
+        // Keys of a later version are skipped
+        assert_eq!(
+            SheetStyles::from_json(
+                "{\"version\": 2, \"borders\": [], \"cells\": {\"A1\": {\"x\": 1}}}"
+            ),
+            Ok(SheetStyles {
+                cells: vec![(Index { x: 0, y: 0 }, CellStyle::default())],
+                ..SheetStyles::default()
+            })
+        );
+    }
+}
@@ -0,0 +1,360 @@
+use super::{export, SpreadSheet};
+use crate::common_types::Index;
+
+/// The characters pasted text may separate its fields by, most likely first.
+const DELIMITERS: [char; 3] = ['\t', ',', ';'];
+
+/// What `selection_to_tsv` writes for each cell.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CopyMode {
+    /// The computed value, errors written as they are displayed.
+    Values,
+    /// The raw content, formulas included as they are typed.
+    Formulas,
+}
+
+/// Writes the rectangle spanned by the corners of `rect` as text other programs read as a
+/// table: fields separated by tabs and a line per row. Empty cells give empty fields.
+pub fn selection_to_tsv(sheet: &SpreadSheet, rect: (Index, Index), mode: CopyMode) -> String {
+    fields(sheet, rect, mode)
+        .into_iter()
+        .map(|row| row.join("\t"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Writes the computed values of the rectangle spanned by the corners of `rect` as comma
+/// separated values, for programs that do not read tab separated ones. Fields holding commas,
+/// quotes or line breaks are quoted.
+pub fn selection_to_csv(sheet: &SpreadSheet, rect: (Index, Index)) -> String {
+    fields(sheet, rect, CopyMode::Values)
+        .into_iter()
+        .map(|row| {
+            row.iter()
+                .map(|field| export::csv_field(field))
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Like `selection_to_tsv`, for cells laid out in `grid` a row at a time, which need not be
+/// next to each other on the sheet.
+pub fn cells_to_tsv(sheet: &SpreadSheet, grid: &[Vec<Index>], mode: CopyMode) -> String {
+    grid.iter()
+        .map(|row| {
+            row.iter()
+                .map(|&index| field(sheet, index, mode))
+                .collect::<Vec<_>>()
+                .join("\t")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns the cells of the rectangle spanned by the corners of `rect` a row at a time, for
+/// `cells_to_tsv`, leaving out the rows and columns `hidden_row` and `hidden_column` tell are
+/// hidden. Copying the rectangle as it is copies hidden cells along with the others, as
+/// ranges read them.
+pub fn shown_cells(
+    rect: (Index, Index),
+    hidden_row: impl Fn(usize) -> bool,
+    hidden_column: impl Fn(usize) -> bool,
+) -> Vec<Vec<Index>> {
+    let (from, to) = rect;
+    let columns: Vec<usize> = (from.x.min(to.x)..=from.x.max(to.x))
+        .filter(|&x| !hidden_column(x))
+        .collect();
+    (from.y.min(to.y)..=from.y.max(to.y))
+        .filter(|&y| !hidden_row(y))
+        .map(|y| columns.iter().map(|&x| Index { x, y }).collect())
+        .collect()
+}
+
+fn fields(sheet: &SpreadSheet, rect: (Index, Index), mode: CopyMode) -> Vec<Vec<String>> {
+    let (from, to) = rect;
+    (from.y.min(to.y)..=from.y.max(to.y))
+        .map(|y| {
+            (from.x.min(to.x)..=from.x.max(to.x))
+                .map(|x| field(sheet, Index { x, y }, mode))
+                .collect()
+        })
+        .collect()
+}
+
+fn field(sheet: &SpreadSheet, index: Index, mode: CopyMode) -> String {
+    match mode {
+        CopyMode::Values => match sheet.get_computed(index) {
+            Some(Ok(value)) => value.to_string(),
+            Some(Err(err)) => err.to_string(),
+            None => String::new(),
+        },
+        CopyMode::Formulas => sheet.get_raw(&index).unwrap_or_default().to_string(),
+    }
+}
+
+/// Returns true when `text` holds a block of cells, with tabs between fields or more than one
+/// line, rather than the content of a single cell.
+pub fn is_block(text: &str) -> bool {
+    let text = text.trim_end_matches(['\r', '\n']);
+    text.contains('\t') || text.contains('\n')
+}
+
+/// Splits text copied from a table into rows of fields: a line per row and fields separated
+/// by tabs, the way spreadsheets and web pages put tables on the clipboard. Fields are
+/// trimmed and lose their surrounding quotes.
+pub fn parse_tsv(text: &str) -> Vec<Vec<String>> {
+    let text = text.trim_end_matches(['\r', '\n']);
+    if text.is_empty() {
+        return Vec::new();
+    }
+
+    text.split('\n')
+        .map(|line| {
+            line.strip_suffix('\r')
+                .unwrap_or(line)
+                .split('\t')
+                .map(unquote)
+                .collect()
+        })
+        .collect()
+}
+
+/// Returns the character the fields of `text` are most likely separated by: the first of tab,
+/// comma and semicolon splitting every line into the same number of fields, more than one.
+/// Without any, the one splitting the most lines, and a tab when none splits any.
+pub fn sniff_delimiter(text: &str) -> char {
+    let widths = |delimiter| -> Vec<usize> {
+        parse_delimited(text, delimiter)
+            .iter()
+            .map(Vec::len)
+            .collect()
+    };
+    let consistent = DELIMITERS.into_iter().find(|&delimiter| {
+        let widths = widths(delimiter);
+        widths
+            .first()
+            .is_some_and(|&first| first > 1 && widths.iter().all(|&width| width == first))
+    });
+    // Ties go to the delimiter listed first
+    consistent
+        .or_else(|| {
+            DELIMITERS
+                .into_iter()
+                .rev()
+                .map(|delimiter| {
+                    let split = widths(delimiter).iter().filter(|&&width| width > 1).count();
+                    (delimiter, split)
+                })
+                .filter(|&(_, split)| split > 0)
+                .max_by_key(|&(_, split)| split)
+                .map(|(delimiter, _)| delimiter)
+        })
+        .unwrap_or('\t')
+}
+
+/// Splits `text` into rows of fields separated by `delimiter`, a line per row. Fields in
+/// double quotes may hold the delimiter, line breaks and quotes written twice. Fields
+/// outside of quotes are trimmed.
+pub fn parse_delimited(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let text = text.trim_end_matches(['\r', '\n']);
+    if text.is_empty() {
+        return Vec::new();
+    }
+
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    // Whether the field started with a quote, and whether it is still open
+    let (mut quoted, mut in_quotes) = (false, false);
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => field.push(chars.next().unwrap()),
+                '"' => in_quotes = false,
+                c => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                (quoted, in_quotes) = (true, true);
+            }
+            c if c == delimiter || c == '\n' => {
+                row.push(finish_field(&mut field, quoted));
+                quoted = false;
+                if c == '\n' {
+                    rows.push(std::mem::take(&mut row));
+                }
+            }
+            '\r' if chars.peek() == Some(&'\n') => {}
+            c => field.push(c),
+        }
+    }
+    row.push(finish_field(&mut field, quoted));
+    rows.push(row);
+    rows
+}
+
+/// Splits pasted text into rows of fields, separated by the delimiter `sniff_delimiter`
+/// finds. Tab separated text is read by `parse_tsv`.
+pub fn parse_pasted(text: &str) -> Vec<Vec<String>> {
+    match sniff_delimiter(text) {
+        '\t' => parse_tsv(text),
+        delimiter => parse_delimited(text, delimiter),
+    }
+}
+
+fn finish_field(field: &mut String, quoted: bool) -> String {
+    let field = std::mem::take(field);
+    match quoted {
+        true => field,
+        false => field.trim().to_string(),
+    }
+}
+
+fn unquote(field: &str) -> String {
+    let field = field.trim();
+    match field
+        .strip_prefix('"')
+        .and_then(|field| field.strip_suffix('"'))
+    {
+        Some(inner) => inner.replace("\"\"", "\""),
+        None => field.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_block() {
+        assert!(is_block("1\t2"));
+        assert!(is_block("1\r\n2\r\n"));
+        assert!(!is_block("=A1 + 1"));
+        // A single value copied with its line ending
+        assert!(!is_block("hello\r\n"));
+    }
+
+    #[test]
+    fn test_parse_tsv() {
+        assert_eq!(
+            parse_tsv("1\t2\t=A1+B1\r\nx\t\ty\r\n"),
+            vec![vec!["1", "2", "=A1+B1"], vec!["x", "", "y"]]
+        );
+        // Rows may have any number of fields
+        assert_eq!(parse_tsv("a\nb\tc"), vec![vec!["a"], vec!["b", "c"]]);
+        assert!(parse_tsv("\n").is_empty());
+    }
+
+    #[test]
+    fn test_selection_to_tsv() {
+        let mut sheet = SpreadSheet::default();
+        sheet
+            .add_cell_and_compute(Index { x: 0, y: 0 }, "2".to_string())
+            .unwrap();
+        sheet
+            .add_cell_and_compute(Index { x: 1, y: 0 }, "=A1 * 3".to_string())
+            .unwrap();
+        sheet
+            .add_cell_and_compute(Index { x: 1, y: 1 }, "=B2".to_string())
+            .unwrap();
+        sheet
+            .add_cell_and_compute(Index { x: 2, y: 1 }, "text".to_string())
+            .unwrap();
+
+        let rect = (Index { x: 2, y: 1 }, Index { x: 0, y: 0 });
+        assert_eq!(
+            selection_to_tsv(&sheet, rect, CopyMode::Values),
+            "2\t6\t\n\t#CYCLE!\ttext"
+        );
+        assert_eq!(
+            selection_to_tsv(&sheet, rect, CopyMode::Formulas),
+            "2\t=A1 * 3\t\n\t=B2\ttext"
+        );
+
+        // Formulas copied out paste back as they were
+        let copied = selection_to_tsv(&sheet, rect, CopyMode::Formulas);
+        assert_eq!(
+            parse_tsv(&copied),
+            vec![vec!["2", "=A1 * 3", ""], vec!["", "=B2", "text"]]
+        );
+
+        // Cells apart on the sheet are written next to each other
+        let grid = [
+            vec![Index { x: 0, y: 0 }, Index { x: 2, y: 0 }],
+            vec![Index { x: 0, y: 1 }, Index { x: 2, y: 1 }],
+        ];
+        assert_eq!(cells_to_tsv(&sheet, &grid, CopyMode::Values), "2\t\n\ttext");
+
+        // Hidden cells are copied unless left out
+        sheet.set_column_hidden(1, true);
+        assert_eq!(
+            selection_to_tsv(&sheet, rect, CopyMode::Values),
+            "2\t6\t\n\t#CYCLE!\ttext"
+        );
+        let shown = shown_cells(rect, |row| row == 0, |col| sheet.is_column_hidden(col));
+        assert_eq!(shown, [vec![Index { x: 0, y: 1 }, Index { x: 2, y: 1 }]]);
+        assert_eq!(cells_to_tsv(&sheet, &shown, CopyMode::Values), "\ttext");
+    }
+
+    #[test]
+    fn test_sniff_delimiter() {
+        assert_eq!(sniff_delimiter("a,b\tc\nd,e\tf"), '\t');
+        assert_eq!(sniff_delimiter("name,price\napple,\"1,5\"\npear,2\n"), ',');
+        assert_eq!(sniff_delimiter("a;b,c\nd;e\nf;g,h,i"), ';');
+        // Without a consistent one, the one splitting most lines
+        assert_eq!(sniff_delimiter("a,b\nc,d,e\nf;g"), ',');
+        assert_eq!(sniff_delimiter("a\nb"), '\t');
+        assert_eq!(sniff_delimiter(""), '\t');
+    }
+
+    #[test]
+    fn test_parse_delimited() {
+        assert_eq!(
+            parse_delimited("a, b ,c\r\n\"x, \"\"y\"\"\",,\"two\nlines\"\r\n", ','),
+            vec![vec!["a", "b", "c"], vec!["x, \"y\"", "", "two\nlines"]]
+        );
+        assert!(parse_delimited("\r\n", ',').is_empty());
+        assert_eq!(
+            parse_pasted("1;=A1 * 2\n3;4"),
+            vec![vec!["1", "=A1 * 2"], vec!["3", "4"]]
+        );
+        assert_eq!(parse_pasted("1\t\"a\"\n2"), vec![vec!["1", "a"], vec!["2"]]);
+    }
+
+    #[test]
+    fn test_selection_to_csv() {
+        let mut sheet = SpreadSheet::default();
+        sheet
+            .add_cell_and_compute(Index { x: 0, y: 0 }, "1".to_string())
+            .unwrap();
+        sheet
+            .add_cell_and_compute(Index { x: 1, y: 0 }, "=concat(A1, \", x\")".to_string())
+            .unwrap();
+        sheet
+            .add_cell_and_compute(Index { x: 1, y: 1 }, "say \"hi\"".to_string())
+            .unwrap();
+
+        let rect = (Index { x: 0, y: 0 }, Index { x: 1, y: 1 });
+        let csv = selection_to_csv(&sheet, rect);
+        assert_eq!(csv, "1,\"1, x\"\n,\"say \"\"hi\"\"\"");
+        // Pasted back the same values
+        assert_eq!(
+            parse_pasted(&csv),
+            vec![vec!["1", "1, x"], vec!["", "say \"hi\""]]
+        );
+    }
+
+    #[test]
+    fn test_parse_tsv_quotes() {
+        assert_eq!(
+            parse_tsv("\"a, b\"\t\"say \"\"hi\"\"\"\t\""),
+            vec![vec!["a, b", "say \"hi\"", "\""]]
+        );
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::mem;
+
+use super::evaluation::Evaluation;
+use super::parser::dependancy_graph::{DependancyGraph, TopologicalSort};
+use super::SpreadSheet;
+use crate::common_types::{index_to_name, ComputeError, Index, ParsedCell, Value};
+
+type Computed = Option<Result<Value, ComputeError>>;
+
+/// A cell whose value, as the sheet computed it along with the edits, is not the one the
+/// whole sheet computed from scratch gives it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    pub index: Index,
+    pub raw: String,
+    /// The formula of the cell as parsed, in canonical form.
+    pub formula: Option<String>,
+    pub computed: Computed,
+    pub expected: Computed,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({:?}", index_to_name(self.index), self.raw)?;
+        if let Some(formula) = &self.formula {
+            write!(f, ", parsed as ={formula}")?;
+        }
+        write!(
+            f,
+            ") holds {:?} where computing the sheet from scratch gives {:?}",
+            self.computed, self.expected
+        )
+    }
+}
+
+impl SpreadSheet {
+    /// Sets whether each compute is checked against the whole sheet computed from scratch,
+    /// panicking at the first cell left with another value. It is only checked in tests and
+    /// with the `verify` feature, where checking is on unless turned off.
+    pub fn set_verify_compute(&mut self, verify: bool) {
+        self.unverified = !verify;
+    }
+
+    /// Returns the first cell in reading order whose value is not the one computing the
+    /// whole sheet from scratch gives it, if any. The sheet is left untouched, cells waiting
+    /// to be computed included.
+    pub fn first_divergence(&self) -> Option<Divergence> {
+        let expected = self.compute_from_scratch();
+        let mut indices: Vec<&Index> = self.cells.keys().collect();
+        indices.sort_by_key(|index| (index.y, index.x));
+        for index in indices {
+            let cell = &self.cells[index];
+            let expected = &expected[index];
+            if same_computed(&cell.computed_value, expected) {
+                continue;
+            }
+            let formula = match &cell.parsed_representation {
+                Some(Ok(ParsedCell::Expr(expr))) => Some(expr.ast.to_formula_string()),
+                _ => None,
+            };
+            return Some(Divergence {
+                index: *index,
+                raw: cell.raw_representation.clone(),
+                formula,
+                computed: cell.computed_value.clone(),
+                expected: expected.clone(),
+            });
+        }
+        None
+    }
+
+    /// Panics at the first cell whose value diverges from the one computed from scratch,
+    /// once every cell with content is computed and unless checking is turned off.
+    pub(super) fn verify_compute(&self) {
+        if !cfg!(any(test, feature = "verify"))
+            || self.unverified
+            || self
+                .cells
+                .values()
+                .any(|cell| cell.needs_compute && !cell.raw_representation.is_empty())
+        {
+            return;
+        }
+        if let Some(divergence) = self.first_divergence() {
+            panic!("Inconsistent compute: {divergence}");
+        }
+    }
+
+    /// Computes every cell in an order worked out again from their parsed contents, in the
+    /// way `recalculate` orders the cells it computes, leaving the cells as they are.
+    fn compute_from_scratch(&self) -> HashMap<Index, Computed> {
+        let mut graph = DependancyGraph::default();
+        let mut volatile: HashSet<Index> = HashSet::new();
+        for (index, cell) in &self.cells {
+            if let Some(Ok(ParsedCell::Expr(expr))) = &cell.parsed_representation {
+                graph.add_node(*index, &expr.dependencies);
+                if expr.volatile {
+                    volatile.insert(*index);
+                }
+            }
+        }
+        let TopologicalSort { sorted, cycles } = graph.topological_sort();
+
+        // Cells neither reading nor read by others are left out of the graph, and come first
+        let graphed: HashSet<Index> = sorted.iter().chain(&cycles).copied().collect();
+        let mut order: Vec<Index> = self
+            .cells
+            .keys()
+            .filter(|index| !graphed.contains(index))
+            .copied()
+            .collect();
+        order.extend(
+            sorted
+                .into_iter()
+                .filter(|index| self.cells.contains_key(index)),
+        );
+        let mut last = volatile.clone();
+        for &index in &volatile {
+            last.extend(graph.get_all_dependants(index));
+        }
+        order.sort_by_key(|index| last.contains(index));
+
+        let mut values: HashMap<Index, Computed> = HashMap::new();
+        for index in cycles {
+            if let Some(cell) = self.cells.get(&index) {
+                let cycle = Err(ComputeError::Cycle);
+                values.insert(index, cell.parsed_representation.as_ref().map(|_| cycle));
+            }
+        }
+        // The cells computed so far read as computed, the others as empty
+        for index in order {
+            let known = |index: Index| match values.get(&index) {
+                Some(computed) => (computed.clone(), false),
+                None => (None, self.cells.contains_key(&index)),
+            };
+            let computed = Evaluation::evaluate(self, index, &self.cells[&index], &known);
+            values.insert(index, computed);
+        }
+        values
+    }
+}
+
+/// Returns true when two values are the same, errors being the same when they are of the
+/// same kind whatever their messages say.
+fn same_computed(computed: &Computed, expected: &Computed) -> bool {
+    match (computed, expected) {
+        (Some(Err(a)), Some(Err(b))) => mem::discriminant(a) == mem::discriminant(b),
+        _ => computed == expected,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sheet(cells: &[(Index, &str)]) -> SpreadSheet {
+        let mut sheet = SpreadSheet::default();
+        for (index, raw) in cells {
+            sheet.set_cell(*index, raw.to_string()).unwrap();
+        }
+        sheet
+    }
+
+    #[test]
+    fn test_first_divergence() {
+        let a1 = Index { x: 0, y: 0 };
+        let a2 = Index { x: 0, y: 1 };
+        let a3 = Index { x: 0, y: 2 };
+        let mut sheet = sheet(&[(a1, "2"), (a2, "=A1 * 3"), (a3, "=sum(A1:A2) + 1")]);
+        assert_eq!(sheet.first_divergence(), None);
+
+        // A value the dirty flags missed
+        sheet.cells.get_mut(&a3).unwrap().computed_value = Some(Ok(Value::Number(1.0)));
+        let divergence = sheet.first_divergence().unwrap();
+        assert_eq!(divergence.index, a3);
+        assert_eq!(divergence.formula, Some("sum(A1:A2) + 1".to_string()));
+        assert_eq!(divergence.expected, Some(Ok(Value::Number(9.0))));
+        assert!(divergence
+            .to_string()
+            .starts_with("A3 (\"=sum(A1:A2) + 1\""));
+
+        // Checking leaves the cells as they are
+        assert_eq!(sheet.get_computed(a3), Some(Ok(Value::Number(1.0))));
+    }
+
+    #[test]
+    fn test_equal_errors() {
+        let a1 = Index { x: 0, y: 0 };
+        let a2 = Index { x: 0, y: 1 };
+        let b1 = Index { x: 1, y: 0 };
+        let mut sheet = sheet(&[(a1, "=B1"), (b1, "=A1"), (a2, "=1 / 0")]);
+        assert_eq!(sheet.get_computed(a1), Some(Err(ComputeError::Cycle)));
+        assert_eq!(sheet.first_divergence(), None);
+
+        let cell = sheet.cells.get_mut(&a2).unwrap();
+        cell.computed_value = Some(Err(ComputeError::TypeError("other".to_string())));
+        assert_eq!(sheet.first_divergence().unwrap().index, a2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Inconsistent compute: A2")]
+    fn test_verify_compute() {
+        let a1 = Index { x: 0, y: 0 };
+        let a2 = Index { x: 0, y: 1 };
+        let mut sheet = sheet(&[(a1, "1"), (a2, "=A1 + 1")]);
+        sheet.cells.get_mut(&a2).unwrap().computed_value = None;
+        sheet
+            .set_cell(Index { x: 5, y: 5 }, "3".to_string())
+            .unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Inconsistent compute: A2")]
+    fn test_verify_compute_past_styles() {
+        let a1 = Index { x: 0, y: 0 };
+        let a2 = Index { x: 0, y: 1 };
+        let b1 = Index { x: 1, y: 0 };
+        let mut sheet = sheet(&[(a1, "1"), (a2, "=A1 + 1")]);
+        // A cell without content waiting to compute is no reason to skip checking
+        sheet.set_fill(b1, Some([255, 0, 0, 255]));
+        sheet.cells.get_mut(&b1).unwrap().needs_compute = true;
+        sheet.cells.get_mut(&a2).unwrap().computed_value = None;
+        sheet
+            .set_cell(Index { x: 5, y: 5 }, "3".to_string())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_unverified() {
+        let a1 = Index { x: 0, y: 0 };
+        let a2 = Index { x: 0, y: 1 };
+        let mut sheet = sheet(&[(a1, "1"), (a2, "=A1 + 1")]);
+        sheet.set_verify_compute(false);
+        sheet.cells.get_mut(&a2).unwrap().computed_value = None;
+        sheet
+            .set_cell(Index { x: 5, y: 5 }, "3".to_string())
+            .unwrap();
+        assert!(sheet.first_divergence().is_some());
+    }
+}
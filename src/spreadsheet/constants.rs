@@ -0,0 +1,174 @@
+use super::SpreadSheet;
+use crate::common_types::{Index, ParseError, ParsedCell, Value};
+
+impl SpreadSheet {
+    /// Defines `name` to stand for `value` in formulas, like `vat` in `=B2 * vat`, replacing
+    /// the value it stood for. Names start with a lowercase letter followed by letters and
+    /// underscores. A name defined like a function is still called as one where arguments
+    /// follow it.
+    pub fn define_constant(&mut self, name: &str, value: Value) -> Result<(), ParseError> {
+        let mut chars = name.chars();
+        let valid = chars.next().is_some_and(|c| c.is_ascii_lowercase())
+            && chars.all(|c| c.is_ascii_alphabetic() || c == '_');
+        if !valid {
+            return Err(ParseError::new(format!("Invalid constant name: {name}")));
+        }
+
+        if self.constants.get(name) != Some(&value) {
+            self.constants.insert(name.to_string(), value);
+            self.constant_changed(name);
+        }
+        Ok(())
+    }
+
+    /// Forgets `name`, the formulas reading it failing with a reference error until it is
+    /// defined again.
+    pub fn remove_constant(&mut self, name: &str) {
+        if self.constants.remove(name).is_some() {
+            self.constant_changed(name);
+        }
+    }
+
+    /// Returns the value `name` stands for, if it is defined.
+    pub fn constant(&self, name: &str) -> Option<&Value> {
+        self.constants.get(name)
+    }
+
+    /// Computes again the formulas reading `name`, then what depends on those whose value
+    /// changed. Names are not part of the dependency graph, so the formulas reading one are
+    /// found by going over the cells, which only happens when a constant changes.
+    fn constant_changed(&mut self, name: &str) {
+        self.condition_results.get_mut().clear();
+        self.validation_results.get_mut().clear();
+
+        let readers: Vec<Index> = self
+            .cells
+            .iter()
+            .filter(|(_, cell)| {
+                matches!(
+                    &cell.parsed_representation,
+                    Some(Ok(ParsedCell::Expr(expr))) if expr.names.iter().any(|read| &**read == name)
+                )
+            })
+            .map(|(index, _)| *index)
+            .collect();
+
+        let mut changed = Vec::new();
+        for index in readers {
+            let computed = self.compute_cell(index, &self.cells[&index]);
+            let cell = self.cells.get_mut(&index).expect("should not fail");
+            if cell.computed_value != computed {
+                changed.push(index);
+            }
+            cell.computed_value = computed;
+        }
+        if self.mark_dependants(&changed) {
+            self.compute_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_types::cell_addr::cell;
+    use crate::common_types::ComputeError;
+
+    fn number(sheet: &SpreadSheet, name: &str) -> Option<f64> {
+        match sheet.get_computed(cell(name))? {
+            Ok(Value::Number(number)) => Some(number),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_define_constant() {
+        let mut sheet = SpreadSheet::default();
+        sheet.define_constant("vat", Value::Number(0.25)).unwrap();
+        sheet.set_cell(cell("B2"), "200".to_string()).unwrap();
+        sheet.set_cell(cell("C2"), "=B2 * vat".to_string()).unwrap();
+        sheet
+            .set_cell(cell("D2"), "=max(vat, 1) + sum(B2:C2)".to_string())
+            .unwrap();
+        assert_eq!(number(&sheet, "C2"), Some(50.0));
+        assert_eq!(number(&sheet, "D2"), Some(251.0));
+        assert_eq!(sheet.constant("vat"), Some(&Value::Number(0.25)));
+        assert_eq!(
+            sheet.explain(cell("C2")).unwrap().to_string(),
+            "B2=200 * vat=0.25 -> 50"
+        );
+
+        // Names nothing stands for are reference errors
+        sheet
+            .set_cell(cell("E2"), "=B2 * rate".to_string())
+            .unwrap();
+        assert!(
+            matches!(sheet.get_error(cell("E2")), Some(ComputeError::UnfindableReference(message)) if message.contains("rate"))
+        );
+        for name in ["", "Vat", "vat1", "_vat", "v at"] {
+            assert!(
+                sheet.define_constant(name, Value::Number(1.0)).is_err(),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_redefine_constant() {
+        let mut sheet = SpreadSheet::default();
+        sheet.define_constant("vat", Value::Number(0.1)).unwrap();
+        sheet.define_constant("rate", Value::Number(2.0)).unwrap();
+        for (name, raw) in [
+            ("A1", "100"),
+            ("B1", "=A1 * vat"),
+            ("C1", "=B1 + A1 * vat"),
+            ("D1", "=sum(B1:C1)"),
+            ("E1", "=A1 * rate"),
+        ] {
+            sheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        assert_eq!(number(&sheet, "D1"), Some(30.0));
+
+        // Readers of the name and everything depending on them follow
+        sheet.define_constant("vat", Value::Number(0.5)).unwrap();
+        assert_eq!(number(&sheet, "B1"), Some(50.0));
+        assert_eq!(number(&sheet, "C1"), Some(100.0));
+        assert_eq!(number(&sheet, "D1"), Some(150.0));
+        assert_eq!(number(&sheet, "E1"), Some(200.0));
+
+        // Only those, while another value of the same kind is fine too
+        sheet.computes.set(0);
+        sheet.define_constant("rate", Value::Number(3.0)).unwrap();
+        assert_eq!(sheet.computes.get(), 1);
+        assert_eq!(number(&sheet, "E1"), Some(300.0));
+        sheet
+            .define_constant("rate", Value::Text("x".to_string()))
+            .unwrap();
+        assert!(matches!(
+            sheet.get_error(cell("E1")),
+            Some(ComputeError::TypeError(_))
+        ));
+    }
+
+    #[test]
+    fn test_remove_constant() {
+        let mut sheet = SpreadSheet::default();
+        sheet.define_constant("vat", Value::Number(0.5)).unwrap();
+        sheet.set_cell(cell("A1"), "=10 * vat".to_string()).unwrap();
+        sheet.set_cell(cell("A2"), "=A1 + 1".to_string()).unwrap();
+        assert_eq!(number(&sheet, "A2"), Some(6.0));
+
+        sheet.remove_constant("vat");
+        assert_eq!(sheet.constant("vat"), None);
+        for name in ["A1", "A2"] {
+            let error = sheet.get_error(cell(name)).unwrap();
+            assert!(
+                matches!(&error, ComputeError::UnfindableReference(message) if message.contains("vat")),
+                "{name}: {error:?}"
+            );
+        }
+
+        sheet.define_constant("vat", Value::Number(1.0)).unwrap();
+        assert_eq!(number(&sheet, "A2"), Some(11.0));
+    }
+}
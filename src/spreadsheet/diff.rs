@@ -0,0 +1,156 @@
+use std::fmt;
+
+use super::SpreadSheet;
+use crate::common_types::{index_to_name, ComputeError, Index, Value};
+
+/// The content of a cell on one side of a diff.
+#[derive(Debug, Clone)]
+pub struct CellVersion {
+    pub raw: String,
+    pub computed: Option<Result<Value, ComputeError>>,
+}
+
+/// A cell that differs between two sheets, in its raw content or its value. `old` is empty
+/// for a cell that was added and `new` for one that was removed.
+#[derive(Debug, Clone)]
+pub struct CellDiff {
+    pub index: Index,
+    pub old: Option<CellVersion>,
+    pub new: Option<CellVersion>,
+}
+
+impl fmt::Display for CellVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.raw)?;
+        match &self.computed {
+            Some(Ok(value)) => write!(f, " ({value})"),
+            Some(Err(err)) => write!(f, " ({err})"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for CellDiff {
+    /// Writes the diff like `B3: "=A1*2" (10) -> "=A1*3" (15)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", index_to_name(self.index))?;
+        match (&self.old, &self.new) {
+            (Some(old), Some(new)) => write!(f, "{old} -> {new}"),
+            (None, Some(new)) => write!(f, "added {new}"),
+            (Some(old), None) => write!(f, "removed {old}"),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// Returns the cells differing between `old` and `new` in reading order. A cell differs
+/// when its raw content does, or its value because a cell it depends on changed. Styles are
+/// not compared.
+pub fn diff(old: &SpreadSheet, new: &SpreadSheet) -> Vec<CellDiff> {
+    let mut indices: Vec<Index> = old.cells.keys().chain(new.cells.keys()).copied().collect();
+    indices.sort_by_key(|index| (index.y, index.x));
+    indices.dedup();
+
+    indices
+        .into_iter()
+        .filter_map(|index| {
+            let (old, new) = (version(old, index), version(new, index));
+            let same = match (&old, &new) {
+                (Some(old), Some(new)) => {
+                    old.raw == new.raw && same_computed(&old.computed, &new.computed)
+                }
+                (None, None) => true,
+                _ => false,
+            };
+            (!same).then_some(CellDiff { index, old, new })
+        })
+        .collect()
+}
+
+fn version(sheet: &SpreadSheet, index: Index) -> Option<CellVersion> {
+    let raw = sheet.get_raw(&index).filter(|raw| !raw.is_empty())?;
+    Some(CellVersion {
+        raw: raw.to_string(),
+        computed: sheet.get_computed(index),
+    })
+}
+
+fn same_computed(
+    a: &Option<Result<Value, ComputeError>>,
+    b: &Option<Result<Value, ComputeError>>,
+) -> bool {
+    match (a, b) {
+        (Some(Ok(a)), Some(Ok(b))) => a == b,
+        (Some(Err(a)), Some(Err(b))) => a.to_string() == b.to_string(),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sheet(cells: &[(Index, &str)]) -> SpreadSheet {
+        let mut sheet = SpreadSheet::default();
+        for (index, raw) in cells {
+            sheet.add_cell_and_compute(*index, raw.to_string()).unwrap();
+        }
+        sheet
+    }
+
+    #[test]
+    fn test_diff() {
+        let (a1, b1, a2, b3) = (
+            Index { x: 0, y: 0 },
+            Index { x: 1, y: 0 },
+            Index { x: 0, y: 1 },
+            Index { x: 1, y: 2 },
+        );
+        let old = sheet(&[(a1, "5"), (b1, "old"), (a2, "=A1 * 2"), (b3, "=A1*2")]);
+        let new = sheet(&[
+            (a1, "5"),
+            (a2, "=A1 * 2"),
+            (b3, "=A1*3"),
+            (b1, "=A1 + TRUE"),
+        ]);
+        assert!(diff(&old, &old).is_empty());
+
+        let lines: Vec<String> = diff(&old, &new).iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "B1: \"old\" (old) -> \"=A1 + TRUE\" (#VALUE!)",
+                "B3: \"=A1*2\" (10) -> \"=A1*3\" (15)",
+            ]
+        );
+
+        let removed = sheet(&[(a1, "5"), (a2, "=A1 * 2")]);
+        let lines: Vec<String> = diff(&old, &removed).iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            lines,
+            vec!["B1: removed \"old\" (old)", "B3: removed \"=A1*2\" (10)"]
+        );
+
+        let added = diff(&removed, &old);
+        assert_eq!(added.len(), 2);
+        assert!(added[0].old.is_none());
+        assert_eq!(added[1].to_string(), "B3: added \"=A1*2\" (10)");
+    }
+
+    #[test]
+    fn test_diff_value_only() {
+        let (a1, a2) = (Index { x: 0, y: 0 }, Index { x: 0, y: 1 });
+        let old = sheet(&[(a1, "5"), (a2, "=A1 * 2")]);
+        let mut new = sheet(&[(a1, "6"), (a2, "=A1 * 2")]);
+        // A style alone is no difference
+        new.set_fill(Index { x: 3, y: 3 }, Some([0, 0, 0, 255]));
+
+        let diffs = diff(&old, &new);
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(
+            diffs[1].to_string(),
+            "A2: \"=A1 * 2\" (10) -> \"=A1 * 2\" (12)"
+        );
+    }
+}
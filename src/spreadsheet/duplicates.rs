@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+use super::SpreadSheet;
+use crate::common_types::{Index, Value};
+
+/// What a value is compared by when looking for duplicates: numbers by their exact value,
+/// text as written or lowercased, and values of different types never alike.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Number(u64),
+    Text(String),
+    Bool(bool),
+}
+
+fn key(value: &Value, ignore_case: bool) -> Option<Key> {
+    match value {
+        // Zero is the same whatever its sign
+        Value::Number(number) => Some(Key::Number((number + 0.0).to_bits())),
+        Value::Text(text) | Value::Link { label: text, .. } => Some(Key::Text(match ignore_case {
+            true => text.to_lowercase(),
+            false => text.clone(),
+        })),
+        Value::Bool(value) => Some(Key::Bool(*value)),
+        // An array shows its top left value
+        Value::Array(rows) => key(rows.first()?.first()?, ignore_case),
+        // A chart is too much to compare by
+        Value::Sparkline(_) => None,
+    }
+}
+
+impl SpreadSheet {
+    /// Groups the cells in the rectangle spanned by the corners of `range` whose values are
+    /// the same, as shown: numbers equal exactly, and text equals text written the same, in
+    /// any case with `ignore_case`. Empty cells and errors are never duplicates. Returns the
+    /// groups of two cells or more, each from the top left row by row, ordered by their
+    /// first cell.
+    pub fn find_duplicates(&self, range: (Index, Index), ignore_case: bool) -> Vec<Vec<Index>> {
+        let (from, to) = range;
+        let top_left = Index {
+            x: from.x.min(to.x),
+            y: from.y.min(to.y),
+        };
+        let bottom_right = Index {
+            x: from.x.max(to.x),
+            y: from.y.max(to.y),
+        };
+
+        let mut groups: HashMap<Key, Vec<Index>> = HashMap::new();
+        for (index, cell) in self.cells_in_rect(top_left, bottom_right) {
+            let Some(Ok(value)) = &cell.computed_value else {
+                continue;
+            };
+            if let Some(key) = key(value, ignore_case) {
+                groups.entry(key).or_default().push(index);
+            }
+        }
+
+        let mut duplicates: Vec<Vec<Index>> = groups
+            .into_values()
+            .filter(|group| group.len() > 1)
+            .map(|mut group| {
+                group.sort_by_key(|index| (index.y, index.x));
+                group
+            })
+            .collect();
+        duplicates.sort_by_key(|group| (group[0].y, group[0].x));
+        duplicates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_types::{cell_addr::cell, index_to_name};
+
+    fn names(groups: Vec<Vec<Index>>) -> Vec<Vec<String>> {
+        groups
+            .into_iter()
+            .map(|group| group.into_iter().map(index_to_name).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let mut sheet = SpreadSheet::default();
+        let cells = [
+            ("A1", "1"),
+            ("B1", "=2 - 1"),
+            ("C1", "'1"),
+            ("A2", "Apple"),
+            ("B2", "apple"),
+            ("C2", "=1 / 0"),
+            ("A3", "=C2"),
+            ("B3", "=true"),
+            ("C3", "'TRUE"),
+            ("A4", "-0"),
+            ("B4", "0"),
+            ("C4", "Apple"),
+        ];
+        for (name, raw) in cells {
+            sheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        let range = (cell("C4"), cell("A1"));
+
+        // Numbers, text and booleans are never alike, nor are errors or empty cells
+        assert_eq!(
+            names(sheet.find_duplicates(range, false)),
+            [vec!["A1", "B1"], vec!["A2", "C4"], vec!["A4", "B4"]]
+        );
+        assert_eq!(
+            names(sheet.find_duplicates(range, true)),
+            [vec!["A1", "B1"], vec!["A2", "B2", "C4"], vec!["A4", "B4"]]
+        );
+
+        // Only cells inside the range count
+        let first_rows = (cell("A1"), cell("C2"));
+        assert_eq!(
+            names(sheet.find_duplicates(first_rows, false)),
+            [vec!["A1", "B1"]]
+        );
+        assert!(SpreadSheet::default()
+            .find_duplicates(first_rows, true)
+            .is_empty());
+    }
+}
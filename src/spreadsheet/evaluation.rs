@@ -0,0 +1,281 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use super::parser::ast_resolver::{InCell, VarContext};
+use super::{Cell, SpreadSheet};
+use crate::common_types::{ComputeError, Index, Value};
+
+type Computed = Option<Result<Value, ComputeError>>;
+
+/// How deep the cells reached by `indirect` or `offset` may nest by default, see
+/// `SpreadSheet::set_max_reference_depth`. Each level takes some kilobytes of stack in debug
+/// builds, which this keeps well within the 2 MiB of a spawned thread.
+pub const MAX_REFERENCE_DEPTH: usize = 100;
+
+/// The cells a compute of the cell at the bottom of `in_progress` reads. A cell read by
+/// `indirect` or `offset`, which the order of computing cannot know of, is computed on demand,
+/// along with the cells it reads that wait to be computed. Reading a cell whose compute is
+/// in progress is a cycle rather than endless recursion, and computes on demand nest no
+/// deeper than `max_depth`.
+pub(super) struct Evaluation<'a> {
+    sheet: &'a SpreadSheet,
+    /// Returns the value of the cell at an index as it is, and whether it waits to be
+    /// computed.
+    known: &'a dyn Fn(Index) -> (Computed, bool),
+    in_progress: RefCell<Vec<Index>>,
+    // The values computed on demand so far
+    computed: RefCell<HashMap<Index, Computed>>,
+    max_depth: usize,
+}
+
+impl<'a> Evaluation<'a> {
+    /// Computes `cell`, which is at `index`, reading the other cells as `known` tells.
+    pub fn evaluate(
+        sheet: &'a SpreadSheet,
+        index: Index,
+        cell: &Cell,
+        known: &'a dyn Fn(Index) -> (Computed, bool),
+    ) -> Computed {
+        let evaluation = Self {
+            sheet,
+            known,
+            in_progress: RefCell::new(Vec::new()),
+            computed: RefCell::new(HashMap::new()),
+            max_depth: sheet.max_reference_depth.unwrap_or(MAX_REFERENCE_DEPTH),
+        };
+        evaluation.compute(index, cell)
+    }
+
+    fn compute(&self, index: Index, cell: &Cell) -> Computed {
+        // The cell the evaluation is for is the first in progress, not one computed on demand
+        if self.in_progress.borrow().len() > self.max_depth {
+            return Some(Err(ComputeError::DepthExceeded(self.max_depth)));
+        }
+        self.in_progress.borrow_mut().push(index);
+        let computed = SpreadSheet::compute_parsed(
+            cell,
+            &InCell {
+                variables: self,
+                index,
+            },
+        );
+        self.in_progress.borrow_mut().pop();
+        computed
+    }
+
+    /// Reads the cell at `index`, computing it on demand when `on_demand` or when it waits
+    /// to be computed.
+    fn read(&self, index: Index, on_demand: bool) -> Computed {
+        if self.in_progress.borrow().contains(&index) {
+            return Some(Err(ComputeError::Cycle));
+        }
+        if let Some(computed) = self.computed.borrow().get(&index) {
+            return computed.clone();
+        }
+        let (value, waiting) = (self.known)(index);
+        if !(on_demand || waiting) {
+            return value;
+        }
+        let cell = self.sheet.cells.get(&index)?;
+        let computed = self.compute(index, cell);
+        self.computed.borrow_mut().insert(index, computed.clone());
+        computed
+    }
+}
+
+impl VarContext for Evaluation<'_> {
+    /// Reads a cell the formula references, which is computed before it unless the formula
+    /// is itself computed on demand.
+    fn get_variable(&self, index: Index) -> Computed {
+        // Not borrowed across reading, which may compute more cells
+        let depth = self.in_progress.borrow().len();
+        match depth {
+            1 => (self.known)(index).0,
+            _ => self.read(index, false),
+        }
+    }
+
+    /// Reads a cell `indirect` or `offset` reaches, computing it whatever the order of
+    /// computing so that how deep they nest does not depend on it.
+    fn get_dynamic_variable(&self, index: Index) -> Computed {
+        self.read(index, true)
+    }
+
+    fn get_constant(&self, name: &str) -> Option<Value> {
+        self.sheet.constants.get(name).cloned()
+    }
+}
+
+impl SpreadSheet {
+    /// Sets how deep the cells reached by `indirect` or `offset` may nest, each computed on
+    /// demand for the one reaching it. A chain of them reaching deeper gives
+    /// `ComputeError::DepthExceeded`, before running out of stack. See `MAX_REFERENCE_DEPTH`.
+    pub fn set_max_reference_depth(&mut self, depth: usize) {
+        self.max_reference_depth = Some(depth);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_types::cell_addr::cell;
+    use crate::spreadsheet::RecalcMode;
+
+    #[test]
+    fn test_indirect_cycle() {
+        let mut sheet = SpreadSheet::default();
+        sheet
+            .set_cell(cell("A1"), "=indirect(\"A1\")".to_string())
+            .unwrap();
+        assert_eq!(sheet.get_error(cell("A1")), Some(ComputeError::Cycle));
+
+        for (name, raw) in [
+            ("B1", "=indirect(\"C1\") + 1"),
+            ("C1", "=indirect(concat(\"D\", 1)) * 2"),
+            ("D1", "=offset(A1, 0, 1)"),
+            ("E1", "=C1"),
+        ] {
+            sheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        for name in ["B1", "C1", "D1", "E1"] {
+            assert_eq!(
+                sheet.get_error(cell(name)),
+                Some(ComputeError::Cycle),
+                "{name}"
+            );
+        }
+
+        // Breaking the cycle anywhere lets every cell compute
+        sheet.set_cell(cell("D1"), "5".to_string()).unwrap();
+        assert_eq!(
+            sheet.get_computed(cell("B1")),
+            Some(Ok(Value::Number(11.0)))
+        );
+        assert_eq!(
+            sheet.get_computed(cell("E1")),
+            Some(Ok(Value::Number(10.0)))
+        );
+    }
+
+    #[test]
+    fn test_indirect_into_static_cycle() {
+        // Whatever the order the cells are written in
+        let cycles = [
+            [("B1", "=C1"), ("C1", "=B1"), ("A1", "=indirect(\"B1\")")],
+            [("A1", "=indirect(\"B1\")"), ("B1", "=C1"), ("C1", "=B1")],
+            [("A1", "=offset(B1, 0, 0)"), ("C1", "=B1"), ("B1", "=C1")],
+        ];
+        for cells in cycles {
+            let mut sheet = SpreadSheet::default();
+            for (name, raw) in cells {
+                sheet.set_cell(cell(name), raw.to_string()).unwrap();
+            }
+            for name in ["A1", "B1", "C1"] {
+                assert_eq!(
+                    sheet.get_error(cell(name)),
+                    Some(ComputeError::Cycle),
+                    "{name} of {cells:?}"
+                );
+            }
+        }
+
+        // Reaching a cycle through a chain of plain references
+        let mut sheet = SpreadSheet::default();
+        for (name, raw) in [
+            ("A1", "=indirect(\"B1\")"),
+            ("B1", "=C1"),
+            ("C1", "=D1"),
+            ("D1", "=C1"),
+        ] {
+            sheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        assert_eq!(sheet.get_error(cell("A1")), Some(ComputeError::Cycle));
+        sheet.set_cell(cell("D1"), "4".to_string()).unwrap();
+        assert_eq!(sheet.get_computed(cell("A1")), Some(Ok(Value::Number(4.0))));
+    }
+
+    /// Returns a sheet where each cell from A1 down to the row `rows` adds 1 to the one below,
+    /// read by `indirect` so the order of computing cannot know of it. It is left to compute.
+    fn indirect_chain(rows: usize) -> SpreadSheet {
+        let mut sheet = SpreadSheet::default();
+        sheet.insert_uncomputed((1..=rows).map(|y| {
+            let raw = format!("=indirect(\"A{}\") + 1", y + 1);
+            (cell(&format!("A{y}")), Cell::from_raw(raw))
+        }));
+        sheet
+    }
+
+    #[test]
+    fn test_indirect_chain_computes_on_demand() {
+        let mut sheet = indirect_chain(50);
+        sheet.set_cell(cell("A51"), "0".to_string()).unwrap();
+        assert_eq!(
+            sheet.get_computed(cell("A1")),
+            Some(Ok(Value::Number(50.0)))
+        );
+        sheet.set_cell(cell("A51"), "100".to_string()).unwrap();
+        assert_eq!(
+            sheet.get_computed(cell("A1")),
+            Some(Ok(Value::Number(150.0)))
+        );
+    }
+
+    #[test]
+    fn test_deep_chain_hits_the_limit() {
+        let mut sheet = indirect_chain(10_000);
+        sheet.set_max_reference_depth(10);
+        sheet.set_cell(cell("A10001"), "0".to_string()).unwrap();
+        // The cells too far up the chain cannot be computed, whatever the order they are in
+        let depth_exceeded = Some(ComputeError::DepthExceeded(10));
+        for y in [1, 5_000, 9_990] {
+            assert_eq!(
+                sheet.get_error(cell(&format!("A{y}"))),
+                depth_exceeded,
+                "A{y}"
+            );
+        }
+        assert_eq!(
+            sheet.get_computed(cell("A9991")),
+            Some(Ok(Value::Number(10.0)))
+        );
+    }
+
+    #[test]
+    fn test_default_limit_fits_the_stack() {
+        let rows = MAX_REFERENCE_DEPTH * 2;
+        let mut sheet = indirect_chain(rows);
+        sheet
+            .set_cell(cell(&format!("A{}", rows + 1)), "0".to_string())
+            .unwrap();
+        assert_eq!(
+            sheet.get_error(cell("A1")),
+            Some(ComputeError::DepthExceeded(MAX_REFERENCE_DEPTH))
+        );
+        let deepest = cell(&format!("A{}", rows - MAX_REFERENCE_DEPTH + 1));
+        assert_eq!(
+            sheet.get_computed(deepest),
+            Some(Ok(Value::Number(MAX_REFERENCE_DEPTH as f64)))
+        );
+    }
+
+    #[test]
+    fn test_static_chain_is_not_limited() {
+        // A chain of references known to the order of computing is computed in that order
+        let mut sheet = SpreadSheet::default();
+        sheet.set_recalc_mode(RecalcMode::Manual);
+        sheet.set_cell(cell("A1"), "1".to_string()).unwrap();
+        for y in 2..=1_000 {
+            let raw = format!("=A{} + 1", y - 1);
+            sheet.set_cell(cell(&format!("A{y}")), raw).unwrap();
+        }
+        sheet
+            .set_cell(cell("B1"), "=indirect(\"A1000\")".to_string())
+            .unwrap();
+        sheet.set_max_reference_depth(10);
+        sheet.set_recalc_mode(RecalcMode::Automatic);
+        assert_eq!(
+            sheet.get_computed(cell("B1")),
+            Some(Ok(Value::Number(1_000.0)))
+        );
+    }
+}
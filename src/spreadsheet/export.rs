@@ -0,0 +1,463 @@
+use super::{clipboard, SpreadSheet};
+use crate::common_types::{
+    col_to_letters, computed_to_text, BorderStyle, Borders, CellStyle, ComputeError, Edge, Index,
+    Value,
+};
+
+/// How `render` writes the computed values of a sheet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// Aligned columns for reading, as `render_table` writes them.
+    Table,
+    Csv,
+    Tsv,
+    /// An array of rows, each an array of values with `null` for empty cells.
+    Json,
+    /// A Markdown table under the column names, as `render_markdown` writes it.
+    Markdown,
+    /// A `<table>`, as `render_html` writes it.
+    Html,
+}
+
+/// Writes the computed values of the used range of `sheet` in `format`, a line per row.
+/// Apart from the table, values are written in full rather than as cells display them.
+pub fn render(sheet: &SpreadSheet, format: OutputFormat) -> String {
+    let Some(range) = sheet.used_range() else {
+        return String::new();
+    };
+
+    match format {
+        OutputFormat::Table => render_table(sheet),
+        OutputFormat::Csv => clipboard::selection_to_csv(sheet, range) + "\n",
+        OutputFormat::Tsv => {
+            clipboard::selection_to_tsv(sheet, range, clipboard::CopyMode::Values) + "\n"
+        }
+        OutputFormat::Json => {
+            let rows: Vec<String> = rows(sheet, range, json_value)
+                .into_iter()
+                .map(|row| format!("[{}]", row.join(", ")))
+                .collect();
+            format!("[\n  {}\n]\n", rows.join(",\n  "))
+        }
+        OutputFormat::Markdown => render_markdown(sheet, None, false),
+        OutputFormat::Html => render_html(sheet, None),
+    }
+}
+
+/// Writes the rectangle spanned by the corners of `range`, or else the used range, as an
+/// HTML table of the values as cells display them. Each cell has the class `num`, `err` or
+/// `text` by its value to style it by, and its fill, text style and borders inline.
+pub fn render_html(sheet: &SpreadSheet, range: Option<(Index, Index)>) -> String {
+    let Some((from, to)) = range.or_else(|| sheet.used_range()) else {
+        return String::new();
+    };
+    let display = sheet.display_settings();
+
+    let (last_x, last_y) = (from.x.max(to.x), from.y.max(to.y));
+
+    let mut html = String::from("<table>\n");
+    for y in from.y.min(to.y)..=last_y {
+        html.push_str("  <tr>\n");
+        for x in from.x.min(to.x)..=last_x {
+            let index = Index { x, y };
+            let computed = sheet.get_computed(index);
+            let class = match computed {
+                Some(Ok(Value::Number(_))) => " class=\"num\"",
+                Some(Err(_)) => " class=\"err\"",
+                Some(Ok(_)) => " class=\"text\"",
+                None => "",
+            };
+            let borders = sheet.borders_to_draw(index, (x == last_x, y == last_y));
+            let style = html_style(sheet.effective_format(index), borders);
+            let text = html_escape(&computed_to_text(computed, display));
+            html.push_str(&format!("    <td{class}{style}>{text}</td>\n"));
+        }
+        html.push_str("  </tr>\n");
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+/// Writes the rectangle spanned by the corners of `range`, or else the used range, as a
+/// GitHub flavored Markdown table of the values as cells display them. The first row is the
+/// header when `first_row_header`, otherwise the column names are. Columns mostly holding
+/// numbers are aligned right.
+pub fn render_markdown(
+    sheet: &SpreadSheet,
+    range: Option<(Index, Index)>,
+    first_row_header: bool,
+) -> String {
+    let Some((from, to)) = range.or_else(|| sheet.used_range()) else {
+        return String::new();
+    };
+    let (from, to) = (
+        Index {
+            x: from.x.min(to.x),
+            y: from.y.min(to.y),
+        },
+        Index {
+            x: from.x.max(to.x),
+            y: from.y.max(to.y),
+        },
+    );
+    let display = sheet.display_settings();
+
+    let columns = from.x..=to.x;
+    let (header, first_body_row): (Vec<String>, usize) = if first_row_header {
+        let header = columns
+            .clone()
+            .map(|x| computed_to_text(sheet.get_computed(Index { x, y: from.y }), display))
+            .collect();
+        (header, from.y + 1)
+    } else {
+        (columns.clone().map(col_to_letters).collect(), from.y)
+    };
+    let body_rows = first_body_row..=to.y;
+
+    let alignments: Vec<&str> = columns
+        .clone()
+        .map(|x| {
+            let (mut numbers, mut others) = (0, 0);
+            for y in body_rows.clone() {
+                match sheet.get_computed(Index { x, y }) {
+                    Some(Ok(Value::Number(_))) => numbers += 1,
+                    Some(_) => others += 1,
+                    None => (),
+                }
+            }
+            if numbers > others {
+                "---:"
+            } else {
+                "---"
+            }
+        })
+        .collect();
+
+    let line = |fields: Vec<String>| format!("| {} |\n", fields.join(" | "));
+    let mut text = line(header.iter().map(|field| markdown_field(field)).collect());
+    text.push_str(&format!("|{}|\n", alignments.join("|")));
+    for y in body_rows {
+        let fields = columns
+            .clone()
+            .map(|x| {
+                markdown_field(&computed_to_text(
+                    sheet.get_computed(Index { x, y }),
+                    display,
+                ))
+            })
+            .collect();
+        text.push_str(&line(fields));
+    }
+    text
+}
+
+/// Writes the used range of `sheet` as cells display it, with the column names above and
+/// the row numbers left of it. Numbers are aligned right, anything else left.
+pub fn render_table(sheet: &SpreadSheet) -> String {
+    let Some((from, to)) = sheet.used_range() else {
+        return String::new();
+    };
+    let display = sheet.display_settings();
+
+    // Each field with whether it is aligned right
+    let mut lines: Vec<Vec<(String, bool)>> = Vec::new();
+    let mut header = vec![(String::new(), false)];
+    header.extend((from.x..=to.x).map(|x| (col_to_letters(x), false)));
+    lines.push(header);
+    for y in from.y..=to.y {
+        let mut line = vec![((y + 1).to_string(), true)];
+        line.extend((from.x..=to.x).map(|x| {
+            let computed = sheet.get_computed(Index { x, y });
+            let right = matches!(computed, Some(Ok(Value::Number(_))));
+            (computed_to_text(computed, display), right)
+        }));
+        lines.push(line);
+    }
+
+    let widths: Vec<usize> = (0..lines[0].len())
+        .map(|i| {
+            let width = |line: &Vec<(String, bool)>| line[i].0.chars().count();
+            lines.iter().map(width).max().unwrap_or_default()
+        })
+        .collect();
+    let mut text = String::new();
+    for line in lines {
+        let fields: Vec<String> = line
+            .iter()
+            .zip(&widths)
+            .map(|((field, right), &width)| match right {
+                true => format!("{field:>width$}"),
+                false => format!("{field:<width$}"),
+            })
+            .collect();
+        text.push_str(fields.join("  ").trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+/// Returns the fields `field` makes of the computed values in `range`, a vector per row.
+fn rows(
+    sheet: &SpreadSheet,
+    range: (Index, Index),
+    field: impl Fn(Option<Result<Value, ComputeError>>) -> String,
+) -> Vec<Vec<String>> {
+    let (from, to) = range;
+    (from.y..=to.y)
+        .map(|y| {
+            (from.x..=to.x)
+                .map(|x| field(sheet.get_computed(Index { x, y })))
+                .collect()
+        })
+        .collect()
+}
+
+fn value_text(computed: Option<Result<Value, ComputeError>>) -> String {
+    match computed {
+        Some(Ok(value)) => value.to_string(),
+        Some(Err(err)) => err.to_string(),
+        None => String::new(),
+    }
+}
+
+/// Returns the `style` attribute showing `style` with `borders` in place of its own, empty
+/// for the default style.
+fn html_style(style: CellStyle, borders: Borders) -> String {
+    let mut declarations = Vec::new();
+    if let Some([r, g, b, a]) = style.fill {
+        let alpha = f32::from(a) / 255.0;
+        declarations.push(format!("background-color: rgba({r}, {g}, {b}, {alpha:.2})"));
+    }
+    if style.bold {
+        declarations.push("font-weight: bold".to_string());
+    }
+    if style.italic {
+        declarations.push("font-style: italic".to_string());
+    }
+    for (edge, name) in [
+        (Edge::Top, "top"),
+        (Edge::Bottom, "bottom"),
+        (Edge::Left, "left"),
+        (Edge::Right, "right"),
+    ] {
+        if let Some(BorderStyle {
+            width,
+            color: [r, g, b, a],
+        }) = borders.get(edge)
+        {
+            let alpha = f32::from(a) / 255.0;
+            declarations.push(format!(
+                "border-{name}: {width}px solid rgba({r}, {g}, {b}, {alpha:.2})"
+            ));
+        }
+    }
+
+    match declarations.is_empty() {
+        true => String::new(),
+        false => format!(" style=\"{}\"", declarations.join("; ")),
+    }
+}
+
+fn html_escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn markdown_field(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', "<br>")
+}
+
+pub(super) fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn json_value(computed: Option<Result<Value, ComputeError>>) -> String {
+    match computed {
+        Some(Ok(Value::Number(num))) if num.is_finite() => num.to_string(),
+        Some(Ok(Value::Bool(b))) => b.to_string(),
+        None | Some(Ok(Value::Number(_))) => "null".to_string(),
+        computed => json_string(&value_text(computed)),
+    }
+}
+
+pub(super) fn json_string(text: &str) -> String {
+    let mut escaped = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_types::TextStyle;
+
+    fn sheet() -> SpreadSheet {
+        let mut sheet = SpreadSheet::default();
+        let cells = [
+            (1, 1, "Item"),
+            (2, 1, "Price"),
+            (1, 2, "Chair, oak"),
+            (2, 2, "1250"),
+            (1, 3, "Total"),
+            (2, 3, "=C3 * 2"),
+            (3, 3, "=C3 + TRUE"),
+        ];
+        for (x, y, raw) in cells {
+            sheet
+                .add_cell_and_compute(Index { x, y }, raw.to_string())
+                .unwrap();
+        }
+        sheet
+    }
+
+    #[test]
+    fn test_render_table() {
+        assert_eq!(
+            render_table(&sheet()),
+            "   B           C      D\n\
+             2  Item        Price\n\
+             3  Chair, oak  1,250\n\
+             4  Total       2,500  #VALUE!\n"
+        );
+        assert_eq!(render_table(&SpreadSheet::default()), "");
+    }
+
+    #[test]
+    fn test_render_machine_formats() {
+        let sheet = sheet();
+        assert_eq!(
+            render(&sheet, OutputFormat::Csv),
+            "Item,Price,\n\"Chair, oak\",1250,\nTotal,2500,#VALUE!\n"
+        );
+        assert_eq!(
+            render(&sheet, OutputFormat::Tsv),
+            "Item\tPrice\t\nChair, oak\t1250\t\nTotal\t2500\t#VALUE!\n"
+        );
+        assert_eq!(
+            render(&sheet, OutputFormat::Json),
+            "[\n  [\"Item\", \"Price\", null],\n  [\"Chair, oak\", 1250, null],\n  \
+             [\"Total\", 2500, \"#VALUE!\"]\n]\n"
+        );
+        assert_eq!(render(&SpreadSheet::default(), OutputFormat::Json), "");
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let sheet = sheet();
+        assert_eq!(
+            render_markdown(&sheet, None, true),
+            "| Item | Price |  |\n\
+             |---|---:|---|\n\
+             | Chair, oak | 1,250 |  |\n\
+             | Total | 2,500 | #VALUE! |\n"
+        );
+        // The corners in any order, under the column names
+        let price = (Index { x: 2, y: 3 }, Index { x: 2, y: 1 });
+        assert_eq!(
+            render_markdown(&sheet, Some(price), false),
+            "| C |\n|---:|\n| Price |\n| 1,250 |\n| 2,500 |\n"
+        );
+        assert_eq!(render_markdown(&SpreadSheet::default(), None, true), "");
+    }
+
+    #[test]
+    fn test_markdown_alignment_by_majority() {
+        let mut sheet = SpreadSheet::default();
+        for (y, raw) in ["1", "2", "n/a", "", "4"].into_iter().enumerate() {
+            if !raw.is_empty() {
+                sheet
+                    .add_cell_and_compute(Index { x: 0, y }, raw.to_string())
+                    .unwrap();
+            }
+        }
+        sheet
+            .add_cell_and_compute(Index { x: 1, y: 0 }, "a".to_string())
+            .unwrap();
+        sheet
+            .add_cell_and_compute(Index { x: 1, y: 1 }, "1".to_string())
+            .unwrap();
+
+        let table = render_markdown(&sheet, None, false);
+        assert_eq!(table.lines().nth(1), Some("|---:|---|"));
+        // Empty cells give empty fields
+        assert_eq!(table.lines().nth(5), Some("|  |  |"));
+    }
+
+    #[test]
+    fn test_render_html() {
+        let mut sheet = sheet();
+        sheet.set_fill(Index { x: 1, y: 1 }, Some([255, 128, 0, 255]));
+        sheet.toggle_text_style(
+            (Index { x: 1, y: 1 }, Index { x: 2, y: 1 }),
+            TextStyle::Bold,
+        );
+        sheet
+            .add_cell_and_compute(Index { x: 1, y: 2 }, "<Chair> & \"oak\"".to_string())
+            .unwrap();
+
+        let header = (Index { x: 2, y: 1 }, Index { x: 1, y: 1 });
+        assert_eq!(
+            render_html(&sheet, Some(header)),
+            "<table>\n  <tr>\n    \
+             <td class=\"text\" style=\"background-color: rgba(255, 128, 0, 1.00); font-weight: bold\">Item</td>\n    \
+             <td class=\"text\" style=\"font-weight: bold\">Price</td>\n  \
+             </tr>\n</table>\n"
+        );
+
+        let html = render_html(&sheet, None);
+        assert!(html.contains("<td class=\"text\">&lt;Chair&gt; &amp; &quot;oak&quot;</td>"));
+        assert!(html.contains("<td class=\"num\">1,250</td>"));
+        assert!(html.contains("<td class=\"err\">#VALUE!</td>"));
+        // An empty cell of the used range
+        assert!(html.contains("<td></td>"));
+
+        // The edge between two cells is written once, the table keeps its outer edge
+        let line = BorderStyle {
+            width: 2,
+            color: [0, 0, 0, 255],
+        };
+        sheet.set_border(header, &[Edge::Right], Some(line));
+        assert_eq!(
+            render_html(&sheet, Some(header)),
+            "<table>\n  <tr>\n    \
+             <td class=\"text\" style=\"background-color: rgba(255, 128, 0, 1.00); font-weight: bold\">Item</td>\n    \
+             <td class=\"text\" style=\"font-weight: bold; border-left: 2px solid rgba(0, 0, 0, 1.00); \
+             border-right: 2px solid rgba(0, 0, 0, 1.00)\">Price</td>\n  \
+             </tr>\n</table>\n"
+        );
+        assert_eq!(render_html(&SpreadSheet::default(), None), "");
+    }
+
+    #[test]
+    fn test_escaping() {
+        assert_eq!(html_escape("it's <b>"), "it&#39;s &lt;b&gt;");
+        assert_eq!(markdown_field("a|b\nc"), "a\\|b<br>c");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(json_string("a\"b\\c\nd"), "\"a\\\"b\\\\c\\nd\"");
+    }
+}
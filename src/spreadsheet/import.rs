@@ -0,0 +1,132 @@
+use crate::common_types::{Cell, ParsedCell, Value};
+
+/// Splits the first pipe table found in `text` into rows of trimmed fields, leaving out the
+/// alignment row under the header. Lines before the table are skipped and the table ends at
+/// the first line without a pipe. `\|` and pipes in inline code are part of the field, and
+/// `<br>` is read as a line break.
+pub fn parse_markdown(text: &str) -> Vec<Vec<String>> {
+    let lines: Vec<&str> = text.lines().map(str::trim).collect();
+    let Some(header) = lines.windows(2).position(|pair| {
+        pair[0].contains('|') && split_row(pair[1]).iter().all(|field| is_delimiter(field))
+    }) else {
+        return Vec::new();
+    };
+
+    let body = lines[header + 2..]
+        .iter()
+        .take_while(|line| line.contains('|'));
+    std::iter::once(&lines[header])
+        .chain(body)
+        .map(|line| split_row(line))
+        .collect()
+}
+
+/// Returns the cell a field of an imported table is loaded as: a number when it reads as one,
+/// thousands separators allowed, and text otherwise, whatever a typed cell would make of it.
+pub fn cell_from_field(field: &str) -> Cell {
+    if let Some(number) = parse_number(field) {
+        return Cell::from_raw(number.to_string());
+    }
+
+    let mut cell = Cell::from_raw(field.to_string());
+    let value = Value::Text(field.to_string());
+    cell.parsed_representation = Some(Ok(ParsedCell::Value(value.clone())));
+    cell.computed_value = Some(Ok(value));
+    cell.needs_compute = false;
+    cell
+}
+
+fn split_row(line: &str) -> Vec<String> {
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_code = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => field.push(chars.next().unwrap()),
+            '`' => {
+                in_code = !in_code;
+                field.push(c);
+            }
+            '|' if !in_code => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    // Without a closing pipe the last field is what follows the last one
+    if !field.trim().is_empty() || fields.is_empty() {
+        fields.push(field);
+    }
+
+    fields
+        .iter()
+        .map(|field| field.trim().replace("<br>", "\n"))
+        .collect()
+}
+
+/// Returns true for a field of the alignment row, such as `---` or `:--:`.
+fn is_delimiter(field: &str) -> bool {
+    let dashes = field.strip_prefix(':').unwrap_or(field);
+    let dashes = dashes.strip_suffix(':').unwrap_or(dashes);
+    !dashes.is_empty() && dashes.chars().all(|c| c == '-')
+}
+
+fn parse_number(field: &str) -> Option<f64> {
+    let unsigned = field.strip_prefix(['-', '+']).unwrap_or(field);
+    let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    let groups: Vec<&str> = whole.split(',').collect();
+    let grouped = groups[0].len() <= 3 && groups[1..].iter().all(|group| group.len() == 3);
+    let digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+    if (groups.len() > 1 && !grouped)
+        || !groups.iter().all(|group| digits(group))
+        || !digits(fraction)
+        || whole.is_empty() && fraction.is_empty()
+    {
+        return None;
+    }
+    field.replace(',', "").parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_markdown() {
+        let readme = "Supported functions:\n\
+                      \n\
+                      | Function | Description          | Example            |\n\
+                      |:---------|:--------------------:|-------------------:|\n\
+                      | `SUM`    | Adds numbers \\| ranges | `=SUM(A1:A3)`      |\n\
+                      | `IF`     | Picks by `a || b`    |\n\
+                      \n\
+                      More text | with a pipe";
+        assert_eq!(
+            parse_markdown(readme),
+            vec![
+                vec!["Function", "Description", "Example"],
+                vec!["`SUM`", "Adds numbers | ranges", "`=SUM(A1:A3)`"],
+                vec!["`IF`", "Picks by `a || b`"],
+            ]
+        );
+
+        // Without the outer pipes, and a line break in a field
+        assert_eq!(
+            parse_markdown("Name | Qty\n--- | ---\nPen<br>blue | 3\nInk |"),
+            vec![vec!["Name", "Qty"], vec!["Pen\nblue", "3"], vec!["Ink"]]
+        );
+        assert!(parse_markdown("| not | a table |\n| a | b |").is_empty());
+    }
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(parse_number("1,250.5"), Some(1250.5));
+        assert_eq!(parse_number("-0.25"), Some(-0.25));
+        assert_eq!(parse_number(".5"), Some(0.5));
+        assert_eq!(parse_number("12,34"), None);
+        assert_eq!(parse_number("2024-01-31"), None);
+        assert_eq!(parse_number("inf"), None);
+        assert_eq!(parse_number("-"), None);
+        assert_eq!(parse_number(""), None);
+    }
+}
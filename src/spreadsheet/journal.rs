@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::SpreadSheet;
+use crate::common_types::{index_to_name, Index};
+
+/// A change of the content of a cell, as the journal keeps it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeRecord {
+    pub time: SystemTime,
+    pub index: Index,
+    /// The content before the change, empty for a cell that had none.
+    pub old_raw: String,
+    /// The content after the change, empty for a cell cleared.
+    pub new_raw: String,
+}
+
+impl ChangeRecord {
+    /// Returns the seconds since the Unix epoch the change was made at.
+    pub fn timestamp(&self) -> u64 {
+        self.time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs())
+    }
+
+    /// Writes the record as a line of the log: the timestamp, the name of the cell and the
+    /// content before and after, separated by tabs. Backslashes, tabs and line breaks in the
+    /// content are escaped with a backslash.
+    pub fn to_log_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}",
+            self.timestamp(),
+            index_to_name(self.index),
+            escape(&self.old_raw),
+            escape(&self.new_raw)
+        )
+    }
+}
+
+fn escape(raw: &str) -> String {
+    raw.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+/// The latest changes of the content of the cells, the oldest forgotten past `capacity`.
+/// Each change can be appended to a log file as well, which keeps them all.
+#[derive(Debug)]
+pub struct Journal {
+    capacity: usize,
+    records: Vec<ChangeRecord>,
+    // The records again by cell, oldest first
+    by_cell: HashMap<Index, Vec<ChangeRecord>>,
+    log_path: Option<PathBuf>,
+    // Why the log could not be written to, after which it no longer is
+    log_error: Option<String>,
+}
+
+impl Journal {
+    pub fn new(capacity: usize, log_path: Option<PathBuf>) -> Self {
+        Self {
+            capacity,
+            records: Vec::new(),
+            by_cell: HashMap::new(),
+            log_path,
+            log_error: None,
+        }
+    }
+
+    pub fn record(&mut self, record: ChangeRecord) {
+        if let Some(path) = &self.log_path {
+            if let Err(err) = append_line(path, &record.to_log_line()) {
+                self.log_error = Some(format!("Cannot write to {}: {err}", path.display()));
+                self.log_path = None;
+            }
+        }
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.records.len() == self.capacity {
+            let oldest = self.records.remove(0);
+            if let Some(changes) = self.by_cell.get_mut(&oldest.index) {
+                changes.remove(0);
+                if changes.is_empty() {
+                    self.by_cell.remove(&oldest.index);
+                }
+            }
+        }
+        self.by_cell
+            .entry(record.index)
+            .or_default()
+            .push(record.clone());
+        self.records.push(record);
+    }
+
+    pub fn history(&self, index: Index) -> &[ChangeRecord] {
+        self.by_cell.get(&index).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn full_history(&self) -> &[ChangeRecord] {
+        &self.records
+    }
+
+    pub fn log_error(&self) -> Option<&str> {
+        self.log_error.as_deref()
+    }
+}
+
+/// Returns the path of the log kept next to the sheet file at `sheet_path`, its name with
+/// `.log` appended.
+pub fn sidecar_path(sheet_path: &Path) -> PathBuf {
+    let mut name = sheet_path.as_os_str().to_owned();
+    name.push(".log");
+    PathBuf::from(name)
+}
+
+fn append_line(path: &Path, line: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{line}")
+}
+
+impl SpreadSheet {
+    /// Starts keeping the last `capacity` changes of the content of the cells, every change
+    /// made through the sheet from now on, undoing and redoing included. Each change is
+    /// appended to the file at `log_path` as well when given, see `ChangeRecord::to_log_line`.
+    pub fn enable_journal(&mut self, capacity: usize, log_path: Option<PathBuf>) {
+        self.journal = Some(Journal::new(capacity, log_path));
+    }
+
+    /// Appends the changes from now on to the file at `log_path` instead, or to none, keeping
+    /// those kept. Does nothing while the journal is off.
+    pub fn set_journal_log(&mut self, log_path: Option<PathBuf>) {
+        if let Some(journal) = &mut self.journal {
+            journal.log_path = log_path;
+            journal.log_error = None;
+        }
+    }
+
+    /// Stops keeping changes, forgetting those kept.
+    pub fn disable_journal(&mut self) {
+        self.journal = None;
+    }
+
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    /// Returns the changes kept of the cell at `index`, oldest first, none when the journal
+    /// is off.
+    pub fn history(&self, index: Index) -> &[ChangeRecord] {
+        self.journal
+            .as_ref()
+            .map_or(&[], |journal| journal.history(index))
+    }
+
+    /// Returns all the changes kept, oldest first.
+    pub fn full_history(&self) -> &[ChangeRecord] {
+        self.journal.as_ref().map_or(&[], Journal::full_history)
+    }
+
+    /// Returns the content of each of the cells at `indices` as it is, for `journal_changes`
+    /// to tell which changed. Nothing is kept when the journal is off.
+    pub(super) fn journal_before(
+        &self,
+        indices: impl IntoIterator<Item = Index>,
+    ) -> Vec<(Index, String)> {
+        if self.journal.is_none() {
+            return Vec::new();
+        }
+        let mut before: Vec<(Index, String)> = indices
+            .into_iter()
+            .map(|index| (index, self.get_raw(&index).unwrap_or_default().to_string()))
+            .collect();
+        before.sort_by_key(|(index, _)| (index.y, index.x));
+        before
+    }
+
+    /// Records the cells of `before` whose content is no longer the same.
+    pub(super) fn journal_changes(&mut self, before: Vec<(Index, String)>) {
+        let time = SystemTime::now();
+        let changes: Vec<ChangeRecord> = before
+            .into_iter()
+            .filter_map(|(index, old_raw)| {
+                let new_raw = self.get_raw(&index).unwrap_or_default();
+                (new_raw != old_raw).then(|| ChangeRecord {
+                    time,
+                    index,
+                    old_raw,
+                    new_raw: new_raw.to_string(),
+                })
+            })
+            .collect();
+        if let Some(journal) = &mut self.journal {
+            for change in changes {
+                journal.record(change);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_types::cell_addr::cell;
+
+    fn changes(records: &[ChangeRecord]) -> Vec<(String, &str, &str)> {
+        records
+            .iter()
+            .map(|record| {
+                let name = index_to_name(record.index);
+                (name, record.old_raw.as_str(), record.new_raw.as_str())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_journal_records_edits() {
+        let mut sheet = SpreadSheet::default();
+        sheet.set_cell(cell("A1"), "1".to_string()).unwrap();
+        sheet.enable_journal(10, None);
+
+        sheet.set_cell(cell("A1"), "2".to_string()).unwrap();
+        // Writing what a cell already holds changes nothing, nor does styling it
+        sheet.set_cell(cell("A1"), "2".to_string()).unwrap();
+        sheet.set_fill(cell("A1"), Some([0, 0, 0, 255]));
+        sheet.set_cell(cell("B1"), "=A1 * 2".to_string()).unwrap();
+        sheet.set_cell(cell("B1"), String::new()).unwrap();
+        assert_eq!(
+            changes(sheet.full_history()),
+            [
+                ("A1".to_string(), "1", "2"),
+                ("B1".to_string(), "", "=A1 * 2"),
+                ("B1".to_string(), "=A1 * 2", ""),
+            ]
+        );
+        assert_eq!(
+            changes(sheet.history(cell("B1"))),
+            [
+                ("B1".to_string(), "", "=A1 * 2"),
+                ("B1".to_string(), "=A1 * 2", ""),
+            ]
+        );
+        assert!(sheet.history(cell("C1")).is_empty());
+
+        // Undoing is a change of its own, and undoing still works the same
+        assert_eq!(sheet.undo(), Some(vec![cell("B1")]));
+        assert_eq!(sheet.get_raw(&cell("B1")), Some("=A1 * 2"));
+        assert_eq!(
+            changes(&sheet.history(cell("B1"))[2..]),
+            [("B1".to_string(), "", "=A1 * 2")]
+        );
+
+        sheet.disable_journal();
+        assert!(sheet.full_history().is_empty());
+    }
+
+    #[test]
+    fn test_journal_capacity() {
+        let mut sheet = SpreadSheet::default();
+        sheet.enable_journal(3, None);
+        for (name, raw) in [
+            ("A1", "1"),
+            ("B1", "2"),
+            ("A1", "3"),
+            ("C1", "4"),
+            ("B1", "5"),
+        ] {
+            sheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        assert_eq!(
+            changes(sheet.full_history()),
+            [
+                ("A1".to_string(), "1", "3"),
+                ("C1".to_string(), "", "4"),
+                ("B1".to_string(), "2", "5"),
+            ]
+        );
+        assert_eq!(
+            changes(sheet.history(cell("A1"))),
+            [("A1".to_string(), "1", "3")]
+        );
+        assert_eq!(
+            changes(sheet.history(cell("B1"))),
+            [("B1".to_string(), "2", "5")]
+        );
+    }
+
+    #[test]
+    fn test_log_file() {
+        let dir = std::env::temp_dir();
+        let sheet_path = dir.join(format!("journal_{}.txt", std::process::id()));
+        let log_path = sidecar_path(&sheet_path);
+        assert_eq!(
+            log_path.file_name().unwrap().to_str(),
+            Some(format!("journal_{}.txt.log", std::process::id()).as_str())
+        );
+
+        // The log keeps what the journal forgets
+        let mut sheet = SpreadSheet::default();
+        sheet.enable_journal(1, Some(log_path.clone()));
+        sheet.set_cell(cell("A1"), "1".to_string()).unwrap();
+        sheet.set_cell(cell("A1"), "2".to_string()).unwrap();
+        assert_eq!(sheet.full_history().len(), 1);
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        let lines: Vec<&str> = log
+            .lines()
+            .map(|line| line.split_once('\t').unwrap().1)
+            .collect();
+        assert_eq!(lines, ["A1\t\t1", "A1\t1\t2"]);
+        std::fs::remove_file(&log_path).unwrap();
+
+        // A log that cannot be written to is given up on, the journal going on
+        sheet.set_journal_log(Some(dir.join("missing").join("journal.log")));
+        sheet.set_cell(cell("A1"), "3".to_string()).unwrap();
+        let journal = sheet.journal().unwrap();
+        assert!(journal.log_error().unwrap().starts_with("Cannot write to"));
+        assert_eq!(
+            changes(journal.full_history()),
+            [("A1".to_string(), "2", "3")]
+        );
+    }
+
+    #[test]
+    fn test_log_line() {
+        let record = ChangeRecord {
+            time: UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000),
+            index: cell("B3"),
+            old_raw: "a\tb".to_string(),
+            new_raw: "c\\d\ne".to_string(),
+        };
+        assert_eq!(record.to_log_line(), "1700000000\tB3\ta\\tb\tc\\\\d\\ne");
+    }
+}
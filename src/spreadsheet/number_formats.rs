@@ -0,0 +1,117 @@
+use super::SpreadSheet;
+use crate::common_types::{Index, NumberFormat, Value};
+
+impl SpreadSheet {
+    /// Sets the number format of every cell at `indices`. `NumberFormat::General` leaves them
+    /// to the format of their row or column, see `effective_format`. Undone as a single step.
+    pub fn set_number_format(
+        &mut self,
+        indices: impl IntoIterator<Item = Index>,
+        format: NumberFormat,
+    ) {
+        let indices: Vec<Index> = indices.into_iter().collect();
+        self.batch(|sheet| {
+            for index in indices {
+                sheet.update_style(index, |style| style.number_format = format);
+            }
+        });
+    }
+
+    /// Sets `format` on the cells at `indices` when any of them is shown in another kind of
+    /// format, and clears the format of all of them otherwise. See `NumberFormat::is_like`.
+    pub fn toggle_number_format(
+        &mut self,
+        indices: impl IntoIterator<Item = Index>,
+        format: NumberFormat,
+    ) {
+        let indices: Vec<Index> = indices.into_iter().collect();
+        let on = indices
+            .iter()
+            .any(|&index| !self.effective_format(index).number_format.is_like(format));
+        let format = if on { format } else { NumberFormat::General };
+        self.set_number_format(indices, format);
+    }
+
+    /// Shows `change` more decimals in the cells at `indices`, or fewer when negative. Each
+    /// cell starts from the format it is shown in, the one of its row or column included,
+    /// and from the decimals its number is shown with when that format does not fix them.
+    /// Undone as a single step.
+    pub fn change_decimals(&mut self, indices: impl IntoIterator<Item = Index>, change: i32) {
+        let changed: Vec<(Index, NumberFormat)> = indices
+            .into_iter()
+            .map(|index| {
+                let format = self.effective_format(index).number_format;
+                let shown = match self.get_computed(index) {
+                    Some(Ok(Value::Number(num))) if format.is_percent() => {
+                        self.display.shown_decimals(num * 100.0)
+                    }
+                    Some(Ok(Value::Number(num))) => self.display.shown_decimals(num),
+                    _ => 0,
+                };
+                (index, format.change_decimals(change, shown))
+            })
+            .collect();
+        self.batch(|sheet| {
+            for (index, format) in changed {
+                sheet.update_style(index, |style| style.number_format = format);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_types::cell_addr::cell;
+
+    fn format(sheet: &SpreadSheet, name: &str) -> NumberFormat {
+        sheet.get_style(cell(name)).number_format
+    }
+
+    #[test]
+    fn test_toggle_number_format() {
+        let mut sheet = SpreadSheet::default();
+        sheet.set_cell(cell("A1"), "0.5".to_string()).unwrap();
+        sheet.set_cell(cell("A2"), "25%".to_string()).unwrap();
+        let cells = [cell("A1"), cell("A2")];
+
+        // On while any of them is not a percentage
+        sheet.toggle_number_format(cells, NumberFormat::Percent);
+        assert_eq!(format(&sheet, "A1"), NumberFormat::Percent);
+        sheet.toggle_number_format(cells, NumberFormat::Percent);
+        assert_eq!(format(&sheet, "A2"), NumberFormat::General);
+
+        let dollars = NumberFormat::Currency {
+            symbol: '$',
+            decimals: 2,
+        };
+        sheet.toggle_number_format(cells, dollars);
+        assert_eq!(format(&sheet, "A1"), dollars);
+        // Undone as one step
+        sheet.undo();
+        assert_eq!(format(&sheet, "A1"), NumberFormat::General);
+        assert_eq!(sheet.get_raw(&cell("A2")), Some("25%"));
+    }
+
+    #[test]
+    fn test_change_decimals() {
+        let mut sheet = SpreadSheet::default();
+        sheet.set_cell(cell("A1"), "1.25".to_string()).unwrap();
+        sheet.set_cell(cell("B1"), "0.5".to_string()).unwrap();
+        sheet.set_column_format(1, NumberFormat::Percent);
+
+        sheet.change_decimals([cell("A1"), cell("B1")], 1);
+        assert_eq!(format(&sheet, "A1"), NumberFormat::Fixed { decimals: 3 });
+        // From the format of the column, 50% showing no decimals
+        assert_eq!(
+            format(&sheet, "B1"),
+            NumberFormat::FixedPercent { decimals: 1 }
+        );
+
+        sheet.change_decimals([cell("A1")], -2);
+        assert_eq!(format(&sheet, "A1"), NumberFormat::Fixed { decimals: 1 });
+        // Empty cells get a format for what is written into them later
+        sheet.change_decimals([cell("C3")], -1);
+        assert_eq!(format(&sheet, "C3"), NumberFormat::Fixed { decimals: 0 });
+    }
+}
@@ -1,89 +1,259 @@
+use std::ops::Range;
+use std::sync::Arc;
+
 use ast_creator::{ASTCreateError, ASTCreator};
-use ast_resolver::ASTResolver;
+use ast_resolver::builtin_functions::{INDIRECT, OFFSET};
 use tokenizer::ExpressionTokenizer;
 
-use crate::common_types::{ParseError, Token, Value};
+use crate::common_types::{NumberFormat, ParseError, Token, Value};
 
 use super::{Cell, Expression, Index, ParsedCell};
 
 pub mod ast_creator;
 pub mod ast_resolver;
 pub mod dependancy_graph;
+pub mod references;
 pub mod tokenizer;
 
 pub struct CellParser {}
 
 impl CellParser {
+    /// Parses the raw content of a cell. A percentage typed into it also sets the percent
+    /// format on its style, and content starting with `'` is the text after it.
     pub fn parse_cell(cell: &mut Cell) {
         let raw_cell = &cell.raw_representation;
         if raw_cell.is_empty() {
             panic!("Parsing empty cell")
         }
 
-        let parsed_cell = match raw_cell.chars().nth(0).expect("Should never fail") {
-            '=' => Self::parse_expression(raw_cell),
-            d if d.is_ascii_digit() || d == '-' || d == '+' => match raw_cell.parse() {
-                Ok(number) => Ok(ParsedCell::Value(Value::Number(number))),
-                Err(e) => Err(ParseError(format!(
-                    "Had error: -{e}- parsing number {raw_cell}"
-                ))),
-            },
-            _ => {
-                let s = raw_cell.to_string();
-                if s == "TRUE" {
-                    Ok(ParsedCell::Value(Value::Bool(true)))
-                } else if s == "FALSE" {
-                    Ok(ParsedCell::Value(Value::Bool(false)))
-                } else {
-                    Ok(ParsedCell::Value(Value::Text(s)))
-                }
+        let parsed_cell = if raw_cell.starts_with('=') {
+            Self::parse_expression(raw_cell)
+        } else if let Some(text) = raw_cell.strip_prefix('\'') {
+            Ok(ParsedCell::Value(Value::Text(text.to_string())))
+        } else if let Some((number, percent)) = Self::parse_number(raw_cell.trim()) {
+            if percent {
+                cell.style.number_format = NumberFormat::Percent;
+            }
+            Ok(ParsedCell::Value(Value::Number(number)))
+        } else {
+            // Anything else is kept as it was typed, like dates
+            let s = raw_cell.to_string();
+            if s == "TRUE" {
+                Ok(ParsedCell::Value(Value::Bool(true)))
+            } else if s == "FALSE" {
+                Ok(ParsedCell::Value(Value::Bool(false)))
+            } else {
+                Ok(ParsedCell::Value(Value::Text(s)))
             }
         };
 
         cell.parsed_representation = Some(parsed_cell);
     }
 
-    fn parse_expression(s: &str) -> Result<ParsedCell, ParseError> {
-        let tokens = ExpressionTokenizer::new(s[1..].chars().collect())
-            .tokenize_expression()
-            .map_err(|e| match e {
-                tokenizer::TokenizeError::UnexpectedCharacter(c) => {
-                    ParseError(format!("Unexpected characther: {c}"))
-                }
-                tokenizer::TokenizeError::InvalidCellName(name) => {
-                    ParseError(format!("Invalid cell name: {name}"))
-                }
-                tokenizer::TokenizeError::InvalidNumber(num) => {
-                    ParseError(format!("Invalid number format: {num}"))
-                }
-                tokenizer::TokenizeError::UnterminatedString => {
-                    ParseError("Unterminated String".to_string())
-                }
+    /// Reads a number typed into a cell: plain like `-1.5e3`, with thousands separators like
+    /// `1,000.5`, or a percentage of either like `75%`. Returns the number, a percentage
+    /// divided by 100, and whether it was a percentage.
+    pub fn parse_number(raw: &str) -> Option<(f64, bool)> {
+        let (raw, percent) = match raw.strip_suffix('%') {
+            Some(raw) => (raw, true),
+            None => (raw, false),
+        };
+        // A sign goes right before the digits, which leaves out words like `inf`
+        let unsigned = raw.strip_prefix(['-', '+']).unwrap_or(raw);
+        if !unsigned.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+        let number: f64 = if raw.contains(',') {
+            // Only digits grouped by three, the first group aside, take separators
+            let (integer, decimals) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+            let mut groups = integer.split(',');
+            let first = groups.next()?;
+            let digits = |group: &str| group.chars().all(|c| c.is_ascii_digit());
+            let grouped = (1..=3).contains(&first.len())
+                && digits(first)
+                && groups.all(|group| group.len() == 3 && digits(group))
+                && digits(decimals);
+            if !grouped {
+                return None;
+            }
+            raw.replace(',', "").parse().ok()?
+        } else {
+            raw.parse().ok()?
+        };
+        // Too large to be a number, like `1e400`
+        if !number.is_finite() {
+            return None;
+        }
+
+        if percent {
+            Some((number / 100.0, true))
+        } else {
+            Some((number, false))
+        }
+    }
+
+    /// Parses a formula, its leading `=` included. The error it fails with tells the bytes of
+    /// `s` it was found at.
+    pub fn parse_expression(s: &str) -> Result<ParsedCell, ParseError> {
+        // Character indices of the expression to byte offsets of the formula
+        let offsets: Vec<usize> = s[1..]
+            .char_indices()
+            .map(|(i, _)| i + 1)
+            .chain([s.len()])
+            .collect();
+        let spanned = ExpressionTokenizer::new(s[1..].chars().collect())
+            .tokenize_spanned()
+            .map_err(|(e, span)| {
+                let message = match e {
+                    tokenizer::TokenizeError::UnexpectedCharacter(c) => {
+                        format!("Unexpected character '{c}'")
+                    }
+                    tokenizer::TokenizeError::InvalidCellName(name) => {
+                        format!("Invalid cell name {name}")
+                    }
+                    tokenizer::TokenizeError::InvalidNumber(num) => {
+                        format!("Invalid number {num}")
+                    }
+                    tokenizer::TokenizeError::UnterminatedString => {
+                        "Unterminated string".to_string()
+                    }
+                };
+                ParseError::at(&message, s, offsets[span.start]..offsets[span.end])
             })?;
+        let (tokens, spans): (Vec<Token>, Vec<Range<usize>>) = spanned
+            .into_iter()
+            .map(|(token, span)| (token, offsets[span.start]..offsets[span.end]))
+            .unzip();
 
         let dependencies = Self::find_dependants(&tokens);
-        let ast = ASTCreator::new(tokens.into_iter())
-            .parse()
-            .map_err(|e| match e {
-                ASTCreateError::UnexpectedToken => ParseError("Unexpected Token".to_string()),
-                ASTCreateError::MismatchedParentheses => {
-                    ParseError("Mismatched Parentheses".to_string())
+        let names = Self::find_names(&tokens);
+        let volatile = tokens.iter().any(|token| {
+            matches!(token, Token::FunctionName(name) if [INDIRECT, OFFSET].contains(&name.as_str()))
+        });
+        let mut creator = ASTCreator::new(tokens.into_iter());
+        let ast = creator.parse().map_err(|e| {
+            // Found at a token, or past the last one at the end of the formula
+            let span = match creator.error_position() {
+                Some(position) => spans[position].clone(),
+                None => s.len()..s.len(),
+            };
+            let message = match e {
+                ASTCreateError::UnexpectedToken if span.is_empty() => {
+                    "Unexpected end of formula".to_string()
                 }
-                ASTCreateError::InvalidRange => ParseError("Invalid Range Expression".to_string()),
-            })?;
-        let expr = Expression { ast, dependencies };
+                ASTCreateError::UnexpectedToken => format!("Unexpected '{}'", &s[span.clone()]),
+                ASTCreateError::MismatchedParentheses => "Mismatched parentheses".to_string(),
+                ASTCreateError::InvalidRange => "Invalid range".to_string(),
+            };
+            ParseError::at(&message, s, span)
+        })?;
+        let expr = Expression {
+            ast: Arc::new(ast),
+            dependencies,
+            names,
+            volatile,
+        };
         Ok(ParsedCell::Expr(expr))
     }
 
-    fn find_dependants(tokens: &[Token]) -> Vec<Index> {
-        let cells = tokens
-            .iter()
-            .filter_map(|x| match x {
-                Token::CellName(name) => Some(ASTResolver::get_cell_idx(name)),
-                _ => None,
-            })
+    /// Tokenizes a formula as far as it is valid, giving every token the range of bytes of
+    /// `formula` it was read from. The leading `=` is not a token.
+    pub fn tokenize_lossy(formula: &str) -> Vec<(Token, Range<usize>)> {
+        let Some(expression) = formula.strip_prefix('=') else {
+            return Vec::new();
+        };
+
+        // Character indices of the expression to byte offsets of the formula
+        let offsets: Vec<usize> = expression
+            .char_indices()
+            .map(|(i, _)| i + 1)
+            .chain([formula.len()])
             .collect();
 
-        cells
+        ExpressionTokenizer::new(expression.chars().collect())
+            .tokenize_lossy()
+            .into_iter()
+            .map(|(token, span)| (token, offsets[span.start]..offsets[span.end]))
+            .collect()
+    }
+
+    /// Returns the cells the expression reads, every cell of a range included.
+    fn find_dependants(tokens: &[Token]) -> Vec<Index> {
+        let mut dependencies = Vec::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            match &tokens[i..] {
+                [Token::CellName(from), Token::Colon, Token::CellName(to), ..] => {
+                    for y in from.y.min(to.y)..=from.y.max(to.y) {
+                        for x in from.x.min(to.x)..=from.x.max(to.x) {
+                            dependencies.push(Index { x, y });
+                        }
+                    }
+                    i += 3;
+                }
+                [Token::CellName(index), ..] => {
+                    dependencies.push(*index);
+                    i += 1;
+                }
+                _ => i += 1,
+            }
+        }
+        dependencies
+    }
+
+    /// Returns the names read by the expression, those not called like functions.
+    fn find_names(tokens: &[Token]) -> Vec<Box<str>> {
+        let mut names: Vec<Box<str>> = Vec::new();
+        for (i, token) in tokens.iter().enumerate() {
+            if let Token::FunctionName(name) = token {
+                if tokens.get(i + 1) != Some(&Token::LParen)
+                    && !names.iter().any(|n| **n == *name.as_str())
+                {
+                    names.push(name.as_str().into());
+                }
+            }
+        }
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &str) -> Value {
+        let mut cell = Cell::from_raw(raw.to_string());
+        CellParser::parse_cell(&mut cell);
+        match cell.parsed_representation {
+            Some(Ok(ParsedCell::Value(value))) => value,
+            parsed => panic!("{raw} parsed to {parsed:?}"),
+        }
+    }
+
+    #[test]
+    fn test_signed_numbers() {
+        assert_eq!(parse("-5"), Value::Number(-5.0));
+        assert_eq!(parse("+5"), Value::Number(5.0));
+        assert_eq!(parse("-0.25"), Value::Number(-0.25));
+        assert_eq!(parse("-1e3"), Value::Number(-1000.0));
+        assert_eq!(parse("-1,000"), Value::Number(-1000.0));
+        assert_eq!(parse("-50%"), Value::Number(-0.5));
+        // Whitespace around a number is left out, the raw content keeps it
+        assert_eq!(parse(" -5 "), Value::Number(-5.0));
+        assert_eq!(parse("\t42"), Value::Number(42.0));
+
+        for text in [
+            "-", "+", "-abc", "- 5", "--5", "+-5", "-inf", "NaN", "-.5%x", "1e400",
+        ] {
+            assert_eq!(parse(text), Value::Text(text.to_string()), "{text}");
+        }
+        assert_eq!(parse("TRUE"), Value::Bool(true));
+    }
+
+    #[test]
+    fn test_quoted_text() {
+        for (raw, text) in [("'=A1", "=A1"), ("'12", "12"), ("'TRUE", "TRUE"), ("'", "")] {
+            assert_eq!(parse(raw), Value::Text(text.to_string()), "{raw}");
+        }
     }
 }
@@ -7,6 +7,10 @@ where
     I: Iterator<Item = Token>,
 {
     tokens: Peekable<I>,
+    // How many tokens were taken, the last of them being the one an error is found at
+    taken: usize,
+    // Whether a token was asked for past the last one
+    ended: bool,
 }
 #[derive(Debug)]
 pub enum ASTCreateError {
@@ -22,16 +26,37 @@ where
     pub fn new(tokens: I) -> Self {
         Self {
             tokens: tokens.peekable(),
+            taken: 0,
+            ended: false,
         }
     }
 
+    /// Returns the position among the tokens of the one the error `parse` failed with was
+    /// found at, `None` when it was found past the last token.
+    pub fn error_position(&self) -> Option<usize> {
+        match self.ended {
+            true => None,
+            false => self.taken.checked_sub(1),
+        }
+    }
+
+    fn take_token(&mut self) -> Option<Token> {
+        let token = self.tokens.next();
+        match token {
+            Some(_) => self.taken += 1,
+            None => self.ended = true,
+        }
+        token
+    }
+
     pub fn parse(&mut self) -> Result<crate::common_types::AST, ASTCreateError> {
-        let result = self.parse_expression(0);
-        if self.tokens.next().is_some() {
+        // The first error found is kept, where it was found
+        let ast = self.parse_expression(0)?;
+        if self.take_token().is_some() {
             // We have not parsed all tokens
             Err(ASTCreateError::UnexpectedToken)
         } else {
-            result
+            Ok(ast)
         }
     }
 
@@ -45,7 +70,7 @@ where
             if precedence < min_precedence {
                 break;
             }
-            self.tokens.next(); // Consume the operator
+            self.take_token(); // Consume the operator
 
             // Handle unary NOT operator specially
             if op == Token::Not {
@@ -68,7 +93,11 @@ where
     }
 
     fn parse_primary(&mut self) -> Result<AST, ASTCreateError> {
-        match self.tokens.next() {
+        match self.take_token() {
+            // A name not followed by arguments stands for a value
+            Some(Token::FunctionName(name)) if self.tokens.peek() != Some(&Token::LParen) => {
+                Ok(AST::Name(name.as_str().into()))
+            }
             Some(Token::FunctionName(name)) => {
                 self.expect_token(Token::LParen)?;
                 let arguments = self.parse_function_arguements()?;
@@ -80,8 +109,8 @@ where
             Some(Token::CellName(name)) => {
                 // Check if this might be the start of a range
                 if let Some(Token::Colon) = self.tokens.peek() {
-                    self.tokens.next(); // consume colon
-                    match self.tokens.next() {
+                    self.take_token(); // consume colon
+                    match self.take_token() {
                         Some(Token::CellName(to_name)) => Ok(AST::Range {
                             from: name,
                             to: to_name,
@@ -96,7 +125,7 @@ where
             Some(Token::StringLiteral(s)) => Ok(AST::Value(Value::Text(s))),
             Some(Token::LParen) => {
                 let expr = self.parse_expression(0)?;
-                match self.tokens.next() {
+                match self.take_token() {
                     Some(Token::RParen) => Ok(expr),
                     _ => Err(ASTCreateError::MismatchedParentheses),
                 }
@@ -136,7 +165,7 @@ where
 
     // Helper function to expect a specific token
     fn expect_token(&mut self, expected: Token) -> Result<(), ASTCreateError> {
-        match self.tokens.next() {
+        match self.take_token() {
             Some(token) if token == expected => Ok(()),
             _ => Err(ASTCreateError::UnexpectedToken),
         }
@@ -146,6 +175,11 @@ where
         let mut arguements = Vec::new();
 
         let mut expecting_comma = false;
+        // A function can be called without arguments
+        if self.tokens.next_if_eq(&Token::RParen).is_some() {
+            self.taken += 1;
+            return Ok(arguements);
+        }
 
         loop {
             if !expecting_comma {
@@ -153,7 +187,7 @@ where
                 let arg = self.parse_expression(0)?;
                 arguements.push(arg);
             } else {
-                match self.tokens.next() {
+                match self.take_token() {
                     Some(Token::Comma) => expecting_comma = false,
                     Some(Token::RParen) => break,
                     Some(_unexpected) => return Err(ASTCreateError::UnexpectedToken),
@@ -169,21 +203,24 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::common_types::cell_addr::cell;
+    use crate::common_types::FunctionId;
+    use crate::spreadsheet::parser::tokenizer::ExpressionTokenizer;
 
     #[test]
     fn test_single_cell_name() {
-        let tokens = vec![Token::CellName("A1".to_string())];
+        let tokens = vec![Token::CellName(cell("A1"))];
         let mut parser = ASTCreator::new(tokens.into_iter());
         let ast = parser.parse().unwrap();
-        assert_eq!(ast, AST::CellName("A1".to_string()));
+        assert_eq!(ast, AST::CellName(cell("A1")));
     }
 
     #[test]
     fn test_simple_addition() {
         let tokens = vec![
-            Token::CellName("A1".to_string()),
+            Token::CellName(cell("A1")),
             Token::Plus,
-            Token::CellName("B2".to_string()),
+            Token::CellName(cell("B2")),
         ];
         let mut parser = ASTCreator::new(tokens.into_iter());
         let ast = parser.parse().unwrap();
@@ -191,8 +228,8 @@ mod tests {
             ast,
             AST::BinaryOp {
                 op: Token::Plus,
-                left: Box::new(AST::CellName("A1".to_string())),
-                right: Box::new(AST::CellName("B2".to_string())),
+                left: Box::new(AST::CellName(cell("A1"))),
+                right: Box::new(AST::CellName(cell("B2"))),
             }
         );
     }
@@ -200,11 +237,11 @@ mod tests {
     #[test]
     fn test_operator_precedence() {
         let tokens = vec![
-            Token::CellName("A1".to_string()),
+            Token::CellName(cell("A1")),
             Token::Plus,
-            Token::CellName("B2".to_string()),
+            Token::CellName(cell("B2")),
             Token::Multiply,
-            Token::CellName("C3".to_string()),
+            Token::CellName(cell("C3")),
         ];
         let mut parser = ASTCreator::new(tokens.into_iter());
         let ast = parser.parse().unwrap();
@@ -212,11 +249,11 @@ mod tests {
             ast,
             AST::BinaryOp {
                 op: Token::Plus,
-                left: Box::new(AST::CellName("A1".to_string())),
+                left: Box::new(AST::CellName(cell("A1"))),
                 right: Box::new(AST::BinaryOp {
                     op: Token::Multiply,
-                    left: Box::new(AST::CellName("B2".to_string())),
-                    right: Box::new(AST::CellName("C3".to_string())),
+                    left: Box::new(AST::CellName(cell("B2"))),
+                    right: Box::new(AST::CellName(cell("C3"))),
                 }),
             }
         );
@@ -226,12 +263,12 @@ mod tests {
     fn test_parentheses_override_precedence() {
         let tokens = vec![
             Token::LParen,
-            Token::CellName("A1".to_string()),
+            Token::CellName(cell("A1")),
             Token::Plus,
-            Token::CellName("B2".to_string()),
+            Token::CellName(cell("B2")),
             Token::RParen,
             Token::Multiply,
-            Token::CellName("C3".to_string()),
+            Token::CellName(cell("C3")),
         ];
         let mut parser = ASTCreator::new(tokens.into_iter());
         let ast = parser.parse().unwrap();
@@ -241,10 +278,10 @@ mod tests {
                 op: Token::Multiply,
                 left: Box::new(AST::BinaryOp {
                     op: Token::Plus,
-                    left: Box::new(AST::CellName("A1".to_string())),
-                    right: Box::new(AST::CellName("B2".to_string())),
+                    left: Box::new(AST::CellName(cell("A1"))),
+                    right: Box::new(AST::CellName(cell("B2"))),
                 }),
-                right: Box::new(AST::CellName("C3".to_string())),
+                right: Box::new(AST::CellName(cell("C3"))),
             }
         );
     }
@@ -253,18 +290,36 @@ mod tests {
     fn test_mismatched_parentheses() {
         let tokens = vec![
             Token::LParen,
-            Token::CellName("A1".to_string()),
+            Token::CellName(cell("A1")),
             Token::Plus,
-            Token::CellName("B2".to_string()),
+            Token::CellName(cell("B2")),
         ];
         let mut parser = ASTCreator::new(tokens.into_iter());
         let result = parser.parse();
         assert!(matches!(result, Err(ASTCreateError::MismatchedParentheses)));
     }
 
+    #[test]
+    fn test_name() {
+        let tokens = vec![
+            Token::FunctionName(FunctionId::new("vat")),
+            Token::Multiply,
+            Token::FunctionName(FunctionId::new("sum")),
+        ];
+        let ast = ASTCreator::new(tokens.into_iter()).parse().unwrap();
+        assert_eq!(
+            ast,
+            AST::BinaryOp {
+                op: Token::Multiply,
+                left: Box::new(AST::Name("vat".into())),
+                right: Box::new(AST::Name("sum".into())),
+            }
+        );
+    }
+
     #[test]
     fn test_unexpected_token() {
-        let tokens = vec![Token::Plus, Token::CellName("A1".to_string())];
+        let tokens = vec![Token::Plus, Token::CellName(cell("A1"))];
         let mut parser = ASTCreator::new(tokens.into_iter());
         let result = parser.parse();
         assert!(matches!(result, Err(ASTCreateError::UnexpectedToken)));
@@ -275,12 +330,12 @@ mod tests {
         let tokens = vec![
             Token::LParen,
             Token::LParen,
-            Token::CellName("A1".to_string()),
+            Token::CellName(cell("A1")),
             Token::Plus,
-            Token::CellName("B2".to_string()),
+            Token::CellName(cell("B2")),
             Token::RParen,
             Token::Multiply,
-            Token::CellName("C3".to_string()),
+            Token::CellName(cell("C3")),
             Token::RParen,
         ];
         let mut parser = ASTCreator::new(tokens.into_iter());
@@ -291,10 +346,10 @@ mod tests {
                 op: Token::Multiply,
                 left: Box::new(AST::BinaryOp {
                     op: Token::Plus,
-                    left: Box::new(AST::CellName("A1".to_string())),
-                    right: Box::new(AST::CellName("B2".to_string())),
+                    left: Box::new(AST::CellName(cell("A1"))),
+                    right: Box::new(AST::CellName(cell("B2"))),
                 }),
-                right: Box::new(AST::CellName("C3".to_string())),
+                right: Box::new(AST::CellName(cell("C3"))),
             }
         );
     }
@@ -302,9 +357,9 @@ mod tests {
     #[test]
     fn test_simple_function_call() {
         let tokens = vec![
-            Token::FunctionName("sum".to_string()),
+            Token::FunctionName(FunctionId::new("sum")),
             Token::LParen,
-            Token::CellName("A1".to_string()),
+            Token::CellName(cell("A1")),
             Token::RParen,
         ];
         let mut parser = ASTCreator::new(tokens.into_iter());
@@ -312,20 +367,52 @@ mod tests {
         assert_eq!(
             ast,
             AST::FunctionCall {
-                name: "sum".to_string(),
-                arguments: vec![AST::CellName("A1".to_string())],
+                name: FunctionId::new("sum"),
+                arguments: vec![AST::CellName(cell("A1"))],
             }
         );
     }
 
+    #[test]
+    fn test_function_call_without_arguments() {
+        let tokens = vec![
+            Token::FunctionName(FunctionId::new("row")),
+            Token::LParen,
+            Token::RParen,
+            Token::Plus,
+            Token::Number(1.0),
+        ];
+        let mut parser = ASTCreator::new(tokens.into_iter());
+        let ast = parser.parse().unwrap();
+        assert_eq!(
+            ast,
+            AST::BinaryOp {
+                op: Token::Plus,
+                left: Box::new(AST::FunctionCall {
+                    name: FunctionId::new("row"),
+                    arguments: vec![],
+                }),
+                right: Box::new(AST::Value(Value::Number(1.0))),
+            }
+        );
+        // A comma still needs an argument before it
+        let tokens = vec![
+            Token::FunctionName(FunctionId::new("row")),
+            Token::LParen,
+            Token::Comma,
+            Token::RParen,
+        ];
+        assert!(ASTCreator::new(tokens.into_iter()).parse().is_err());
+    }
+
     #[test]
     fn test_function_call_multiple_arguments() {
         let tokens = vec![
-            Token::FunctionName("average".to_string()),
+            Token::FunctionName(FunctionId::new("average")),
             Token::LParen,
-            Token::CellName("A1".to_string()),
+            Token::CellName(cell("A1")),
             Token::Comma,
-            Token::CellName("B2".to_string()),
+            Token::CellName(cell("B2")),
             Token::Comma,
             Token::Number(42.0),
             Token::RParen,
@@ -335,10 +422,10 @@ mod tests {
         assert_eq!(
             ast,
             AST::FunctionCall {
-                name: "average".to_string(),
+                name: FunctionId::new("average"),
                 arguments: vec![
-                    AST::CellName("A1".to_string()),
-                    AST::CellName("B2".to_string()),
+                    AST::CellName(cell("A1")),
+                    AST::CellName(cell("B2")),
                     AST::Value(Value::Number(42.0)),
                 ],
             }
@@ -348,13 +435,13 @@ mod tests {
     #[test]
     fn test_nested_function_calls() {
         let tokens = vec![
-            Token::FunctionName("sum".to_string()),
+            Token::FunctionName(FunctionId::new("sum")),
             Token::LParen,
-            Token::FunctionName("average".to_string()),
+            Token::FunctionName(FunctionId::new("average")),
             Token::LParen,
-            Token::CellName("A1".to_string()),
+            Token::CellName(cell("A1")),
             Token::Comma,
-            Token::CellName("B2".to_string()),
+            Token::CellName(cell("B2")),
             Token::RParen,
             Token::RParen,
         ];
@@ -363,13 +450,10 @@ mod tests {
         assert_eq!(
             ast,
             AST::FunctionCall {
-                name: "sum".to_string(),
+                name: FunctionId::new("sum"),
                 arguments: vec![AST::FunctionCall {
-                    name: "average".to_string(),
-                    arguments: vec![
-                        AST::CellName("A1".to_string()),
-                        AST::CellName("B2".to_string()),
-                    ],
+                    name: FunctionId::new("average"),
+                    arguments: vec![AST::CellName(cell("A1")), AST::CellName(cell("B2")),],
                 }],
             }
         );
@@ -378,9 +462,9 @@ mod tests {
     #[test]
     fn test_function_call_with_expression() {
         let tokens = vec![
-            Token::FunctionName("max".to_string()),
+            Token::FunctionName(FunctionId::new("max")),
             Token::LParen,
-            Token::CellName("A1".to_string()),
+            Token::CellName(cell("A1")),
             Token::Plus,
             Token::Number(10.0),
             Token::RParen,
@@ -390,10 +474,10 @@ mod tests {
         assert_eq!(
             ast,
             AST::FunctionCall {
-                name: "max".to_string(),
+                name: FunctionId::new("max"),
                 arguments: vec![AST::BinaryOp {
                     op: Token::Plus,
-                    left: Box::new(AST::CellName("A1".to_string())),
+                    left: Box::new(AST::CellName(cell("A1"))),
                     right: Box::new(AST::Value(Value::Number(10.0))),
                 }],
             }
@@ -403,8 +487,8 @@ mod tests {
     #[test]
     fn test_function_call_missing_parentheses() {
         let tokens = vec![
-            Token::FunctionName("sum".to_string()),
-            Token::CellName("A1".to_string()),
+            Token::FunctionName(FunctionId::new("sum")),
+            Token::CellName(cell("A1")),
         ];
         let mut parser = ASTCreator::new(tokens.into_iter());
         let result = parser.parse();
@@ -414,9 +498,9 @@ mod tests {
     #[test]
     fn test_function_call_missing_closing_parenthesis() {
         let tokens = vec![
-            Token::FunctionName("sum".to_string()),
+            Token::FunctionName(FunctionId::new("sum")),
             Token::LParen,
-            Token::CellName("A1".to_string()),
+            Token::CellName(cell("A1")),
         ];
         let mut parser = ASTCreator::new(tokens.into_iter());
         let result = parser.parse();
@@ -426,17 +510,17 @@ mod tests {
     #[test]
     fn test_simple_range() {
         let tokens = vec![
-            Token::CellName("A1".to_string()),
+            Token::CellName(cell("A1")),
             Token::Colon,
-            Token::CellName("B5".to_string()),
+            Token::CellName(cell("B5")),
         ];
         let mut parser = ASTCreator::new(tokens.into_iter());
         let ast = parser.parse().unwrap();
         assert_eq!(
             ast,
             AST::Range {
-                from: "A1".to_string(),
-                to: "B5".to_string(),
+                from: cell("A1"),
+                to: cell("B5"),
             }
         );
     }
@@ -444,11 +528,11 @@ mod tests {
     #[test]
     fn test_range_in_function() {
         let tokens = vec![
-            Token::FunctionName("sum".to_string()),
+            Token::FunctionName(FunctionId::new("sum")),
             Token::LParen,
-            Token::CellName("A1".to_string()),
+            Token::CellName(cell("A1")),
             Token::Colon,
-            Token::CellName("A10".to_string()),
+            Token::CellName(cell("A10")),
             Token::RParen,
         ];
         let mut parser = ASTCreator::new(tokens.into_iter());
@@ -456,10 +540,10 @@ mod tests {
         assert_eq!(
             ast,
             AST::FunctionCall {
-                name: "sum".to_string(),
+                name: FunctionId::new("sum"),
                 arguments: vec![AST::Range {
-                    from: "A1".to_string(),
-                    to: "A10".to_string(),
+                    from: cell("A1"),
+                    to: cell("A10"),
                 }],
             }
         );
@@ -468,7 +552,7 @@ mod tests {
     #[test]
     fn test_invalid_range_missing_second_cell() {
         let tokens = vec![
-            Token::CellName("A1".to_string()),
+            Token::CellName(cell("A1")),
             Token::Colon,
             Token::Number(42.0), // Should be a cell name
         ];
@@ -479,10 +563,7 @@ mod tests {
 
     #[test]
     fn test_invalid_range_missing_colon() {
-        let tokens = vec![
-            Token::CellName("A1".to_string()),
-            Token::CellName("A10".to_string()),
-        ];
+        let tokens = vec![Token::CellName(cell("A1")), Token::CellName(cell("A10"))];
         let mut parser = ASTCreator::new(tokens.into_iter());
         let result = parser.parse();
         assert!(matches!(result, Err(ASTCreateError::UnexpectedToken)));
@@ -491,9 +572,9 @@ mod tests {
     #[test]
     fn test_range_with_operation() {
         let tokens = vec![
-            Token::CellName("A1".to_string()),
+            Token::CellName(cell("A1")),
             Token::Colon,
-            Token::CellName("A10".to_string()),
+            Token::CellName(cell("A10")),
             Token::Plus,
             Token::Number(5.0),
         ];
@@ -504,8 +585,8 @@ mod tests {
             AST::BinaryOp {
                 op: Token::Plus,
                 left: Box::new(AST::Range {
-                    from: "A1".to_string(),
-                    to: "A10".to_string(),
+                    from: cell("A1"),
+                    to: cell("A10"),
                 }),
                 right: Box::new(AST::Value(Value::Number(5.0))),
             }
@@ -529,7 +610,7 @@ mod tests {
     #[test]
     fn test_simple_comparison() {
         let tokens = vec![
-            Token::CellName("A1".to_string()),
+            Token::CellName(cell("A1")),
             Token::Equals,
             Token::Bool(true),
         ];
@@ -539,7 +620,7 @@ mod tests {
             ast,
             AST::BinaryOp {
                 op: Token::Equals,
-                left: Box::new(AST::CellName("A1".to_string())),
+                left: Box::new(AST::CellName(cell("A1"))),
                 right: Box::new(AST::Value(Value::Bool(true))),
             }
         );
@@ -562,11 +643,11 @@ mod tests {
     #[test]
     fn test_complex_logical_expression() {
         let tokens = vec![
-            Token::CellName("A1".to_string()),
+            Token::CellName(cell("A1")),
             Token::GreaterThan,
             Token::Number(10.0),
             Token::And,
-            Token::CellName("B1".to_string()),
+            Token::CellName(cell("B1")),
             Token::LessThan,
             Token::Number(20.0),
         ];
@@ -578,12 +659,12 @@ mod tests {
                 op: Token::And,
                 left: Box::new(AST::BinaryOp {
                     op: Token::GreaterThan,
-                    left: Box::new(AST::CellName("A1".to_string())),
+                    left: Box::new(AST::CellName(cell("A1"))),
                     right: Box::new(AST::Value(Value::Number(10.0))),
                 }),
                 right: Box::new(AST::BinaryOp {
                     op: Token::LessThan,
-                    left: Box::new(AST::CellName("B1".to_string())),
+                    left: Box::new(AST::CellName(cell("B1"))),
                     right: Box::new(AST::Value(Value::Number(20.0))),
                 }),
             }
@@ -595,7 +676,7 @@ mod tests {
     fn test_logical_operator_precedence() {
         let tokens = vec![
             Token::Not,
-            Token::CellName("A1".to_string()),
+            Token::CellName(cell("A1")),
             Token::And,
             Token::Bool(true),
             Token::Or,
@@ -611,7 +692,7 @@ mod tests {
                     op: Token::And,
                     left: Box::new(AST::UnaryOp {
                         op: Token::Not,
-                        expr: Box::new(AST::CellName("A1".to_string())),
+                        expr: Box::new(AST::CellName(cell("A1"))),
                     }),
                     right: Box::new(AST::Value(Value::Bool(true))),
                 }),
@@ -624,9 +705,9 @@ mod tests {
     #[test]
     fn test_if_function_with_logical_condition() {
         let tokens = vec![
-            Token::FunctionName("if".to_string()),
+            Token::FunctionName(FunctionId::new("if")),
             Token::LParen,
-            Token::CellName("A1".to_string()),
+            Token::CellName(cell("A1")),
             Token::GreaterThan,
             Token::Number(10.0),
             Token::Comma,
@@ -640,11 +721,11 @@ mod tests {
         assert_eq!(
             ast,
             AST::FunctionCall {
-                name: "if".to_string(),
+                name: FunctionId::new("if"),
                 arguments: vec![
                     AST::BinaryOp {
                         op: Token::GreaterThan,
-                        left: Box::new(AST::CellName("A1".to_string())),
+                        left: Box::new(AST::CellName(cell("A1"))),
                         right: Box::new(AST::Value(Value::Number(10.0))),
                     },
                     AST::Value(Value::Bool(true)),
@@ -660,12 +741,12 @@ mod tests {
             Token::LParen,
             Token::Not,
             Token::LParen,
-            Token::CellName("A1".to_string()),
+            Token::CellName(cell("A1")),
             Token::Equals,
             Token::Bool(true),
             Token::RParen,
             Token::And,
-            Token::CellName("B1".to_string()),
+            Token::CellName(cell("B1")),
             Token::RParen,
         ];
         let mut parser = ASTCreator::new(tokens.into_iter());
@@ -678,11 +759,11 @@ mod tests {
                     op: Token::Not,
                     expr: Box::new(AST::BinaryOp {
                         op: Token::Equals,
-                        left: Box::new(AST::CellName("A1".to_string())),
+                        left: Box::new(AST::CellName(cell("A1"))),
                         right: Box::new(AST::Value(Value::Bool(true))),
                     }),
                 }),
-                right: Box::new(AST::CellName("B1".to_string())),
+                right: Box::new(AST::CellName(cell("B1"))),
             }
         );
     }
@@ -697,10 +778,7 @@ mod tests {
 
     #[test]
     fn test_invalid_comparison() {
-        let tokens = vec![
-            Token::CellName("A1".to_string()),
-            Token::GreaterThan,
-        ];
+        let tokens = vec![Token::CellName(cell("A1")), Token::GreaterThan];
         let mut parser = ASTCreator::new(tokens.into_iter());
         assert!(matches!(parser.parse(), Err(ASTCreateError::UnexpectedToken)));
     }
@@ -708,7 +786,7 @@ mod tests {
     #[test]
     fn test_mixed_arithmetic_logical() {
         let tokens = vec![
-            Token::CellName("A1".to_string()),
+            Token::CellName(cell("A1")),
             Token::Plus,
             Token::Number(5.0),
             Token::GreaterThan,
@@ -722,11 +800,68 @@ mod tests {
                 op: Token::GreaterThan,
                 left: Box::new(AST::BinaryOp {
                     op: Token::Plus,
-                    left: Box::new(AST::CellName("A1".to_string())),
+                    left: Box::new(AST::CellName(cell("A1"))),
                     right: Box::new(AST::Value(Value::Number(5.0))),
                 }),
                 right: Box::new(AST::Value(Value::Number(10.0))),
             }
         );
     }
+
+    fn parse_formula(formula: &str) -> AST {
+        let tokens = ExpressionTokenizer::new(formula.chars().collect())
+            .tokenize_expression()
+            .unwrap();
+        ASTCreator::new(tokens.into_iter()).parse().unwrap()
+    }
+
+    #[test]
+    fn test_to_formula_string() {
+        let canonical = |formula: &str| parse_formula(formula).to_formula_string();
+        assert_eq!(canonical("  A1+   sum( B2:B4 ,5 )"), "A1 + sum(B2:B4, 5)");
+        assert_eq!(canonical("((A1*2))-(B1-C1)"), "A1 * 2 - (B1 - C1)");
+        assert_eq!(canonical("(A1-B1)-C1"), "A1 - B1 - C1");
+        assert_eq!(
+            canonical("(A1+B1)*C1/(D1*E1)"),
+            "(A1 + B1) * C1 / (D1 * E1)"
+        );
+        assert_eq!(canonical("!(A1&&B1)||!C1"), "!(A1 && B1) || !C1");
+        assert_eq!(canonical("\"a  b\"==TRUE"), "\"a  b\" == TRUE");
+        assert_eq!(
+            canonical("if(A1>=0.50,len(\"x\"),2)"),
+            "if(A1 >= 0.5, len(\"x\"), 2)"
+        );
+    }
+
+    #[test]
+    fn test_to_formula_string_round_trip() {
+        let corpus = [
+            "A1",
+            "1.25 + 2 * 3",
+            "(1 + 2) * 3",
+            "1 - (2 - 3) - 4",
+            "10 / (2 / 5) / 2",
+            "A1 + B2 > C3 * 2 && !D4 || E5 != \"text\"",
+            "(A1 || B1) && (C1 || D1)",
+            "!(A1 > 2) == FALSE",
+            "A1 <= 3 == (B1 < 4)",
+            "sum(A1:B10, 5, max(C1:C3) - 1)",
+            "if(A1 == \"\", 0, A1 / (B1 + C1))",
+            "concat(\"a, b\", \"(c)\")",
+            "!!TRUE",
+            "ZZZ1000000 * 1000000000000000000000",
+            "B2 * vat",
+            "round(vat_rate * sum(A1:A3), 2)",
+        ];
+        for formula in corpus {
+            let ast = parse_formula(formula);
+            let printed = ast.to_formula_string();
+            assert_eq!(
+                parse_formula(&printed),
+                ast,
+                "{formula} printed as {printed}"
+            );
+            assert_eq!(printed, formula);
+        }
+    }
 }
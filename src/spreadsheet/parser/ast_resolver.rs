@@ -1,158 +1,614 @@
-use builtin_functions::get_func;
+use builtin_functions::{get_func, takes_arrays, COLUMN, INDIRECT, OFFSET, ROW};
 
-use crate::common_types::{ComputeError, Index, Token, Value, AST};
-mod builtin_functions;
+use crate::common_types::{
+    index_to_name, name_to_index, ComputeError, FunctionId, Index, Token, Value, AST,
+};
+use crate::spreadsheet::trace::{TraceStep, TraceValue};
+pub mod builtin_functions;
 pub trait VarContext {
     fn get_variable(&self, index: Index) -> Option<Result<Value, ComputeError>>;
+
+    /// Returns the value of a cell reached by `indirect` or `offset`, which the order of
+    /// computing cannot know to compute first.
+    fn get_dynamic_variable(&self, index: Index) -> Option<Result<Value, ComputeError>> {
+        self.get_variable(index)
+    }
+
+    /// Returns the cell being computed, if the expression is computed in one.
+    fn current_cell(&self) -> Option<Index> {
+        None
+    }
+
+    /// Returns the value a name in the expression stands for, if it is defined.
+    fn get_constant(&self, _name: &str) -> Option<Value> {
+        None
+    }
+}
+
+/// The variables of a context, computing the expression of the cell at `index`.
+pub struct InCell<'a> {
+    pub variables: &'a dyn VarContext,
+    pub index: Index,
+}
+
+impl VarContext for InCell<'_> {
+    fn get_variable(&self, index: Index) -> Option<Result<Value, ComputeError>> {
+        self.variables.get_variable(index)
+    }
+
+    fn get_dynamic_variable(&self, index: Index) -> Option<Result<Value, ComputeError>> {
+        self.variables.get_dynamic_variable(index)
+    }
+
+    fn current_cell(&self) -> Option<Index> {
+        Some(self.index)
+    }
+
+    fn get_constant(&self, name: &str) -> Option<Value> {
+        self.variables.get_constant(name)
+    }
 }
 
 pub struct ASTResolver {}
 
+/// What is left to do to resolve an expression, the next step last.
+enum Step<'a> {
+    /// Resolves the expression, pushing its value.
+    Resolve(&'a AST),
+    /// Applies the operator to the two values pushed last, resolved from the two expressions.
+    Binary(&'a Token, &'a AST, &'a AST),
+    Unary(&'a Token, &'a AST),
+    /// Passes the value pushed last, resolved from the expression, to the call gathered last.
+    Argument(&'a AST),
+    /// Passes the cells of the range to the call gathered last.
+    Range(Index, Index),
+    /// Pushes the values of the range as an array, for an operation over each of them.
+    Array(Index, Index),
+    /// Calls the function with the arguments gathered last.
+    Call(&'a FunctionId),
+}
+
+/// The arguments of a function call gathered so far.
+struct PendingCall {
+    arguments: Vec<Value>,
+    /// The arguments as they are traced, each cell of a range on its own
+    traced: Vec<TraceValue>,
+    takes_arrays: bool,
+}
+
 impl ASTResolver {
     pub fn resolve(ast: &AST, variables: &dyn VarContext) -> Result<Value, ComputeError> {
-        match ast {
-            AST::Value(value) => Ok(value.clone()),
-            AST::CellName(name) => match variables.get_variable(Self::get_cell_idx(name)) {
-                Some(value) => value,
-                None => Err(ComputeError::UnfindableReference(format!(
-                    "Could not find variable {name} with in context"
-                ))),
-            },
-            AST::BinaryOp { op, left, right } => {
-                let left_resolved = Self::resolve(left, variables)?;
-                let right_resolved = Self::resolve(right, variables)?;
-
-                match op {
-                    Token::Plus => {
-                        left_resolved
-                            .add(right_resolved)
-                            .ok_or(ComputeError::TypeError(
-                                "Addition requires two numeric values".to_string(),
-                            ))
+        Self::resolve_traced(ast, variables, None)
+    }
+
+    /// Resolves like `resolve`, recording every function call and operation made in `trace`
+    /// when given one. The tree is walked with a stack of its own rather than by recursion, so
+    /// formulas chaining thousands of operations do not overflow the stack of the thread.
+    pub fn resolve_traced(
+        ast: &AST,
+        variables: &dyn VarContext,
+        mut trace: Option<&mut Vec<TraceStep>>,
+    ) -> Result<Value, ComputeError> {
+        // A number that is not finite becomes an error after every operation and call, and in
+        // the result, where a literal too long to be a number would show, so none gets stored
+        let mut steps = vec![Step::Resolve(ast)];
+        let mut values: Vec<Value> = Vec::new();
+        let mut calls: Vec<PendingCall> = Vec::new();
+        // Any error ends the whole expression, as it would any operation or call it is part of
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Resolve(ast) => match ast {
+                    AST::Value(value) => values.push(value.clone()),
+                    AST::CellName(index) => values.push(Self::read_cell(*index, variables)?),
+                    AST::Name(name) => values.push(Self::read_constant(name, variables)?),
+                    AST::Range { from: _, to: _ } => {
+                        return Err(ComputeError::TypeError(
+                            "Ranges can only appear as function arguments".to_owned(),
+                        ))
                     }
-                    Token::Minus => {
-                        left_resolved
-                            .sub(right_resolved)
-                            .ok_or(ComputeError::TypeError(
-                                "Subtraction requires two numeric values".to_string(),
-                            ))
+                    AST::BinaryOp { op, left, right } => {
+                        steps.extend([
+                            Step::Binary(op, left, right),
+                            Self::operand(right),
+                            Self::operand(left),
+                        ]);
                     }
-                    Token::Division => {
-                        left_resolved
-                            .div(right_resolved)
-                            .ok_or(ComputeError::TypeError(
-                                "Division requires two numeric values".to_string(),
-                            ))
+                    AST::UnaryOp { op, expr } => {
+                        steps.extend([Step::Unary(op, expr), Step::Resolve(expr)]);
                     }
-                    Token::Multiply => {
-                        left_resolved
-                            .mult(right_resolved)
-                            .ok_or(ComputeError::TypeError(
-                                "Multiplication requires two numeric values".to_string(),
-                            ))
+                    AST::FunctionCall { name, arguments } if name.as_str() == INDIRECT => {
+                        values.push(Self::resolve_indirect(
+                            arguments,
+                            variables,
+                            trace.as_deref_mut(),
+                        )?);
                     }
-
-                    Token::Equals => Ok(Value::Bool(left_resolved.eq(&right_resolved))),
-                    Token::NotEquals => Ok(Value::Bool(left_resolved.ne(&right_resolved))),
-                    Token::GreaterThan => {
-                        left_resolved
-                            .greater_than(right_resolved)
-                            .ok_or(ComputeError::TypeError(
-                                "Greater than comparison requires two numeric values".to_string(),
-                            ))
+                    AST::FunctionCall { name, arguments } if name.as_str() == OFFSET => {
+                        values.push(Self::resolve_offset(
+                            arguments,
+                            variables,
+                            trace.as_deref_mut(),
+                        )?);
                     }
-                    Token::LessThan => {
-                        left_resolved
-                            .less_than(right_resolved)
-                            .ok_or(ComputeError::TypeError(
-                                "Less than comparison requires two numeric values".to_string(),
-                            ))
+                    AST::FunctionCall { name, arguments }
+                        if [ROW, COLUMN].contains(&name.as_str()) =>
+                    {
+                        values.push(Self::resolve_position(
+                            name.as_str(),
+                            arguments,
+                            variables,
+                            trace.as_deref_mut(),
+                        )?);
                     }
-                    Token::GreaterEquals => {
-                        left_resolved
-                            .greater_equals(right_resolved)
-                            .ok_or(ComputeError::TypeError(
-                                "Greater or equal comparison requires two numeric values".to_string(),
-                            ))
+                    AST::FunctionCall { name, arguments } => {
+                        calls.push(PendingCall {
+                            arguments: Vec::new(),
+                            traced: Vec::new(),
+                            takes_arrays: takes_arrays(name.as_str()),
+                        });
+                        steps.push(Step::Call(name));
+                        for argument in arguments.iter().rev() {
+                            match argument {
+                                AST::Range { from, to } => steps.push(Step::Range(*from, *to)),
+                                argument => steps
+                                    .extend([Step::Argument(argument), Step::Resolve(argument)]),
+                            }
+                        }
+                    }
+                },
+                Step::Binary(op, left, right) => {
+                    let right_resolved = values.pop().expect("Should never fail");
+                    let left_resolved = values.pop().expect("Should never fail");
+                    let operands = trace.is_some().then(|| {
+                        vec![
+                            Self::trace_value(left, &left_resolved),
+                            Self::trace_value(right, &right_resolved),
+                        ]
+                    });
+                    let result = Self::apply_binary(op, left_resolved, right_resolved)
+                        .and_then(Self::finite);
+                    if let (Some(trace), Some(operands)) = (trace.as_deref_mut(), operands) {
+                        trace.push(TraceStep::Operation {
+                            op: op.clone(),
+                            operands,
+                            result: result.clone(),
+                        });
                     }
-                    Token::LessEquals => {
-                        left_resolved
-                            .less_equals(right_resolved)
-                            .ok_or(ComputeError::TypeError(
-                                "Less or equal comparison requires two numeric values".to_string(),
-                            ))
+                    values.push(result?);
+                }
+                Step::Unary(op, expr) => {
+                    let resolved = values.pop().expect("Should never fail");
+                    let operand = trace.is_some().then(|| Self::trace_value(expr, &resolved));
+                    let result = Self::apply_not(resolved);
+                    if let (Some(trace), Some(operand)) = (trace.as_deref_mut(), operand) {
+                        trace.push(TraceStep::Operation {
+                            op: op.clone(),
+                            operands: vec![operand],
+                            result: result.clone(),
+                        });
+                    }
+                    values.push(result?);
+                }
+                Step::Argument(argument) => {
+                    let var = values.pop().expect("Should never fail");
+                    let call = calls.last_mut().expect("Should never fail");
+                    if trace.is_some() {
+                        call.traced.push(Self::trace_value(argument, &var));
+                    }
+                    match var {
+                        Value::Array(rows) if !call.takes_arrays => {
+                            call.arguments.extend(rows.into_iter().flatten())
+                        }
+                        var => call.arguments.push(var),
+                    }
+                }
+                Step::Range(from, to) => {
+                    let call = calls.last_mut().expect("Should never fail");
+                    let traced = trace.is_some().then_some(&mut call.traced);
+                    if call.takes_arrays {
+                        call.arguments
+                            .push(Self::resolve_array(from, to, variables, traced)?);
+                    } else {
+                        Self::resolve_range(from, to, variables, &mut call.arguments, traced)?;
                     }
-                    Token::And => left_resolved
-                        .and(right_resolved)
-                        .ok_or(ComputeError::TypeError(
-                            "Logical AND requires two boolean values".to_string(),
-                        )),
-                    Token::Or => left_resolved
-                        .or(right_resolved)
-                        .ok_or(ComputeError::TypeError(
-                            "Logical OR requires two boolean values".to_string(),
-                        )),
-                    other => panic!("{other:?} is not a binary operator"),
                 }
+                Step::Array(from, to) => {
+                    values.push(Self::resolve_array(from, to, variables, None)?);
+                }
+                Step::Call(name) => {
+                    let call = calls.pop().expect("Should never fail");
+                    let result = Self::apply_function(name, call.arguments).and_then(Self::finite);
+                    if let Some(trace) = trace.as_deref_mut() {
+                        trace.push(TraceStep::Call {
+                            name: name.to_string(),
+                            arguments: call.traced,
+                            result: result.clone(),
+                        });
+                    }
+                    values.push(result?);
+                }
+            }
+        }
+
+        Self::finite(values.pop().expect("Should never fail"))
+    }
+
+    /// Turns a number that is not finite, alone or in an array, into
+    /// `ComputeError::NotANumber`.
+    fn finite(value: Value) -> Result<Value, ComputeError> {
+        let is_finite = |value: &Value| !matches!(value, Value::Number(num) if !num.is_finite());
+        let finite = match &value {
+            Value::Array(rows) => rows.iter().flatten().all(is_finite),
+            value => is_finite(value),
+        };
+        if finite {
+            Ok(value)
+        } else {
+            Err(ComputeError::NotANumber)
+        }
+    }
+
+    /// Returns the step resolving an operand of a binary operation, a range being one as the
+    /// array of its values.
+    fn operand(ast: &AST) -> Step<'_> {
+        match ast {
+            AST::Range { from, to } => Step::Array(*from, *to),
+            ast => Step::Resolve(ast),
+        }
+    }
+
+    fn apply_binary(
+        op: &Token,
+        left_resolved: Value,
+        right_resolved: Value,
+    ) -> Result<Value, ComputeError> {
+        // Links are operated on as the text they show
+        let (left_resolved, right_resolved) =
+            (left_resolved.into_label(), right_resolved.into_label());
+        if matches!(left_resolved, Value::Array(_)) || matches!(right_resolved, Value::Array(_)) {
+            return Self::apply_elementwise(op, left_resolved, right_resolved);
+        }
+        match op {
+            Token::Plus => left_resolved
+                .add(right_resolved)
+                .ok_or(ComputeError::TypeError(
+                    "Addition requires two numeric values".to_string(),
+                )),
+            Token::Minus => left_resolved
+                .sub(right_resolved)
+                .ok_or(ComputeError::TypeError(
+                    "Subtraction requires two numeric values".to_string(),
+                )),
+            Token::Division
+                if matches!(left_resolved, Value::Number(_))
+                    && right_resolved == Value::Number(0.0) =>
+            {
+                Err(ComputeError::DivisionByZero)
+            }
+            Token::Division => left_resolved
+                .div(right_resolved)
+                .ok_or(ComputeError::TypeError(
+                    "Division requires two numeric values".to_string(),
+                )),
+            Token::Multiply => left_resolved
+                .mult(right_resolved)
+                .ok_or(ComputeError::TypeError(
+                    "Multiplication requires two numeric values".to_string(),
+                )),
+
+            Token::Equals => Ok(Value::Bool(left_resolved.eq(&right_resolved))),
+            Token::NotEquals => Ok(Value::Bool(left_resolved.ne(&right_resolved))),
+            Token::GreaterThan => {
+                left_resolved
+                    .greater_than(right_resolved)
+                    .ok_or(ComputeError::TypeError(
+                        "Greater than comparison requires two numeric values".to_string(),
+                    ))
+            }
+            Token::LessThan => {
+                left_resolved
+                    .less_than(right_resolved)
+                    .ok_or(ComputeError::TypeError(
+                        "Less than comparison requires two numeric values".to_string(),
+                    ))
             }
-            AST::Range { from: _, to: _ } => {
-                Err(ComputeError::TypeError("Ranges can only appear as function arguments".to_owned()))
+            Token::GreaterEquals => {
+                left_resolved
+                    .greater_equals(right_resolved)
+                    .ok_or(ComputeError::TypeError(
+                        "Greater or equal comparison requires two numeric values".to_string(),
+                    ))
             }
+            Token::LessEquals => {
+                left_resolved
+                    .less_equals(right_resolved)
+                    .ok_or(ComputeError::TypeError(
+                        "Less or equal comparison requires two numeric values".to_string(),
+                    ))
+            }
+            Token::And => left_resolved
+                .and(right_resolved)
+                .ok_or(ComputeError::TypeError(
+                    "Logical AND requires two boolean values".to_string(),
+                )),
+            Token::Or => left_resolved
+                .or(right_resolved)
+                .ok_or(ComputeError::TypeError(
+                    "Logical OR requires two boolean values".to_string(),
+                )),
+            other => panic!("{other:?} is not a binary operator"),
+        }
+    }
 
-            AST::FunctionCall { name, arguments } => {
-                let mut resolved_args = Vec::new();
-                for arg in arguments {
-                    match arg {
-                        AST::Range { from, to } => {
-                            for index in Self::range_to_indeces(from, to) {
-                                if let Some(var) = variables.get_variable(index) {
-                                    resolved_args.push(var?)
-                                }
-                            }
-                        }
-                        ast => resolved_args.push(Self::resolve(ast, variables)?),
-                    }
+    /// Applies the operator between each value of an array and a single value, or between the
+    /// values at the same positions of two arrays of the same shape, giving an array.
+    fn apply_elementwise(op: &Token, left: Value, right: Value) -> Result<Value, ComputeError> {
+        let map = |rows: Vec<Vec<Value>>, apply: &dyn Fn(Value) -> Result<Value, ComputeError>| {
+            rows.into_iter()
+                .map(|row| row.into_iter().map(apply).collect())
+                .collect::<Result<_, _>>()
+        };
+        let rows = match (left, right) {
+            (Value::Array(left), Value::Array(right)) => {
+                let shape = |rows: &[Vec<Value>]| rows.iter().map(Vec::len).collect::<Vec<_>>();
+                if shape(&left) != shape(&right) {
+                    return Err(ComputeError::TypeError(format!(
+                        "Element-wise {op} requires arrays of the same shape"
+                    )));
                 }
+                left.into_iter()
+                    .zip(right)
+                    .map(|(left, right)| {
+                        left.into_iter()
+                            .zip(right)
+                            .map(|(left, right)| Self::apply_binary(op, left, right))
+                            .collect()
+                    })
+                    .collect::<Result<_, _>>()?
+            }
+            (Value::Array(left), right) => map(left, &|element| {
+                Self::apply_binary(op, element, right.clone())
+            })?,
+            (left, Value::Array(right)) => map(right, &|element| {
+                Self::apply_binary(op, left.clone(), element)
+            })?,
+            _ => unreachable!("Either operand is an array"),
+        };
+        Ok(Value::Array(rows))
+    }
+
+    fn apply_not(resolved: Value) -> Result<Value, ComputeError> {
+        if let Value::Bool(boolean) = resolved {
+            Ok(Value::Bool(!boolean))
+        } else {
+            Err(ComputeError::TypeError(
+                "Not(!) operator can only work on boolean expressions".to_owned(),
+            ))
+        }
+    }
+
+    fn apply_function(name: &FunctionId, arguments: Vec<Value>) -> Result<Value, ComputeError> {
+        match get_func(name.as_str()) {
+            Some(func) => func(arguments),
+            None => Err(ComputeError::UnknownFunction(name.to_string())),
+        }
+    }
 
-                if let Some(func) = get_func(name) {
-                    func(resolved_args)
-                } else {
-                    Err(ComputeError::UnknownFunction(name.to_owned()))
+    /// Resolves `row()` or `column()`, the position of the cell computed.
+    fn resolve_position(
+        name: &str,
+        arguments: &[AST],
+        variables: &dyn VarContext,
+        trace: Option<&mut Vec<TraceStep>>,
+    ) -> Result<Value, ComputeError> {
+        let result = match (arguments.is_empty(), variables.current_cell()) {
+            (false, _) => Err(ComputeError::InvalidArgument(format!(
+                "{name} expects no arguments"
+            ))),
+            (true, None) => Err(ComputeError::InvalidArgument(format!(
+                "{name} can only be computed in a cell"
+            ))),
+            (true, Some(index)) if name == ROW => Ok(Value::Number((index.y + 1) as f64)),
+            (true, Some(index)) => Ok(Value::Number((index.x + 1) as f64)),
+        };
+        Self::trace_call(trace, name, Vec::new(), &result);
+        result
+    }
+
+    /// Reads the cells of the range from `start` to `end` into `arguments`, each on its own
+    /// and leaving out empty ones.
+    fn resolve_range(
+        start: Index,
+        end: Index,
+        variables: &dyn VarContext,
+        arguments: &mut Vec<Value>,
+        mut traced: Option<&mut Vec<TraceValue>>,
+    ) -> Result<(), ComputeError> {
+        for index in Self::range_to_indeces(start, end) {
+            if let Some(var) = variables.get_variable(index) {
+                let var = var?;
+                if let Some(traced) = traced.as_deref_mut() {
+                    traced.push(TraceValue {
+                        reference: Some(index_to_name(index)),
+                        value: Ok(var.clone()),
+                    });
                 }
+                arguments.push(var)
             }
-            AST::UnaryOp { op, expr } => {
-                matches!(op, Token::Not);
-                if let Value::Bool(boolean) = Self::resolve(expr, variables)? {
-                    Ok(Value::Bool(!boolean))
-                } else {
-                    Err(ComputeError::TypeError("Not(!) operator can only work on boolean expressions".to_owned()))
+        }
+        Ok(())
+    }
+
+    /// Reads the cells of the range from `start` to `end` into an array, empty cells as empty
+    /// text.
+    fn resolve_array(
+        start: Index,
+        end: Index,
+        variables: &dyn VarContext,
+        mut traced: Option<&mut Vec<TraceValue>>,
+    ) -> Result<Value, ComputeError> {
+        let mut rows = Vec::new();
+        for y in start.y..=end.y {
+            let mut row = Vec::new();
+            for x in start.x..=end.x {
+                let index = Index { x, y };
+                let var = match variables.get_variable(index) {
+                    Some(var) => var?,
+                    None => Value::Text(String::new()),
+                };
+                if let Some(traced) = traced.as_deref_mut() {
+                    traced.push(TraceValue {
+                        reference: Some(index_to_name(index)),
+                        value: Ok(var.clone()),
+                    });
                 }
+                row.push(var);
             }
+            rows.push(row);
         }
+        Ok(Value::Array(rows))
+    }
+
+    fn read_cell(index: Index, variables: &dyn VarContext) -> Result<Value, ComputeError> {
+        Self::found(index, variables.get_variable(index))
+    }
+
+    /// Reads a cell reached by `indirect` or `offset`, see `VarContext::get_dynamic_variable`.
+    fn read_dynamic_cell(index: Index, variables: &dyn VarContext) -> Result<Value, ComputeError> {
+        Self::found(index, variables.get_dynamic_variable(index))
     }
 
-    pub fn get_cell_idx(cell_name: &str) -> Index {
-        let mut x: usize = 0;
-        let mut y = 0;
+    fn found(
+        index: Index,
+        value: Option<Result<Value, ComputeError>>,
+    ) -> Result<Value, ComputeError> {
+        match value {
+            Some(value) => value,
+            None => Err(ComputeError::UnfindableReference(format!(
+                "Could not find variable {} with in context",
+                index_to_name(index)
+            ))),
+        }
+    }
+
+    /// Reads the value `name` stands for. Only constants are known by name so far.
+    fn read_constant(name: &str, variables: &dyn VarContext) -> Result<Value, ComputeError> {
+        variables
+            .get_constant(name)
+            .ok_or_else(|| ComputeError::UnfindableReference(format!("Unknown name {name}")))
+    }
+
+    /// Reads the cell named by the text `arguments` resolve to, like `indirect("B" + A1)`.
+    fn resolve_indirect(
+        arguments: &[AST],
+        variables: &dyn VarContext,
+        mut trace: Option<&mut Vec<TraceStep>>,
+    ) -> Result<Value, ComputeError> {
+        let [argument] = arguments else {
+            return Err(ComputeError::InvalidArgument(
+                "indirect expects exactly one argument".to_string(),
+            ));
+        };
+        let resolved = Self::resolve_traced(argument, variables, trace.as_deref_mut())?;
+        let result = match &resolved {
+            Value::Text(name) => match name_to_index(name) {
+                Ok(index) => Self::read_dynamic_cell(index, variables),
+                Err(_) => Err(ComputeError::InvalidArgument(format!(
+                    "indirect expects the name of a cell, not \"{name}\""
+                ))),
+            },
+            _ => Err(ComputeError::InvalidArgument(
+                "indirect expects a string argument".to_string(),
+            )),
+        };
+        let arguments = vec![Self::trace_value(argument, &resolved)];
+        Self::trace_call(trace, INDIRECT, arguments, &result);
+        result
+    }
 
-        for (i, c) in cell_name.chars().enumerate() {
-            if c.is_ascii_digit() {
-                // Parse row number
-                y = cell_name[i..].parse::<usize>().expect("Invalid row number");
-                break;
-            } else {
-                // Parse column letters
-                x = x * 26 + (c as usize - 'A' as usize + 1);
+    /// Reads the cell a number of rows and columns away from a reference, like
+    /// `offset(A1, 2, 1)` reading B3.
+    fn resolve_offset(
+        arguments: &[AST],
+        variables: &dyn VarContext,
+        mut trace: Option<&mut Vec<TraceStep>>,
+    ) -> Result<Value, ComputeError> {
+        let [AST::CellName(from), rows, columns] = arguments else {
+            return Err(ComputeError::InvalidArgument(
+                "offset expects a cell reference and two numbers".to_string(),
+            ));
+        };
+        let mut distance = |ast| -> Result<(isize, Value), ComputeError> {
+            match Self::resolve_traced(ast, variables, trace.as_deref_mut())? {
+                Value::Number(num) if num.fract() == 0.0 && num.abs() < isize::MAX as f64 => {
+                    Ok((num as isize, Value::Number(num)))
+                }
+                _ => Err(ComputeError::InvalidArgument(
+                    "offset expects whole numbers of rows and columns".to_string(),
+                )),
             }
+        };
+        let (dy, rows_value) = distance(rows)?;
+        let (dx, columns_value) = distance(columns)?;
+
+        let from = *from;
+        let name = index_to_name(from);
+        let target = from
+            .x
+            .checked_add_signed(dx)
+            .zip(from.y.checked_add_signed(dy));
+        let result = match target {
+            Some((x, y)) => Self::read_dynamic_cell(Index { x, y }, variables),
+            None => Err(ComputeError::UnfindableReference(format!(
+                "offset({name}, {dy}, {dx}) is outside of the sheet"
+            ))),
+        };
+        if trace.is_some() {
+            let arguments = vec![
+                TraceValue {
+                    reference: Some(name),
+                    value: Self::read_cell(from, variables),
+                },
+                Self::trace_value(rows, &rows_value),
+                Self::trace_value(columns, &columns_value),
+            ];
+            Self::trace_call(trace, OFFSET, arguments, &result);
         }
+        result
+    }
 
-        // Adjust for 0-based indexing
-        Index { x: x - 1, y: y - 1 }
+    fn trace_call(
+        trace: Option<&mut Vec<TraceStep>>,
+        name: &str,
+        arguments: Vec<TraceValue>,
+        result: &Result<Value, ComputeError>,
+    ) {
+        if let Some(trace) = trace {
+            trace.push(TraceStep::Call {
+                name: name.to_string(),
+                arguments,
+                result: result.clone(),
+            });
+        }
     }
 
-    fn range_to_indeces(from: &str, to: &str) -> Vec<Index> {
-        let start = Self::get_cell_idx(from);
-        let end = Self::get_cell_idx(to);
+    /// Returns how a resolved operand or argument shows in a trace, naming the cell it was read
+    /// from if any.
+    fn trace_value(ast: &AST, value: &Value) -> TraceValue {
+        TraceValue {
+            reference: match ast {
+                AST::CellName(index) => Some(index_to_name(*index)),
+                AST::Name(name) => Some(name.to_string()),
+                AST::Range { from, to } => {
+                    Some(format!("{}:{}", index_to_name(*from), index_to_name(*to)))
+                }
+                _ => None,
+            },
+            value: Ok(value.clone()),
+        }
+    }
+
+    fn range_to_indeces(start: Index, end: Index) -> Vec<Index> {
         let mut indices = Vec::new();
         for x in start.x..=end.x {
             for y in start.y..=end.y {
@@ -167,6 +623,8 @@ impl ASTResolver {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::common_types::cell_addr::cell;
+    use crate::common_types::FunctionId;
     use std::collections::HashMap;
 
     struct MockVarContext {
@@ -200,7 +658,7 @@ mod tests {
         vars.insert(Index { x: 0, y: 0 }, Value::Number(10.0));
 
         let variables = MockVarContext::new(vars);
-        let ast = AST::CellName("A1".to_string());
+        let ast = AST::CellName(cell("A1"));
 
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
         assert_eq!(result, Value::Number(10.0));
@@ -215,8 +673,8 @@ mod tests {
         let variables = MockVarContext::new(vars);
         let ast = AST::BinaryOp {
             op: Token::Plus,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("B1".to_string())),
+            left: Box::new(AST::CellName(cell("A1"))),
+            right: Box::new(AST::CellName(cell("B1"))),
         };
 
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
@@ -232,8 +690,8 @@ mod tests {
         let variables = MockVarContext::new(vars);
         let ast = AST::BinaryOp {
             op: Token::Minus,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("B1".to_string())),
+            left: Box::new(AST::CellName(cell("A1"))),
+            right: Box::new(AST::CellName(cell("B1"))),
         };
 
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
@@ -249,8 +707,8 @@ mod tests {
         let variables = MockVarContext::new(vars);
         let ast = AST::BinaryOp {
             op: Token::Multiply,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("B1".to_string())),
+            left: Box::new(AST::CellName(cell("A1"))),
+            right: Box::new(AST::CellName(cell("B1"))),
         };
 
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
@@ -266,8 +724,8 @@ mod tests {
         let variables = MockVarContext::new(vars);
         let ast = AST::BinaryOp {
             op: Token::Division,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("B1".to_string())),
+            left: Box::new(AST::CellName(cell("A1"))),
+            right: Box::new(AST::CellName(cell("B1"))),
         };
 
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
@@ -278,7 +736,7 @@ mod tests {
     #[should_panic]
     fn test_resolve_missing_cellname() {
         let variables = MockVarContext::new(HashMap::new());
-        let ast = AST::CellName("A1".to_string());
+        let ast = AST::CellName(cell("A1"));
 
         // This should panic because "A1" is not in the context
         ASTResolver::resolve(&ast, &variables).unwrap();
@@ -296,10 +754,10 @@ mod tests {
             op: Token::Plus,
             left: Box::new(AST::BinaryOp {
                 op: Token::Multiply,
-                left: Box::new(AST::CellName("A1".to_string())),
-                right: Box::new(AST::CellName("B1".to_string())),
+                left: Box::new(AST::CellName(cell("A1"))),
+                right: Box::new(AST::CellName(cell("B1"))),
             }),
-            right: Box::new(AST::CellName("C1".to_string())),
+            right: Box::new(AST::CellName(cell("C1"))),
         };
 
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
@@ -318,18 +776,17 @@ mod tests {
             op: Token::Minus,
             left: Box::new(AST::BinaryOp {
                 op: Token::Division,
-                left: Box::new(AST::CellName("A1".to_string())),
-                right: Box::new(AST::CellName("B1".to_string())),
+                left: Box::new(AST::CellName(cell("A1"))),
+                right: Box::new(AST::CellName(cell("B1"))),
             }),
-            right: Box::new(AST::CellName("C1".to_string())),
+            right: Box::new(AST::CellName(cell("C1"))),
         };
 
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
         assert_eq!(result, Value::Number(3.0));
     }
 
-    #[cfg(test)]
-    mod tests {
+    mod function_tests {
         use super::*;
         use crate::common_types::{Index, Token};
         use std::collections::HashMap;
@@ -342,11 +799,8 @@ mod tests {
             let variables = MockVarContext::new(vars);
 
             let ast = AST::FunctionCall {
-                name: "sum".to_string(),
-                arguments: vec![
-                    AST::CellName("A1".to_string()),
-                    AST::CellName("B1".to_string()),
-                ],
+                name: FunctionId::new("sum"),
+                arguments: vec![AST::CellName(cell("A1")), AST::CellName(cell("B1"))],
             };
 
             let result = ASTResolver::resolve(&ast, &variables).unwrap();
@@ -362,10 +816,10 @@ mod tests {
             let variables = MockVarContext::new(vars);
 
             let ast = AST::FunctionCall {
-                name: "sum".to_string(),
+                name: FunctionId::new("sum"),
                 arguments: vec![AST::Range {
-                    from: "A1".to_string(),
-                    to: "A3".to_string(),
+                    from: cell("A1"),
+                    to: cell("A3"),
                 }],
             };
 
@@ -381,11 +835,11 @@ mod tests {
             let variables = MockVarContext::new(vars);
 
             let ast = AST::FunctionCall {
-                name: "sum".to_string(),
+                name: FunctionId::new("sum"),
                 arguments: vec![
                     AST::Range {
-                        from: "A1".to_string(),
-                        to: "A2".to_string(),
+                        from: cell("A1"),
+                        to: cell("A2"),
                     },
                     AST::Value(Value::Number(5.0)),
                 ],
@@ -402,11 +856,11 @@ mod tests {
             let variables = MockVarContext::new(vars);
 
             let ast = AST::FunctionCall {
-                name: "sum".to_string(),
+                name: FunctionId::new("sum"),
                 arguments: vec![
                     AST::BinaryOp {
                         op: Token::Plus,
-                        left: Box::new(AST::CellName("A1".to_string())),
+                        left: Box::new(AST::CellName(cell("A1"))),
                         right: Box::new(AST::Value(Value::Number(5.0))),
                     },
                     AST::Value(Value::Number(15.0)),
@@ -425,14 +879,11 @@ mod tests {
             let variables = MockVarContext::new(vars);
 
             let ast = AST::FunctionCall {
-                name: "sum".to_string(),
+                name: FunctionId::new("sum"),
                 arguments: vec![
                     AST::FunctionCall {
-                        name: "sum".to_string(),
-                        arguments: vec![
-                            AST::CellName("A1".to_string()),
-                            AST::CellName("B1".to_string()),
-                        ],
+                        name: FunctionId::new("sum"),
+                        arguments: vec![AST::CellName(cell("A1")), AST::CellName(cell("B1"))],
                     },
                     AST::Value(Value::Number(5.0)),
                 ],
@@ -447,7 +898,7 @@ mod tests {
             let variables = MockVarContext::new(HashMap::new());
 
             let ast = AST::FunctionCall {
-                name: "nonexistent".to_string(),
+                name: FunctionId::new("nonexistent"),
                 arguments: vec![AST::Value(Value::Number(10.0))],
             };
 
@@ -462,8 +913,8 @@ mod tests {
             let variables = MockVarContext::new(vars);
 
             let ast = AST::FunctionCall {
-                name: "sum".to_string(),
-                arguments: vec![AST::CellName("A1".to_string())],
+                name: FunctionId::new("sum"),
+                arguments: vec![AST::CellName(cell("A1"))],
             };
 
             let result = ASTResolver::resolve(&ast, &variables);
@@ -475,10 +926,10 @@ mod tests {
             let variables = MockVarContext::new(HashMap::new());
 
             let ast = AST::FunctionCall {
-                name: "sum".to_string(),
+                name: FunctionId::new("sum"),
                 arguments: vec![AST::Range {
-                    from: "A1".to_string(),
-                    to: "A2".to_string(),
+                    from: cell("A1"),
+                    to: cell("A2"),
                 }],
             };
 
@@ -516,8 +967,8 @@ mod tests {
         // Test true && false
         let ast = AST::BinaryOp {
             op: Token::And,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("A2".to_string())),
+            left: Box::new(AST::CellName(cell("A1"))),
+            right: Box::new(AST::CellName(cell("A2"))),
         };
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
         assert_eq!(result, Value::Bool(false));
@@ -533,8 +984,8 @@ mod tests {
         // Test true || false
         let ast = AST::BinaryOp {
             op: Token::Or,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("A2".to_string())),
+            left: Box::new(AST::CellName(cell("A1"))),
+            right: Box::new(AST::CellName(cell("A2"))),
         };
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
         assert_eq!(result, Value::Bool(true));
@@ -550,8 +1001,8 @@ mod tests {
         // Test greater than
         let ast = AST::BinaryOp {
             op: Token::GreaterThan,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("A2".to_string())),
+            left: Box::new(AST::CellName(cell("A1"))),
+            right: Box::new(AST::CellName(cell("A2"))),
         };
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
         assert_eq!(result, Value::Bool(false));
@@ -559,8 +1010,8 @@ mod tests {
         // Test less than
         let ast = AST::BinaryOp {
             op: Token::LessThan,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("A2".to_string())),
+            left: Box::new(AST::CellName(cell("A1"))),
+            right: Box::new(AST::CellName(cell("A2"))),
         };
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
         assert_eq!(result, Value::Bool(true));
@@ -577,8 +1028,8 @@ mod tests {
         // Test number equality
         let ast = AST::BinaryOp {
             op: Token::Equals,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("A2".to_string())),
+            left: Box::new(AST::CellName(cell("A1"))),
+            right: Box::new(AST::CellName(cell("A2"))),
         };
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
         assert_eq!(result, Value::Bool(true));
@@ -586,8 +1037,8 @@ mod tests {
         // Test different types equality
         let ast = AST::BinaryOp {
             op: Token::Equals,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("A3".to_string())),
+            left: Box::new(AST::CellName(cell("A1"))),
+            right: Box::new(AST::CellName(cell("A3"))),
         };
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
         assert_eq!(result, Value::Bool(false));
@@ -604,11 +1055,11 @@ mod tests {
         // Test (A1 && (A2 > A3))
         let ast = AST::BinaryOp {
             op: Token::And,
-            left: Box::new(AST::CellName("A1".to_string())),
+            left: Box::new(AST::CellName(cell("A1"))),
             right: Box::new(AST::BinaryOp {
                 op: Token::GreaterThan,
-                left: Box::new(AST::CellName("A2".to_string())),
-                right: Box::new(AST::CellName("A3".to_string())),
+                left: Box::new(AST::CellName(cell("A2"))),
+                right: Box::new(AST::CellName(cell("A3"))),
             }),
         };
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
@@ -627,8 +1078,8 @@ mod tests {
 
         let ast = AST::BinaryOp {
             op: Token::And,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("A2".to_string())),
+            left: Box::new(AST::CellName(cell("A1"))),
+            right: Box::new(AST::CellName(cell("A2"))),
         };
         let result = ASTResolver::resolve(&ast, &variables);
         assert!(matches!(result, Err(ComputeError::TypeError(_))));
@@ -642,7 +1093,7 @@ mod tests {
 
         let ast = AST::UnaryOp {
             op: Token::Not,
-            expr: Box::new(AST::CellName("A1".to_string())),
+            expr: Box::new(AST::CellName(cell("A1"))),
         };
         let result = ASTResolver::resolve(&ast, &variables);
         assert!(matches!(result, Err(ComputeError::TypeError(_))));
@@ -657,10 +1108,176 @@ mod tests {
 
         let ast = AST::BinaryOp {
             op: Token::GreaterThan,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("A2".to_string())),
+            left: Box::new(AST::CellName(cell("A1"))),
+            right: Box::new(AST::CellName(cell("A2"))),
         };
         let result = ASTResolver::resolve(&ast, &variables);
         assert!(matches!(result, Err(ComputeError::TypeError(_))));
     }
+
+    /// The cells of a 3 by 3 block holding a value of every kind, an error and empty cells.
+    struct Block;
+
+    impl VarContext for Block {
+        fn get_variable(&self, index: Index) -> Option<Result<Value, ComputeError>> {
+            let value = match (index.x, index.y) {
+                (0, 0) => Value::Number(2.0),
+                (1, 0) => Value::Number(-1.5),
+                (2, 0) => Value::Text("a".to_string()),
+                (0, 1) => Value::Bool(true),
+                (1, 1) => return Some(Err(ComputeError::Cycle)),
+                (0, 2) => Value::Number(0.0),
+                _ => return None,
+            };
+            Some(Ok(value))
+        }
+    }
+
+    /// A xorshift generator, enough to pick expressions at random reproducibly.
+    struct Random(u64);
+
+    impl Random {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+
+        fn cell(&mut self) -> Index {
+            Index {
+                x: self.below(3),
+                y: self.below(3),
+            }
+        }
+
+        fn ast(&mut self, depth: usize) -> AST {
+            if depth == 0 || self.below(4) == 0 {
+                return match self.below(5) {
+                    0 => AST::Value(Value::Number(self.below(4) as f64)),
+                    1 => AST::Value(Value::Bool(self.below(2) == 0)),
+                    2 => AST::Value(Value::Text(["", "a", "1"][self.below(3)].to_string())),
+                    _ => AST::CellName(self.cell()),
+                };
+            }
+
+            match self.below(5) {
+                0 | 1 => {
+                    let ops = [
+                        Token::Plus,
+                        Token::Minus,
+                        Token::Multiply,
+                        Token::Division,
+                        Token::Equals,
+                        Token::NotEquals,
+                        Token::GreaterThan,
+                        Token::LessEquals,
+                        Token::And,
+                        Token::Or,
+                    ];
+                    // Ranges are operands now and then, only over a few cells
+                    let operand = |random: &mut Self| match random.below(6) {
+                        0 => AST::Range {
+                            from: random.cell(),
+                            to: Index { x: 2, y: 2 },
+                        },
+                        _ => random.ast(depth - 1),
+                    };
+                    AST::BinaryOp {
+                        op: ops[self.below(ops.len())].clone(),
+                        left: Box::new(operand(self)),
+                        right: Box::new(operand(self)),
+                    }
+                }
+                2 => AST::UnaryOp {
+                    op: Token::Not,
+                    expr: Box::new(self.ast(depth - 1)),
+                },
+                _ => {
+                    let names = [
+                        "sum",
+                        "max",
+                        "count",
+                        "concat",
+                        "if",
+                        "transpose",
+                        "sumproduct",
+                        "nope",
+                    ];
+                    let name = FunctionId::new(names[self.below(names.len())]);
+                    let arguments = (0..self.below(4))
+                        .map(|_| match self.below(3) {
+                            0 => AST::Range {
+                                from: self.cell(),
+                                to: Index { x: 2, y: 2 },
+                            },
+                            _ => self.ast(depth - 1),
+                        })
+                        .collect();
+                    AST::FunctionCall { name, arguments }
+                }
+            }
+        }
+    }
+
+    /// Resolves by recursion over the tree, as a reference for walking it with a stack.
+    fn resolve_recursive(ast: &AST, variables: &dyn VarContext) -> Result<Value, ComputeError> {
+        match ast {
+            AST::BinaryOp { op, left, right } => {
+                let operand = |ast: &AST| match ast {
+                    AST::Range { from, to } => {
+                        ASTResolver::resolve_array(*from, *to, variables, None)
+                    }
+                    ast => resolve_recursive(ast, variables),
+                };
+                let left = operand(left)?;
+                let right = operand(right)?;
+                ASTResolver::apply_binary(op, left, right)
+            }
+            AST::UnaryOp { expr, .. } => {
+                ASTResolver::apply_not(resolve_recursive(expr, variables)?)
+            }
+            AST::FunctionCall { name, arguments } => {
+                let takes_arrays = takes_arrays(name.as_str());
+                let mut resolved = Vec::new();
+                for argument in arguments {
+                    match argument {
+                        AST::Range { from, to } if takes_arrays => {
+                            resolved.push(ASTResolver::resolve_array(*from, *to, variables, None)?)
+                        }
+                        AST::Range { from, to } => {
+                            ASTResolver::resolve_range(*from, *to, variables, &mut resolved, None)?
+                        }
+                        argument => match resolve_recursive(argument, variables)? {
+                            Value::Array(rows) if !takes_arrays => {
+                                resolved.extend(rows.into_iter().flatten())
+                            }
+                            value => resolved.push(value),
+                        },
+                    }
+                }
+                ASTResolver::apply_function(name, resolved)
+            }
+            leaf => ASTResolver::resolve(leaf, variables),
+        }
+    }
+
+    #[test]
+    fn test_matches_recursive_resolution() {
+        let mut random = Random(0x2545_f491_4f6c_dd1d);
+        let mut errors = 0;
+        for _ in 0..5000 {
+            let ast = random.ast(4);
+            let resolved = ASTResolver::resolve(&ast, &Block);
+            errors += usize::from(resolved.is_err());
+            assert_eq!(
+                format!("{resolved:?}"),
+                format!("{:?}", resolve_recursive(&ast, &Block)),
+                "{}",
+                ast.to_formula_string()
+            );
+        }
+        // Both values and errors were compared
+        assert!((500..4500).contains(&errors), "{errors}");
+    }
 }
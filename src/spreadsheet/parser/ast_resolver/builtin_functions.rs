@@ -1,6 +1,146 @@
 use crate::common_types::{ComputeError, Value};
+use crate::spreadsheet::parser::CellParser;
 
-pub fn get_func(name: &str) -> Option<fn(Vec<Value>) -> Result<Value, ComputeError>> {
+pub type BuiltinFunction = fn(Vec<Value>) -> Result<Value, ComputeError>;
+
+/// A builtin function as presented to users, with the arguments it takes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FunctionInfo {
+    pub name: &'static str,
+    pub signature: &'static str,
+}
+
+/// The function reading the cell named by its argument. It and the functions below are called
+/// by the resolver itself, as they read cells or need to know which one is computed.
+pub const INDIRECT: &str = "indirect";
+/// The function reading the cell a number of rows and columns away from a reference.
+pub const OFFSET: &str = "offset";
+/// The functions giving the row and the column of the cell they are computed in.
+pub const ROW: &str = "row";
+pub const COLUMN: &str = "column";
+
+/// Every function formulas can call, those `get_func` knows and those the resolver calls
+/// itself, sorted by name.
+pub const CATALOG: &[FunctionInfo] = &[
+    FunctionInfo {
+        name: "average",
+        signature: "average(number, ...)",
+    },
+    FunctionInfo {
+        name: "column",
+        signature: "column()",
+    },
+    FunctionInfo {
+        name: "concat",
+        signature: "concat(value, ...)",
+    },
+    FunctionInfo {
+        name: "correl",
+        signature: "correl(range y, range x)",
+    },
+    FunctionInfo {
+        name: "count",
+        signature: "count(number, ...)",
+    },
+    FunctionInfo {
+        name: "covar",
+        signature: "covar(range y, range x)",
+    },
+    FunctionInfo {
+        name: "geomean",
+        signature: "geomean(number, ...)",
+    },
+    FunctionInfo {
+        name: "harmean",
+        signature: "harmean(number, ...)",
+    },
+    FunctionInfo {
+        name: "hyperlink",
+        signature: "hyperlink(url, label)",
+    },
+    FunctionInfo {
+        name: "if",
+        signature: "if(condition, then, else)",
+    },
+    FunctionInfo {
+        name: "indirect",
+        signature: "indirect(cell name)",
+    },
+    FunctionInfo {
+        name: "intercept",
+        signature: "intercept(range y, range x)",
+    },
+    FunctionInfo {
+        name: "length",
+        signature: "length(text)",
+    },
+    FunctionInfo {
+        name: "max",
+        signature: "max(number, ...)",
+    },
+    FunctionInfo {
+        name: "min",
+        signature: "min(number, ...)",
+    },
+    FunctionInfo {
+        name: "n",
+        signature: "n(value)",
+    },
+    FunctionInfo {
+        name: "offset",
+        signature: "offset(cell, rows, columns)",
+    },
+    FunctionInfo {
+        name: "pow",
+        signature: "pow(base, exponent)",
+    },
+    FunctionInfo {
+        name: "product",
+        signature: "product(number, ...)",
+    },
+    FunctionInfo {
+        name: "round",
+        signature: "round(number)",
+    },
+    FunctionInfo {
+        name: "row",
+        signature: "row()",
+    },
+    FunctionInfo {
+        name: "slope",
+        signature: "slope(range y, range x)",
+    },
+    FunctionInfo {
+        name: "sparkline",
+        signature: "sparkline(range)",
+    },
+    FunctionInfo {
+        name: "sum",
+        signature: "sum(number, ...)",
+    },
+    FunctionInfo {
+        name: "sumproduct",
+        signature: "sumproduct(range, ...)",
+    },
+    FunctionInfo {
+        name: "sumsq",
+        signature: "sumsq(number, ...)",
+    },
+    FunctionInfo {
+        name: "t",
+        signature: "t(value)",
+    },
+    FunctionInfo {
+        name: "transpose",
+        signature: "transpose(range)",
+    },
+    FunctionInfo {
+        name: "type",
+        signature: "type(value)",
+    },
+];
+
+pub fn get_func(name: &str) -> Option<BuiltinFunction> {
     match name {
         "sum" => Some(self::sum),
         "product" => Some(self::product),
@@ -8,14 +148,39 @@ pub fn get_func(name: &str) -> Option<fn(Vec<Value>) -> Result<Value, ComputeErr
         "min" => Some(self::min),
         "average" => Some(self::average),
         "count" => Some(self::count),
+        "geomean" => Some(self::geomean),
+        "harmean" => Some(self::harmean),
+        "sumsq" => Some(self::sumsq),
+        "correl" => Some(self::correl),
+        "covar" => Some(self::covar),
+        "slope" => Some(self::slope),
+        "intercept" => Some(self::intercept),
         "length" => Some(self::length),
         "if" => Some(self::if_func),
         "round" => Some(self::round),
         "pow" => Some(self::power),
+        "concat" => Some(self::concat),
+        "transpose" => Some(self::transpose),
+        "sumproduct" => Some(self::sumproduct),
+        "n" => Some(self::n),
+        "t" => Some(self::t),
+        "type" => Some(self::type_func),
+        "hyperlink" => Some(self::hyperlink),
+        "sparkline" => Some(self::sparkline),
         _ => None,
     }
 }
 
+/// Returns whether the function `name` is given the ranges among its arguments as arrays.
+/// The others are given each of their cells, and the values of arrays, as arguments of
+/// their own.
+pub fn takes_arrays(name: &str) -> bool {
+    matches!(
+        name,
+        "transpose" | "sumproduct" | "correl" | "covar" | "slope" | "intercept"
+    )
+}
+
 pub fn sum(args: Vec<Value>) -> Result<Value, ComputeError> {
     let mut sum = 0.0;
     for arg in args {
@@ -101,13 +266,85 @@ pub fn count(args: Vec<Value>) -> Result<Value, ComputeError> {
     Ok(Value::Number(count))
 }
 
+/// Returns the nth root of the product of n numbers, all above 0, as for averaging growth
+/// rates.
+pub fn geomean(args: Vec<Value>) -> Result<Value, ComputeError> {
+    if args.is_empty() {
+        return Err(ComputeError::InvalidArgument(
+            "geomean expects at least one numeric value".to_string(),
+        ));
+    }
+
+    let mut prod = 1.0;
+    let len = args.len() as f64;
+    for arg in args {
+        match arg {
+            Value::Number(num) if num > 0.0 => prod *= num,
+            Value::Number(_) => {
+                return Err(ComputeError::InvalidArgument(
+                    "geomean expects only numbers above 0".to_string(),
+                ))
+            }
+            _ => {
+                return Err(ComputeError::InvalidArgument(
+                    "geomean expects only numeric values".to_string(),
+                ))
+            }
+        }
+    }
+    Ok(Value::Number(prod.powf(1.0 / len)))
+}
+
+/// Returns n divided by the sum of the inverses of n numbers, none of them 0, as for
+/// averaging rates like speeds.
+pub fn harmean(args: Vec<Value>) -> Result<Value, ComputeError> {
+    if args.is_empty() {
+        return Err(ComputeError::InvalidArgument(
+            "harmean expects at least one numeric value".to_string(),
+        ));
+    }
+
+    let mut inverses = 0.0;
+    let len = args.len() as f64;
+    for arg in args {
+        match arg {
+            Value::Number(num) if num != 0.0 => inverses += 1.0 / num,
+            Value::Number(_) => {
+                return Err(ComputeError::InvalidArgument(
+                    "harmean expects no zeros".to_string(),
+                ))
+            }
+            _ => {
+                return Err(ComputeError::InvalidArgument(
+                    "harmean expects only numeric values".to_string(),
+                ))
+            }
+        }
+    }
+    Ok(Value::Number(len / inverses))
+}
+
+pub fn sumsq(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let mut sum = 0.0;
+    for arg in args {
+        if let Value::Number(num) = arg {
+            sum += num * num;
+        } else {
+            return Err(ComputeError::InvalidArgument(
+                "sumsq expects only numeric values".to_string(),
+            ));
+        }
+    }
+    Ok(Value::Number(sum))
+}
+
 pub fn length(args: Vec<Value>) -> Result<Value, ComputeError> {
     if args.len() != 1 {
         return Err(ComputeError::InvalidArgument("length expects exactly one argument".to_string()));
     }
 
     match &args[0] {
-        Value::Text(t) => Ok(Value::Number(t.len() as f64)),
+        Value::Text(t) | Value::Link { label: t, .. } => Ok(Value::Number(t.len() as f64)),
         _ => Err(ComputeError::InvalidArgument("length expects a string argument".to_string())),
     }
 }
@@ -154,3 +391,541 @@ pub fn power(mut args: Vec<Value>) -> Result<Value, ComputeError> {
         _ => Err(ComputeError::InvalidArgument("pow expects both arguments to be numeric".to_string())),
     }
 }
+
+pub fn concat(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Text(args.iter().map(Value::to_string).collect()))
+}
+
+/// Swaps the rows and the columns of an array. Any other value is left as it is.
+pub fn transpose(mut args: Vec<Value>) -> Result<Value, ComputeError> {
+    if args.len() != 1 {
+        return Err(ComputeError::InvalidArgument(
+            "transpose expects exactly one range".to_string(),
+        ));
+    }
+
+    match args.pop().unwrap() {
+        Value::Array(rows) => {
+            let columns = rows.first().map_or(0, Vec::len);
+            let mut transposed: Vec<Vec<Value>> = (0..columns)
+                .map(|_| Vec::with_capacity(rows.len()))
+                .collect();
+            for row in rows {
+                for (column, value) in transposed.iter_mut().zip(row) {
+                    column.push(value);
+                }
+            }
+            Ok(Value::Array(transposed))
+        }
+        value => Ok(value),
+    }
+}
+
+/// Multiplies the values at the same place of arrays of the same size together and sums the
+/// products. Values other than numbers count as 0.
+pub fn sumproduct(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let arrays: Vec<Vec<Vec<Value>>> = args
+        .into_iter()
+        .map(|arg| match arg {
+            Value::Array(rows) => rows,
+            value => vec![vec![value]],
+        })
+        .collect();
+    let Some(first) = arrays.first() else {
+        return Err(ComputeError::InvalidArgument(
+            "sumproduct expects at least one range".to_string(),
+        ));
+    };
+    let size = |rows: &Vec<Vec<Value>>| (rows.len(), rows.first().map_or(0, Vec::len));
+    if arrays.iter().any(|array| size(array) != size(first)) {
+        return Err(ComputeError::InvalidArgument(
+            "sumproduct expects ranges of the same size".to_string(),
+        ));
+    }
+
+    let number = |value: &Value| match value {
+        Value::Number(num) => *num,
+        _ => 0.0,
+    };
+    let (rows, columns) = size(first);
+    let mut sum = 0.0;
+    for y in 0..rows {
+        for x in 0..columns {
+            sum += arrays
+                .iter()
+                .map(|array| number(&array[y][x]))
+                .product::<f64>();
+        }
+    }
+    Ok(Value::Number(sum))
+}
+
+/// The sums paired statistics are computed from, over the pairs of numbers at the same place
+/// of two ranges.
+struct PairedSums {
+    len: f64,
+    mean_x: f64,
+    mean_y: f64,
+    // Sums of the squared deviations from the means, and of their products
+    xx: f64,
+    yy: f64,
+    xy: f64,
+}
+
+impl PairedSums {
+    /// Pairs the values at the same place of the ranges `y` and `x` of the same size, leaving
+    /// out pairs with anything other than a number on either side. At least two must be
+    /// left.
+    fn new(name: &str, args: Vec<Value>) -> Result<Self, ComputeError> {
+        let [y, x] = <[Value; 2]>::try_from(args).map_err(|_| {
+            ComputeError::InvalidArgument(format!("{name} expects exactly two ranges"))
+        })?;
+        let rows = |arg| match arg {
+            Value::Array(rows) => rows,
+            value => vec![vec![value]],
+        };
+        let (y, x) = (rows(y), rows(x));
+        let size = |rows: &Vec<Vec<Value>>| (rows.len(), rows.first().map_or(0, Vec::len));
+        if size(&y) != size(&x) {
+            return Err(ComputeError::InvalidArgument(format!(
+                "{name} expects ranges of the same size"
+            )));
+        }
+
+        let pairs: Vec<(f64, f64)> = y
+            .into_iter()
+            .flatten()
+            .zip(x.into_iter().flatten())
+            .filter_map(|pair| match pair {
+                (Value::Number(y), Value::Number(x)) => Some((y, x)),
+                _ => None,
+            })
+            .collect();
+        if pairs.len() < 2 {
+            return Err(ComputeError::InvalidArgument(format!(
+                "{name} expects at least two pairs of numbers"
+            )));
+        }
+
+        let len = pairs.len() as f64;
+        let mean_y = pairs.iter().map(|(y, _)| y).sum::<f64>() / len;
+        let mean_x = pairs.iter().map(|(_, x)| x).sum::<f64>() / len;
+        let mut sums = Self {
+            len,
+            mean_x,
+            mean_y,
+            xx: 0.0,
+            yy: 0.0,
+            xy: 0.0,
+        };
+        for (y, x) in pairs {
+            let (dy, dx) = (y - mean_y, x - mean_x);
+            sums.xx += dx * dx;
+            sums.yy += dy * dy;
+            sums.xy += dx * dy;
+        }
+        Ok(sums)
+    }
+
+    fn slope(&self, name: &str) -> Result<f64, ComputeError> {
+        if self.xx == 0.0 {
+            return Err(ComputeError::InvalidArgument(format!(
+                "{name} expects x values that are not all the same"
+            )));
+        }
+        Ok(self.xy / self.xx)
+    }
+}
+
+/// Returns the Pearson correlation of the numbers of two ranges of the same size, from -1
+/// to 1. See `PairedSums::new` for how they are paired.
+pub fn correl(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let sums = PairedSums::new("correl", args)?;
+    if sums.xx == 0.0 || sums.yy == 0.0 {
+        return Err(ComputeError::InvalidArgument(
+            "correl expects values that are not all the same".to_string(),
+        ));
+    }
+    Ok(Value::Number(sums.xy / (sums.xx * sums.yy).sqrt()))
+}
+
+/// Returns the population covariance of the numbers of two ranges of the same size.
+pub fn covar(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let sums = PairedSums::new("covar", args)?;
+    Ok(Value::Number(sums.xy / sums.len))
+}
+
+/// Returns the slope of the least squares line through the points of the numbers of the
+/// first range over those of the second.
+pub fn slope(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let sums = PairedSums::new("slope", args)?;
+    Ok(Value::Number(sums.slope("slope")?))
+}
+
+/// Returns where the line of `slope` crosses the y axis.
+pub fn intercept(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let sums = PairedSums::new("intercept", args)?;
+    let slope = sums.slope("intercept")?;
+    Ok(Value::Number(sums.mean_y - slope * sums.mean_x))
+}
+
+/// Converts a value to a number: booleans to 1 or 0, text reading like a number typed into
+/// a cell to that number, and any other text to 0. Errors are not caught but passed on, as
+/// for every function.
+pub fn n(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let [value] = args.as_slice() else {
+        return Err(ComputeError::InvalidArgument(
+            "n expects exactly one argument".to_string(),
+        ));
+    };
+
+    let num = match value {
+        Value::Number(num) => *num,
+        Value::Bool(b) => f64::from(u8::from(*b)),
+        Value::Text(text) | Value::Link { label: text, .. } => {
+            CellParser::parse_number(text.trim()).map_or(0.0, |(num, _)| num)
+        }
+        Value::Array(_) | Value::Sparkline(_) => 0.0,
+    };
+    Ok(Value::Number(num))
+}
+
+/// Returns text and links as they are and empty text for any other value.
+pub fn t(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let [value] = args.as_slice() else {
+        return Err(ComputeError::InvalidArgument(
+            "t expects exactly one argument".to_string(),
+        ));
+    };
+
+    match value {
+        Value::Text(_) | Value::Link { .. } => Ok(value.clone()),
+        _ => Ok(Value::Text(String::new())),
+    }
+}
+
+/// Returns the kind of a value as the codes of Excel's `TYPE`: 1 for a number, 2 for text,
+/// 4 for a boolean and 64 for an array.
+pub fn type_func(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let [value] = args.as_slice() else {
+        return Err(ComputeError::InvalidArgument(
+            "type expects exactly one argument".to_string(),
+        ));
+    };
+
+    let code = match value {
+        Value::Number(_) => 1.0,
+        Value::Text(_) | Value::Link { .. } | Value::Sparkline(_) => 2.0,
+        Value::Bool(_) => 4.0,
+        Value::Array(_) => 64.0,
+    };
+    Ok(Value::Number(code))
+}
+
+/// Returns a link to the url given as text, showing the label when one is given and the url
+/// otherwise.
+pub fn hyperlink(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let (url, label) = match args.as_slice() {
+        [url] => (url, url),
+        [url, label] => (url, label),
+        _ => {
+            return Err(ComputeError::InvalidArgument(
+                "hyperlink expects a url and an optional label".to_string(),
+            ))
+        }
+    };
+
+    match url {
+        Value::Text(url) if !url.trim().is_empty() => Ok(Value::Link {
+            url: url.trim().to_string(),
+            label: label.to_string(),
+        }),
+        _ => Err(ComputeError::InvalidArgument(
+            "hyperlink expects the url as text".to_string(),
+        )),
+    }
+}
+
+/// The most numbers a sparkline keeps, more than a cell has room to draw.
+pub const MAX_SPARKLINE_POINTS: usize = 100;
+
+/// Returns a sparkline of the numbers given, a range read row by row with its empty cells
+/// left out. Past `MAX_SPARKLINE_POINTS` numbers, as many are kept evenly spread out over
+/// them, the first and the last included.
+pub fn sparkline(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let mut numbers = Vec::with_capacity(args.len());
+    for arg in args {
+        match arg {
+            Value::Number(num) => numbers.push(num),
+            _ => {
+                return Err(ComputeError::InvalidArgument(
+                    "sparkline expects only numeric values".to_string(),
+                ))
+            }
+        }
+    }
+    if numbers.is_empty() {
+        return Err(ComputeError::InvalidArgument(
+            "sparkline expects at least one numeric value".to_string(),
+        ));
+    }
+
+    if numbers.len() > MAX_SPARKLINE_POINTS {
+        let last = numbers.len() - 1;
+        numbers = (0..MAX_SPARKLINE_POINTS)
+            .map(|i| numbers[i * last / (MAX_SPARKLINE_POINTS - 1)])
+            .collect();
+    }
+    Ok(Value::Sparkline(numbers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalog_matches_get_func() {
+        for function in CATALOG {
+            assert!(
+                get_func(function.name).is_some()
+                    || [INDIRECT, OFFSET, ROW, COLUMN].contains(&function.name),
+                "{}",
+                function.name
+            );
+            assert!(function
+                .signature
+                .starts_with(&format!("{}(", function.name)));
+        }
+        assert!(CATALOG.windows(2).all(|pair| pair[0].name < pair[1].name));
+    }
+
+    #[test]
+    fn test_concat() {
+        let args = vec![
+            Value::Text("A".to_string()),
+            Value::Number(3.0),
+            Value::Bool(true),
+        ];
+        assert_eq!(concat(args).unwrap(), Value::Text("A3TRUE".to_string()));
+        assert_eq!(concat(Vec::new()).unwrap(), Value::Text(String::new()));
+    }
+
+    #[test]
+    fn test_means_and_sumsq() {
+        let numbers = |nums: &[f64]| nums.iter().map(|num| Value::Number(*num)).collect();
+
+        assert_eq!(geomean(numbers(&[2.0, 8.0])).unwrap(), Value::Number(4.0));
+        assert_eq!(geomean(numbers(&[5.0])).unwrap(), Value::Number(5.0));
+        assert_eq!(
+            harmean(numbers(&[1.0, 4.0, 4.0])).unwrap(),
+            Value::Number(2.0)
+        );
+        assert_eq!(harmean(numbers(&[-2.0])).unwrap(), Value::Number(-2.0));
+        assert_eq!(sumsq(numbers(&[3.0, -4.0])).unwrap(), Value::Number(25.0));
+        assert_eq!(sumsq(Vec::new()).unwrap(), Value::Number(0.0));
+
+        // Out of their domains
+        for nums in [&[2.0, 0.0][..], &[4.0, -1.0]] {
+            assert!(matches!(
+                geomean(numbers(nums)),
+                Err(ComputeError::InvalidArgument(_))
+            ));
+        }
+        assert!(matches!(
+            harmean(numbers(&[2.0, 0.0])),
+            Err(ComputeError::InvalidArgument(_))
+        ));
+        for func in [geomean, harmean] {
+            assert!(func(Vec::new()).is_err());
+        }
+        for func in [geomean, harmean, sumsq] {
+            assert!(func(vec![Value::Number(1.0), Value::Text("1".to_string())]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_paired_statistics() {
+        let column =
+            |nums: &[f64]| Value::Array(nums.iter().map(|num| vec![Value::Number(*num)]).collect());
+        let (y, x) = (
+            column(&[2.0, 4.0, 5.0, 4.0, 5.0]),
+            column(&[1.0, 2.0, 3.0, 4.0, 5.0]),
+        );
+        let computed =
+            |func: BuiltinFunction, y: &Value, x: &Value| match func(vec![y.clone(), x.clone()]) {
+                Ok(Value::Number(num)) => num,
+                other => panic!("{other:?}"),
+            };
+        // Deviations from the means 4 and 3 multiply to 6, those of x square to 10 and
+        // those of y to 6
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        assert!(close(computed(slope, &y, &x), 0.6));
+        assert!(close(computed(intercept, &y, &x), 2.2));
+        assert!(close(computed(covar, &y, &x), 1.2));
+        assert!(close(computed(correl, &y, &x), 6.0 / 60f64.sqrt()));
+        assert!(close(computed(correl, &x, &y), computed(correl, &y, &x)));
+
+        // Pairs with text on either side are left out
+        let text = || vec![Value::Text("n/a".to_string())];
+        let Value::Array(mut y_rows) = y.clone() else {
+            unreachable!()
+        };
+        let Value::Array(mut x_rows) = x.clone() else {
+            unreachable!()
+        };
+        y_rows.extend([text(), vec![Value::Number(100.0)]]);
+        x_rows.extend([vec![Value::Number(100.0)], text()]);
+        assert!(close(
+            computed(slope, &Value::Array(y_rows), &Value::Array(x_rows)),
+            0.6
+        ));
+
+        let invalid = |func: BuiltinFunction, args: Vec<Value>| {
+            matches!(func(args), Err(ComputeError::InvalidArgument(_)))
+        };
+        for func in [correl, covar, slope, intercept] {
+            // Shapes differ, a single pair, and not two ranges
+            assert!(invalid(func, vec![y.clone(), column(&[1.0, 2.0])]));
+            assert!(invalid(func, vec![column(&[1.0]), column(&[2.0])]));
+            assert!(invalid(func, vec![y.clone()]));
+        }
+        let flat = column(&[3.0, 3.0, 3.0, 3.0, 3.0]);
+        for func in [correl, slope, intercept] {
+            assert!(invalid(func, vec![y.clone(), flat.clone()]));
+        }
+        assert_eq!(computed(covar, &y, &flat), 0.0);
+    }
+
+    #[test]
+    fn test_coercions() {
+        let text = |text: &str| Value::Text(text.to_string());
+        let one = |func: BuiltinFunction, value: Value| func(vec![value]).unwrap();
+
+        assert_eq!(one(n, Value::Number(-2.5)), Value::Number(-2.5));
+        assert_eq!(one(n, Value::Bool(true)), Value::Number(1.0));
+        assert_eq!(one(n, Value::Bool(false)), Value::Number(0.0));
+        assert_eq!(one(n, text(" 1,500 ")), Value::Number(1500.0));
+        assert_eq!(one(n, text("25%")), Value::Number(0.25));
+        assert_eq!(one(n, text("abc")), Value::Number(0.0));
+        assert_eq!(one(n, text("")), Value::Number(0.0));
+        assert_eq!(
+            one(n, Value::Array(vec![vec![Value::Number(3.0)]])),
+            Value::Number(0.0)
+        );
+
+        assert_eq!(one(t, text("abc")), text("abc"));
+        assert_eq!(one(t, text("12")), text("12"));
+        assert_eq!(one(t, Value::Number(12.0)), text(""));
+        assert_eq!(one(t, Value::Bool(true)), text(""));
+
+        assert_eq!(one(type_func, Value::Number(1.0)), Value::Number(1.0));
+        assert_eq!(one(type_func, text("1")), Value::Number(2.0));
+        assert_eq!(one(type_func, Value::Bool(false)), Value::Number(4.0));
+        assert_eq!(
+            one(type_func, Value::Array(Vec::new())),
+            Value::Number(64.0)
+        );
+
+        for func in [n, t, type_func] {
+            assert!(func(Vec::new()).is_err());
+            assert!(func(vec![text("a"), text("b")]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_hyperlink() {
+        let text = |text: &str| Value::Text(text.to_string());
+        let link = |url: &str, label: &str| Value::Link {
+            url: url.to_string(),
+            label: label.to_string(),
+        };
+
+        assert_eq!(
+            hyperlink(vec![text(" https://example.com "), text("Example")]).unwrap(),
+            link("https://example.com", "Example")
+        );
+        // The url shows without a label, and a label of another kind shows as text
+        assert_eq!(
+            hyperlink(vec![text("https://example.com")]).unwrap(),
+            link("https://example.com", "https://example.com")
+        );
+        assert_eq!(
+            hyperlink(vec![text("mailto:a@b.c"), Value::Number(2.5)]).unwrap(),
+            link("mailto:a@b.c", "2.5")
+        );
+
+        let invalid = |args| matches!(hyperlink(args), Err(ComputeError::InvalidArgument(_)));
+        assert!(invalid(Vec::new()));
+        assert!(invalid(vec![text("a"), text("b"), text("c")]));
+        assert!(invalid(vec![Value::Number(1.0), text("label")]));
+        assert!(invalid(vec![text(" "), text("label")]));
+
+        // Links read as their label
+        let example = link("https://example.com", "Example");
+        assert_eq!(length(vec![example.clone()]).unwrap(), Value::Number(7.0));
+        assert_eq!(t(vec![example.clone()]).unwrap(), example);
+        assert_eq!(
+            type_func(vec![example.clone()]).unwrap(),
+            Value::Number(2.0)
+        );
+        assert_eq!(concat(vec![example, text("!")]).unwrap(), text("Example!"));
+    }
+
+    #[test]
+    fn test_sparkline() {
+        let numbers = |numbers: &[f64]| numbers.iter().map(|&num| Value::Number(num)).collect();
+        assert_eq!(
+            sparkline(numbers(&[3.0, 1.0, 2.0])).unwrap(),
+            Value::Sparkline(vec![3.0, 1.0, 2.0])
+        );
+
+        // Long ranges are sampled, keeping both ends
+        let many: Vec<f64> = (0..1000).map(f64::from).collect();
+        let Value::Sparkline(points) = sparkline(numbers(&many)).unwrap() else {
+            panic!("Expected a sparkline");
+        };
+        assert_eq!(points.len(), MAX_SPARKLINE_POINTS);
+        assert_eq!((points[0], points[1], points[99]), (0.0, 10.0, 999.0));
+        assert!(points.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let invalid = |args| matches!(sparkline(args), Err(ComputeError::InvalidArgument(_)));
+        assert!(invalid(Vec::new()));
+        assert!(invalid(vec![
+            Value::Number(1.0),
+            Value::Text("2".to_string())
+        ]));
+    }
+
+    #[test]
+    fn test_transpose_and_sumproduct() {
+        let array = |rows: &[&[f64]]| {
+            Value::Array(
+                rows.iter()
+                    .map(|row| row.iter().map(|num| Value::Number(*num)).collect())
+                    .collect(),
+            )
+        };
+        let wide = array(&[&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]]);
+        let tall = array(&[&[1.0, 4.0], &[2.0, 5.0], &[3.0, 6.0]]);
+        assert_eq!(transpose(vec![wide.clone()]).unwrap(), tall);
+        assert_eq!(transpose(vec![tall.clone()]).unwrap(), wide);
+        assert_eq!(
+            transpose(vec![Value::Number(1.0)]).unwrap(),
+            Value::Number(1.0)
+        );
+        assert!(transpose(vec![wide.clone(), tall.clone()]).is_err());
+
+        // 1*1 + 2*2 + ... + 6*6
+        assert_eq!(
+            sumproduct(vec![wide.clone(), wide.clone()]).unwrap(),
+            Value::Number(91.0)
+        );
+        let text = Value::Array(vec![vec![Value::Text("x".to_string()), Value::Number(2.0)]]);
+        assert_eq!(
+            sumproduct(vec![text, array(&[&[5.0, 3.0]])]).unwrap(),
+            Value::Number(6.0)
+        );
+        assert!(sumproduct(vec![wide, tall]).is_err());
+        assert!(sumproduct(Vec::new()).is_err());
+    }
+}
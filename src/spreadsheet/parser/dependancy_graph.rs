@@ -82,6 +82,17 @@ impl DependancyGraph {
         self.add_node(index, dependencies);
     }
 
+    /// Return the nodes that depend on this directly
+    pub fn get_dependants(&self, index: Index) -> Vec<Index> {
+        let mut result: Vec<Index> = Vec::new();
+        for dependant in self.allows_compute.get(&index).into_iter().flatten() {
+            if !result.contains(dependant) {
+                result.push(*dependant);
+            }
+        }
+        result
+    }
+
     /// Return all nodes that depend on this
     pub fn get_all_dependants(&self, index: Index) -> Vec<Index> {        
         let mut result = Vec::new();
@@ -100,4 +111,111 @@ impl DependancyGraph {
 
         result
     }
+
+    /// Returns how many pairs of a node and a node depending on it directly there are, each
+    /// pair counted once.
+    pub fn edge_count(&self) -> usize {
+        self.allows_compute
+            .keys()
+            .map(|&node| self.get_dependants(node).len())
+            .sum()
+    }
+
+    /// Returns the number of edges of the longest path through the graph, found by passing
+    /// every node of the topological order its depth plus one. Nodes in or behind a cycle
+    /// are left out.
+    pub fn longest_chain(&self) -> usize {
+        let mut depths: HashMap<Index, usize> = HashMap::new();
+        let mut longest = 0;
+        for node in self.topological_sort().sorted {
+            let depth = depths.get(&node).copied().unwrap_or(0);
+            longest = longest.max(depth);
+            for dependant in self.allows_compute.get(&node).into_iter().flatten() {
+                let dependant_depth = depths.entry(*dependant).or_insert(0);
+                *dependant_depth = (*dependant_depth).max(depth + 1);
+            }
+        }
+        longest
+    }
+
+    /// Writes the graph in the Graphviz DOT language, a node per cell named by `label` and an
+    /// edge from each cell to those computed from it. Cells `topological_sort` finds in or
+    /// behind a cycle are red. Nodes and edges are in reading order.
+    pub fn to_dot(&self, label: impl Fn(Index) -> String) -> String {
+        let reading_order = |index: &Index| (index.y, index.x);
+        let mut edges: Vec<(Index, Index)> = self
+            .allows_compute
+            .iter()
+            .flat_map(|(node, dependants)| dependants.iter().map(|dependant| (*node, *dependant)))
+            .collect();
+        edges.sort_by_key(|(from, to)| (reading_order(from), reading_order(to)));
+        edges.dedup();
+
+        let mut nodes: Vec<Index> = edges.iter().flat_map(|&(from, to)| [from, to]).collect();
+        nodes.sort_by_key(reading_order);
+        nodes.dedup();
+        let cycles = self.topological_sort().cycles;
+
+        let mut dot = String::from("digraph dependencies {\n");
+        for node in nodes {
+            let color = match cycles.contains(&node) {
+                true => " [color=red, fontcolor=red]",
+                false => "",
+            };
+            dot.push_str(&format!("    \"{}\"{color};\n", label(node)));
+        }
+        for (from, to) in edges {
+            dot.push_str(&format!("    \"{}\" -> \"{}\";\n", label(from), label(to)));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_types::index_to_name;
+
+    #[test]
+    fn test_to_dot() {
+        let (a1, b1, c1, a2, b2) = (
+            Index { x: 0, y: 0 },
+            Index { x: 1, y: 0 },
+            Index { x: 2, y: 0 },
+            Index { x: 0, y: 1 },
+            Index { x: 1, y: 1 },
+        );
+        let mut graph = DependancyGraph::default();
+        graph.add_node(c1, &vec![a1, b1, a1]);
+        graph.add_node(b1, &vec![a1]);
+        // A2 and B2 depend on each other
+        graph.add_node(a2, &vec![b2]);
+        graph.add_node(b2, &vec![a2]);
+
+        let dot = graph.to_dot(index_to_name);
+        assert_eq!(
+            dot,
+            "digraph dependencies {\n    \
+             \"A1\";\n    \
+             \"B1\";\n    \
+             \"C1\";\n    \
+             \"A2\" [color=red, fontcolor=red];\n    \
+             \"B2\" [color=red, fontcolor=red];\n    \
+             \"A1\" -> \"B1\";\n    \
+             \"A1\" -> \"C1\";\n    \
+             \"B1\" -> \"C1\";\n    \
+             \"A2\" -> \"B2\";\n    \
+             \"B2\" -> \"A2\";\n\
+             }\n"
+        );
+
+        // The same graph built in another order writes the same
+        let mut reordered = DependancyGraph::default();
+        reordered.add_node(b2, &vec![a2]);
+        reordered.add_node(b1, &vec![a1]);
+        reordered.add_node(a2, &vec![b2]);
+        reordered.add_node(c1, &vec![b1, a1]);
+        assert_eq!(reordered.to_dot(index_to_name), dot);
+    }
 }
@@ -0,0 +1,252 @@
+use std::ops::Range;
+
+use crate::common_types::{index_to_name, name_to_index, Index, Reference, Token};
+
+/// Written in place of a reference that would move outside of the sheet.
+pub const INVALID_REFERENCE: &str = "#REF!";
+
+/// The parts of a cell reference written with a `$` before them, as in `$A$1`, `A$1` or
+/// `$A1`. An anchored column or row stays the same when the formula is copied.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Anchors {
+    pub column: bool,
+    pub row: bool,
+}
+
+impl Anchors {
+    /// The form F4 turns a reference into: `A1`, `$A$1`, `A$1`, `$A1` and back to `A1`.
+    fn next(self) -> Self {
+        let (column, row) = match (self.column, self.row) {
+            (false, false) => (true, true),
+            (true, true) => (false, true),
+            (false, true) => (true, false),
+            (true, false) => (false, false),
+        };
+        Self { column, row }
+    }
+}
+
+/// Splits a cell name like `$A1` into its anchors and the name without them.
+pub fn split_anchors(name: &str) -> (Anchors, String) {
+    let column = name.starts_with('$');
+    let row = name[usize::from(column)..].contains('$');
+    let unanchored = name.chars().filter(|&c| c != '$').collect();
+    (Anchors { column, row }, unanchored)
+}
+
+/// Writes a cell name without anchors, like `A1`, with `anchors`.
+pub fn with_anchors(name: &str, anchors: Anchors) -> String {
+    let letters = name.chars().take_while(char::is_ascii_uppercase).count();
+    let (column, row) = name.split_at(letters);
+    let anchor = |anchored: bool| if anchored { "$" } else { "" };
+    format!(
+        "{}{column}{}{row}",
+        anchor(anchors.column),
+        anchor(anchors.row)
+    )
+}
+
+/// Returns `reference` in the next of its absolute and relative forms, see `Anchors::next`.
+/// Every cell of a range like `A1:B2` takes the form following the one of its first cell.
+/// Spacing is kept.
+pub fn cycle_anchors(reference: &str) -> String {
+    let cells: Vec<&str> = reference.split(':').collect();
+    let (anchors, _) = split_anchors(cells[0].trim());
+    let next = anchors.next();
+    cells
+        .iter()
+        .map(|cell| {
+            let name = cell.trim();
+            let (_, unanchored) = split_anchors(name);
+            cell.replacen(name, &with_anchors(&unanchored, next), 1)
+        })
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Moves every cell reference in `formula` by `(columns, rows)`, the way a formula changes
+/// when it is copied to another cell. Anchored columns and rows stay, see `Anchors`. String
+/// literals are left untouched and so is the rest of the text, spacing included. References
+/// pushed before the first row or column become `INVALID_REFERENCE`, which no longer parses.
+pub fn shift_references(formula: &str, offset: (isize, isize)) -> String {
+    let chars: Vec<char> = formula.chars().collect();
+    let mut shifted = String::with_capacity(formula.len());
+    let mut in_string = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let follows_identifier = i > 0 && chars[i - 1].is_ascii_alphanumeric();
+
+        if c == '"' {
+            in_string = !in_string;
+        } else if !in_string && (c == '$' || c.is_ascii_uppercase()) && !follows_identifier {
+            let letters_start = i + usize::from(c == '$');
+            let letters_end = scan(&chars, letters_start, |c| c.is_ascii_uppercase());
+            let digits_start = letters_end + usize::from(chars.get(letters_end) == Some(&'$'));
+            let digits_end = scan(&chars, digits_start, |c| c.is_ascii_digit());
+
+            if letters_end > letters_start && digits_end > digits_start {
+                let name: String = chars[i..digits_end].iter().collect();
+                shifted.push_str(&shift_cell_name(&name, offset));
+                i = digits_end;
+            } else {
+                // TRUE, FALSE or anything else that is not a reference
+                let end = letters_end.max(i + 1);
+                shifted.extend(&chars[i..end]);
+                i = end;
+            }
+            continue;
+        }
+
+        shifted.push(c);
+        i += 1;
+    }
+
+    shifted
+}
+
+/// Picks the references out of the tokens of a formula, each with the byte range it was read
+/// from. A range like `A1:B2` is a single reference spanning all three of its tokens.
+pub fn find_references(tokens: &[(Token, Range<usize>)]) -> Vec<(Reference, Range<usize>)> {
+    let cell = |i: usize| match tokens.get(i) {
+        Some((Token::CellName(index), span)) => Some((*index, span.clone())),
+        _ => None,
+    };
+
+    let mut references = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let Some((from, span)) = cell(i) else {
+            i += 1;
+            continue;
+        };
+
+        let colon = matches!(tokens.get(i + 1), Some((Token::Colon, _)));
+        match cell(i + 2).filter(|_| colon) {
+            Some((to, end)) => {
+                references.push((Reference::Range(from, to), span.start..end.end));
+                i += 3;
+            }
+            None => {
+                references.push((Reference::Cell(from), span));
+                i += 1;
+            }
+        }
+    }
+    references
+}
+
+fn scan(chars: &[char], start: usize, matches: impl Fn(char) -> bool) -> usize {
+    chars[start..]
+        .iter()
+        .position(|&c| !matches(c))
+        .map_or(chars.len(), |len| start + len)
+}
+
+fn shift_cell_name(name: &str, offset: (isize, isize)) -> String {
+    let (anchors, unanchored) = split_anchors(name);
+    let Ok(idx) = name_to_index(&unanchored) else {
+        return name.to_string();
+    };
+
+    let shift = |position: usize, anchored: bool, by: isize| {
+        if anchored {
+            Some(position)
+        } else {
+            position.checked_add_signed(by)
+        }
+    };
+    match (
+        shift(idx.x, anchors.column, offset.0),
+        shift(idx.y, anchors.row, offset.1),
+    ) {
+        (Some(x), Some(y)) => with_anchors(&index_to_name(Index { x, y }), anchors),
+        _ => INVALID_REFERENCE.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_types::cell_addr::cell;
+    use crate::common_types::FunctionId;
+
+    #[test]
+    fn test_shift_references() {
+        assert_eq!(shift_references("=A1+B2", (1, 2)), "=B3+C4");
+        assert_eq!(
+            shift_references("=sum(A1:A3) * 2", (0, 1)),
+            "=sum(A2:A4) * 2"
+        );
+        assert_eq!(shift_references("=Z1", (1, 0)), "=AA1");
+        assert_eq!(shift_references("=AB10", (-2, -9)), "=Z1");
+    }
+
+    #[test]
+    fn test_shift_references_leaves_other_text() {
+        assert_eq!(
+            shift_references("=if(TRUE, \"A1\", C3)", (1, 1)),
+            "=if(TRUE, \"A1\", D4)"
+        );
+        assert_eq!(shift_references("=log10(A1)", (0, 1)), "=log10(A2)");
+    }
+
+    #[test]
+    fn test_find_references() {
+        let tokens = [
+            (Token::CellName(cell("A1")), 1..3),
+            (Token::Plus, 3..4),
+            (Token::FunctionName(FunctionId::new("sum")), 4..7),
+            (Token::LParen, 7..8),
+            (Token::CellName(cell("B2")), 8..10),
+            (Token::Colon, 10..11),
+            (Token::CellName(cell("C4")), 11..13),
+            (Token::Comma, 13..14),
+            (Token::CellName(cell("D1")), 14..16),
+            (Token::Colon, 16..17),
+        ];
+
+        assert_eq!(
+            find_references(&tokens),
+            vec![
+                (Reference::Cell(Index { x: 0, y: 0 }), 1..3),
+                (
+                    Reference::Range(Index { x: 1, y: 1 }, Index { x: 2, y: 3 }),
+                    8..13
+                ),
+                (Reference::Cell(Index { x: 3, y: 0 }), 14..16),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cycle_anchors() {
+        let forms = ["A1", "$A$1", "A$1", "$A1", "A1"];
+        for pair in forms.windows(2) {
+            assert_eq!(cycle_anchors(pair[0]), pair[1]);
+        }
+        assert_eq!(cycle_anchors("AB12"), "$AB$12");
+        // A range follows its first cell
+        assert_eq!(cycle_anchors("A1:$B$2"), "$A$1:$B$2");
+        assert_eq!(cycle_anchors("$A$1 : B2"), "A$1 : B$2");
+    }
+
+    #[test]
+    fn test_shift_anchored_references() {
+        assert_eq!(shift_references("=$A$1+A$1+$A1", (2, 3)), "=$A$1+C$1+$A4");
+        assert_eq!(
+            shift_references("=sum($B$2:B3) * \"$C1\"", (0, 1)),
+            "=sum($B$2:B4) * \"$C1\""
+        );
+        // Only the part that moves can leave the sheet
+        assert_eq!(shift_references("=$A1+A$1", (-1, 0)), "=$A1+#REF!");
+        assert_eq!(shift_references("=$B$1", (-5, -5)), "=$B$1");
+    }
+
+    #[test]
+    fn test_shift_references_off_the_sheet() {
+        assert_eq!(shift_references("=A1+B2", (-1, 0)), "=#REF!+A2");
+        assert_eq!(shift_references("=B1", (0, -1)), "=#REF!");
+    }
+}
@@ -1,10 +1,15 @@
-use crate::common_types::Token;
+use std::ops::Range;
+
+use crate::common_types::{name_to_index, FunctionId, Token};
 
 pub struct ExpressionTokenizer {
     index: usize,
     chars: Vec<char>,
 }
 
+/// A token, or an error, with the range of character indices it was read from.
+pub type Spanned<T> = (T, Range<usize>);
+
 #[derive(Debug)]
 pub enum TokenizeError {
     UnexpectedCharacter(char),
@@ -18,21 +23,29 @@ impl ExpressionTokenizer {
         Self { index: 0, chars }
     }
 
+    /// Like `tokenize_spanned`, without the ranges.
+    #[cfg(test)]
     pub fn tokenize_expression(&mut self) -> Result<Vec<Token>, TokenizeError> {
+        self.tokenize_spanned()
+            .map(|tokens| tokens.into_iter().map(|(token, _)| token).collect())
+            .map_err(|(err, _)| err)
+    }
+
+    /// Like `tokenize_expression`, giving every token the range of character indices it was
+    /// read from, and the error the range of those it was found in.
+    pub fn tokenize_spanned(&mut self) -> Result<Vec<Spanned<Token>>, Spanned<TokenizeError>> {
         self.skip_whitespace();
         let mut expr_tokens = Vec::new();
         while !self.is_done() {
-            let token = match self.peek().expect("Should never fail") {
-                '+' | '-' | '/' | '*' | '(' | ')' | ':' | ',' => self.parse_operator(),
-                '=' | '!' | '>' | '<' | '&' | '|' => self.parse_logical_operator()?,
-                '"' => self.parse_string_literal()?,
-                letter if letter.is_uppercase() => self.parse_cell_name_or_bool()?,
-                letter if letter.is_lowercase() => self.parse_function_name()?,
-                digit if digit.is_ascii_digit() => self.parse_number()?,
-                unknown => return Err(TokenizeError::UnexpectedCharacter(*unknown)),
-            };
-
-            expr_tokens.push(token);
+            let start = self.index;
+            match self.next_token() {
+                Ok(token) => expr_tokens.push((token, start..self.index)),
+                Err(err) => {
+                    // At least the character it starts at, however far it was read
+                    let end = self.index.clamp(start + 1, self.chars.len());
+                    return Err((err, start..end));
+                }
+            }
 
             self.skip_whitespace();
         }
@@ -40,54 +53,75 @@ impl ExpressionTokenizer {
         Ok(expr_tokens)
     }
 
-    fn parse_cell_name_or_bool(&mut self) -> Result<Token, TokenizeError> {
-        // [A-Z]+\d+
+    /// Tokenizes the expression up to its first error instead of failing, so a half written
+    /// expression still gives its valid start. Every token comes with the range of character
+    /// indices it was read from.
+    pub fn tokenize_lossy(&mut self) -> Vec<(Token, Range<usize>)> {
+        self.skip_whitespace();
+        let mut expr_tokens = Vec::new();
+        while !self.is_done() {
+            let start = self.index;
+            let Ok(token) = self.next_token() else {
+                break;
+            };
 
-        let mut is_valid = false;
-        let mut letters = String::new();
+            expr_tokens.push((token, start..self.index));
 
-        // Collect the uppercase letters
-        while let Some(&ch) = self.peek() {
-            if ch.is_ascii_uppercase() {
-                letters.push(ch);
-                self.pop();
-            } else {
-                break;
-            }
+            self.skip_whitespace();
         }
 
-        if letters == "TRUE" {
+        expr_tokens
+    }
+
+    fn next_token(&mut self) -> Result<Token, TokenizeError> {
+        let token = match self.peek().expect("Should never fail") {
+            '+' | '-' | '/' | '*' | '(' | ')' | ':' | ',' => self.parse_operator(),
+            '=' | '!' | '>' | '<' | '&' | '|' => self.parse_logical_operator()?,
+            '"' => self.parse_string_literal()?,
+            '$' => self.parse_cell_name_or_bool()?,
+            letter if letter.is_uppercase() => self.parse_cell_name_or_bool()?,
+            letter if letter.is_lowercase() => self.parse_function_name()?,
+            digit if digit.is_ascii_digit() => self.parse_number()?,
+            unknown => return Err(TokenizeError::UnexpectedCharacter(*unknown)),
+        };
+        Ok(token)
+    }
+
+    fn parse_cell_name_or_bool(&mut self) -> Result<Token, TokenizeError> {
+        // \$?[A-Z]+\$?\d+, read straight into the index it names. The anchors only matter
+        // when the formula is moved, so they are left out of the index
+        let start = self.index;
+        let anchored = self.peek() == Some(&'$');
+        if anchored {
+            self.pop();
+        }
+        let letters_start = self.index;
+        while self.peek().is_some_and(char::is_ascii_uppercase) {
+            self.pop();
+        }
+        let letters = &self.chars[letters_start..self.index];
+
+        if !anchored && letters == ['T', 'R', 'U', 'E'] {
             return Ok(Token::Bool(true));
         }
 
-        if letters == "FALSE" {
+        if !anchored && letters == ['F', 'A', 'L', 'S', 'E'] {
             return Ok(Token::Bool(false));
         }
 
         // At this point we know that we are parsing a Cell Name
-
-        // Ensure there are letters
-        if letters.is_empty() {
-            return Err(TokenizeError::InvalidCellName(String::new()));
+        if self.peek() == Some(&'$') {
+            self.pop();
         }
-
-        // Collect the digits
-        while let Some(&ch) = self.peek() {
-            if ch.is_ascii_digit() {
-                letters.push(ch);
-                self.pop();
-                is_valid = true;
-            } else {
-                break;
-            }
+        while self.peek().is_some_and(char::is_ascii_digit) {
+            self.pop();
         }
-
-        // Ensure the format was valid ``
-        if !is_valid {
-            return Err(TokenizeError::InvalidCellName(letters));
+        let name = &self.chars[start..self.index];
+        let unanchored: String = name.iter().filter(|&&c| c != '$').collect();
+        match name_to_index(&unanchored) {
+            Ok(index) => Ok(Token::CellName(index)),
+            Err(_) => Err(TokenizeError::InvalidCellName(name.iter().collect())),
         }
-
-        Ok(Token::CellName(letters))
     }
 
     fn parse_operator(&mut self) -> Token {
@@ -147,17 +181,17 @@ impl ExpressionTokenizer {
     }
 
     fn parse_function_name(&mut self) -> Result<Token, TokenizeError> {
-        let mut name = String::new();
-        while let Some(&ch) = self.peek() {
-            if ch.is_ascii_alphabetic() || ch == '_' {
-                name.push(ch);
-                self.pop();
-            } else {
-                break;
-            }
+        let start = self.index;
+        while self
+            .peek()
+            .is_some_and(|ch| ch.is_ascii_alphabetic() || *ch == '_')
+        {
+            self.pop();
         }
 
-        Ok(Token::FunctionName(name))
+        Ok(Token::FunctionName(FunctionId::from_chars(
+            &self.chars[start..self.index],
+        )))
     }
 
     fn parse_logical_operator(&mut self) -> Result<Token, TokenizeError> {
@@ -240,6 +274,8 @@ impl ExpressionTokenizer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::common_types::cell_addr::cell;
+    use crate::common_types::Index;
 
     #[test]
     fn test_simple_expression() {
@@ -250,9 +286,9 @@ mod tests {
         assert_eq!(
             tokens,
             vec![
-                Token::CellName("A1".to_string()),
+                Token::CellName(cell("A1")),
                 Token::Plus,
-                Token::CellName("A2".to_string())
+                Token::CellName(cell("A2"))
             ]
         );
     }
@@ -267,12 +303,12 @@ mod tests {
             tokens,
             vec![
                 Token::LParen,
-                Token::CellName("A1".to_string()),
+                Token::CellName(cell("A1")),
                 Token::Plus,
-                Token::CellName("B2".to_string()),
+                Token::CellName(cell("B2")),
                 Token::RParen,
                 Token::Multiply,
-                Token::CellName("C3".to_string())
+                Token::CellName(cell("C3"))
             ]
         );
     }
@@ -286,9 +322,9 @@ mod tests {
         assert_eq!(
             tokens,
             vec![
-                Token::CellName("A1".to_string()),
+                Token::CellName(cell("A1")),
                 Token::Division,
-                Token::CellName("B2".to_string())
+                Token::CellName(cell("B2"))
             ]
         );
     }
@@ -304,17 +340,17 @@ mod tests {
             vec![
                 Token::LParen,
                 Token::LParen,
-                Token::CellName("A1".to_string()),
+                Token::CellName(cell("A1")),
                 Token::Plus,
-                Token::CellName("B2".to_string()),
+                Token::CellName(cell("B2")),
                 Token::RParen,
                 Token::Minus,
-                Token::CellName("C3".to_string()),
+                Token::CellName(cell("C3")),
                 Token::RParen,
                 Token::Multiply,
-                Token::CellName("D4".to_string()),
+                Token::CellName(cell("D4")),
                 Token::Division,
-                Token::CellName("E5".to_string())
+                Token::CellName(cell("E5"))
             ]
         );
     }
@@ -340,14 +376,15 @@ mod tests {
         assert_eq!(
             tokens,
             vec![
-                Token::CellName("A1".to_string()),
+                Token::CellName(cell("A1")),
                 Token::Plus,
-                Token::CellName("A2".to_string())
+                Token::CellName(cell("A2"))
             ]
         );
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_expression_with_numbers() {
         let s = "3.14 + 42";
         let tokens = ExpressionTokenizer::new(s.chars().collect())
@@ -366,6 +403,37 @@ mod tests {
         assert!(matches!(result, Err(TokenizeError::InvalidCellName(_))));
     }
 
+    #[test]
+    fn test_names_read_into_indices_and_ids() {
+        let s = "sum(AB12) + nope(A1)";
+        let tokens = ExpressionTokenizer::new(s.chars().collect())
+            .tokenize_expression()
+            .unwrap();
+        assert_eq!(tokens[2], Token::CellName(Index { x: 27, y: 11 }));
+        assert!(matches!(
+            tokens[0],
+            Token::FunctionName(FunctionId::Builtin(_))
+        ));
+        assert_eq!(
+            tokens[5],
+            Token::FunctionName(FunctionId::Unknown("nope".into()))
+        );
+        let names: Vec<String> = tokens.iter().map(Token::to_string).collect();
+        assert_eq!(
+            names,
+            ["sum", "(", "AB12", ")", "+", "nope", "(", "A1", ")"]
+        );
+
+        // Names of no cell of the sheet
+        for name in ["A0", "AAAAAAAAAAAAAAAAAAAAA1", "A99999999999999999999999"] {
+            let result = ExpressionTokenizer::new(name.chars().collect()).tokenize_expression();
+            assert!(
+                matches!(&result, Err(TokenizeError::InvalidCellName(invalid)) if invalid == name),
+                "{name}"
+            );
+        }
+    }
+
     #[test]
     fn test_expression_with_invalid_number() {
         let s = "42.3.14 + B2";
@@ -375,14 +443,33 @@ mod tests {
 
     #[test]
     fn test_expression_with_unexpected_character() {
-        let s = "A1 + $B2";
+        let s = "A1 + #B2";
         let result = ExpressionTokenizer::new(s.chars().collect()).tokenize_expression();
         assert!(matches!(
             result,
-            Err(TokenizeError::UnexpectedCharacter('$'))
+            Err(TokenizeError::UnexpectedCharacter('#'))
         ));
     }
 
+    #[test]
+    fn test_anchored_cell_names() {
+        let s = "$A$1 + A$2 + $B3";
+        let tokens = ExpressionTokenizer::new(s.chars().collect())
+            .tokenize_expression()
+            .unwrap();
+        assert_eq!(tokens[0], Token::CellName(Index { x: 0, y: 0 }));
+        assert_eq!(tokens[2], Token::CellName(Index { x: 0, y: 1 }));
+        assert_eq!(tokens[4], Token::CellName(Index { x: 1, y: 2 }));
+
+        for name in ["$TRUE", "$1", "B$"] {
+            let result = ExpressionTokenizer::new(name.chars().collect()).tokenize_expression();
+            assert!(
+                matches!(&result, Err(TokenizeError::InvalidCellName(invalid)) if invalid == name),
+                "{name}"
+            );
+        }
+    }
+
     #[test]
     fn test_expression_with_nested_parentheses() {
         let s = "(((A1))) + B2";
@@ -395,12 +482,12 @@ mod tests {
                 Token::LParen,
                 Token::LParen,
                 Token::LParen,
-                Token::CellName("A1".to_string()),
+                Token::CellName(cell("A1")),
                 Token::RParen,
                 Token::RParen,
                 Token::RParen,
                 Token::Plus,
-                Token::CellName("B2".to_string())
+                Token::CellName(cell("B2"))
             ]
         );
     }
@@ -435,9 +522,9 @@ mod tests {
         assert_eq!(
             tokens,
             vec![
-                Token::CellName("A1".to_string()),
+                Token::CellName(cell("A1")),
                 Token::Plus,
-                Token::CellName("B2".to_string()),
+                Token::CellName(cell("B2")),
             ]
         );
     }
@@ -451,9 +538,9 @@ mod tests {
         assert_eq!(
             tokens,
             vec![
-                Token::CellName("A123".to_string()),
+                Token::CellName(cell("A123")),
                 Token::Plus,
-                Token::CellName("B456".to_string()),
+                Token::CellName(cell("B456")),
             ]
         );
     }
@@ -491,11 +578,11 @@ mod tests {
         assert_eq!(
             tokens,
             vec![
-                Token::FunctionName("sum".to_string()),
+                Token::FunctionName(FunctionId::new("sum")),
                 Token::LParen,
-                Token::CellName("A1".to_string()),
+                Token::CellName(cell("A1")),
                 Token::Colon,
-                Token::CellName("B1".to_string()),
+                Token::CellName(cell("B1")),
                 Token::RParen
             ]
         );
@@ -510,11 +597,11 @@ mod tests {
         assert_eq!(
             tokens,
             vec![
-                Token::FunctionName("sum".to_string()),
+                Token::FunctionName(FunctionId::new("sum")),
                 Token::LParen,
-                Token::CellName("A1".to_string()),
+                Token::CellName(cell("A1")),
                 Token::Comma,
-                Token::CellName("C1".to_string()),
+                Token::CellName(cell("C1")),
                 Token::RParen
             ]
         );
@@ -529,9 +616,9 @@ mod tests {
         assert_eq!(
             tokens,
             vec![
-                Token::CellName("A1".to_string()),
+                Token::CellName(cell("A1")),
                 Token::Equals,
-                Token::CellName("B1".to_string()),
+                Token::CellName(cell("B1")),
             ]
         );
     }
@@ -545,13 +632,13 @@ mod tests {
         assert_eq!(
             tokens,
             vec![
-                Token::CellName("A1".to_string()),
+                Token::CellName(cell("A1")),
                 Token::GreaterThan,
-                Token::CellName("B1".to_string()),
+                Token::CellName(cell("B1")),
                 Token::And,
-                Token::CellName("C1".to_string()),
+                Token::CellName(cell("C1")),
                 Token::LessEquals,
-                Token::CellName("D1".to_string()),
+                Token::CellName(cell("D1")),
             ]
         );
     }
@@ -565,13 +652,13 @@ mod tests {
         assert_eq!(
             tokens,
             vec![
-                Token::CellName("A1".to_string()),
+                Token::CellName(cell("A1")),
                 Token::NotEquals,
-                Token::CellName("B1".to_string()),
+                Token::CellName(cell("B1")),
                 Token::Or,
-                Token::CellName("C1".to_string()),
+                Token::CellName(cell("C1")),
                 Token::NotEquals,
-                Token::CellName("D1".to_string()),
+                Token::CellName(cell("D1")),
             ]
         );
     }
@@ -585,13 +672,13 @@ mod tests {
         assert_eq!(
             tokens,
             vec![
-                Token::CellName("A1".to_string()),
+                Token::CellName(cell("A1")),
                 Token::Plus,
-                Token::CellName("B1".to_string()),
+                Token::CellName(cell("B1")),
                 Token::GreaterThan,
-                Token::CellName("C1".to_string()),
+                Token::CellName(cell("C1")),
                 Token::Multiply,
-                Token::CellName("D1".to_string()),
+                Token::CellName(cell("D1")),
             ]
         );
     }
@@ -605,14 +692,14 @@ mod tests {
         assert_eq!(
             tokens,
             vec![
-                Token::FunctionName("sum".to_string()),
+                Token::FunctionName(FunctionId::new("sum")),
                 Token::LParen,
-                Token::CellName("A1".to_string()),
+                Token::CellName(cell("A1")),
                 Token::Comma,
-                Token::CellName("B1".to_string()),
+                Token::CellName(cell("B1")),
                 Token::RParen,
                 Token::GreaterEquals,
-                Token::CellName("C1".to_string()),
+                Token::CellName(cell("C1")),
             ]
         );
     }
@@ -623,7 +710,7 @@ mod tests {
         let tokens = ExpressionTokenizer::new(s.chars().collect())
             .tokenize_expression()
             .unwrap();
-        assert_eq!(tokens, vec![Token::Not, Token::CellName("A1".to_string()),]);
+        assert_eq!(tokens, vec![Token::Not, Token::CellName(cell("A1")),]);
     }
 
     #[test]
@@ -636,18 +723,18 @@ mod tests {
             tokens,
             vec![
                 Token::LParen,
-                Token::CellName("A1".to_string()),
+                Token::CellName(cell("A1")),
                 Token::GreaterThan,
-                Token::CellName("B1".to_string()),
+                Token::CellName(cell("B1")),
                 Token::And,
-                Token::CellName("C1".to_string()),
+                Token::CellName(cell("C1")),
                 Token::LessThan,
-                Token::CellName("D1".to_string()),
+                Token::CellName(cell("D1")),
                 Token::RParen,
                 Token::Or,
-                Token::CellName("E1".to_string()),
+                Token::CellName(cell("E1")),
                 Token::Equals,
-                Token::CellName("F1".to_string()),
+                Token::CellName(cell("F1")),
             ]
         );
     }
@@ -717,7 +804,7 @@ mod tests {
         assert_eq!(
             tokens,
             vec![
-                Token::CellName("A1".to_string()),
+                Token::CellName(cell("A1")),
                 Token::Equals,
                 Token::Bool(true),
             ]
@@ -760,9 +847,9 @@ mod tests {
         assert_eq!(
             tokens,
             vec![
-                Token::FunctionName("if".to_string()),
+                Token::FunctionName(FunctionId::new("if")),
                 Token::LParen,
-                Token::CellName("A1".to_string()),
+                Token::CellName(cell("A1")),
                 Token::GreaterThan,
                 Token::Number(10.0),
                 Token::Comma,
@@ -784,20 +871,20 @@ mod tests {
             tokens,
             vec![
                 Token::LParen,
-                Token::CellName("A1".to_string()),
+                Token::CellName(cell("A1")),
                 Token::GreaterThan,
-                Token::CellName("B1".to_string()),
+                Token::CellName(cell("B1")),
                 Token::And,
                 Token::Bool(true),
                 Token::RParen,
                 Token::Or,
                 Token::LParen,
-                Token::CellName("C1".to_string()),
+                Token::CellName(cell("C1")),
                 Token::Equals,
                 Token::Bool(false),
                 Token::And,
                 Token::Not,
-                Token::CellName("D1".to_string()),
+                Token::CellName(cell("D1")),
                 Token::RParen,
             ]
         );
@@ -872,4 +959,19 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_tokenize_lossy_keeps_valid_start() {
+        let tokens = ExpressionTokenizer::new("sum(A1, \"x".chars().collect()).tokenize_lossy();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (Token::FunctionName(FunctionId::new("sum")), 0..3),
+                (Token::LParen, 3..4),
+                (Token::CellName(cell("A1")), 4..6),
+                (Token::Comma, 6..7),
+            ]
+        );
+    }
 }
@@ -0,0 +1,112 @@
+use std::mem::size_of;
+
+use super::SpreadSheet;
+use crate::common_types::{ParsedCell, Value, AST};
+
+/// Counts and sizes describing a whole sheet, see `SpreadSheet::stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SheetStats {
+    /// Cells with content, those holding nothing but their style aside.
+    pub cells: usize,
+    pub formula_cells: usize,
+    pub value_cells: usize,
+    /// Cells whose computed value is an error.
+    pub error_cells: usize,
+    /// Pairs of a cell and a formula reading it, each counted once.
+    pub dependency_edges: usize,
+    /// The most cells computed one after the other from a single change, less the changed
+    /// one. Cells in or behind a cycle are left out.
+    pub longest_chain: usize,
+    /// Approximate bytes taken by the text typed into the cells.
+    pub raw_bytes: usize,
+    /// Approximate bytes taken by the syntax trees of the formulas.
+    pub ast_bytes: usize,
+}
+
+impl SpreadSheet {
+    /// Counts the cells of the sheet by kind and measures its dependencies and memory.
+    pub fn stats(&self) -> SheetStats {
+        let mut stats = SheetStats {
+            dependency_edges: self.dependencies.edge_count(),
+            longest_chain: self.dependencies.longest_chain(),
+            ..SheetStats::default()
+        };
+        for cell in self.cells.values() {
+            if cell.raw_representation.is_empty() {
+                continue;
+            }
+            stats.cells += 1;
+            if cell.raw_representation.starts_with('=') {
+                stats.formula_cells += 1;
+            } else {
+                stats.value_cells += 1;
+            }
+            if let Some(Err(_)) = cell.computed_value {
+                stats.error_cells += 1;
+            }
+            stats.raw_bytes += cell.raw_representation.capacity();
+            if let Some(Ok(ParsedCell::Expr(expr))) = &cell.parsed_representation {
+                stats.ast_bytes += ast_size(&expr.ast);
+            }
+        }
+        stats
+    }
+}
+
+/// Approximates the bytes `ast` takes on the heap: its nodes and the text they hold. Walked
+/// without recursion, like `AST::drop`, as formulas may chain thousands of operations.
+fn ast_size(ast: &AST) -> usize {
+    let mut size = 0;
+    let mut nodes = vec![ast];
+    while let Some(node) = nodes.pop() {
+        size += size_of::<AST>();
+        match node {
+            AST::BinaryOp { left, right, .. } => nodes.extend([&**left, &**right]),
+            AST::UnaryOp { expr, .. } => nodes.push(expr),
+            AST::FunctionCall { arguments, .. } => nodes.extend(arguments),
+            AST::Value(Value::Text(text)) => size += text.len(),
+            AST::Name(name) => size += name.len(),
+            _ => {}
+        }
+    }
+    size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_types::cell_addr::cell;
+
+    #[test]
+    fn test_stats() {
+        let mut sheet = SpreadSheet::default();
+        for (name, raw) in [
+            ("A1", "1"),
+            ("A2", "=A1 + 1"),
+            ("A3", "=A2 * A1"),
+            ("A4", "=sum(A1:A3)"),
+            ("B1", "text"),
+            ("B2", "=B1 + 1"),
+            ("C1", "=C2"),
+            ("C2", "=C1"),
+        ] {
+            sheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        sheet.set_fill(cell("D1"), Some([0, 0, 0, 255]));
+
+        let stats = sheet.stats();
+        assert_eq!(stats.cells, 8);
+        assert_eq!(stats.formula_cells, 6);
+        assert_eq!(stats.value_cells, 2);
+        // B2 adds to text, C1 and C2 form a cycle
+        assert_eq!(stats.error_cells, 3);
+        // A1 -> A2, A3, A4; A2 -> A3, A4; A3 -> A4; B1 -> B2; C1 <-> C2
+        assert_eq!(stats.dependency_edges, 9);
+        // A1 -> A2 -> A3 -> A4
+        assert_eq!(stats.longest_chain, 3);
+        assert!(stats.raw_bytes >= 44);
+        assert!(stats.ast_bytes >= 11 * size_of::<AST>());
+
+        assert_eq!(SpreadSheet::default().stats(), SheetStats::default());
+    }
+}
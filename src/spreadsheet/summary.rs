@@ -0,0 +1,260 @@
+use std::ops::RangeInclusive;
+
+use super::SpreadSheet;
+use crate::common_types::{EditError, FunctionId, Index, ParsedCell, AST};
+
+/// An aggregate of the values of a column, written beneath it by
+/// `SpreadSheet::add_summary_row`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SummaryStat {
+    Sum,
+    Average,
+    Count,
+    Min,
+    Max,
+}
+
+impl SummaryStat {
+    pub const ALL: [SummaryStat; 5] = [
+        SummaryStat::Sum,
+        SummaryStat::Average,
+        SummaryStat::Count,
+        SummaryStat::Min,
+        SummaryStat::Max,
+    ];
+
+    /// Returns the name of the function computing the aggregate.
+    pub fn function(self) -> &'static str {
+        match self {
+            SummaryStat::Sum => "sum",
+            SummaryStat::Average => "average",
+            SummaryStat::Count => "count",
+            SummaryStat::Min => "min",
+            SummaryStat::Max => "max",
+        }
+    }
+
+    /// Returns the formula computing the aggregate over `rows` of `column`, like
+    /// `=sum(B2:B41)`.
+    fn formula(self, column: usize, rows: (usize, usize)) -> String {
+        let ast = AST::FunctionCall {
+            name: FunctionId::new(self.function()),
+            arguments: vec![AST::Range {
+                from: Index {
+                    x: column,
+                    y: rows.0,
+                },
+                to: Index {
+                    x: column,
+                    y: rows.1,
+                },
+            }],
+        };
+        format!("={}", ast.to_formula_string())
+    }
+}
+
+impl SpreadSheet {
+    /// Writes a row of formulas beneath the rectangle spanned by the corners of `range` for
+    /// each of `stats`, in order, aggregating the column above each cell. The rows summarizing
+    /// the same data written before are replaced instead of being stacked under, and a range
+    /// reaching into them only covers the data above them. Undone as a single step, and
+    /// nothing is written when a cell to write into holds anything else or is locked.
+    pub fn add_summary_row(
+        &mut self,
+        range: (Index, Index),
+        stats: &[SummaryStat],
+    ) -> Result<(), EditError> {
+        let (from, to) = range;
+        let columns = from.x.min(to.x)..=from.x.max(to.x);
+        let top = from.y.min(to.y);
+        let mut bottom = from.y.max(to.y);
+
+        // Summaries written before are never part of the data, they would read themselves
+        let last_row = Index {
+            x: *columns.start(),
+            y: bottom,
+        };
+        if let Some(rows) = self.summarized_rows(last_row) {
+            if rows.0 == top
+                && rows.1 < bottom
+                && (rows.1 + 1..=bottom).all(|y| self.is_summary_row(&columns, y, rows))
+            {
+                bottom = rows.1;
+            }
+        }
+        let rows = (top, bottom);
+        let existing = (bottom + 1..)
+            .take_while(|&y| self.is_summary_row(&columns, y, rows))
+            .count();
+
+        let mut written = Vec::new();
+        for (i, stat) in stats.iter().enumerate() {
+            for x in columns.clone() {
+                let index = Index {
+                    x,
+                    y: bottom + 1 + i,
+                };
+                if i >= existing && self.get_raw(&index).is_some() {
+                    return Err(EditError::CellOccupied(index));
+                }
+                written.push((index, stat.formula(x, rows)));
+            }
+        }
+        // Summaries left over from a longer list go
+        for i in stats.len()..existing {
+            for x in columns.clone() {
+                written.push((
+                    Index {
+                        x,
+                        y: bottom + 1 + i,
+                    },
+                    String::new(),
+                ));
+            }
+        }
+        self.check_all_unlocked(written.iter().map(|(index, _)| *index))?;
+
+        self.batch(|sheet| {
+            written
+                .into_iter()
+                .try_for_each(|(index, raw)| sheet.set_cell(index, raw))
+        })
+    }
+
+    /// Returns the first and last rows the cell at `index` aggregates when it holds a summary
+    /// of the column it is in.
+    fn summarized_rows(&self, index: Index) -> Option<(usize, usize)> {
+        let Some(Ok(ParsedCell::Expr(expr))) = &self.cells.get(&index)?.parsed_representation
+        else {
+            return None;
+        };
+        let AST::FunctionCall { name, arguments } = &*expr.ast else {
+            return None;
+        };
+        let [AST::Range { from, to }] = arguments.as_slice() else {
+            return None;
+        };
+        let is_stat = SummaryStat::ALL
+            .iter()
+            .any(|stat| stat.function() == name.as_str());
+
+        (is_stat && from.x == index.x && to.x == index.x && from.y <= to.y && to.y < index.y)
+            .then_some((from.y, to.y))
+    }
+
+    /// Returns true when every cell of row `y` in `columns` summarizes `rows` of its column.
+    fn is_summary_row(
+        &self,
+        columns: &RangeInclusive<usize>,
+        y: usize,
+        rows: (usize, usize),
+    ) -> bool {
+        columns
+            .clone()
+            .all(|x| self.summarized_rows(Index { x, y }) == Some(rows))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_types::cell_addr::cell;
+    use crate::common_types::Value;
+
+    fn number(sheet: &SpreadSheet, name: &str) -> f64 {
+        match sheet.get_computed(cell(name)) {
+            Some(Ok(Value::Number(num))) => num,
+            computed => panic!("Expected a number in {name}, got {computed:?}"),
+        }
+    }
+
+    /// A block of three columns and three rows, B1 to D3.
+    fn sheet_with_data() -> SpreadSheet {
+        let mut sheet = SpreadSheet::default();
+        for (y, row) in [["1", "10", "x"], ["2", "20", "y"], ["3", "=C1 + C2", "z"]]
+            .iter()
+            .enumerate()
+        {
+            for (x, raw) in row.iter().enumerate() {
+                sheet
+                    .set_cell(Index { x: x + 1, y }, raw.to_string())
+                    .unwrap();
+            }
+        }
+        sheet
+    }
+
+    #[test]
+    fn test_summaries_follow_the_data() {
+        let mut sheet = sheet_with_data();
+        let stats = [SummaryStat::Sum, SummaryStat::Average, SummaryStat::Count];
+        sheet
+            .add_summary_row((cell("D3"), cell("B1")), &stats)
+            .unwrap();
+
+        assert_eq!(sheet.get_raw(&cell("B4")), Some("=sum(B1:B3)"));
+        assert_eq!(sheet.get_raw(&cell("C5")), Some("=average(C1:C3)"));
+        assert_eq!(sheet.get_raw(&cell("D6")), Some("=count(D1:D3)"));
+        assert_eq!(number(&sheet, "B4"), 6.0);
+        assert_eq!(number(&sheet, "C4"), 60.0);
+        assert_eq!(number(&sheet, "C5"), 20.0);
+        assert_eq!(number(&sheet, "B6"), 3.0);
+
+        // The summaries stay live
+        sheet.set_cell(cell("C1"), "40".to_string()).unwrap();
+        assert_eq!(number(&sheet, "C4"), 120.0);
+        assert_eq!(number(&sheet, "C5"), 40.0);
+        sheet.set_cell(cell("B2"), String::new()).unwrap();
+        assert_eq!(number(&sheet, "B4"), 4.0);
+        assert_eq!(number(&sheet, "B6"), 2.0);
+
+        // Written as one step
+        sheet.undo();
+        sheet.undo();
+        sheet.undo();
+        assert_eq!(sheet.get_raw(&cell("B4")), None);
+    }
+
+    #[test]
+    fn test_summaries_are_replaced() {
+        let mut sheet = sheet_with_data();
+        let range = (cell("B1"), cell("D3"));
+        sheet
+            .add_summary_row(range, &[SummaryStat::Sum, SummaryStat::Count])
+            .unwrap();
+        sheet.add_summary_row(range, &[SummaryStat::Max]).unwrap();
+        assert_eq!(sheet.get_raw(&cell("B4")), Some("=max(B1:B3)"));
+        assert_eq!(sheet.get_raw(&cell("B5")), None);
+
+        // A range taking in the summaries summarizes the data above them
+        sheet
+            .add_summary_row(
+                (cell("B1"), cell("D4")),
+                &[SummaryStat::Min, SummaryStat::Sum],
+            )
+            .unwrap();
+        assert_eq!(sheet.get_raw(&cell("D4")), Some("=min(D1:D3)"));
+        assert_eq!(sheet.get_raw(&cell("D5")), Some("=sum(D1:D3)"));
+        assert_eq!(number(&sheet, "B4"), 1.0);
+        assert_eq!(number(&sheet, "C5"), 60.0);
+    }
+
+    #[test]
+    fn test_summaries_keep_other_content() {
+        let mut sheet = sheet_with_data();
+        sheet.set_cell(cell("C5"), "note".to_string()).unwrap();
+        assert_eq!(
+            sheet.add_summary_row(
+                (cell("B1"), cell("D3")),
+                &[SummaryStat::Sum, SummaryStat::Count],
+            ),
+            Err(EditError::CellOccupied(cell("C5")))
+        );
+        assert_eq!(sheet.get_raw(&cell("B4")), None);
+        assert_eq!(
+            EditError::CellOccupied(cell("C5")).to_string(),
+            "Cell C5 is not empty"
+        );
+    }
+}
@@ -0,0 +1,240 @@
+use std::time::{Duration, Instant};
+
+use super::{parser::CellParser, SpreadSheet};
+use crate::common_types::{index_to_name, Cell, Index};
+
+/// A generated sheet for measuring the engine, with the cell whose edit reaches the most
+/// formulas.
+#[derive(Debug, Clone)]
+pub struct SyntheticSheet {
+    pub name: &'static str,
+    pub cells: Vec<(Index, String)>,
+    pub upstream: Index,
+}
+
+/// How long each part of the work on a synthetic sheet took.
+#[derive(Debug, Clone, Copy)]
+pub struct Timings {
+    pub parse: Duration,
+    pub compute_all: Duration,
+    pub edit: Duration,
+}
+
+/// A column of `n` cells, each formula adding one to the cell above it.
+pub fn chain(n: usize) -> SyntheticSheet {
+    let cells = (0..n)
+        .map(|y| {
+            let raw = match y {
+                0 => "1".to_string(),
+                // The row of the cell above, counting from 1
+                y => format!("=A{y} + 1"),
+            };
+            (Index { x: 0, y }, raw)
+        })
+        .collect();
+    SyntheticSheet {
+        name: "chain",
+        cells,
+        upstream: Index { x: 0, y: 0 },
+    }
+}
+
+/// A1 feeding `n` formulas below each other in column B.
+pub fn fan_out(n: usize) -> SyntheticSheet {
+    let a1 = Index { x: 0, y: 0 };
+    let mut cells = vec![(a1, "1".to_string())];
+    cells.extend((0..n).map(|y| (Index { x: 1, y }, format!("=A1 * {}", y + 1))));
+    SyntheticSheet {
+        name: "fan-out",
+        cells,
+        upstream: a1,
+    }
+}
+
+/// An `n` by `n` block of numbers with the sum of each row in the column right of it.
+pub fn row_sums(n: usize) -> SyntheticSheet {
+    let mut cells = Vec::new();
+    for y in 0..n {
+        for x in 0..n {
+            cells.push((Index { x, y }, (x + y).to_string()));
+        }
+        let last = index_to_name(Index { x: n - 1, y });
+        cells.push((Index { x: n, y }, format!("=sum(A{}:{last})", y + 1)));
+    }
+    SyntheticSheet {
+        name: "row sums",
+        cells,
+        upstream: Index { x: 0, y: 0 },
+    }
+}
+
+/// `n` numbers spread over a million rows, one every so many rows and in one of 26 columns,
+/// like a sheet scrolled far down for the grid to draw.
+pub fn scattered(n: usize) -> SyntheticSheet {
+    let step = (SCATTERED_ROWS / n.max(1)).max(1);
+    let cells = (0..n)
+        .map(|i| {
+            (
+                Index {
+                    x: i % 26,
+                    y: i * step,
+                },
+                i.to_string(),
+            )
+        })
+        .collect();
+    SyntheticSheet {
+        name: "scattered",
+        cells,
+        upstream: Index { x: 0, y: 0 },
+    }
+}
+
+/// How many rows `scattered` spreads its cells over.
+const SCATTERED_ROWS: usize = 1_000_000;
+
+impl SyntheticSheet {
+    /// Returns the sheet with every cell parsed and in the dependency graph, but no formula
+    /// computed yet. Other cells are computed as they are added, like `replace_cells` does,
+    /// as ranges only make their corners dependencies.
+    pub fn uncomputed(&self) -> SpreadSheet {
+        let mut sheet = SpreadSheet::default();
+        for (index, raw) in &self.cells {
+            let mut cell = Cell::from_raw(raw.clone());
+            CellParser::parse_cell(&mut cell);
+            sheet.add_dependencies(*index, &cell);
+            if !raw.starts_with('=') {
+                cell.computed_value = sheet.compute_cell(*index, &cell);
+                cell.needs_compute = false;
+            }
+            sheet.cells.insert(*index, cell);
+        }
+        sheet
+    }
+
+    /// Returns the sheet computed as a whole.
+    pub fn build(&self) -> SpreadSheet {
+        let mut sheet = self.uncomputed();
+        sheet.compute_all();
+        sheet
+    }
+
+    /// Times parsing every cell, computing the whole sheet and then changing the upstream
+    /// cell.
+    pub fn measure(&self) -> Timings {
+        let start = Instant::now();
+        for (_, raw) in &self.cells {
+            CellParser::parse_cell(&mut Cell::from_raw(raw.clone()));
+        }
+        let parse = start.elapsed();
+
+        let mut sheet = self.uncomputed();
+        let start = Instant::now();
+        sheet.compute_all();
+        let compute_all = start.elapsed();
+
+        let start = Instant::now();
+        sheet
+            .mutate_cell(self.upstream, "2".to_string())
+            .expect("Expected a synthetic sheet to be unprotected");
+        let edit = start.elapsed();
+
+        Timings {
+            parse,
+            compute_all,
+            edit,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_types::Value;
+
+    fn number(sheet: &SpreadSheet, index: Index) -> f64 {
+        match sheet.get_computed(index) {
+            Some(Ok(Value::Number(num))) => num,
+            other => panic!("{} is {other:?}", index_to_name(index)),
+        }
+    }
+
+    #[test]
+    fn test_chain() {
+        let synthetic = chain(200);
+        let mut sheet = synthetic.build();
+        let last = Index { x: 0, y: 199 };
+        assert_eq!(number(&sheet, last), 200.0);
+
+        sheet
+            .mutate_cell(synthetic.upstream, "2".to_string())
+            .unwrap();
+        assert_eq!(number(&sheet, last), 201.0);
+    }
+
+    #[test]
+    fn test_fan_out() {
+        let synthetic = fan_out(50);
+        let mut sheet = synthetic.build();
+        assert_eq!(sheet.cells.len(), 51);
+        assert_eq!(number(&sheet, Index { x: 1, y: 49 }), 50.0);
+
+        sheet
+            .mutate_cell(synthetic.upstream, "2".to_string())
+            .unwrap();
+        assert_eq!(number(&sheet, Index { x: 1, y: 49 }), 100.0);
+    }
+
+    #[test]
+    fn test_row_sums() {
+        let synthetic = row_sums(30);
+        let mut sheet = synthetic.build();
+        // 0 + 1 + ... + 29, then each row one more per cell
+        assert_eq!(number(&sheet, Index { x: 30, y: 0 }), 435.0);
+        assert_eq!(number(&sheet, Index { x: 30, y: 2 }), 495.0);
+
+        sheet
+            .mutate_cell(synthetic.upstream, "10".to_string())
+            .unwrap();
+        assert_eq!(number(&sheet, Index { x: 30, y: 0 }), 445.0);
+    }
+
+    #[test]
+    fn test_cells_in_rect() {
+        // A cell every 100 rows down to row 1000000
+        let sheet = scattered(10_000).build();
+        let in_rect = |from, to| {
+            let mut cells: Vec<Index> = sheet
+                .cells_in_rect(from, to)
+                .map(|(index, _)| index)
+                .collect();
+            cells.sort_by_key(|index| (index.y, index.x));
+            cells
+        };
+
+        // A view around row 100000 is looked up place by place
+        let view = in_rect(Index { x: 0, y: 99_990 }, Index { x: 19, y: 100_250 });
+        assert_eq!(
+            view,
+            vec![
+                Index { x: 12, y: 100_000 },
+                Index { x: 13, y: 100_100 },
+                Index { x: 14, y: 100_200 },
+            ]
+        );
+        // Corners in any order
+        assert_eq!(
+            in_rect(Index { x: 19, y: 100_250 }, Index { x: 0, y: 99_990 }),
+            view
+        );
+
+        // A rectangle larger than the sheet has cells filters them
+        let wide = in_rect(Index { x: 0, y: 0 }, Index { x: 25, y: 2_000 });
+        assert_eq!(wide.len(), 21);
+        assert_eq!(wide[20], Index { x: 20, y: 2_000 });
+        assert_eq!(
+            sheet.used_range(),
+            Some((Index { x: 0, y: 0 }, Index { x: 25, y: 999_900 }))
+        );
+    }
+}
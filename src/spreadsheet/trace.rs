@@ -0,0 +1,152 @@
+use std::fmt;
+
+use crate::common_types::{ComputeError, Token, Value};
+
+/// A value a step of a computation used, read from the cell `reference` if it names one.
+#[derive(Debug, Clone)]
+pub struct TraceValue {
+    pub reference: Option<String>,
+    pub value: Result<Value, ComputeError>,
+}
+
+/// A function call or an operation made while computing a formula, with what it was given
+/// and what it gave.
+#[derive(Debug, Clone)]
+pub enum TraceStep {
+    Call {
+        name: String,
+        arguments: Vec<TraceValue>,
+        result: Result<Value, ComputeError>,
+    },
+    /// A binary operation has two operands, `!` one.
+    Operation {
+        op: Token,
+        operands: Vec<TraceValue>,
+        result: Result<Value, ComputeError>,
+    },
+}
+
+/// How the formula of a cell was computed, step by step in the order they were made. See
+/// `SpreadSheet::explain`.
+#[derive(Debug, Clone)]
+pub struct Trace {
+    pub steps: Vec<TraceStep>,
+    /// The value of the formula, which is the result of the last step when it has any.
+    pub value: TraceValue,
+}
+
+impl fmt::Display for TraceValue {
+    /// Writes the value like `A1=5`, or `5` when it was not read from a cell. Text is quoted.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(reference) = &self.reference {
+            write!(f, "{reference}=")?;
+        }
+        write_result(f, &self.value)
+    }
+}
+
+impl fmt::Display for TraceStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let result = match self {
+            TraceStep::Call {
+                name,
+                arguments,
+                result,
+            } => {
+                let arguments: Vec<String> = arguments.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "{name}({})", arguments.join(", "))?;
+                result
+            }
+            TraceStep::Operation {
+                op,
+                operands,
+                result,
+            } => {
+                match operands.as_slice() {
+                    [left, right] => write!(f, "{left} {op} {right}")?,
+                    operands => {
+                        write!(f, "{op}")?;
+                        for operand in operands {
+                            write!(f, "{operand}")?;
+                        }
+                    }
+                }
+                result
+            }
+        };
+        write!(f, " -> ")?;
+        write_result(f, result)
+    }
+}
+
+impl fmt::Display for Trace {
+    /// Writes the steps separated by semicolons, like `sum(A1=5, A2=7) -> 12; 12 * 2 -> 24`,
+    /// or only the value for a formula computed without any.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.steps.is_empty() {
+            return write!(f, "{}", self.value);
+        }
+        for (i, step) in self.steps.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{step}")?;
+        }
+        Ok(())
+    }
+}
+
+fn write_result(f: &mut fmt::Formatter<'_>, result: &Result<Value, ComputeError>) -> fmt::Result {
+    match result {
+        Ok(Value::Text(text)) => write!(f, "{text:?}"),
+        Ok(value) => write!(f, "{value}"),
+        Err(err) => write!(f, "{err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::SpreadSheet;
+    use crate::common_types::{cell_addr::cell, Index};
+
+    #[test]
+    fn test_explain_nested_formula() {
+        let mut sheet = SpreadSheet::default();
+        let cells = [
+            ("A1", "5"),
+            ("A2", "7"),
+            ("B1", "=sum(A1:A2) * 2"),
+            ("C1", "=if(B1 > 20, B1 - max(A1, 10), 0)"),
+            ("D1", "=!(B1 > 20)"),
+            ("E1", "=A1 + \"x\""),
+            ("F1", "=A2"),
+        ];
+        for (name, raw) in cells {
+            sheet
+                .add_cell_and_compute(cell(name), raw.to_string())
+                .unwrap();
+        }
+        let explain = |x, y| sheet.explain(Index { x, y }).map(|trace| trace.to_string());
+
+        assert_eq!(
+            explain(1, 0).unwrap(),
+            "sum(A1=5, A2=7) -> 12; 12 * 2 -> 24"
+        );
+        assert_eq!(
+            explain(2, 0).unwrap(),
+            "B1=24 > 20 -> TRUE; max(A1=5, 10) -> 10; B1=24 - 10 -> 14; if(TRUE, 14, 0) -> 14"
+        );
+        assert_eq!(explain(3, 0).unwrap(), "B1=24 > 20 -> TRUE; !TRUE -> FALSE");
+        assert_eq!(explain(4, 0).unwrap(), "A1=5 + \"x\" -> #VALUE!");
+        assert_eq!(explain(5, 0).unwrap(), "A2=7");
+        // Values and empty cells have nothing to explain
+        assert_eq!(explain(0, 0), None);
+        assert_eq!(explain(9, 9), None);
+
+        let trace = sheet.explain(Index { x: 1, y: 0 }).unwrap();
+        assert_eq!(trace.steps.len(), 2);
+        assert!(
+            matches!(trace.value.value, Ok(crate::common_types::Value::Number(n)) if n == 24.0)
+        );
+    }
+}
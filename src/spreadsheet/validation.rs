@@ -0,0 +1,340 @@
+use std::fmt;
+
+use super::SpreadSheet;
+use crate::common_types::{ComputeError, Index, ParseError, Value};
+use crate::spreadsheet::parser::ast_resolver::VarContext;
+
+/// What the values of the cells a `ValidationRule` covers are expected to be.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationKind {
+    /// A number from the first to the second, both included.
+    NumberBetween(f64, f64),
+    /// One of the texts, compared with the value as text.
+    OneOf(Vec<String>),
+    /// A formula written for the top left cell of the range, like the condition of a
+    /// `ConditionalFormat`, evaluating to true for valid values.
+    Formula(String),
+}
+
+/// A rule the values of the cells of a range are checked against. See
+/// `SpreadSheet::set_validation`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationRule {
+    /// The corners of the rectangle the rule covers.
+    pub range: (Index, Index),
+    pub kind: ValidationKind,
+    /// Whether values breaking the rule are rejected instead of only flagged.
+    pub strict: bool,
+}
+
+/// How the value of a cell fares against the rule covering it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationState {
+    Valid,
+    /// The value breaks the rule, `message` telling what was expected.
+    Invalid {
+        message: String,
+        strict: bool,
+    },
+}
+
+impl fmt::Display for ValidationKind {
+    /// Writes what the rule expects, like `a number from 1 to 10`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationKind::NumberBetween(min, max) => write!(f, "a number from {min} to {max}"),
+            ValidationKind::OneOf(options) => write!(f, "one of {}", options.join(", ")),
+            ValidationKind::Formula(formula) => write!(f, "a value for which {formula} holds"),
+        }
+    }
+}
+
+/// The values of a sheet, with the value `value` in the cell at `index` as it is computed or
+/// would be once written.
+struct WithValue<'a> {
+    sheet: &'a SpreadSheet,
+    index: Index,
+    value: &'a Result<Value, ComputeError>,
+}
+
+impl VarContext for WithValue<'_> {
+    fn get_variable(&self, index: Index) -> Option<Result<Value, ComputeError>> {
+        if index == self.index {
+            Some(self.value.clone())
+        } else {
+            self.sheet.get_variable(index)
+        }
+    }
+
+    fn current_cell(&self) -> Option<Index> {
+        Some(self.index)
+    }
+
+    fn get_constant(&self, name: &str) -> Option<Value> {
+        self.sheet.get_constant(name)
+    }
+}
+
+impl SpreadSheet {
+    /// Checks the values of the cells of the rectangle spanned by the corners of `range`
+    /// against `kind`, replacing the rule set before for the same range. A later rule wins
+    /// over an earlier one for the cells both cover. Values breaking the rule are still
+    /// stored, only flagged, see `validation_state`.
+    pub fn set_validation(
+        &mut self,
+        range: (Index, Index),
+        kind: ValidationKind,
+        strict: bool,
+    ) -> Result<(), ParseError> {
+        let kind = match kind {
+            ValidationKind::Formula(formula) => {
+                let formula = formula.trim().to_string();
+                Self::validate_formula(&formula)?;
+                ValidationKind::Formula(formula)
+            }
+            kind => kind,
+        };
+
+        let (from, to) = range;
+        let range = (
+            Index {
+                x: from.x.min(to.x),
+                y: from.y.min(to.y),
+            },
+            Index {
+                x: from.x.max(to.x),
+                y: from.y.max(to.y),
+            },
+        );
+        self.validation_rules.retain(|rule| rule.range != range);
+        self.validation_rules.push(ValidationRule {
+            range,
+            kind,
+            strict,
+        });
+        self.validation_results.get_mut().clear();
+        Ok(())
+    }
+
+    pub fn validation_rules(&self) -> &[ValidationRule] {
+        &self.validation_rules
+    }
+
+    /// Returns the rule the value of the cell at `index` is checked against, if any.
+    pub fn validation_rule(&self, index: Index) -> Option<&ValidationRule> {
+        self.validation_rules.iter().rev().find(|rule| {
+            let (top_left, bottom_right) = rule.range;
+            (top_left.x..=bottom_right.x).contains(&index.x)
+                && (top_left.y..=bottom_right.y).contains(&index.y)
+        })
+    }
+
+    /// Returns how the value of the cell at `index` fares against the rule covering it, or
+    /// `None` when no rule covers it or it is empty.
+    pub fn validation_state(&self, index: Index) -> Option<ValidationState> {
+        let rule = self.validation_rule(index)?;
+        let value = self.get_computed(index)?;
+        if let Some(&valid) = self.validation_results.borrow().get(&index) {
+            return Some(Self::state(rule, index, valid));
+        }
+
+        let valid = self.is_valid(rule, index, &value);
+        self.validation_results.borrow_mut().insert(index, valid);
+        Some(Self::state(rule, index, valid))
+    }
+
+    /// Returns how `raw` would fare against the rule covering the cell at `index` once
+    /// written into it, without changing anything. The other cells are read as they are.
+    pub fn check_validation(&self, index: Index, raw: &str) -> Option<ValidationState> {
+        let rule = self.validation_rule(index)?;
+        let raw = raw.trim();
+        if raw.is_empty() {
+            return None;
+        }
+
+        let value = self.evaluate_in_cell(index, raw);
+        Some(Self::state(rule, index, self.is_valid(rule, index, &value)))
+    }
+
+    fn is_valid(
+        &self,
+        rule: &ValidationRule,
+        index: Index,
+        value: &Result<Value, ComputeError>,
+    ) -> bool {
+        match (&rule.kind, value) {
+            (ValidationKind::NumberBetween(min, max), Ok(Value::Number(num))) => {
+                (*min..=*max).contains(num)
+            }
+            (ValidationKind::OneOf(options), Ok(value)) => options.contains(&value.to_string()),
+            (ValidationKind::Formula(formula), _) => {
+                let formula = Self::relocate_raw(formula, rule.range.0, index);
+                let variables = WithValue {
+                    sheet: self,
+                    index,
+                    value,
+                };
+                matches!(
+                    Self::evaluate_with(&formula, &variables),
+                    Ok(Value::Bool(true))
+                )
+            }
+            _ => false,
+        }
+    }
+
+    fn state(rule: &ValidationRule, index: Index, valid: bool) -> ValidationState {
+        if valid {
+            return ValidationState::Valid;
+        }
+
+        // The formula is told as written for the cell
+        let expected = match &rule.kind {
+            ValidationKind::Formula(formula) => {
+                ValidationKind::Formula(Self::relocate_raw(formula, rule.range.0, index))
+            }
+            kind => kind.clone(),
+        };
+        ValidationState::Invalid {
+            message: format!("Expected {expected}"),
+            strict: rule.strict,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_types::cell_addr::cell;
+
+    fn is_valid(sheet: &SpreadSheet, name: &str) -> Option<bool> {
+        sheet
+            .validation_state(cell(name))
+            .map(|state| state == ValidationState::Valid)
+    }
+
+    #[test]
+    fn test_number_between() {
+        let mut sheet = SpreadSheet::default();
+        sheet
+            .set_validation(
+                (cell("A3"), cell("A1")),
+                ValidationKind::NumberBetween(1.0, 10.0),
+                false,
+            )
+            .unwrap();
+        sheet.set_cell(cell("A1"), "10".to_string()).unwrap();
+        sheet.set_cell(cell("A2"), "11".to_string()).unwrap();
+        sheet.set_cell(cell("A3"), "=A1 / 20".to_string()).unwrap();
+        sheet.set_cell(cell("B1"), "11".to_string()).unwrap();
+
+        assert_eq!(is_valid(&sheet, "A1"), Some(true));
+        // Still stored, only flagged
+        assert_eq!(sheet.get_raw(&cell("A2")), Some("11"));
+        assert_eq!(
+            sheet.validation_state(cell("A2")),
+            Some(ValidationState::Invalid {
+                message: "Expected a number from 1 to 10".to_string(),
+                strict: false,
+            })
+        );
+        assert_eq!(is_valid(&sheet, "A3"), Some(false));
+        // Outside of the rule and empty cells are not checked
+        assert_eq!(is_valid(&sheet, "B1"), None);
+        sheet.set_cell(cell("A2"), String::new()).unwrap();
+        assert_eq!(is_valid(&sheet, "A2"), None);
+
+        // The rule outlives edits of the values it checks
+        sheet.set_cell(cell("A1"), "40".to_string()).unwrap();
+        assert_eq!(is_valid(&sheet, "A1"), Some(false));
+        assert_eq!(is_valid(&sheet, "A3"), Some(true));
+        assert_eq!(
+            sheet.check_validation(cell("A1"), "5"),
+            Some(ValidationState::Valid)
+        );
+        assert_eq!(sheet.check_validation(cell("B1"), "50"), None);
+    }
+
+    #[test]
+    fn test_one_of() {
+        let mut sheet = SpreadSheet::default();
+        let options = vec!["yes".to_string(), "no".to_string(), "1".to_string()];
+        sheet
+            .set_validation(
+                (cell("B1"), cell("B1")),
+                ValidationKind::OneOf(options),
+                true,
+            )
+            .unwrap();
+        sheet.set_cell(cell("B1"), "maybe".to_string()).unwrap();
+        assert_eq!(
+            sheet.validation_state(cell("B1")),
+            Some(ValidationState::Invalid {
+                message: "Expected one of yes, no, 1".to_string(),
+                strict: true,
+            })
+        );
+        for raw in ["no", "=2 - 1"] {
+            sheet.set_cell(cell("B1"), raw.to_string()).unwrap();
+            assert_eq!(is_valid(&sheet, "B1"), Some(true), "{raw}");
+        }
+
+        // Setting a rule for the same range replaces it
+        sheet
+            .set_validation(
+                (cell("B1"), cell("B1")),
+                ValidationKind::OneOf(vec!["a".to_string()]),
+                false,
+            )
+            .unwrap();
+        assert_eq!(sheet.validation_rules().len(), 1);
+        assert_eq!(is_valid(&sheet, "B1"), Some(false));
+    }
+
+    #[test]
+    fn test_formula_follows_other_cells() {
+        let mut sheet = SpreadSheet::default();
+        for (name, raw) in [("A1", "100"), ("A2", "100"), ("A3", "10")] {
+            sheet.set_cell(cell(name), raw.to_string()).unwrap();
+        }
+        // Each value of column B stays within the limit next to it
+        sheet
+            .set_validation(
+                (cell("B1"), cell("B3")),
+                ValidationKind::Formula("=B1 <= A1".to_string()),
+                false,
+            )
+            .unwrap();
+        sheet.set_cell(cell("B1"), "50".to_string()).unwrap();
+        sheet.set_cell(cell("B2"), "150".to_string()).unwrap();
+        assert_eq!(is_valid(&sheet, "B1"), Some(true));
+        assert_eq!(is_valid(&sheet, "B2"), Some(false));
+
+        sheet.set_cell(cell("A1"), "40".to_string()).unwrap();
+        assert_eq!(is_valid(&sheet, "B1"), Some(false));
+        sheet.set_cell(cell("A2"), "=A1 * 5".to_string()).unwrap();
+        assert_eq!(is_valid(&sheet, "B2"), Some(true));
+        sheet.set_cell(cell("A1"), "20".to_string()).unwrap();
+        assert_eq!(is_valid(&sheet, "B2"), Some(false));
+
+        // Checked with the value the cell would have
+        assert_eq!(
+            sheet.check_validation(cell("B3"), "=A3"),
+            Some(ValidationState::Valid)
+        );
+        assert_eq!(
+            sheet.check_validation(cell("B3"), "=A3 + 1"),
+            Some(ValidationState::Invalid {
+                message: "Expected a value for which =B3 <= A3 holds".to_string(),
+                strict: false,
+            })
+        );
+        assert!(sheet
+            .set_validation(
+                (cell("C1"), cell("C1")),
+                ValidationKind::Formula("=C1 >".to_string()),
+                false,
+            )
+            .is_err());
+    }
+}
@@ -0,0 +1,552 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::export::json_string;
+use crate::common_types::{col_to_letters, index_to_name, letters_to_col, name_to_index, Index};
+
+/// The version of the view state files written. Files of later versions are read all the
+/// same, keys unknown to this one left out.
+pub const VIEW_STATE_VERSION: u32 = 1;
+
+// Deeper documents are refused rather than read by ever deeper recursion
+const MAX_JSON_DEPTH: usize = 32;
+
+/// How a sheet was shown when it was last saved or closed, kept in a file of its own next
+/// to the sheet, see `view_state_path`. The sheet itself never depends on it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ViewState {
+    pub selected: Index,
+    /// The first column and row scrolled to, past the frozen ones.
+    pub scroll: Index,
+    /// How many columns and rows stay in place while scrolling.
+    pub frozen: (usize, usize),
+    /// The columns and rows resized, the others having the default size.
+    pub column_widths: BTreeMap<usize, f32>,
+    pub row_heights: BTreeMap<usize, f32>,
+    /// The columns and rows hidden, see `SpreadSheet::set_column_hidden`.
+    pub hidden_columns: BTreeSet<usize>,
+    pub hidden_rows: BTreeSet<usize>,
+    /// The name of the theme, which only means something to the GUI.
+    pub theme: Option<String>,
+}
+
+impl Default for ViewState {
+    fn default() -> Self {
+        Self {
+            selected: Index { x: 0, y: 0 },
+            scroll: Index { x: 0, y: 0 },
+            frozen: (0, 0),
+            column_widths: BTreeMap::new(),
+            row_heights: BTreeMap::new(),
+            hidden_columns: BTreeSet::new(),
+            hidden_rows: BTreeSet::new(),
+            theme: None,
+        }
+    }
+}
+
+/// Returns the file the view state of the sheet at `sheet_path` is kept in, like
+/// `budget.sheet.view.json` for `budget.sheet`.
+pub fn view_state_path(sheet_path: &Path) -> PathBuf {
+    let mut name = sheet_path.as_os_str().to_owned();
+    name.push(".view.json");
+    PathBuf::from(name)
+}
+
+impl ViewState {
+    /// Reads the view state kept for the sheet at `sheet_path`, `None` when there is none.
+    pub fn load(sheet_path: &Path) -> Result<Option<Self>, String> {
+        let path = view_state_path(sheet_path);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(format!("Cannot read {}: {err}", path.display())),
+        };
+        Self::from_json(&text)
+            .map(Some)
+            .map_err(|err| format!("{}: {err}", path.display()))
+    }
+
+    /// Writes the view state kept for the sheet at `sheet_path`.
+    pub fn save(&self, sheet_path: &Path) -> io::Result<()> {
+        fs::write(view_state_path(sheet_path), self.to_json())
+    }
+
+    /// Writes the view state as a JSON object, cells by name, columns by letter and rows by
+    /// number like the labels of the grid.
+    pub fn to_json(&self) -> String {
+        let sizes = |sizes: &BTreeMap<usize, f32>, name: fn(usize) -> String| {
+            let entries: Vec<String> = sizes
+                .iter()
+                .map(|(&line, size)| format!("{}: {size}", json_string(&name(line))))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        };
+        let mut fields = vec![
+            format!("\"version\": {VIEW_STATE_VERSION}"),
+            format!("\"selected\": \"{}\"", index_to_name(self.selected)),
+            format!("\"scroll\": \"{}\"", index_to_name(self.scroll)),
+            format!(
+                "\"frozen\": {{\"columns\": {}, \"rows\": {}}}",
+                self.frozen.0, self.frozen.1
+            ),
+            format!(
+                "\"column_widths\": {}",
+                sizes(&self.column_widths, col_to_letters)
+            ),
+            format!(
+                "\"row_heights\": {}",
+                sizes(&self.row_heights, |row| (row + 1).to_string())
+            ),
+        ];
+        if !self.hidden_columns.is_empty() {
+            let columns: Vec<String> = self
+                .hidden_columns
+                .iter()
+                .map(|&col| json_string(&col_to_letters(col)))
+                .collect();
+            fields.push(format!("\"hidden_columns\": [{}]", columns.join(", ")));
+        }
+        if !self.hidden_rows.is_empty() {
+            let rows: Vec<String> = self
+                .hidden_rows
+                .iter()
+                .map(|row| (row + 1).to_string())
+                .collect();
+            fields.push(format!("\"hidden_rows\": [{}]", rows.join(", ")));
+        }
+        if let Some(theme) = &self.theme {
+            fields.push(format!("\"theme\": {}", json_string(theme)));
+        }
+        format!("{{\n  {}\n}}\n", fields.join(",\n  "))
+    }
+
+    /// Reads a view state written by `to_json`. Keys left out keep their default and unknown
+    /// keys are skipped, but a known key holding a value it cannot is an error.
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let Json::Object(entries) = JsonReader::read(text)? else {
+            return Err("Expected an object".to_string());
+        };
+
+        let mut state = Self::default();
+        let mut version = None;
+        for (key, value) in entries {
+            let key = key.as_str();
+            match key {
+                "version" => version = Some(count(key, value)?),
+                "selected" => state.selected = cell(key, value)?,
+                "scroll" => state.scroll = cell(key, value)?,
+                "frozen" => {
+                    let Json::Object(fields) = value else {
+                        return Err(format!("{key} should be an object"));
+                    };
+                    for (field, value) in fields {
+                        match field.as_str() {
+                            "columns" => state.frozen.0 = count("frozen.columns", value)?,
+                            "rows" => state.frozen.1 = count("frozen.rows", value)?,
+                            _ => (),
+                        }
+                    }
+                }
+                "column_widths" => state.column_widths = sizes(key, value, column_of)?,
+                "row_heights" => state.row_heights = sizes(key, value, row_of)?,
+                "hidden_columns" => {
+                    state.hidden_columns = lines(key, value, |line| match line {
+                        Json::Text(name) => column_of(&name),
+                        _ => None,
+                    })?;
+                }
+                "hidden_rows" => {
+                    state.hidden_rows = lines(key, value, |line| match line {
+                        Json::Number(row) if row.fract() == 0.0 && row >= 1.0 => {
+                            Some(row as usize - 1)
+                        }
+                        _ => None,
+                    })?;
+                }
+                "theme" => match value {
+                    Json::Text(theme) => state.theme = Some(theme),
+                    _ => return Err(format!("{key} should be a string")),
+                },
+                // Written by a later version
+                _ => (),
+            }
+        }
+        match version {
+            Some(_) => Ok(state),
+            None => Err("Missing version".to_string()),
+        }
+    }
+
+    /// Returns the view state fitted to a grid of `bounds` columns and rows: cells past it
+    /// are moved to its last column or row and the sizes of lines past it dropped, as are
+    /// sizes that are not positive numbers.
+    pub fn clamped(mut self, bounds: (usize, usize)) -> Self {
+        let (columns, rows) = bounds;
+        let last = |bound: usize| bound.saturating_sub(1);
+        self.frozen = (
+            self.frozen.0.min(last(columns)),
+            self.frozen.1.min(last(rows)),
+        );
+        self.selected = Index {
+            x: self.selected.x.min(last(columns)),
+            y: self.selected.y.min(last(rows)),
+        };
+        self.scroll = Index {
+            x: self.scroll.x.min(last(columns)).max(self.frozen.0),
+            y: self.scroll.y.min(last(rows)).max(self.frozen.1),
+        };
+        let valid = |bound: usize| move |line: &usize, size: &mut f32| *line < bound && *size > 0.0;
+        self.column_widths.retain(valid(columns));
+        self.row_heights.retain(valid(rows));
+        self.hidden_columns.retain(|&col| col < columns);
+        self.hidden_rows.retain(|&row| row < rows);
+        self
+    }
+}
+
+fn count(key: &str, value: Json) -> Result<usize, String> {
+    match value {
+        Json::Number(number) if number.fract() == 0.0 && number >= 0.0 => Ok(number as usize),
+        _ => Err(format!("{key} should be a whole number")),
+    }
+}
+
+fn cell(key: &str, value: Json) -> Result<Index, String> {
+    match value {
+        Json::Text(name) => {
+            name_to_index(&name).map_err(|_| format!("{key}: invalid cell name {name}"))
+        }
+        _ => Err(format!("{key} should be a cell name")),
+    }
+}
+
+/// Reads an object of sizes by line, its keys read into lines by `line`.
+fn sizes(
+    key: &str,
+    value: Json,
+    line: fn(&str) -> Option<usize>,
+) -> Result<BTreeMap<usize, f32>, String> {
+    let Json::Object(entries) = value else {
+        return Err(format!("{key} should be an object"));
+    };
+    entries
+        .into_iter()
+        .map(|(name, size)| match (line(&name), size) {
+            (Some(line), Json::Number(size)) => Ok((line, size as f32)),
+            (None, _) => Err(format!("{key}: invalid line {name}")),
+            (_, _) => Err(format!("{key}: the size of {name} should be a number")),
+        })
+        .collect()
+}
+
+/// Reads an array of lines, each read by `line`.
+fn lines(
+    key: &str,
+    value: Json,
+    line: fn(Json) -> Option<usize>,
+) -> Result<BTreeSet<usize>, String> {
+    let Json::Array(items) = value else {
+        return Err(format!("{key} should be an array"));
+    };
+    items
+        .into_iter()
+        .map(|item| line(item).ok_or_else(|| format!("{key}: invalid line")))
+        .collect()
+}
+
+pub(super) fn column_of(name: &str) -> Option<usize> {
+    letters_to_col(name).ok()
+}
+
+pub(super) fn row_of(name: &str) -> Option<usize> {
+    name.parse::<usize>().ok()?.checked_sub(1)
+}
+
+/// A value of a JSON document.
+#[derive(Debug, PartialEq)]
+pub(super) enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Text(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+/// Reads a JSON document, which is all the view state and the cell styles need of JSON.
+pub(super) struct JsonReader<'a> {
+    text: &'a str,
+    position: usize,
+    depth: usize,
+}
+
+impl JsonReader<'_> {
+    pub(super) fn read(text: &str) -> Result<Json, String> {
+        let mut reader = JsonReader {
+            text,
+            position: 0,
+            depth: 0,
+        };
+        let value = reader.value()?;
+        reader.skip_whitespace();
+        match reader.peek() {
+            None => Ok(value),
+            Some(c) => Err(format!("Unexpected '{c}' after the document")),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn pop(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .peek()
+            .is_some_and(|c| matches!(c, ' ' | '\t' | '\n' | '\r'))
+        {
+            self.pop();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.pop() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("Expected '{expected}', found '{c}'")),
+            None => Err(format!("Expected '{expected}' before the end")),
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{' | '[') if self.depth == MAX_JSON_DEPTH => {
+                Err("The document is nested too deeply".to_string())
+            }
+            Some('{') => self.nested(Self::object),
+            Some('[') => self.nested(Self::array),
+            Some('"') => Ok(Json::Text(self.string()?)),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(_) => self.literal(),
+            None => Err("Unexpected end of the document".to_string()),
+        }
+    }
+
+    fn nested(&mut self, read: fn(&mut Self) -> Result<Json, String>) -> Result<Json, String> {
+        self.depth += 1;
+        let value = read(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut entries = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pop();
+            return Ok(Json::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            entries.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.pop() {
+                Some(',') => continue,
+                Some('}') => return Ok(Json::Object(entries)),
+                _ => return Err("Expected ',' or '}' in an object".to_string()),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pop();
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.pop() {
+                Some(',') => continue,
+                Some(']') => return Ok(Json::Array(values)),
+                _ => return Err("Expected ',' or ']' in an array".to_string()),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut text = String::new();
+        loop {
+            match self.pop() {
+                Some('"') => return Ok(text),
+                Some('\\') => {
+                    let escaped = match self.pop() {
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => {
+                            let hex = self.text.get(self.position..self.position + 4);
+                            let code = hex.and_then(|hex| u32::from_str_radix(hex, 16).ok());
+                            let Some(code) = code else {
+                                return Err("Invalid \\u escape".to_string());
+                            };
+                            self.position += 4;
+                            char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        Some(c @ ('"' | '\\' | '/')) => c,
+                        _ => return Err("Invalid escape in a string".to_string()),
+                    };
+                    text.push(escaped);
+                }
+                Some(c) => text.push(c),
+                None => return Err("Unterminated string".to_string()),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.position;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            self.pop();
+        }
+        let number = &self.text[start..self.position];
+        number
+            .parse()
+            .map(Json::Number)
+            .map_err(|_| format!("Invalid number {number}"))
+    }
+
+    fn literal(&mut self) -> Result<Json, String> {
+        let rest = &self.text[self.position..];
+        for (word, value) in [
+            ("true", Json::Bool(true)),
+            ("false", Json::Bool(false)),
+            ("null", Json::Null),
+        ] {
+            if rest.starts_with(word) {
+                self.position += word.len();
+                return Ok(value);
+            }
+        }
+        let found = rest.chars().next().unwrap_or_default();
+        Err(format!("Unexpected '{found}'"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> ViewState {
+        ViewState {
+            selected: name_to_index("C12").unwrap(),
+            scroll: name_to_index("B10").unwrap(),
+            frozen: (1, 2),
+            column_widths: BTreeMap::from([(0, 150.0), (27, 80.5)]),
+            row_heights: BTreeMap::from([(4, 40.0)]),
+            hidden_columns: BTreeSet::from([1, 27]),
+            hidden_rows: BTreeSet::from([0, 9]),
+            theme: Some("dark".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_view_state_round_trip() {
+        let json = state().to_json();
+        assert!(json.contains("\"column_widths\": {\"A\": 150, \"AB\": 80.5}"));
+        assert!(json.contains("\"row_heights\": {\"5\": 40}"));
+        assert!(json.contains("\"hidden_columns\": [\"B\", \"AB\"]"));
+        assert!(json.contains("\"hidden_rows\": [1, 10]"));
+        assert_eq!(ViewState::from_json(&json), Ok(state()));
+
+        let plain = ViewState::default();
+        assert_eq!(ViewState::from_json(&plain.to_json()), Ok(plain));
+    }
+
+    #[test]
+    fn test_view_state_from_later_versions() {
+        let json = r#"{
+            "version": 3,
+            "selected": "B2",
+            "zoom": 1.5,
+            "panes": [{"split": null, "tabs": ["a", "é"]}, true],
+            "frozen": {"columns": 2, "diagonal": false}
+        }"#;
+        let state = ViewState::from_json(json).unwrap();
+        assert_eq!(state.selected, Index { x: 1, y: 1 });
+        assert_eq!(state.frozen, (2, 0));
+        assert_eq!(state.theme, None);
+
+        for invalid in [
+            "",
+            "[]",
+            "{\"selected\": \"A1\"}",
+            "{\"version\": 1, \"selected\": \"A0\"}",
+            "{\"version\": 1, \"column_widths\": {\"a\": 10}}",
+            "{\"version\": 1, \"frozen\": {\"rows\": -1}}",
+            "{\"version\": 1, \"hidden_rows\": [0]}",
+            "{\"version\": 1, \"hidden_columns\": \"B\"}",
+            "{\"version\": 1,}",
+            "{\"version\": 1} {}",
+            &"[".repeat(100),
+        ] {
+            assert!(ViewState::from_json(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_view_state_clamped() {
+        let clamped = state().clamped((3, 8));
+        assert_eq!(clamped.selected, Index { x: 2, y: 7 });
+        assert_eq!(clamped.scroll, Index { x: 1, y: 7 });
+        assert_eq!(clamped.frozen, (1, 2));
+        assert_eq!(clamped.column_widths, BTreeMap::from([(0, 150.0)]));
+        assert_eq!(clamped.row_heights, BTreeMap::from([(4, 40.0)]));
+        assert_eq!(clamped.hidden_columns, BTreeSet::from([1]));
+        assert_eq!(clamped.hidden_rows, BTreeSet::from([0]));
+
+        // Frozen lines stay before the scrolled ones
+        let mut state = state();
+        state.row_heights.insert(1, -5.0);
+        let clamped = state.clamped((1, 2));
+        assert_eq!(clamped.frozen, (0, 1));
+        assert_eq!(clamped.scroll, Index { x: 0, y: 1 });
+        assert!(clamped.row_heights.is_empty());
+    }
+
+    #[test]
+    fn test_view_state_file() {
+        let sheet = std::env::temp_dir().join("mini_spreadsheet_view.sheet");
+        assert_eq!(
+            view_state_path(&sheet),
+            std::env::temp_dir().join("mini_spreadsheet_view.sheet.view.json")
+        );
+        let _ = fs::remove_file(view_state_path(&sheet));
+        assert_eq!(ViewState::load(&sheet), Ok(None));
+
+        state().save(&sheet).unwrap();
+        assert_eq!(ViewState::load(&sheet), Ok(Some(state())));
+    }
+}
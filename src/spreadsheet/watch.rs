@@ -0,0 +1,182 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use super::SpreadSheet;
+
+/// Notices changes of a file by polling the time it was last modified.
+#[derive(Debug)]
+pub struct FileWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl FileWatcher {
+    /// Starts watching `path` from its current state, which is not reported as a change.
+    pub fn new(path: PathBuf) -> Self {
+        let modified = modified_time(&path);
+        Self { path, modified }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns true once for every change of the file since it was last asked. A file that
+    /// disappeared has not changed until it is back.
+    pub fn changed(&mut self) -> bool {
+        let modified = modified_time(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Replaces the cells of `current` with those of the sheet read again from its file, styles
+/// included. The rest is kept as set for the session: the columns and rows it hides and
+/// their number formats, its conditional formats, validation rules, protection, constants,
+/// recalculation mode, reference depth and settings, the cells being computed with them.
+/// When it could not be read, as when the file is caught half written, `current` stays as
+/// it is and the returned error says why.
+pub fn apply_reload(
+    current: &mut SpreadSheet,
+    loaded: io::Result<SpreadSheet>,
+) -> Result<(), String> {
+    match loaded {
+        Ok(mut loaded) => {
+            let kept = std::mem::take(current);
+            loaded.hidden_columns = kept.hidden_columns;
+            loaded.hidden_rows = kept.hidden_rows;
+            loaded.column_formats = kept.column_formats;
+            loaded.row_formats = kept.row_formats;
+            loaded.conditional_formats = kept.conditional_formats;
+            loaded.condition_results.get_mut().clear();
+            loaded.validation_rules = kept.validation_rules;
+            loaded.validation_results.get_mut().clear();
+            loaded.protected = kept.protected;
+            loaded.display = kept.display;
+            loaded.canonical_formulas = kept.canonical_formulas;
+            loaded.unverified = kept.unverified;
+
+            // The cells were computed without the constants and the reference depth, which
+            // only matters to the cells reading through `indirect` or `offset`
+            loaded.max_reference_depth = kept.max_reference_depth;
+            if kept.max_reference_depth.is_some() && loaded.mark_dependants(&[]) {
+                loaded.compute_all();
+            }
+            for (name, value) in kept.constants {
+                loaded
+                    .define_constant(&name, value)
+                    .expect("Expected a defined constant to have a valid name");
+            }
+            loaded.recalc_mode = kept.recalc_mode;
+            loaded.deferred_compute = kept.deferred_compute;
+            *current = loaded;
+            Ok(())
+        }
+        Err(err) => Err(format!(
+            "Keeping the previous sheet, reloading failed: {err}"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, time::Duration};
+
+    use super::*;
+    use crate::common_types::{cell_addr::cell, CellStyle, ComputeError, NumberFormat, Value};
+    use crate::spreadsheet::validation::{ValidationKind, ValidationState};
+    use crate::spreadsheet::RecalcMode;
+
+    #[test]
+    fn test_apply_reload() {
+        let a1 = cell("A1");
+        let mut sheet = SpreadSheet::default();
+        sheet.add_cell_and_compute(a1, "old".to_string()).unwrap();
+
+        let failed = Err(io::Error::new(io::ErrorKind::UnexpectedEof, "half written"));
+        assert!(apply_reload(&mut sheet, failed).is_err());
+        assert_eq!(sheet.get_raw(&a1), Some("old"));
+
+        sheet.set_row_hidden(3, true);
+        sheet.set_column_format(1, NumberFormat::Percent);
+        sheet
+            .add_conditional_format((a1, a1), "=A1 > 100", CellStyle::default())
+            .unwrap();
+        sheet
+            .set_validation((a1, a1), ValidationKind::NumberBetween(1.0, 2.0), false)
+            .unwrap();
+        sheet.define_constant("rate", Value::Number(2.0)).unwrap();
+        sheet.set_max_reference_depth(1);
+        sheet.set_protection(true);
+        sheet.set_recalc_mode(RecalcMode::Manual);
+
+        let mut loaded = SpreadSheet::default();
+        for (name, raw) in [
+            ("A1", "new"),
+            ("B1", "=rate * 3"),
+            ("C1", "=indirect(\"C2\")"),
+            ("C2", "=indirect(\"C3\")"),
+            ("C3", "=indirect(\"C4\")"),
+            ("C4", "1"),
+        ] {
+            loaded
+                .add_cell_and_compute(cell(name), raw.to_string())
+                .unwrap();
+        }
+        assert!(loaded.get_error(cell("B1")).is_some());
+        assert_eq!(
+            loaded.get_computed(cell("C1")),
+            Some(Ok(Value::Number(1.0)))
+        );
+        assert_eq!(apply_reload(&mut sheet, Ok(loaded)), Ok(()));
+        assert_eq!(sheet.get_raw(&a1), Some("new"));
+
+        // What the file does not hold is kept, the cells computed with it
+        assert!(sheet.is_row_hidden(3));
+        assert_eq!(sheet.column_format(1), NumberFormat::Percent);
+        assert_eq!(sheet.conditional_formats().len(), 1);
+        assert_eq!(sheet.validation_rules().len(), 1);
+        assert!(sheet.validation_state(a1) != Some(ValidationState::Valid));
+        assert!(sheet.is_protected());
+        assert_eq!(sheet.recalc_mode(), RecalcMode::Manual);
+        assert_eq!(sheet.constant("rate"), Some(&Value::Number(2.0)));
+        assert_eq!(sheet.get_computed(cell("B1")), Some(Ok(Value::Number(6.0))));
+        assert_eq!(
+            sheet.get_error(cell("C1")),
+            Some(ComputeError::DepthExceeded(1))
+        );
+        assert!(!sheet.needs_recalc());
+    }
+
+    #[test]
+    fn test_file_watcher() {
+        let path = std::env::temp_dir().join("mini_spreadsheet_watched.csv");
+        fs::write(&path, "1").unwrap();
+        let mut watcher = FileWatcher::new(path.clone());
+        assert!(!watcher.changed());
+
+        let later = SystemTime::now() + Duration::from_secs(5);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        // Gone for a moment while being replaced
+        fs::remove_file(&path).unwrap();
+        assert!(!watcher.changed());
+    }
+}